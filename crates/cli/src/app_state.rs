@@ -9,6 +9,7 @@ use std::{convert::Infallible, net::IpAddr, sync::Arc, time::Instant};
 use axum::{
     async_trait,
     extract::{FromRef, FromRequestParts},
+    http::HeaderName,
 };
 use ipnetwork::IpNetwork;
 use mas_data_model::SiteConfig;
@@ -49,6 +50,7 @@ pub struct AppState {
     pub site_config: SiteConfig,
     pub activity_tracker: ActivityTracker,
     pub trusted_proxies: Vec<IpNetwork>,
+    pub location_header: Option<HeaderName>,
     pub limiter: Limiter,
     pub conn_acquisition_histogram: Option<Histogram<u64>>,
 }
@@ -313,6 +315,31 @@ fn infer_client_ip(
     client_ip.or(fallback)
 }
 
+/// Get the approximate location of the client from the configured header, if
+/// the request went through a trusted proxy
+fn infer_client_location(
+    parts: &axum::http::request::Parts,
+    trusted_proxies: &[IpNetwork],
+    location_header: &HeaderName,
+) -> Option<String> {
+    let connection_info = parts.extensions.get::<mas_listener::ConnectionInfo>()?;
+
+    // Requests going through the proxy protocol always come from a trusted proxy
+    let trusted = connection_info.get_proxy_ref().is_some()
+        || connection_info.get_peer_addr().is_some_and(|addr| {
+            trusted_proxies
+                .iter()
+                .any(|network| network.contains(addr.ip()))
+        });
+
+    if !trusted {
+        return None;
+    }
+
+    let location = parts.headers.get(location_header)?.to_str().ok()?.trim();
+    (!location.is_empty()).then(|| location.to_owned())
+}
+
 #[async_trait]
 impl FromRequestParts<AppState> for BoundActivityTracker {
     type Rejection = Infallible;
@@ -324,7 +351,15 @@ impl FromRequestParts<AppState> for BoundActivityTracker {
         // TODO: we may infer the IP twice, for the activity tracker and the limiter
         let ip = infer_client_ip(parts, &state.trusted_proxies);
         tracing::debug!(ip = ?ip, "Inferred client IP address");
-        Ok(state.activity_tracker.clone().bind(ip))
+        let location = state
+            .location_header
+            .as_ref()
+            .and_then(|header| infer_client_location(parts, &state.trusted_proxies, header));
+        Ok(state
+            .activity_tracker
+            .clone()
+            .bind(ip)
+            .with_location(location))
     }
 }
 
@@ -4,35 +4,57 @@
 // SPDX-License-Identifier: AGPL-3.0-only
 // Please see LICENSE in the repository root for full details.
 
-use std::{collections::BTreeMap, process::ExitCode};
+use std::{
+    collections::BTreeMap,
+    io::{BufRead, BufReader, BufWriter, Write},
+    process::ExitCode,
+};
 
 use anyhow::Context;
-use clap::{ArgAction, CommandFactory, Parser};
+use camino::{Utf8Path, Utf8PathBuf};
+use chrono::{DateTime, Utc};
+use clap::{ArgAction, CommandFactory, Parser, ValueEnum};
 use console::{pad_str, style, Alignment, Style, Term};
 use dialoguer::{theme::ColorfulTheme, Confirm, FuzzySelect, Input, Password};
 use figment::Figment;
+use futures_util::TryStreamExt;
 use mas_config::{
     ConfigurationSection, ConfigurationSectionExt, DatabaseConfig, MatrixConfig, PasswordsConfig,
 };
-use mas_data_model::{Device, TokenType, Ulid, UpstreamOAuthProvider, User};
+use mas_data_model::{Device, TokenType, Ulid, UpstreamOAuthProvider, User, UserRegistrationToken};
 use mas_email::Address;
+use mas_handlers::{
+    bulk_users::{
+        export_users, import_user, EmailRecord, ImportOutcome, PasswordRecord, UpstreamLinkRecord,
+        UserRecord,
+    },
+    passwords::SchemeVersion,
+};
 use mas_matrix::HomeserverConnection;
 use mas_matrix_synapse::SynapseConnection;
 use mas_storage::{
+    audit_log::AuditLogFilter,
     compat::{CompatAccessTokenRepository, CompatSessionFilter, CompatSessionRepository},
     job::{
-        DeactivateUserJob, JobRepositoryExt, ProvisionUserJob, ReactivateUserJob, SyncDevicesJob,
+        DeactivateUserJob, JobRepositoryExt, ProvisionUserJob, ReactivateUserJob,
+        SendWebhookEventJob, SyncDevicesJob,
     },
     oauth2::OAuth2SessionFilter,
-    user::{BrowserSessionFilter, UserEmailRepository, UserPasswordRepository, UserRepository},
-    Clock, RepositoryAccess, SystemClock,
+    user::{
+        BrowserSessionFilter, UserEmailRepository, UserPasswordRepository,
+        UserRegistrationTokenFilter, UserRegistrationTokenRepository, UserRepository,
+    },
+    Clock, Pagination, RepositoryAccess, SystemClock,
 };
 use mas_storage_pg::{DatabaseError, PgRepository};
 use rand::{RngCore, SeedableRng};
+use serde::{Deserialize, Serialize};
 use sqlx::{types::Uuid, Acquire};
 use tracing::{error, info, info_span, warn};
 
-use crate::util::{database_connection_from_config, password_manager_from_config};
+use crate::util::{
+    database_connection_from_config, database_pool_from_config, password_manager_from_config,
+};
 
 const USER_ATTRIBUTES_HEADING: &str = "User attributes";
 
@@ -72,7 +94,7 @@ enum Subcommand {
         username: String,
         password: String,
         /// Don't enforce that the password provided is above the minimum
-        /// configured complexity.
+        /// configured complexity, and absent from the breached passwords list.
         #[clap(long)]
         ignore_complexity: bool,
     },
@@ -165,10 +187,89 @@ enum Subcommand {
         #[arg(short, long, help_heading = USER_ATTRIBUTES_HEADING)]
         display_name: Option<String>,
         /// Don't enforce that the password provided is above the minimum
-        /// configured complexity.
+        /// configured complexity, and absent from the breached passwords list.
         #[clap(long)]
         ignore_password_complexity: bool,
     },
+
+    /// Export the security audit log as JSON lines on the standard output
+    ExportAuditLog {
+        /// Only export events affecting this user
+        #[arg(long)]
+        user: Option<String>,
+
+        /// Only export events recorded after this date, in RFC 3339 format
+        #[arg(long)]
+        since: Option<DateTime<Utc>>,
+
+        /// Only export events recorded before this date, in RFC 3339 format
+        #[arg(long)]
+        until: Option<DateTime<Utc>>,
+    },
+
+    /// Import users from a file
+    ///
+    /// Existing users are updated: missing email addresses and upstream links
+    /// are added, and the password and admin status are replaced if they
+    /// differ. Each user is imported in its own transaction, and invalid users
+    /// are reported without stopping the import.
+    ImportUsers {
+        /// The file to import, as written by `export-users`
+        file: Utf8PathBuf,
+
+        /// The format of the file
+        #[arg(long, value_enum, default_value_t)]
+        format: UserFileFormat,
+
+        /// Skip checking with the homeserver whether the usernames of new users
+        /// are available
+        #[arg(long)]
+        skip_homeserver_check: bool,
+
+        /// Validate the users without importing them
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Export all users on the standard output
+    ExportUsers {
+        /// The format of the output
+        #[arg(long, value_enum, default_value_t)]
+        format: UserFileFormat,
+    },
+
+    /// Create a registration token
+    ///
+    /// Tokens are only required to register if
+    /// `account.registration_token_required` is set in the configuration.
+    AddRegistrationToken {
+        /// The token users have to provide. If not specified, a random token
+        /// will be generated.
+        #[arg(long)]
+        token: Option<String>,
+
+        /// How many times the token can be used. Defaults to unlimited.
+        #[arg(long)]
+        usage_limit: Option<u32>,
+
+        /// When the token expires, in RFC 3339 format. Defaults to never.
+        #[arg(long)]
+        expires_at: Option<DateTime<Utc>>,
+    },
+
+    /// List the registration tokens
+    ListRegistrationTokens {
+        /// Also list the tokens which were revoked, have expired, or were used
+        /// as many times as allowed
+        #[arg(long)]
+        all: bool,
+    },
+
+    /// Revoke a registration token, so that it can't be used anymore
+    RevokeRegistrationToken {
+        /// The token to revoke
+        token: String,
+    },
 }
 
 impl Options {
@@ -207,6 +308,11 @@ impl Options {
                     return Ok(ExitCode::from(1));
                 }
 
+                if !ignore_complexity && password_manager.is_password_breached(&password) {
+                    error!("That password was found in a list of breached passwords.");
+                    return Ok(ExitCode::from(1));
+                }
+
                 let password = password.into_bytes().into();
 
                 let (version, hashed_password) = password_manager.hash(&mut rng, password).await?;
@@ -258,6 +364,10 @@ impl Options {
                     repo.user_email().set_as_primary(&email).await?;
                 }
 
+                repo.job()
+                    .schedule_job(SendWebhookEventJob::user_email_verified(&user, &email))
+                    .await?;
+
                 repo.into_inner().commit().await?;
                 info!(?email, "Email added and marked as verified");
 
@@ -295,6 +405,10 @@ impl Options {
                     repo.user_email().set_as_primary(&email).await?;
                 }
 
+                repo.job()
+                    .schedule_job(SendWebhookEventJob::user_email_verified(&user, &email))
+                    .await?;
+
                 repo.into_inner().commit().await?;
                 info!(?email, "Email marked as verified");
 
@@ -465,6 +579,10 @@ impl Options {
                 // synchronously yet.
                 let user = repo.user().lock(&clock, user).await?;
 
+                repo.job()
+                    .schedule_job(SendWebhookEventJob::user_locked(&user))
+                    .await?;
+
                 if deactivate {
                     warn!(%user.id, "Scheduling user deactivation");
                     repo.job()
@@ -500,6 +618,123 @@ impl Options {
                 Ok(ExitCode::SUCCESS)
             }
 
+            SC::ImportUsers {
+                file,
+                format,
+                skip_homeserver_check,
+                dry_run,
+            } => {
+                let _span = info_span!("cli.manage.import_users").entered();
+                let http_client = mas_http::reqwest_client();
+                let password_config = PasswordsConfig::extract_or_default(figment)?;
+                let database_config = DatabaseConfig::extract_or_default(figment)?;
+                let matrix_config = MatrixConfig::extract(figment)?;
+
+                let password_manager = password_manager_from_config(&password_config).await?;
+                let homeserver = SynapseConnection::new(
+                    matrix_config.homeserver,
+                    matrix_config.endpoint,
+                    matrix_config.secret,
+                    http_client,
+                );
+                let pool = database_pool_from_config(&database_config).await?;
+
+                let (mut created, mut updated, mut unchanged, mut failed) = (0, 0, 0, 0);
+                for (line, record) in read_user_records(&file, format)? {
+                    let record = match record {
+                        Ok(record) => record,
+                        Err(e) => {
+                            error!("Line {line}: {e:#}");
+                            failed += 1;
+                            continue;
+                        }
+                    };
+
+                    // Use a transaction per user, so that an invalid user doesn't roll back
+                    // the ones imported before
+                    let mut repo = PgRepository::from_pool(&pool).await?.boxed();
+                    let result = import_user(
+                        &mut repo,
+                        &mut rng,
+                        &clock,
+                        &password_manager,
+                        &homeserver,
+                        record,
+                        skip_homeserver_check,
+                    )
+                    .await;
+
+                    match result {
+                        Ok((_, outcome)) => {
+                            if dry_run {
+                                repo.cancel().await?;
+                            } else {
+                                repo.save().await?;
+                            }
+
+                            match outcome {
+                                ImportOutcome::Created => created += 1,
+                                ImportOutcome::Updated => updated += 1,
+                                ImportOutcome::Unchanged => unchanged += 1,
+                            }
+                        }
+                        Err(e) if e.is_invalid_record() => {
+                            repo.cancel().await?;
+                            error!("Line {line}: {e}");
+                            failed += 1;
+                        }
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+
+                if dry_run {
+                    info!(
+                        created,
+                        updated, unchanged, failed, "Dry run, no user was imported"
+                    );
+                } else {
+                    info!(created, updated, unchanged, failed, "Imported users");
+                }
+
+                if failed > 0 {
+                    Ok(ExitCode::from(1))
+                } else {
+                    Ok(ExitCode::SUCCESS)
+                }
+            }
+            SC::ExportUsers { format } => {
+                let _span = info_span!("cli.manage.export_users").entered();
+                let config = DatabaseConfig::extract_or_default(figment)?;
+                let pool = database_pool_from_config(&config).await?;
+                let mut users = std::pin::pin!(export_users(pool));
+
+                // Logs go to stderr, so the users can be piped to a file
+                let stdout = BufWriter::new(std::io::stdout());
+                let mut exported = 0;
+                match format {
+                    UserFileFormat::Jsonl => {
+                        let mut stdout = stdout;
+                        while let Some(record) = users.try_next().await? {
+                            serde_json::to_writer(&mut stdout, &record)?;
+                            stdout.write_all(b"\n")?;
+                            exported += 1;
+                        }
+                        stdout.flush()?;
+                    }
+                    UserFileFormat::Csv => {
+                        let mut writer = csv::Writer::from_writer(stdout);
+                        while let Some(record) = users.try_next().await? {
+                            writer.serialize(CsvUserRecord::from(record))?;
+                            exported += 1;
+                        }
+                        writer.flush()?;
+                    }
+                }
+
+                info!("Exported {exported} users");
+
+                Ok(ExitCode::SUCCESS)
+            }
             SC::RegisterUser {
                 username,
                 password,
@@ -534,6 +769,13 @@ impl Options {
                         error!("That password is too weak.");
                         return Ok(ExitCode::from(1));
                     }
+
+                    if !ignore_password_complexity
+                        && password_manager.is_password_breached(password)
+                    {
+                        error!("That password was found in a list of breached passwords.");
+                        return Ok(ExitCode::from(1));
+                    }
                 }
 
                 // If the username is provided, check if it's available and normalize it.
@@ -748,6 +990,186 @@ impl Options {
 
                 Ok(ExitCode::SUCCESS)
             }
+
+            SC::ExportAuditLog { user, since, until } => {
+                let _span = info_span!("cli.manage.export_audit_log").entered();
+                let config = DatabaseConfig::extract_or_default(figment)?;
+                let mut conn = database_connection_from_config(&config).await?;
+                let txn = conn.begin().await?;
+                let mut repo = PgRepository::from_conn(txn);
+
+                let user = if let Some(username) = user {
+                    let user = repo
+                        .user()
+                        .find_by_username(&username)
+                        .await?
+                        .context("User not found")?;
+                    Some(user)
+                } else {
+                    None
+                };
+
+                let mut filter = AuditLogFilter::new();
+                if let Some(user) = &user {
+                    filter = filter.for_user(user);
+                }
+                if let Some(since) = since {
+                    filter = filter.with_created_after(since);
+                }
+                if let Some(until) = until {
+                    filter = filter.with_created_before(until);
+                }
+
+                // Logs go to stderr, so the events can be piped to a file
+                let mut stdout = BufWriter::new(std::io::stdout());
+                let mut pagination = Pagination::first(1000);
+                let mut exported = 0;
+                loop {
+                    let page = repo.audit_log().list(filter, pagination).await?;
+                    for event in &page.edges {
+                        serde_json::to_writer(&mut stdout, event)?;
+                        stdout.write_all(b"\n")?;
+                    }
+                    exported += page.edges.len();
+
+                    match page.edges.last() {
+                        Some(last) if page.has_next_page => pagination = pagination.after(last.id),
+                        _ => break,
+                    }
+                }
+                stdout.flush()?;
+
+                info!("Exported {exported} audit log events");
+                repo.into_inner().rollback().await?;
+
+                Ok(ExitCode::SUCCESS)
+            }
+
+            SC::AddRegistrationToken {
+                token,
+                usage_limit,
+                expires_at,
+            } => {
+                let _span = info_span!("cli.manage.add_registration_token").entered();
+                let config = DatabaseConfig::extract_or_default(figment)?;
+                let mut conn = database_connection_from_config(&config).await?;
+                let txn = conn.begin().await?;
+                let mut repo = PgRepository::from_conn(txn);
+
+                let token = match token {
+                    Some(token) => {
+                        if !UserRegistrationToken::is_token_well_formed(&token) {
+                            error!("Tokens must be at most 64 characters long, and only contain letters, digits, '.', '_', '~' and '-'");
+                            return Ok(ExitCode::from(1));
+                        }
+
+                        if repo
+                            .user_registration_token()
+                            .find_by_token(&token)
+                            .await?
+                            .is_some()
+                        {
+                            error!("This registration token already exists");
+                            return Ok(ExitCode::from(1));
+                        }
+
+                        token
+                    }
+                    None => UserRegistrationToken::generate_token(&mut rng),
+                };
+
+                if expires_at.is_some_and(|expires_at| expires_at <= clock.now()) {
+                    error!("The expiration date is in the past");
+                    return Ok(ExitCode::from(1));
+                }
+
+                let token = repo
+                    .user_registration_token()
+                    .add(&mut rng, &clock, token, usage_limit, expires_at)
+                    .await?;
+
+                repo.into_inner().commit().await?;
+                info!(
+                    %token.id,
+                    ?token.usage_limit,
+                    ?token.expires_at,
+                    "Registration token created: {}", token.token
+                );
+
+                Ok(ExitCode::SUCCESS)
+            }
+
+            SC::ListRegistrationTokens { all } => {
+                let _span = info_span!("cli.manage.list_registration_tokens").entered();
+                let config = DatabaseConfig::extract_or_default(figment)?;
+                let mut conn = database_connection_from_config(&config).await?;
+                let txn = conn.begin().await?;
+                let mut repo = PgRepository::from_conn(txn);
+
+                let mut filter = UserRegistrationTokenFilter::new(clock.now());
+                if !all {
+                    filter = filter
+                        .with_revoked(false)
+                        .with_expired(false)
+                        .with_exhausted(false);
+                }
+
+                let mut pagination = Pagination::first(1000);
+                let mut listed = 0;
+                loop {
+                    let page = repo
+                        .user_registration_token()
+                        .list(filter, pagination)
+                        .await?;
+                    for token in &page.edges {
+                        info!(
+                            %token.id,
+                            ?token.usage_limit,
+                            token.times_used,
+                            token.pending_uses,
+                            ?token.expires_at,
+                            ?token.revoked_at,
+                            "{}", token.token
+                        );
+                    }
+                    listed += page.edges.len();
+
+                    match page.edges.last() {
+                        Some(last) if page.has_next_page => pagination = pagination.after(last.id),
+                        _ => break,
+                    }
+                }
+
+                info!("Listed {listed} registration tokens");
+                repo.into_inner().rollback().await?;
+
+                Ok(ExitCode::SUCCESS)
+            }
+
+            SC::RevokeRegistrationToken { token } => {
+                let _span = info_span!("cli.manage.revoke_registration_token").entered();
+                let config = DatabaseConfig::extract_or_default(figment)?;
+                let mut conn = database_connection_from_config(&config).await?;
+                let txn = conn.begin().await?;
+                let mut repo = PgRepository::from_conn(txn);
+
+                let token = repo
+                    .user_registration_token()
+                    .find_by_token(&token)
+                    .await?
+                    .context("Registration token not found")?;
+
+                if token.revoked_at.is_some() {
+                    warn!(%token.id, "Registration token was already revoked");
+                    return Ok(ExitCode::SUCCESS);
+                }
+
+                let token = repo.user_registration_token().revoke(&clock, token).await?;
+                repo.into_inner().commit().await?;
+                info!(%token.id, "Registration token revoked");
+
+                Ok(ExitCode::SUCCESS)
+            }
         }
     }
 }
@@ -952,6 +1374,10 @@ impl UserCreationRequest<'_> {
                 repo.user_email().set_as_primary(&user_email).await?;
                 user.primary_user_email_id = Some(user_email.id);
             }
+
+            repo.job()
+                .schedule_job(SendWebhookEventJob::user_email_verified(&user, &user_email))
+                .await?;
         }
 
         for (provider, subject) in upstream_provider_mappings {
@@ -976,6 +1402,10 @@ impl UserCreationRequest<'_> {
 
         repo.job().schedule_job(provision_job).await?;
 
+        repo.job()
+            .schedule_job(SendWebhookEventJob::user_registered(&user))
+            .await?;
+
         Ok(user)
     }
 }
@@ -1068,3 +1498,151 @@ impl std::fmt::Display for UserCreationCommand<'_> {
         Ok(())
     }
 }
+
+/// The format of the files used to import and export users
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+enum UserFileFormat {
+    /// One JSON object per line
+    #[default]
+    Jsonl,
+
+    /// Comma-separated values, with a header row
+    Csv,
+}
+
+/// A user, as a row of a CSV file
+///
+/// Lists are separated by semicolons, and upstream links are written as
+/// `UPSTREAM_PROVIDER_ID:SUBJECT`, like the `--upstream-provider-mapping`
+/// argument of `register-user`.
+#[derive(Debug, Serialize, Deserialize)]
+struct CsvUserRecord {
+    username: String,
+    #[serde(default)]
+    display_name: Option<String>,
+    #[serde(default)]
+    emails: String,
+    #[serde(default)]
+    unverified_emails: String,
+    #[serde(default)]
+    password_version: Option<SchemeVersion>,
+    #[serde(default)]
+    password_hash: Option<String>,
+    #[serde(default)]
+    upstream_links: String,
+    #[serde(default)]
+    admin: Option<bool>,
+}
+
+fn split_list(list: &str) -> impl Iterator<Item = &str> {
+    list.split(';')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+}
+
+impl TryFrom<CsvUserRecord> for UserRecord {
+    type Error = anyhow::Error;
+
+    fn try_from(row: CsvUserRecord) -> Result<Self, Self::Error> {
+        let verified = split_list(&row.emails).map(|email| EmailRecord {
+            email: email.to_owned(),
+            verified: true,
+        });
+        let unverified = split_list(&row.unverified_emails).map(|email| EmailRecord {
+            email: email.to_owned(),
+            verified: false,
+        });
+        let emails = verified.chain(unverified).collect();
+
+        let password = match (row.password_version, row.password_hash) {
+            (Some(version), Some(hash)) => Some(PasswordRecord { version, hash }),
+            (None, None) => None,
+            _ => anyhow::bail!("password_version and password_hash must be set together"),
+        };
+
+        let upstream_links = split_list(&row.upstream_links)
+            .map(|mapping| {
+                let mapping = parse_upstream_provider_mapping(mapping)
+                    .with_context(|| format!("Invalid upstream link {mapping:?}"))?;
+                Ok(UpstreamLinkRecord {
+                    provider_id: mapping.upstream_provider_id,
+                    subject: mapping.subject,
+                })
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Self {
+            username: row.username,
+            display_name: row.display_name,
+            emails,
+            password,
+            upstream_links,
+            admin: row.admin,
+        })
+    }
+}
+
+impl From<UserRecord> for CsvUserRecord {
+    fn from(record: UserRecord) -> Self {
+        let join = |emails: &mut dyn Iterator<Item = &EmailRecord>| {
+            emails
+                .map(|email| email.email.as_str())
+                .collect::<Vec<_>>()
+                .join(";")
+        };
+
+        Self {
+            emails: join(&mut record.emails.iter().filter(|email| email.verified)),
+            unverified_emails: join(&mut record.emails.iter().filter(|email| !email.verified)),
+            password_version: record.password.as_ref().map(|password| password.version),
+            password_hash: record.password.map(|password| password.hash),
+            upstream_links: record
+                .upstream_links
+                .iter()
+                .map(|link| format!("{}:{}", link.provider_id, link.subject))
+                .collect::<Vec<_>>()
+                .join(";"),
+            username: record.username,
+            display_name: record.display_name,
+            admin: record.admin,
+        }
+    }
+}
+
+/// Read the users from a file, along with the line they are on
+fn read_user_records(
+    path: &Utf8Path,
+    format: UserFileFormat,
+) -> anyhow::Result<Box<dyn Iterator<Item = (usize, anyhow::Result<UserRecord>)> + Send>> {
+    let file = std::fs::File::open(path).with_context(|| format!("Could not open {path}"))?;
+
+    let records: Box<dyn Iterator<Item = _> + Send> = match format {
+        UserFileFormat::Jsonl => Box::new(
+            BufReader::new(file)
+                .lines()
+                .enumerate()
+                .map(|(index, line)| (index + 1, line))
+                .filter(|(_, line)| !line.as_ref().is_ok_and(|line| line.trim().is_empty()))
+                .map(|(line, record)| {
+                    let record = record
+                        .map_err(anyhow::Error::from)
+                        .and_then(|record| Ok(serde_json::from_str(&record)?));
+                    (line, record)
+                }),
+        ),
+        UserFileFormat::Csv => Box::new(
+            csv::Reader::from_reader(file)
+                .into_deserialize::<CsvUserRecord>()
+                .enumerate()
+                // The first line is the header
+                .map(|(index, row)| {
+                    let record = row
+                        .map_err(anyhow::Error::from)
+                        .and_then(UserRecord::try_from);
+                    (index + 2, record)
+                }),
+        ),
+    };
+
+    Ok(records)
+}
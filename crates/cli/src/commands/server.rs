@@ -7,13 +7,16 @@
 use std::{collections::BTreeSet, process::ExitCode, sync::Arc, time::Duration};
 
 use anyhow::Context;
+use axum::http::HeaderName;
 use clap::Parser;
 use figment::Figment;
 use itertools::Itertools;
 use mas_config::{
     AppConfig, ClientsConfig, ConfigurationSection, ConfigurationSectionExt, UpstreamOAuth2Config,
 };
-use mas_handlers::{ActivityTracker, CookieManager, Limiter, MetadataCache};
+use mas_handlers::{
+    ActivityTracker, CookieManager, Limiter, MetadataCache, UpstreamTokenRefresher,
+};
 use mas_listener::server::Server;
 use mas_matrix_synapse::SynapseConnection;
 use mas_router::UrlBuilder;
@@ -31,8 +34,8 @@ use crate::{
     shutdown::ShutdownManager,
     util::{
         database_pool_from_config, mailer_from_config, password_manager_from_config,
-        policy_factory_from_config, register_sighup, site_config_from_config,
-        templates_from_config,
+        policy_factory_from_config, register_sighup, session_expiration_from_config,
+        site_config_from_config, templates_from_config, webhook_endpoints_from_config,
     },
 };
 
@@ -142,6 +145,7 @@ impl Options {
             &config.passwords,
             &config.account,
             &config.captcha,
+            &config.rate_limiting,
         )?;
 
         // Load and compile the templates
@@ -157,6 +161,9 @@ impl Options {
             http_client.clone(),
         );
 
+        // The upstream OIDC metadata cache
+        let metadata_cache = MetadataCache::new();
+
         if !self.no_worker {
             let mailer = mailer_from_config(&config.email, &templates)?;
             mailer.test_connection().await?;
@@ -172,6 +179,17 @@ impl Options {
                 &mailer,
                 homeserver_connection.clone(),
                 url_builder.clone(),
+                session_expiration_from_config(&config.sessions),
+                config.audit_log.retention,
+                http_client.clone(),
+                key_store.clone(),
+                webhook_endpoints_from_config(&config.webhooks),
+                UpstreamTokenRefresher::new(
+                    http_client.clone(),
+                    metadata_cache.clone(),
+                    key_store.clone(),
+                    encrypter.clone(),
+                ),
             )
             .await?;
 
@@ -195,9 +213,6 @@ impl Options {
 
         let password_manager = password_manager_from_config(&config.passwords).await?;
 
-        // The upstream OIDC metadata cache
-        let metadata_cache = MetadataCache::new();
-
         // Initialize the activity tracker
         // Activity is flushed every minute
         let activity_tracker = ActivityTracker::new(
@@ -207,6 +222,13 @@ impl Options {
             shutdown.soft_shutdown_token(),
         );
         let trusted_proxies = config.http.trusted_proxies.clone();
+        let location_header = config
+            .http
+            .location_header
+            .as_deref()
+            .map(HeaderName::try_from)
+            .transpose()
+            .context("Invalid http.location_header")?;
 
         // Build a rate limiter.
         // This should not raise an error here as the config should already have been
@@ -228,6 +250,7 @@ impl Options {
             homeserver_connection.clone(),
             site_config.clone(),
             password_manager.clone(),
+            url_builder.clone(),
         );
 
         let state = {
@@ -247,6 +270,7 @@ impl Options {
                 site_config,
                 activity_tracker,
                 trusted_proxies,
+                location_header,
                 limiter,
                 conn_acquisition_histogram: None,
             };
@@ -6,9 +6,11 @@
 
 use std::process::ExitCode;
 
+use anyhow::Context;
 use clap::Parser;
 use figment::Figment;
 use mas_config::{AppConfig, ConfigurationSection};
+use mas_handlers::{MetadataCache, UpstreamTokenRefresher};
 use mas_matrix_synapse::SynapseConnection;
 use mas_router::UrlBuilder;
 use rand::{
@@ -18,7 +20,8 @@ use rand::{
 use tracing::{info, info_span};
 
 use crate::util::{
-    database_pool_from_config, mailer_from_config, site_config_from_config, templates_from_config,
+    database_pool_from_config, mailer_from_config, session_expiration_from_config,
+    site_config_from_config, templates_from_config, webhook_endpoints_from_config,
 };
 
 #[derive(Parser, Debug, Default)]
@@ -47,6 +50,7 @@ impl Options {
             &config.passwords,
             &config.account,
             &config.captcha,
+            &config.rate_limiting,
         )?;
 
         // Load and compile the templates
@@ -56,12 +60,30 @@ impl Options {
         let mailer = mailer_from_config(&config.email, &templates)?;
         mailer.test_connection().await?;
 
+        // Initialize the key store, used to sign webhooks and to authenticate to the
+        // upstream providers
+        let key_store = config
+            .secrets
+            .key_store()
+            .await
+            .context("could not import keys from config")?;
+
         let http_client = mas_http::reqwest_client();
         let conn = SynapseConnection::new(
             config.matrix.homeserver.clone(),
             config.matrix.endpoint.clone(),
             config.matrix.secret.clone(),
-            http_client,
+            http_client.clone(),
+        );
+
+        let session_expiration = session_expiration_from_config(&config.sessions);
+        let audit_log_retention = config.audit_log.retention;
+        let webhook_endpoints = webhook_endpoints_from_config(&config.webhooks);
+        let upstream_token_refresher = UpstreamTokenRefresher::new(
+            http_client.clone(),
+            MetadataCache::new(),
+            key_store.clone(),
+            config.secrets.encrypter(),
         );
 
         drop(config);
@@ -71,7 +93,20 @@ impl Options {
         let worker_name = Alphanumeric.sample_string(&mut rng, 10);
 
         info!(worker_name, "Starting task scheduler");
-        let monitor = mas_tasks::init(&worker_name, &pool, &mailer, conn, url_builder).await?;
+        let monitor = mas_tasks::init(
+            &worker_name,
+            &pool,
+            &mailer,
+            conn,
+            url_builder,
+            session_expiration,
+            audit_log_retention,
+            http_client,
+            key_store,
+            webhook_endpoints,
+            upstream_token_refresher,
+        )
+        .await?;
 
         span.exit();
 
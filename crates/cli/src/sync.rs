@@ -47,14 +47,17 @@ fn map_claims_imports(
         localpart: mas_data_model::UpstreamOAuthProviderImportPreference {
             action: map_import_action(config.localpart.action),
             template: config.localpart.template.clone(),
+            sync_on_login: false,
         },
         displayname: mas_data_model::UpstreamOAuthProviderImportPreference {
             action: map_import_action(config.displayname.action),
             template: config.displayname.template.clone(),
+            sync_on_login: config.displayname.sync_on_login,
         },
         email: mas_data_model::UpstreamOAuthProviderImportPreference {
             action: map_import_action(config.email.action),
             template: config.email.template.clone(),
+            sync_on_login: config.email.sync_on_login,
         },
         verify_email: match config.email.set_email_verification {
             mas_config::UpstreamOAuth2SetEmailVerification::Always => {
@@ -67,6 +70,11 @@ fn map_claims_imports(
                 mas_data_model::UpsreamOAuthProviderSetEmailVerification::Import
             }
         },
+        groups: mas_data_model::UpstreamOAuthProviderGroupsPreference {
+            template: config.groups.template.clone(),
+            admin_groups: config.groups.admin_groups.clone(),
+            required_groups: config.groups.required_groups.clone(),
+        },
     }
 }
 
@@ -284,6 +292,11 @@ pub async fn config_sync(
                         encrypted_client_secret,
                         claims_imports: map_claims_imports(&provider.claims_imports),
                         token_endpoint_override: provider.token_endpoint,
+                        userinfo_endpoint_override: provider.userinfo_endpoint,
+                        fetch_userinfo: provider.fetch_userinfo,
+                        store_tokens: provider.store_tokens,
+                        token_exchange_clients: provider.token_exchange_clients,
+                        domains: provider.domains,
                         authorization_endpoint_override: provider.authorization_endpoint,
                         jwks_uri_override: provider.jwks_uri,
                         discovery_mode,
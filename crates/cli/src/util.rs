@@ -10,11 +10,18 @@ use anyhow::Context;
 use mas_config::{
     AccountConfig, BrandingConfig, CaptchaConfig, DatabaseConfig, EmailConfig, EmailSmtpMode,
     EmailTransportKind, ExperimentalConfig, MatrixConfig, PasswordsConfig, PolicyConfig,
-    TemplatesConfig,
+    RateLimitingConfig, SessionsConfig, TemplatesConfig, WebhookEventConfig, WebhookSigningConfig,
+    WebhooksConfig,
+};
+use mas_data_model::{
+    LoginLockoutConfig, SessionExpiration, SessionExpirationConfig, SiteConfig, WebhookEndpoint,
+    WebhookEventKind, WebhookSigning,
 };
-use mas_data_model::SiteConfig;
 use mas_email::{MailTransport, Mailer};
-use mas_handlers::{passwords::PasswordManager, ActivityTracker};
+use mas_handlers::{
+    passwords::{BreachedPasswords, ExternalPasswordProvider, PasswordManager},
+    ActivityTracker,
+};
 use mas_policy::PolicyFactory;
 use mas_router::UrlBuilder;
 use mas_templates::{SiteConfigExt, TemplateLoadingError, Templates};
@@ -46,7 +53,42 @@ pub async fn password_manager_from_config(
             (version, hasher)
         });
 
-    PasswordManager::new(config.minimum_complexity(), schemes)
+    let mut password_manager = PasswordManager::new(config.minimum_complexity(), schemes)?;
+
+    if let Some(path) = config.breached_passwords_file() {
+        let breached_passwords = BreachedPasswords::load(path)
+            .await
+            .with_context(|| format!("failed to load the breached passwords list from {path}"))?;
+        info!(
+            count = breached_passwords.len(),
+            "Loaded the breached passwords list"
+        );
+        password_manager = password_manager.with_breached_passwords(breached_passwords);
+    }
+
+    if let Some(external) = config.external() {
+        let http_client = if let Some(tls) = &external.client_certificate {
+            let (key, certificate_chain) = tls
+                .load()
+                .context("failed to load the external password provider client certificate")?;
+            mas_http::reqwest_client_with_certificate(certificate_chain, key)
+                .context("invalid external password provider client certificate")?
+        } else {
+            mas_http::reqwest_client()
+        };
+
+        let mut provider = ExternalPasswordProvider::new(http_client, external.url.clone())
+            .with_provision_users(external.provision_users)
+            .with_local_fallback(external.local_fallback);
+
+        if let Some(shared_secret) = external.shared_secret().await? {
+            provider = provider.with_shared_secret(shared_secret);
+        }
+
+        password_manager = password_manager.with_external_provider(provider);
+    }
+
+    Ok(password_manager)
 }
 
 pub fn mailer_from_config(
@@ -111,6 +153,7 @@ pub async fn policy_factory_from_config(
         client_registration: config.client_registration_entrypoint.clone(),
         authorization_grant: config.authorization_grant_entrypoint.clone(),
         email: config.email_entrypoint.clone(),
+        upstream_oauth2_mapping: config.upstream_oauth2_mapping_entrypoint.clone(),
     };
 
     PolicyFactory::load(policy_file, config.data.clone(), entrypoints)
@@ -153,8 +196,19 @@ pub fn site_config_from_config(
     password_config: &PasswordsConfig,
     account_config: &AccountConfig,
     captcha_config: &CaptchaConfig,
+    rate_limiting_config: &RateLimitingConfig,
 ) -> Result<SiteConfig, anyhow::Error> {
     let captcha = captcha_config_from_config(captcha_config)?;
+    let login_lockout =
+        rate_limiting_config
+            .login
+            .lockout
+            .as_ref()
+            .map(|lockout| LoginLockoutConfig {
+                max_failed_attempts: lockout.max_failed_attempts.get(),
+                window: lockout.window,
+                duration: lockout.duration,
+            });
     Ok(SiteConfig {
         access_token_ttl: experimental_config.access_token_ttl,
         compat_token_ttl: experimental_config.compat_token_ttl,
@@ -165,6 +219,7 @@ pub fn site_config_from_config(
         password_login_enabled: password_config.enabled(),
         password_registration_enabled: password_config.enabled()
             && account_config.password_registration_enabled,
+        registration_token_required: account_config.registration_token_required,
         email_change_allowed: account_config.email_change_allowed,
         displayname_change_allowed: account_config.displayname_change_allowed,
         password_change_allowed: password_config.enabled()
@@ -173,9 +228,58 @@ pub fn site_config_from_config(
             && account_config.password_recovery_enabled,
         captcha,
         minimum_password_complexity: password_config.minimum_complexity(),
+        login_lockout,
     })
 }
 
+pub fn session_expiration_from_config(config: &SessionsConfig) -> SessionExpirationConfig {
+    let convert = |config: &mas_config::SessionExpirationConfig| SessionExpiration {
+        max_lifetime: config.max_lifetime,
+        idle_timeout: config.idle_timeout,
+    };
+
+    SessionExpirationConfig {
+        browser: convert(&config.browser),
+        compat: convert(&config.compat),
+        oauth2: convert(&config.oauth2.expiration),
+        oauth2_clients: config
+            .oauth2
+            .clients
+            .iter()
+            .map(|client| (client.client_id, convert(&client.expiration)))
+            .collect(),
+    }
+}
+
+pub fn webhook_endpoints_from_config(config: &WebhooksConfig) -> Vec<WebhookEndpoint> {
+    let convert_event = |event: &WebhookEventConfig| match event {
+        WebhookEventConfig::UserRegistered => WebhookEventKind::UserRegistered,
+        WebhookEventConfig::UserEmailVerified => WebhookEventKind::UserEmailVerified,
+        WebhookEventConfig::UserLocked => WebhookEventKind::UserLocked,
+        WebhookEventConfig::UserDeactivated => WebhookEventKind::UserDeactivated,
+        WebhookEventConfig::SessionStarted => WebhookEventKind::SessionStarted,
+        WebhookEventConfig::SessionEnded => WebhookEventKind::SessionEnded,
+    };
+
+    config
+        .endpoints
+        .iter()
+        .map(|endpoint| WebhookEndpoint {
+            id: endpoint.id,
+            url: endpoint.url.clone(),
+            events: endpoint.events.iter().map(convert_event).collect(),
+            signing: match &endpoint.signing {
+                WebhookSigningConfig::Hmac { secret } => WebhookSigning::Hmac {
+                    secret: secret.clone(),
+                },
+                WebhookSigningConfig::Jws { algorithm } => WebhookSigning::Jws {
+                    algorithm: algorithm.clone(),
+                },
+            },
+        })
+        .collect()
+}
+
 pub async fn templates_from_config(
     config: &TemplatesConfig,
     site_config: &SiteConfig,
@@ -50,6 +50,15 @@ pub struct AccountConfig {
     #[serde(default = "default_false", skip_serializing_if = "is_default_false")]
     pub password_registration_enabled: bool,
 
+    /// Whether a registration token is required to create an account.
+    /// Defaults to `false`.
+    ///
+    /// Registration tokens can be created with the admin API or the
+    /// `mas-cli manage add-registration-token` command. This applies to both
+    /// password registration and registration through an upstream provider.
+    #[serde(default = "default_false", skip_serializing_if = "is_default_false")]
+    pub registration_token_required: bool,
+
     /// Whether users are allowed to change their passwords. Defaults to `true`.
     ///
     /// This has no effect if password login is disabled.
@@ -69,6 +78,7 @@ impl Default for AccountConfig {
             email_change_allowed: default_true(),
             displayname_change_allowed: default_true(),
             password_registration_enabled: default_false(),
+            registration_token_required: default_false(),
             password_change_allowed: default_true(),
             password_recovery_enabled: default_false(),
         }
@@ -79,6 +89,7 @@ impl AccountConfig {
     /// Returns true if the configuration is the default one
     pub(crate) fn is_default(&self) -> bool {
         is_default_false(&self.password_registration_enabled)
+            && is_default_false(&self.registration_token_required)
             && is_default_true(&self.email_change_allowed)
             && is_default_true(&self.displayname_change_allowed)
             && is_default_true(&self.password_change_allowed)
@@ -0,0 +1,96 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+use chrono::Duration;
+use schemars::JsonSchema;
+use serde::{de::Error, Deserialize, Serialize};
+use serde_with::serde_as;
+
+use crate::ConfigurationSection;
+
+/// Configuration of the security audit log, which records events like logins,
+/// password changes and admin API operations
+#[serde_as]
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+pub struct AuditLogConfig {
+    /// How long events are kept in the audit log, in seconds. Events are kept
+    /// forever if not set.
+    #[schemars(with = "Option<u64>", range(min = 86400))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[serde_as(as = "Option<serde_with::DurationSeconds<i64>>")]
+    pub retention: Option<Duration>,
+}
+
+impl AuditLogConfig {
+    pub(crate) fn is_default(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+impl ConfigurationSection for AuditLogConfig {
+    const PATH: Option<&'static str> = Some("audit_log");
+
+    fn validate(&self, figment: &figment::Figment) -> Result<(), figment::Error> {
+        if self.retention.is_some_and(|d| d < Duration::days(1)) {
+            let mut err = figment::error::Error::custom("must be at least one day");
+            err.metadata = figment.find_metadata(Self::PATH.unwrap()).cloned();
+            err.profile = Some(figment::Profile::Default);
+            err.path = vec![Self::PATH.unwrap().to_owned(), "retention".to_owned()];
+            return Err(err);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use figment::{
+        providers::{Format, Yaml},
+        Figment, Jail,
+    };
+
+    use super::*;
+
+    #[test]
+    fn load_config() {
+        Jail::expect_with(|jail| {
+            jail.create_file(
+                "config.yaml",
+                r"
+                  audit_log:
+                    retention: 31536000
+                ",
+            )?;
+
+            let figment = Figment::new().merge(Yaml::file("config.yaml"));
+            let config = figment.extract_inner::<AuditLogConfig>("audit_log")?;
+
+            assert_eq!(config.retention, Some(Duration::days(365)));
+            config.validate(&figment)?;
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn reject_short_retention() {
+        Jail::expect_with(|jail| {
+            jail.create_file(
+                "config.yaml",
+                r"
+                  audit_log:
+                    retention: 3600
+                ",
+            )?;
+
+            let figment = Figment::new().merge(Yaml::file("config.yaml"));
+            let config = figment.extract_inner::<AuditLogConfig>("audit_log")?;
+            assert!(config.validate(&figment).is_err());
+
+            Ok(())
+        });
+    }
+}
@@ -362,6 +362,13 @@ pub struct HttpConfig {
     /// OIDC issuer URL. Defaults to `public_base` if not set.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub issuer: Option<Url>,
+
+    /// Name of a header set by the reverse proxy with the approximate location
+    /// of the client, e.g. `CF-IPCountry`. It is shown in the emails sent
+    /// about sign-ins from new devices, and only read on requests coming from
+    /// one of the `trusted_proxies`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location_header: Option<String>,
 }
 
 impl Default for HttpConfig {
@@ -405,6 +412,7 @@ impl Default for HttpConfig {
             trusted_proxies: default_trusted_proxies(),
             issuer: Some(default_public_base()),
             public_base: default_public_base(),
+            location_header: None,
         }
     }
 }
@@ -9,6 +9,7 @@ use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 mod account;
+mod audit_log;
 mod branding;
 mod captcha;
 mod clients;
@@ -21,12 +22,15 @@ mod passwords;
 mod policy;
 mod rate_limiting;
 mod secrets;
+mod sessions;
 mod telemetry;
 mod templates;
 mod upstream_oauth2;
+mod webhooks;
 
 pub use self::{
     account::AccountConfig,
+    audit_log::AuditLogConfig,
     branding::BrandingConfig,
     captcha::{CaptchaConfig, CaptchaServiceKind},
     clients::{ClientAuthMethodConfig, ClientConfig, ClientsConfig},
@@ -38,10 +42,14 @@ pub use self::{
         Resource as HttpResource, TlsConfig as HttpTlsConfig, UnixOrTcp,
     },
     matrix::MatrixConfig,
-    passwords::{Algorithm as PasswordAlgorithm, PasswordsConfig},
+    passwords::{Algorithm as PasswordAlgorithm, ExternalPasswordProviderConfig, PasswordsConfig},
     policy::PolicyConfig,
-    rate_limiting::RateLimitingConfig,
+    rate_limiting::{LoginLockoutConfig, RateLimitingConfig},
     secrets::SecretsConfig,
+    sessions::{
+        ClientSessionExpirationConfig, OAuth2SessionsConfig, SessionExpirationConfig,
+        SessionsConfig,
+    },
     telemetry::{
         MetricsConfig, MetricsExporterKind, Propagator, TelemetryConfig, TracingConfig,
         TracingExporterKind,
@@ -50,11 +58,13 @@ pub use self::{
     upstream_oauth2::{
         ClaimsImports as UpstreamOAuth2ClaimsImports, DiscoveryMode as UpstreamOAuth2DiscoveryMode,
         EmailImportPreference as UpstreamOAuth2EmailImportPreference,
+        GroupsImportPreference as UpstreamOAuth2GroupsImportPreference,
         ImportAction as UpstreamOAuth2ImportAction, PkceMethod as UpstreamOAuth2PkceMethod,
         ResponseMode as UpstreamOAuth2ResponseMode,
         SetEmailVerification as UpstreamOAuth2SetEmailVerification,
         TokenAuthMethod as UpstreamOAuth2TokenAuthMethod, UpstreamOAuth2Config,
     },
+    webhooks::{WebhookEndpointConfig, WebhookEventConfig, WebhookSigningConfig, WebhooksConfig},
 };
 use crate::util::ConfigurationSection;
 
@@ -121,6 +131,19 @@ pub struct RootConfig {
     #[serde(default, skip_serializing_if = "AccountConfig::is_default")]
     pub account: AccountConfig,
 
+    /// Configuration section to automatically end sessions after some time,
+    /// or when they are unused
+    #[serde(default, skip_serializing_if = "SessionsConfig::is_default")]
+    pub sessions: SessionsConfig,
+
+    /// Configuration section for the security audit log
+    #[serde(default, skip_serializing_if = "AuditLogConfig::is_default")]
+    pub audit_log: AuditLogConfig,
+
+    /// Configuration section for the webhooks sent to external systems
+    #[serde(default, skip_serializing_if = "WebhooksConfig::is_default")]
+    pub webhooks: WebhooksConfig,
+
     /// Experimental configuration options
     #[serde(default, skip_serializing_if = "ExperimentalConfig::is_default")]
     pub experimental: ExperimentalConfig,
@@ -143,6 +166,9 @@ impl ConfigurationSection for RootConfig {
         self.branding.validate(figment)?;
         self.captcha.validate(figment)?;
         self.account.validate(figment)?;
+        self.sessions.validate(figment)?;
+        self.audit_log.validate(figment)?;
+        self.webhooks.validate(figment)?;
         self.experimental.validate(figment)?;
 
         Ok(())
@@ -175,6 +201,9 @@ impl RootConfig {
             branding: BrandingConfig::default(),
             captcha: CaptchaConfig::default(),
             account: AccountConfig::default(),
+            sessions: SessionsConfig::default(),
+            audit_log: AuditLogConfig::default(),
+            webhooks: WebhooksConfig::default(),
             experimental: ExperimentalConfig::default(),
         })
     }
@@ -198,6 +227,9 @@ impl RootConfig {
             branding: BrandingConfig::default(),
             captcha: CaptchaConfig::default(),
             account: AccountConfig::default(),
+            sessions: SessionsConfig::default(),
+            audit_log: AuditLogConfig::default(),
+            webhooks: WebhooksConfig::default(),
             experimental: ExperimentalConfig::default(),
         }
     }
@@ -241,6 +273,15 @@ pub struct AppConfig {
     #[serde(default)]
     pub account: AccountConfig,
 
+    #[serde(default)]
+    pub sessions: SessionsConfig,
+
+    #[serde(default)]
+    pub audit_log: AuditLogConfig,
+
+    #[serde(default)]
+    pub webhooks: WebhooksConfig,
+
     #[serde(default)]
     pub experimental: ExperimentalConfig,
 }
@@ -259,6 +300,9 @@ impl ConfigurationSection for AppConfig {
         self.branding.validate(figment)?;
         self.captcha.validate(figment)?;
         self.account.validate(figment)?;
+        self.sessions.validate(figment)?;
+        self.audit_log.validate(figment)?;
+        self.webhooks.validate(figment)?;
         self.experimental.validate(figment)?;
 
         Ok(())
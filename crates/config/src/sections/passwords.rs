@@ -7,10 +7,12 @@
 use std::cmp::Reverse;
 
 use anyhow::bail;
-use camino::Utf8PathBuf;
+use camino::{Utf8Path, Utf8PathBuf};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use url::Url;
 
+use super::http::TlsConfig;
 use crate::ConfigurationSection;
 
 fn default_schemes() -> Vec<HashingScheme> {
@@ -52,6 +54,23 @@ pub struct PasswordsConfig {
     /// - 4: any more than that
     #[serde(default = "default_minimum_complexity")]
     minimum_complexity: u8,
+
+    /// Path to a file containing the SHA-1 hashes of known breached passwords,
+    /// in the format of the Have I Been Pwned password downloads.
+    ///
+    /// Each line holds the uppercase hex-encoded SHA-1 hash of a password,
+    /// optionally followed by a colon and a count, which is ignored. New
+    /// passwords found in this list are rejected. The whole list is loaded in
+    /// memory at startup, so it is advised to trim it to the most common
+    /// passwords.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<String>")]
+    breached_passwords_file: Option<Utf8PathBuf>,
+
+    /// External HTTP service used to verify user passwords, in addition to
+    /// the local password database
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    external: Option<ExternalPasswordProviderConfig>,
 }
 
 impl Default for PasswordsConfig {
@@ -60,6 +79,8 @@ impl Default for PasswordsConfig {
             enabled: default_enabled(),
             schemes: default_schemes(),
             minimum_complexity: default_minimum_complexity(),
+            breached_passwords_file: None,
+            external: None,
         }
     }
 }
@@ -94,6 +115,15 @@ impl ConfigurationSection for PasswordsConfig {
             }
         }
 
+        if let Some(external) = &self.external {
+            if external.shared_secret.is_some() && external.shared_secret_file.is_some() {
+                return annotate(figment::Error::from(
+                    "Cannot specify both `external.shared_secret` and `external.shared_secret_file`"
+                        .to_owned(),
+                ));
+            }
+        }
+
         Ok(())
     }
 }
@@ -112,6 +142,18 @@ impl PasswordsConfig {
         self.minimum_complexity
     }
 
+    /// Path to the list of known breached passwords, if any
+    #[must_use]
+    pub fn breached_passwords_file(&self) -> Option<&Utf8Path> {
+        self.breached_passwords_file.as_deref()
+    }
+
+    /// Configuration of the external password provider, if any
+    #[must_use]
+    pub fn external(&self) -> Option<&ExternalPasswordProviderConfig> {
+        self.external.as_ref()
+    }
+
     /// Load the password hashing schemes defined by the config
     ///
     /// # Errors
@@ -154,6 +196,76 @@ impl PasswordsConfig {
     }
 }
 
+const fn default_true() -> bool {
+    true
+}
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+const fn is_default_true(value: &bool) -> bool {
+    *value == default_true()
+}
+
+/// Configuration of an external HTTP service which verifies user credentials
+///
+/// When set, credentials submitted on login are sent as JSON to this
+/// endpoint, and the reply decides whether the login is allowed.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ExternalPasswordProviderConfig {
+    /// URL of the endpoint which verifies the credentials
+    pub url: Url,
+
+    /// Shared secret sent to the endpoint as a bearer token in the
+    /// `Authorization` header
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shared_secret: Option<String>,
+
+    /// File containing the shared secret sent to the endpoint
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<String>")]
+    pub shared_secret_file: Option<Utf8PathBuf>,
+
+    /// Client certificate and key used to authenticate to the endpoint with
+    /// mutual TLS
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_certificate: Option<TlsConfig>,
+
+    /// Whether to create the user locally if it was accepted by the external
+    /// provider but does not exist yet
+    ///
+    /// Defaults to `false`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub provision_users: bool,
+
+    /// Whether to check the local password database if the external provider
+    /// did not accept the credentials
+    ///
+    /// Defaults to `true`.
+    #[serde(default = "default_true", skip_serializing_if = "is_default_true")]
+    pub local_fallback: bool,
+}
+
+impl ExternalPasswordProviderConfig {
+    /// Load the shared secret, either from the config or from the file
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if both the secret and the file are set, or if the
+    /// file could not be read.
+    pub async fn shared_secret(&self) -> Result<Option<String>, anyhow::Error> {
+        match (&self.shared_secret, &self.shared_secret_file) {
+            (Some(secret), None) => Ok(Some(secret.clone())),
+            (None, Some(path)) => {
+                let secret = tokio::fs::read_to_string(path).await?;
+                Ok(Some(secret.trim().to_owned()))
+            }
+            (Some(_), Some(_)) => {
+                bail!("Cannot specify both `shared_secret` and `shared_secret_file`")
+            }
+            (None, None) => Ok(None),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct HashingScheme {
     version: u16,
@@ -125,6 +125,11 @@ pub struct PolicyConfig {
     )]
     pub email_entrypoint: String,
 
+    /// Entrypoint to use when mapping the claims of upstream OAuth 2.0
+    /// providers. The mapping policy is not evaluated if this isn't set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upstream_oauth2_mapping_entrypoint: Option<String>,
+
     /// Arbitrary data to pass to the policy
     #[serde(default = "default_data", skip_serializing_if = "is_default_data")]
     pub data: serde_json::Value,
@@ -139,6 +144,7 @@ impl Default for PolicyConfig {
             authorization_grant_entrypoint: default_authorization_grant_entrypoint(),
             password_entrypoint: default_password_entrypoint(),
             email_entrypoint: default_email_entrypoint(),
+            upstream_oauth2_mapping_entrypoint: None,
             data: default_data(),
         }
     }
@@ -153,6 +159,7 @@ impl PolicyConfig {
             && is_default_authorization_grant_entrypoint(&self.authorization_grant_entrypoint)
             && is_default_password_entrypoint(&self.password_entrypoint)
             && is_default_email_entrypoint(&self.email_entrypoint)
+            && self.upstream_oauth2_mapping_entrypoint.is_none()
             && is_default_data(&self.data)
     }
 }
@@ -9,6 +9,7 @@ use std::{num::NonZeroU32, time::Duration};
 use governor::Quota;
 use schemars::JsonSchema;
 use serde::{de::Error as _, Deserialize, Serialize};
+use serde_with::serde_as;
 
 use crate::ConfigurationSection;
 
@@ -47,6 +48,39 @@ pub struct LoginRateLimitingConfig {
     /// change their own password.
     #[serde(default = "default_login_per_account")]
     pub per_account: RateLimiterConfiguration,
+    /// Temporarily lock accounts out after too many failed login attempts.
+    ///
+    /// Unlike the rate limits above, failed attempts are tracked in the
+    /// database, so they are shared between all instances of the service and
+    /// survive restarts. Users are notified by email when their account gets
+    /// locked out.
+    ///
+    /// Disabled by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lockout: Option<LoginLockoutConfig>,
+}
+
+/// Configuration of the temporary lockout of accounts after repeated failed
+/// login attempts
+#[serde_as]
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct LoginLockoutConfig {
+    /// Number of failed login attempts after which the account is locked
+    /// out. Defaults to 10.
+    #[serde(default = "default_lockout_max_failed_attempts")]
+    pub max_failed_attempts: NonZeroU32,
+    /// Time window in seconds in which failed login attempts are counted.
+    /// Defaults to 15 minutes.
+    #[schemars(with = "u64", range(min = 1))]
+    #[serde(default = "default_lockout_window")]
+    #[serde_as(as = "serde_with::DurationSeconds<i64>")]
+    pub window: chrono::Duration,
+    /// How long the account stays locked out, in seconds. Defaults to 15
+    /// minutes.
+    #[schemars(with = "u64", range(min = 1))]
+    #[serde(default = "default_lockout_duration")]
+    #[serde_as(as = "serde_with::DurationSeconds<i64>")]
+    pub duration: chrono::Duration,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq)]
@@ -137,6 +171,19 @@ impl ConfigurationSection for RateLimitingConfig {
         if let Some(error) = error_on_limiter(&self.login.per_account) {
             return Err(error_on_nested_field(error, "login", "per_account"));
         }
+        if let Some(lockout) = &self.login.lockout {
+            if lockout.window <= chrono::Duration::zero()
+                || lockout.duration <= chrono::Duration::zero()
+            {
+                return Err(error_on_nested_field(
+                    figment::error::Error::custom(
+                        "`window` and `duration` must be greater than zero",
+                    ),
+                    "login",
+                    "lockout",
+                ));
+            }
+        }
 
         Ok(())
     }
@@ -172,6 +219,18 @@ fn default_login_per_account() -> RateLimiterConfiguration {
     }
 }
 
+fn default_lockout_max_failed_attempts() -> NonZeroU32 {
+    NonZeroU32::new(10).unwrap()
+}
+
+fn default_lockout_window() -> chrono::Duration {
+    chrono::Duration::minutes(15)
+}
+
+fn default_lockout_duration() -> chrono::Duration {
+    chrono::Duration::minutes(15)
+}
+
 fn default_registration() -> RateLimiterConfiguration {
     RateLimiterConfiguration {
         burst: NonZeroU32::new(3).unwrap(),
@@ -208,6 +267,7 @@ impl Default for LoginRateLimitingConfig {
         LoginRateLimitingConfig {
             per_ip: default_login_per_ip(),
             per_account: default_login_per_account(),
+            lockout: None,
         }
     }
 }
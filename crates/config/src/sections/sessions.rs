@@ -0,0 +1,202 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+use chrono::Duration;
+use schemars::JsonSchema;
+use serde::{de::Error, Deserialize, Serialize};
+use serde_with::serde_as;
+use ulid::Ulid;
+
+use crate::ConfigurationSection;
+
+/// Limits on how long sessions of a given type can live
+#[serde_as]
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+pub struct SessionExpirationConfig {
+    /// Maximum lifetime of a session in seconds, after which it is ended
+    /// regardless of its activity. Sessions live forever if not set.
+    #[schemars(with = "Option<u64>", range(min = 60))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[serde_as(as = "Option<serde_with::DurationSeconds<i64>>")]
+    pub max_lifetime: Option<Duration>,
+
+    /// Time in seconds after which an unused session is ended. Sessions are
+    /// never ended for inactivity if not set.
+    #[schemars(with = "Option<u64>", range(min = 60))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[serde_as(as = "Option<serde_with::DurationSeconds<i64>>")]
+    pub idle_timeout: Option<Duration>,
+}
+
+impl SessionExpirationConfig {
+    pub(crate) fn is_default(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// Get the name of the first limit which is too short, if any
+    fn invalid_field(&self) -> Option<&'static str> {
+        let minimum = Duration::minutes(1);
+
+        if self.max_lifetime.is_some_and(|d| d < minimum) {
+            return Some("max_lifetime");
+        }
+
+        if self.idle_timeout.is_some_and(|d| d < minimum) {
+            return Some("idle_timeout");
+        }
+
+        None
+    }
+}
+
+/// Limits on how long sessions of a specific OAuth 2.0 client can live
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+pub struct ClientSessionExpirationConfig {
+    /// The client ID
+    #[schemars(
+        with = "String",
+        regex(pattern = r"^[0123456789ABCDEFGHJKMNPQRSTVWXYZ]{26}$"),
+        description = "A ULID as per https://github.com/ulid/spec"
+    )]
+    pub client_id: Ulid,
+
+    /// Limits for the sessions of this client, replacing the ones set for all
+    /// OAuth 2.0 sessions
+    #[serde(flatten)]
+    pub expiration: SessionExpirationConfig,
+}
+
+/// Limits on how long OAuth 2.0 sessions can live
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+pub struct OAuth2SessionsConfig {
+    /// Limits for all OAuth 2.0 sessions, unless overridden for the client
+    #[serde(flatten)]
+    pub expiration: SessionExpirationConfig,
+
+    /// Per-client overrides
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub clients: Vec<ClientSessionExpirationConfig>,
+}
+
+impl OAuth2SessionsConfig {
+    pub(crate) fn is_default(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+/// Configuration section to automatically end sessions after some time, or
+/// when they are unused
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+pub struct SessionsConfig {
+    /// Limits on browser sessions, used to sign in to the service itself
+    #[serde(default, skip_serializing_if = "SessionExpirationConfig::is_default")]
+    pub browser: SessionExpirationConfig,
+
+    /// Limits on sessions created through the Matrix C-S API compatibility
+    /// layer
+    #[serde(default, skip_serializing_if = "SessionExpirationConfig::is_default")]
+    pub compat: SessionExpirationConfig,
+
+    /// Limits on OAuth 2.0 sessions
+    #[serde(default, skip_serializing_if = "OAuth2SessionsConfig::is_default")]
+    pub oauth2: OAuth2SessionsConfig,
+}
+
+impl SessionsConfig {
+    pub(crate) fn is_default(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+impl ConfigurationSection for SessionsConfig {
+    const PATH: Option<&'static str> = Some("sessions");
+
+    fn validate(&self, figment: &figment::Figment) -> Result<(), figment::Error> {
+        // Save the error location information in the error
+        let annotate = |path: &[&str], field: &str| {
+            let mut err = figment::error::Error::custom("must be at least 60 seconds");
+            err.metadata = figment.find_metadata(Self::PATH.unwrap()).cloned();
+            err.profile = Some(figment::Profile::Default);
+            err.path = std::iter::once(Self::PATH.unwrap())
+                .chain(path.iter().copied())
+                .chain(std::iter::once(field))
+                .map(ToOwned::to_owned)
+                .collect();
+            err
+        };
+
+        if let Some(field) = self.browser.invalid_field() {
+            return Err(annotate(&["browser"], field));
+        }
+
+        if let Some(field) = self.compat.invalid_field() {
+            return Err(annotate(&["compat"], field));
+        }
+
+        if let Some(field) = self.oauth2.expiration.invalid_field() {
+            return Err(annotate(&["oauth2"], field));
+        }
+
+        for (index, client) in self.oauth2.clients.iter().enumerate() {
+            if let Some(field) = client.expiration.invalid_field() {
+                return Err(annotate(&["oauth2", "clients", &index.to_string()], field));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use figment::{
+        providers::{Format, Yaml},
+        Figment, Jail,
+    };
+
+    use super::*;
+
+    #[test]
+    fn load_config() {
+        Jail::expect_with(|jail| {
+            jail.create_file(
+                "config.yaml",
+                r"
+                  sessions:
+                    browser:
+                      idle_timeout: 7776000
+                    oauth2:
+                      max_lifetime: 2592000
+                      clients:
+                        - client_id: 01GFWR28C4KNE04WG3HKXB7C9R
+                          idle_timeout: 3600
+                ",
+            )?;
+
+            let config = Figment::new()
+                .merge(Yaml::file("config.yaml"))
+                .extract_inner::<SessionsConfig>("sessions")?;
+
+            assert_eq!(config.browser.idle_timeout, Some(Duration::days(90)));
+            assert_eq!(config.browser.max_lifetime, None);
+            assert!(config.compat.is_default());
+            assert_eq!(
+                config.oauth2.expiration.max_lifetime,
+                Some(Duration::days(30))
+            );
+            assert_eq!(config.oauth2.clients.len(), 1);
+            assert_eq!(
+                config.oauth2.clients[0].client_id,
+                Ulid::from_string("01GFWR28C4KNE04WG3HKXB7C9R").unwrap()
+            );
+            assert_eq!(
+                config.oauth2.clients[0].expiration.idle_timeout,
+                Some(Duration::hours(1))
+            );
+
+            Ok(())
+        });
+    }
+}
@@ -32,7 +32,12 @@ impl UpstreamOAuth2Config {
 impl ConfigurationSection for UpstreamOAuth2Config {
     const PATH: Option<&'static str> = Some("upstream_oauth2");
 
+    #[allow(clippy::too_many_lines)]
     fn validate(&self, figment: &figment::Figment) -> Result<(), figment::Error> {
+        // Keep track of which provider each domain is mapped to, to make sure a domain
+        // doesn't point to multiple providers
+        let mut domains = std::collections::BTreeSet::new();
+
         for (index, provider) in self.providers.iter().enumerate() {
             let annotate = |mut error: figment::Error| {
                 error.metadata = figment
@@ -99,6 +104,52 @@ impl ConfigurationSection for UpstreamOAuth2Config {
                     }
                 }
             }
+
+            // Syncing on login only makes sense for claims which are forced on the user
+            if provider.claims_imports.displayname.sync_on_login
+                && !provider.claims_imports.displayname.action.is_forced()
+            {
+                return annotate(figment::Error::custom(
+                    "`claims_imports.displayname.sync_on_login` requires the action to be `force` or `require`",
+                ));
+            }
+
+            if provider.claims_imports.email.sync_on_login
+                && !provider.claims_imports.email.action.is_forced()
+            {
+                return annotate(figment::Error::custom(
+                    "`claims_imports.email.sync_on_login` requires the action to be `force` or `require`",
+                ));
+            }
+
+            if !provider.token_exchange_clients.is_empty() && !provider.store_tokens {
+                return annotate(figment::Error::custom(
+                    "`token_exchange_clients` requires `store_tokens` to be enabled",
+                ));
+            }
+
+            for domain in &provider.domains {
+                if domain.is_empty() || domain.contains('@') || domain.to_lowercase() != *domain {
+                    return annotate(figment::Error::custom(format!(
+                        "Invalid domain {domain:?} in `domains`, it must be a lowercase domain name without the `@`"
+                    )));
+                }
+
+                if !domains.insert(domain.as_str()) {
+                    return annotate(figment::Error::custom(format!(
+                        "Domain {domain:?} is used by multiple providers"
+                    )));
+                }
+            }
+
+            // Without the `openid` scope, the provider won't return an ID token, so the
+            // claims have to come from the userinfo endpoint
+            if !provider.fetch_userinfo && !provider.scope.split(' ').any(|scope| scope == "openid")
+            {
+                return annotate(figment::Error::custom(
+                    "The `openid` scope is required unless `fetch_userinfo` is enabled",
+                ));
+            }
         }
 
         Ok(())
@@ -178,6 +229,11 @@ impl ImportAction {
     const fn is_default(&self) -> bool {
         matches!(self, ImportAction::Ignore)
     }
+
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    const fn is_forced(&self) -> bool {
+        matches!(self, ImportAction::Force | ImportAction::Require)
+    }
 }
 
 /// Should the email address be marked as verified
@@ -251,11 +307,18 @@ pub struct DisplaynameImportPreference {
     /// If not provided, the default template is `{{ user.name }}`
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub template: Option<String>,
+
+    /// Whether the displayname should be updated from the upstream provider
+    /// every time the user logs in, and not only when the account is created.
+    ///
+    /// This requires the action to be `force` or `require`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub sync_on_login: bool,
 }
 
 impl DisplaynameImportPreference {
     const fn is_default(&self) -> bool {
-        self.action.is_default() && self.template.is_none()
+        self.action.is_default() && self.template.is_none() && !self.sync_on_login
     }
 }
 
@@ -275,6 +338,14 @@ pub struct EmailImportPreference {
     /// Should the email address be marked as verified
     #[serde(default, skip_serializing_if = "SetEmailVerification::is_default")]
     pub set_email_verification: SetEmailVerification,
+
+    /// Whether the email address should be updated from the upstream provider
+    /// every time the user logs in, and not only when the account is created.
+    /// The previously imported email address is removed when it changes.
+    ///
+    /// This requires the action to be `force` or `require`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub sync_on_login: bool,
 }
 
 impl EmailImportPreference {
@@ -282,6 +353,40 @@ impl EmailImportPreference {
         self.action.is_default()
             && self.template.is_none()
             && self.set_email_verification.is_default()
+            && !self.sync_on_login
+    }
+}
+
+/// How the groups or roles of the user are mapped
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default, JsonSchema)]
+pub struct GroupsImportPreference {
+    /// The Jinja2 template to use for the groups of the user. It should render
+    /// to a JSON list of strings, or to a single string.
+    ///
+    /// If not provided, the default template is `{{ user.groups | tojson }}`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+
+    /// Members of any of those groups can request admin access.
+    ///
+    /// If not empty, the ability of the user to request admin access is
+    /// updated every time they log in through the provider.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub admin_groups: Vec<String>,
+
+    /// Users have to be a member of at least one of those groups to log in
+    /// through the provider.
+    ///
+    /// Users who aren't a member of any of those groups can't register, and
+    /// existing users are locked when they log in without being a member of
+    /// any of them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub required_groups: Vec<String>,
+}
+
+impl GroupsImportPreference {
+    const fn is_default(&self) -> bool {
+        self.template.is_none() && self.admin_groups.is_empty() && self.required_groups.is_empty()
     }
 }
 
@@ -307,6 +412,14 @@ pub struct ClaimsImports {
     /// `email_verified` claims
     #[serde(default, skip_serializing_if = "EmailImportPreference::is_default")]
     pub email: EmailImportPreference,
+
+    /// Import the groups or roles of the user, to map them to admin rights,
+    /// lock users who aren't members of the required groups, and pass them to
+    /// the authorization grant policy.
+    ///
+    /// The groups are imported again every time the user logs in.
+    #[serde(default, skip_serializing_if = "GroupsImportPreference::is_default")]
+    pub groups: GroupsImportPreference,
 }
 
 impl ClaimsImports {
@@ -315,6 +428,7 @@ impl ClaimsImports {
             && self.localpart.is_default()
             && self.displayname.is_default()
             && self.email.is_default()
+            && self.groups.is_default()
     }
 }
 
@@ -477,6 +591,48 @@ pub struct Provider {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub token_endpoint: Option<Url>,
 
+    /// The URL to use for the provider's userinfo endpoint
+    ///
+    /// Defaults to the `userinfo_endpoint` provided through discovery
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub userinfo_endpoint: Option<Url>,
+
+    /// Whether to fetch the user claims from the userinfo endpoint
+    ///
+    /// This is needed for plain OAuth 2.0 providers which don't return an ID
+    /// token, like GitHub. The claims are available in templates as
+    /// `userinfo_claims`, and are merged with the ID token claims in `user`.
+    ///
+    /// Defaults to `false`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub fetch_userinfo: bool,
+
+    /// Whether to keep the access and refresh tokens issued by the provider
+    ///
+    /// The tokens are stored encrypted, and refreshed in the background before
+    /// they expire, so that they can be handed to the clients listed in
+    /// `token_exchange_clients`.
+    ///
+    /// Defaults to `false`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub store_tokens: bool,
+
+    /// The IDs of the OAuth 2.0 clients allowed to exchange their access
+    /// tokens for the user's access token at this provider
+    ///
+    /// This requires `store_tokens` to be enabled
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[schemars(with = "Vec<String>")]
+    pub token_exchange_clients: Vec<Ulid>,
+
+    /// The email domains for which users should be sent to this provider
+    ///
+    /// When set, the login page asks for the user's email address first, and
+    /// sends them to the provider matching its domain, instead of showing a
+    /// button for this provider. Each domain can only be used by one provider.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub domains: Vec<String>,
+
     /// The URL to use for getting the provider's public keys
     ///
     /// Defaults to the `jwks_uri` provided through discovery
@@ -487,8 +643,8 @@ pub struct Provider {
     #[serde(default, skip_serializing_if = "ResponseMode::is_default")]
     pub response_mode: ResponseMode,
 
-    /// How claims should be imported from the `id_token` provided by the
-    /// provider
+    /// How claims should be imported from the `id_token` and the userinfo
+    /// endpoint of the provider
     #[serde(default, skip_serializing_if = "ClaimsImports::is_default")]
     pub claims_imports: ClaimsImports,
 
@@ -0,0 +1,248 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+use std::collections::BTreeSet;
+
+use mas_iana::jose::JsonWebSignatureAlg;
+use schemars::JsonSchema;
+use serde::{de::Error, Deserialize, Serialize};
+use ulid::Ulid;
+use url::Url;
+
+use crate::ConfigurationSection;
+
+/// An event which can be sent to a webhook endpoint
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, JsonSchema,
+)]
+pub enum WebhookEventConfig {
+    /// A new user registered
+    #[serde(rename = "user.registered")]
+    UserRegistered,
+
+    /// A user verified one of their email addresses
+    #[serde(rename = "user.email_verified")]
+    UserEmailVerified,
+
+    /// A user was locked
+    #[serde(rename = "user.locked")]
+    UserLocked,
+
+    /// A user was deactivated
+    #[serde(rename = "user.deactivated")]
+    UserDeactivated,
+
+    /// A user started a new session
+    #[serde(rename = "session.started")]
+    SessionStarted,
+
+    /// A session of a user ended
+    #[serde(rename = "session.ended")]
+    SessionEnded,
+}
+
+/// How the requests sent to a webhook endpoint are signed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum WebhookSigningConfig {
+    /// `hmac`: the request body is the JSON event. The `X-MAS-Timestamp`
+    /// header holds the Unix timestamp of the request, and the
+    /// `X-MAS-Signature` header holds the HMAC-SHA256 of the timestamp and
+    /// the body joined with a dot, hex-encoded and prefixed with `sha256=`
+    Hmac {
+        /// The secret shared with the endpoint
+        secret: String,
+    },
+
+    /// `jws`: the request body is a JWT which claims are the event, signed
+    /// with a key of the keystore. It can be verified using the keys
+    /// published at the JWKS endpoint.
+    Jws {
+        /// The algorithm used to sign the request. Defaults to `RS256`
+        #[serde(default = "default_jws_algorithm")]
+        algorithm: JsonWebSignatureAlg,
+    },
+}
+
+fn default_jws_algorithm() -> JsonWebSignatureAlg {
+    JsonWebSignatureAlg::Rs256
+}
+
+/// An endpoint which receives webhook events
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct WebhookEndpointConfig {
+    /// An internal unique identifier for this endpoint
+    #[schemars(
+        with = "String",
+        regex(pattern = r"^[0123456789ABCDEFGHJKMNPQRSTVWXYZ]{26}$"),
+        description = "A ULID as per https://github.com/ulid/spec"
+    )]
+    pub id: Ulid,
+
+    /// The URL to which the events are sent with a `POST` request
+    pub url: Url,
+
+    /// The events sent to this endpoint. All events are sent if empty.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub events: BTreeSet<WebhookEventConfig>,
+
+    /// How the requests are signed
+    pub signing: WebhookSigningConfig,
+}
+
+/// Configuration of the webhooks, which notify external systems of events
+/// like user registrations or deactivations
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct WebhooksConfig {
+    /// The endpoints which receive the events
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub endpoints: Vec<WebhookEndpointConfig>,
+}
+
+impl WebhookEndpointConfig {
+    fn validate(&self) -> Result<(), figment::error::Error> {
+        if !matches!(self.url.scheme(), "http" | "https") {
+            let error = figment::error::Error::custom("webhook URL must use http or https");
+            return Err(error.with_path("url"));
+        }
+
+        if let WebhookSigningConfig::Jws { algorithm } = &self.signing {
+            if matches!(
+                algorithm,
+                JsonWebSignatureAlg::None
+                    | JsonWebSignatureAlg::Hs256
+                    | JsonWebSignatureAlg::Hs384
+                    | JsonWebSignatureAlg::Hs512
+            ) {
+                let error = figment::error::Error::custom(format!(
+                    "{algorithm} can't be used with the jws method, use the hmac method instead"
+                ));
+                return Err(error.with_path("signing"));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl WebhooksConfig {
+    pub(crate) fn is_default(&self) -> bool {
+        self.endpoints.is_empty()
+    }
+}
+
+impl ConfigurationSection for WebhooksConfig {
+    const PATH: Option<&'static str> = Some("webhooks");
+
+    fn validate(&self, figment: &figment::Figment) -> Result<(), figment::error::Error> {
+        let mut seen = BTreeSet::new();
+        for (index, endpoint) in self.endpoints.iter().enumerate() {
+            let res = if seen.insert(endpoint.id) {
+                endpoint.validate()
+            } else {
+                Err(figment::error::Error::custom("duplicate webhook endpoint ID").with_path("id"))
+            };
+
+            res.map_err(|mut err| {
+                // Save the error location information in the error
+                err.metadata = figment.find_metadata(Self::PATH.unwrap()).cloned();
+                err.profile = Some(figment::Profile::Default);
+                err.path.insert(0, Self::PATH.unwrap().to_owned());
+                err.path.insert(1, "endpoints".to_owned());
+                err.path.insert(2, format!("{index}"));
+                err
+            })?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use figment::{
+        providers::{Format, Yaml},
+        Figment, Jail,
+    };
+
+    use super::*;
+
+    #[test]
+    fn load_config() {
+        Jail::expect_with(|jail| {
+            jail.create_file(
+                "config.yaml",
+                r"
+                  webhooks:
+                    endpoints:
+                      - id: 01JEXZ5YB2FDSHAKSPGMD4P6E5
+                        url: https://crm.example.com/hooks/mas
+                        events:
+                          - user.registered
+                          - user.deactivated
+                        signing:
+                          method: hmac
+                          secret: hunter2
+                      - id: 01JEXZ69P4ZC7B4A5KPVDV4M80
+                        url: https://billing.example.com/mas
+                        signing:
+                          method: jws
+                ",
+            )?;
+
+            let figment = Figment::new().merge(Yaml::file("config.yaml"));
+            let config = figment.extract_inner::<WebhooksConfig>("webhooks")?;
+            config.validate(&figment)?;
+
+            assert_eq!(config.endpoints.len(), 2);
+            assert_eq!(
+                config.endpoints[0].events,
+                BTreeSet::from([
+                    WebhookEventConfig::UserRegistered,
+                    WebhookEventConfig::UserDeactivated
+                ])
+            );
+            assert_eq!(
+                config.endpoints[0].signing,
+                WebhookSigningConfig::Hmac {
+                    secret: "hunter2".to_owned()
+                }
+            );
+            assert!(config.endpoints[1].events.is_empty());
+            assert_eq!(
+                config.endpoints[1].signing,
+                WebhookSigningConfig::Jws {
+                    algorithm: JsonWebSignatureAlg::Rs256
+                }
+            );
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn reject_symmetric_jws() {
+        Jail::expect_with(|jail| {
+            jail.create_file(
+                "config.yaml",
+                r"
+                  webhooks:
+                    endpoints:
+                      - id: 01JEXZ5YB2FDSHAKSPGMD4P6E5
+                        url: https://crm.example.com/hooks/mas
+                        signing:
+                          method: jws
+                          algorithm: HS256
+                ",
+            )?;
+
+            let figment = Figment::new().merge(Yaml::file("config.yaml"));
+            let config = figment.extract_inner::<WebhooksConfig>("webhooks")?;
+            assert!(config.validate(&figment).is_err());
+
+            Ok(())
+        });
+    }
+}
@@ -0,0 +1,164 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+use std::net::IpAddr;
+
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use ulid::Ulid;
+
+/// The kind of a security-relevant event recorded in the audit log
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AuditLogEventKind {
+    /// A user successfully logged in
+    #[serde(rename = "login.succeeded")]
+    LoginSucceeded,
+
+    /// A login attempt failed
+    #[serde(rename = "login.failed")]
+    LoginFailed,
+
+    /// The password of a user was changed or reset
+    #[serde(rename = "password.changed")]
+    PasswordChanged,
+
+    /// An email address was added to a user
+    #[serde(rename = "email.added")]
+    EmailAdded,
+
+    /// An email address was removed from a user
+    #[serde(rename = "email.removed")]
+    EmailRemoved,
+
+    /// The primary email address of a user changed
+    #[serde(rename = "email.primary_changed")]
+    PrimaryEmailChanged,
+
+    /// A session was created
+    #[serde(rename = "session.created")]
+    SessionCreated,
+
+    /// A session was ended
+    #[serde(rename = "session.finished")]
+    SessionFinished,
+
+    /// An operation was done through the admin API
+    #[serde(rename = "admin.action")]
+    AdminAction,
+
+    /// The policy denied a request
+    #[serde(rename = "policy.denied")]
+    PolicyDenied,
+}
+
+#[derive(Debug, Clone, Error)]
+#[error("Invalid audit log event kind {0:?}")]
+pub struct InvalidAuditLogEventKindError(String);
+
+impl std::str::FromStr for AuditLogEventKind {
+    type Err = InvalidAuditLogEventKindError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "login.succeeded" => Ok(Self::LoginSucceeded),
+            "login.failed" => Ok(Self::LoginFailed),
+            "password.changed" => Ok(Self::PasswordChanged),
+            "email.added" => Ok(Self::EmailAdded),
+            "email.removed" => Ok(Self::EmailRemoved),
+            "email.primary_changed" => Ok(Self::PrimaryEmailChanged),
+            "session.created" => Ok(Self::SessionCreated),
+            "session.finished" => Ok(Self::SessionFinished),
+            "admin.action" => Ok(Self::AdminAction),
+            "policy.denied" => Ok(Self::PolicyDenied),
+            s => Err(InvalidAuditLogEventKindError(s.to_owned())),
+        }
+    }
+}
+
+impl AuditLogEventKind {
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::LoginSucceeded => "login.succeeded",
+            Self::LoginFailed => "login.failed",
+            Self::PasswordChanged => "password.changed",
+            Self::EmailAdded => "email.added",
+            Self::EmailRemoved => "email.removed",
+            Self::PrimaryEmailChanged => "email.primary_changed",
+            Self::SessionCreated => "session.created",
+            Self::SessionFinished => "session.finished",
+            Self::AdminAction => "admin.action",
+            Self::PolicyDenied => "policy.denied",
+        }
+    }
+}
+
+impl std::fmt::Display for AuditLogEventKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A security-relevant event, recorded in the append-only audit log
+///
+/// Events reference users and sessions by ID only, so that they outlive them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AuditLogEvent {
+    pub id: Ulid,
+    pub created_at: DateTime<Utc>,
+    pub kind: AuditLogEventKind,
+
+    /// The user who did the action, if any
+    pub actor_user_id: Option<Ulid>,
+
+    /// The OAuth 2.0 session which did the action, for actions done through an
+    /// API, like the admin API
+    pub actor_session_id: Option<Ulid>,
+
+    /// The user affected by the action, if any
+    pub user_id: Option<Ulid>,
+
+    pub ip_address: Option<IpAddr>,
+    pub user_agent: Option<String>,
+
+    /// Additional details, which depend on the kind of event
+    pub data: serde_json::Value,
+}
+
+impl AuditLogEvent {
+    #[doc(hidden)]
+    #[must_use]
+    pub fn samples(now: chrono::DateTime<Utc>, rng: &mut impl Rng) -> Vec<Self> {
+        let user_id = Ulid::from_datetime_with_source(now.into(), rng);
+        let admin_session_id = Ulid::from_datetime_with_source(now.into(), rng);
+
+        vec![
+            Self {
+                id: Ulid::from_datetime_with_source(now.into(), rng),
+                created_at: now,
+                kind: AuditLogEventKind::LoginFailed,
+                actor_user_id: None,
+                actor_session_id: None,
+                user_id: Some(user_id),
+                ip_address: Some("1.2.3.4".parse().unwrap()),
+                user_agent: Some("Mozilla/5.0".to_owned()),
+                data: serde_json::json!({ "method": "password", "username": "alice" }),
+            },
+            Self {
+                id: Ulid::from_datetime_with_source(now.into(), rng),
+                created_at: now,
+                kind: AuditLogEventKind::AdminAction,
+                actor_user_id: None,
+                actor_session_id: Some(admin_session_id),
+                user_id: Some(user_id),
+                ip_address: Some("5.6.7.8".parse().unwrap()),
+                user_agent: None,
+                data: serde_json::json!({ "operation": "lockUser", "resource_id": user_id }),
+            },
+        ]
+    }
+}
@@ -8,13 +8,16 @@
 
 use thiserror::Error;
 
+pub(crate) mod audit_log;
 pub(crate) mod compat;
 pub mod oauth2;
 mod site_config;
 pub(crate) mod tokens;
 pub(crate) mod upstream_oauth2;
+pub(crate) mod usage_stats;
 pub(crate) mod user_agent;
 pub(crate) mod users;
+pub(crate) mod webhook;
 
 /// Error when an invalid state transition is attempted.
 #[derive(Debug, Error)]
@@ -24,6 +27,7 @@ pub struct InvalidTransitionError;
 pub use ulid::Ulid;
 
 pub use self::{
+    audit_log::{AuditLogEvent, AuditLogEventKind, InvalidAuditLogEventKindError},
     compat::{
         CompatAccessToken, CompatRefreshToken, CompatRefreshTokenState, CompatSession,
         CompatSessionState, CompatSsoLogin, CompatSsoLoginState, Device,
@@ -32,21 +36,31 @@ pub use self::{
         AuthorizationCode, AuthorizationGrant, AuthorizationGrantStage, Client, DeviceCodeGrant,
         DeviceCodeGrantState, InvalidRedirectUriError, JwksOrJwksUri, Pkce, Session, SessionState,
     },
-    site_config::{CaptchaConfig, CaptchaService, SiteConfig},
+    site_config::{
+        CaptchaConfig, CaptchaService, LoginLockoutConfig, SessionExpiration,
+        SessionExpirationConfig, SiteConfig,
+    },
     tokens::{
         AccessToken, AccessTokenState, RefreshToken, RefreshTokenState, TokenFormatError, TokenType,
     },
     upstream_oauth2::{
         UpsreamOAuthProviderSetEmailVerification, UpstreamOAuthAuthorizationSession,
-        UpstreamOAuthAuthorizationSessionState, UpstreamOAuthLink, UpstreamOAuthProvider,
-        UpstreamOAuthProviderClaimsImports, UpstreamOAuthProviderDiscoveryMode,
+        UpstreamOAuthAuthorizationSessionState, UpstreamOAuthLink, UpstreamOAuthLinkTokens,
+        UpstreamOAuthProvider, UpstreamOAuthProviderClaimsImports,
+        UpstreamOAuthProviderDiscoveryMode, UpstreamOAuthProviderGroupsPreference,
         UpstreamOAuthProviderImportAction, UpstreamOAuthProviderImportPreference,
         UpstreamOAuthProviderPkceMode, UpstreamOAuthProviderResponseMode,
         UpstreamOAuthProviderSubjectPreference, UpstreamOAuthProviderTokenAuthMethod,
     },
+    usage_stats::UsageStats,
     user_agent::{DeviceType, UserAgent},
     users::{
         Authentication, AuthenticationMethod, BrowserSession, Password, User, UserEmail,
-        UserEmailVerification, UserEmailVerificationState, UserRecoverySession, UserRecoveryTicket,
+        UserEmailVerification, UserEmailVerificationState, UserLoginLockout, UserRecoverySession,
+        UserRecoveryTicket, UserRegistrationToken,
+    },
+    webhook::{
+        InvalidWebhookEventKindError, WebhookDelivery, WebhookEndpoint, WebhookEventKind,
+        WebhookSigning,
     },
 };
@@ -83,6 +83,10 @@ pub struct Client {
     /// URI using the https scheme that a third party can use to initiate a
     /// login by the RP
     pub initiate_login_uri: Option<Url>,
+
+    /// Whether this client is defined in the configuration file, in which case
+    /// it is overwritten every time the configuration is synced
+    pub is_static: bool,
 }
 
 #[derive(Debug, Error)]
@@ -196,6 +200,7 @@ impl Client {
                 id_token_signed_response_alg: None,
                 userinfo_signed_response_alg: None,
                 jwks: None,
+                is_static: false,
             },
             // Another client without any URIs set
             Self {
@@ -216,6 +221,7 @@ impl Client {
                 id_token_signed_response_alg: None,
                 userinfo_signed_response_alg: None,
                 jwks: None,
+                is_static: false,
             },
         ]
     }
@@ -4,7 +4,10 @@
 // SPDX-License-Identifier: AGPL-3.0-only
 // Please see LICENSE in the repository root for full details.
 
+use std::collections::BTreeMap;
+
 use chrono::Duration;
+use ulid::Ulid;
 use url::Url;
 
 /// Which Captcha service is being used
@@ -28,6 +31,53 @@ pub struct CaptchaConfig {
     pub secret_key: String,
 }
 
+/// Configuration of the lockout of accounts after repeated failed logins
+#[derive(Debug, Clone, Copy)]
+pub struct LoginLockoutConfig {
+    /// Number of failed login attempts after which the account is locked out
+    pub max_failed_attempts: u32,
+
+    /// Failed attempts older than this are forgotten
+    pub window: Duration,
+
+    /// How long the account stays locked out
+    pub duration: Duration,
+}
+
+/// Limits on how long sessions of a given type can live
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SessionExpiration {
+    /// Sessions are ended after this time, regardless of their activity
+    pub max_lifetime: Option<Duration>,
+
+    /// Sessions are ended if they weren't used for this long
+    pub idle_timeout: Option<Duration>,
+}
+
+impl SessionExpiration {
+    /// Whether sessions expire at all with those limits
+    #[must_use]
+    pub fn is_enabled(&self) -> bool {
+        self.max_lifetime.is_some() || self.idle_timeout.is_some()
+    }
+}
+
+/// Configuration of the automatic expiration of sessions
+#[derive(Debug, Clone, Default)]
+pub struct SessionExpirationConfig {
+    /// Limits on browser sessions
+    pub browser: SessionExpiration,
+
+    /// Limits on compatibility sessions
+    pub compat: SessionExpiration,
+
+    /// Limits on OAuth 2.0 sessions, unless overridden for the client
+    pub oauth2: SessionExpiration,
+
+    /// Limits on OAuth 2.0 sessions of specific clients
+    pub oauth2_clients: BTreeMap<Ulid, SessionExpiration>,
+}
+
 /// Random site configuration we want accessible in various places.
 #[allow(clippy::struct_excessive_bools)]
 #[derive(Debug, Clone)]
@@ -56,6 +106,9 @@ pub struct SiteConfig {
     /// Whether password registration is enabled.
     pub password_registration_enabled: bool,
 
+    /// Whether a registration token is required to create an account.
+    pub registration_token_required: bool,
+
     /// Whether users can change their email.
     pub email_change_allowed: bool,
 
@@ -74,4 +127,7 @@ pub struct SiteConfig {
     /// Minimum password complexity, between 0 and 4.
     /// This is a score from zxcvbn.
     pub minimum_password_complexity: u8,
+
+    /// Lockout of accounts after repeated failed logins, if enabled
+    pub login_lockout: Option<LoginLockoutConfig>,
 }
@@ -14,5 +14,34 @@ pub struct UpstreamOAuthLink {
     pub provider_id: Ulid,
     pub user_id: Option<Ulid>,
     pub subject: String,
+    pub imported_user_email_id: Option<Ulid>,
+
+    /// The groups the upstream provider claimed the user is a member of, the
+    /// last time they logged in through this link
+    pub groups: Vec<String>,
+
     pub created_at: DateTime<Utc>,
 }
+
+/// The tokens issued by the upstream provider for a link
+///
+/// The tokens are kept encrypted, and are only stored if the provider is
+/// configured to do so.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UpstreamOAuthLinkTokens {
+    pub link_id: Ulid,
+    pub encrypted_access_token: String,
+    pub encrypted_refresh_token: Option<String>,
+    pub access_token_expires_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl UpstreamOAuthLinkTokens {
+    /// Whether the access token is known to have expired at the given time
+    #[must_use]
+    pub fn is_access_token_expired(&self, now: DateTime<Utc>) -> bool {
+        self.access_token_expires_at
+            .is_some_and(|expires_at| expires_at <= now)
+    }
+}
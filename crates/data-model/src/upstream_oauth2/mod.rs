@@ -9,10 +9,11 @@ mod provider;
 mod session;
 
 pub use self::{
-    link::UpstreamOAuthLink,
+    link::{UpstreamOAuthLink, UpstreamOAuthLinkTokens},
     provider::{
         ClaimsImports as UpstreamOAuthProviderClaimsImports,
         DiscoveryMode as UpstreamOAuthProviderDiscoveryMode,
+        GroupsPreference as UpstreamOAuthProviderGroupsPreference,
         ImportAction as UpstreamOAuthProviderImportAction,
         ImportPreference as UpstreamOAuthProviderImportPreference,
         PkceMode as UpstreamOAuthProviderPkceMode,
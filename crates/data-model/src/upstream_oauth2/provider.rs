@@ -228,6 +228,11 @@ pub struct UpstreamOAuthProvider {
     pub authorization_endpoint_override: Option<Url>,
     pub scope: Scope,
     pub token_endpoint_override: Option<Url>,
+    pub userinfo_endpoint_override: Option<Url>,
+    pub fetch_userinfo: bool,
+    pub store_tokens: bool,
+    pub token_exchange_clients: Vec<Ulid>,
+    pub domains: Vec<String>,
     pub client_id: String,
     pub encrypted_client_secret: Option<String>,
     pub token_endpoint_signing_alg: Option<JsonWebSignatureAlg>,
@@ -301,6 +306,9 @@ pub struct ClaimsImports {
 
     #[serde(default)]
     pub verify_email: SetEmailVerification,
+
+    #[serde(default)]
+    pub groups: GroupsPreference,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
@@ -309,6 +317,49 @@ pub struct SubjectPreference {
     pub template: Option<String>,
 }
 
+/// How the groups claimed by the upstream provider are mapped
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct GroupsPreference {
+    #[serde(default)]
+    pub template: Option<String>,
+
+    /// Members of any of those groups can request admin access
+    #[serde(default)]
+    pub admin_groups: Vec<String>,
+
+    /// Users must be a member of at least one of those groups
+    #[serde(default)]
+    pub required_groups: Vec<String>,
+}
+
+impl GroupsPreference {
+    /// Whether the groups should be imported at all
+    #[must_use]
+    pub fn enabled(&self) -> bool {
+        self.template.is_some() || !self.admin_groups.is_empty() || !self.required_groups.is_empty()
+    }
+
+    /// Whether a user with the given groups can request admin access, if this
+    /// should be managed by the provider
+    #[must_use]
+    pub fn can_request_admin(&self, groups: &[String]) -> Option<bool> {
+        if self.admin_groups.is_empty() {
+            return None;
+        }
+
+        Some(groups.iter().any(|group| self.admin_groups.contains(group)))
+    }
+
+    /// Whether a user with the given groups is allowed to log in
+    #[must_use]
+    pub fn is_allowed(&self, groups: &[String]) -> bool {
+        self.required_groups.is_empty()
+            || groups
+                .iter()
+                .any(|group| self.required_groups.contains(group))
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
 pub struct ImportPreference {
     #[serde(default)]
@@ -316,6 +367,10 @@ pub struct ImportPreference {
 
     #[serde(default)]
     pub template: Option<String>,
+
+    /// Whether the claim should be updated every time the user logs in
+    #[serde(default)]
+    pub sync_on_login: bool,
 }
 
 impl std::ops::Deref for ImportPreference {
@@ -0,0 +1,48 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+use ulid::Ulid;
+
+/// Usage statistics of the service over a day
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UsageStats {
+    pub id: Ulid,
+
+    /// The day, in UTC, the statistics are about
+    pub day: NaiveDate,
+
+    /// Number of users with a session active during the day
+    pub daily_active_users: u64,
+
+    /// Number of users with a session active during the 30 days up to the end
+    /// of the day
+    pub monthly_active_users: u64,
+
+    /// Number of users who registered during the day
+    pub registrations: u64,
+
+    /// Number of logins with a password during the day
+    pub password_logins: u64,
+
+    /// Number of logins through an upstream OAuth 2.0 provider during the day
+    pub upstream_oauth2_logins: u64,
+
+    /// Number of logins through the compatibility layer during the day
+    pub compat_logins: u64,
+
+    /// When the statistics were last computed
+    pub computed_at: DateTime<Utc>,
+}
+
+impl UsageStats {
+    /// Whether the statistics were computed after the end of the day, meaning
+    /// they won't change anymore
+    #[must_use]
+    pub fn is_final(&self) -> bool {
+        self.computed_at.date_naive() > self.day
+    }
+}
@@ -7,7 +7,10 @@
 use std::{net::IpAddr, ops::Deref};
 
 use chrono::{DateTime, Duration, Utc};
-use rand::{Rng, SeedableRng};
+use rand::{
+    distributions::{Alphanumeric, DistString},
+    Rng, RngCore, SeedableRng,
+};
 use serde::Serialize;
 use ulid::Ulid;
 
@@ -76,10 +79,13 @@ pub enum AuthenticationMethod {
 /// For each session intiated, there may be multiple [`UserRecoveryTicket`]s
 /// sent to the user, either because multiple [`User`] have the same email
 /// address, or because the user asked to send the recovery email again.
+///
+/// Sessions started by signing in with a linked upstream provider have no
+/// email address, and a single ticket issued for the upstream link.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 pub struct UserRecoverySession {
     pub id: Ulid,
-    pub email: String,
+    pub email: Option<String>,
     pub user_agent: UserAgent,
     pub ip_address: Option<IpAddr>,
     pub locale: String,
@@ -92,11 +98,16 @@ pub struct UserRecoverySession {
 /// Whenever a new recovery session is initiated, a new ticket is created for
 /// each email address matching in the database. That ticket is sent by email,
 /// as a link that the user can click to recover their account.
+///
+/// Tickets can also be issued for an upstream link, after the user signed in
+/// with the upstream provider, in which case they have no email address.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 pub struct UserRecoveryTicket {
     pub id: Ulid,
     pub user_recovery_session_id: Ulid,
-    pub user_email_id: Ulid,
+    pub user_id: Ulid,
+    pub user_email_id: Option<Ulid>,
+    pub upstream_oauth_link_id: Option<Ulid>,
     pub ticket: String,
     pub created_at: DateTime<Utc>,
     pub expires_at: DateTime<Utc>,
@@ -109,6 +120,89 @@ impl UserRecoveryTicket {
     }
 }
 
+/// Failed login attempts on a [`User`] account, and whether it is temporarily
+/// locked out because of them
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UserLoginLockout {
+    pub user_id: Ulid,
+    pub failed_attempts: u32,
+    pub last_failed_at: DateTime<Utc>,
+    pub locked_until: Option<DateTime<Utc>>,
+}
+
+impl UserLoginLockout {
+    /// Returns `true` if the account is locked out at the given time
+    #[must_use]
+    pub fn is_locked(&self, now: DateTime<Utc>) -> bool {
+        self.locked_until
+            .is_some_and(|locked_until| now < locked_until)
+    }
+}
+
+const GENERATED_REGISTRATION_TOKEN_LENGTH: usize = 16;
+const MAX_REGISTRATION_TOKEN_LENGTH: usize = 64;
+
+/// A token which can be required to register a new account
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UserRegistrationToken {
+    pub id: Ulid,
+    pub token: String,
+
+    /// How many times the token can be used, `None` for unlimited
+    pub usage_limit: Option<u32>,
+
+    /// How many times the token was used
+    pub times_used: u32,
+
+    /// How many of the users who registered with the token haven't verified
+    /// an email address yet
+    pub pending_uses: u32,
+
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl UserRegistrationToken {
+    /// Generate a random token
+    pub fn generate_token<R: RngCore + ?Sized>(rng: &mut R) -> String {
+        Alphanumeric.sample_string(rng, GENERATED_REGISTRATION_TOKEN_LENGTH)
+    }
+
+    /// Returns `true` if the given string can be used as a token
+    ///
+    /// Like in Synapse, tokens are at most 64 characters long, and only use
+    /// unreserved URI characters.
+    #[must_use]
+    pub fn is_token_well_formed(token: &str) -> bool {
+        !token.is_empty()
+            && token.len() <= MAX_REGISTRATION_TOKEN_LENGTH
+            && token
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '~' | '-'))
+    }
+
+    /// Returns `true` if the token was used as many times as allowed
+    #[must_use]
+    pub fn is_exhausted(&self) -> bool {
+        self.usage_limit
+            .is_some_and(|usage_limit| self.times_used >= usage_limit)
+    }
+
+    /// Returns `true` if the token has expired at the given time
+    #[must_use]
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_some_and(|expires_at| now >= expires_at)
+    }
+
+    /// Returns `true` if the token can be used to register at the given time
+    #[must_use]
+    pub fn is_valid(&self, now: DateTime<Utc>) -> bool {
+        self.revoked_at.is_none() && !self.is_expired(now) && !self.is_exhausted()
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 pub struct BrowserSession {
     pub id: Ulid,
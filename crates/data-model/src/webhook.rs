@@ -0,0 +1,152 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+use std::collections::BTreeSet;
+
+use chrono::{DateTime, Utc};
+use mas_iana::jose::JsonWebSignatureAlg;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use ulid::Ulid;
+use url::Url;
+
+/// The kind of an event sent to webhook endpoints
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum WebhookEventKind {
+    /// A new user registered
+    #[serde(rename = "user.registered")]
+    UserRegistered,
+
+    /// A user verified one of their email addresses
+    #[serde(rename = "user.email_verified")]
+    UserEmailVerified,
+
+    /// A user was locked
+    #[serde(rename = "user.locked")]
+    UserLocked,
+
+    /// A user was deactivated
+    #[serde(rename = "user.deactivated")]
+    UserDeactivated,
+
+    /// A user started a new session
+    #[serde(rename = "session.started")]
+    SessionStarted,
+
+    /// A session of a user ended
+    #[serde(rename = "session.ended")]
+    SessionEnded,
+}
+
+#[derive(Debug, Clone, Error)]
+#[error("Invalid webhook event kind {0:?}")]
+pub struct InvalidWebhookEventKindError(String);
+
+impl std::str::FromStr for WebhookEventKind {
+    type Err = InvalidWebhookEventKindError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "user.registered" => Ok(Self::UserRegistered),
+            "user.email_verified" => Ok(Self::UserEmailVerified),
+            "user.locked" => Ok(Self::UserLocked),
+            "user.deactivated" => Ok(Self::UserDeactivated),
+            "session.started" => Ok(Self::SessionStarted),
+            "session.ended" => Ok(Self::SessionEnded),
+            s => Err(InvalidWebhookEventKindError(s.to_owned())),
+        }
+    }
+}
+
+impl WebhookEventKind {
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::UserRegistered => "user.registered",
+            Self::UserEmailVerified => "user.email_verified",
+            Self::UserLocked => "user.locked",
+            Self::UserDeactivated => "user.deactivated",
+            Self::SessionStarted => "session.started",
+            Self::SessionEnded => "session.ended",
+        }
+    }
+}
+
+impl std::fmt::Display for WebhookEventKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// How the requests sent to a webhook endpoint are signed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WebhookSigning {
+    /// The body is signed with HMAC-SHA256, using a secret shared with the
+    /// endpoint
+    Hmac { secret: String },
+
+    /// The body is a JWS, signed with a key from the keystore
+    Jws { algorithm: JsonWebSignatureAlg },
+}
+
+/// An endpoint which receives webhook events, as configured
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebhookEndpoint {
+    pub id: Ulid,
+    pub url: Url,
+
+    /// The events sent to this endpoint. All events are sent if empty.
+    pub events: BTreeSet<WebhookEventKind>,
+
+    pub signing: WebhookSigning,
+}
+
+impl WebhookEndpoint {
+    /// Whether this endpoint should receive events of the given kind
+    #[must_use]
+    pub fn wants(&self, kind: WebhookEventKind) -> bool {
+        self.events.is_empty() || self.events.contains(&kind)
+    }
+}
+
+/// The delivery of an event to a webhook endpoint, with the outcome of the
+/// attempts made so far
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct WebhookDelivery {
+    pub id: Ulid,
+
+    /// The ID of the endpoint, as configured
+    pub endpoint_id: Ulid,
+
+    pub kind: WebhookEventKind,
+
+    /// The event sent to the endpoint
+    pub payload: serde_json::Value,
+
+    pub created_at: DateTime<Utc>,
+
+    /// How many times the delivery was attempted
+    pub attempts: u32,
+
+    pub last_attempted_at: Option<DateTime<Utc>>,
+
+    /// The error of the last failed attempt
+    pub last_error: Option<String>,
+
+    /// When the endpoint acknowledged the event
+    pub delivered_at: Option<DateTime<Utc>>,
+
+    /// When we gave up on delivering the event
+    pub failed_at: Option<DateTime<Utc>>,
+}
+
+impl WebhookDelivery {
+    /// Whether the delivery is still pending, i.e. neither delivered nor
+    /// given up on
+    #[must_use]
+    pub fn is_pending(&self) -> bool {
+        self.delivered_at.is_none() && self.failed_at.is_none()
+    }
+}
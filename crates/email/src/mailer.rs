@@ -10,7 +10,10 @@ use lettre::{
     message::{Mailbox, MessageBuilder, MultiPart},
     AsyncTransport, Message,
 };
-use mas_templates::{EmailRecoveryContext, EmailVerificationContext, Templates, WithLanguage};
+use mas_templates::{
+    EmailLoginLockoutContext, EmailNewSignInContext, EmailRecoveryContext,
+    EmailVerificationContext, Templates, WithLanguage,
+};
 use thiserror::Error;
 
 use crate::MailTransport;
@@ -99,6 +102,50 @@ impl Mailer {
         Ok(message)
     }
 
+    fn prepare_login_lockout_email(
+        &self,
+        to: Mailbox,
+        context: &WithLanguage<EmailLoginLockoutContext>,
+    ) -> Result<Message, Error> {
+        let plain = self.templates.render_email_login_lockout_txt(context)?;
+
+        let html = self.templates.render_email_login_lockout_html(context)?;
+
+        let multipart = MultiPart::alternative_plain_html(plain, html);
+
+        let subject = self.templates.render_email_login_lockout_subject(context)?;
+
+        let message = self
+            .base_message()
+            .subject(subject.trim())
+            .to(to)
+            .multipart(multipart)?;
+
+        Ok(message)
+    }
+
+    fn prepare_new_sign_in_email(
+        &self,
+        to: Mailbox,
+        context: &WithLanguage<EmailNewSignInContext>,
+    ) -> Result<Message, Error> {
+        let plain = self.templates.render_email_new_sign_in_txt(context)?;
+
+        let html = self.templates.render_email_new_sign_in_html(context)?;
+
+        let multipart = MultiPart::alternative_plain_html(plain, html);
+
+        let subject = self.templates.render_email_new_sign_in_subject(context)?;
+
+        let message = self
+            .base_message()
+            .subject(subject.trim())
+            .to(to)
+            .multipart(multipart)?;
+
+        Ok(message)
+    }
+
     /// Send the verification email to a user
     ///
     /// # Errors
@@ -152,6 +199,58 @@ impl Mailer {
         Ok(())
     }
 
+    /// Tell a user that their account was locked out after too many failed
+    /// login attempts
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the email failed rendering or failed sending
+    #[tracing::instrument(
+        name = "email.login_lockout.send",
+        skip_all,
+        fields(
+            email.to = %to,
+            email.language = %context.language(),
+            user.id = %context.user().id,
+        ),
+        err,
+    )]
+    pub async fn send_login_lockout_email(
+        &self,
+        to: Mailbox,
+        context: &WithLanguage<EmailLoginLockoutContext>,
+    ) -> Result<(), Error> {
+        let message = self.prepare_login_lockout_email(to, context)?;
+        self.transport.send(message).await?;
+        Ok(())
+    }
+
+    /// Send an email to a user telling them about a new sign-in to their
+    /// account
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the email failed rendering or failed sending
+    #[tracing::instrument(
+        name = "email.new_sign_in.send",
+        skip_all,
+        fields(
+            email.to = %to,
+            email.language = %context.language(),
+            user.id = %context.user().id,
+        ),
+        err,
+    )]
+    pub async fn send_new_sign_in_email(
+        &self,
+        to: Mailbox,
+        context: &WithLanguage<EmailNewSignInContext>,
+    ) -> Result<(), Error> {
+        let message = self.prepare_new_sign_in_email(to, context)?;
+        self.transport.send(message).await?;
+        Ok(())
+    }
+
     /// Test the connetion to the mail server
     ///
     /// # Errors
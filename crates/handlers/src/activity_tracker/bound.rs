@@ -16,13 +16,26 @@ use crate::activity_tracker::ActivityTracker;
 pub struct Bound {
     tracker: ActivityTracker,
     ip: Option<IpAddr>,
+    location: Option<String>,
 }
 
 impl Bound {
     /// Create a new bound activity tracker.
     #[must_use]
     pub fn new(tracker: ActivityTracker, ip: Option<IpAddr>) -> Self {
-        Self { tracker, ip }
+        Self {
+            tracker,
+            ip,
+            location: None,
+        }
+    }
+
+    /// Set the approximate location of the client, as given by a trusted
+    /// reverse proxy.
+    #[must_use]
+    pub fn with_location(mut self, location: Option<String>) -> Self {
+        self.location = location;
+        self
     }
 
     /// Get the IP address bound to this activity tracker.
@@ -31,6 +44,12 @@ impl Bound {
         self.ip
     }
 
+    /// Get the approximate location of the client, if known.
+    #[must_use]
+    pub fn location(&self) -> Option<&str> {
+        self.location.as_deref()
+    }
+
     /// Record activity in an OAuth 2.0 session.
     pub async fn record_oauth2_session(&self, clock: &dyn Clock, session: &Session) {
         self.tracker
@@ -140,6 +140,17 @@ impl Worker {
                         &[TYPE.string("record"), SESSION_KIND.string(kind.as_str())],
                     );
 
+                    // Only the last activity of each session is written, so when a
+                    // session is active again on another day, flush the activity of
+                    // the previous day first, to have it counted in the usage
+                    // statistics
+                    let other_day = self.pending_records.get(&(kind, id)).is_some_and(|record| {
+                        record.end_time.date_naive() != date_time.date_naive()
+                    });
+                    if other_day {
+                        self.flush().await;
+                    }
+
                     let record =
                         self.pending_records
                             .entry((kind, id))
@@ -4,9 +4,13 @@
 // SPDX-License-Identifier: AGPL-3.0-only
 // Please see LICENSE in the repository root for full details.
 
-use std::convert::Infallible;
+use std::{convert::Infallible, marker::PhantomData, net::IpAddr};
 
-use aide::OperationIo;
+use aide::{
+    gen::GenContext,
+    openapi::{Operation, SecurityRequirement},
+    OperationInput,
+};
 use axum::{
     extract::FromRequestParts,
     response::{IntoResponse, Response},
@@ -14,9 +18,13 @@ use axum::{
 };
 use axum_extra::TypedHeader;
 use headers::{authorization::Bearer, Authorization};
-use hyper::StatusCode;
-use mas_data_model::{Session, User};
-use mas_storage::{BoxClock, BoxRepository, RepositoryError};
+use hyper::{
+    header::{HeaderValue, WWW_AUTHENTICATE},
+    HeaderMap, StatusCode,
+};
+use mas_data_model::{AuditLogEventKind, Session, User};
+use mas_storage::{audit_log::NewAuditLogEvent, BoxClock, BoxRepository, RepositoryError};
+use serde_json::json;
 use ulid::Ulid;
 
 use super::response::ErrorResponse;
@@ -64,13 +72,13 @@ pub enum Rejection {
     #[error("Failed to load user {0}")]
     LoadUser(Ulid),
 
-    /// The session does not have the `urn:mas:admin` scope
-    #[error("Missing urn:mas:admin scope")]
-    MissingScope,
+    /// The session does not have any of the scopes required by the operation
+    #[error("Missing scope, one of {0:?} is required")]
+    MissingScope(&'static [&'static str]),
 }
 
 impl Rejection {
-    fn status_code(&self) -> StatusCode {
+    pub(super) fn status_code(&self) -> StatusCode {
         match self {
             Self::InvalidAuthorizationHeader | Self::MissingAuthorizationHeader => {
                 StatusCode::BAD_REQUEST
@@ -78,39 +86,202 @@ impl Rejection {
             Self::UnknownAccessToken
             | Self::TokenExpired
             | Self::SessionRevoked
-            | Self::UserLocked
-            | Self::MissingScope => StatusCode::UNAUTHORIZED,
+            | Self::UserLocked => StatusCode::UNAUTHORIZED,
+            Self::MissingScope(_) => StatusCode::FORBIDDEN,
             _ => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
+
+    /// The `WWW-Authenticate` header to send with the rejection, as defined by
+    /// RFC6750 section 3.1
+    pub(super) fn www_authenticate(&self) -> Option<HeaderValue> {
+        match self {
+            Self::MissingScope(scopes) => {
+                let value = format!(
+                    "Bearer error=\"insufficient_scope\", scope=\"{}\"",
+                    scopes.join(" ")
+                );
+                HeaderValue::from_str(&value).ok()
+            }
+            _ => None,
+        }
+    }
 }
 
 impl IntoResponse for Rejection {
     fn into_response(self) -> Response {
         let response = ErrorResponse::from_error(&self);
         let status = self.status_code();
-        (status, Json(response)).into_response()
+        let mut headers = HeaderMap::new();
+        if let Some(www_authenticate) = self.www_authenticate() {
+            headers.insert(WWW_AUTHENTICATE, www_authenticate);
+        }
+        (status, headers, Json(response)).into_response()
     }
 }
 
+/// The scope granting full access to the admin API
+pub const ADMIN_SCOPE: &str = "urn:mas:admin";
+
+/// The scope granting read-only access to the whole admin API
+pub const READ_SCOPE: &str = "urn:mas:admin:read";
+
+/// The scope granting read access to users, their emails and upstream links
+pub const USERS_READ_SCOPE: &str = "urn:mas:admin:users:read";
+
+/// The scope granting write access to users, their emails and upstream links
+pub const USERS_WRITE_SCOPE: &str = "urn:mas:admin:users:write";
+
+/// The scope granting the ability to lock and unlock users
+pub const USERS_LOCK_SCOPE: &str = "urn:mas:admin:users:lock";
+
+/// The scope granting access to the sessions of users
+pub const SESSIONS_SCOPE: &str = "urn:mas:admin:sessions";
+
+/// The scope granting access to the OAuth 2.0 clients
+pub const CLIENTS_SCOPE: &str = "urn:mas:admin:clients";
+
+/// All the scopes understood by the admin API, with their description
+pub const SCOPES: [(&str, &str); 7] = [
+    (ADMIN_SCOPE, "Grant full access to the admin API"),
+    (READ_SCOPE, "Grant read-only access to the admin API"),
+    (
+        USERS_READ_SCOPE,
+        "Grant read access to users, their email addresses and upstream links",
+    ),
+    (
+        USERS_WRITE_SCOPE,
+        "Grant write access to users, their email addresses and upstream links",
+    ),
+    (
+        USERS_LOCK_SCOPE,
+        "Grant the ability to lock and unlock users",
+    ),
+    (SESSIONS_SCOPE, "Grant access to the sessions of users"),
+    (CLIENTS_SCOPE, "Grant access to the OAuth 2.0 clients"),
+];
+
+/// Describes which scopes give access to an operation
+///
+/// The session must have at least one of the [`Self::SCOPES`]
+pub trait RequiredScope {
+    /// The scopes which give access to the operation
+    const SCOPES: &'static [&'static str];
+}
+
+macro_rules! required_scope {
+    ($(#[$meta:meta])* $name:ident => [$($scope:expr),* $(,)?]) => {
+        $(#[$meta])*
+        pub struct $name;
+
+        impl RequiredScope for $name {
+            const SCOPES: &'static [&'static str] = &[$($scope),*];
+        }
+    };
+}
+
+required_scope!(
+    /// Operations which require full access to the admin API
+    Admin => [ADMIN_SCOPE]
+);
+
+required_scope!(
+    /// Read-only operations which don't belong to a more specific area
+    Read => [ADMIN_SCOPE, READ_SCOPE]
+);
+
+required_scope!(
+    /// Read-only operations on users, their emails and upstream links
+    ReadUsers => [ADMIN_SCOPE, READ_SCOPE, USERS_READ_SCOPE]
+);
+
+required_scope!(
+    /// Operations which modify users, their emails and upstream links
+    WriteUsers => [ADMIN_SCOPE, USERS_WRITE_SCOPE]
+);
+
+required_scope!(
+    /// Operations which lock and unlock users
+    LockUsers => [ADMIN_SCOPE, USERS_LOCK_SCOPE]
+);
+
+required_scope!(
+    /// Read-only operations on sessions
+    ReadSessions => [ADMIN_SCOPE, READ_SCOPE, SESSIONS_SCOPE]
+);
+
+required_scope!(
+    /// Operations which end sessions
+    ManageSessions => [ADMIN_SCOPE, SESSIONS_SCOPE]
+);
+
+required_scope!(
+    /// Read-only operations on OAuth 2.0 clients
+    ReadClients => [ADMIN_SCOPE, READ_SCOPE, CLIENTS_SCOPE]
+);
+
+required_scope!(
+    /// Operations which modify OAuth 2.0 clients
+    ManageClients => [ADMIN_SCOPE, CLIENTS_SCOPE]
+);
+
 /// An extractor which authorizes the request
 ///
+/// The session must have one of the scopes listed by the `R` parameter.
+///
 /// Because we need to load the database repository and the clock, we keep them
 /// in the context to avoid creating two instances for each request.
 #[non_exhaustive]
-#[derive(OperationIo)]
-#[aide(input)]
-pub struct CallContext {
+#[allow(dead_code)]
+pub struct CallContext<R> {
     pub repo: BoxRepository,
     pub clock: BoxClock,
     pub user: Option<User>,
     pub session: Session,
+    pub actor: Actor,
+    _scope: PhantomData<R>,
+}
+
+/// The caller of an admin API operation, used to record its actions in the
+/// audit log
+#[derive(Debug, Clone)]
+pub struct Actor {
+    session: Session,
+    ip: Option<IpAddr>,
+}
+
+impl Actor {
+    /// Start an audit log event of the given kind, done by this caller
+    pub fn event(&self, kind: AuditLogEventKind) -> NewAuditLogEvent {
+        NewAuditLogEvent::new(kind)
+            .with_actor_session(&self.session)
+            .with_ip_address(self.ip)
+    }
+
+    /// Start an audit log event recording that the given operation was done
+    /// on the given resource
+    pub fn action(&self, operation: &str, resource_id: Ulid) -> NewAuditLogEvent {
+        self.event(AuditLogEventKind::AdminAction).with_data(json!({
+            "operation": operation,
+            "resource_id": resource_id,
+        }))
+    }
+}
+
+impl<R: RequiredScope> OperationInput for CallContext<R> {
+    fn operation_input(_ctx: &mut GenContext, operation: &mut Operation) {
+        // Each security requirement is an alternative, so we add one per scope
+        operation.security.extend(R::SCOPES.iter().map(|scope| {
+            SecurityRequirement::from([("oauth2".to_owned(), vec![(*scope).to_owned()])])
+        }));
+    }
 }
 
 #[async_trait::async_trait]
-impl<S> FromRequestParts<S> for CallContext
+impl<S, R> FromRequestParts<S> for CallContext<R>
 where
     S: Send + Sync,
+    R: RequiredScope,
     BoundActivityTracker: FromRequestParts<S, Rejection = Infallible>,
     BoxRepository: FromRequestParts<S>,
     BoxClock: FromRequestParts<S, Rejection = Infallible>,
@@ -193,17 +364,165 @@ where
             return Err(Rejection::TokenExpired);
         }
 
-        // For now, we only check that the session has the admin scope
-        // Later we might want to check other route-specific scopes
-        if !session.scope.contains("urn:mas:admin") {
-            return Err(Rejection::MissingScope);
+        // Check that the session has one of the scopes required by the operation
+        if !R::SCOPES.iter().any(|scope| session.scope.contains(scope)) {
+            return Err(Rejection::MissingScope(R::SCOPES));
         }
 
+        let actor = Actor {
+            session: session.clone(),
+            ip: activity_tracker.ip(),
+        };
+
         Ok(Self {
             repo,
             clock,
             user,
             session,
+            actor,
+            _scope: PhantomData,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use hyper::{header::WWW_AUTHENTICATE, Method, Request, StatusCode};
+    use sqlx::PgPool;
+    use ulid::Ulid;
+
+    use super::{
+        ADMIN_SCOPE, CLIENTS_SCOPE, READ_SCOPE, SCOPES, SESSIONS_SCOPE, USERS_LOCK_SCOPE,
+        USERS_READ_SCOPE, USERS_WRITE_SCOPE,
+    };
+    use crate::test_utils::{setup, RequestBuilderExt, TestState};
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_scopes(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+
+        // The write operations target a resource which doesn't exist, so they give
+        // a 404 once the scope check passed
+        let missing = Ulid::nil();
+        let operations: Vec<(Method, String, StatusCode, Vec<&str>)> = vec![
+            (
+                Method::GET,
+                "/api/admin/v1/users".to_owned(),
+                StatusCode::OK,
+                vec![ADMIN_SCOPE, READ_SCOPE, USERS_READ_SCOPE],
+            ),
+            (
+                Method::GET,
+                "/api/admin/v1/user-emails".to_owned(),
+                StatusCode::OK,
+                vec![ADMIN_SCOPE, READ_SCOPE, USERS_READ_SCOPE],
+            ),
+            (
+                Method::GET,
+                "/api/admin/v1/upstream-oauth-links".to_owned(),
+                StatusCode::OK,
+                vec![ADMIN_SCOPE, READ_SCOPE, USERS_READ_SCOPE],
+            ),
+            (
+                Method::POST,
+                format!("/api/admin/v1/users/{missing}/deactivate"),
+                StatusCode::NOT_FOUND,
+                vec![ADMIN_SCOPE, USERS_WRITE_SCOPE],
+            ),
+            (
+                Method::POST,
+                format!("/api/admin/v1/users/{missing}/lock"),
+                StatusCode::NOT_FOUND,
+                vec![ADMIN_SCOPE, USERS_LOCK_SCOPE],
+            ),
+            (
+                Method::GET,
+                "/api/admin/v1/compat-sessions".to_owned(),
+                StatusCode::OK,
+                vec![ADMIN_SCOPE, READ_SCOPE, SESSIONS_SCOPE],
+            ),
+            (
+                Method::GET,
+                "/api/admin/v1/oauth2-sessions".to_owned(),
+                StatusCode::OK,
+                vec![ADMIN_SCOPE, READ_SCOPE, SESSIONS_SCOPE],
+            ),
+            (
+                Method::GET,
+                "/api/admin/v1/user-sessions".to_owned(),
+                StatusCode::OK,
+                vec![ADMIN_SCOPE, READ_SCOPE, SESSIONS_SCOPE],
+            ),
+            (
+                Method::POST,
+                format!("/api/admin/v1/compat-sessions/{missing}/finish"),
+                StatusCode::NOT_FOUND,
+                vec![ADMIN_SCOPE, SESSIONS_SCOPE],
+            ),
+            (
+                Method::GET,
+                "/api/admin/v1/oauth2-clients".to_owned(),
+                StatusCode::OK,
+                vec![ADMIN_SCOPE, READ_SCOPE, CLIENTS_SCOPE],
+            ),
+            (
+                Method::POST,
+                format!("/api/admin/v1/oauth2-clients/{missing}/rotate-secret"),
+                StatusCode::NOT_FOUND,
+                vec![ADMIN_SCOPE, CLIENTS_SCOPE],
+            ),
+            (
+                Method::GET,
+                "/api/admin/v1/upstream-oauth-providers".to_owned(),
+                StatusCode::OK,
+                vec![ADMIN_SCOPE, READ_SCOPE],
+            ),
+            (
+                Method::GET,
+                "/api/admin/v1/audit-log".to_owned(),
+                StatusCode::OK,
+                vec![ADMIN_SCOPE, READ_SCOPE],
+            ),
+            (
+                Method::POST,
+                format!("/api/admin/v1/upstream-oauth-providers/{missing}/disable"),
+                StatusCode::NOT_FOUND,
+                vec![ADMIN_SCOPE],
+            ),
+            (
+                Method::GET,
+                "/api/admin/v1/user-registration-tokens".to_owned(),
+                StatusCode::OK,
+                vec![ADMIN_SCOPE],
+            ),
+        ];
+
+        for (scope, _) in SCOPES {
+            let token = state.token_with_scope(scope).await;
+
+            for (method, path, status, allowed_scopes) in &operations {
+                let request = Request::builder()
+                    .method(method)
+                    .uri(path)
+                    .bearer(&token)
+                    .empty();
+                let response = state.request(request).await;
+
+                if allowed_scopes.contains(&scope) {
+                    assert_eq!(response.status(), *status, "{scope} on {path}");
+                } else {
+                    assert_eq!(
+                        response.status(),
+                        StatusCode::FORBIDDEN,
+                        "{scope} on {path}"
+                    );
+                    let www_authenticate = response.headers().get(WWW_AUTHENTICATE).unwrap();
+                    let www_authenticate = www_authenticate.to_str().unwrap();
+                    assert!(www_authenticate.starts_with("Bearer error=\"insufficient_scope\""));
+                    assert!(www_authenticate.contains(ADMIN_SCOPE));
+                }
+            }
+        }
+    }
+}
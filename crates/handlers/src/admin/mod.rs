@@ -4,6 +4,8 @@
 // SPDX-License-Identifier: AGPL-3.0-only
 // Please see LICENSE in the repository root for full details.
 
+use std::convert::Infallible;
+
 use aide::{
     axum::ApiRouter,
     openapi::{OAuth2Flow, OAuth2Flows, OpenApi, SecurityScheme, Server, Tag},
@@ -18,13 +20,15 @@ use hyper::header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE};
 use indexmap::IndexMap;
 use mas_axum_utils::FancyError;
 use mas_http::CorsLayerExt;
+use mas_keystore::Encrypter;
 use mas_matrix::BoxHomeserverConnection;
 use mas_router::{
     ApiDoc, ApiDocCallback, OAuth2AuthorizationEndpoint, OAuth2TokenEndpoint, Route, SimpleRoute,
     UrlBuilder,
 };
-use mas_storage::BoxRng;
+use mas_storage::{BoxClock, BoxRepository, BoxRng};
 use mas_templates::{ApiDocContext, Templates};
+use sqlx::PgPool;
 use tower_http::cors::{Any, CorsLayer};
 
 mod call_context;
@@ -32,18 +36,26 @@ mod model;
 mod params;
 mod response;
 mod schema;
+mod scim;
 mod v1;
 
-use self::call_context::CallContext;
-use crate::passwords::PasswordManager;
+use self::call_context::SCOPES;
+use crate::{passwords::PasswordManager, BoundActivityTracker};
 
+#[allow(clippy::too_many_lines)]
 pub fn router<S>() -> (OpenApi, Router<S>)
 where
     S: Clone + Send + Sync + 'static,
     BoxHomeserverConnection: FromRef<S>,
+    Encrypter: FromRef<S>,
     PasswordManager: FromRef<S>,
+    PgPool: FromRef<S>,
     BoxRng: FromRequestParts<S>,
-    CallContext: FromRequestParts<S>,
+    BoxRepository: FromRequestParts<S>,
+    <BoxRepository as FromRequestParts<S>>::Rejection:
+        Into<Box<dyn std::error::Error + Send + Sync + 'static>>,
+    BoxClock: FromRequestParts<S, Rejection = Infallible>,
+    BoundActivityTracker: FromRequestParts<S, Rejection = Infallible>,
     Templates: FromRef<S>,
     UrlBuilder: FromRef<S>,
 {
@@ -51,11 +63,28 @@ where
         ctx.schema = schemars::gen::SchemaGenerator::new(schemars::gen::SchemaSettings::openapi3());
     });
 
+    let scopes: IndexMap<String, String> = SCOPES
+        .iter()
+        .map(|(scope, description)| ((*scope).to_owned(), (*description).to_owned()))
+        .collect();
+
     let mut api = OpenApi::default();
     let router = ApiRouter::<S>::new()
         .nest("/api/admin/v1", self::v1::router())
         .finish_api_with(&mut api, |t| {
             t.title("Matrix Authentication Service admin API")
+                .tag(Tag {
+                    name: "compat-session".to_owned(),
+                    description: Some(
+                        "Manage compatibility sessions from legacy clients".to_owned(),
+                    ),
+                    ..Tag::default()
+                })
+                .tag(Tag {
+                    name: "oauth2-client".to_owned(),
+                    description: Some("Manage OAuth 2.0 clients".to_owned()),
+                    ..Tag::default()
+                })
                 .tag(Tag {
                     name: "oauth2-session".to_owned(),
                     description: Some("Manage OAuth2 sessions".to_owned()),
@@ -66,6 +95,43 @@ where
                     description: Some("Manage users".to_owned()),
                     ..Tag::default()
                 })
+                .tag(Tag {
+                    name: "user-email".to_owned(),
+                    description: Some("Manage email addresses of users".to_owned()),
+                    ..Tag::default()
+                })
+                .tag(Tag {
+                    name: "user-registration-token".to_owned(),
+                    description: Some("Manage the tokens required to register".to_owned()),
+                    ..Tag::default()
+                })
+                .tag(Tag {
+                    name: "user-session".to_owned(),
+                    description: Some("Manage browser sessions of users".to_owned()),
+                    ..Tag::default()
+                })
+                .tag(Tag {
+                    name: "upstream-oauth-provider".to_owned(),
+                    description: Some("Manage upstream OAuth 2.0 providers".to_owned()),
+                    ..Tag::default()
+                })
+                .tag(Tag {
+                    name: "upstream-oauth-link".to_owned(),
+                    description: Some(
+                        "Manage links between local users and upstream accounts".to_owned(),
+                    ),
+                    ..Tag::default()
+                })
+                .tag(Tag {
+                    name: "audit-log".to_owned(),
+                    description: Some("Read the security audit log".to_owned()),
+                    ..Tag::default()
+                })
+                .tag(Tag {
+                    name: "usage-stats".to_owned(),
+                    description: Some("Read the usage statistics".to_owned()),
+                    ..Tag::default()
+                })
                 .security_scheme(
                     "oauth2",
                     SecurityScheme::OAuth2 {
@@ -73,19 +139,13 @@ where
                             client_credentials: Some(OAuth2Flow::ClientCredentials {
                                 refresh_url: Some(OAuth2TokenEndpoint::PATH.to_owned()),
                                 token_url: OAuth2TokenEndpoint::PATH.to_owned(),
-                                scopes: IndexMap::from([(
-                                    "urn:mas:admin".to_owned(),
-                                    "Grant access to the admin API".to_owned(),
-                                )]),
+                                scopes: scopes.clone(),
                             }),
                             authorization_code: Some(OAuth2Flow::AuthorizationCode {
                                 authorization_url: OAuth2AuthorizationEndpoint::PATH.to_owned(),
                                 refresh_url: Some(OAuth2TokenEndpoint::PATH.to_owned()),
                                 token_url: OAuth2TokenEndpoint::PATH.to_owned(),
-                                scopes: IndexMap::from([(
-                                    "urn:mas:admin".to_owned(),
-                                    "Grant access to the admin API".to_owned(),
-                                )]),
+                                scopes: scopes.clone(),
                             }),
                             implicit: None,
                             password: None,
@@ -94,7 +154,6 @@ where
                         extensions: IndexMap::default(),
                     },
                 )
-                .security_requirement_scopes("oauth2", ["urn:mas:admin"])
         });
 
     let router = router
@@ -115,6 +174,8 @@ where
                 }
             }),
         )
+        // Serve the SCIM API, which is not described by the OpenAPI spec
+        .nest(self::scim::PREFIX, self::scim::router())
         // Serve the Swagger API reference
         .route(ApiDoc::route(), axum::routing::get(swagger))
         .route(
@@ -6,10 +6,11 @@
 
 use std::net::IpAddr;
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
 use schemars::JsonSchema;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use ulid::Ulid;
+use url::Url;
 
 /// A resource, with a type and an ID
 pub trait Resource {
@@ -99,6 +100,69 @@ impl Resource for User {
     }
 }
 
+/// An email address of a user
+#[derive(Serialize, JsonSchema)]
+pub struct UserEmail {
+    #[serde(skip)]
+    id: Ulid,
+
+    /// When the object was created
+    created_at: DateTime<Utc>,
+
+    /// The ID of the user who owns this email address
+    #[schemars(with = "super::schema::Ulid")]
+    user_id: Ulid,
+
+    /// The email address
+    email: String,
+
+    /// When the email address was verified. If null, it is not verified yet.
+    confirmed_at: Option<DateTime<Utc>>,
+}
+
+impl From<mas_data_model::UserEmail> for UserEmail {
+    fn from(user_email: mas_data_model::UserEmail) -> Self {
+        Self {
+            id: user_email.id,
+            created_at: user_email.created_at,
+            user_id: user_email.user_id,
+            email: user_email.email,
+            confirmed_at: user_email.confirmed_at,
+        }
+    }
+}
+
+impl UserEmail {
+    /// Samples of user emails
+    pub fn samples() -> [Self; 2] {
+        [
+            Self {
+                id: Ulid::from_bytes([0x01; 16]),
+                created_at: DateTime::default(),
+                user_id: Ulid::from_bytes([0x02; 16]),
+                email: "alice@example.com".to_owned(),
+                confirmed_at: Some(DateTime::default()),
+            },
+            Self {
+                id: Ulid::from_bytes([0x02; 16]),
+                created_at: DateTime::default(),
+                user_id: Ulid::from_bytes([0x02; 16]),
+                email: "alice@example.org".to_owned(),
+                confirmed_at: None,
+            },
+        ]
+    }
+}
+
+impl Resource for UserEmail {
+    const KIND: &'static str = "user-email";
+    const PATH: &'static str = "/api/admin/v1/user-emails";
+
+    fn id(&self) -> Ulid {
+        self.id
+    }
+}
+
 /// A OAuth 2.0 session
 #[derive(Serialize, JsonSchema)]
 pub struct OAuth2Session {
@@ -205,3 +269,837 @@ impl Resource for OAuth2Session {
         self.id
     }
 }
+
+/// An OAuth 2.0 client
+#[derive(Serialize, JsonSchema)]
+pub struct OAuth2Client {
+    #[serde(skip)]
+    id: Ulid,
+
+    /// The client ID, to use in OAuth 2.0 requests
+    client_id: String,
+
+    /// The human-readable name of the client
+    client_name: Option<String>,
+
+    /// The kind of application, either `web` or `native`
+    application_type: Option<String>,
+
+    /// The redirect URIs registered for this client
+    redirect_uris: Vec<Url>,
+
+    /// The grant types this client is allowed to use
+    grant_types: Vec<String>,
+
+    /// The method used by the client to authenticate to the token endpoint
+    token_endpoint_auth_method: Option<String>,
+
+    /// URI of the logo of the client
+    logo_uri: Option<Url>,
+
+    /// URI of the home page of the client
+    client_uri: Option<Url>,
+
+    /// URI of the privacy policy of the client
+    policy_uri: Option<Url>,
+
+    /// URI of the terms of service of the client
+    tos_uri: Option<Url>,
+
+    /// Whether the client is defined in the configuration file. Those can't be
+    /// modified through the API.
+    #[serde(rename = "static")]
+    is_static: bool,
+
+    /// The client secret. It is only returned when the client is created or
+    /// when its secret is rotated.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_secret: Option<String>,
+}
+
+impl From<mas_data_model::Client> for OAuth2Client {
+    fn from(client: mas_data_model::Client) -> Self {
+        Self {
+            id: client.id,
+            client_id: client.client_id,
+            client_name: client.client_name,
+            application_type: client.application_type.map(|t| t.to_string()),
+            redirect_uris: client.redirect_uris,
+            grant_types: client.grant_types.iter().map(ToString::to_string).collect(),
+            token_endpoint_auth_method: client.token_endpoint_auth_method.map(|m| m.to_string()),
+            logo_uri: client.logo_uri,
+            client_uri: client.client_uri,
+            policy_uri: client.policy_uri,
+            tos_uri: client.tos_uri,
+            is_static: client.is_static,
+            client_secret: None,
+        }
+    }
+}
+
+impl OAuth2Client {
+    /// Include the plaintext client secret in the response
+    #[must_use]
+    pub fn with_client_secret(mut self, client_secret: String) -> Self {
+        self.client_secret = Some(client_secret);
+        self
+    }
+
+    /// Samples of OAuth 2.0 clients
+    pub fn samples() -> [Self; 2] {
+        [
+            Self {
+                id: Ulid::from_bytes([0x01; 16]),
+                client_id: Ulid::from_bytes([0x01; 16]).to_string(),
+                client_name: Some("Internal dashboard".to_owned()),
+                application_type: Some("web".to_owned()),
+                redirect_uris: vec!["https://dashboard.example.com/callback".parse().unwrap()],
+                grant_types: vec!["authorization_code".to_owned(), "refresh_token".to_owned()],
+                token_endpoint_auth_method: Some("client_secret_basic".to_owned()),
+                logo_uri: None,
+                client_uri: Some("https://dashboard.example.com/".parse().unwrap()),
+                policy_uri: None,
+                tos_uri: None,
+                is_static: false,
+                client_secret: None,
+            },
+            Self {
+                id: Ulid::from_bytes([0x02; 16]),
+                client_id: Ulid::from_bytes([0x02; 16]).to_string(),
+                client_name: None,
+                application_type: None,
+                redirect_uris: Vec::new(),
+                grant_types: vec!["client_credentials".to_owned()],
+                token_endpoint_auth_method: Some("client_secret_post".to_owned()),
+                logo_uri: None,
+                client_uri: None,
+                policy_uri: None,
+                tos_uri: None,
+                is_static: true,
+                client_secret: None,
+            },
+        ]
+    }
+}
+
+impl Resource for OAuth2Client {
+    const KIND: &'static str = "oauth2-client";
+    const PATH: &'static str = "/api/admin/v1/oauth2-clients";
+
+    fn id(&self) -> Ulid {
+        self.id
+    }
+}
+
+/// A compatibility session for legacy clients
+#[derive(Serialize, JsonSchema)]
+pub struct CompatSession {
+    #[serde(skip)]
+    id: Ulid,
+
+    /// The ID of the user who owns the session
+    #[schemars(with = "super::schema::Ulid")]
+    user_id: Ulid,
+
+    /// The Matrix device ID of this session
+    device_id: String,
+
+    /// The ID of the browser session which started this session, if any
+    #[schemars(with = "Option<super::schema::Ulid>")]
+    user_session_id: Option<Ulid>,
+
+    /// The redirect URI used to login in the client, if it was an SSO login
+    redirect_uri: Option<Url>,
+
+    /// When the object was created
+    created_at: DateTime<Utc>,
+
+    /// The user agent string of the client which started this session
+    user_agent: Option<String>,
+
+    /// The last time the session was active
+    last_active_at: Option<DateTime<Utc>>,
+
+    /// The last IP address used by the session
+    last_active_ip: Option<IpAddr>,
+
+    /// When the session was finished
+    finished_at: Option<DateTime<Utc>>,
+}
+
+impl
+    From<(
+        mas_data_model::CompatSession,
+        Option<mas_data_model::CompatSsoLogin>,
+    )> for CompatSession
+{
+    fn from(
+        (session, sso_login): (
+            mas_data_model::CompatSession,
+            Option<mas_data_model::CompatSsoLogin>,
+        ),
+    ) -> Self {
+        let finished_at = session.finished_at();
+        Self {
+            id: session.id,
+            user_id: session.user_id,
+            device_id: session.device.to_string(),
+            user_session_id: session.user_session_id,
+            redirect_uri: sso_login.map(|sso| sso.redirect_uri),
+            created_at: session.created_at,
+            user_agent: session.user_agent.map(|ua| ua.raw),
+            last_active_at: session.last_active_at,
+            last_active_ip: session.last_active_ip,
+            finished_at,
+        }
+    }
+}
+
+impl CompatSession {
+    /// Samples of compatibility sessions
+    pub fn samples() -> [Self; 3] {
+        [
+            Self {
+                id: Ulid::from_bytes([0x01; 16]),
+                user_id: Ulid::from_bytes([0x01; 16]),
+                device_id: "AABBCCDDEE".to_owned(),
+                user_session_id: Some(Ulid::from_bytes([0x11; 16])),
+                redirect_uri: Some("https://example.com/redirect".parse().unwrap()),
+                created_at: DateTime::default(),
+                user_agent: Some("Mozilla/5.0".to_owned()),
+                last_active_at: Some(DateTime::default()),
+                last_active_ip: Some([1, 2, 3, 4].into()),
+                finished_at: None,
+            },
+            Self {
+                id: Ulid::from_bytes([0x02; 16]),
+                user_id: Ulid::from_bytes([0x01; 16]),
+                device_id: "FFGGHHIIJJ".to_owned(),
+                user_session_id: Some(Ulid::from_bytes([0x12; 16])),
+                redirect_uri: None,
+                created_at: DateTime::default(),
+                user_agent: Some("Mozilla/5.0".to_owned()),
+                last_active_at: Some(DateTime::default()),
+                last_active_ip: Some([1, 2, 3, 4].into()),
+                finished_at: Some(DateTime::default()),
+            },
+            Self {
+                id: Ulid::from_bytes([0x03; 16]),
+                user_id: Ulid::from_bytes([0x01; 16]),
+                device_id: "KKLLMMNNOO".to_owned(),
+                user_session_id: None,
+                redirect_uri: None,
+                created_at: DateTime::default(),
+                user_agent: None,
+                last_active_at: None,
+                last_active_ip: None,
+                finished_at: None,
+            },
+        ]
+    }
+}
+
+impl Resource for CompatSession {
+    const KIND: &'static str = "compat-session";
+    const PATH: &'static str = "/api/admin/v1/compat-sessions";
+
+    fn id(&self) -> Ulid {
+        self.id
+    }
+}
+
+/// A browser session, started when a user signs in through the web interface
+#[derive(Serialize, JsonSchema)]
+pub struct UserSession {
+    #[serde(skip)]
+    id: Ulid,
+
+    /// When the object was created
+    created_at: DateTime<Utc>,
+
+    /// When the session was finished
+    finished_at: Option<DateTime<Utc>>,
+
+    /// The ID of the user who owns the session
+    #[schemars(with = "super::schema::Ulid")]
+    user_id: Ulid,
+
+    /// The user agent string of the browser which started this session
+    user_agent: Option<String>,
+
+    /// The last time the session was active
+    last_active_at: Option<DateTime<Utc>>,
+
+    /// The last IP address used by the session
+    last_active_ip: Option<IpAddr>,
+}
+
+impl From<mas_data_model::BrowserSession> for UserSession {
+    fn from(session: mas_data_model::BrowserSession) -> Self {
+        Self {
+            id: session.id,
+            created_at: session.created_at,
+            finished_at: session.finished_at,
+            user_id: session.user.id,
+            user_agent: session.user_agent.map(|ua| ua.raw),
+            last_active_at: session.last_active_at,
+            last_active_ip: session.last_active_ip,
+        }
+    }
+}
+
+impl UserSession {
+    /// Samples of user sessions
+    pub fn samples() -> [Self; 3] {
+        [
+            Self {
+                id: Ulid::from_bytes([0x01; 16]),
+                created_at: DateTime::default(),
+                finished_at: None,
+                user_id: Ulid::from_bytes([0x02; 16]),
+                user_agent: Some("Mozilla/5.0".to_owned()),
+                last_active_at: Some(DateTime::default()),
+                last_active_ip: Some("127.0.0.1".parse().unwrap()),
+            },
+            Self {
+                id: Ulid::from_bytes([0x02; 16]),
+                created_at: DateTime::default(),
+                finished_at: None,
+                user_id: Ulid::from_bytes([0x03; 16]),
+                user_agent: None,
+                last_active_at: None,
+                last_active_ip: None,
+            },
+            Self {
+                id: Ulid::from_bytes([0x03; 16]),
+                created_at: DateTime::default(),
+                finished_at: Some(DateTime::default()),
+                user_id: Ulid::from_bytes([0x04; 16]),
+                user_agent: Some("Mozilla/5.0".to_owned()),
+                last_active_at: Some(DateTime::default()),
+                last_active_ip: Some("127.0.0.1".parse().unwrap()),
+            },
+        ]
+    }
+}
+
+impl Resource for UserSession {
+    const KIND: &'static str = "user-session";
+    const PATH: &'static str = "/api/admin/v1/user-sessions";
+
+    fn id(&self) -> Ulid {
+        self.id
+    }
+}
+
+/// An upstream OAuth 2.0 provider
+#[derive(Serialize, JsonSchema)]
+pub struct UpstreamOAuthProvider {
+    #[serde(skip)]
+    id: Ulid,
+
+    /// The OIDC issuer of the provider
+    issuer: String,
+
+    /// A human-readable name for the provider
+    human_name: Option<String>,
+
+    /// A brand identifier for the provider, like `google` or `github`
+    brand_name: Option<String>,
+
+    /// The client ID used to authenticate with the provider
+    client_id: String,
+
+    /// When the provider was created
+    created_at: DateTime<Utc>,
+
+    /// When the provider was disabled. If null, the provider is enabled.
+    disabled_at: Option<DateTime<Utc>>,
+}
+
+impl From<mas_data_model::UpstreamOAuthProvider> for UpstreamOAuthProvider {
+    fn from(provider: mas_data_model::UpstreamOAuthProvider) -> Self {
+        Self {
+            id: provider.id,
+            issuer: provider.issuer,
+            human_name: provider.human_name,
+            brand_name: provider.brand_name,
+            client_id: provider.client_id,
+            created_at: provider.created_at,
+            disabled_at: provider.disabled_at,
+        }
+    }
+}
+
+impl UpstreamOAuthProvider {
+    /// Samples of upstream OAuth 2.0 providers
+    pub fn samples() -> [Self; 2] {
+        [
+            Self {
+                id: Ulid::from_bytes([0x01; 16]),
+                issuer: "https://accounts.google.com".to_owned(),
+                human_name: Some("Google".to_owned()),
+                brand_name: Some("google".to_owned()),
+                client_id: "123456789.apps.googleusercontent.com".to_owned(),
+                created_at: DateTime::default(),
+                disabled_at: None,
+            },
+            Self {
+                id: Ulid::from_bytes([0x02; 16]),
+                issuer: "https://id.example.com/".to_owned(),
+                human_name: None,
+                brand_name: None,
+                client_id: "mas".to_owned(),
+                created_at: DateTime::default(),
+                disabled_at: Some(DateTime::default()),
+            },
+        ]
+    }
+}
+
+impl Resource for UpstreamOAuthProvider {
+    const KIND: &'static str = "upstream-oauth-provider";
+    const PATH: &'static str = "/api/admin/v1/upstream-oauth-providers";
+
+    fn id(&self) -> Ulid {
+        self.id
+    }
+}
+
+/// A link between a local user and an upstream account
+#[derive(Serialize, JsonSchema)]
+pub struct UpstreamOAuthLink {
+    #[serde(skip)]
+    id: Ulid,
+
+    /// When the object was created
+    created_at: DateTime<Utc>,
+
+    /// The ID of the provider
+    #[schemars(with = "super::schema::Ulid")]
+    provider_id: Ulid,
+
+    /// The subject of the upstream account, unique per provider
+    subject: String,
+
+    /// The ID of the user who owns this link, if any
+    #[schemars(with = "Option<super::schema::Ulid>")]
+    user_id: Option<Ulid>,
+}
+
+impl From<mas_data_model::UpstreamOAuthLink> for UpstreamOAuthLink {
+    fn from(link: mas_data_model::UpstreamOAuthLink) -> Self {
+        Self {
+            id: link.id,
+            created_at: link.created_at,
+            provider_id: link.provider_id,
+            subject: link.subject,
+            user_id: link.user_id,
+        }
+    }
+}
+
+impl UpstreamOAuthLink {
+    /// Samples of upstream OAuth 2.0 links
+    pub fn samples() -> [Self; 2] {
+        [
+            Self {
+                id: Ulid::from_bytes([0x01; 16]),
+                created_at: DateTime::default(),
+                provider_id: Ulid::from_bytes([0x02; 16]),
+                subject: "john-42".to_owned(),
+                user_id: Some(Ulid::from_bytes([0x03; 16])),
+            },
+            Self {
+                id: Ulid::from_bytes([0x02; 16]),
+                created_at: DateTime::default(),
+                provider_id: Ulid::from_bytes([0x03; 16]),
+                subject: "jane-123".to_owned(),
+                user_id: None,
+            },
+        ]
+    }
+}
+
+impl Resource for UpstreamOAuthLink {
+    const KIND: &'static str = "upstream-oauth-link";
+    const PATH: &'static str = "/api/admin/v1/upstream-oauth-links";
+
+    fn id(&self) -> Ulid {
+        self.id
+    }
+}
+
+/// The kind of an event recorded in the audit log
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AuditLogEventKind {
+    /// A user successfully logged in
+    #[serde(rename = "login.succeeded")]
+    LoginSucceeded,
+
+    /// A login attempt failed
+    #[serde(rename = "login.failed")]
+    LoginFailed,
+
+    /// The password of a user was changed or reset
+    #[serde(rename = "password.changed")]
+    PasswordChanged,
+
+    /// An email address was added to a user
+    #[serde(rename = "email.added")]
+    EmailAdded,
+
+    /// An email address was removed from a user
+    #[serde(rename = "email.removed")]
+    EmailRemoved,
+
+    /// The primary email address of a user changed
+    #[serde(rename = "email.primary_changed")]
+    PrimaryEmailChanged,
+
+    /// A session was created
+    #[serde(rename = "session.created")]
+    SessionCreated,
+
+    /// A session was ended
+    #[serde(rename = "session.finished")]
+    SessionFinished,
+
+    /// An operation was done through the admin API
+    #[serde(rename = "admin.action")]
+    AdminAction,
+
+    /// The policy denied a request
+    #[serde(rename = "policy.denied")]
+    PolicyDenied,
+}
+
+impl From<mas_data_model::AuditLogEventKind> for AuditLogEventKind {
+    fn from(kind: mas_data_model::AuditLogEventKind) -> Self {
+        use mas_data_model::AuditLogEventKind as Kind;
+        match kind {
+            Kind::LoginSucceeded => Self::LoginSucceeded,
+            Kind::LoginFailed => Self::LoginFailed,
+            Kind::PasswordChanged => Self::PasswordChanged,
+            Kind::EmailAdded => Self::EmailAdded,
+            Kind::EmailRemoved => Self::EmailRemoved,
+            Kind::PrimaryEmailChanged => Self::PrimaryEmailChanged,
+            Kind::SessionCreated => Self::SessionCreated,
+            Kind::SessionFinished => Self::SessionFinished,
+            Kind::AdminAction => Self::AdminAction,
+            Kind::PolicyDenied => Self::PolicyDenied,
+        }
+    }
+}
+
+impl From<AuditLogEventKind> for mas_data_model::AuditLogEventKind {
+    fn from(kind: AuditLogEventKind) -> Self {
+        match kind {
+            AuditLogEventKind::LoginSucceeded => Self::LoginSucceeded,
+            AuditLogEventKind::LoginFailed => Self::LoginFailed,
+            AuditLogEventKind::PasswordChanged => Self::PasswordChanged,
+            AuditLogEventKind::EmailAdded => Self::EmailAdded,
+            AuditLogEventKind::EmailRemoved => Self::EmailRemoved,
+            AuditLogEventKind::PrimaryEmailChanged => Self::PrimaryEmailChanged,
+            AuditLogEventKind::SessionCreated => Self::SessionCreated,
+            AuditLogEventKind::SessionFinished => Self::SessionFinished,
+            AuditLogEventKind::AdminAction => Self::AdminAction,
+            AuditLogEventKind::PolicyDenied => Self::PolicyDenied,
+        }
+    }
+}
+
+impl std::fmt::Display for AuditLogEventKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        mas_data_model::AuditLogEventKind::from(*self).fmt(f)
+    }
+}
+
+/// An event recorded in the security audit log
+#[derive(Serialize, JsonSchema)]
+pub struct AuditLogEvent {
+    #[serde(skip)]
+    id: Ulid,
+
+    /// When the event was recorded
+    created_at: DateTime<Utc>,
+
+    /// The kind of event
+    kind: AuditLogEventKind,
+
+    /// The ID of the user who did the action, if any
+    #[schemars(with = "Option<super::schema::Ulid>")]
+    actor_user_id: Option<Ulid>,
+
+    /// The ID of the OAuth 2.0 session which did the action, for actions done
+    /// through an API like the admin API
+    #[schemars(with = "Option<super::schema::Ulid>")]
+    actor_session_id: Option<Ulid>,
+
+    /// The ID of the user affected by the action, if any
+    #[schemars(with = "Option<super::schema::Ulid>")]
+    user_id: Option<Ulid>,
+
+    /// The IP address from which the request was made
+    ip_address: Option<IpAddr>,
+
+    /// The user agent which made the request
+    user_agent: Option<String>,
+
+    /// Additional details, which depend on the kind of event
+    data: serde_json::Value,
+}
+
+impl From<mas_data_model::AuditLogEvent> for AuditLogEvent {
+    fn from(event: mas_data_model::AuditLogEvent) -> Self {
+        Self {
+            id: event.id,
+            created_at: event.created_at,
+            kind: event.kind.into(),
+            actor_user_id: event.actor_user_id,
+            actor_session_id: event.actor_session_id,
+            user_id: event.user_id,
+            ip_address: event.ip_address,
+            user_agent: event.user_agent,
+            data: event.data,
+        }
+    }
+}
+
+impl AuditLogEvent {
+    /// Samples of audit log events
+    pub fn samples() -> [Self; 2] {
+        [
+            Self {
+                id: Ulid::from_bytes([0x01; 16]),
+                created_at: DateTime::default(),
+                kind: AuditLogEventKind::LoginFailed,
+                actor_user_id: None,
+                actor_session_id: None,
+                user_id: Some(Ulid::from_bytes([0x02; 16])),
+                ip_address: Some("1.2.3.4".parse().unwrap()),
+                user_agent: Some("Mozilla/5.0".to_owned()),
+                data: serde_json::json!({
+                    "method": "password",
+                    "username": "alice",
+                }),
+            },
+            Self {
+                id: Ulid::from_bytes([0x02; 16]),
+                created_at: DateTime::default(),
+                kind: AuditLogEventKind::AdminAction,
+                actor_user_id: Some(Ulid::from_bytes([0x03; 16])),
+                actor_session_id: Some(Ulid::from_bytes([0x04; 16])),
+                user_id: Some(Ulid::from_bytes([0x02; 16])),
+                ip_address: Some("5.6.7.8".parse().unwrap()),
+                user_agent: None,
+                data: serde_json::json!({
+                    "operation": "lockUser",
+                    "resource_id": Ulid::from_bytes([0x02; 16]),
+                }),
+            },
+        ]
+    }
+}
+
+impl Resource for AuditLogEvent {
+    const KIND: &'static str = "audit-log-event";
+    const PATH: &'static str = "/api/admin/v1/audit-log";
+
+    fn id(&self) -> Ulid {
+        self.id
+    }
+}
+
+/// Usage statistics of the service over a day
+#[derive(Serialize, JsonSchema)]
+pub struct UsageStats {
+    #[serde(skip)]
+    id: Ulid,
+
+    /// The day, in UTC, the statistics are about
+    day: NaiveDate,
+
+    /// Number of users with a session active during the day
+    daily_active_users: u64,
+
+    /// Number of users with a session active during the 30 days up to the end
+    /// of the day
+    monthly_active_users: u64,
+
+    /// Number of users who registered during the day
+    registrations: u64,
+
+    /// Number of logins with a password during the day
+    password_logins: u64,
+
+    /// Number of logins through an upstream OAuth 2.0 provider during the day
+    upstream_oauth2_logins: u64,
+
+    /// Number of logins through the compatibility layer during the day, not
+    /// counting the ones through the SSO login flow
+    compat_logins: u64,
+
+    /// When the statistics were last computed
+    computed_at: DateTime<Utc>,
+
+    /// Whether the statistics were computed after the end of the day, meaning
+    /// they won't change anymore
+    is_final: bool,
+}
+
+impl From<mas_data_model::UsageStats> for UsageStats {
+    fn from(stats: mas_data_model::UsageStats) -> Self {
+        Self {
+            id: stats.id,
+            is_final: stats.is_final(),
+            day: stats.day,
+            daily_active_users: stats.daily_active_users,
+            monthly_active_users: stats.monthly_active_users,
+            registrations: stats.registrations,
+            password_logins: stats.password_logins,
+            upstream_oauth2_logins: stats.upstream_oauth2_logins,
+            compat_logins: stats.compat_logins,
+            computed_at: stats.computed_at,
+        }
+    }
+}
+
+impl UsageStats {
+    /// Samples of usage statistics
+    pub fn samples() -> [Self; 2] {
+        [
+            Self {
+                id: Ulid::from_bytes([0x01; 16]),
+                day: NaiveDate::from_ymd_opt(2024, 12, 1).unwrap(),
+                daily_active_users: 1234,
+                monthly_active_users: 5678,
+                registrations: 12,
+                password_logins: 120,
+                upstream_oauth2_logins: 42,
+                compat_logins: 8,
+                computed_at: DateTime::default(),
+                is_final: true,
+            },
+            Self {
+                id: Ulid::from_bytes([0x02; 16]),
+                day: NaiveDate::from_ymd_opt(2024, 12, 2).unwrap(),
+                daily_active_users: 987,
+                monthly_active_users: 5690,
+                registrations: 3,
+                password_logins: 98,
+                upstream_oauth2_logins: 37,
+                compat_logins: 5,
+                computed_at: DateTime::default(),
+                is_final: false,
+            },
+        ]
+    }
+}
+
+impl Resource for UsageStats {
+    const KIND: &'static str = "usage-stats";
+    const PATH: &'static str = "/api/admin/v1/usage-stats";
+
+    fn id(&self) -> Ulid {
+        self.id
+    }
+}
+
+/// A token which can be required to register a new account
+#[derive(Serialize, JsonSchema)]
+pub struct UserRegistrationToken {
+    #[serde(skip)]
+    id: Ulid,
+
+    /// The token users have to provide when registering
+    token: String,
+
+    /// How many times the token can be used, `null` for unlimited
+    usage_limit: Option<u32>,
+
+    /// How many times the token was used
+    times_used: u32,
+
+    /// How many of the users who registered with the token haven't verified
+    /// an email address yet
+    pending_uses: u32,
+
+    /// When the token was created
+    created_at: DateTime<Utc>,
+
+    /// When the token was last used
+    last_used_at: Option<DateTime<Utc>>,
+
+    /// When the token expires, `null` if it never does
+    expires_at: Option<DateTime<Utc>>,
+
+    /// When the token was revoked
+    revoked_at: Option<DateTime<Utc>>,
+}
+
+impl From<mas_data_model::UserRegistrationToken> for UserRegistrationToken {
+    fn from(token: mas_data_model::UserRegistrationToken) -> Self {
+        Self {
+            id: token.id,
+            token: token.token,
+            usage_limit: token.usage_limit,
+            times_used: token.times_used,
+            pending_uses: token.pending_uses,
+            created_at: token.created_at,
+            last_used_at: token.last_used_at,
+            expires_at: token.expires_at,
+            revoked_at: token.revoked_at,
+        }
+    }
+}
+
+impl UserRegistrationToken {
+    /// Samples of registration tokens
+    pub fn samples() -> [Self; 3] {
+        [
+            Self {
+                id: Ulid::from_bytes([0x01; 16]),
+                token: "Kx7zB3mQpW9vLtYc".to_owned(),
+                usage_limit: Some(10),
+                times_used: 3,
+                pending_uses: 1,
+                created_at: DateTime::default(),
+                last_used_at: Some(DateTime::default()),
+                expires_at: None,
+                revoked_at: None,
+            },
+            Self {
+                id: Ulid::from_bytes([0x02; 16]),
+                token: "community-invite".to_owned(),
+                usage_limit: None,
+                times_used: 0,
+                pending_uses: 0,
+                created_at: DateTime::default(),
+                last_used_at: None,
+                expires_at: Some(DateTime::default()),
+                revoked_at: None,
+            },
+            Self {
+                id: Ulid::from_bytes([0x03; 16]),
+                token: "R4nd0mT0k3nV4lue".to_owned(),
+                usage_limit: Some(1),
+                times_used: 0,
+                pending_uses: 0,
+                created_at: DateTime::default(),
+                last_used_at: None,
+                expires_at: None,
+                revoked_at: Some(DateTime::default()),
+            },
+        ]
+    }
+}
+
+impl Resource for UserRegistrationToken {
+    const KIND: &'static str = "user-registration-token";
+    const PATH: &'static str = "/api/admin/v1/user-registration-tokens";
+
+    fn id(&self) -> Ulid {
+        self.id
+    }
+}
@@ -0,0 +1,277 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+//! A parser for the subset of the SCIM filter syntax we support
+//!
+//! Only equality comparisons on a few attributes, optionally joined with
+//! `and`, are supported. This covers what identity providers send to look up
+//! users before provisioning them.
+
+use mas_data_model::{User, UserEmail};
+use thiserror::Error;
+use ulid::Ulid;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum FilterError {
+    #[error("unexpected end of filter")]
+    UnexpectedEnd,
+
+    #[error("unexpected character {0:?}")]
+    UnexpectedCharacter(char),
+
+    #[error("unsupported operator {0:?}, only \"eq\" is supported")]
+    UnsupportedOperator(String),
+
+    #[error("unsupported attribute {0:?}")]
+    UnsupportedAttribute(String),
+
+    #[error("invalid value for attribute {0:?}")]
+    InvalidValue(&'static str),
+
+    #[error("unsupported logical operator {0:?}, only \"and\" is supported")]
+    UnsupportedLogicalOperator(String),
+}
+
+/// A comparison between an attribute of a user and a value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Comparison {
+    /// `id eq "…"`
+    Id(Ulid),
+
+    /// `userName eq "…"`
+    UserName(String),
+
+    /// `emails eq "…"` or `emails.value eq "…"`
+    Email(String),
+
+    /// `active eq true|false`
+    Active(bool),
+}
+
+impl Comparison {
+    /// Whether the user, with the given email addresses, satisfies the
+    /// comparison
+    fn matches(&self, user: &User, emails: &[UserEmail]) -> bool {
+        match self {
+            Self::Id(id) => user.id == *id,
+            Self::UserName(username) => user.username == *username,
+            Self::Email(value) => emails
+                .iter()
+                .any(|email| email.email.eq_ignore_ascii_case(value)),
+            Self::Active(active) => user.locked_at.is_none() == *active,
+        }
+    }
+}
+
+/// A parsed filter, which matches users satisfying all of its comparisons
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Filter {
+    pub comparisons: Vec<Comparison>,
+}
+
+impl Filter {
+    /// Whether the user, with the given email addresses, matches the filter
+    pub fn matches(&self, user: &User, emails: &[UserEmail]) -> bool {
+        self.comparisons
+            .iter()
+            .all(|comparison| comparison.matches(user, emails))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Word(String),
+    String(String),
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, FilterError> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '"' {
+            // Find the closing quote, skipping escaped characters
+            chars.next();
+            let mut escaped = false;
+            let end = loop {
+                let (index, c) = chars.next().ok_or(FilterError::UnexpectedEnd)?;
+                match c {
+                    '\\' if !escaped => escaped = true,
+                    '"' if !escaped => break index,
+                    _ => escaped = false,
+                }
+            };
+
+            // SCIM strings follow the JSON syntax
+            let value = serde_json::from_str(&input[start..=end])
+                .map_err(|_| FilterError::UnexpectedCharacter('"'))?;
+            tokens.push(Token::String(value));
+        } else if c.is_ascii_alphanumeric() || matches!(c, '.' | ':' | '_' | '-' | '$') {
+            let mut end = start;
+            while let Some(&(index, c)) = chars.peek() {
+                if c.is_ascii_alphanumeric() || matches!(c, '.' | ':' | '_' | '-' | '$') {
+                    end = index + c.len_utf8();
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            tokens.push(Token::Word(input[start..end].to_owned()));
+        } else {
+            return Err(FilterError::UnexpectedCharacter(c));
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Strip the core user schema URN from an attribute path, if present
+fn strip_schema(attribute: &str) -> &str {
+    let prefix = super::USER_SCHEMA;
+    if attribute.len() > prefix.len()
+        && attribute[..prefix.len()].eq_ignore_ascii_case(prefix)
+        && attribute.as_bytes()[prefix.len()] == b':'
+    {
+        &attribute[prefix.len() + 1..]
+    } else {
+        attribute
+    }
+}
+
+fn comparison(attribute: &str, value: Token) -> Result<Comparison, FilterError> {
+    let attribute = strip_schema(attribute).to_ascii_lowercase();
+    match (attribute.as_str(), value) {
+        ("id", Token::String(value)) => value
+            .parse()
+            .map(Comparison::Id)
+            .map_err(|_| FilterError::InvalidValue("id")),
+        ("username", Token::String(value)) => Ok(Comparison::UserName(value)),
+        ("emails" | "emails.value", Token::String(value)) => Ok(Comparison::Email(value)),
+        ("active", Token::Word(value)) if value.eq_ignore_ascii_case("true") => {
+            Ok(Comparison::Active(true))
+        }
+        ("active", Token::Word(value)) if value.eq_ignore_ascii_case("false") => {
+            Ok(Comparison::Active(false))
+        }
+        ("id", _) => Err(FilterError::InvalidValue("id")),
+        ("username", _) => Err(FilterError::InvalidValue("userName")),
+        ("emails" | "emails.value", _) => Err(FilterError::InvalidValue("emails")),
+        ("active", _) => Err(FilterError::InvalidValue("active")),
+        _ => Err(FilterError::UnsupportedAttribute(attribute)),
+    }
+}
+
+impl std::str::FromStr for Filter {
+    type Err = FilterError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tokens = tokenize(s)?.into_iter();
+        let mut comparisons = Vec::new();
+
+        loop {
+            let Some(Token::Word(attribute)) = tokens.next() else {
+                return Err(FilterError::UnexpectedEnd);
+            };
+
+            let Some(Token::Word(operator)) = tokens.next() else {
+                return Err(FilterError::UnexpectedEnd);
+            };
+
+            if !operator.eq_ignore_ascii_case("eq") {
+                return Err(FilterError::UnsupportedOperator(operator));
+            }
+
+            let value = tokens.next().ok_or(FilterError::UnexpectedEnd)?;
+            comparisons.push(comparison(&attribute, value)?);
+
+            match tokens.next() {
+                None => break,
+                Some(Token::Word(word)) if word.eq_ignore_ascii_case("and") => {}
+                Some(Token::Word(word)) => {
+                    return Err(FilterError::UnsupportedLogicalOperator(word));
+                }
+                Some(Token::String(_)) => return Err(FilterError::UnexpectedCharacter('"')),
+            }
+        }
+
+        Ok(Self { comparisons })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_filter() {
+        let filter: Filter = r#"userName eq "alice""#.parse().unwrap();
+        assert_eq!(
+            filter.comparisons,
+            vec![Comparison::UserName("alice".to_owned())]
+        );
+
+        // Attribute names and operators are case insensitive, and may be prefixed
+        // by the schema URN
+        let filter: Filter =
+            r#"urn:ietf:params:scim:schemas:core:2.0:User:USERNAME EQ "alice" and active eq True"#
+                .parse()
+                .unwrap();
+        assert_eq!(
+            filter.comparisons,
+            vec![
+                Comparison::UserName("alice".to_owned()),
+                Comparison::Active(true)
+            ]
+        );
+
+        let filter: Filter = r#"emails.value eq "alice\"@example.com""#.parse().unwrap();
+        assert_eq!(
+            filter.comparisons,
+            vec![Comparison::Email("alice\"@example.com".to_owned())]
+        );
+
+        let filter: Filter = r#"id eq "01040G2081040G2081040G2081""#.parse().unwrap();
+        assert_eq!(
+            filter.comparisons,
+            vec![Comparison::Id(
+                Ulid::from_string("01040G2081040G2081040G2081").unwrap()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_filter() {
+        assert_eq!(
+            r#"userName sw "al""#.parse::<Filter>(),
+            Err(FilterError::UnsupportedOperator("sw".to_owned()))
+        );
+        assert_eq!(
+            r#"userName eq "alice" or userName eq "bob""#.parse::<Filter>(),
+            Err(FilterError::UnsupportedLogicalOperator("or".to_owned()))
+        );
+        assert_eq!(
+            r#"externalId eq "1234""#.parse::<Filter>(),
+            Err(FilterError::UnsupportedAttribute("externalid".to_owned()))
+        );
+        assert_eq!(
+            r#"active eq "yes""#.parse::<Filter>(),
+            Err(FilterError::InvalidValue("active"))
+        );
+        assert_eq!(
+            r#"(userName eq "alice")"#.parse::<Filter>(),
+            Err(FilterError::UnexpectedCharacter('('))
+        );
+        assert_eq!(
+            r#"userName eq "alice"#.parse::<Filter>(),
+            Err(FilterError::UnexpectedEnd)
+        );
+        assert_eq!(
+            "userName eq".parse::<Filter>(),
+            Err(FilterError::UnexpectedEnd)
+        );
+    }
+}
@@ -0,0 +1,338 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+//! A SCIM 2.0 server, to provision users from identity providers
+//!
+//! See [RFC7643] and [RFC7644]. Only the `Users` resource is supported, on
+//! top of the same authentication as the rest of the admin API.
+//!
+//! [RFC7643]: https://datatracker.ietf.org/doc/html/rfc7643
+//! [RFC7644]: https://datatracker.ietf.org/doc/html/rfc7644
+
+use std::convert::Infallible;
+
+use axum::{
+    extract::{
+        rejection::{JsonRejection, QueryRejection},
+        FromRef, FromRequestParts, State,
+    },
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use hyper::{
+    header::{HeaderValue, CONTENT_TYPE, WWW_AUTHENTICATE},
+    HeaderMap, StatusCode,
+};
+use mas_matrix::BoxHomeserverConnection;
+use mas_router::UrlBuilder;
+use mas_storage::{BoxClock, BoxRepository, BoxRng};
+use serde::Serialize;
+use serde_json::json;
+use ulid::Ulid;
+use url::Url;
+
+use super::call_context::Rejection;
+use crate::{impl_from_error_for_route, BoundActivityTracker};
+
+mod filter;
+mod model;
+mod users;
+
+/// Where the SCIM API is served
+pub const PREFIX: &str = "/scim/v2";
+
+/// The media type of SCIM requests and responses
+const SCIM_CONTENT_TYPE: &str = "application/scim+json";
+
+const USER_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:User";
+const LIST_RESPONSE_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:ListResponse";
+const PATCH_OP_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:PatchOp";
+const ERROR_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:Error";
+const SERVICE_PROVIDER_CONFIG_SCHEMA: &str =
+    "urn:ietf:params:scim:schemas:core:2.0:ServiceProviderConfig";
+const RESOURCE_TYPE_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:ResourceType";
+const SCHEMA_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:Schema";
+
+/// The maximum number of resources returned in a single list response
+const MAX_RESULTS: usize = 100;
+
+pub fn router<S>() -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+    BoxHomeserverConnection: FromRef<S>,
+    UrlBuilder: FromRef<S>,
+    BoxRng: FromRequestParts<S>,
+    BoxRepository: FromRequestParts<S>,
+    <BoxRepository as FromRequestParts<S>>::Rejection:
+        Into<Box<dyn std::error::Error + Send + Sync + 'static>>,
+    BoxClock: FromRequestParts<S, Rejection = Infallible>,
+    BoundActivityTracker: FromRequestParts<S, Rejection = Infallible>,
+{
+    Router::new()
+        .route("/ServiceProviderConfig", get(self::service_provider_config))
+        .route("/ResourceTypes", get(self::resource_types))
+        .route("/Schemas", get(self::schemas))
+        .route("/Users", get(self::users::list).post(self::users::create))
+        .route(
+            "/Users/:id",
+            get(self::users::get)
+                .put(self::users::replace)
+                .patch(self::users::patch)
+                .delete(self::users::delete),
+        )
+}
+
+/// A response with the SCIM media type
+pub struct Scim<T>(pub StatusCode, pub T);
+
+impl<T: Serialize> IntoResponse for Scim<T> {
+    fn into_response(self) -> Response {
+        let Scim(status, body) = self;
+        match serde_json::to_vec(&body) {
+            Ok(body) => (status, [(CONTENT_TYPE, SCIM_CONTENT_TYPE)], body).into_response(),
+            Err(e) => RouteError::Internal(Box::new(e)).into_response(),
+        }
+    }
+}
+
+/// The base URL of the SCIM API
+fn base_url(url_builder: &UrlBuilder) -> Url {
+    let mut url = url_builder.http_base();
+    url.set_path(&format!("{}{PREFIX}/", url.path().trim_end_matches('/')));
+    url
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error(transparent)]
+    Homeserver(anyhow::Error),
+
+    #[error(transparent)]
+    Unauthorized(#[from] Rejection),
+
+    #[error("Invalid request body: {0}")]
+    InvalidBody(#[from] JsonRejection),
+
+    #[error("Invalid query parameters: {0}")]
+    InvalidQuery(QueryRejection),
+
+    #[error("User {0} not found")]
+    UserNotFound(String),
+
+    #[error("Invalid filter: {0}")]
+    InvalidFilter(#[from] filter::FilterError),
+
+    #[error("Invalid patch request: {0}")]
+    InvalidPatch(String),
+
+    #[error("Username {0:?} is not valid")]
+    UsernameNotValid(String),
+
+    #[error("User {0:?} already exists")]
+    UserAlreadyExists(String),
+
+    #[error("Username {0:?} is reserved by the homeserver")]
+    UsernameReserved(String),
+
+    #[error("The username of a user can't be changed")]
+    UsernameImmutable,
+
+    #[error("Email address {0:?} is not valid")]
+    EmailNotValid(String),
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl RouteError {
+    /// The `scimType` of the error, as defined in RFC7644 section 3.12
+    fn scim_type(&self) -> Option<&'static str> {
+        match self {
+            Self::InvalidFilter(_) => Some("invalidFilter"),
+            Self::InvalidBody(_) => Some("invalidSyntax"),
+            Self::InvalidQuery(_)
+            | Self::InvalidPatch(_)
+            | Self::UsernameNotValid(_)
+            | Self::EmailNotValid(_) => Some("invalidValue"),
+            Self::UserAlreadyExists(_) | Self::UsernameReserved(_) => Some("uniqueness"),
+            Self::UsernameImmutable => Some("mutability"),
+            _ => None,
+        }
+    }
+}
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            Self::Internal(_) | Self::Homeserver(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::Unauthorized(rejection) => rejection.status_code(),
+            Self::UserNotFound(_) => StatusCode::NOT_FOUND,
+            Self::InvalidBody(_)
+            | Self::InvalidQuery(_)
+            | Self::InvalidFilter(_)
+            | Self::InvalidPatch(_)
+            | Self::UsernameNotValid(_)
+            | Self::UsernameImmutable
+            | Self::EmailNotValid(_) => StatusCode::BAD_REQUEST,
+            Self::UserAlreadyExists(_) | Self::UsernameReserved(_) => StatusCode::CONFLICT,
+        };
+
+        let mut body = json!({
+            "schemas": [ERROR_SCHEMA],
+            "status": status.as_str(),
+            "detail": self.to_string(),
+        });
+
+        if let Some(scim_type) = self.scim_type() {
+            body["scimType"] = scim_type.into();
+        }
+
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static(SCIM_CONTENT_TYPE));
+        if let Self::Unauthorized(rejection) = &self {
+            if let Some(www_authenticate) = rejection.www_authenticate() {
+                headers.insert(WWW_AUTHENTICATE, www_authenticate);
+            }
+        }
+
+        // Don't go through the `Scim` wrapper, to avoid recursing if the
+        // serialization fails
+        (status, headers, body.to_string()).into_response()
+    }
+}
+
+/// Parse the ID of a resource in the path
+///
+/// Invalid IDs are reported as unknown resources, as they can't exist
+fn parse_id(id: &str) -> Result<Ulid, RouteError> {
+    id.parse()
+        .map_err(|_| RouteError::UserNotFound(id.to_owned()))
+}
+
+async fn service_provider_config(State(url_builder): State<UrlBuilder>) -> impl IntoResponse {
+    let base = base_url(&url_builder);
+    Scim(
+        StatusCode::OK,
+        json!({
+            "schemas": [SERVICE_PROVIDER_CONFIG_SCHEMA],
+            "patch": { "supported": true },
+            "bulk": { "supported": false, "maxOperations": 0, "maxPayloadSize": 0 },
+            "filter": { "supported": true, "maxResults": MAX_RESULTS },
+            "changePassword": { "supported": false },
+            "sort": { "supported": false },
+            "etag": { "supported": false },
+            "authenticationSchemes": [{
+                "type": "oauthbearertoken",
+                "name": "OAuth Bearer Token",
+                "description": "Authentication with an access token having the urn:mas:admin scope",
+                "primary": true,
+            }],
+            "meta": {
+                "resourceType": "ServiceProviderConfig",
+                "location": base.join("ServiceProviderConfig").ok(),
+            },
+        }),
+    )
+}
+
+async fn resource_types(State(url_builder): State<UrlBuilder>) -> impl IntoResponse {
+    let base = base_url(&url_builder);
+    Scim(
+        StatusCode::OK,
+        json!({
+            "schemas": [LIST_RESPONSE_SCHEMA],
+            "totalResults": 1,
+            "startIndex": 1,
+            "itemsPerPage": 1,
+            "Resources": [{
+                "schemas": [RESOURCE_TYPE_SCHEMA],
+                "id": "User",
+                "name": "User",
+                "endpoint": "/Users",
+                "schema": USER_SCHEMA,
+                "meta": {
+                    "resourceType": "ResourceType",
+                    "location": base.join("ResourceTypes/User").ok(),
+                },
+            }],
+        }),
+    )
+}
+
+async fn schemas(State(url_builder): State<UrlBuilder>) -> impl IntoResponse {
+    let base = base_url(&url_builder);
+    Scim(
+        StatusCode::OK,
+        json!({
+            "schemas": [LIST_RESPONSE_SCHEMA],
+            "totalResults": 1,
+            "startIndex": 1,
+            "itemsPerPage": 1,
+            "Resources": [{
+                "schemas": [SCHEMA_SCHEMA],
+                "id": USER_SCHEMA,
+                "name": "User",
+                "description": "User Account",
+                "attributes": [
+                    {
+                        "name": "userName",
+                        "type": "string",
+                        "multiValued": false,
+                        "description": "The localpart of the Matrix ID of the user",
+                        "required": true,
+                        "caseExact": true,
+                        "mutability": "immutable",
+                        "returned": "default",
+                        "uniqueness": "server",
+                    },
+                    {
+                        "name": "active",
+                        "type": "boolean",
+                        "multiValued": false,
+                        "description": "Whether the user is active. Inactive users are deactivated on the homeserver",
+                        "required": false,
+                        "mutability": "readWrite",
+                        "returned": "default",
+                    },
+                    {
+                        "name": "emails",
+                        "type": "complex",
+                        "multiValued": true,
+                        "description": "The verified email addresses of the user",
+                        "required": false,
+                        "mutability": "readWrite",
+                        "returned": "default",
+                        "subAttributes": [
+                            {
+                                "name": "value",
+                                "type": "string",
+                                "multiValued": false,
+                                "required": true,
+                                "caseExact": false,
+                                "mutability": "readWrite",
+                                "returned": "default",
+                            },
+                            {
+                                "name": "primary",
+                                "type": "boolean",
+                                "multiValued": false,
+                                "required": false,
+                                "mutability": "readWrite",
+                                "returned": "default",
+                            },
+                        ],
+                    },
+                ],
+                "meta": {
+                    "resourceType": "Schema",
+                    "location": base.join(&format!("Schemas/{USER_SCHEMA}")).ok(),
+                },
+            }],
+        }),
+    )
+}
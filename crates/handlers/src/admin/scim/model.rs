@@ -0,0 +1,439 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+use chrono::{DateTime, Utc};
+use mas_data_model::UserEmail;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use ulid::Ulid;
+use url::Url;
+
+use super::{LIST_RESPONSE_SCHEMA, PATCH_OP_SCHEMA, USER_SCHEMA};
+
+/// Metadata of a SCIM resource
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Meta {
+    resource_type: &'static str,
+    created: DateTime<Utc>,
+    location: Url,
+}
+
+/// An email address of a SCIM user
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Email {
+    pub value: String,
+
+    #[serde(default)]
+    pub primary: bool,
+}
+
+/// A user, as represented by SCIM
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct User {
+    schemas: [&'static str; 1],
+    id: Ulid,
+    #[serde(rename = "userName")]
+    username: String,
+    active: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    emails: Vec<Email>,
+    meta: Meta,
+}
+
+impl User {
+    pub fn new(user: mas_data_model::User, emails: Vec<UserEmail>, base: &Url) -> Self {
+        let emails = emails
+            .into_iter()
+            .map(|email| Email {
+                primary: user.primary_user_email_id == Some(email.id),
+                value: email.email,
+            })
+            .collect();
+
+        // The base URL ends with `/Users/`, so we can join the ID directly
+        let location = base
+            .join(&user.id.to_string())
+            .unwrap_or_else(|_| base.clone());
+
+        Self {
+            schemas: [USER_SCHEMA],
+            id: user.id,
+            active: user.locked_at.is_none(),
+            username: user.username,
+            emails,
+            meta: Meta {
+                resource_type: "User",
+                created: user.created_at,
+                location,
+            },
+        }
+    }
+}
+
+/// A page of resources
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListResponse<T> {
+    schemas: [&'static str; 1],
+    total_results: usize,
+    start_index: usize,
+    items_per_page: usize,
+    #[serde(rename = "Resources")]
+    resources: Vec<T>,
+}
+
+impl<T> ListResponse<T> {
+    pub fn new(resources: Vec<T>, total_results: usize, start_index: usize) -> Self {
+        Self {
+            schemas: [LIST_RESPONSE_SCHEMA],
+            total_results,
+            start_index,
+            items_per_page: resources.len(),
+            resources,
+        }
+    }
+}
+
+/// The body of the requests creating or replacing a user
+///
+/// Attributes which are not part of the schema, like `name` or `externalId`,
+/// are ignored.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserRequest {
+    pub user_name: String,
+
+    #[serde(default = "default_true")]
+    pub active: bool,
+
+    #[serde(default)]
+    pub emails: Vec<Email>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// The attributes of a user which can be changed through SCIM
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserAttributes {
+    pub user_name: String,
+    pub active: bool,
+    pub emails: Vec<Email>,
+}
+
+impl From<UserRequest> for UserAttributes {
+    fn from(request: UserRequest) -> Self {
+        Self {
+            user_name: request.user_name,
+            active: request.active,
+            emails: request.emails,
+        }
+    }
+}
+
+impl UserAttributes {
+    pub fn new(user: &mas_data_model::User, emails: &[UserEmail]) -> Self {
+        Self {
+            user_name: user.username.clone(),
+            active: user.locked_at.is_none(),
+            emails: emails
+                .iter()
+                .map(|email| Email {
+                    value: email.email.clone(),
+                    primary: user.primary_user_email_id == Some(email.id),
+                })
+                .collect(),
+        }
+    }
+
+    /// Apply the operations of a PATCH request
+    ///
+    /// # Errors
+    ///
+    /// Returns an error message if one of the operations is invalid
+    pub fn apply(&mut self, request: PatchRequest) -> Result<(), String> {
+        if !request
+            .schemas
+            .iter()
+            .any(|schema| schema == PATCH_OP_SCHEMA)
+        {
+            return Err(format!(
+                "the request must have the {PATCH_OP_SCHEMA} schema"
+            ));
+        }
+
+        for operation in request.operations {
+            self.apply_operation(operation)?;
+        }
+
+        Ok(())
+    }
+
+    fn apply_operation(&mut self, operation: PatchOperation) -> Result<(), String> {
+        let op = operation.op.to_ascii_lowercase();
+        let Some(path) = operation.path else {
+            // Without a path, the value is an object with the attributes to set
+            if op == "remove" {
+                return Err("a path is required to remove an attribute".to_owned());
+            }
+
+            let Some(Value::Object(attributes)) = operation.value else {
+                return Err("the value must be an object when there is no path".to_owned());
+            };
+
+            for (path, value) in attributes {
+                self.apply_operation(PatchOperation {
+                    op: op.clone(),
+                    path: Some(path),
+                    value: Some(value),
+                })?;
+            }
+
+            return Ok(());
+        };
+
+        let path = path.to_ascii_lowercase();
+        let path = path
+            .strip_prefix(&format!("{}:", USER_SCHEMA.to_ascii_lowercase()))
+            .unwrap_or(&path);
+
+        match (op.as_str(), path) {
+            ("add" | "replace", "active") => {
+                self.active = match operation.value {
+                    Some(Value::Bool(active)) => active,
+                    // Some identity providers send booleans as strings
+                    Some(Value::String(active)) if active.eq_ignore_ascii_case("true") => true,
+                    Some(Value::String(active)) if active.eq_ignore_ascii_case("false") => false,
+                    _ => return Err("active must be a boolean".to_owned()),
+                };
+            }
+
+            ("add" | "replace", "username") => {
+                let Some(Value::String(user_name)) = operation.value else {
+                    return Err("userName must be a string".to_owned());
+                };
+                self.user_name = user_name;
+            }
+
+            ("add" | "replace", "emails") => {
+                let emails: Vec<Email> = match operation.value {
+                    Some(value @ Value::Array(_)) => serde_json::from_value(value),
+                    Some(value @ Value::Object(_)) => {
+                        serde_json::from_value(value).map(|e| vec![e])
+                    }
+                    _ => return Err("emails must be an array".to_owned()),
+                }
+                .map_err(|e| format!("invalid emails: {e}"))?;
+
+                if op == "replace" {
+                    self.emails = emails;
+                } else {
+                    for email in emails {
+                        self.add_email(email);
+                    }
+                }
+            }
+
+            ("remove", "emails") => self.emails.clear(),
+
+            ("remove", path) if path.starts_with("emails[") => {
+                // Only the `emails[value eq "…"]` form is supported
+                let filter = path
+                    .strip_prefix("emails[")
+                    .and_then(|filter| filter.strip_suffix(']'))
+                    .ok_or_else(|| format!("unsupported path {path:?}"))?;
+                // The attributes in the filter are relative to the emails
+                let filter: super::filter::Filter = format!("emails.{filter}")
+                    .parse()
+                    .map_err(|e| format!("invalid path: {e}"))?;
+
+                for comparison in filter.comparisons {
+                    let super::filter::Comparison::Email(value) = comparison else {
+                        return Err(format!("unsupported path {path:?}"));
+                    };
+                    self.emails
+                        .retain(|email| !email.value.eq_ignore_ascii_case(&value));
+                }
+            }
+
+            ("remove", "active" | "username") => {
+                return Err(format!("{path} can't be removed"));
+            }
+
+            ("add" | "replace" | "remove", _) => {
+                // Attributes which are not part of the schema are ignored
+            }
+
+            (op, _) => return Err(format!("unsupported operation {op:?}")),
+        }
+
+        Ok(())
+    }
+
+    fn add_email(&mut self, email: Email) {
+        if email.primary {
+            for existing in &mut self.emails {
+                existing.primary = false;
+            }
+        }
+
+        if let Some(existing) = self
+            .emails
+            .iter_mut()
+            .find(|existing| existing.value.eq_ignore_ascii_case(&email.value))
+        {
+            existing.primary |= email.primary;
+        } else {
+            self.emails.push(email);
+        }
+    }
+}
+
+/// A single operation of a PATCH request
+#[derive(Deserialize)]
+pub struct PatchOperation {
+    op: String,
+    path: Option<String>,
+    value: Option<Value>,
+}
+
+/// The body of a PATCH request
+#[derive(Deserialize)]
+pub struct PatchRequest {
+    schemas: Vec<String>,
+
+    #[serde(rename = "Operations")]
+    operations: Vec<PatchOperation>,
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn attributes() -> UserAttributes {
+        UserAttributes {
+            user_name: "alice".to_owned(),
+            active: true,
+            emails: vec![Email {
+                value: "alice@example.com".to_owned(),
+                primary: true,
+            }],
+        }
+    }
+
+    fn patch(operations: &Value) -> PatchRequest {
+        serde_json::from_value(json!({
+            "schemas": [PATCH_OP_SCHEMA],
+            "Operations": operations,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_patch_active() {
+        let mut attrs = attributes();
+        attrs
+            .apply(patch(
+                &json!([{ "op": "replace", "path": "active", "value": false }]),
+            ))
+            .unwrap();
+        assert!(!attrs.active);
+
+        // Entra ID capitalizes the operation and sends booleans as strings
+        attrs
+            .apply(patch(
+                &json!([{ "op": "Replace", "path": "active", "value": "True" }]),
+            ))
+            .unwrap();
+        assert!(attrs.active);
+
+        // Okta sends the attributes in the value, without a path
+        attrs
+            .apply(patch(
+                &json!([{ "op": "replace", "value": { "active": false } }]),
+            ))
+            .unwrap();
+        assert!(!attrs.active);
+    }
+
+    #[test]
+    fn test_patch_emails() {
+        let mut attrs = attributes();
+        attrs
+            .apply(patch(&json!([{
+                "op": "add",
+                "path": "emails",
+                "value": [{ "value": "alice@corp.example.com", "primary": true }],
+            }])))
+            .unwrap();
+        assert_eq!(
+            attrs.emails,
+            vec![
+                Email {
+                    value: "alice@example.com".to_owned(),
+                    primary: false,
+                },
+                Email {
+                    value: "alice@corp.example.com".to_owned(),
+                    primary: true,
+                },
+            ]
+        );
+
+        attrs
+            .apply(patch(&json!([{
+                "op": "remove",
+                "path": "emails[value eq \"alice@example.com\"]",
+            }])))
+            .unwrap();
+        assert_eq!(attrs.emails.len(), 1);
+        assert_eq!(attrs.emails[0].value, "alice@corp.example.com");
+
+        attrs
+            .apply(patch(&json!([{ "op": "remove", "path": "emails" }])))
+            .unwrap();
+        assert!(attrs.emails.is_empty());
+    }
+
+    #[test]
+    fn test_patch_invalid() {
+        let mut attrs = attributes();
+
+        // Missing schema
+        let request = serde_json::from_value(json!({
+            "schemas": [],
+            "Operations": [{ "op": "replace", "path": "active", "value": false }],
+        }))
+        .unwrap();
+        assert!(attrs.apply(request).is_err());
+
+        // Invalid value
+        assert!(attrs
+            .apply(patch(
+                &json!([{ "op": "replace", "path": "active", "value": 42 }])
+            ))
+            .is_err());
+
+        // Invalid operation
+        assert!(attrs
+            .apply(patch(
+                &json!([{ "op": "move", "path": "active", "value": false }])
+            ))
+            .is_err());
+
+        // Unknown attributes are ignored
+        attrs
+            .apply(patch(
+                &json!([{ "op": "replace", "path": "name.givenName", "value": "Alice" }]),
+            ))
+            .unwrap();
+        assert_eq!(attrs, attributes());
+    }
+}
@@ -0,0 +1,1013 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+use axum::{
+    extract::{
+        rejection::{JsonRejection, QueryRejection},
+        Path, Query, State,
+    },
+    Json,
+};
+use hyper::StatusCode;
+use mas_data_model::{AuditLogEventKind, User, UserEmail};
+use mas_matrix::BoxHomeserverConnection;
+use mas_router::UrlBuilder;
+use mas_storage::{
+    job::{
+        DeactivateUserJob, JobRepositoryExt, ProvisionUserJob, ReactivateUserJob,
+        SendWebhookEventJob,
+    },
+    user::{UserEmailFilter, UserFilter},
+    BoxClock, BoxRepository, BoxRng, Pagination,
+};
+use serde::Deserialize;
+use serde_json::json;
+use tracing::{info, warn};
+use url::Url;
+
+use super::{
+    filter::{Comparison, Filter},
+    model::{self, ListResponse, PatchRequest, UserAttributes, UserRequest},
+    parse_id, RouteError, Scim, MAX_RESULTS,
+};
+use crate::{
+    admin::call_context::{Actor, CallContext, ReadUsers, Rejection, WriteUsers},
+    username::username_valid,
+};
+
+/// The base URL of the `Users` resource
+fn users_url(url_builder: &UrlBuilder) -> Url {
+    let base = super::base_url(url_builder);
+    base.join("Users/").unwrap_or(base)
+}
+
+/// Load the verified email addresses of a user
+///
+/// Pending email addresses are not exposed through SCIM
+async fn verified_emails(
+    repo: &mut BoxRepository,
+    user: &User,
+) -> Result<Vec<UserEmail>, RouteError> {
+    let emails = repo.user_email().all(user).await?;
+    Ok(emails
+        .into_iter()
+        .filter(|email| email.confirmed_at.is_some())
+        .collect())
+}
+
+/// Find the users which could match the filter, if it has a comparison which
+/// restricts it to a few users
+async fn candidates(
+    repo: &mut BoxRepository,
+    filter: &Filter,
+) -> Result<Option<Vec<User>>, RouteError> {
+    for comparison in &filter.comparisons {
+        match comparison {
+            Comparison::Id(id) => {
+                let user = repo.user().lookup(*id).await?;
+                return Ok(Some(user.into_iter().collect()));
+            }
+
+            Comparison::UserName(username) => {
+                let user = repo.user().find_by_username(username).await?;
+                return Ok(Some(user.into_iter().collect()));
+            }
+
+            Comparison::Email(email) => {
+                let page = repo
+                    .user_email()
+                    .list(
+                        UserEmailFilter::new().for_email(email).verified_only(),
+                        Pagination::first(MAX_RESULTS),
+                    )
+                    .await?;
+
+                let mut users = Vec::with_capacity(page.edges.len());
+                for user_email in page.edges {
+                    if let Some(user) = repo.user().lookup(user_email.user_id).await? {
+                        users.push(user);
+                    }
+                }
+                return Ok(Some(users));
+            }
+
+            Comparison::Active(_) => {}
+        }
+    }
+
+    Ok(None)
+}
+
+/// List the users matching the filter, skipping the first `offset` ones
+///
+/// The storage layer only supports cursor-based pagination, so we walk
+/// through the users to find the requested page.
+async fn list_with_offset(
+    repo: &mut BoxRepository,
+    filter: UserFilter<'_>,
+    mut offset: usize,
+    count: usize,
+) -> Result<Vec<User>, RouteError> {
+    let mut users = Vec::with_capacity(count);
+    let mut pagination = Pagination::first(MAX_RESULTS);
+
+    while users.len() < count {
+        let page = repo.user().list(filter, pagination).await?;
+        let Some(last) = page.edges.last() else {
+            break;
+        };
+        pagination = pagination.after(last.id);
+
+        let skipped = offset.min(page.edges.len());
+        offset -= skipped;
+        users.extend(
+            page.edges
+                .into_iter()
+                .skip(skipped)
+                .take(count - users.len()),
+        );
+
+        if !page.has_next_page {
+            break;
+        }
+    }
+
+    Ok(users)
+}
+
+/// Bring the user in line with the attributes sent by the identity provider
+///
+/// Returns the updated user and their verified email addresses
+#[allow(clippy::too_many_lines)]
+async fn sync(
+    repo: &mut BoxRepository,
+    rng: &mut BoxRng,
+    clock: &BoxClock,
+    actor: &Actor,
+    user: User,
+    emails: Vec<UserEmail>,
+    attributes: UserAttributes,
+) -> Result<(User, Vec<UserEmail>), RouteError> {
+    for email in &attributes.emails {
+        if email.value.parse::<lettre::Address>().is_err() {
+            return Err(RouteError::EmailNotValid(email.value.clone()));
+        }
+    }
+
+    let mut emails_changed = false;
+
+    // Remove the email addresses which are not there anymore
+    let mut kept = Vec::with_capacity(emails.len());
+    for user_email in emails {
+        if attributes
+            .emails
+            .iter()
+            .any(|email| email.value.eq_ignore_ascii_case(&user_email.email))
+        {
+            kept.push(user_email);
+            continue;
+        }
+
+        repo.audit_log()
+            .add(
+                rng,
+                clock,
+                actor
+                    .event(AuditLogEventKind::EmailRemoved)
+                    .for_user(&user)
+                    .with_data(json!({
+                        "user_email_id": user_email.id,
+                        "email": user_email.email,
+                    })),
+            )
+            .await?;
+
+        repo.user_email().remove(user_email).await?;
+        emails_changed = true;
+    }
+    let mut emails = kept;
+
+    // Add the new ones. They come from a trusted source, so they are
+    // considered verified
+    for email in &attributes.emails {
+        if emails
+            .iter()
+            .any(|user_email| user_email.email.eq_ignore_ascii_case(&email.value))
+        {
+            continue;
+        }
+
+        // The address may already be there but pending verification
+        let existing = repo.user_email().find(&user, &email.value).await?;
+        let user_email = if let Some(user_email) = existing {
+            user_email
+        } else {
+            repo.user_email()
+                .add(rng, clock, &user, email.value.clone())
+                .await?
+        };
+
+        let user_email = repo
+            .user_email()
+            .mark_as_verified(clock, user_email)
+            .await?;
+
+        repo.job()
+            .schedule_job(SendWebhookEventJob::user_email_verified(&user, &user_email))
+            .await?;
+
+        repo.audit_log()
+            .add(
+                rng,
+                clock,
+                actor
+                    .event(AuditLogEventKind::EmailAdded)
+                    .for_user(&user)
+                    .with_data(json!({
+                        "user_email_id": user_email.id,
+                        "email": user_email.email,
+                    })),
+            )
+            .await?;
+
+        emails.push(user_email);
+        emails_changed = true;
+    }
+
+    // Pick the primary email address: the one flagged as such, else keep the
+    // current one if it is still there, else the first one
+    let primary = attributes
+        .emails
+        .iter()
+        .find(|email| email.primary)
+        .and_then(|primary| {
+            emails
+                .iter()
+                .find(|user_email| user_email.email.eq_ignore_ascii_case(&primary.value))
+        })
+        .or_else(|| {
+            emails
+                .iter()
+                .find(|user_email| Some(user_email.id) == user.primary_user_email_id)
+        })
+        .or_else(|| emails.first());
+
+    if let Some(primary) = primary {
+        if Some(primary.id) != user.primary_user_email_id {
+            repo.user_email().set_as_primary(primary).await?;
+
+            repo.audit_log()
+                .add(
+                    rng,
+                    clock,
+                    actor
+                        .event(AuditLogEventKind::PrimaryEmailChanged)
+                        .for_user(&user)
+                        .with_data(json!({
+                            "user_email_id": primary.id,
+                            "email": primary.email,
+                        })),
+                )
+                .await?;
+        }
+    }
+
+    let mut user = repo
+        .user()
+        .lookup(user.id)
+        .await?
+        .ok_or_else(|| RouteError::UserNotFound(user.id.to_string()))?;
+
+    if emails_changed {
+        // Sync the email addresses of the user with the homeserver
+        repo.job()
+            .schedule_job(ProvisionUserJob::new(&user))
+            .await?;
+    }
+
+    if !attributes.active && user.locked_at.is_none() {
+        info!(user.id = %user.id, "Deactivating user");
+        user = repo.user().lock(clock, user).await?;
+
+        repo.job()
+            .schedule_job(SendWebhookEventJob::user_locked(&user))
+            .await?;
+
+        // Don't erase the user, as they may be reactivated later
+        repo.job()
+            .schedule_job(DeactivateUserJob::new(&user, false))
+            .await?;
+    } else if attributes.active && user.locked_at.is_some() {
+        // The user is unlocked by the job, once the homeserver reactivated them
+        info!(user.id = %user.id, "Reactivating user");
+        repo.job()
+            .schedule_job(ReactivateUserJob::new(&user))
+            .await?;
+    }
+
+    Ok((user, emails))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListParams {
+    filter: Option<String>,
+    start_index: Option<usize>,
+    count: Option<usize>,
+}
+
+#[tracing::instrument(name = "handler.admin.scim.users.list", skip_all, err)]
+pub async fn list(
+    context: Result<CallContext<ReadUsers>, Rejection>,
+    State(url_builder): State<UrlBuilder>,
+    params: Result<Query<ListParams>, QueryRejection>,
+) -> Result<Scim<ListResponse<model::User>>, RouteError> {
+    let CallContext { mut repo, .. } = context?;
+    let Query(params) = params.map_err(RouteError::InvalidQuery)?;
+
+    let filter: Filter = params
+        .filter
+        .as_deref()
+        .map(str::parse)
+        .transpose()?
+        .unwrap_or_default();
+
+    // Indexes are 1-based
+    let start_index = params.start_index.unwrap_or(1).max(1);
+    let count = params.count.unwrap_or(MAX_RESULTS).min(MAX_RESULTS);
+    let base = users_url(&url_builder);
+
+    let (users, total) = if let Some(candidates) = candidates(&mut repo, &filter).await? {
+        let mut users = Vec::with_capacity(candidates.len());
+        for user in candidates {
+            let emails = verified_emails(&mut repo, &user).await?;
+            if filter.matches(&user, &emails) {
+                users.push((user, emails));
+            }
+        }
+
+        let total = users.len();
+        let users = users
+            .into_iter()
+            .skip(start_index - 1)
+            .take(count)
+            .collect();
+        (users, total)
+    } else {
+        // The filter only has comparisons on the `active` attribute
+        let active = filter
+            .comparisons
+            .iter()
+            .filter_map(|comparison| match comparison {
+                Comparison::Active(active) => Some(*active),
+                _ => None,
+            });
+
+        let (mut wants_active, mut wants_locked) = (false, false);
+        for active in active {
+            wants_active |= active;
+            wants_locked |= !active;
+        }
+
+        let user_filter = match (wants_active, wants_locked) {
+            (true, true) => None,
+            (true, false) => Some(UserFilter::new().active_only()),
+            (false, true) => Some(UserFilter::new().locked_only()),
+            (false, false) => Some(UserFilter::new()),
+        };
+
+        if let Some(user_filter) = user_filter {
+            let total = repo.user().count(user_filter).await?;
+            let page = list_with_offset(&mut repo, user_filter, start_index - 1, count).await?;
+
+            let mut users = Vec::with_capacity(page.len());
+            for user in page {
+                let emails = verified_emails(&mut repo, &user).await?;
+                users.push((user, emails));
+            }
+            (users, total)
+        } else {
+            // A user can't be both active and inactive
+            (Vec::new(), 0)
+        }
+    };
+
+    let resources = users
+        .into_iter()
+        .map(|(user, emails)| model::User::new(user, emails, &base))
+        .collect();
+
+    Ok(Scim(
+        StatusCode::OK,
+        ListResponse::new(resources, total, start_index),
+    ))
+}
+
+#[tracing::instrument(name = "handler.admin.scim.users.get", skip_all, err)]
+pub async fn get(
+    context: Result<CallContext<ReadUsers>, Rejection>,
+    State(url_builder): State<UrlBuilder>,
+    Path(id): Path<String>,
+) -> Result<Scim<model::User>, RouteError> {
+    let CallContext { mut repo, .. } = context?;
+    let id = parse_id(&id)?;
+
+    let user = repo
+        .user()
+        .lookup(id)
+        .await?
+        .ok_or_else(|| RouteError::UserNotFound(id.to_string()))?;
+
+    let emails = verified_emails(&mut repo, &user).await?;
+
+    Ok(Scim(
+        StatusCode::OK,
+        model::User::new(user, emails, &users_url(&url_builder)),
+    ))
+}
+
+#[tracing::instrument(name = "handler.admin.scim.users.create", skip_all, err)]
+pub async fn create(
+    context: Result<CallContext<WriteUsers>, Rejection>,
+    mut rng: BoxRng,
+    State(homeserver): State<BoxHomeserverConnection>,
+    State(url_builder): State<UrlBuilder>,
+    body: Result<Json<UserRequest>, JsonRejection>,
+) -> Result<Scim<model::User>, RouteError> {
+    let CallContext {
+        mut repo,
+        clock,
+        actor,
+        ..
+    } = context?;
+    let Json(body) = body?;
+    let attributes = UserAttributes::from(body);
+    let username = attributes.user_name.clone();
+
+    if repo.user().exists(&username).await? {
+        return Err(RouteError::UserAlreadyExists(username));
+    }
+
+    if !username_valid(&username) {
+        return Err(RouteError::UsernameNotValid(username));
+    }
+
+    let homeserver_available = homeserver
+        .is_localpart_available(&username)
+        .await
+        .map_err(RouteError::Homeserver)?;
+
+    if !homeserver_available {
+        warn!("Username {username} is reserved by the homeserver");
+        return Err(RouteError::UsernameReserved(username));
+    }
+
+    let user = repo.user().add(&mut rng, &clock, username).await?;
+
+    repo.job()
+        .schedule_job(ProvisionUserJob::new(&user))
+        .await?;
+
+    repo.job()
+        .schedule_job(SendWebhookEventJob::user_registered(&user))
+        .await?;
+
+    repo.audit_log()
+        .add(
+            &mut rng,
+            &clock,
+            actor.action("scimCreateUser", user.id).for_user(&user),
+        )
+        .await?;
+
+    let (user, emails) = sync(
+        &mut repo,
+        &mut rng,
+        &clock,
+        &actor,
+        user,
+        Vec::new(),
+        attributes,
+    )
+    .await?;
+
+    repo.save().await?;
+
+    Ok(Scim(
+        StatusCode::CREATED,
+        model::User::new(user, emails, &users_url(&url_builder)),
+    ))
+}
+
+#[tracing::instrument(name = "handler.admin.scim.users.replace", skip_all, err)]
+pub async fn replace(
+    context: Result<CallContext<WriteUsers>, Rejection>,
+    mut rng: BoxRng,
+    State(url_builder): State<UrlBuilder>,
+    Path(id): Path<String>,
+    body: Result<Json<UserRequest>, JsonRejection>,
+) -> Result<Scim<model::User>, RouteError> {
+    let CallContext {
+        mut repo,
+        clock,
+        actor,
+        ..
+    } = context?;
+    let id = parse_id(&id)?;
+    let Json(body) = body?;
+    let attributes = UserAttributes::from(body);
+
+    let user = repo
+        .user()
+        .lookup(id)
+        .await?
+        .ok_or_else(|| RouteError::UserNotFound(id.to_string()))?;
+
+    if attributes.user_name != user.username {
+        return Err(RouteError::UsernameImmutable);
+    }
+
+    repo.audit_log()
+        .add(
+            &mut rng,
+            &clock,
+            actor.action("scimReplaceUser", user.id).for_user(&user),
+        )
+        .await?;
+
+    let emails = verified_emails(&mut repo, &user).await?;
+    let (user, emails) = sync(
+        &mut repo, &mut rng, &clock, &actor, user, emails, attributes,
+    )
+    .await?;
+
+    repo.save().await?;
+
+    Ok(Scim(
+        StatusCode::OK,
+        model::User::new(user, emails, &users_url(&url_builder)),
+    ))
+}
+
+#[tracing::instrument(name = "handler.admin.scim.users.patch", skip_all, err)]
+pub async fn patch(
+    context: Result<CallContext<WriteUsers>, Rejection>,
+    mut rng: BoxRng,
+    State(url_builder): State<UrlBuilder>,
+    Path(id): Path<String>,
+    body: Result<Json<PatchRequest>, JsonRejection>,
+) -> Result<Scim<model::User>, RouteError> {
+    let CallContext {
+        mut repo,
+        clock,
+        actor,
+        ..
+    } = context?;
+    let id = parse_id(&id)?;
+    let Json(body) = body?;
+
+    let user = repo
+        .user()
+        .lookup(id)
+        .await?
+        .ok_or_else(|| RouteError::UserNotFound(id.to_string()))?;
+
+    let emails = verified_emails(&mut repo, &user).await?;
+    let mut attributes = UserAttributes::new(&user, &emails);
+    attributes.apply(body).map_err(RouteError::InvalidPatch)?;
+
+    if attributes.user_name != user.username {
+        return Err(RouteError::UsernameImmutable);
+    }
+
+    repo.audit_log()
+        .add(
+            &mut rng,
+            &clock,
+            actor.action("scimPatchUser", user.id).for_user(&user),
+        )
+        .await?;
+
+    let (user, emails) = sync(
+        &mut repo, &mut rng, &clock, &actor, user, emails, attributes,
+    )
+    .await?;
+
+    repo.save().await?;
+
+    Ok(Scim(
+        StatusCode::OK,
+        model::User::new(user, emails, &users_url(&url_builder)),
+    ))
+}
+
+/// Users can't be deleted, so deleting them through SCIM deactivates them and
+/// erases them from the homeserver
+///
+/// This deviates from RFC 7644 §3.6: the user stays visible through `GET` and
+/// in listings, with `active` set to `false`. MAS has no record of deleted
+/// users, and keeping them around is what keeps their username reserved.
+#[tracing::instrument(name = "handler.admin.scim.users.delete", skip_all, err)]
+pub async fn delete(
+    context: Result<CallContext<WriteUsers>, Rejection>,
+    mut rng: BoxRng,
+    Path(id): Path<String>,
+) -> Result<StatusCode, RouteError> {
+    let CallContext {
+        mut repo,
+        clock,
+        actor,
+        ..
+    } = context?;
+    let id = parse_id(&id)?;
+
+    let mut user = repo
+        .user()
+        .lookup(id)
+        .await?
+        .ok_or_else(|| RouteError::UserNotFound(id.to_string()))?;
+
+    if user.locked_at.is_none() {
+        user = repo.user().lock(&clock, user).await?;
+
+        repo.job()
+            .schedule_job(SendWebhookEventJob::user_locked(&user))
+            .await?;
+    }
+
+    info!("Scheduling deactivation of user {}", user.id);
+    repo.job()
+        .schedule_job(DeactivateUserJob::new(&user, true))
+        .await?;
+
+    repo.audit_log()
+        .add(
+            &mut rng,
+            &clock,
+            actor.action("scimDeleteUser", user.id).for_user(&user),
+        )
+        .await?;
+
+    repo.save().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use hyper::{header::CONTENT_TYPE, Request, Response, StatusCode};
+    use mas_storage::{user::UserRepository, RepositoryAccess};
+    use sqlx::{types::Json, PgPool};
+
+    use crate::test_utils::{setup, RequestBuilderExt, ResponseExt, TestState};
+
+    /// Parse a SCIM response body, which isn't served as `application/json`
+    #[track_caller]
+    fn scim_json(response: &Response<String>) -> serde_json::Value {
+        response.assert_header_value(CONTENT_TYPE, "application/scim+json");
+        serde_json::from_str(response.body()).unwrap()
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_create_user(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool.clone()).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let request = Request::post("/scim/v2/Users")
+            .bearer(&token)
+            .json(serde_json::json!({
+                "schemas": ["urn:ietf:params:scim:schemas:core:2.0:User"],
+                "userName": "alice",
+                "name": { "givenName": "Alice" },
+                "emails": [{ "value": "alice@example.com", "primary": true }],
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::CREATED);
+        assert_eq!(
+            response.headers().get("content-type").unwrap(),
+            "application/scim+json"
+        );
+        let body = scim_json(&response);
+        assert_eq!(body["userName"], "alice");
+        assert_eq!(body["active"], true);
+        assert_eq!(body["emails"][0]["value"], "alice@example.com");
+        assert_eq!(body["emails"][0]["primary"], true);
+
+        // The user and their email address should be in the database
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .find_by_username("alice")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(body["id"], user.id.to_string());
+        let email = repo.user_email().get_primary(&user).await.unwrap().unwrap();
+        assert_eq!(email.email, "alice@example.com");
+        assert!(email.confirmed_at.is_some());
+
+        // Creating the same user again should fail
+        let request = Request::post("/scim/v2/Users")
+            .bearer(&token)
+            .json(serde_json::json!({
+                "schemas": ["urn:ietf:params:scim:schemas:core:2.0:User"],
+                "userName": "alice",
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::CONFLICT);
+        let body = scim_json(&response);
+        assert_eq!(body["scimType"], "uniqueness");
+        assert_eq!(body["status"], "409");
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_list_users(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin:users:read").await;
+
+        let mut repo = state.repository().await.unwrap();
+        let alice = repo
+            .user()
+            .add(&mut state.rng(), &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        let email = repo
+            .user_email()
+            .add(
+                &mut state.rng(),
+                &state.clock,
+                &alice,
+                "alice@example.com".to_owned(),
+            )
+            .await
+            .unwrap();
+        repo.user_email()
+            .mark_as_verified(&state.clock, email)
+            .await
+            .unwrap();
+        // Advance the clock so that the users are listed in a stable order
+        state.clock.advance(Duration::try_minutes(1).unwrap());
+        let bob = repo
+            .user()
+            .add(&mut state.rng(), &state.clock, "bob".to_owned())
+            .await
+            .unwrap();
+        repo.user().lock(&state.clock, bob).await.unwrap();
+        repo.save().await.unwrap();
+
+        // List all users
+        let request = Request::get("/scim/v2/Users").bearer(&token).empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body = scim_json(&response);
+        assert_eq!(body["totalResults"], 2);
+        assert_eq!(body["startIndex"], 1);
+        assert_eq!(body["Resources"][0]["userName"], "alice");
+        assert_eq!(body["Resources"][1]["userName"], "bob");
+
+        // Get the second page
+        let request = Request::get("/scim/v2/Users?startIndex=2&count=1")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body = scim_json(&response);
+        assert_eq!(body["totalResults"], 2);
+        assert_eq!(body["itemsPerPage"], 1);
+        assert_eq!(body["Resources"][0]["userName"], "bob");
+
+        // Filter by username
+        let request = Request::get("/scim/v2/Users?filter=userName%20eq%20%22alice%22")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body = scim_json(&response);
+        assert_eq!(body["totalResults"], 1);
+        assert_eq!(body["Resources"][0]["userName"], "alice");
+
+        // Filter by email
+        let request =
+            Request::get("/scim/v2/Users?filter=emails.value%20eq%20%22alice%40example.com%22")
+                .bearer(&token)
+                .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body = scim_json(&response);
+        assert_eq!(body["totalResults"], 1);
+        assert_eq!(body["Resources"][0]["userName"], "alice");
+
+        // Filter inactive users
+        let request = Request::get("/scim/v2/Users?filter=active%20eq%20false")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body = scim_json(&response);
+        assert_eq!(body["totalResults"], 1);
+        assert_eq!(body["Resources"][0]["userName"], "bob");
+        assert_eq!(body["Resources"][0]["active"], false);
+
+        // Unsupported filters are rejected
+        let request = Request::get("/scim/v2/Users?filter=userName%20sw%20%22a%22")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        let body = scim_json(&response);
+        assert_eq!(body["scimType"], "invalidFilter");
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_patch_user(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool.clone()).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin:users:write").await;
+
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut state.rng(), &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let request = Request::patch(format!("/scim/v2/Users/{}", user.id))
+            .bearer(&token)
+            .json(serde_json::json!({
+                "schemas": ["urn:ietf:params:scim:api:messages:2.0:PatchOp"],
+                "Operations": [
+                    {
+                        "op": "add",
+                        "path": "emails",
+                        "value": [{ "value": "alice@example.com", "primary": true }],
+                    },
+                    { "op": "Replace", "path": "active", "value": "False" },
+                ],
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body = scim_json(&response);
+        assert_eq!(body["active"], false);
+        assert_eq!(body["emails"][0]["value"], "alice@example.com");
+
+        // The user should be locked, and a deactivation job scheduled
+        let mut repo = state.repository().await.unwrap();
+        let user = repo.user().lookup(user.id).await.unwrap().unwrap();
+        assert!(user.locked_at.is_some());
+
+        let job: Json<serde_json::Value> =
+            sqlx::query_scalar("SELECT job FROM apalis.jobs WHERE job_type = 'deactivate-user'")
+                .fetch_one(&pool)
+                .await
+                .expect("Deactivation job to be scheduled");
+        assert_eq!(job["user_id"], serde_json::json!(user.id));
+        assert_eq!(job["hs_erase"], false);
+
+        // Reactivating the user schedules a reactivation job
+        let request = Request::patch(format!("/scim/v2/Users/{}", user.id))
+            .bearer(&token)
+            .json(serde_json::json!({
+                "schemas": ["urn:ietf:params:scim:api:messages:2.0:PatchOp"],
+                "Operations": [{ "op": "replace", "value": { "active": true } }],
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+
+        let job: Json<serde_json::Value> =
+            sqlx::query_scalar("SELECT job FROM apalis.jobs WHERE job_type = 'reactivate-user'")
+                .fetch_one(&pool)
+                .await
+                .expect("Reactivation job to be scheduled");
+        assert_eq!(job["user_id"], serde_json::json!(user.id));
+
+        // The username can't be changed
+        let request = Request::patch(format!("/scim/v2/Users/{}", user.id))
+            .bearer(&token)
+            .json(serde_json::json!({
+                "schemas": ["urn:ietf:params:scim:api:messages:2.0:PatchOp"],
+                "Operations": [{ "op": "replace", "path": "userName", "value": "bob" }],
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        let body = scim_json(&response);
+        assert_eq!(body["scimType"], "mutability");
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_replace_user_emails(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let request = Request::post("/scim/v2/Users")
+            .bearer(&token)
+            .json(serde_json::json!({
+                "schemas": ["urn:ietf:params:scim:schemas:core:2.0:User"],
+                "userName": "alice",
+                "emails": [
+                    { "value": "alice@example.com" },
+                    { "value": "alice@corp.example.com" },
+                ],
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::CREATED);
+        let body = scim_json(&response);
+        let id = body["id"].as_str().unwrap().to_owned();
+        // The first address becomes the primary one
+        assert_eq!(body["emails"][0]["primary"], true);
+        assert_eq!(body["emails"][1]["primary"], false);
+
+        let request = Request::put(format!("/scim/v2/Users/{id}"))
+            .bearer(&token)
+            .json(serde_json::json!({
+                "schemas": ["urn:ietf:params:scim:schemas:core:2.0:User"],
+                "userName": "alice",
+                "emails": [{ "value": "alice@corp.example.com", "primary": true }],
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body = scim_json(&response);
+        assert_eq!(body["emails"].as_array().unwrap().len(), 1);
+        assert_eq!(body["emails"][0]["value"], "alice@corp.example.com");
+        assert_eq!(body["emails"][0]["primary"], true);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_delete_user(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool.clone()).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut state.rng(), &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let request = Request::delete(format!("/scim/v2/Users/{}", user.id))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NO_CONTENT);
+
+        let job: Json<serde_json::Value> =
+            sqlx::query_scalar("SELECT job FROM apalis.jobs WHERE job_type = 'deactivate-user'")
+                .fetch_one(&pool)
+                .await
+                .expect("Deactivation job to be scheduled");
+        assert_eq!(job["user_id"], serde_json::json!(user.id));
+        assert_eq!(job["hs_erase"], true);
+
+        // The user is still returned afterwards, as inactive
+        let request = Request::get(format!("/scim/v2/Users/{}", user.id))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body = scim_json(&response);
+        assert_eq!(body["userName"], "alice");
+        assert_eq!(body["active"], false);
+
+        // Unknown users are reported as such
+        let request = Request::delete("/scim/v2/Users/not-a-ulid")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NOT_FOUND);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_scopes(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin:users:read").await;
+
+        // Read-only tokens can't create users
+        let request = Request::post("/scim/v2/Users")
+            .bearer(&token)
+            .json(serde_json::json!({
+                "schemas": ["urn:ietf:params:scim:schemas:core:2.0:User"],
+                "userName": "alice",
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::FORBIDDEN);
+        let body = scim_json(&response);
+        assert_eq!(
+            body["schemas"][0],
+            "urn:ietf:params:scim:api:messages:2.0:Error"
+        );
+
+        // Requests without a token are rejected
+        let request = Request::get("/scim/v2/Users").empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,319 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+use aide::{transform::TransformOperation, OperationIo};
+use axum::{
+    extract::{rejection::QueryRejection, Query},
+    response::IntoResponse,
+    Json,
+};
+use axum_macros::FromRequestParts;
+use chrono::{DateTime, SecondsFormat, Utc};
+use hyper::StatusCode;
+use mas_storage::{audit_log::AuditLogFilter, Page};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use ulid::Ulid;
+
+use crate::{
+    admin::{
+        call_context::{CallContext, Read},
+        model::{AuditLogEvent, AuditLogEventKind, Resource},
+        params::Pagination,
+        response::{ErrorResponse, PaginatedResponse},
+    },
+    impl_from_error_for_route,
+};
+
+#[derive(FromRequestParts, Deserialize, JsonSchema, OperationIo)]
+#[serde(rename = "AuditLogFilter")]
+#[aide(input_with = "Query<FilterParams>")]
+#[from_request(via(Query), rejection(RouteError))]
+pub struct FilterParams {
+    /// Retrieve the events affecting the given user
+    #[serde(rename = "filter[user]")]
+    #[schemars(with = "Option<crate::admin::schema::Ulid>")]
+    user: Option<Ulid>,
+
+    /// Retrieve the events done by the given user
+    #[serde(rename = "filter[actor-user]")]
+    #[schemars(with = "Option<crate::admin::schema::Ulid>")]
+    actor_user: Option<Ulid>,
+
+    /// Retrieve the events of the given kind
+    #[serde(rename = "filter[kind]")]
+    kind: Option<AuditLogEventKind>,
+
+    /// Retrieve the events recorded before the given date
+    #[serde(rename = "filter[created-before]")]
+    created_before: Option<DateTime<Utc>>,
+
+    /// Retrieve the events recorded after the given date
+    #[serde(rename = "filter[created-after]")]
+    created_after: Option<DateTime<Utc>>,
+}
+
+impl std::fmt::Display for FilterParams {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut sep = '?';
+
+        if let Some(user) = self.user {
+            write!(f, "{sep}filter[user]={user}")?;
+            sep = '&';
+        }
+
+        if let Some(actor_user) = self.actor_user {
+            write!(f, "{sep}filter[actor-user]={actor_user}")?;
+            sep = '&';
+        }
+
+        if let Some(kind) = self.kind {
+            write!(f, "{sep}filter[kind]={kind}")?;
+            sep = '&';
+        }
+
+        if let Some(created_before) = self.created_before {
+            let created_before = created_before.to_rfc3339_opts(SecondsFormat::Secs, true);
+            write!(f, "{sep}filter[created-before]={created_before}")?;
+            sep = '&';
+        }
+
+        if let Some(created_after) = self.created_after {
+            let created_after = created_after.to_rfc3339_opts(SecondsFormat::Secs, true);
+            write!(f, "{sep}filter[created-after]={created_after}")?;
+            sep = '&';
+        }
+
+        let _ = sep;
+        Ok(())
+    }
+}
+
+#[derive(Debug, thiserror::Error, OperationIo)]
+#[aide(output_with = "Json<ErrorResponse>")]
+pub enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error("User ID {0} not found")]
+    UserNotFound(Ulid),
+
+    #[error("Invalid filter parameters")]
+    InvalidFilter(#[from] QueryRejection),
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let error = ErrorResponse::from_error(&self);
+        let status = match self {
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::UserNotFound(_) => StatusCode::NOT_FOUND,
+            Self::InvalidFilter(_) => StatusCode::BAD_REQUEST,
+        };
+        (status, Json(error)).into_response()
+    }
+}
+
+pub fn doc(operation: TransformOperation) -> TransformOperation {
+    operation
+        .id("listAuditLogEvents")
+        .summary("List audit log events")
+        .description(
+            "Retrieve a list of security events recorded in the audit log, oldest first.
+Use the `filter[user]` parameter to retrieve the events affecting a specific user, and the `filter[actor-user]` parameter to retrieve the events done by a specific user.",
+        )
+        .tag("audit-log")
+        .response_with::<200, Json<PaginatedResponse<AuditLogEvent>>, _>(|t| {
+            let events = AuditLogEvent::samples();
+            let pagination = mas_storage::Pagination::first(events.len());
+            let page = Page {
+                edges: events.into(),
+                has_next_page: true,
+                has_previous_page: false,
+            };
+
+            t.description("Paginated response of audit log events")
+                .example(PaginatedResponse::new(
+                    page,
+                    pagination,
+                    42,
+                    AuditLogEvent::PATH,
+                ))
+        })
+        .response_with::<404, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::UserNotFound(Ulid::nil()));
+            t.description("User was not found").example(response)
+        })
+}
+
+#[tracing::instrument(name = "handler.admin.v1.audit_log.list", skip_all, err)]
+pub async fn handler(
+    CallContext { mut repo, .. }: CallContext<Read>,
+    Pagination(pagination): Pagination,
+    params: FilterParams,
+) -> Result<Json<PaginatedResponse<AuditLogEvent>>, RouteError> {
+    let base = format!("{path}{params}", path = AuditLogEvent::PATH);
+    let filter = AuditLogFilter::new();
+
+    // Load the users from the filter
+    let user = if let Some(user_id) = params.user {
+        let user = repo
+            .user()
+            .lookup(user_id)
+            .await?
+            .ok_or(RouteError::UserNotFound(user_id))?;
+
+        Some(user)
+    } else {
+        None
+    };
+
+    let filter = match &user {
+        Some(user) => filter.for_user(user),
+        None => filter,
+    };
+
+    let actor_user = if let Some(user_id) = params.actor_user {
+        let user = repo
+            .user()
+            .lookup(user_id)
+            .await?
+            .ok_or(RouteError::UserNotFound(user_id))?;
+
+        Some(user)
+    } else {
+        None
+    };
+
+    let filter = match &actor_user {
+        Some(user) => filter.by_actor(user),
+        None => filter,
+    };
+
+    let filter = match params.kind {
+        Some(kind) => filter.with_kind(kind.into()),
+        None => filter,
+    };
+
+    let filter = match params.created_before {
+        Some(created_before) => filter.with_created_before(created_before),
+        None => filter,
+    };
+
+    let filter = match params.created_after {
+        Some(created_after) => filter.with_created_after(created_after),
+        None => filter,
+    };
+
+    let page = repo.audit_log().list(filter, pagination).await?;
+    let count = repo.audit_log().count(filter).await?;
+
+    Ok(Json(PaginatedResponse::new(
+        page.map(AuditLogEvent::from),
+        pagination,
+        count,
+        &base,
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{Request, StatusCode};
+    use mas_data_model::AuditLogEventKind;
+    use mas_storage::{audit_log::NewAuditLogEvent, RepositoryAccess};
+    use sqlx::PgPool;
+
+    use crate::test_utils::{setup, RequestBuilderExt, ResponseExt, TestState};
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_list(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let mut rng = state.rng();
+
+        let mut repo = state.repository().await.unwrap();
+        let alice = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        let bob = repo
+            .user()
+            .add(&mut rng, &state.clock, "bob".to_owned())
+            .await
+            .unwrap();
+
+        repo.audit_log()
+            .add(
+                &mut rng,
+                &state.clock,
+                NewAuditLogEvent::new(AuditLogEventKind::LoginFailed).for_user(&alice),
+            )
+            .await
+            .unwrap();
+        let event = repo
+            .audit_log()
+            .add(
+                &mut rng,
+                &state.clock,
+                NewAuditLogEvent::new(AuditLogEventKind::PasswordChanged)
+                    .with_actor(&alice)
+                    .for_user(&bob),
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        // Creating the admin token recorded the creation of its session
+        let request = Request::get("/api/admin/v1/audit-log")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["meta"]["count"], 3);
+
+        // Filter by user
+        let request = Request::get(format!("/api/admin/v1/audit-log?filter[user]={}", bob.id))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["meta"]["count"], 1);
+        assert_eq!(body["data"][0]["type"], "audit-log-event");
+        assert_eq!(body["data"][0]["id"], event.id.to_string());
+        assert_eq!(body["data"][0]["attributes"]["kind"], "password.changed");
+
+        // Filter by actor
+        let request = Request::get(format!(
+            "/api/admin/v1/audit-log?filter[actor-user]={}",
+            alice.id
+        ))
+        .bearer(&token)
+        .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["meta"]["count"], 1);
+        assert_eq!(body["data"][0]["id"], event.id.to_string());
+
+        // Filter by kind
+        let request = Request::get("/api/admin/v1/audit-log?filter[kind]=login.failed")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["meta"]["count"], 1);
+        assert_eq!(
+            body["data"][0]["attributes"]["user_id"],
+            alice.id.to_string()
+        );
+    }
+}
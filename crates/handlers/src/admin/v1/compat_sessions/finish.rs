@@ -0,0 +1,239 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+use aide::{transform::TransformOperation, NoApi, OperationIo};
+use axum::{response::IntoResponse, Json};
+use hyper::StatusCode;
+use mas_data_model::AuditLogEventKind;
+use mas_storage::{
+    job::{JobRepositoryExt, NewSignInSession, SendWebhookEventJob, SyncDevicesJob},
+    BoxRng,
+};
+use serde_json::json;
+use tracing::info;
+use ulid::Ulid;
+
+use crate::{
+    admin::{
+        call_context::{CallContext, ManageSessions},
+        model::{CompatSession, Resource},
+        params::UlidPathParam,
+        response::{ErrorResponse, SingleResponse},
+    },
+    impl_from_error_for_route,
+};
+
+#[derive(Debug, thiserror::Error, OperationIo)]
+#[aide(output_with = "Json<ErrorResponse>")]
+pub enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error("Compatibility session ID {0} not found")]
+    NotFound(Ulid),
+
+    #[error("Compatibility session ID {0} is already finished")]
+    AlreadyFinished(Ulid),
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let error = ErrorResponse::from_error(&self);
+        let status = match self {
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::AlreadyFinished(_) => StatusCode::BAD_REQUEST,
+        };
+        (status, Json(error)).into_response()
+    }
+}
+
+pub fn doc(operation: TransformOperation) -> TransformOperation {
+    operation
+        .id("finishCompatSession")
+        .summary("Finish a compatibility session")
+        .description("Calling this endpoint will finish the compatibility session, revoking its access and refresh tokens.
+The corresponding device will be removed from the homeserver shortly after.")
+        .tag("compat-session")
+        .response_with::<200, Json<SingleResponse<CompatSession>>, _>(|t| {
+            // In the samples, the second session is the one finished
+            let [_, finished, ..] = CompatSession::samples();
+            let id = finished.id();
+            let response = SingleResponse::new(
+                finished,
+                format!("/api/admin/v1/compat-sessions/{id}/finish"),
+            );
+            t.description("Compatibility session was finished")
+                .example(response)
+        })
+        .response_with::<400, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::AlreadyFinished(Ulid::nil()));
+            t.description("Compatibility session was already finished")
+                .example(response)
+        })
+        .response_with::<404, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::NotFound(Ulid::nil()));
+            t.description("Compatibility session was not found")
+                .example(response)
+        })
+}
+
+#[tracing::instrument(name = "handler.admin.v1.compat_sessions.finish", skip_all, err)]
+pub async fn handler(
+    CallContext {
+        mut repo,
+        clock,
+        actor,
+        ..
+    }: CallContext<ManageSessions>,
+    NoApi(mut rng): NoApi<BoxRng>,
+    id: UlidPathParam,
+) -> Result<Json<SingleResponse<CompatSession>>, RouteError> {
+    let id = *id;
+    let session = repo
+        .compat_session()
+        .lookup(id)
+        .await?
+        .ok_or(RouteError::NotFound(id))?;
+
+    if session.is_finished() {
+        return Err(RouteError::AlreadyFinished(id));
+    }
+
+    let session = repo.compat_session().finish(&clock, session).await?;
+
+    // Schedule a job to sync the devices of the user with the homeserver
+    let user = repo.user().lookup(session.user_id).await?;
+    if let Some(user) = user {
+        info!(user.id = %user.id, "Scheduling device sync for user");
+        repo.job().schedule_job(SyncDevicesJob::new(&user)).await?;
+    }
+
+    let sso_login = repo.compat_sso_login().find_for_session(&session).await?;
+
+    repo.audit_log()
+        .add(
+            &mut rng,
+            &clock,
+            actor
+                .event(AuditLogEventKind::SessionFinished)
+                .for_user_id(session.user_id)
+                .with_data(json!({
+                    "session_type": "compat",
+                    "session_id": session.id,
+                    "reason": "admin",
+                })),
+        )
+        .await?;
+
+    repo.job()
+        .schedule_job(SendWebhookEventJob::session_ended(
+            session.user_id,
+            NewSignInSession::Compat(session.id),
+        ))
+        .await?;
+
+    repo.save().await?;
+
+    Ok(Json(SingleResponse::new(
+        CompatSession::from((session, sso_login)),
+        format!("/api/admin/v1/compat-sessions/{id}/finish"),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{Request, StatusCode};
+    use mas_data_model::{AuditLogEventKind, Device};
+    use mas_storage::{audit_log::AuditLogFilter, Clock, Pagination, RepositoryAccess};
+    use sqlx::PgPool;
+    use ulid::Ulid;
+
+    use crate::test_utils::{setup, RequestBuilderExt, ResponseExt, TestState};
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_finish_session(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let mut rng = state.rng();
+
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        let device = Device::generate(&mut rng);
+        let session = repo
+            .compat_session()
+            .add(&mut rng, &state.clock, &user, device, None, false)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let request = Request::post(format!(
+            "/api/admin/v1/compat-sessions/{}/finish",
+            session.id
+        ))
+        .bearer(&token)
+        .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(
+            body["data"]["attributes"]["finished_at"],
+            serde_json::json!(state.clock.now())
+        );
+
+        // A single event records that the admin session finished it
+        let mut repo = state.repository().await.unwrap();
+        let events = repo
+            .audit_log()
+            .list(AuditLogFilter::new().for_user(&user), Pagination::first(10))
+            .await
+            .unwrap();
+        repo.cancel().await.unwrap();
+        assert_eq!(events.edges.len(), 1);
+        let event = &events.edges[0];
+        assert_eq!(event.kind, AuditLogEventKind::SessionFinished);
+        assert!(event.actor_session_id.is_some());
+        assert_eq!(event.data["session_type"], "compat");
+        assert_eq!(event.data["reason"], "admin");
+
+        // Finishing it a second time should fail
+        let request = Request::post(format!(
+            "/api/admin/v1/compat-sessions/{}/finish",
+            session.id
+        ))
+        .bearer(&token)
+        .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_finish_unknown_session(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let request = Request::post(format!(
+            "/api/admin/v1/compat-sessions/{}/finish",
+            Ulid::nil()
+        ))
+        .bearer(&token)
+        .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NOT_FOUND);
+        let body: serde_json::Value = response.json();
+        assert_eq!(
+            body["errors"][0]["title"],
+            "Compatibility session ID 00000000000000000000000000 not found"
+        );
+    }
+}
@@ -0,0 +1,14 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+mod finish;
+mod get;
+mod list;
+
+pub use self::{
+    finish::{doc as finish_doc, handler as finish},
+    get::{doc as get_doc, handler as get},
+    list::{doc as list_doc, handler as list},
+};
@@ -4,29 +4,95 @@
 // SPDX-License-Identifier: AGPL-3.0-only
 // Please see LICENSE in the repository root for full details.
 
+use std::convert::Infallible;
+
 use aide::axum::{
     routing::{get_with, post_with},
     ApiRouter,
 };
 use axum::extract::{FromRef, FromRequestParts};
+use mas_keystore::Encrypter;
 use mas_matrix::BoxHomeserverConnection;
-use mas_storage::BoxRng;
+use mas_storage::{BoxClock, BoxRepository, BoxRng};
+use sqlx::PgPool;
 
-use super::call_context::CallContext;
-use crate::passwords::PasswordManager;
+use crate::{passwords::PasswordManager, BoundActivityTracker};
 
+mod audit_log;
+mod compat_sessions;
+mod oauth2_clients;
 mod oauth2_sessions;
+mod upstream_oauth_links;
+mod upstream_oauth_providers;
+mod usage_stats;
+mod user_emails;
+mod user_registration_tokens;
+mod user_sessions;
 mod users;
 
+#[allow(clippy::too_many_lines)]
 pub fn router<S>() -> ApiRouter<S>
 where
     S: Clone + Send + Sync + 'static,
     BoxHomeserverConnection: FromRef<S>,
+    Encrypter: FromRef<S>,
     PasswordManager: FromRef<S>,
+    PgPool: FromRef<S>,
     BoxRng: FromRequestParts<S>,
-    CallContext: FromRequestParts<S>,
+    BoxRepository: FromRequestParts<S>,
+    <BoxRepository as FromRequestParts<S>>::Rejection:
+        Into<Box<dyn std::error::Error + Send + Sync + 'static>>,
+    BoxClock: FromRequestParts<S, Rejection = Infallible>,
+    BoundActivityTracker: FromRequestParts<S, Rejection = Infallible>,
 {
     ApiRouter::<S>::new()
+        .api_route(
+            "/audit-log",
+            get_with(self::audit_log::list, self::audit_log::list_doc),
+        )
+        .api_route(
+            "/audit-log/:id",
+            get_with(self::audit_log::get, self::audit_log::get_doc),
+        )
+        .api_route(
+            "/compat-sessions",
+            get_with(self::compat_sessions::list, self::compat_sessions::list_doc),
+        )
+        .api_route(
+            "/compat-sessions/:id",
+            get_with(self::compat_sessions::get, self::compat_sessions::get_doc),
+        )
+        .api_route(
+            "/compat-sessions/:id/finish",
+            post_with(
+                self::compat_sessions::finish,
+                self::compat_sessions::finish_doc,
+            ),
+        )
+        .api_route(
+            "/oauth2-clients",
+            get_with(self::oauth2_clients::list, self::oauth2_clients::list_doc)
+                .post_with(self::oauth2_clients::add, self::oauth2_clients::add_doc),
+        )
+        .api_route(
+            "/oauth2-clients/:id",
+            get_with(self::oauth2_clients::get, self::oauth2_clients::get_doc)
+                .put_with(
+                    self::oauth2_clients::update,
+                    self::oauth2_clients::update_doc,
+                )
+                .delete_with(
+                    self::oauth2_clients::delete,
+                    self::oauth2_clients::delete_doc,
+                ),
+        )
+        .api_route(
+            "/oauth2-clients/:id/rotate-secret",
+            post_with(
+                self::oauth2_clients::rotate_secret,
+                self::oauth2_clients::rotate_secret_doc,
+            ),
+        )
         .api_route(
             "/oauth2-sessions",
             get_with(self::oauth2_sessions::list, self::oauth2_sessions::list_doc),
@@ -35,11 +101,81 @@ where
             "/oauth2-sessions/:id",
             get_with(self::oauth2_sessions::get, self::oauth2_sessions::get_doc),
         )
+        .api_route(
+            "/usage-stats",
+            get_with(self::usage_stats::list, self::usage_stats::list_doc),
+        )
+        .api_route(
+            "/usage-stats/:id",
+            get_with(self::usage_stats::get, self::usage_stats::get_doc),
+        )
+        .api_route(
+            "/user-emails",
+            get_with(self::user_emails::list, self::user_emails::list_doc)
+                .post_with(self::user_emails::add, self::user_emails::add_doc),
+        )
+        .api_route(
+            "/user-emails/:id",
+            get_with(self::user_emails::get, self::user_emails::get_doc)
+                .delete_with(self::user_emails::delete, self::user_emails::delete_doc),
+        )
+        .api_route(
+            "/user-emails/:id/set-primary",
+            post_with(
+                self::user_emails::set_primary,
+                self::user_emails::set_primary_doc,
+            ),
+        )
+        .api_route(
+            "/user-registration-tokens",
+            get_with(
+                self::user_registration_tokens::list,
+                self::user_registration_tokens::list_doc,
+            )
+            .post_with(
+                self::user_registration_tokens::add,
+                self::user_registration_tokens::add_doc,
+            ),
+        )
+        .api_route(
+            "/user-registration-tokens/:id",
+            get_with(
+                self::user_registration_tokens::get,
+                self::user_registration_tokens::get_doc,
+            ),
+        )
+        .api_route(
+            "/user-registration-tokens/:id/revoke",
+            post_with(
+                self::user_registration_tokens::revoke,
+                self::user_registration_tokens::revoke_doc,
+            ),
+        )
+        .api_route(
+            "/user-sessions",
+            get_with(self::user_sessions::list, self::user_sessions::list_doc),
+        )
+        .api_route(
+            "/user-sessions/:id",
+            get_with(self::user_sessions::get, self::user_sessions::get_doc),
+        )
+        .api_route(
+            "/user-sessions/:id/finish",
+            post_with(self::user_sessions::finish, self::user_sessions::finish_doc),
+        )
         .api_route(
             "/users",
             get_with(self::users::list, self::users::list_doc)
                 .post_with(self::users::add, self::users::add_doc),
         )
+        .api_route(
+            "/users/import",
+            post_with(self::users::import, self::users::import_doc),
+        )
+        .api_route(
+            "/users/export",
+            get_with(self::users::export, self::users::export_doc),
+        )
         .api_route(
             "/users/:id",
             get_with(self::users::get, self::users::get_doc),
@@ -52,6 +188,10 @@ where
             "/users/by-username/:username",
             get_with(self::users::by_username, self::users::by_username_doc),
         )
+        .api_route(
+            "/users/by-email/:email",
+            get_with(self::users::by_email, self::users::by_email_doc),
+        )
         .api_route(
             "/users/:id/set-admin",
             post_with(self::users::set_admin, self::users::set_admin_doc),
@@ -68,4 +208,47 @@ where
             "/users/:id/unlock",
             post_with(self::users::unlock, self::users::unlock_doc),
         )
+        .api_route(
+            "/upstream-oauth-providers",
+            get_with(
+                self::upstream_oauth_providers::list,
+                self::upstream_oauth_providers::list_doc,
+            ),
+        )
+        .api_route(
+            "/upstream-oauth-providers/:id",
+            get_with(
+                self::upstream_oauth_providers::get,
+                self::upstream_oauth_providers::get_doc,
+            ),
+        )
+        .api_route(
+            "/upstream-oauth-providers/:id/disable",
+            post_with(
+                self::upstream_oauth_providers::disable,
+                self::upstream_oauth_providers::disable_doc,
+            ),
+        )
+        .api_route(
+            "/upstream-oauth-links",
+            get_with(
+                self::upstream_oauth_links::list,
+                self::upstream_oauth_links::list_doc,
+            )
+            .post_with(
+                self::upstream_oauth_links::add,
+                self::upstream_oauth_links::add_doc,
+            ),
+        )
+        .api_route(
+            "/upstream-oauth-links/:id",
+            get_with(
+                self::upstream_oauth_links::get,
+                self::upstream_oauth_links::get_doc,
+            )
+            .delete_with(
+                self::upstream_oauth_links::delete,
+                self::upstream_oauth_links::delete_doc,
+            ),
+        )
 }
@@ -0,0 +1,331 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+use aide::{transform::TransformOperation, NoApi, OperationIo};
+use axum::{extract::State, response::IntoResponse, Json};
+use hyper::StatusCode;
+use mas_iana::oauth::OAuthClientAuthenticationMethod;
+use mas_keystore::Encrypter;
+use mas_storage::BoxRng;
+use oauth2_types::{oidc::ApplicationType, requests::GrantType};
+use rand::distributions::{Alphanumeric, DistString};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tracing::info;
+use url::Url;
+
+use crate::{
+    admin::{
+        call_context::{CallContext, ManageClients},
+        model::OAuth2Client,
+        response::{ErrorResponse, SingleResponse},
+    },
+    impl_from_error_for_route,
+};
+
+#[derive(Debug, thiserror::Error, OperationIo)]
+#[aide(output_with = "Json<ErrorResponse>")]
+pub enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error("At least one redirect URI is required for the authorization_code grant type")]
+    MissingRedirectUris,
+
+    #[error("Clients using the client_credentials grant type must authenticate")]
+    PublicClientCredentials,
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+impl_from_error_for_route!(mas_keystore::aead::Error);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let error = ErrorResponse::from_error(&self);
+        let status = match self {
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::MissingRedirectUris | Self::PublicClientCredentials => StatusCode::BAD_REQUEST,
+        };
+        (status, Json(error)).into_response()
+    }
+}
+
+/// A grant type which can be enabled on a client through the API
+#[derive(Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+pub enum ClientGrantType {
+    #[serde(rename = "authorization_code")]
+    AuthorizationCode,
+
+    #[serde(rename = "refresh_token")]
+    RefreshToken,
+
+    #[serde(rename = "client_credentials")]
+    ClientCredentials,
+
+    #[serde(rename = "urn:ietf:params:oauth:grant-type:device_code")]
+    DeviceCode,
+}
+
+impl From<ClientGrantType> for GrantType {
+    fn from(value: ClientGrantType) -> Self {
+        match value {
+            ClientGrantType::AuthorizationCode => Self::AuthorizationCode,
+            ClientGrantType::RefreshToken => Self::RefreshToken,
+            ClientGrantType::ClientCredentials => Self::ClientCredentials,
+            ClientGrantType::DeviceCode => Self::DeviceCode,
+        }
+    }
+}
+
+/// The kind of application
+#[derive(Deserialize, JsonSchema, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum ClientApplicationType {
+    Web,
+    Native,
+}
+
+impl From<ClientApplicationType> for ApplicationType {
+    fn from(value: ClientApplicationType) -> Self {
+        match value {
+            ClientApplicationType::Web => Self::Web,
+            ClientApplicationType::Native => Self::Native,
+        }
+    }
+}
+
+/// How the client authenticates to the token endpoint
+#[derive(Deserialize, JsonSchema, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ClientAuthenticationMethod {
+    /// The client is public and doesn't authenticate
+    None,
+
+    /// The client sends its secret using HTTP Basic authentication
+    #[default]
+    ClientSecretBasic,
+
+    /// The client sends its secret in the request body
+    ClientSecretPost,
+}
+
+impl From<ClientAuthenticationMethod> for OAuthClientAuthenticationMethod {
+    fn from(value: ClientAuthenticationMethod) -> Self {
+        match value {
+            ClientAuthenticationMethod::None => Self::None,
+            ClientAuthenticationMethod::ClientSecretBasic => Self::ClientSecretBasic,
+            ClientAuthenticationMethod::ClientSecretPost => Self::ClientSecretPost,
+        }
+    }
+}
+
+/// Check that the redirect URIs are consistent with the requested grant types
+pub(super) fn validate_grant_types(grant_types: &[ClientGrantType], redirect_uris: &[Url]) -> bool {
+    !grant_types.contains(&ClientGrantType::AuthorizationCode) || !redirect_uris.is_empty()
+}
+
+/// # JSON payload for the `POST /api/admin/v1/oauth2-clients` endpoint
+#[derive(Deserialize, JsonSchema)]
+#[serde(rename = "AddOAuth2ClientRequest")]
+pub struct Request {
+    /// The human-readable name of the client
+    client_name: Option<String>,
+
+    /// The kind of application
+    application_type: Option<ClientApplicationType>,
+
+    /// The redirect URIs of the client. Required if the `authorization_code`
+    /// grant type is enabled.
+    #[serde(default)]
+    redirect_uris: Vec<Url>,
+
+    /// The grant types the client is allowed to use
+    grant_types: Vec<ClientGrantType>,
+
+    /// The method the client uses to authenticate to the token endpoint.
+    /// Defaults to `client_secret_basic`.
+    #[serde(default)]
+    token_endpoint_auth_method: ClientAuthenticationMethod,
+
+    /// URI of the logo of the client
+    logo_uri: Option<Url>,
+
+    /// URI of the home page of the client
+    client_uri: Option<Url>,
+
+    /// URI of the privacy policy of the client
+    policy_uri: Option<Url>,
+
+    /// URI of the terms of service of the client
+    tos_uri: Option<Url>,
+}
+
+pub fn doc(operation: TransformOperation) -> TransformOperation {
+    operation
+        .id("addOAuth2Client")
+        .summary("Register a new OAuth 2.0 client")
+        .description("If the client authenticates with a secret, the generated secret is included in the response.
+It is not possible to retrieve it afterwards, only to rotate it.")
+        .tag("oauth2-client")
+        .response_with::<200, Json<SingleResponse<OAuth2Client>>, _>(|t| {
+            let [sample, ..] = OAuth2Client::samples();
+            let response =
+                SingleResponse::new_canonical(sample.with_client_secret("s3cr3t".to_owned()));
+            t.description("OAuth 2.0 client was registered")
+                .example(response)
+        })
+        .response_with::<400, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::MissingRedirectUris);
+            t.description("The client metadata is invalid")
+                .example(response)
+        })
+}
+
+#[tracing::instrument(name = "handler.admin.v1.oauth2_clients.add", skip_all, err)]
+pub async fn handler(
+    CallContext {
+        mut repo,
+        clock,
+        actor,
+        ..
+    }: CallContext<ManageClients>,
+    NoApi(mut rng): NoApi<BoxRng>,
+    State(encrypter): State<Encrypter>,
+    Json(params): Json<Request>,
+) -> Result<Json<SingleResponse<OAuth2Client>>, RouteError> {
+    if !validate_grant_types(&params.grant_types, &params.redirect_uris) {
+        return Err(RouteError::MissingRedirectUris);
+    }
+
+    let is_public = matches!(
+        params.token_endpoint_auth_method,
+        ClientAuthenticationMethod::None
+    );
+    if is_public
+        && params
+            .grant_types
+            .contains(&ClientGrantType::ClientCredentials)
+    {
+        return Err(RouteError::PublicClientCredentials);
+    }
+
+    let (client_secret, encrypted_client_secret) = if is_public {
+        (None, None)
+    } else {
+        let client_secret = Alphanumeric.sample_string(&mut rng, 20);
+        let encrypted_client_secret = encrypter.encrypt_to_string(client_secret.as_bytes())?;
+        (Some(client_secret), Some(encrypted_client_secret))
+    };
+
+    let client = repo
+        .oauth2_client()
+        .add(
+            &mut rng,
+            &clock,
+            params.redirect_uris,
+            encrypted_client_secret,
+            params.application_type.map(Into::into),
+            params.grant_types.into_iter().map(Into::into).collect(),
+            params.client_name,
+            params.logo_uri,
+            params.client_uri,
+            params.policy_uri,
+            params.tos_uri,
+            None,
+            None,
+            None,
+            None,
+            Some(params.token_endpoint_auth_method.into()),
+            None,
+            None,
+        )
+        .await?;
+
+    repo.audit_log()
+        .add(&mut rng, &clock, actor.action("addOAuth2Client", client.id))
+        .await?;
+
+    repo.save().await?;
+
+    info!(client.id = %client.id, "Registered OAuth 2.0 client through the admin API");
+
+    let mut client = OAuth2Client::from(client);
+    if let Some(client_secret) = client_secret {
+        client = client.with_client_secret(client_secret);
+    }
+
+    Ok(Json(SingleResponse::new_canonical(client)))
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{Request, StatusCode};
+    use sqlx::PgPool;
+
+    use crate::test_utils::{setup, RequestBuilderExt, ResponseExt, TestState};
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_add_client(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let request = Request::post("/api/admin/v1/oauth2-clients")
+            .bearer(&token)
+            .json(serde_json::json!({
+                "client_name": "My client",
+                "redirect_uris": ["https://example.com/callback"],
+                "grant_types": ["authorization_code", "refresh_token"],
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["data"]["attributes"]["client_name"], "My client");
+        assert_eq!(
+            body["data"]["attributes"]["token_endpoint_auth_method"],
+            "client_secret_basic"
+        );
+        assert!(body["data"]["attributes"]["client_secret"].is_string());
+
+        // A public client doesn't get a secret
+        let request = Request::post("/api/admin/v1/oauth2-clients")
+            .bearer(&token)
+            .json(serde_json::json!({
+                "redirect_uris": ["https://example.com/callback"],
+                "grant_types": ["authorization_code"],
+                "token_endpoint_auth_method": "none",
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert!(body["data"]["attributes"].get("client_secret").is_none());
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_add_invalid_client(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        // Missing redirect URIs
+        let request = Request::post("/api/admin/v1/oauth2-clients")
+            .bearer(&token)
+            .json(serde_json::json!({
+                "grant_types": ["authorization_code"],
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+
+        // Public client using client_credentials
+        let request = Request::post("/api/admin/v1/oauth2-clients")
+            .bearer(&token)
+            .json(serde_json::json!({
+                "grant_types": ["client_credentials"],
+                "token_endpoint_auth_method": "none",
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,20 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+mod add;
+mod delete;
+mod get;
+mod list;
+mod rotate_secret;
+mod update;
+
+pub use self::{
+    add::{doc as add_doc, handler as add},
+    delete::{doc as delete_doc, handler as delete},
+    get::{doc as get_doc, handler as get},
+    list::{doc as list_doc, handler as list},
+    rotate_secret::{doc as rotate_secret_doc, handler as rotate_secret},
+    update::{doc as update_doc, handler as update},
+};
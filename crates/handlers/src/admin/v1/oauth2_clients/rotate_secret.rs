@@ -0,0 +1,212 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+use aide::{transform::TransformOperation, NoApi, OperationIo};
+use axum::{extract::State, response::IntoResponse, Json};
+use hyper::StatusCode;
+use mas_iana::oauth::OAuthClientAuthenticationMethod;
+use mas_keystore::Encrypter;
+use mas_storage::BoxRng;
+use rand::distributions::{Alphanumeric, DistString};
+use tracing::info;
+use ulid::Ulid;
+
+use crate::{
+    admin::{
+        call_context::{CallContext, ManageClients},
+        model::{OAuth2Client, Resource},
+        params::UlidPathParam,
+        response::{ErrorResponse, SingleResponse},
+    },
+    impl_from_error_for_route,
+};
+
+#[derive(Debug, thiserror::Error, OperationIo)]
+#[aide(output_with = "Json<ErrorResponse>")]
+pub enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error("OAuth 2.0 client ID {0} not found")]
+    NotFound(Ulid),
+
+    #[error("OAuth 2.0 client ID {0} is managed through the configuration")]
+    Static(Ulid),
+
+    #[error("OAuth 2.0 client ID {0} does not authenticate with a client secret")]
+    NoClientSecret(Ulid),
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+impl_from_error_for_route!(mas_keystore::aead::Error);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let error = ErrorResponse::from_error(&self);
+        let status = match self {
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::Static(_) | Self::NoClientSecret(_) => StatusCode::BAD_REQUEST,
+        };
+        (status, Json(error)).into_response()
+    }
+}
+
+pub fn doc(operation: TransformOperation) -> TransformOperation {
+    operation
+        .id("rotateOAuth2ClientSecret")
+        .summary("Rotate the secret of an OAuth 2.0 client")
+        .description(
+            "Generate a new secret for the client, and invalidate the previous one.
+The new secret is included in the response.",
+        )
+        .tag("oauth2-client")
+        .response_with::<200, Json<SingleResponse<OAuth2Client>>, _>(|t| {
+            let [sample, ..] = OAuth2Client::samples();
+            let id = sample.id();
+            let response = SingleResponse::new(
+                sample.with_client_secret("s3cr3t".to_owned()),
+                format!("/api/admin/v1/oauth2-clients/{id}/rotate-secret"),
+            );
+            t.description("The client secret was rotated")
+                .example(response)
+        })
+        .response_with::<400, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::NoClientSecret(Ulid::nil()));
+            t.description("The client is static, or doesn't use a client secret")
+                .example(response)
+        })
+        .response_with::<404, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::NotFound(Ulid::nil()));
+            t.description("OAuth 2.0 client was not found")
+                .example(response)
+        })
+}
+
+#[tracing::instrument(name = "handler.admin.v1.oauth2_clients.rotate_secret", skip_all, err)]
+pub async fn handler(
+    CallContext {
+        mut repo,
+        clock,
+        actor,
+        ..
+    }: CallContext<ManageClients>,
+    NoApi(mut rng): NoApi<BoxRng>,
+    State(encrypter): State<Encrypter>,
+    id: UlidPathParam,
+) -> Result<Json<SingleResponse<OAuth2Client>>, RouteError> {
+    let id = *id;
+    let client = repo
+        .oauth2_client()
+        .lookup(id)
+        .await?
+        .ok_or(RouteError::NotFound(id))?;
+
+    if client.is_static {
+        return Err(RouteError::Static(id));
+    }
+
+    if !matches!(
+        client.token_endpoint_auth_method,
+        Some(
+            OAuthClientAuthenticationMethod::ClientSecretBasic
+                | OAuthClientAuthenticationMethod::ClientSecretPost
+                | OAuthClientAuthenticationMethod::ClientSecretJwt
+        )
+    ) {
+        return Err(RouteError::NoClientSecret(id));
+    }
+
+    let client_secret = Alphanumeric.sample_string(&mut rng, 20);
+    let encrypted_client_secret = encrypter.encrypt_to_string(client_secret.as_bytes())?;
+
+    let client = repo
+        .oauth2_client()
+        .set_encrypted_client_secret(client, encrypted_client_secret)
+        .await?;
+
+    repo.audit_log()
+        .add(
+            &mut rng,
+            &clock,
+            actor.action("rotateOAuth2ClientSecret", client.id),
+        )
+        .await?;
+
+    repo.save().await?;
+
+    info!(client.id = %client.id, "Rotated OAuth 2.0 client secret through the admin API");
+
+    let client = OAuth2Client::from(client).with_client_secret(client_secret);
+    Ok(Json(SingleResponse::new(
+        client,
+        format!("/api/admin/v1/oauth2-clients/{id}/rotate-secret"),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{Request, StatusCode};
+    use sqlx::PgPool;
+
+    use crate::test_utils::{setup, RequestBuilderExt, ResponseExt, TestState};
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_rotate_secret(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let request = Request::post("/api/admin/v1/oauth2-clients")
+            .bearer(&token)
+            .json(serde_json::json!({
+                "grant_types": ["client_credentials"],
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        let id = body["data"]["id"].as_str().unwrap().to_owned();
+        let secret = body["data"]["attributes"]["client_secret"]
+            .as_str()
+            .unwrap()
+            .to_owned();
+
+        let request = Request::post(format!("/api/admin/v1/oauth2-clients/{id}/rotate-secret"))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        let new_secret = body["data"]["attributes"]["client_secret"]
+            .as_str()
+            .unwrap();
+        assert_ne!(secret, new_secret);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_rotate_public_client(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let request = Request::post("/api/admin/v1/oauth2-clients")
+            .bearer(&token)
+            .json(serde_json::json!({
+                "redirect_uris": ["https://example.com/callback"],
+                "grant_types": ["authorization_code"],
+                "token_endpoint_auth_method": "none",
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        let id = body["data"]["id"].as_str().unwrap().to_owned();
+
+        let request = Request::post(format!("/api/admin/v1/oauth2-clients/{id}/rotate-secret"))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,233 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+use aide::{transform::TransformOperation, NoApi, OperationIo};
+use axum::{response::IntoResponse, Json};
+use hyper::StatusCode;
+use mas_iana::oauth::OAuthClientAuthenticationMethod;
+use mas_storage::BoxRng;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tracing::info;
+use ulid::Ulid;
+use url::Url;
+
+use super::add::{validate_grant_types, ClientGrantType};
+use crate::{
+    admin::{
+        call_context::{CallContext, ManageClients},
+        model::OAuth2Client,
+        params::UlidPathParam,
+        response::{ErrorResponse, SingleResponse},
+    },
+    impl_from_error_for_route,
+};
+
+#[derive(Debug, thiserror::Error, OperationIo)]
+#[aide(output_with = "Json<ErrorResponse>")]
+pub enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error("OAuth 2.0 client ID {0} not found")]
+    NotFound(Ulid),
+
+    #[error("OAuth 2.0 client ID {0} is managed through the configuration")]
+    Static(Ulid),
+
+    #[error("At least one redirect URI is required for the authorization_code grant type")]
+    MissingRedirectUris,
+
+    #[error("Clients using the client_credentials grant type must authenticate")]
+    PublicClientCredentials,
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let error = ErrorResponse::from_error(&self);
+        let status = match self {
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::Static(_) | Self::MissingRedirectUris | Self::PublicClientCredentials => {
+                StatusCode::BAD_REQUEST
+            }
+        };
+        (status, Json(error)).into_response()
+    }
+}
+
+/// # JSON payload for the `PUT /api/admin/v1/oauth2-clients/:id` endpoint
+#[derive(Deserialize, JsonSchema)]
+#[serde(rename = "UpdateOAuth2ClientRequest")]
+pub struct Request {
+    /// The human-readable name of the client
+    client_name: Option<String>,
+
+    /// The redirect URIs of the client. Required if the `authorization_code`
+    /// grant type is enabled.
+    #[serde(default)]
+    redirect_uris: Vec<Url>,
+
+    /// The grant types the client is allowed to use
+    grant_types: Vec<ClientGrantType>,
+
+    /// URI of the logo of the client
+    logo_uri: Option<Url>,
+
+    /// URI of the home page of the client
+    client_uri: Option<Url>,
+
+    /// URI of the privacy policy of the client
+    policy_uri: Option<Url>,
+
+    /// URI of the terms of service of the client
+    tos_uri: Option<Url>,
+}
+
+pub fn doc(operation: TransformOperation) -> TransformOperation {
+    operation
+        .id("updateOAuth2Client")
+        .summary("Update an OAuth 2.0 client")
+        .description(
+            "Replace the metadata of a client.
+Clients defined in the configuration can't be updated through this API.",
+        )
+        .tag("oauth2-client")
+        .response_with::<200, Json<SingleResponse<OAuth2Client>>, _>(|t| {
+            let [sample, ..] = OAuth2Client::samples();
+            let response = SingleResponse::new_canonical(sample);
+            t.description("OAuth 2.0 client was updated")
+                .example(response)
+        })
+        .response_with::<400, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::Static(Ulid::nil()));
+            t.description("The client is static, or the metadata is invalid")
+                .example(response)
+        })
+        .response_with::<404, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::NotFound(Ulid::nil()));
+            t.description("OAuth 2.0 client was not found")
+                .example(response)
+        })
+}
+
+#[tracing::instrument(name = "handler.admin.v1.oauth2_clients.update", skip_all, err)]
+pub async fn handler(
+    CallContext {
+        mut repo,
+        clock,
+        actor,
+        ..
+    }: CallContext<ManageClients>,
+    NoApi(mut rng): NoApi<BoxRng>,
+    id: UlidPathParam,
+    Json(params): Json<Request>,
+) -> Result<Json<SingleResponse<OAuth2Client>>, RouteError> {
+    let id = *id;
+    let client = repo
+        .oauth2_client()
+        .lookup(id)
+        .await?
+        .ok_or(RouteError::NotFound(id))?;
+
+    if client.is_static {
+        return Err(RouteError::Static(id));
+    }
+
+    if !validate_grant_types(&params.grant_types, &params.redirect_uris) {
+        return Err(RouteError::MissingRedirectUris);
+    }
+
+    let is_public = matches!(
+        client.token_endpoint_auth_method,
+        None | Some(OAuthClientAuthenticationMethod::None)
+    );
+    if is_public
+        && params
+            .grant_types
+            .contains(&ClientGrantType::ClientCredentials)
+    {
+        return Err(RouteError::PublicClientCredentials);
+    }
+
+    let client = repo
+        .oauth2_client()
+        .update_metadata(
+            client,
+            params.redirect_uris,
+            params.grant_types.into_iter().map(Into::into).collect(),
+            params.client_name,
+            params.logo_uri,
+            params.client_uri,
+            params.policy_uri,
+            params.tos_uri,
+        )
+        .await?;
+
+    repo.audit_log()
+        .add(
+            &mut rng,
+            &clock,
+            actor.action("updateOAuth2Client", client.id),
+        )
+        .await?;
+
+    repo.save().await?;
+
+    info!(client.id = %client.id, "Updated OAuth 2.0 client through the admin API");
+
+    Ok(Json(SingleResponse::new_canonical(OAuth2Client::from(
+        client,
+    ))))
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{Request, StatusCode};
+    use sqlx::PgPool;
+
+    use crate::test_utils::{setup, RequestBuilderExt, ResponseExt, TestState};
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_update_client(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let request = Request::post("/api/admin/v1/oauth2-clients")
+            .bearer(&token)
+            .json(serde_json::json!({
+                "client_name": "My client",
+                "redirect_uris": ["https://example.com/callback"],
+                "grant_types": ["authorization_code"],
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        let id = body["data"]["id"].as_str().unwrap().to_owned();
+
+        let request = Request::put(format!("/api/admin/v1/oauth2-clients/{id}"))
+            .bearer(&token)
+            .json(serde_json::json!({
+                "client_name": "Renamed client",
+                "redirect_uris": ["https://example.com/other-callback"],
+                "grant_types": ["authorization_code", "refresh_token"],
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["data"]["attributes"]["client_name"], "Renamed client");
+        assert_eq!(
+            body["data"]["attributes"]["redirect_uris"],
+            serde_json::json!(["https://example.com/other-callback"])
+        );
+        assert_eq!(
+            body["data"]["attributes"]["grant_types"],
+            serde_json::json!(["authorization_code", "refresh_token"])
+        );
+    }
+}
@@ -11,7 +11,7 @@ use ulid::Ulid;
 
 use crate::{
     admin::{
-        call_context::CallContext,
+        call_context::{CallContext, ReadSessions},
         model::OAuth2Session,
         params::UlidPathParam,
         response::{ErrorResponse, SingleResponse},
@@ -62,7 +62,7 @@ pub fn doc(operation: TransformOperation) -> TransformOperation {
 
 #[tracing::instrument(name = "handler.admin.v1.oauth2_session.get", skip_all, err)]
 pub async fn handler(
-    CallContext { mut repo, .. }: CallContext,
+    CallContext { mut repo, .. }: CallContext<ReadSessions>,
     id: UlidPathParam,
 ) -> Result<Json<SingleResponse<OAuth2Session>>, RouteError> {
     let session = repo
@@ -22,7 +22,7 @@ use ulid::Ulid;
 
 use crate::{
     admin::{
-        call_context::CallContext,
+        call_context::{CallContext, ReadSessions},
         model::{OAuth2Session, Resource},
         params::Pagination,
         response::{ErrorResponse, PaginatedResponse},
@@ -190,7 +190,7 @@ Use the `filter[status]` parameter to filter the sessions by their status and `p
 
 #[tracing::instrument(name = "handler.admin.v1.oauth2_sessions.list", skip_all, err)]
 pub async fn handler(
-    CallContext { mut repo, .. }: CallContext,
+    CallContext { mut repo, .. }: CallContext<ReadSessions>,
     Pagination(pagination): Pagination,
     params: FilterParams,
 ) -> Result<Json<PaginatedResponse<OAuth2Session>>, RouteError> {
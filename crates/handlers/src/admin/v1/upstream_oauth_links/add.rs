@@ -0,0 +1,311 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+use aide::{transform::TransformOperation, NoApi, OperationIo};
+use axum::{response::IntoResponse, Json};
+use hyper::StatusCode;
+use mas_storage::BoxRng;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use ulid::Ulid;
+
+use crate::{
+    admin::{
+        call_context::{CallContext, WriteUsers},
+        model::UpstreamOAuthLink,
+        response::{ErrorResponse, SingleResponse},
+    },
+    impl_from_error_for_route,
+};
+
+#[derive(Debug, thiserror::Error, OperationIo)]
+#[aide(output_with = "Json<ErrorResponse>")]
+pub enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error("User ID {0} not found")]
+    UserNotFound(Ulid),
+
+    #[error("Provider ID {0} not found")]
+    ProviderNotFound(Ulid),
+
+    #[error("Upstream account is already linked to another user")]
+    LinkAlreadyExists,
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let error = ErrorResponse::from_error(&self);
+        let status = match self {
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::UserNotFound(_) | Self::ProviderNotFound(_) => StatusCode::NOT_FOUND,
+            Self::LinkAlreadyExists => StatusCode::CONFLICT,
+        };
+        (status, Json(error)).into_response()
+    }
+}
+
+/// # JSON payload for the `POST /api/admin/v1/upstream-oauth-links` endpoint
+#[derive(Deserialize, JsonSchema)]
+#[serde(rename = "AddUpstreamOAuthLinkRequest")]
+pub struct Request {
+    /// The ID of the user to which the link should be added.
+    #[schemars(with = "crate::admin::schema::Ulid")]
+    user_id: Ulid,
+
+    /// The ID of the upstream provider to which the link is for.
+    #[schemars(with = "crate::admin::schema::Ulid")]
+    provider_id: Ulid,
+
+    /// The subject (`sub` claim) of the user on the provider.
+    subject: String,
+}
+
+pub fn doc(operation: TransformOperation) -> TransformOperation {
+    operation
+        .id("addUpstreamOAuthLink")
+        .summary("Add an upstream OAuth 2.0 link")
+        .description("Link a local user to an upstream account, before they first sign in with it.
+If an upstream account with the same subject already signed in but was never linked to a user, it gets linked to this user.")
+        .tag("upstream-oauth-link")
+        .response_with::<200, Json<SingleResponse<UpstreamOAuthLink>>, _>(|t| {
+            let [sample, ..] = UpstreamOAuthLink::samples();
+            let response = SingleResponse::new_canonical(sample);
+            t.description("Upstream OAuth 2.0 link was created")
+                .example(response)
+        })
+        .response_with::<404, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::UserNotFound(Ulid::nil()));
+            t.description("User or provider was not found")
+                .example(response)
+        })
+        .response_with::<409, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::LinkAlreadyExists);
+            t.description("Upstream account is already linked to another user")
+                .example(response)
+        })
+}
+
+#[tracing::instrument(name = "handler.admin.v1.upstream_oauth_links.add", skip_all, err)]
+pub async fn handler(
+    CallContext {
+        mut repo,
+        clock,
+        actor,
+        ..
+    }: CallContext<WriteUsers>,
+    NoApi(mut rng): NoApi<BoxRng>,
+    Json(params): Json<Request>,
+) -> Result<Json<SingleResponse<UpstreamOAuthLink>>, RouteError> {
+    let user = repo
+        .user()
+        .lookup(params.user_id)
+        .await?
+        .ok_or(RouteError::UserNotFound(params.user_id))?;
+
+    let provider = repo
+        .upstream_oauth_provider()
+        .lookup(params.provider_id)
+        .await?
+        .ok_or(RouteError::ProviderNotFound(params.provider_id))?;
+
+    let existing = repo
+        .upstream_oauth_link()
+        .find_by_subject(&provider, &params.subject)
+        .await?;
+
+    let mut link = match existing {
+        // The upstream account is already linked to someone else
+        Some(link) if link.user_id.is_some_and(|id| id != user.id) => {
+            return Err(RouteError::LinkAlreadyExists)
+        }
+        Some(link) => link,
+        None => {
+            repo.upstream_oauth_link()
+                .add(&mut rng, &clock, &provider, params.subject)
+                .await?
+        }
+    };
+
+    if link.user_id.is_none() {
+        repo.upstream_oauth_link()
+            .associate_to_user(&link, &user)
+            .await?;
+        link.user_id = Some(user.id);
+    }
+
+    repo.audit_log()
+        .add(
+            &mut rng,
+            &clock,
+            actor
+                .action("addUpstreamOAuthLink", link.id)
+                .for_user(&user),
+        )
+        .await?;
+
+    repo.save().await?;
+
+    Ok(Json(SingleResponse::new_canonical(
+        UpstreamOAuthLink::from(link),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{Request, StatusCode};
+    use mas_data_model::UpstreamOAuthProvider;
+    use mas_storage::{user::UserRepository, RepositoryAccess};
+    use sqlx::PgPool;
+
+    use crate::test_utils::{
+        setup, test_upstream_oauth_provider_params, RequestBuilderExt, ResponseExt, TestState,
+    };
+
+    async fn add_provider(state: &mut TestState) -> UpstreamOAuthProvider {
+        let mut repo = state.repository().await.unwrap();
+        let provider = repo
+            .upstream_oauth_provider()
+            .add(
+                &mut state.rng(),
+                &state.clock,
+                test_upstream_oauth_provider_params(),
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+        provider
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_add_link(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let provider = add_provider(&mut state).await;
+
+        let mut repo = state.repository().await.unwrap();
+        let alice = repo
+            .user()
+            .add(&mut state.rng(), &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        let bob = repo
+            .user()
+            .add(&mut state.rng(), &state.clock, "bob".to_owned())
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let request = Request::post("/api/admin/v1/upstream-oauth-links")
+            .bearer(&token)
+            .json(serde_json::json!({
+                "user_id": alice.id,
+                "provider_id": provider.id,
+                "subject": "alice-subject",
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["data"]["type"], "upstream-oauth-link");
+        assert_eq!(body["data"]["attributes"]["subject"], "alice-subject");
+        assert_eq!(
+            body["data"]["attributes"]["user_id"],
+            serde_json::json!(alice.id)
+        );
+
+        // The link should be found when looking up the subject
+        let mut repo = state.repository().await.unwrap();
+        let link = repo
+            .upstream_oauth_link()
+            .find_by_subject(&provider, "alice-subject")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(link.user_id, Some(alice.id));
+        repo.save().await.unwrap();
+
+        // Linking the same subject to another user should fail
+        let request = Request::post("/api/admin/v1/upstream-oauth-links")
+            .bearer(&token)
+            .json(serde_json::json!({
+                "user_id": bob.id,
+                "provider_id": provider.id,
+                "subject": "alice-subject",
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::CONFLICT);
+        let body: serde_json::Value = response.json();
+        assert_eq!(
+            body["errors"][0]["title"],
+            "Upstream account is already linked to another user"
+        );
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_add_link_dangling(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let provider = add_provider(&mut state).await;
+
+        // Create a link which isn't associated to any user, like when someone
+        // signs in upstream but never finishes registering
+        let mut repo = state.repository().await.unwrap();
+        let alice = repo
+            .user()
+            .add(&mut state.rng(), &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        let link = repo
+            .upstream_oauth_link()
+            .add(
+                &mut state.rng(),
+                &state.clock,
+                &provider,
+                "alice-subject".to_owned(),
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let request = Request::post("/api/admin/v1/upstream-oauth-links")
+            .bearer(&token)
+            .json(serde_json::json!({
+                "user_id": alice.id,
+                "provider_id": provider.id,
+                "subject": "alice-subject",
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["data"]["id"], serde_json::json!(link.id));
+        assert_eq!(
+            body["data"]["attributes"]["user_id"],
+            serde_json::json!(alice.id)
+        );
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_add_link_unknown_user(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let provider = add_provider(&mut state).await;
+
+        let request = Request::post("/api/admin/v1/upstream-oauth-links")
+            .bearer(&token)
+            .json(serde_json::json!({
+                "user_id": ulid::Ulid::nil(),
+                "provider_id": provider.id,
+                "subject": "alice-subject",
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,204 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+use aide::{transform::TransformOperation, OperationIo};
+use axum::{
+    extract::{rejection::QueryRejection, Query},
+    response::IntoResponse,
+    Json,
+};
+use axum_macros::FromRequestParts;
+use hyper::StatusCode;
+use mas_storage::{upstream_oauth2::UpstreamOAuthProviderFilter, Page};
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+use crate::{
+    admin::{
+        call_context::{CallContext, Read},
+        model::{Resource, UpstreamOAuthProvider},
+        params::Pagination,
+        response::{ErrorResponse, PaginatedResponse},
+    },
+    impl_from_error_for_route,
+};
+
+#[derive(FromRequestParts, Deserialize, JsonSchema, OperationIo)]
+#[serde(rename = "UpstreamOAuthProviderFilter")]
+#[aide(input_with = "Query<FilterParams>")]
+#[from_request(via(Query), rejection(RouteError))]
+pub struct FilterParams {
+    /// Retrieve providers which are (or aren't) enabled
+    ///
+    /// Defaults to retrieve all providers, including disabled ones.
+    #[serde(rename = "filter[enabled]")]
+    enabled: Option<bool>,
+}
+
+impl std::fmt::Display for FilterParams {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut sep = '?';
+
+        if let Some(enabled) = self.enabled {
+            write!(f, "{sep}filter[enabled]={enabled}")?;
+            sep = '&';
+        }
+
+        let _ = sep;
+        Ok(())
+    }
+}
+
+#[derive(Debug, thiserror::Error, OperationIo)]
+#[aide(output_with = "Json<ErrorResponse>")]
+pub enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error("Invalid filter parameters")]
+    InvalidFilter(#[from] QueryRejection),
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let error = ErrorResponse::from_error(&self);
+        let status = match self {
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::InvalidFilter(_) => StatusCode::BAD_REQUEST,
+        };
+        (status, Json(error)).into_response()
+    }
+}
+
+pub fn doc(operation: TransformOperation) -> TransformOperation {
+    operation
+        .id("listUpstreamOAuthProviders")
+        .summary("List upstream OAuth 2.0 providers")
+        .tag("upstream-oauth-provider")
+        .response_with::<200, Json<PaginatedResponse<UpstreamOAuthProvider>>, _>(|t| {
+            let providers = UpstreamOAuthProvider::samples();
+            let pagination = mas_storage::Pagination::first(providers.len());
+            let page = Page {
+                edges: providers.into(),
+                has_next_page: true,
+                has_previous_page: false,
+            };
+
+            t.description("Paginated response of upstream OAuth 2.0 providers")
+                .example(PaginatedResponse::new(
+                    page,
+                    pagination,
+                    42,
+                    UpstreamOAuthProvider::PATH,
+                ))
+        })
+}
+
+#[tracing::instrument(name = "handler.admin.v1.upstream_oauth_providers.list", skip_all, err)]
+pub async fn handler(
+    CallContext { mut repo, .. }: CallContext<Read>,
+    Pagination(pagination): Pagination,
+    params: FilterParams,
+) -> Result<Json<PaginatedResponse<UpstreamOAuthProvider>>, RouteError> {
+    let base = format!("{path}{params}", path = UpstreamOAuthProvider::PATH);
+    let filter = UpstreamOAuthProviderFilter::new();
+
+    let filter = match params.enabled {
+        Some(true) => filter.enabled_only(),
+        Some(false) => filter.disabled_only(),
+        None => filter,
+    };
+
+    let page = repo
+        .upstream_oauth_provider()
+        .list(filter, pagination)
+        .await?;
+    let count = repo.upstream_oauth_provider().count(filter).await?;
+
+    Ok(Json(PaginatedResponse::new(
+        page.map(UpstreamOAuthProvider::from),
+        pagination,
+        count,
+        &base,
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{Request, StatusCode};
+    use mas_storage::{upstream_oauth2::UpstreamOAuthProviderParams, RepositoryAccess};
+    use sqlx::PgPool;
+
+    use crate::test_utils::{
+        setup, test_upstream_oauth_provider_params, RequestBuilderExt, ResponseExt, TestState,
+    };
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_list(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let mut rng = state.rng();
+
+        let mut repo = state.repository().await.unwrap();
+        let enabled = repo
+            .upstream_oauth_provider()
+            .add(
+                &mut rng,
+                &state.clock,
+                test_upstream_oauth_provider_params(),
+            )
+            .await
+            .unwrap();
+        let disabled = repo
+            .upstream_oauth_provider()
+            .add(
+                &mut rng,
+                &state.clock,
+                UpstreamOAuthProviderParams {
+                    issuer: "https://other.example.com/".to_owned(),
+                    ..test_upstream_oauth_provider_params()
+                },
+            )
+            .await
+            .unwrap();
+        let disabled = repo
+            .upstream_oauth_provider()
+            .disable(&state.clock, disabled)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let request = Request::get("/api/admin/v1/upstream-oauth-providers")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["meta"]["count"], 2);
+
+        // Filter by enabled status
+        let request = Request::get("/api/admin/v1/upstream-oauth-providers?filter[enabled]=true")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["meta"]["count"], 1);
+        assert_eq!(body["data"][0]["type"], "upstream-oauth-provider");
+        assert_eq!(body["data"][0]["id"], enabled.id.to_string());
+
+        let request = Request::get("/api/admin/v1/upstream-oauth-providers?filter[enabled]=false")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["meta"]["count"], 1);
+        assert_eq!(body["data"][0]["id"], disabled.id.to_string());
+    }
+}
@@ -0,0 +1,214 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+use aide::{transform::TransformOperation, OperationIo};
+use axum::{
+    extract::{rejection::QueryRejection, Query},
+    response::IntoResponse,
+    Json,
+};
+use axum_macros::FromRequestParts;
+use chrono::NaiveDate;
+use hyper::StatusCode;
+use mas_storage::{usage_stats::UsageStatsFilter, Page};
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+use crate::{
+    admin::{
+        call_context::{CallContext, Read},
+        model::{Resource, UsageStats},
+        params::Pagination,
+        response::{ErrorResponse, PaginatedResponse},
+    },
+    impl_from_error_for_route,
+};
+
+#[derive(FromRequestParts, Deserialize, JsonSchema, OperationIo)]
+#[serde(rename = "UsageStatsFilter")]
+#[aide(input_with = "Query<FilterParams>")]
+#[from_request(via(Query), rejection(RouteError))]
+pub struct FilterParams {
+    /// Retrieve the statistics of this day and the following ones
+    #[serde(rename = "filter[since]")]
+    since: Option<NaiveDate>,
+
+    /// Retrieve the statistics of this day and the previous ones
+    #[serde(rename = "filter[until]")]
+    until: Option<NaiveDate>,
+}
+
+impl std::fmt::Display for FilterParams {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut sep = '?';
+
+        if let Some(since) = self.since {
+            write!(f, "{sep}filter[since]={since}")?;
+            sep = '&';
+        }
+
+        if let Some(until) = self.until {
+            write!(f, "{sep}filter[until]={until}")?;
+            sep = '&';
+        }
+
+        let _ = sep;
+        Ok(())
+    }
+}
+
+#[derive(Debug, thiserror::Error, OperationIo)]
+#[aide(output_with = "Json<ErrorResponse>")]
+pub enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error("Invalid filter parameters")]
+    InvalidFilter(#[from] QueryRejection),
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let error = ErrorResponse::from_error(&self);
+        let status = match self {
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::InvalidFilter(_) => StatusCode::BAD_REQUEST,
+        };
+        (status, Json(error)).into_response()
+    }
+}
+
+pub fn doc(operation: TransformOperation) -> TransformOperation {
+    operation
+        .id("listUsageStats")
+        .summary("List the daily usage statistics")
+        .description(
+            "Retrieve the usage statistics of each day, oldest first.
+The statistics are computed every hour by a background job, and finalized shortly after the end of the day.
+Because only the last activity of each session is tracked, they can't be computed for the days before the job ran.",
+        )
+        .tag("usage-stats")
+        .response_with::<200, Json<PaginatedResponse<UsageStats>>, _>(|t| {
+            let stats = UsageStats::samples();
+            let pagination = mas_storage::Pagination::first(stats.len());
+            let page = Page {
+                edges: stats.into(),
+                has_next_page: true,
+                has_previous_page: false,
+            };
+
+            t.description("Paginated response of usage statistics")
+                .example(PaginatedResponse::new(
+                    page,
+                    pagination,
+                    42,
+                    UsageStats::PATH,
+                ))
+        })
+}
+
+#[tracing::instrument(name = "handler.admin.v1.usage_stats.list", skip_all, err)]
+pub async fn handler(
+    CallContext { mut repo, .. }: CallContext<Read>,
+    Pagination(pagination): Pagination,
+    params: FilterParams,
+) -> Result<Json<PaginatedResponse<UsageStats>>, RouteError> {
+    let base = format!("{path}{params}", path = UsageStats::PATH);
+    let filter = UsageStatsFilter::new();
+
+    let filter = match params.since {
+        Some(since) => filter.with_since(since),
+        None => filter,
+    };
+
+    let filter = match params.until {
+        Some(until) => filter.with_until(until),
+        None => filter,
+    };
+
+    let page = repo.usage_stats().list(filter, pagination).await?;
+    let count = repo.usage_stats().count(filter).await?;
+
+    Ok(Json(PaginatedResponse::new(
+        page.map(UsageStats::from),
+        pagination,
+        count,
+        &base,
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{Request, StatusCode};
+    use mas_storage::{Clock, RepositoryAccess};
+    use sqlx::PgPool;
+
+    use crate::test_utils::{setup, RequestBuilderExt, ResponseExt, TestState};
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_list(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let mut rng = state.rng();
+
+        let today = state.clock.now().date_naive();
+        let yesterday = today.pred_opt().unwrap();
+        let mut repo = state.repository().await.unwrap();
+        repo.usage_stats()
+            .record(&mut rng, &state.clock, yesterday)
+            .await
+            .unwrap();
+        let stats = repo
+            .usage_stats()
+            .record(&mut rng, &state.clock, today)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let request = Request::get("/api/admin/v1/usage-stats")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["meta"]["count"], 2);
+        // The statistics are ordered by day
+        assert_eq!(body["data"][0]["attributes"]["day"], yesterday.to_string());
+        assert_eq!(body["data"][0]["attributes"]["is_final"], true);
+        assert_eq!(body["data"][1]["attributes"]["day"], today.to_string());
+
+        // Filter by day
+        let request = Request::get(format!("/api/admin/v1/usage-stats?filter[since]={today}"))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["meta"]["count"], 1);
+        assert_eq!(body["data"][0]["type"], "usage-stats");
+        assert_eq!(body["data"][0]["id"], stats.id.to_string());
+
+        let request = Request::get(format!(
+            "/api/admin/v1/usage-stats?filter[until]={yesterday}"
+        ))
+        .bearer(&token)
+        .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["meta"]["count"], 1);
+        assert_eq!(body["data"][0]["attributes"]["day"], yesterday.to_string());
+
+        // Invalid dates are rejected
+        let request = Request::get("/api/admin/v1/usage-stats?filter[since]=yesterday")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,311 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+use aide::{transform::TransformOperation, NoApi, OperationIo};
+use axum::{response::IntoResponse, Json};
+use hyper::StatusCode;
+use mas_data_model::AuditLogEventKind;
+use mas_storage::{
+    job::{JobRepositoryExt, ProvisionUserJob, SendWebhookEventJob, VerifyEmailJob},
+    BoxRng,
+};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::json;
+use tracing::info;
+use ulid::Ulid;
+
+use crate::{
+    admin::{
+        call_context::{CallContext, WriteUsers},
+        model::UserEmail,
+        response::{ErrorResponse, SingleResponse},
+    },
+    impl_from_error_for_route,
+};
+
+#[derive(Debug, thiserror::Error, OperationIo)]
+#[aide(output_with = "Json<ErrorResponse>")]
+pub enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error("User ID {0} not found")]
+    UserNotFound(Ulid),
+
+    #[error("Email address {0:?} is not valid")]
+    EmailNotValid(String),
+
+    #[error("User already has the email address {0:?}")]
+    EmailAlreadyExists(String),
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let error = ErrorResponse::from_error(&self);
+        let status = match self {
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::UserNotFound(_) => StatusCode::NOT_FOUND,
+            Self::EmailNotValid(_) => StatusCode::BAD_REQUEST,
+            Self::EmailAlreadyExists(_) => StatusCode::CONFLICT,
+        };
+        (status, Json(error)).into_response()
+    }
+}
+
+/// # JSON payload for the `POST /api/admin/v1/user-emails` endpoint
+#[derive(Deserialize, JsonSchema)]
+#[serde(rename = "AddUserEmailRequest")]
+pub struct Request {
+    /// The ID of the user to which the email address should be added.
+    #[schemars(with = "crate::admin::schema::Ulid")]
+    user_id: Ulid,
+
+    /// The email address to add.
+    email: String,
+
+    /// Mark the email address as verified, instead of sending a verification
+    /// code to it.
+    #[serde(default)]
+    verified: bool,
+}
+
+pub fn doc(operation: TransformOperation) -> TransformOperation {
+    operation
+        .id("addUserEmail")
+        .summary("Add a user email address")
+        .description("Add an email address to a user.
+Unless `verified` is set, a verification code is sent to the address, and the user will have to confirm it.
+If the user has no primary email address yet, a verified address becomes their primary one.")
+        .tag("user-email")
+        .response_with::<200, Json<SingleResponse<UserEmail>>, _>(|t| {
+            let [sample, ..] = UserEmail::samples();
+            let response = SingleResponse::new_canonical(sample);
+            t.description("User email address was added")
+                .example(response)
+        })
+        .response_with::<400, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::EmailNotValid(
+                "not an email".to_owned(),
+            ));
+            t.description("Email address is not valid").example(response)
+        })
+        .response_with::<404, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::UserNotFound(Ulid::nil()));
+            t.description("User was not found").example(response)
+        })
+        .response_with::<409, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::EmailAlreadyExists(
+                "alice@example.com".to_owned(),
+            ));
+            t.description("User already has this email address")
+                .example(response)
+        })
+}
+
+#[tracing::instrument(name = "handler.admin.v1.user_emails.add", skip_all, err)]
+pub async fn handler(
+    CallContext {
+        mut repo,
+        clock,
+        actor,
+        ..
+    }: CallContext<WriteUsers>,
+    NoApi(mut rng): NoApi<BoxRng>,
+    Json(params): Json<Request>,
+) -> Result<Json<SingleResponse<UserEmail>>, RouteError> {
+    let user = repo
+        .user()
+        .lookup(params.user_id)
+        .await?
+        .ok_or(RouteError::UserNotFound(params.user_id))?;
+
+    if params.email.parse::<lettre::Address>().is_err() {
+        return Err(RouteError::EmailNotValid(params.email));
+    }
+
+    if repo
+        .user_email()
+        .find(&user, &params.email)
+        .await?
+        .is_some()
+    {
+        return Err(RouteError::EmailAlreadyExists(params.email));
+    }
+
+    let mut user_email = repo
+        .user_email()
+        .add(&mut rng, &clock, &user, params.email)
+        .await?;
+
+    if params.verified {
+        user_email = repo
+            .user_email()
+            .mark_as_verified(&clock, user_email)
+            .await?;
+
+        // If the user has no primary email, set this one as primary
+        if user.primary_user_email_id.is_none() {
+            repo.user_email().set_as_primary(&user_email).await?;
+        }
+
+        info!(user.id = %user.id, user_email.id = %user_email.id, "Added verified email address");
+
+        repo.job()
+            .schedule_job(SendWebhookEventJob::user_email_verified(&user, &user_email))
+            .await?;
+
+        // Sync the email addresses of the user with the homeserver
+        repo.job()
+            .schedule_job(ProvisionUserJob::new(&user))
+            .await?;
+    } else {
+        repo.job()
+            .schedule_job(VerifyEmailJob::new(&user_email))
+            .await?;
+    }
+
+    repo.audit_log()
+        .add(
+            &mut rng,
+            &clock,
+            actor
+                .event(AuditLogEventKind::EmailAdded)
+                .for_user(&user)
+                .with_data(json!({
+                    "user_email_id": user_email.id,
+                    "email": user_email.email,
+                })),
+        )
+        .await?;
+
+    repo.save().await?;
+
+    Ok(Json(SingleResponse::new_canonical(UserEmail::from(
+        user_email,
+    ))))
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{Request, StatusCode};
+    use mas_data_model::AuditLogEventKind;
+    use mas_storage::{audit_log::AuditLogFilter, Clock, Pagination, RepositoryAccess};
+    use sqlx::PgPool;
+    use ulid::Ulid;
+
+    use crate::test_utils::{setup, RequestBuilderExt, ResponseExt, TestState};
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_add_email(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut state.rng(), &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let request = Request::post("/api/admin/v1/user-emails")
+            .bearer(&token)
+            .json(serde_json::json!({
+                "user_id": user.id,
+                "email": "alice@example.com",
+                "verified": true,
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["data"]["attributes"]["email"], "alice@example.com");
+        assert_eq!(
+            body["data"]["attributes"]["confirmed_at"],
+            serde_json::json!(state.clock.now())
+        );
+
+        // It became the primary email of the user
+        let mut repo = state.repository().await.unwrap();
+        let user = repo.user().lookup(user.id).await.unwrap().unwrap();
+        assert_eq!(
+            user.primary_user_email_id.map(|id| id.to_string()),
+            body["data"]["id"].as_str().map(ToOwned::to_owned)
+        );
+
+        // A single event records that the admin session did it
+        let events = repo
+            .audit_log()
+            .list(AuditLogFilter::new().for_user(&user), Pagination::first(10))
+            .await
+            .unwrap();
+        assert_eq!(events.edges.len(), 1);
+        assert_eq!(events.edges[0].kind, AuditLogEventKind::EmailAdded);
+        assert!(events.edges[0].actor_session_id.is_some());
+        assert_eq!(events.edges[0].data["email"], "alice@example.com");
+        repo.save().await.unwrap();
+
+        // Adding it a second time should conflict
+        let request = Request::post("/api/admin/v1/user-emails")
+            .bearer(&token)
+            .json(serde_json::json!({
+                "user_id": user.id,
+                "email": "alice@example.com",
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::CONFLICT);
+
+        // An unverified email is not confirmed
+        let request = Request::post("/api/admin/v1/user-emails")
+            .bearer(&token)
+            .json(serde_json::json!({
+                "user_id": user.id,
+                "email": "alice@example.org",
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(
+            body["data"]["attributes"]["confirmed_at"],
+            serde_json::Value::Null
+        );
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_add_invalid_email(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut state.rng(), &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let request = Request::post("/api/admin/v1/user-emails")
+            .bearer(&token)
+            .json(serde_json::json!({
+                "user_id": user.id,
+                "email": "not an email",
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+
+        let request = Request::post("/api/admin/v1/user-emails")
+            .bearer(&token)
+            .json(serde_json::json!({
+                "user_id": Ulid::nil(),
+                "email": "alice@example.com",
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NOT_FOUND);
+    }
+}
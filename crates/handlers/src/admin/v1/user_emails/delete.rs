@@ -0,0 +1,220 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+use aide::{transform::TransformOperation, NoApi, OperationIo};
+use axum::{response::IntoResponse, Json};
+use hyper::StatusCode;
+use mas_data_model::AuditLogEventKind;
+use mas_storage::{
+    job::{JobRepositoryExt, ProvisionUserJob},
+    BoxRng,
+};
+use serde_json::json;
+use tracing::info;
+use ulid::Ulid;
+
+use crate::{
+    admin::{
+        call_context::{CallContext, WriteUsers},
+        params::UlidPathParam,
+        response::ErrorResponse,
+    },
+    impl_from_error_for_route,
+};
+
+#[derive(Debug, thiserror::Error, OperationIo)]
+#[aide(output_with = "Json<ErrorResponse>")]
+pub enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error("User email ID {0} not found")]
+    NotFound(Ulid),
+
+    #[error("User email ID {0} is the primary email address of the user")]
+    Primary(Ulid),
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let error = ErrorResponse::from_error(&self);
+        let status = match self {
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::Primary(_) => StatusCode::BAD_REQUEST,
+        };
+        (status, Json(error)).into_response()
+    }
+}
+
+pub fn doc(operation: TransformOperation) -> TransformOperation {
+    operation
+        .id("deleteUserEmail")
+        .summary("Delete a user email address")
+        .description(
+            "Remove an email address from a user.
+The primary email address of a user can't be removed: set another address as primary first.",
+        )
+        .tag("user-email")
+        .response::<204, ()>()
+        .response_with::<400, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::Primary(Ulid::nil()));
+            t.description("User email address is the primary one")
+                .example(response)
+        })
+        .response_with::<404, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::NotFound(Ulid::nil()));
+            t.description("User email address was not found")
+                .example(response)
+        })
+}
+
+#[tracing::instrument(name = "handler.admin.v1.user_emails.delete", skip_all, err)]
+pub async fn handler(
+    CallContext {
+        mut repo,
+        clock,
+        actor,
+        ..
+    }: CallContext<WriteUsers>,
+    NoApi(mut rng): NoApi<BoxRng>,
+    id: UlidPathParam,
+) -> Result<StatusCode, RouteError> {
+    let id = *id;
+    let user_email = repo
+        .user_email()
+        .lookup(id)
+        .await?
+        .ok_or(RouteError::NotFound(id))?;
+
+    let user = repo.user().lookup(user_email.user_id).await?;
+
+    if let Some(user) = &user {
+        if user.primary_user_email_id == Some(user_email.id) {
+            return Err(RouteError::Primary(id));
+        }
+    }
+
+    info!(
+        user.id = %user_email.user_id,
+        user_email.id = %user_email.id,
+        "Removing user email address"
+    );
+
+    repo.audit_log()
+        .add(
+            &mut rng,
+            &clock,
+            actor
+                .event(AuditLogEventKind::EmailRemoved)
+                .for_user_id(user_email.user_id)
+                .with_data(json!({
+                    "user_email_id": user_email.id,
+                    "email": user_email.email,
+                })),
+        )
+        .await?;
+
+    repo.user_email().remove(user_email).await?;
+
+    // Sync the email addresses of the user with the homeserver
+    if let Some(user) = &user {
+        repo.job().schedule_job(ProvisionUserJob::new(user)).await?;
+    }
+
+    repo.save().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{Request, StatusCode};
+    use mas_data_model::AuditLogEventKind;
+    use mas_storage::{audit_log::AuditLogFilter, Pagination, RepositoryAccess};
+    use sqlx::PgPool;
+    use ulid::Ulid;
+
+    use crate::test_utils::{setup, RequestBuilderExt, ResponseExt, TestState};
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_delete(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let mut rng = state.rng();
+
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        let primary = repo
+            .user_email()
+            .add(
+                &mut rng,
+                &state.clock,
+                &user,
+                "alice@example.com".to_owned(),
+            )
+            .await
+            .unwrap();
+        let primary = repo
+            .user_email()
+            .mark_as_verified(&state.clock, primary)
+            .await
+            .unwrap();
+        repo.user_email().set_as_primary(&primary).await.unwrap();
+        let other = repo
+            .user_email()
+            .add(
+                &mut rng,
+                &state.clock,
+                &user,
+                "alice@example.org".to_owned(),
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        // The primary email can't be removed
+        let request = Request::delete(format!("/api/admin/v1/user-emails/{}", primary.id))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+
+        let request = Request::delete(format!("/api/admin/v1/user-emails/{}", other.id))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NO_CONTENT);
+
+        // It is gone
+        let mut repo = state.repository().await.unwrap();
+        assert!(repo.user_email().lookup(other.id).await.unwrap().is_none());
+
+        // A single event records that the admin session did it
+        let events = repo
+            .audit_log()
+            .list(AuditLogFilter::new().for_user(&user), Pagination::first(10))
+            .await
+            .unwrap();
+        assert_eq!(events.edges.len(), 1);
+        assert_eq!(events.edges[0].kind, AuditLogEventKind::EmailRemoved);
+        assert!(events.edges[0].actor_session_id.is_some());
+        assert_eq!(events.edges[0].data["email"], "alice@example.org");
+        repo.save().await.unwrap();
+
+        let request = Request::delete(format!("/api/admin/v1/user-emails/{}", Ulid::nil()))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NOT_FOUND);
+    }
+}
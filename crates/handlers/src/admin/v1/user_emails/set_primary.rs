@@ -0,0 +1,222 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+use aide::{transform::TransformOperation, NoApi, OperationIo};
+use axum::{response::IntoResponse, Json};
+use hyper::StatusCode;
+use mas_data_model::AuditLogEventKind;
+use mas_storage::{
+    job::{JobRepositoryExt, ProvisionUserJob},
+    BoxRng,
+};
+use serde_json::json;
+use ulid::Ulid;
+
+use crate::{
+    admin::{
+        call_context::{CallContext, WriteUsers},
+        model::{Resource, UserEmail},
+        params::UlidPathParam,
+        response::{ErrorResponse, SingleResponse},
+    },
+    impl_from_error_for_route,
+};
+
+#[derive(Debug, thiserror::Error, OperationIo)]
+#[aide(output_with = "Json<ErrorResponse>")]
+pub enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error("User email ID {0} not found")]
+    NotFound(Ulid),
+
+    #[error("User email ID {0} is not verified")]
+    Unverified(Ulid),
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let error = ErrorResponse::from_error(&self);
+        let status = match self {
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::Unverified(_) => StatusCode::BAD_REQUEST,
+        };
+        (status, Json(error)).into_response()
+    }
+}
+
+pub fn doc(operation: TransformOperation) -> TransformOperation {
+    operation
+        .id("setPrimaryUserEmail")
+        .summary("Set a user email address as primary")
+        .description(
+            "Make this email address the primary one of the user it belongs to.
+Only verified email addresses can be set as primary.",
+        )
+        .tag("user-email")
+        .response_with::<200, Json<SingleResponse<UserEmail>>, _>(|t| {
+            let [sample, ..] = UserEmail::samples();
+            let id = sample.id();
+            let response = SingleResponse::new(
+                sample,
+                format!("/api/admin/v1/user-emails/{id}/set-primary"),
+            );
+            t.description("User email address was set as primary")
+                .example(response)
+        })
+        .response_with::<400, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::Unverified(Ulid::nil()));
+            t.description("User email address is not verified")
+                .example(response)
+        })
+        .response_with::<404, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::NotFound(Ulid::nil()));
+            t.description("User email address was not found")
+                .example(response)
+        })
+}
+
+#[tracing::instrument(name = "handler.admin.v1.user_emails.set_primary", skip_all, err)]
+pub async fn handler(
+    CallContext {
+        mut repo,
+        clock,
+        actor,
+        ..
+    }: CallContext<WriteUsers>,
+    NoApi(mut rng): NoApi<BoxRng>,
+    id: UlidPathParam,
+) -> Result<Json<SingleResponse<UserEmail>>, RouteError> {
+    let id = *id;
+    let user_email = repo
+        .user_email()
+        .lookup(id)
+        .await?
+        .ok_or(RouteError::NotFound(id))?;
+
+    if user_email.confirmed_at.is_none() {
+        return Err(RouteError::Unverified(id));
+    }
+
+    repo.user_email().set_as_primary(&user_email).await?;
+
+    // Sync the email addresses of the user with the homeserver
+    let user = repo.user().lookup(user_email.user_id).await?;
+    if let Some(user) = user {
+        repo.job()
+            .schedule_job(ProvisionUserJob::new(&user))
+            .await?;
+    }
+
+    repo.audit_log()
+        .add(
+            &mut rng,
+            &clock,
+            actor
+                .event(AuditLogEventKind::PrimaryEmailChanged)
+                .for_user_id(user_email.user_id)
+                .with_data(json!({
+                    "user_email_id": user_email.id,
+                    "email": user_email.email,
+                })),
+        )
+        .await?;
+
+    repo.save().await?;
+
+    Ok(Json(SingleResponse::new(
+        UserEmail::from(user_email),
+        format!("/api/admin/v1/user-emails/{id}/set-primary"),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{Request, StatusCode};
+    use mas_data_model::AuditLogEventKind;
+    use mas_storage::{audit_log::AuditLogFilter, Pagination, RepositoryAccess};
+    use sqlx::PgPool;
+
+    use crate::test_utils::{setup, RequestBuilderExt, ResponseExt, TestState};
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_set_primary(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let mut rng = state.rng();
+
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        let verified = repo
+            .user_email()
+            .add(
+                &mut rng,
+                &state.clock,
+                &user,
+                "alice@example.com".to_owned(),
+            )
+            .await
+            .unwrap();
+        let verified = repo
+            .user_email()
+            .mark_as_verified(&state.clock, verified)
+            .await
+            .unwrap();
+        let pending = repo
+            .user_email()
+            .add(
+                &mut rng,
+                &state.clock,
+                &user,
+                "alice@example.org".to_owned(),
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        // An unverified email can't be set as primary
+        let request = Request::post(format!(
+            "/api/admin/v1/user-emails/{}/set-primary",
+            pending.id
+        ))
+        .bearer(&token)
+        .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+
+        let request = Request::post(format!(
+            "/api/admin/v1/user-emails/{}/set-primary",
+            verified.id
+        ))
+        .bearer(&token)
+        .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+
+        let mut repo = state.repository().await.unwrap();
+        let user = repo.user().lookup(user.id).await.unwrap().unwrap();
+        assert_eq!(user.primary_user_email_id, Some(verified.id));
+
+        // A single event records that the admin session did it
+        let events = repo
+            .audit_log()
+            .list(AuditLogFilter::new().for_user(&user), Pagination::first(10))
+            .await
+            .unwrap();
+        assert_eq!(events.edges.len(), 1);
+        assert_eq!(events.edges[0].kind, AuditLogEventKind::PrimaryEmailChanged);
+        assert!(events.edges[0].actor_session_id.is_some());
+        repo.save().await.unwrap();
+    }
+}
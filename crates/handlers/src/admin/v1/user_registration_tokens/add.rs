@@ -0,0 +1,242 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+use aide::{transform::TransformOperation, NoApi, OperationIo};
+use axum::{response::IntoResponse, Json};
+use chrono::{DateTime, Utc};
+use hyper::StatusCode;
+use mas_storage::BoxRng;
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+use crate::{
+    admin::{
+        call_context::{Admin, CallContext},
+        model::UserRegistrationToken,
+        response::{ErrorResponse, SingleResponse},
+    },
+    impl_from_error_for_route,
+};
+
+#[derive(Debug, thiserror::Error, OperationIo)]
+#[aide(output_with = "Json<ErrorResponse>")]
+pub enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error("Registration token {0:?} is not valid")]
+    TokenNotValid(String),
+
+    #[error("Registration token {0:?} already exists")]
+    TokenAlreadyExists(String),
+
+    #[error("Expiration date {0} is in the past")]
+    ExpiresInPast(DateTime<Utc>),
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let error = ErrorResponse::from_error(&self);
+        let status = match self {
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::TokenNotValid(_) | Self::ExpiresInPast(_) => StatusCode::BAD_REQUEST,
+            Self::TokenAlreadyExists(_) => StatusCode::CONFLICT,
+        };
+        (status, Json(error)).into_response()
+    }
+}
+
+/// # JSON payload for the `POST /api/admin/v1/user-registration-tokens` endpoint
+#[derive(Deserialize, JsonSchema)]
+#[serde(rename = "AddUserRegistrationTokenRequest")]
+pub struct Request {
+    /// The token users have to provide when registering. It must be at most 64
+    /// characters long, and only contain letters, digits and the `.`, `_`, `~`
+    /// and `-` characters.
+    ///
+    /// A random token is generated if none is given.
+    #[serde(default)]
+    token: Option<String>,
+
+    /// How many times the token can be used. Defaults to unlimited.
+    #[serde(default)]
+    usage_limit: Option<u32>,
+
+    /// When the token expires. Defaults to never.
+    #[serde(default)]
+    expires_at: Option<DateTime<Utc>>,
+}
+
+pub fn doc(operation: TransformOperation) -> TransformOperation {
+    operation
+        .id("addUserRegistrationToken")
+        .summary("Create a registration token")
+        .description(
+            "Create a token which users can provide when registering a new account.
+Tokens are only required if `account.registration_token_required` is set in the configuration.",
+        )
+        .tag("user-registration-token")
+        .response_with::<200, Json<SingleResponse<UserRegistrationToken>>, _>(|t| {
+            let [sample, ..] = UserRegistrationToken::samples();
+            let response = SingleResponse::new_canonical(sample);
+            t.description("Registration token was created")
+                .example(response)
+        })
+        .response_with::<400, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::TokenNotValid(
+                "not a valid token!".to_owned(),
+            ));
+            t.description("Registration token is not valid")
+                .example(response)
+        })
+        .response_with::<409, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::TokenAlreadyExists(
+                "community-invite".to_owned(),
+            ));
+            t.description("Registration token already exists")
+                .example(response)
+        })
+}
+
+#[tracing::instrument(name = "handler.admin.v1.user_registration_tokens.add", skip_all, err)]
+pub async fn handler(
+    CallContext {
+        mut repo,
+        clock,
+        actor,
+        ..
+    }: CallContext<Admin>,
+    NoApi(mut rng): NoApi<BoxRng>,
+    Json(params): Json<Request>,
+) -> Result<Json<SingleResponse<UserRegistrationToken>>, RouteError> {
+    let token = match params.token {
+        Some(token) => {
+            if !mas_data_model::UserRegistrationToken::is_token_well_formed(&token) {
+                return Err(RouteError::TokenNotValid(token));
+            }
+
+            if repo
+                .user_registration_token()
+                .find_by_token(&token)
+                .await?
+                .is_some()
+            {
+                return Err(RouteError::TokenAlreadyExists(token));
+            }
+
+            token
+        }
+        None => mas_data_model::UserRegistrationToken::generate_token(&mut rng),
+    };
+
+    if let Some(expires_at) = params.expires_at {
+        if expires_at <= clock.now() {
+            return Err(RouteError::ExpiresInPast(expires_at));
+        }
+    }
+
+    let token = repo
+        .user_registration_token()
+        .add(
+            &mut rng,
+            &clock,
+            token,
+            params.usage_limit,
+            params.expires_at,
+        )
+        .await?;
+
+    repo.audit_log()
+        .add(
+            &mut rng,
+            &clock,
+            actor.action("addUserRegistrationToken", token.id),
+        )
+        .await?;
+
+    repo.save().await?;
+
+    Ok(Json(SingleResponse::new_canonical(
+        UserRegistrationToken::from(token),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use hyper::{Request, StatusCode};
+    use mas_storage::{Clock, RepositoryAccess};
+    use sqlx::PgPool;
+
+    use crate::test_utils::{setup, RequestBuilderExt, ResponseExt, TestState};
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_add(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let expires_at = state.clock.now() + Duration::try_days(7).unwrap();
+        let request = Request::post("/api/admin/v1/user-registration-tokens")
+            .bearer(&token)
+            .json(serde_json::json!({
+                "token": "community-invite",
+                "usage_limit": 10,
+                "expires_at": expires_at,
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["data"]["type"], "user-registration-token");
+        assert_eq!(body["data"]["attributes"]["token"], "community-invite");
+        assert_eq!(body["data"]["attributes"]["usage_limit"], 10);
+
+        let mut repo = state.repository().await.unwrap();
+        let registration_token = repo
+            .user_registration_token()
+            .find_by_token("community-invite")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(registration_token.expires_at, Some(expires_at));
+        repo.save().await.unwrap();
+
+        // The same token can't be added twice
+        let request = Request::post("/api/admin/v1/user-registration-tokens")
+            .bearer(&token)
+            .json(serde_json::json!({
+                "token": "community-invite",
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::CONFLICT);
+
+        // Tokens with invalid characters are rejected
+        let request = Request::post("/api/admin/v1/user-registration-tokens")
+            .bearer(&token)
+            .json(serde_json::json!({
+                "token": "not valid!",
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+
+        // A random token is generated if none is given
+        let request = Request::post("/api/admin/v1/user-registration-tokens")
+            .bearer(&token)
+            .json(serde_json::json!({}));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(
+            body["data"]["attributes"]["token"].as_str().unwrap().len(),
+            16
+        );
+        assert_eq!(
+            body["data"]["attributes"]["usage_limit"],
+            serde_json::Value::Null
+        );
+    }
+}
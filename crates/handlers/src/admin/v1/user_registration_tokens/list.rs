@@ -0,0 +1,248 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+use aide::{transform::TransformOperation, OperationIo};
+use axum::{
+    extract::{rejection::QueryRejection, Query},
+    response::IntoResponse,
+    Json,
+};
+use axum_macros::FromRequestParts;
+use hyper::StatusCode;
+use mas_storage::{user::UserRegistrationTokenFilter, Page};
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+use crate::{
+    admin::{
+        call_context::{Admin, CallContext},
+        model::{Resource, UserRegistrationToken},
+        params::Pagination,
+        response::{ErrorResponse, PaginatedResponse},
+    },
+    impl_from_error_for_route,
+};
+
+#[derive(FromRequestParts, Deserialize, JsonSchema, OperationIo)]
+#[serde(rename = "UserRegistrationTokenFilter")]
+#[aide(input_with = "Query<FilterParams>")]
+#[from_request(via(Query), rejection(RouteError))]
+pub struct FilterParams {
+    /// Retrieve tokens which were (or weren't) revoked
+    #[serde(rename = "filter[revoked]")]
+    revoked: Option<bool>,
+
+    /// Retrieve tokens which have (or haven't) expired
+    #[serde(rename = "filter[expired]")]
+    expired: Option<bool>,
+
+    /// Retrieve tokens which were (or weren't) used as many times as allowed
+    #[serde(rename = "filter[exhausted]")]
+    exhausted: Option<bool>,
+}
+
+impl std::fmt::Display for FilterParams {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut sep = '?';
+
+        if let Some(revoked) = self.revoked {
+            write!(f, "{sep}filter[revoked]={revoked}")?;
+            sep = '&';
+        }
+
+        if let Some(expired) = self.expired {
+            write!(f, "{sep}filter[expired]={expired}")?;
+            sep = '&';
+        }
+
+        if let Some(exhausted) = self.exhausted {
+            write!(f, "{sep}filter[exhausted]={exhausted}")?;
+            sep = '&';
+        }
+
+        let _ = sep;
+        Ok(())
+    }
+}
+
+#[derive(Debug, thiserror::Error, OperationIo)]
+#[aide(output_with = "Json<ErrorResponse>")]
+pub enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error("Invalid filter parameters")]
+    InvalidFilter(#[from] QueryRejection),
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let error = ErrorResponse::from_error(&self);
+        let status = match self {
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::InvalidFilter(_) => StatusCode::BAD_REQUEST,
+        };
+        (status, Json(error)).into_response()
+    }
+}
+
+pub fn doc(operation: TransformOperation) -> TransformOperation {
+    operation
+        .id("listUserRegistrationTokens")
+        .summary("List registration tokens")
+        .tag("user-registration-token")
+        .response_with::<200, Json<PaginatedResponse<UserRegistrationToken>>, _>(|t| {
+            let tokens = UserRegistrationToken::samples();
+            let pagination = mas_storage::Pagination::first(tokens.len());
+            let page = Page {
+                edges: tokens.into(),
+                has_next_page: true,
+                has_previous_page: false,
+            };
+
+            t.description("Paginated response of registration tokens")
+                .example(PaginatedResponse::new(
+                    page,
+                    pagination,
+                    42,
+                    UserRegistrationToken::PATH,
+                ))
+        })
+}
+
+#[tracing::instrument(name = "handler.admin.v1.user_registration_tokens.list", skip_all, err)]
+pub async fn handler(
+    CallContext {
+        mut repo, clock, ..
+    }: CallContext<Admin>,
+    Pagination(pagination): Pagination,
+    params: FilterParams,
+) -> Result<Json<PaginatedResponse<UserRegistrationToken>>, RouteError> {
+    let base = format!("{path}{params}", path = UserRegistrationToken::PATH);
+    let filter = UserRegistrationTokenFilter::new(clock.now());
+
+    let filter = match params.revoked {
+        Some(revoked) => filter.with_revoked(revoked),
+        None => filter,
+    };
+
+    let filter = match params.expired {
+        Some(expired) => filter.with_expired(expired),
+        None => filter,
+    };
+
+    let filter = match params.exhausted {
+        Some(exhausted) => filter.with_exhausted(exhausted),
+        None => filter,
+    };
+
+    let page = repo
+        .user_registration_token()
+        .list(filter, pagination)
+        .await?;
+    let count = repo.user_registration_token().count(filter).await?;
+
+    Ok(Json(PaginatedResponse::new(
+        page.map(UserRegistrationToken::from),
+        pagination,
+        count,
+        &base,
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use hyper::{Request, StatusCode};
+    use mas_storage::{Clock, RepositoryAccess};
+    use sqlx::PgPool;
+
+    use crate::test_utils::{setup, RequestBuilderExt, ResponseExt, TestState};
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_list(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let mut rng = state.rng();
+
+        let mut repo = state.repository().await.unwrap();
+        let unlimited = repo
+            .user_registration_token()
+            .add(&mut rng, &state.clock, "unlimited".to_owned(), None, None)
+            .await
+            .unwrap();
+        // Advance the clock so that the tokens are listed in a stable order
+        state.clock.advance(Duration::try_minutes(1).unwrap());
+        let expiring = repo
+            .user_registration_token()
+            .add(
+                &mut rng,
+                &state.clock,
+                "expiring".to_owned(),
+                None,
+                Some(state.clock.now() + Duration::try_hours(1).unwrap()),
+            )
+            .await
+            .unwrap();
+        state.clock.advance(Duration::try_minutes(1).unwrap());
+        let revoked = repo
+            .user_registration_token()
+            .add(&mut rng, &state.clock, "revoked".to_owned(), Some(1), None)
+            .await
+            .unwrap();
+        let revoked = repo
+            .user_registration_token()
+            .revoke(&state.clock, revoked)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let request = Request::get("/api/admin/v1/user-registration-tokens")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["meta"]["count"], 3);
+        assert_eq!(body["data"][0]["type"], "user-registration-token");
+        assert_eq!(body["data"][0]["id"], unlimited.id.to_string());
+        assert_eq!(body["data"][0]["attributes"]["token"], "unlimited");
+
+        let request = Request::get("/api/admin/v1/user-registration-tokens?filter[revoked]=true")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["meta"]["count"], 1);
+        assert_eq!(body["data"][0]["id"], revoked.id.to_string());
+
+        // Once the second token expired, only the first one is usable
+        state.clock.advance(Duration::try_hours(2).unwrap());
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let request = Request::get("/api/admin/v1/user-registration-tokens?filter[expired]=true")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["meta"]["count"], 1);
+        assert_eq!(body["data"][0]["id"], expiring.id.to_string());
+
+        let request = Request::get(
+            "/api/admin/v1/user-registration-tokens?filter[revoked]=false&filter[expired]=false",
+        )
+        .bearer(&token)
+        .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["meta"]["count"], 1);
+        assert_eq!(body["data"][0]["id"], unlimited.id.to_string());
+    }
+}
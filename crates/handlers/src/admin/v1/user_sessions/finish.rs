@@ -0,0 +1,222 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+use aide::{transform::TransformOperation, NoApi, OperationIo};
+use axum::{response::IntoResponse, Json};
+use hyper::StatusCode;
+use mas_data_model::AuditLogEventKind;
+use mas_storage::{
+    job::{JobRepositoryExt, NewSignInSession, SendWebhookEventJob},
+    BoxRng,
+};
+use serde_json::json;
+use ulid::Ulid;
+
+use crate::{
+    admin::{
+        call_context::{CallContext, ManageSessions},
+        model::{Resource, UserSession},
+        params::UlidPathParam,
+        response::{ErrorResponse, SingleResponse},
+    },
+    impl_from_error_for_route,
+};
+
+#[derive(Debug, thiserror::Error, OperationIo)]
+#[aide(output_with = "Json<ErrorResponse>")]
+pub enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error("User session ID {0} not found")]
+    NotFound(Ulid),
+
+    #[error("User session ID {0} is already finished")]
+    AlreadyFinished(Ulid),
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let error = ErrorResponse::from_error(&self);
+        let status = match self {
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::AlreadyFinished(_) => StatusCode::BAD_REQUEST,
+        };
+        (status, Json(error)).into_response()
+    }
+}
+
+pub fn doc(operation: TransformOperation) -> TransformOperation {
+    operation
+        .id("finishUserSession")
+        .summary("Finish a user session")
+        .description("Calling this endpoint will finish the user session, signing the user out of the browser it was started in.
+Sessions of clients which were started from this user session are left untouched.")
+        .tag("user-session")
+        .response_with::<200, Json<SingleResponse<UserSession>>, _>(|t| {
+            // In the samples, the third session is the one finished
+            let [_, _, finished, ..] = UserSession::samples();
+            let id = finished.id();
+            let response = SingleResponse::new(
+                finished,
+                format!("/api/admin/v1/user-sessions/{id}/finish"),
+            );
+            t.description("User session was finished")
+                .example(response)
+        })
+        .response_with::<400, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::AlreadyFinished(Ulid::nil()));
+            t.description("User session was already finished")
+                .example(response)
+        })
+        .response_with::<404, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::NotFound(Ulid::nil()));
+            t.description("User session was not found")
+                .example(response)
+        })
+}
+
+#[tracing::instrument(name = "handler.admin.v1.user_sessions.finish", skip_all, err)]
+pub async fn handler(
+    CallContext {
+        mut repo,
+        clock,
+        actor,
+        ..
+    }: CallContext<ManageSessions>,
+    NoApi(mut rng): NoApi<BoxRng>,
+    id: UlidPathParam,
+) -> Result<Json<SingleResponse<UserSession>>, RouteError> {
+    let id = *id;
+    let session = repo
+        .browser_session()
+        .lookup(id)
+        .await?
+        .ok_or(RouteError::NotFound(id))?;
+
+    if session.finished_at.is_some() {
+        return Err(RouteError::AlreadyFinished(id));
+    }
+
+    let session = repo.browser_session().finish(&clock, session).await?;
+
+    repo.audit_log()
+        .add(
+            &mut rng,
+            &clock,
+            actor
+                .event(AuditLogEventKind::SessionFinished)
+                .for_user(&session.user)
+                .with_data(json!({
+                    "session_type": "browser",
+                    "session_id": session.id,
+                    "reason": "admin",
+                })),
+        )
+        .await?;
+
+    repo.job()
+        .schedule_job(SendWebhookEventJob::session_ended(
+            session.user.id,
+            NewSignInSession::Browser(session.id),
+        ))
+        .await?;
+
+    repo.save().await?;
+
+    Ok(Json(SingleResponse::new(
+        UserSession::from(session),
+        format!("/api/admin/v1/user-sessions/{id}/finish"),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{Request, StatusCode};
+    use mas_data_model::AuditLogEventKind;
+    use mas_storage::{audit_log::AuditLogFilter, Clock, Pagination, RepositoryAccess};
+    use sqlx::PgPool;
+    use ulid::Ulid;
+
+    use crate::test_utils::{setup, RequestBuilderExt, ResponseExt, TestState};
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_finish_session(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let mut rng = state.rng();
+
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        let session = repo
+            .browser_session()
+            .add(&mut rng, &state.clock, &user, None)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let request = Request::post(format!("/api/admin/v1/user-sessions/{}/finish", session.id))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(
+            body["data"]["attributes"]["finished_at"],
+            serde_json::json!(state.clock.now())
+        );
+
+        // A single event records that the admin session finished it
+        let mut repo = state.repository().await.unwrap();
+        let events = repo
+            .audit_log()
+            .list(AuditLogFilter::new().for_user(&user), Pagination::first(10))
+            .await
+            .unwrap();
+        repo.cancel().await.unwrap();
+        assert_eq!(events.edges.len(), 1);
+        let event = &events.edges[0];
+        assert_eq!(event.kind, AuditLogEventKind::SessionFinished);
+        assert!(event.actor_session_id.is_some());
+        assert_eq!(event.data["session_type"], "browser");
+        assert_eq!(event.data["reason"], "admin");
+
+        // Finishing it a second time should fail
+        let request = Request::post(format!("/api/admin/v1/user-sessions/{}/finish", session.id))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_finish_unknown_session(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let request = Request::post(format!(
+            "/api/admin/v1/user-sessions/{}/finish",
+            Ulid::nil()
+        ))
+        .bearer(&token)
+        .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NOT_FOUND);
+        let body: serde_json::Value = response.json();
+        assert_eq!(
+            body["errors"][0]["title"],
+            "User session ID 00000000000000000000000000 not found"
+        );
+    }
+}
@@ -9,7 +9,7 @@ use axum::{extract::State, response::IntoResponse, Json};
 use hyper::StatusCode;
 use mas_matrix::BoxHomeserverConnection;
 use mas_storage::{
-    job::{JobRepositoryExt, ProvisionUserJob},
+    job::{JobRepositoryExt, ProvisionUserJob, SendWebhookEventJob},
     BoxRng,
 };
 use schemars::JsonSchema;
@@ -18,43 +18,14 @@ use tracing::warn;
 
 use crate::{
     admin::{
-        call_context::CallContext,
+        call_context::{CallContext, WriteUsers},
         model::User,
         response::{ErrorResponse, SingleResponse},
     },
     impl_from_error_for_route,
+    username::username_valid,
 };
 
-fn valid_username_character(c: char) -> bool {
-    c.is_ascii_lowercase()
-        || c.is_ascii_digit()
-        || c == '='
-        || c == '_'
-        || c == '-'
-        || c == '.'
-        || c == '/'
-        || c == '+'
-}
-
-// XXX: this should be shared with the graphql handler
-fn username_valid(username: &str) -> bool {
-    if username.is_empty() || username.len() > 255 {
-        return false;
-    }
-
-    // Should not start with an underscore
-    if username.starts_with('_') {
-        return false;
-    }
-
-    // Should only contain valid characters
-    if !username.chars().all(valid_username_character) {
-        return false;
-    }
-
-    true
-}
-
 #[derive(Debug, thiserror::Error, OperationIo)]
 #[aide(output_with = "Json<ErrorResponse>")]
 pub enum RouteError {
@@ -132,8 +103,11 @@ pub fn doc(operation: TransformOperation) -> TransformOperation {
 #[tracing::instrument(name = "handler.admin.v1.users.add", skip_all, err)]
 pub async fn handler(
     CallContext {
-        mut repo, clock, ..
-    }: CallContext,
+        mut repo,
+        clock,
+        actor,
+        ..
+    }: CallContext<WriteUsers>,
     NoApi(mut rng): NoApi<BoxRng>,
     State(homeserver): State<BoxHomeserverConnection>,
     Json(params): Json<Request>,
@@ -168,6 +142,18 @@ pub async fn handler(
         .schedule_job(ProvisionUserJob::new(&user))
         .await?;
 
+    repo.job()
+        .schedule_job(SendWebhookEventJob::user_registered(&user))
+        .await?;
+
+    repo.audit_log()
+        .add(
+            &mut rng,
+            &clock,
+            actor.action("createUser", user.id).for_user(&user),
+        )
+        .await?;
+
     repo.save().await?;
 
     Ok(Json(SingleResponse::new_canonical(User::from(user))))
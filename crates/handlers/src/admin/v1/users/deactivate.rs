@@ -4,16 +4,19 @@
 // SPDX-License-Identifier: AGPL-3.0-only
 // Please see LICENSE in the repository root for full details.
 
-use aide::{transform::TransformOperation, OperationIo};
+use aide::{transform::TransformOperation, NoApi, OperationIo};
 use axum::{response::IntoResponse, Json};
 use hyper::StatusCode;
-use mas_storage::job::{DeactivateUserJob, JobRepositoryExt};
+use mas_storage::{
+    job::{DeactivateUserJob, JobRepositoryExt, SendWebhookEventJob},
+    BoxRng,
+};
 use tracing::info;
 use ulid::Ulid;
 
 use crate::{
     admin::{
-        call_context::CallContext,
+        call_context::{CallContext, WriteUsers},
         model::{Resource, User},
         params::UlidPathParam,
         response::{ErrorResponse, SingleResponse},
@@ -67,8 +70,12 @@ This invalidates any existing session, and will ask the homeserver to make them
 #[tracing::instrument(name = "handler.admin.v1.users.deactivate", skip_all, err)]
 pub async fn handler(
     CallContext {
-        mut repo, clock, ..
-    }: CallContext,
+        mut repo,
+        clock,
+        actor,
+        ..
+    }: CallContext<WriteUsers>,
+    NoApi(mut rng): NoApi<BoxRng>,
     id: UlidPathParam,
 ) -> Result<Json<SingleResponse<User>>, RouteError> {
     let id = *id;
@@ -80,6 +87,10 @@ pub async fn handler(
 
     if user.locked_at.is_none() {
         user = repo.user().lock(&clock, user).await?;
+
+        repo.job()
+            .schedule_job(SendWebhookEventJob::user_locked(&user))
+            .await?;
     }
 
     info!("Scheduling deactivation of user {}", user.id);
@@ -87,6 +98,14 @@ pub async fn handler(
         .schedule_job(DeactivateUserJob::new(&user, true))
         .await?;
 
+    repo.audit_log()
+        .add(
+            &mut rng,
+            &clock,
+            actor.action("deactivateUser", user.id).for_user(&user),
+        )
+        .await?;
+
     repo.save().await?;
 
     Ok(Json(SingleResponse::new(
@@ -199,4 +218,38 @@ mod tests {
             "User ID 01040G2081040G2081040G2081 not found"
         );
     }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_deactivate_user_read_only_scope(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool.clone()).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin:users:read").await;
+
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut state.rng(), &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        // The user can be looked up…
+        let request = Request::get(format!("/api/admin/v1/users/{}", user.id))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+
+        // …but not deactivated
+        let request = Request::post(format!("/api/admin/v1/users/{}/deactivate", user.id))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::FORBIDDEN);
+
+        // Check that the user was not deactivated
+        let mut repo = state.repository().await.unwrap();
+        let user = repo.user().lookup(user.id).await.unwrap().unwrap();
+        assert!(user.locked_at.is_none());
+    }
 }
@@ -0,0 +1,168 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+use aide::{
+    gen::GenContext,
+    openapi::{MediaType, Operation, SchemaObject},
+    transform::TransformOperation,
+    OperationOutput,
+};
+use axum::{
+    body::Body,
+    extract::State,
+    response::{IntoResponse, Response},
+};
+use futures_util::{Stream, StreamExt};
+use hyper::header::CONTENT_TYPE;
+use indexmap::IndexMap;
+use mas_storage::RepositoryError;
+use sqlx::PgPool;
+
+use crate::{
+    admin::call_context::{Admin, CallContext},
+    bulk_users::{export_users, UserRecord},
+};
+
+/// A response streaming records as JSON lines
+pub struct JsonLines<S>(S);
+
+impl<S> IntoResponse for JsonLines<S>
+where
+    S: Stream<Item = Result<UserRecord, RepositoryError>> + Send + 'static,
+{
+    fn into_response(self) -> Response {
+        let lines = self.0.map(|record| {
+            let mut line = serde_json::to_vec(&record?)?;
+            line.push(b'\n');
+            Ok::<_, Box<dyn std::error::Error + Send + Sync>>(line)
+        });
+
+        (
+            [(CONTENT_TYPE, "application/x-ndjson")],
+            Body::from_stream(lines),
+        )
+            .into_response()
+    }
+}
+
+impl<S> OperationOutput for JsonLines<S> {
+    type Inner = UserRecord;
+
+    fn operation_response(
+        ctx: &mut GenContext,
+        _operation: &mut Operation,
+    ) -> Option<aide::openapi::Response> {
+        let schema = ctx.schema.subschema_for::<UserRecord>().into_object();
+
+        Some(aide::openapi::Response {
+            description: "One user per line".to_owned(),
+            content: IndexMap::from_iter([(
+                "application/x-ndjson".into(),
+                MediaType {
+                    schema: Some(SchemaObject {
+                        json_schema: schema.into(),
+                        example: None,
+                        external_docs: None,
+                    }),
+                    ..Default::default()
+                },
+            )]),
+            ..Default::default()
+        })
+    }
+}
+
+pub fn doc(operation: TransformOperation) -> TransformOperation {
+    operation
+        .id("exportUsers")
+        .summary("Export all users")
+        .description(
+            "Stream all the users as JSON lines, in the format accepted by the import endpoint. \
+             As the records include password hashes, this requires the `urn:mas:admin` scope.",
+        )
+        .tag("user")
+        .response_with::<200, JsonLines<()>, _>(|t| t.description("The users, one per line"))
+}
+
+#[tracing::instrument(name = "handler.admin.v1.users.export", skip_all)]
+pub async fn handler(
+    _: CallContext<Admin>,
+    State(pool): State<PgPool>,
+) -> JsonLines<impl Stream<Item = Result<UserRecord, RepositoryError>> + Send + 'static> {
+    // The stream reads the users one page at a time, each in a short
+    // transaction, while the response is being sent
+    JsonLines(export_users(pool))
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{Request, StatusCode};
+    use sqlx::PgPool;
+
+    use crate::{
+        bulk_users::UserRecord,
+        test_utils::{setup, RequestBuilderExt, ResponseExt, TestState},
+    };
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_export_users(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let request = Request::post("/api/admin/v1/users/import")
+            .bearer(&token)
+            .json(serde_json::json!({
+                "users": [
+                    {
+                        "username": "alice",
+                        "emails": [
+                            { "email": "alice@example.org" },
+                            { "email": "alice@example.com", "verified": true },
+                        ],
+                    },
+                    { "username": "bob", "admin": true },
+                ],
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+
+        let request = Request::get("/api/admin/v1/users/export")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        response.assert_header_value(hyper::header::CONTENT_TYPE, "application/x-ndjson");
+
+        let records: Vec<UserRecord> = response
+            .body()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(records.len(), 2);
+        let alice = records.iter().find(|r| r.username == "alice").unwrap();
+        assert_eq!(alice.admin, Some(false));
+        // The primary email comes first
+        assert_eq!(alice.emails[0].email, "alice@example.com");
+        assert!(alice.emails[0].verified);
+        assert!(!alice.emails[1].verified);
+        let bob = records.iter().find(|r| r.username == "bob").unwrap();
+        assert_eq!(bob.admin, Some(true));
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_export_requires_admin_scope(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin:users:read").await;
+
+        let request = Request::get("/api/admin/v1/users/export")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::FORBIDDEN);
+    }
+}
@@ -0,0 +1,431 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+use aide::{transform::TransformOperation, NoApi, OperationIo};
+use axum::{extract::State, response::IntoResponse, Json};
+use hyper::StatusCode;
+use mas_matrix::BoxHomeserverConnection;
+use mas_storage::{BoxRng, RepositoryError};
+use mas_storage_pg::PgRepository;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use tracing::error;
+use ulid::Ulid;
+
+use crate::{
+    admin::{
+        call_context::{CallContext, WriteUsers, ADMIN_SCOPE},
+        response::ErrorResponse,
+    },
+    bulk_users::{import_user, ImportError, ImportOutcome, UserRecord},
+    impl_from_error_for_route,
+    passwords::PasswordManager,
+};
+
+/// The maximum number of users which can be imported in a single request
+const MAX_USERS: usize = 1000;
+
+#[derive(Debug, thiserror::Error, OperationIo)]
+#[aide(output_with = "Json<ErrorResponse>")]
+pub enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error("Too many users in a single request, the maximum is {MAX_USERS}")]
+    TooManyUsers,
+
+    #[error("Setting the admin flag of users requires the {ADMIN_SCOPE} scope")]
+    AdminScopeRequired,
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let error = ErrorResponse::from_error(&self);
+        let status = match self {
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::TooManyUsers => StatusCode::BAD_REQUEST,
+            Self::AdminScopeRequired => StatusCode::FORBIDDEN,
+        };
+        (status, Json(error)).into_response()
+    }
+}
+
+/// # JSON payload for the `POST /api/admin/v1/users/import` endpoint
+#[derive(Deserialize, JsonSchema)]
+#[serde(rename = "ImportUsersRequest")]
+pub struct Request {
+    /// The users to import, at most 1000 per request
+    users: Vec<UserRecord>,
+
+    /// Skip checking with the homeserver whether the usernames of the new
+    /// users are available.
+    #[serde(default)]
+    skip_homeserver_check: bool,
+}
+
+/// The result of importing a single user
+#[derive(Serialize, JsonSchema)]
+#[serde(rename = "UserImportResult")]
+pub struct ImportResult {
+    /// The username of the imported user
+    username: String,
+
+    /// The ID of the user, if it was imported
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<crate::admin::schema::Ulid>")]
+    id: Option<Ulid>,
+
+    /// What importing the user did, if it was imported
+    #[serde(skip_serializing_if = "Option::is_none")]
+    outcome: Option<ImportOutcome>,
+
+    /// Why the user could not be imported
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// # JSON response of the `POST /api/admin/v1/users/import` endpoint
+#[derive(Serialize, JsonSchema, Default)]
+#[serde(rename = "ImportUsersResponse")]
+pub struct Response {
+    /// How many users were created
+    created: usize,
+
+    /// How many existing users were updated
+    updated: usize,
+
+    /// How many existing users were left unchanged
+    unchanged: usize,
+
+    /// How many users could not be imported
+    failed: usize,
+
+    /// The result for each user, in the order of the request
+    results: Vec<ImportResult>,
+}
+
+impl Response {
+    fn samples() -> [Self; 1] {
+        [Self {
+            created: 1,
+            updated: 0,
+            unchanged: 0,
+            failed: 1,
+            results: vec![
+                ImportResult {
+                    username: "alice".to_owned(),
+                    id: Some(Ulid::from_bytes([0x01; 16])),
+                    outcome: Some(ImportOutcome::Created),
+                    error: None,
+                },
+                ImportResult {
+                    username: "_bob".to_owned(),
+                    id: None,
+                    outcome: None,
+                    error: Some(ImportError::UsernameNotValid("_bob".to_owned()).to_string()),
+                },
+            ],
+        }]
+    }
+}
+
+pub fn doc(operation: TransformOperation) -> TransformOperation {
+    operation
+        .id("importUsers")
+        .summary("Import users in bulk")
+        .description(
+            "Create or update users from a list of records. \
+             Importing is idempotent: attributes missing from a user are added, \
+             and the ones it already has are left untouched. \
+             Each user is imported in its own transaction: \
+             users which could not be imported are reported in the response and don't prevent the others from being imported. \
+             Setting the `admin` attribute of users requires the `urn:mas:admin` scope.",
+        )
+        .tag("user")
+        .response_with::<200, Json<Response>, _>(|t| {
+            let [sample] = Response::samples();
+            t.description("Users were imported").example(sample)
+        })
+        .response_with::<400, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::TooManyUsers);
+            t.description("Too many users in the request")
+                .example(response)
+        })
+        .response_with::<403, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::AdminScopeRequired);
+            t.description("A user sets the admin attribute without the full admin scope")
+                .example(response)
+        })
+}
+
+#[tracing::instrument(name = "handler.admin.v1.users.import", skip_all, err)]
+pub async fn handler(
+    CallContext {
+        repo,
+        clock,
+        session,
+        actor,
+        ..
+    }: CallContext<WriteUsers>,
+    NoApi(mut rng): NoApi<BoxRng>,
+    State(pool): State<PgPool>,
+    State(homeserver): State<BoxHomeserverConnection>,
+    State(password_manager): State<PasswordManager>,
+    Json(params): Json<Request>,
+) -> Result<Json<Response>, RouteError> {
+    // Users are imported in their own transactions below
+    repo.cancel().await?;
+
+    if params.users.len() > MAX_USERS {
+        return Err(RouteError::TooManyUsers);
+    }
+
+    // Like the dedicated endpoint, changing whether users can request admin
+    // privileges requires full access to the admin API
+    if params.users.iter().any(|record| record.admin.is_some())
+        && !session.scope.contains(ADMIN_SCOPE)
+    {
+        return Err(RouteError::AdminScopeRequired);
+    }
+
+    let mut response = Response::default();
+
+    for record in params.users {
+        let username = record.username.clone();
+
+        // Use a transaction per user, so that the transaction isn't held while
+        // importing the whole batch, and a failure doesn't roll back the users
+        // imported before
+        let mut repo = PgRepository::from_pool(&pool)
+            .await
+            .map_err(RepositoryError::from_error)?
+            .boxed();
+
+        let result = import_user(
+            &mut repo,
+            &mut rng,
+            &clock,
+            &password_manager,
+            &*homeserver,
+            record,
+            params.skip_homeserver_check,
+        )
+        .await;
+
+        let result = match result {
+            Ok((user, outcome)) if outcome != ImportOutcome::Unchanged => repo
+                .audit_log()
+                .add(
+                    &mut rng,
+                    &clock,
+                    actor.action("importUser", user.id).for_user(&user),
+                )
+                .await
+                .map(|_| (user, outcome))
+                .map_err(ImportError::from),
+            result => result,
+        };
+
+        let (user, outcome) = match result {
+            Ok(imported) => {
+                repo.save().await?;
+                imported
+            }
+            Err(e) => {
+                repo.cancel().await?;
+                if !e.is_invalid_record() {
+                    error!(error = &e as &dyn std::error::Error, user.username = %username, "Failed to import user");
+                }
+
+                response.failed += 1;
+                response.results.push(ImportResult {
+                    username,
+                    id: None,
+                    outcome: None,
+                    error: Some(e.to_string()),
+                });
+                continue;
+            }
+        };
+
+        match outcome {
+            ImportOutcome::Created => response.created += 1,
+            ImportOutcome::Updated => response.updated += 1,
+            ImportOutcome::Unchanged => response.unchanged += 1,
+        }
+
+        response.results.push(ImportResult {
+            username,
+            id: Some(user.id),
+            outcome: Some(outcome),
+            error: None,
+        });
+    }
+
+    Ok(Json(response))
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{Request, StatusCode};
+    use mas_storage::{
+        user::{UserEmailRepository, UserPasswordRepository, UserRepository},
+        RepositoryAccess,
+    };
+    use sqlx::PgPool;
+
+    use crate::test_utils::{setup, RequestBuilderExt, ResponseExt, TestState};
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_import_users(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let body = serde_json::json!({
+            "users": [
+                {
+                    "username": "alice",
+                    "emails": [{ "email": "alice@example.com", "verified": true }],
+                    "password": { "version": 1, "hash": "$argon2id$v=19$m=16,t=2,p=1$c29tZXNhbHQ$j1+x2Tn4k9bbDnNx0hA1dg" },
+                    "admin": true,
+                },
+                { "username": "_bob" },
+            ],
+        });
+
+        let request = Request::post("/api/admin/v1/users/import")
+            .bearer(&token)
+            .json(&body);
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let response: serde_json::Value = response.json();
+        assert_eq!(response["created"], 1);
+        assert_eq!(response["failed"], 1);
+        assert_eq!(response["results"][0]["outcome"], "created");
+        assert!(response["results"][1]["error"].is_string());
+
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .find_by_username("alice")
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(user.can_request_admin);
+        assert!(user.primary_user_email_id.is_some());
+        let emails = repo.user_email().all(&user).await.unwrap();
+        assert_eq!(emails.len(), 1);
+        assert!(emails[0].confirmed_at.is_some());
+        let password = repo.user_password().active(&user).await.unwrap().unwrap();
+        assert_eq!(password.version, 1);
+        repo.save().await.unwrap();
+
+        // Importing the same records again doesn't change anything
+        let request = Request::post("/api/admin/v1/users/import")
+            .bearer(&token)
+            .json(&body);
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let response: serde_json::Value = response.json();
+        assert_eq!(response["created"], 0);
+        assert_eq!(response["unchanged"], 1);
+        assert_eq!(response["results"][0]["outcome"], "unchanged");
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_import_invalid_records(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let request = Request::post("/api/admin/v1/users/import")
+            .bearer(&token)
+            .json(serde_json::json!({
+                "users": [
+                    { "username": "alice", "emails": [{ "email": "not an email" }] },
+                    { "username": "bob", "password": { "version": 42, "hash": "hash" } },
+                    {
+                        "username": "charlie",
+                        "upstream_links": [{ "provider_id": "01040G2081040G2081040G2081", "subject": "1234" }],
+                    },
+                    {
+                        "username": "dave",
+                        "emails": [{ "email": "dave@example.com", "verified": true }],
+                        "password": { "version": 1, "hash": "$argon2id$v=19$not-a-hash" },
+                    },
+                ],
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let response: serde_json::Value = response.json();
+        assert_eq!(response["created"], 0);
+        assert_eq!(response["failed"], 4);
+        assert_eq!(
+            response["results"][3]["error"],
+            "Password hash is not valid for hashing scheme version 1"
+        );
+
+        // None of the users were created
+        let mut repo = state.repository().await.unwrap();
+        assert!(!repo.user().exists("alice").await.unwrap());
+        assert!(!repo.user().exists("bob").await.unwrap());
+        assert!(!repo.user().exists("charlie").await.unwrap());
+        assert!(!repo.user().exists("dave").await.unwrap());
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_import_admin_requires_admin_scope(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin:users:write").await;
+
+        // Setting the admin flag is refused without the full admin scope
+        let request = Request::post("/api/admin/v1/users/import")
+            .bearer(&token)
+            .json(serde_json::json!({
+                "users": [
+                    { "username": "alice" },
+                    { "username": "bob", "admin": true },
+                ],
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::FORBIDDEN);
+
+        // Nothing was imported
+        let mut repo = state.repository().await.unwrap();
+        assert!(!repo.user().exists("alice").await.unwrap());
+        assert!(!repo.user().exists("bob").await.unwrap());
+        repo.save().await.unwrap();
+
+        // Users without the admin flag can still be imported
+        let request = Request::post("/api/admin/v1/users/import")
+            .bearer(&token)
+            .json(serde_json::json!({ "users": [{ "username": "alice" }] }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let response: serde_json::Value = response.json();
+        assert_eq!(response["created"], 1);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_import_too_many_users(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let users: Vec<_> = (0..1001)
+            .map(|i| serde_json::json!({ "username": format!("user{i}") }))
+            .collect();
+        let request = Request::post("/api/admin/v1/users/import")
+            .bearer(&token)
+            .json(serde_json::json!({ "users": users }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+    }
+}
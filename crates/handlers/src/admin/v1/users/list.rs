@@ -18,7 +18,7 @@ use serde::Deserialize;
 
 use crate::{
     admin::{
-        call_context::CallContext,
+        call_context::{CallContext, ReadUsers},
         model::{Resource, User},
         params::Pagination,
         response::{ErrorResponse, PaginatedResponse},
@@ -124,7 +124,7 @@ pub fn doc(operation: TransformOperation) -> TransformOperation {
 
 #[tracing::instrument(name = "handler.admin.v1.users.list", skip_all, err)]
 pub async fn handler(
-    CallContext { mut repo, .. }: CallContext,
+    CallContext { mut repo, .. }: CallContext<ReadUsers>,
     Pagination(pagination): Pagination,
     params: FilterParams,
 ) -> Result<Json<PaginatedResponse<User>>, RouteError> {
@@ -4,14 +4,18 @@
 // SPDX-License-Identifier: AGPL-3.0-only
 // Please see LICENSE in the repository root for full details.
 
-use aide::{transform::TransformOperation, OperationIo};
+use aide::{transform::TransformOperation, NoApi, OperationIo};
 use axum::{response::IntoResponse, Json};
 use hyper::StatusCode;
+use mas_storage::{
+    job::{JobRepositoryExt, SendWebhookEventJob},
+    BoxRng,
+};
 use ulid::Ulid;
 
 use crate::{
     admin::{
-        call_context::CallContext,
+        call_context::{CallContext, LockUsers},
         model::{Resource, User},
         params::UlidPathParam,
         response::{ErrorResponse, SingleResponse},
@@ -65,8 +69,12 @@ This DOES NOT invalidate any existing session, meaning that all their existing s
 #[tracing::instrument(name = "handler.admin.v1.users.lock", skip_all, err)]
 pub async fn handler(
     CallContext {
-        mut repo, clock, ..
-    }: CallContext,
+        mut repo,
+        clock,
+        actor,
+        ..
+    }: CallContext<LockUsers>,
+    NoApi(mut rng): NoApi<BoxRng>,
     id: UlidPathParam,
 ) -> Result<Json<SingleResponse<User>>, RouteError> {
     let id = *id;
@@ -78,8 +86,20 @@ pub async fn handler(
 
     if user.locked_at.is_none() {
         user = repo.user().lock(&clock, user).await?;
+
+        repo.job()
+            .schedule_job(SendWebhookEventJob::user_locked(&user))
+            .await?;
     }
 
+    repo.audit_log()
+        .add(
+            &mut rng,
+            &clock,
+            actor.action("lockUser", user.id).for_user(&user),
+        )
+        .await?;
+
     repo.save().await?;
 
     Ok(Json(SingleResponse::new(
@@ -5,9 +5,12 @@
 // Please see LICENSE in the repository root for full details.
 
 mod add;
+mod by_email;
 mod by_username;
 mod deactivate;
+mod export;
 mod get;
+mod import;
 mod list;
 mod lock;
 mod set_admin;
@@ -16,9 +19,12 @@ mod unlock;
 
 pub use self::{
     add::{doc as add_doc, handler as add},
+    by_email::{doc as by_email_doc, handler as by_email},
     by_username::{doc as by_username_doc, handler as by_username},
     deactivate::{doc as deactivate_doc, handler as deactivate},
+    export::{doc as export_doc, handler as export},
     get::{doc as get_doc, handler as get},
+    import::{doc as import_doc, handler as import},
     list::{doc as list_doc, handler as list},
     lock::{doc as lock_doc, handler as lock},
     set_admin::{doc as set_admin_doc, handler as set_admin},
@@ -4,16 +4,17 @@
 // SPDX-License-Identifier: AGPL-3.0-only
 // Please see LICENSE in the repository root for full details.
 
-use aide::{transform::TransformOperation, OperationIo};
+use aide::{transform::TransformOperation, NoApi, OperationIo};
 use axum::{response::IntoResponse, Json};
 use hyper::StatusCode;
+use mas_storage::BoxRng;
 use schemars::JsonSchema;
 use serde::Deserialize;
 use ulid::Ulid;
 
 use crate::{
     admin::{
-        call_context::CallContext,
+        call_context::{Admin, CallContext},
         model::{Resource, User},
         params::UlidPathParam,
         response::{ErrorResponse, SingleResponse},
@@ -73,7 +74,13 @@ pub fn doc(operation: TransformOperation) -> TransformOperation {
 
 #[tracing::instrument(name = "handler.admin.v1.users.set_admin", skip_all, err)]
 pub async fn handler(
-    CallContext { mut repo, .. }: CallContext,
+    CallContext {
+        mut repo,
+        clock,
+        actor,
+        ..
+    }: CallContext<Admin>,
+    NoApi(mut rng): NoApi<BoxRng>,
     id: UlidPathParam,
     Json(params): Json<Request>,
 ) -> Result<Json<SingleResponse<User>>, RouteError> {
@@ -89,6 +96,14 @@ pub async fn handler(
         .set_can_request_admin(user, params.admin)
         .await?;
 
+    repo.audit_log()
+        .add(
+            &mut rng,
+            &clock,
+            actor.action("userSetAdmin", user.id).for_user(&user),
+        )
+        .await?;
+
     repo.save().await?;
 
     Ok(Json(SingleResponse::new(
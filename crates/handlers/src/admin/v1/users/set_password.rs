@@ -7,14 +7,20 @@
 use aide::{transform::TransformOperation, NoApi, OperationIo};
 use axum::{extract::State, response::IntoResponse, Json};
 use hyper::StatusCode;
+use mas_data_model::AuditLogEventKind;
 use mas_storage::BoxRng;
 use schemars::JsonSchema;
 use serde::Deserialize;
+use serde_json::json;
 use ulid::Ulid;
 use zeroize::Zeroizing;
 
 use crate::{
-    admin::{call_context::CallContext, params::UlidPathParam, response::ErrorResponse},
+    admin::{
+        call_context::{CallContext, WriteUsers},
+        params::UlidPathParam,
+        response::ErrorResponse,
+    },
     impl_from_error_for_route,
     passwords::PasswordManager,
 };
@@ -28,6 +34,9 @@ pub enum RouteError {
     #[error("Password is too weak")]
     PasswordTooWeak,
 
+    #[error("Password was found in a list of breached passwords")]
+    PasswordBreached,
+
     #[error("Password auth is disabled")]
     PasswordAuthDisabled,
 
@@ -46,7 +55,7 @@ impl IntoResponse for RouteError {
         let status = match self {
             Self::Internal(_) | Self::Password(_) => StatusCode::INTERNAL_SERVER_ERROR,
             Self::PasswordAuthDisabled => StatusCode::FORBIDDEN,
-            Self::PasswordTooWeak => StatusCode::BAD_REQUEST,
+            Self::PasswordTooWeak | Self::PasswordBreached => StatusCode::BAD_REQUEST,
             Self::NotFound(_) => StatusCode::NOT_FOUND,
         };
         (status, Json(error)).into_response()
@@ -65,7 +74,7 @@ pub struct Request {
     #[schemars(example = "password_example")]
     password: String,
 
-    /// Skip the password complexity check
+    /// Skip the password complexity and breached password checks
     skip_password_check: Option<bool>,
 }
 
@@ -77,7 +86,8 @@ pub fn doc(operation: TransformOperation) -> TransformOperation {
         .response_with::<200, StatusCode, _>(|t| t.description("Password was set"))
         .response_with::<400, RouteError, _>(|t| {
             let response = ErrorResponse::from_error(&RouteError::PasswordTooWeak);
-            t.description("Password is too weak").example(response)
+            t.description("Password is too weak, or was found in a list of breached passwords")
+                .example(response)
         })
         .response_with::<403, RouteError, _>(|t| {
             let response = ErrorResponse::from_error(&RouteError::PasswordAuthDisabled);
@@ -93,8 +103,11 @@ pub fn doc(operation: TransformOperation) -> TransformOperation {
 #[tracing::instrument(name = "handler.admin.v1.users.set_password", skip_all, err)]
 pub async fn handler(
     CallContext {
-        mut repo, clock, ..
-    }: CallContext,
+        mut repo,
+        clock,
+        actor,
+        ..
+    }: CallContext<WriteUsers>,
     NoApi(mut rng): NoApi<BoxRng>,
     State(password_manager): State<PasswordManager>,
     id: UlidPathParam,
@@ -120,6 +133,10 @@ pub async fn handler(
         return Err(RouteError::PasswordTooWeak);
     }
 
+    if !skip_password_check && password_manager.is_password_breached(&params.password) {
+        return Err(RouteError::PasswordBreached);
+    }
+
     let password = Zeroizing::new(params.password.into_bytes());
     let (version, hashed_password) = password_manager
         .hash(&mut rng, password)
@@ -130,6 +147,19 @@ pub async fn handler(
         .add(&mut rng, &clock, &user, version, hashed_password, None)
         .await?;
 
+    repo.audit_log()
+        .add(
+            &mut rng,
+            &clock,
+            actor
+                .event(AuditLogEventKind::PasswordChanged)
+                .for_user(&user)
+                .with_data(json!({
+                    "method": "admin",
+                })),
+        )
+        .await?;
+
     repo.save().await?;
 
     Ok(StatusCode::NO_CONTENT)
@@ -138,12 +168,15 @@ pub async fn handler(
 #[cfg(test)]
 mod tests {
     use hyper::{Request, StatusCode};
-    use mas_storage::{user::UserPasswordRepository, RepositoryAccess};
+    use mas_data_model::AuditLogEventKind;
+    use mas_storage::{
+        audit_log::AuditLogFilter, user::UserPasswordRepository, Pagination, RepositoryAccess,
+    };
     use sqlx::PgPool;
     use zeroize::Zeroizing;
 
     use crate::{
-        passwords::PasswordManager,
+        passwords::{BreachedPasswords, PasswordManager},
         test_utils::{setup, RequestBuilderExt, ResponseExt, TestState},
     };
 
@@ -192,6 +225,17 @@ mod tests {
             )
             .await
             .unwrap();
+
+        // A single event records that the admin session did it
+        let events = repo
+            .audit_log()
+            .list(AuditLogFilter::new().for_user(&user), Pagination::first(10))
+            .await
+            .unwrap();
+        assert_eq!(events.edges.len(), 1);
+        assert_eq!(events.edges[0].kind, AuditLogEventKind::PasswordChanged);
+        assert!(events.edges[0].actor_session_id.is_some());
+        assert_eq!(events.edges[0].data["method"], "admin");
     }
 
     #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
@@ -253,6 +297,60 @@ mod tests {
             .unwrap();
     }
 
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_breached_password(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        // SHA-1 hash of "this is a good enough password"
+        let breached =
+            BreachedPasswords::parse("5D7CC4315B8117F8D7690C65DABB9572C92E091D:1\n").unwrap();
+        state.password_manager = state.password_manager.with_breached_passwords(breached);
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        // Create a user
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut state.rng(), &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let user_id = user.id;
+
+        // Set a breached password through the API
+        let request = Request::post(format!("/api/admin/v1/users/{user_id}/set-password"))
+            .bearer(&token)
+            .json(serde_json::json!({
+                "password": "this is a good enough password",
+            }));
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = response.json();
+        assert_eq!(
+            body["errors"][0]["title"],
+            "Password was found in a list of breached passwords"
+        );
+
+        // Check that the user still has no password
+        let mut repo = state.repository().await.unwrap();
+        let user_password = repo.user_password().active(&user).await.unwrap();
+        assert!(user_password.is_none());
+        repo.save().await.unwrap();
+
+        // The skip_password_check flag also skips this check
+        let request = Request::post(format!("/api/admin/v1/users/{user_id}/set-password"))
+            .bearer(&token)
+            .json(serde_json::json!({
+                "password": "this is a good enough password",
+                "skip_password_check": true,
+            }));
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NO_CONTENT);
+    }
+
     #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
     async fn test_unknown_user(pool: PgPool) {
         setup();
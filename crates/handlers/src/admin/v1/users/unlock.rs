@@ -4,15 +4,16 @@
 // SPDX-License-Identifier: AGPL-3.0-only
 // Please see LICENSE in the repository root for full details.
 
-use aide::{transform::TransformOperation, OperationIo};
+use aide::{transform::TransformOperation, NoApi, OperationIo};
 use axum::{extract::State, response::IntoResponse, Json};
 use hyper::StatusCode;
 use mas_matrix::BoxHomeserverConnection;
+use mas_storage::BoxRng;
 use ulid::Ulid;
 
 use crate::{
     admin::{
-        call_context::CallContext,
+        call_context::{CallContext, LockUsers},
         model::{Resource, User},
         params::UlidPathParam,
         response::{ErrorResponse, SingleResponse},
@@ -50,6 +51,9 @@ pub fn doc(operation: TransformOperation) -> TransformOperation {
     operation
         .id("unlockUser")
         .summary("Unlock a user")
+        .description(
+            "This also lifts any temporary lockout caused by repeated failed login attempts.",
+        )
         .tag("user")
         .response_with::<200, Json<SingleResponse<User>>, _>(|t| {
             // In the samples, the third user is the one locked
@@ -66,7 +70,13 @@ pub fn doc(operation: TransformOperation) -> TransformOperation {
 
 #[tracing::instrument(name = "handler.admin.v1.users.unlock", skip_all, err)]
 pub async fn handler(
-    CallContext { mut repo, .. }: CallContext,
+    CallContext {
+        mut repo,
+        clock,
+        actor,
+        ..
+    }: CallContext<LockUsers>,
+    NoApi(mut rng): NoApi<BoxRng>,
     State(homeserver): State<BoxHomeserverConnection>,
     id: UlidPathParam,
 ) -> Result<Json<SingleResponse<User>>, RouteError> {
@@ -87,6 +97,17 @@ pub async fn handler(
     // Now unlock the user in our database
     let user = repo.user().unlock(user).await?;
 
+    // Also lift any lockout caused by failed login attempts
+    repo.user_login_lockout().clear(&user).await?;
+
+    repo.audit_log()
+        .add(
+            &mut rng,
+            &clock,
+            actor.action("unlockUser", user.id).for_user(&user),
+        )
+        .await?;
+
     repo.save().await?;
 
     Ok(Json(SingleResponse::new(
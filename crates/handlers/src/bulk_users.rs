@@ -0,0 +1,635 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+//! Import and export users in bulk, to migrate them between deployments
+//!
+//! This is shared by the admin API and the `mas-cli manage import-users` and
+//! `mas-cli manage export-users` commands.
+
+use futures_util::{stream, Stream, TryStreamExt};
+use mas_data_model::User;
+use mas_matrix::HomeserverConnection;
+use mas_storage::{
+    job::{JobRepositoryExt, ProvisionUserJob, SendWebhookEventJob},
+    upstream_oauth2::UpstreamOAuthLinkFilter,
+    user::UserFilter,
+    BoxRepository, Clock, Pagination, RepositoryAccess, RepositoryError,
+};
+use mas_storage_pg::PgRepository;
+use rand::RngCore;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use thiserror::Error;
+use tracing::warn;
+use ulid::Ulid;
+
+use crate::{
+    passwords::{PasswordManager, SchemeVersion},
+    username::username_valid,
+};
+
+/// How many users are fetched at once when exporting
+const EXPORT_PAGE_SIZE: usize = 100;
+
+/// An email address of a user
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct EmailRecord {
+    /// The email address
+    pub email: String,
+
+    /// Whether the email address was verified. Unverified addresses are
+    /// imported as such, and can't be used until the user verifies them.
+    #[serde(default)]
+    pub verified: bool,
+}
+
+/// A password of a user, already hashed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct PasswordRecord {
+    /// The version of the hashing scheme, as set in the `passwords.schemes`
+    /// configuration section
+    pub version: SchemeVersion,
+
+    /// The password hash, in the format of the hashing scheme
+    pub hash: String,
+}
+
+/// A link between a user and an account on an upstream provider
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct UpstreamLinkRecord {
+    /// The ID of the upstream provider
+    #[schemars(with = "String")]
+    pub provider_id: Ulid,
+
+    /// The subject of the account on the upstream provider
+    pub subject: String,
+}
+
+/// A user, as imported or exported in bulk
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct UserRecord {
+    /// The username (localpart) of the user
+    pub username: String,
+
+    /// The display name to set on the homeserver. It is not stored by the
+    /// service, so it is never exported.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+
+    /// The email addresses of the user. The first verified one becomes the
+    /// primary address if the user doesn't have one yet.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub emails: Vec<EmailRecord>,
+
+    /// The password of the user
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<PasswordRecord>,
+
+    /// The links to upstream accounts of the user
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub upstream_links: Vec<UpstreamLinkRecord>,
+
+    /// Whether the user can request admin privileges. Left untouched on
+    /// existing users if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub admin: Option<bool>,
+}
+
+/// What importing a record did
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportOutcome {
+    /// The user did not exist and was created
+    Created,
+
+    /// The user existed, and some of its attributes were added or changed
+    Updated,
+
+    /// The user existed and already had all the attributes of the record
+    Unchanged,
+}
+
+#[derive(Debug, Error)]
+pub enum ImportError {
+    #[error("Username {0:?} is not valid")]
+    UsernameNotValid(String),
+
+    #[error("Username {0:?} is reserved by the homeserver")]
+    UsernameReserved(String),
+
+    #[error("Email address {0:?} is not valid")]
+    EmailNotValid(String),
+
+    #[error("Passwords can't be imported, as password authentication is disabled")]
+    PasswordAuthDisabled,
+
+    #[error("Password hashing scheme version {0} is not configured")]
+    UnsupportedPasswordVersion(SchemeVersion),
+
+    #[error("Password hash is not valid for hashing scheme version {0}")]
+    InvalidPasswordHash(SchemeVersion),
+
+    #[error("Upstream provider {0} not found")]
+    ProviderNotFound(Ulid),
+
+    #[error("Upstream account {subject:?} of provider {provider_id} is linked to another user")]
+    LinkConflict { provider_id: Ulid, subject: String },
+
+    #[error(transparent)]
+    Homeserver(anyhow::Error),
+
+    #[error(transparent)]
+    Repository(#[from] RepositoryError),
+}
+
+impl ImportError {
+    /// Whether the error is caused by the record itself, as opposed to a
+    /// failure of the database or the homeserver
+    #[must_use]
+    pub fn is_invalid_record(&self) -> bool {
+        !matches!(self, Self::Homeserver(_) | Self::Repository(_))
+    }
+}
+
+/// Import a user, creating it if it doesn't exist
+///
+/// Importing is additive and idempotent: email addresses and upstream links
+/// which are not in the record are kept, and importing the same record twice
+/// leaves the user unchanged the second time. The whole record is validated
+/// before anything is written, so that a failed import doesn't leave a
+/// partially imported user behind.
+///
+/// This doesn't save the repository, so that callers can decide how many
+/// records are imported in a single transaction.
+///
+/// # Errors
+///
+/// Returns an error if the record is not valid, or if the database or the
+/// homeserver failed
+#[allow(clippy::too_many_lines)]
+#[tracing::instrument(name = "bulk_users.import", skip_all, fields(user.username = record.username))]
+pub async fn import_user(
+    repo: &mut BoxRepository,
+    rng: &mut (dyn RngCore + Send),
+    clock: &dyn Clock,
+    password_manager: &PasswordManager,
+    homeserver: &dyn HomeserverConnection<Error = anyhow::Error>,
+    record: UserRecord,
+    skip_homeserver_check: bool,
+) -> Result<(User, ImportOutcome), ImportError> {
+    if !username_valid(&record.username) {
+        return Err(ImportError::UsernameNotValid(record.username));
+    }
+
+    for email in &record.emails {
+        if email.email.parse::<lettre::Address>().is_err() {
+            return Err(ImportError::EmailNotValid(email.email.clone()));
+        }
+    }
+
+    if let Some(password) = &record.password {
+        let supported = password_manager
+            .is_supported_version(password.version)
+            .map_err(|_| ImportError::PasswordAuthDisabled)?;
+        if !supported {
+            return Err(ImportError::UnsupportedPasswordVersion(password.version));
+        }
+
+        let valid = password_manager
+            .is_valid_hash(password.version, &password.hash)
+            .map_err(|_| ImportError::PasswordAuthDisabled)?;
+        if !valid {
+            return Err(ImportError::InvalidPasswordHash(password.version));
+        }
+    }
+
+    let existing_user = repo.user().find_by_username(&record.username).await?;
+
+    // Look up the upstream links, to make sure they can all be associated to
+    // this user before changing anything
+    let mut links = Vec::with_capacity(record.upstream_links.len());
+    for link in record.upstream_links {
+        let provider = repo
+            .upstream_oauth_provider()
+            .lookup(link.provider_id)
+            .await?
+            .ok_or(ImportError::ProviderNotFound(link.provider_id))?;
+
+        let existing_link = repo
+            .upstream_oauth_link()
+            .find_by_subject(&provider, &link.subject)
+            .await?;
+
+        if let Some(user_id) = existing_link.as_ref().and_then(|link| link.user_id) {
+            if existing_user.as_ref().map(|user| user.id) != Some(user_id) {
+                return Err(ImportError::LinkConflict {
+                    provider_id: provider.id,
+                    subject: link.subject,
+                });
+            }
+        }
+
+        links.push((provider, link.subject, existing_link));
+    }
+
+    let (mut user, mut outcome) = if let Some(user) = existing_user {
+        (user, ImportOutcome::Unchanged)
+    } else {
+        let available = homeserver
+            .is_localpart_available(&record.username)
+            .await
+            .map_err(ImportError::Homeserver)?;
+
+        if !available {
+            if !skip_homeserver_check {
+                return Err(ImportError::UsernameReserved(record.username));
+            }
+
+            warn!("Skipped homeserver check for username {}", record.username);
+        }
+
+        let user = repo.user().add(rng, clock, record.username).await?;
+        repo.job()
+            .schedule_job(SendWebhookEventJob::user_registered(&user))
+            .await?;
+        (user, ImportOutcome::Created)
+    };
+
+    let mut mark_as_updated = || {
+        if outcome == ImportOutcome::Unchanged {
+            outcome = ImportOutcome::Updated;
+        }
+    };
+
+    for email in record.emails {
+        let existing_email = repo.user_email().find(&user, &email.email).await?;
+        let user_email = if let Some(user_email) = existing_email {
+            user_email
+        } else {
+            mark_as_updated();
+            repo.user_email()
+                .add(rng, clock, &user, email.email)
+                .await?
+        };
+
+        let user_email = if email.verified && user_email.confirmed_at.is_none() {
+            mark_as_updated();
+            let user_email = repo
+                .user_email()
+                .mark_as_verified(clock, user_email)
+                .await?;
+            repo.job()
+                .schedule_job(SendWebhookEventJob::user_email_verified(&user, &user_email))
+                .await?;
+            user_email
+        } else {
+            user_email
+        };
+
+        if user.primary_user_email_id.is_none() && user_email.confirmed_at.is_some() {
+            mark_as_updated();
+            repo.user_email().set_as_primary(&user_email).await?;
+            user.primary_user_email_id = Some(user_email.id);
+        }
+    }
+
+    if let Some(password) = record.password {
+        let active = repo.user_password().active(&user).await?;
+        let up_to_date = active.as_ref().is_some_and(|active| {
+            active.version == password.version && active.hashed_password == password.hash
+        });
+
+        if !up_to_date {
+            mark_as_updated();
+            repo.user_password()
+                .add(rng, clock, &user, password.version, password.hash, None)
+                .await?;
+        }
+    }
+
+    for (provider, subject, existing_link) in links {
+        match existing_link {
+            Some(link) if link.user_id.is_some() => {}
+            Some(link) => {
+                mark_as_updated();
+                repo.upstream_oauth_link()
+                    .associate_to_user(&link, &user)
+                    .await?;
+            }
+            None => {
+                mark_as_updated();
+                let link = repo
+                    .upstream_oauth_link()
+                    .add(rng, clock, &provider, subject)
+                    .await?;
+                repo.upstream_oauth_link()
+                    .associate_to_user(&link, &user)
+                    .await?;
+            }
+        }
+    }
+
+    if let Some(admin) = record.admin {
+        if admin != user.can_request_admin {
+            mark_as_updated();
+            user = repo.user().set_can_request_admin(user, admin).await?;
+        }
+    }
+
+    // The display name isn't stored locally, so we can't tell whether it
+    // changed, and always provision the user when one is set
+    if outcome != ImportOutcome::Unchanged || record.display_name.is_some() {
+        let mut job = ProvisionUserJob::new(&user);
+        if let Some(display_name) = record.display_name {
+            job = job.set_display_name(display_name);
+        }
+        repo.job().schedule_job(job).await?;
+    }
+
+    Ok((user, outcome))
+}
+
+/// Export a user to a record, which can be imported back with
+/// [`import_user`]
+///
+/// # Errors
+///
+/// Returns an error if the database failed
+pub async fn export_user(
+    repo: &mut BoxRepository,
+    user: &User,
+) -> Result<UserRecord, RepositoryError> {
+    // Put the primary email first, so that it stays primary once imported
+    let mut emails = repo.user_email().all(user).await?;
+    emails.sort_by_key(|email| Some(email.id) != user.primary_user_email_id);
+    let emails = emails
+        .into_iter()
+        .map(|email| EmailRecord {
+            verified: email.confirmed_at.is_some(),
+            email: email.email,
+        })
+        .collect();
+
+    let password = repo
+        .user_password()
+        .active(user)
+        .await?
+        .map(|password| PasswordRecord {
+            version: password.version,
+            hash: password.hashed_password,
+        });
+
+    let filter = UpstreamOAuthLinkFilter::new().for_user(user);
+    let mut pagination = Pagination::first(EXPORT_PAGE_SIZE);
+    let mut upstream_links = Vec::new();
+    loop {
+        let page = repo.upstream_oauth_link().list(filter, pagination).await?;
+        upstream_links.extend(page.edges.iter().map(|link| UpstreamLinkRecord {
+            provider_id: link.provider_id,
+            subject: link.subject.clone(),
+        }));
+
+        match page.edges.last() {
+            Some(last) if page.has_next_page => pagination = pagination.after(last.id),
+            _ => break,
+        }
+    }
+
+    Ok(UserRecord {
+        username: user.username.clone(),
+        display_name: None,
+        emails,
+        password,
+        upstream_links,
+        admin: Some(user.can_request_admin),
+    })
+}
+
+/// Export all the users, one page at a time
+///
+/// Each page is read in its own transaction, so that no transaction is kept
+/// open while the records are being consumed.
+pub fn export_users(
+    pool: PgPool,
+) -> impl Stream<Item = Result<UserRecord, RepositoryError>> + Send {
+    let state = Some(Pagination::first(EXPORT_PAGE_SIZE));
+    stream::try_unfold(state, move |state| {
+        let pool = pool.clone();
+        async move {
+            let Some(pagination) = state else {
+                return Ok(None);
+            };
+
+            let mut repo = PgRepository::from_pool(&pool)
+                .await
+                .map_err(RepositoryError::from_error)?
+                .boxed();
+
+            let page = repo.user().list(UserFilter::new(), pagination).await?;
+
+            let mut records = Vec::with_capacity(page.edges.len());
+            for user in &page.edges {
+                records.push(Ok(export_user(&mut repo, user).await?));
+            }
+
+            repo.cancel().await?;
+
+            let next = match page.edges.last() {
+                Some(last) if page.has_next_page => Some(pagination.after(last.id)),
+                _ => None,
+            };
+
+            Ok(Some((stream::iter(records), next)))
+        }
+    })
+    .try_flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use mas_matrix::MockHomeserverConnection;
+    use mas_storage::clock::MockClock;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::passwords::Hasher;
+
+    #[test]
+    fn test_parse_record() {
+        // Only the username is required
+        let record: UserRecord = serde_json::from_str(r#"{"username": "alice"}"#).unwrap();
+        assert_eq!(record.username, "alice");
+        assert!(record.emails.is_empty());
+        assert!(record.password.is_none());
+        assert!(record.admin.is_none());
+
+        let record: UserRecord = serde_json::from_str(
+            r#"{
+                "username": "bob",
+                "display_name": "Bob",
+                "emails": [{"email": "bob@example.com", "verified": true}, {"email": "bob@example.org"}],
+                "password": {"version": 1, "hash": "$2b$12$abcdefghijklmnopqrstuv"},
+                "upstream_links": [{"provider_id": "01040G2081040G2081040G2081", "subject": "1234"}],
+                "admin": true
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(record.display_name.as_deref(), Some("Bob"));
+        assert!(record.emails[0].verified);
+        assert!(!record.emails[1].verified);
+        assert_eq!(record.password.as_ref().unwrap().version, 1);
+        assert_eq!(record.upstream_links[0].subject, "1234");
+        assert_eq!(record.admin, Some(true));
+
+        // Empty fields are skipped when serializing
+        let record = UserRecord {
+            username: "alice".to_owned(),
+            display_name: None,
+            emails: Vec::new(),
+            password: None,
+            upstream_links: Vec::new(),
+            admin: Some(false),
+        };
+        assert_eq!(
+            serde_json::to_string(&record).unwrap(),
+            r#"{"username":"alice","admin":false}"#
+        );
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_import_export(pool: PgPool) {
+        let mut rng = rand_chacha::ChaChaRng::seed_from_u64(42);
+        let clock = MockClock::default();
+        let homeserver = MockHomeserverConnection::new("example.com");
+        let password_manager = PasswordManager::new(0, [(1, Hasher::argon2id(None))]).unwrap();
+        let mut repo = PgRepository::from_pool(&pool).await.unwrap().boxed();
+
+        let record: UserRecord = serde_json::from_value(serde_json::json!({
+            "username": "alice",
+            "emails": [
+                { "email": "alice@example.org" },
+                { "email": "alice@example.com", "verified": true },
+            ],
+            "password": { "version": 1, "hash": "$argon2id$v=19$m=16,t=2,p=1$c29tZXNhbHQ$j1+x2Tn4k9bbDnNx0hA1dg" },
+            "admin": true,
+        }))
+        .unwrap();
+
+        let (user, outcome) = import_user(
+            &mut repo,
+            &mut rng,
+            &clock,
+            &password_manager,
+            &homeserver,
+            record.clone(),
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(outcome, ImportOutcome::Created);
+        assert!(user.can_request_admin);
+
+        // Importing the same record again doesn't change anything
+        let (_, outcome) = import_user(
+            &mut repo,
+            &mut rng,
+            &clock,
+            &password_manager,
+            &homeserver,
+            record.clone(),
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(outcome, ImportOutcome::Unchanged);
+
+        // The exported record has the verified, primary email first
+        let exported = export_user(&mut repo, &user).await.unwrap();
+        assert_eq!(exported.emails[0].email, "alice@example.com");
+        assert!(exported.emails[0].verified);
+        assert_eq!(exported.emails[1].email, "alice@example.org");
+        assert!(!exported.emails[1].verified);
+        assert_eq!(exported.password, record.password);
+        assert_eq!(exported.admin, Some(true));
+
+        // Changing an attribute updates the user
+        let record = UserRecord {
+            admin: Some(false),
+            ..record
+        };
+        let (user, outcome) = import_user(
+            &mut repo,
+            &mut rng,
+            &clock,
+            &password_manager,
+            &homeserver,
+            record,
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(outcome, ImportOutcome::Updated);
+        assert!(!user.can_request_admin);
+
+        repo.save().await.unwrap();
+
+        let records: Vec<UserRecord> = export_users(pool).try_collect().await.unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].username, "alice");
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_import_invalid(pool: PgPool) {
+        let mut rng = rand_chacha::ChaChaRng::seed_from_u64(42);
+        let clock = MockClock::default();
+        let homeserver = MockHomeserverConnection::new("example.com");
+        let password_manager = PasswordManager::new(0, [(1, Hasher::argon2id(None))]).unwrap();
+        let mut repo = PgRepository::from_pool(&pool).await.unwrap().boxed();
+
+        let invalid = [
+            serde_json::json!({ "username": "_alice" }),
+            serde_json::json!({ "username": "alice", "emails": [{ "email": "alice" }] }),
+            serde_json::json!({ "username": "alice", "password": { "version": 2, "hash": "hash" } }),
+            // The rest of the record is valid, but the hash is malformed
+            serde_json::json!({
+                "username": "alice",
+                "emails": [{ "email": "alice@example.com", "verified": true }],
+                "password": { "version": 1, "hash": "hash" },
+            }),
+            // A valid bcrypt hash, under an argon2id scheme
+            serde_json::json!({
+                "username": "alice",
+                "password": {
+                    "version": 1,
+                    "hash": "$2b$04$Xz2vXOKr1SBr9/lDmhkg6OeYj2Vm8VYgd4MKl9f3.NwT4gN0Ka.8u",
+                },
+            }),
+            serde_json::json!({
+                "username": "alice",
+                "upstream_links": [{ "provider_id": "01040G2081040G2081040G2081", "subject": "1234" }],
+            }),
+        ];
+
+        for record in invalid {
+            let record = serde_json::from_value(record).unwrap();
+            let error = import_user(
+                &mut repo,
+                &mut rng,
+                &clock,
+                &password_manager,
+                &homeserver,
+                record,
+                false,
+            )
+            .await
+            .unwrap_err();
+            assert!(error.is_invalid_record());
+        }
+
+        // Nothing was written
+        assert!(!repo.user().exists("alice").await.unwrap());
+    }
+}
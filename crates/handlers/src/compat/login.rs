@@ -4,32 +4,40 @@
 // SPDX-License-Identifier: AGPL-3.0-only
 // Please see LICENSE in the repository root for full details.
 
+use std::net::IpAddr;
+
 use axum::{extract::State, response::IntoResponse, Json};
 use axum_extra::typed_header::TypedHeader;
 use chrono::Duration;
 use hyper::StatusCode;
 use mas_axum_utils::sentry::SentryEventID;
 use mas_data_model::{
-    CompatSession, CompatSsoLoginState, Device, SiteConfig, TokenType, User, UserAgent,
+    AuditLogEventKind, CompatSession, CompatSsoLoginState, Device, SiteConfig, TokenType, User,
+    UserAgent,
 };
 use mas_matrix::BoxHomeserverConnection;
 use mas_storage::{
+    audit_log::NewAuditLogEvent,
     compat::{
         CompatAccessTokenRepository, CompatRefreshTokenRepository, CompatSessionRepository,
         CompatSsoLoginRepository,
     },
+    job::{JobRepositoryExt, NewSignInSession, SendWebhookEventJob},
     user::{UserPasswordRepository, UserRepository},
     BoxClock, BoxRepository, BoxRng, Clock, RepositoryAccess,
 };
 use rand::{CryptoRng, RngCore};
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use serde_with::{serde_as, skip_serializing_none, DurationMilliSeconds};
 use thiserror::Error;
 use zeroize::Zeroizing;
 
 use super::MatrixError;
 use crate::{
-    impl_from_error_for_route, passwords::PasswordManager, rate_limit::PasswordCheckLimitedError,
+    impl_from_error_for_route, login_lockout, new_sign_in,
+    passwords::{verify_external, ExternalVerification, PasswordManager},
+    rate_limit::PasswordCheckLimitedError,
     BoundActivityTracker, Limiter, RequesterFingerprint,
 };
 
@@ -231,7 +239,7 @@ pub(crate) async fn post(
     Json(input): Json<RequestBody>,
 ) -> Result<impl IntoResponse, RouteError> {
     let user_agent = user_agent.map(|ua| UserAgent::parse(ua.as_str().to_owned()));
-    let (mut session, user) = match (password_manager.is_enabled(), input.credentials) {
+    let (mut session, user, method) = match (password_manager.is_enabled(), input.credentials) {
         (
             true,
             Credentials::Password {
@@ -239,7 +247,7 @@ pub(crate) async fn post(
                 password,
             },
         ) => {
-            user_password_login(
+            let res = user_password_login(
                 &mut rng,
                 &clock,
                 &password_manager,
@@ -247,19 +255,68 @@ pub(crate) async fn post(
                 requester,
                 &mut repo,
                 &homeserver,
+                &site_config,
+                user_agent.as_ref(),
+                activity_tracker.ip(),
+                activity_tracker.location(),
                 user,
                 password,
             )
-            .await?
+            .await;
+
+            match res {
+                Ok((session, user)) => (session, user, "compat_password"),
+                Err(
+                    e @ (RouteError::UserNotFound
+                    | RouteError::NoPassword
+                    | RouteError::PasswordVerificationFailed(_)),
+                ) => {
+                    // Persist the failed login attempt
+                    repo.save().await?;
+                    return Err(e);
+                }
+                Err(e) => return Err(e),
+            }
         }
 
-        (_, Credentials::Token { token }) => token_login(&mut repo, &clock, &token).await?,
+        (_, Credentials::Token { token }) => {
+            let (session, user) = token_login(&mut repo, &clock, &token).await?;
+            (session, user, "compat_token")
+        }
 
         _ => {
             return Err(RouteError::Unsupported);
         }
     };
 
+    for kind in [
+        AuditLogEventKind::LoginSucceeded,
+        AuditLogEventKind::SessionCreated,
+    ] {
+        repo.audit_log()
+            .add(
+                &mut rng,
+                &clock,
+                NewAuditLogEvent::new(kind)
+                    .for_user(&user)
+                    .with_ip_address(activity_tracker.ip())
+                    .with_user_agent(user_agent.as_ref())
+                    .with_data(json!({
+                        "method": method,
+                        "session_type": "compat",
+                        "session_id": session.id,
+                    })),
+            )
+            .await?;
+    }
+
+    repo.job()
+        .schedule_job(SendWebhookEventJob::session_started(
+            user.id,
+            NewSignInSession::Compat(session.id),
+        ))
+        .await?;
+
     if let Some(user_agent) = user_agent {
         session = repo
             .compat_session()
@@ -375,6 +432,7 @@ async fn token_login(
     Ok((session, user))
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn user_password_login(
     mut rng: &mut (impl RngCore + CryptoRng + Send),
     clock: &impl Clock,
@@ -383,53 +441,75 @@ async fn user_password_login(
     requester: RequesterFingerprint,
     repo: &mut BoxRepository,
     homeserver: &BoxHomeserverConnection,
+    site_config: &SiteConfig,
+    user_agent: Option<&UserAgent>,
+    ip: Option<IpAddr>,
+    location: Option<&str>,
     username: String,
     password: String,
 ) -> Result<(CompatSession, User), RouteError> {
     // Find the user
-    let user = repo
-        .user()
-        .find_by_username(&username)
-        .await?
-        .filter(mas_data_model::User::is_valid)
-        .ok_or(RouteError::UserNotFound)?;
+    let user = repo.user().find_by_username(&username).await?;
+
+    // Check the rate limit. Unknown users can only log in if they are accepted by
+    // the external password provider.
+    match &user {
+        Some(user) if user.is_valid() => limiter.check_password(requester, user)?,
+        None if password_manager.external_provider().is_some() => {
+            limiter.check_password_for_unknown_user(requester)?;
+        }
+        _ => return Err(RouteError::UserNotFound),
+    }
 
-    // Check the rate limit
-    limiter.check_password(requester, &user)?;
+    // Check if the account was locked out after too many failed attempts
+    let lockout_config = site_config.login_lockout.as_ref();
+    if let Some(user) = &user {
+        if login_lockout::is_locked_out(lockout_config, clock, repo, user).await? {
+            return Err(PasswordCheckLimitedError::LockedOut(user.id).into());
+        }
+    }
 
-    // Lookup its password
-    let user_password = repo
-        .user_password()
-        .active(&user)
-        .await?
-        .ok_or(RouteError::NoPassword)?;
+    // Keep the user around to record failed attempts on it
+    let known_user = user.clone();
+
+    let res = verify_password(
+        rng,
+        clock,
+        password_manager,
+        repo,
+        homeserver,
+        user,
+        &username,
+        password,
+    )
+    .await;
+
+    let user = match res {
+        Ok(user) => user,
+        Err(
+            e @ (RouteError::UserNotFound
+            | RouteError::NoPassword
+            | RouteError::PasswordVerificationFailed(_)),
+        ) => {
+            let mut event = NewAuditLogEvent::new(AuditLogEventKind::LoginFailed)
+                .with_ip_address(ip)
+                .with_user_agent(user_agent)
+                .with_data(json!({ "method": "compat_password", "username": username }));
+            if let Some(user) = &known_user {
+                event = event.for_user(user);
+            }
+            repo.audit_log().add(&mut rng, clock, event).await?;
 
-    // Verify the password
-    let password = Zeroizing::new(password.into_bytes());
+            if let Some(user) = &known_user {
+                login_lockout::record_failure(lockout_config, clock, repo, user, None).await?;
+            }
 
-    let new_password_hash = password_manager
-        .verify_and_upgrade(
-            &mut rng,
-            user_password.version,
-            password,
-            user_password.hashed_password.clone(),
-        )
-        .await
-        .map_err(RouteError::PasswordVerificationFailed)?;
+            return Err(e);
+        }
+        Err(e) => return Err(e),
+    };
 
-    if let Some((version, hashed_password)) = new_password_hash {
-        // Save the upgraded password if needed
-        repo.user_password()
-            .add(
-                &mut rng,
-                clock,
-                &user,
-                version,
-                hashed_password,
-                Some(&user_password),
-            )
-            .await?;
-    }
+    login_lockout::clear(lockout_config, repo, &user).await?;
 
     // Lock the user sync to make sure we don't get into a race condition
     repo.user().acquire_lock_for_sync(&user).await?;
@@ -442,23 +522,117 @@ async fn user_password_login(
         .await
         .map_err(RouteError::ProvisionDeviceFailed)?;
 
+    // This has to be checked before the new session is created
+    let familiar = new_sign_in::is_familiar(repo, &user, user_agent, ip).await?;
+
     let session = repo
         .compat_session()
         .add(&mut rng, clock, &user, device, None, false)
         .await?;
 
+    if !familiar {
+        new_sign_in::notify(
+            repo,
+            &user,
+            NewSignInSession::Compat(session.id),
+            ip,
+            location,
+            None,
+        )
+        .await?;
+    }
+
     Ok((session, user))
 }
 
+/// Verify the credentials, either with the external password provider or
+/// against the local password database
+#[allow(clippy::too_many_arguments)]
+async fn verify_password(
+    mut rng: &mut (impl RngCore + CryptoRng + Send),
+    clock: &impl Clock,
+    password_manager: &PasswordManager,
+    repo: &mut BoxRepository,
+    homeserver: &BoxHomeserverConnection,
+    user: Option<User>,
+    username: &str,
+    password: String,
+) -> Result<User, RouteError> {
+    let password = Zeroizing::new(password.into_bytes());
+
+    // Ask the external password provider first, if any
+    let external = verify_external(
+        password_manager,
+        &mut rng,
+        clock,
+        repo,
+        homeserver,
+        user,
+        username,
+        &password,
+    )
+    .await
+    .map_err(|e| RouteError::Internal(e.into()))?;
+
+    let user = match external {
+        ExternalVerification::Accepted { user, .. } => user,
+        ExternalVerification::Rejected => return Err(RouteError::UserNotFound),
+        ExternalVerification::Skipped(user) => {
+            let user = user.ok_or(RouteError::UserNotFound)?;
+
+            // Lookup its password
+            let user_password = repo
+                .user_password()
+                .active(&user)
+                .await?
+                .ok_or(RouteError::NoPassword)?;
+
+            // Verify the password
+            let new_password_hash = password_manager
+                .verify_and_upgrade(
+                    &mut rng,
+                    user_password.version,
+                    password,
+                    user_password.hashed_password.clone(),
+                )
+                .await
+                .map_err(RouteError::PasswordVerificationFailed)?;
+
+            if let Some((version, hashed_password)) = new_password_hash {
+                // Save the upgraded password if needed
+                repo.user_password()
+                    .add(
+                        &mut rng,
+                        clock,
+                        &user,
+                        version,
+                        hashed_password,
+                        Some(&user_password),
+                    )
+                    .await?;
+            }
+
+            user
+        }
+    };
+
+    Ok(user)
+}
+
 #[cfg(test)]
 mod tests {
     use hyper::Request;
+    use mas_config::RateLimitingConfig;
     use mas_matrix::{HomeserverConnection, ProvisionRequest};
     use rand::distributions::{Alphanumeric, DistString};
     use sqlx::PgPool;
 
     use super::*;
-    use crate::test_utils::{setup, RequestBuilderExt, ResponseExt, TestState};
+    use crate::{
+        passwords::ExternalPasswordProvider,
+        test_utils::{setup, test_site_config, RequestBuilderExt, ResponseExt, TestState},
+        Limiter,
+    };
 
     /// Test that the server advertises the right login flows.
     #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
@@ -713,6 +887,212 @@ mod tests {
         assert_eq!(body["error"], "Too many login attempts");
     }
 
+    /// Test that accounts are locked out after too many failed login attempts,
+    /// even if the right password is given afterwards.
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_password_login_lockout(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool_with_site_config(
+            pool,
+            SiteConfig {
+                login_lockout: Some(mas_data_model::LoginLockoutConfig {
+                    max_failed_attempts: 2,
+                    window: chrono::Duration::try_minutes(15).unwrap(),
+                    duration: chrono::Duration::try_minutes(15).unwrap(),
+                }),
+                ..test_site_config()
+            },
+        )
+        .await
+        .unwrap();
+
+        let mut repo = state.repository().await.unwrap();
+
+        let user = repo
+            .user()
+            .add(&mut state.rng(), &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+
+        let mxid = state.homeserver_connection.mxid(&user.username);
+        state
+            .homeserver_connection
+            .provision_user(&ProvisionRequest::new(mxid, &user.sub))
+            .await
+            .unwrap();
+
+        let (version, hashed_password) = state
+            .password_manager
+            .hash(
+                &mut state.rng(),
+                Zeroizing::new("password".to_owned().into_bytes()),
+            )
+            .await
+            .unwrap();
+
+        repo.user_password()
+            .add(
+                &mut state.rng(),
+                &state.clock,
+                &user,
+                version,
+                hashed_password,
+                None,
+            )
+            .await
+            .unwrap();
+
+        repo.save().await.unwrap();
+
+        let request = |password: &str| {
+            Request::post("/_matrix/client/v3/login").json(serde_json::json!({
+                "type": "m.login.password",
+                "identifier": {
+                    "type": "m.id.user",
+                    "user": "alice",
+                },
+                "password": password,
+            }))
+        };
+
+        // The first two attempts with the wrong password are rejected
+        let response = state.request(request("wrongpassword")).await;
+        response.assert_status(StatusCode::FORBIDDEN);
+        let response = state.request(request("wrongpassword")).await;
+        response.assert_status(StatusCode::FORBIDDEN);
+
+        // The account is now locked out, even with the right password
+        let response = state.request(request("password")).await;
+        response.assert_status(StatusCode::TOO_MANY_REQUESTS);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["errcode"], "M_LIMIT_EXCEEDED");
+
+        let mut repo = state.repository().await.unwrap();
+        let lockout = repo
+            .user_login_lockout()
+            .lookup(&user)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(lockout.failed_attempts, 2);
+        assert!(lockout.is_locked(state.clock.now()));
+
+        // The lockout expires after the configured duration, and a wrong
+        // password is rejected as usual instead of being rate-limited
+        state
+            .clock
+            .advance(chrono::Duration::try_minutes(16).unwrap());
+        assert!(!lockout.is_locked(state.clock.now()));
+
+        // The in-memory rate limiter doesn't follow the mock clock, so reset it
+        state.limiter = Limiter::new(&RateLimitingConfig::default()).unwrap();
+
+        let response = state.request(request("wrongpassword")).await;
+        response.assert_status(StatusCode::FORBIDDEN);
+
+        // The right password works again
+        let response = state.request(request("password")).await;
+        response.assert_status(StatusCode::OK);
+    }
+
+    /// Test that an unknown user accepted by the external password provider is
+    /// provisioned on login, and that the provider's decision is respected.
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_external_password_provider(pool: PgPool) {
+        use wiremock::{
+            matchers::{bearer_token, body_json, method, path},
+            Mock, MockServer, ResponseTemplate,
+        };
+
+        setup();
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/verify"))
+            .and(bearer_token("shared-secret"))
+            .and(body_json(serde_json::json!({
+                "username": "alice",
+                "password": "password",
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "authenticated": true,
+                "display_name": "Alice",
+                "email": "alice@example.com",
+            })))
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("POST"))
+            .and(path("/verify"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "authenticated": false,
+            })))
+            .mount(&mock_server)
+            .await;
+
+        let state = {
+            let mut state = TestState::from_pool(pool).await.unwrap();
+            let provider = ExternalPasswordProvider::new(
+                state.http_client.clone(),
+                format!("{}/verify", mock_server.uri()).parse().unwrap(),
+            )
+            .with_shared_secret("shared-secret".to_owned())
+            .with_provision_users(true)
+            .with_local_fallback(false);
+            state.password_manager = state.password_manager.with_external_provider(provider);
+            state
+        };
+
+        let request = Request::post("/_matrix/client/v3/login").json(serde_json::json!({
+            "type": "m.login.password",
+            "identifier": {
+                "type": "m.id.user",
+                "user": "alice",
+            },
+            "password": "password",
+        }));
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: ResponseBody = response.json();
+        assert_eq!(body.user_id, "@alice:example.com");
+
+        // The user should have been created, with its email and password
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .find_by_username("alice")
+            .await
+            .unwrap()
+            .expect("user to be provisioned");
+        let email = repo.user_email().get_primary(&user).await.unwrap().unwrap();
+        assert_eq!(email.email, "alice@example.com");
+        assert!(email.confirmed_at.is_some());
+        assert!(repo.user_password().active(&user).await.unwrap().is_some());
+        repo.cancel().await.unwrap();
+
+        // The homeserver should know about the display name
+        let mxid = state.homeserver_connection.mxid("alice");
+        let query = state.homeserver_connection.query_user(&mxid).await.unwrap();
+        assert_eq!(query.displayname.as_deref(), Some("Alice"));
+
+        // A password rejected by the provider should not work, even though the
+        // local password database is not consulted
+        let request = Request::post("/_matrix/client/v3/login").json(serde_json::json!({
+            "type": "m.login.password",
+            "identifier": {
+                "type": "m.id.user",
+                "user": "alice",
+            },
+            "password": "wrongpassword",
+        }));
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::FORBIDDEN);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["errcode"], "M_FORBIDDEN");
+    }
+
     /// Test the response of an unsupported login flow.
     #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
     async fn test_unsupported_login(pool: PgPool) {
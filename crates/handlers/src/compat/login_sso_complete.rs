@@ -22,13 +22,14 @@ use mas_matrix::BoxHomeserverConnection;
 use mas_router::{CompatLoginSsoAction, PostAuthAction, UrlBuilder};
 use mas_storage::{
     compat::{CompatSessionRepository, CompatSsoLoginRepository},
+    job::NewSignInSession,
     BoxClock, BoxRepository, BoxRng, Clock, RepositoryAccess,
 };
 use mas_templates::{CompatSsoContext, ErrorContext, TemplateContext, Templates};
 use serde::{Deserialize, Serialize};
 use ulid::Ulid;
 
-use crate::PreferredLanguage;
+use crate::{new_sign_in, BoundActivityTracker, PreferredLanguage};
 
 #[derive(Serialize)]
 struct AllParams<'s> {
@@ -124,6 +125,7 @@ pub async fn post(
     mut rng: BoxRng,
     clock: BoxClock,
     mut repo: BoxRepository,
+    activity_tracker: BoundActivityTracker,
     PreferredLanguage(locale): PreferredLanguage,
     State(templates): State<Templates>,
     State(url_builder): State<UrlBuilder>,
@@ -203,6 +205,10 @@ pub async fn post(
         .await
         .context("Failed to provision device")?;
 
+    // This has to be checked before the new session is created
+    let ip = activity_tracker.ip();
+    let familiar = new_sign_in::is_familiar_for_browser_session(&mut repo, &session, ip).await?;
+
     let compat_session = repo
         .compat_session()
         .add(
@@ -215,6 +221,18 @@ pub async fn post(
         )
         .await?;
 
+    if !familiar {
+        new_sign_in::notify(
+            &mut repo,
+            &session.user,
+            NewSignInSession::Compat(compat_session.id),
+            ip,
+            activity_tracker.location(),
+            Some(locale.to_string()),
+        )
+        .await?;
+    }
+
     repo.compat_sso_login()
         .fulfill(&clock, login, &compat_session)
         .await?;
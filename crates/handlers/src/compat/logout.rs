@@ -9,12 +9,14 @@ use axum_extra::typed_header::TypedHeader;
 use headers::{authorization::Bearer, Authorization};
 use hyper::StatusCode;
 use mas_axum_utils::sentry::SentryEventID;
-use mas_data_model::TokenType;
+use mas_data_model::{AuditLogEventKind, TokenType};
 use mas_storage::{
+    audit_log::NewAuditLogEvent,
     compat::{CompatAccessTokenRepository, CompatSessionRepository},
-    job::{JobRepositoryExt, SyncDevicesJob},
-    BoxClock, BoxRepository, Clock, RepositoryAccess,
+    job::{JobRepositoryExt, NewSignInSession, SendWebhookEventJob, SyncDevicesJob},
+    BoxClock, BoxRepository, BoxRng, Clock, RepositoryAccess,
 };
+use serde_json::json;
 use thiserror::Error;
 
 use super::MatrixError;
@@ -64,6 +66,7 @@ impl IntoResponse for RouteError {
 
 #[tracing::instrument(name = "handlers.compat.logout.post", skip_all, err)]
 pub(crate) async fn post(
+    mut rng: BoxRng,
     clock: BoxClock,
     mut repo: BoxRepository,
     activity_tracker: BoundActivityTracker,
@@ -106,9 +109,32 @@ pub(crate) async fn post(
     // Schedule a job to sync the devices of the user with the homeserver
     repo.job().schedule_job(SyncDevicesJob::new(&user)).await?;
 
+    repo.audit_log()
+        .add(
+            &mut rng,
+            &clock,
+            NewAuditLogEvent::new(AuditLogEventKind::SessionFinished)
+                .with_actor(&user)
+                .for_user(&user)
+                .with_ip_address(activity_tracker.ip())
+                .with_data(json!({
+                    "session_type": "compat",
+                    "session_id": session.id,
+                    "reason": "logout",
+                })),
+        )
+        .await?;
+
+    repo.job()
+        .schedule_job(SendWebhookEventJob::session_ended(
+            user.id,
+            NewSignInSession::Compat(session.id),
+        ))
+        .await?;
+
     repo.compat_session().finish(&clock, session).await?;
 
     repo.save().await?;
 
-    Ok(Json(serde_json::json!({})))
+    Ok(Json(json!({})))
 }
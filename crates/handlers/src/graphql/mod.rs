@@ -29,10 +29,14 @@ use hyper::header::CACHE_CONTROL;
 use mas_axum_utils::{
     cookies::CookieJar, sentry::SentryEventID, FancyError, SessionInfo, SessionInfoExt,
 };
-use mas_data_model::{BrowserSession, Session, SiteConfig, User};
+use mas_data_model::{AuditLogEventKind, BrowserSession, Session, SiteConfig, User};
 use mas_matrix::HomeserverConnection;
 use mas_policy::{InstantiateError, Policy, PolicyFactory};
-use mas_storage::{BoxClock, BoxRepository, BoxRng, Clock, RepositoryError, SystemClock};
+use mas_router::UrlBuilder;
+use mas_storage::{
+    audit_log::NewAuditLogEvent, BoxClock, BoxRepository, BoxRng, Clock, RepositoryError,
+    SystemClock,
+};
 use mas_storage_pg::PgRepository;
 use opentelemetry_semantic_conventions::trace::{GRAPHQL_DOCUMENT, GRAPHQL_OPERATION_NAME};
 use rand::{thread_rng, SeedableRng};
@@ -70,6 +74,7 @@ struct GraphQLState {
     policy_factory: Arc<PolicyFactory>,
     site_config: SiteConfig,
     password_manager: PasswordManager,
+    url_builder: UrlBuilder,
 }
 
 #[async_trait]
@@ -94,6 +99,10 @@ impl state::State for GraphQLState {
         &self.site_config
     }
 
+    fn url_builder(&self) -> &UrlBuilder {
+        &self.url_builder
+    }
+
     fn homeserver_connection(&self) -> &dyn HomeserverConnection<Error = anyhow::Error> {
         self.homeserver_connection.as_ref()
     }
@@ -119,6 +128,7 @@ pub fn schema(
     homeserver_connection: impl HomeserverConnection<Error = anyhow::Error> + 'static,
     site_config: SiteConfig,
     password_manager: PasswordManager,
+    url_builder: UrlBuilder,
 ) -> Schema {
     let state = GraphQLState {
         pool: pool.clone(),
@@ -126,6 +136,7 @@ pub fn schema(
         homeserver_connection: Arc::new(homeserver_connection),
         site_config,
         password_manager,
+        url_builder,
     };
     let state: BoxState = Box::new(state);
 
@@ -501,6 +512,16 @@ impl Requester {
         user.id == owner_id
     }
 
+    /// Start a new audit log event with the requester as the actor
+    fn audit_event(&self, kind: AuditLogEventKind) -> NewAuditLogEvent {
+        let event = NewAuditLogEvent::new(kind);
+        match self {
+            Self::BrowserSession(session) => event.with_actor(&session.user),
+            Self::OAuth2Session(tuple) => event.with_actor_session(&tuple.0),
+            Self::Anonymous => event,
+        }
+    }
+
     fn is_admin(&self) -> bool {
         match self {
             Self::OAuth2Session(tuple) => {
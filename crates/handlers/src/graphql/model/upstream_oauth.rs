@@ -7,7 +7,9 @@
 use anyhow::Context as _;
 use async_graphql::{Context, Object, ID};
 use chrono::{DateTime, Utc};
+use mas_router::{PostAuthAction, UpstreamOAuth2Authorize};
 use mas_storage::{upstream_oauth2::UpstreamOAuthProviderRepository, user::UserRepository};
+use url::Url;
 
 use super::{NodeType, User};
 use crate::graphql::state::ContextExt;
@@ -45,6 +47,27 @@ impl UpstreamOAuth2Provider {
     pub async fn client_id(&self) -> &str {
         &self.provider.client_id
     }
+
+    /// A human-readable name for this provider.
+    pub async fn human_name(&self) -> Option<&str> {
+        self.provider.human_name.as_deref()
+    }
+
+    /// A brand identifier for this provider, used to customise the UI, e.g.
+    /// `apple`, `google`, `github`, etc.
+    pub async fn brand_name(&self) -> Option<&str> {
+        self.provider.brand_name.as_deref()
+    }
+
+    /// URL to start linking the current user to this provider.
+    ///
+    /// The user is sent back to the account management page once done.
+    pub async fn link_url(&self, ctx: &Context<'_>) -> Url {
+        let state = ctx.state();
+        let route = UpstreamOAuth2Authorize::new(self.provider.id)
+            .and_then(PostAuthAction::manage_account(None));
+        state.url_builder().absolute_url_for(&route)
+    }
 }
 
 impl UpstreamOAuth2Link {
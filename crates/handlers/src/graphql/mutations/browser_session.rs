@@ -5,7 +5,12 @@
 // Please see LICENSE in the repository root for full details.
 
 use async_graphql::{Context, Enum, InputObject, Object, ID};
-use mas_storage::RepositoryAccess;
+use mas_data_model::AuditLogEventKind;
+use mas_storage::{
+    job::{JobRepositoryExt, NewSignInSession, SendWebhookEventJob},
+    RepositoryAccess,
+};
+use serde_json::json;
 
 use crate::graphql::{
     model::{BrowserSession, NodeType},
@@ -84,6 +89,28 @@ impl BrowserSessionMutations {
             return Ok(EndBrowserSessionPayload::NotFound);
         }
 
+        repo.audit_log()
+            .add(
+                &mut state.rng(),
+                &clock,
+                requester
+                    .audit_event(AuditLogEventKind::SessionFinished)
+                    .for_user(&session.user)
+                    .with_data(json!({
+                        "session_type": "browser",
+                        "session_id": session.id,
+                        "reason": "ended",
+                    })),
+            )
+            .await?;
+
+        repo.job()
+            .schedule_job(SendWebhookEventJob::session_ended(
+                session.user.id,
+                NewSignInSession::Browser(session.id),
+            ))
+            .await?;
+
         let session = repo.browser_session().finish(&clock, session).await?;
 
         repo.save().await?;
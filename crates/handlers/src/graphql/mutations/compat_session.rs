@@ -6,11 +6,13 @@
 
 use anyhow::Context as _;
 use async_graphql::{Context, Enum, InputObject, Object, ID};
+use mas_data_model::AuditLogEventKind;
 use mas_storage::{
     compat::CompatSessionRepository,
-    job::{JobRepositoryExt, SyncDevicesJob},
+    job::{JobRepositoryExt, NewSignInSession, SendWebhookEventJob, SyncDevicesJob},
     RepositoryAccess,
 };
+use serde_json::json;
 
 use crate::graphql::{
     model::{CompatSession, NodeType},
@@ -96,6 +98,28 @@ impl CompatSessionMutations {
         // Schedule a job to sync the devices of the user with the homeserver
         repo.job().schedule_job(SyncDevicesJob::new(&user)).await?;
 
+        repo.audit_log()
+            .add(
+                &mut state.rng(),
+                &clock,
+                requester
+                    .audit_event(AuditLogEventKind::SessionFinished)
+                    .for_user(&user)
+                    .with_data(json!({
+                        "session_type": "compat",
+                        "session_id": session.id,
+                        "reason": "ended",
+                    })),
+            )
+            .await?;
+
+        repo.job()
+            .schedule_job(SendWebhookEventJob::session_ended(
+                user.id,
+                NewSignInSession::Compat(session.id),
+            ))
+            .await?;
+
         let session = repo.compat_session().finish(&clock, session).await?;
 
         repo.save().await?;
@@ -7,9 +7,9 @@
 use anyhow::Context as _;
 use async_graphql::{Context, Description, Enum, InputObject, Object, ID};
 use chrono::Duration;
-use mas_data_model::{Device, TokenType};
+use mas_data_model::{AuditLogEventKind, Device, TokenType};
 use mas_storage::{
-    job::{JobRepositoryExt, SyncDevicesJob},
+    job::{JobRepositoryExt, NewSignInSession, SendWebhookEventJob, SyncDevicesJob},
     oauth2::{
         OAuth2AccessTokenRepository, OAuth2ClientRepository, OAuth2RefreshTokenRepository,
         OAuth2SessionRepository,
@@ -18,6 +18,7 @@ use mas_storage::{
     RepositoryAccess,
 };
 use oauth2_types::scope::Scope;
+use serde_json::json;
 
 use crate::graphql::{
     model::{NodeType, OAuth2Session},
@@ -238,6 +239,30 @@ impl OAuth2SessionMutations {
             repo.job().schedule_job(SyncDevicesJob::new(&user)).await?;
         }
 
+        let mut event = requester
+            .audit_event(AuditLogEventKind::SessionFinished)
+            .with_data(json!({
+                "session_type": "oauth2",
+                "session_id": session.id,
+                "client_id": session.client_id,
+                "reason": "ended",
+            }));
+        if let Some(user_id) = session.user_id {
+            event = event.for_user_id(user_id);
+        }
+        repo.audit_log()
+            .add(&mut state.rng(), &clock, event)
+            .await?;
+
+        if let Some(user_id) = session.user_id {
+            repo.job()
+                .schedule_job(SendWebhookEventJob::session_ended(
+                    user_id,
+                    NewSignInSession::OAuth2(session.id),
+                ))
+                .await?;
+        }
+
         let session = repo.oauth2_session().finish(&clock, session).await?;
 
         repo.save().await?;
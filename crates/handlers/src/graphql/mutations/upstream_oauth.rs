@@ -0,0 +1,156 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+use anyhow::Context as _;
+use async_graphql::{Context, Enum, InputObject, Object, ID};
+use mas_data_model::{UpstreamOAuthLink, User};
+use mas_storage::{upstream_oauth2::UpstreamOAuthLinkFilter, RepositoryAccess};
+use tracing::info;
+
+use crate::graphql::{
+    model::{NodeType, UpstreamOAuth2Link},
+    state::ContextExt,
+};
+
+#[derive(Default)]
+pub struct UpstreamOAuthMutations {
+    _private: (),
+}
+
+/// The input of the `removeUpstreamLink` mutation.
+#[derive(InputObject)]
+pub struct RemoveUpstreamLinkInput {
+    /// The ID of the upstream link to remove.
+    upstream_link_id: ID,
+}
+
+/// The status of the `removeUpstreamLink` mutation.
+#[derive(Enum, Copy, Clone, PartialEq, Eq, Debug)]
+enum RemoveUpstreamLinkStatus {
+    /// The upstream link was removed.
+    Removed,
+
+    /// The upstream link was not found.
+    NotFound,
+
+    /// The upstream link is the last way the user has to sign in, so it can't
+    /// be removed.
+    LastSignInMethod,
+}
+
+/// The payload of the `removeUpstreamLink` mutation.
+enum RemoveUpstreamLinkPayload {
+    Removed(Box<UpstreamOAuthLink>),
+    NotFound,
+    LastSignInMethod,
+}
+
+#[Object]
+impl RemoveUpstreamLinkPayload {
+    /// Status of the operation
+    async fn status(&self) -> RemoveUpstreamLinkStatus {
+        match self {
+            Self::Removed(_) => RemoveUpstreamLinkStatus::Removed,
+            Self::NotFound => RemoveUpstreamLinkStatus::NotFound,
+            Self::LastSignInMethod => RemoveUpstreamLinkStatus::LastSignInMethod,
+        }
+    }
+
+    /// The upstream link that was removed.
+    async fn upstream_link(&self) -> Option<UpstreamOAuth2Link> {
+        match self {
+            Self::Removed(link) => Some(UpstreamOAuth2Link::new(*link.clone())),
+            Self::NotFound | Self::LastSignInMethod => None,
+        }
+    }
+}
+
+/// Check whether the [`User`] can still sign in once the given link is removed,
+/// either with their password, or with another upstream link
+async fn has_other_sign_in_method<R: RepositoryAccess>(
+    repo: &mut R,
+    user: &User,
+    link: &UpstreamOAuthLink,
+    password_login_enabled: bool,
+) -> Result<bool, R::Error> {
+    if password_login_enabled && repo.user_password().active(user).await?.is_some() {
+        return Ok(true);
+    }
+
+    // Links to disabled providers can't be used to sign in
+    let filter = UpstreamOAuthLinkFilter::new()
+        .for_user(user)
+        .enabled_providers_only();
+    let count = repo.upstream_oauth_link().count(filter).await?;
+
+    // The link being removed is counted if its provider is enabled
+    let provider_enabled = repo
+        .upstream_oauth_provider()
+        .lookup(link.provider_id)
+        .await?
+        .is_some_and(|provider| provider.enabled());
+    let others = if provider_enabled {
+        count.saturating_sub(1)
+    } else {
+        count
+    };
+
+    Ok(others > 0)
+}
+
+#[Object]
+impl UpstreamOAuthMutations {
+    /// Remove a link between a user and an upstream OAuth 2.0 provider, as long
+    /// as the user can still sign in afterwards
+    async fn remove_upstream_link(
+        &self,
+        ctx: &Context<'_>,
+        input: RemoveUpstreamLinkInput,
+    ) -> Result<RemoveUpstreamLinkPayload, async_graphql::Error> {
+        let state = ctx.state();
+        let upstream_link_id =
+            NodeType::UpstreamOAuth2Link.extract_ulid(&input.upstream_link_id)?;
+        let requester = ctx.requester();
+
+        let mut repo = state.repository().await?;
+
+        let link = repo.upstream_oauth_link().lookup(upstream_link_id).await?;
+        let Some(link) = link else {
+            return Ok(RemoveUpstreamLinkPayload::NotFound);
+        };
+
+        if !requester.is_owner_or_admin(&link) {
+            return Ok(RemoveUpstreamLinkPayload::NotFound);
+        }
+
+        // Links which aren't associated to a user can't be removed this way
+        let Some(user_id) = link.user_id else {
+            return Ok(RemoveUpstreamLinkPayload::NotFound);
+        };
+
+        let user = repo
+            .user()
+            .lookup(user_id)
+            .await?
+            .context("Could not load user")?;
+
+        let password_login_enabled = state.site_config().password_login_enabled;
+        if !has_other_sign_in_method(&mut repo, &user, &link, password_login_enabled).await? {
+            return Ok(RemoveUpstreamLinkPayload::LastSignInMethod);
+        }
+
+        info!(
+            user.id = %user.id,
+            upstream_oauth_link.id = %link.id,
+            upstream_oauth_provider.id = %link.provider_id,
+            "Removing upstream link"
+        );
+        repo.upstream_oauth_link().remove(link.clone()).await?;
+
+        repo.save().await?;
+
+        Ok(RemoveUpstreamLinkPayload::Removed(Box::new(link)))
+    }
+}
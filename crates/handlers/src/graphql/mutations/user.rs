@@ -6,17 +6,23 @@
 
 use anyhow::Context as _;
 use async_graphql::{Context, Description, Enum, InputObject, Object, ID};
+use mas_data_model::AuditLogEventKind;
 use mas_storage::{
-    job::{DeactivateUserJob, JobRepositoryExt, ProvisionUserJob},
+    audit_log::NewAuditLogEvent,
+    job::{DeactivateUserJob, JobRepositoryExt, ProvisionUserJob, SendWebhookEventJob},
     user::UserRepository,
 };
+use serde_json::json;
 use tracing::{info, warn};
 use zeroize::Zeroizing;
 
-use crate::graphql::{
-    model::{NodeType, User},
-    state::ContextExt,
-    Requester, UserId,
+use crate::{
+    graphql::{
+        model::{NodeType, User},
+        state::ContextExt,
+        Requester, UserId,
+    },
+    username::username_valid,
 };
 
 #[derive(Default)]
@@ -291,6 +297,9 @@ enum SetPasswordStatus {
     /// security requirements.
     InvalidNewPassword,
 
+    /// The new password was found in a list of known breached passwords.
+    BreachedNewPassword,
+
     /// You aren't allowed to set the password for that user.
     /// This happens if you aren't setting your own password and you aren't a
     /// server administrator.
@@ -323,36 +332,6 @@ impl SetPasswordPayload {
     }
 }
 
-fn valid_username_character(c: char) -> bool {
-    c.is_ascii_lowercase()
-        || c.is_ascii_digit()
-        || c == '='
-        || c == '_'
-        || c == '-'
-        || c == '.'
-        || c == '/'
-        || c == '+'
-}
-
-// XXX: this should probably be moved somewhere else
-fn username_valid(username: &str) -> bool {
-    if username.is_empty() || username.len() > 255 {
-        return false;
-    }
-
-    // Should not start with an underscore
-    if username.starts_with('_') {
-        return false;
-    }
-
-    // Should only contain valid characters
-    if !username.chars().all(valid_username_character) {
-        return false;
-    }
-
-    true
-}
-
 #[Object]
 impl UserMutations {
     /// Add a user. This is only available to administrators.
@@ -402,6 +381,10 @@ impl UserMutations {
             .schedule_job(ProvisionUserJob::new(&user))
             .await?;
 
+        repo.job()
+            .schedule_job(SendWebhookEventJob::user_registered(&user))
+            .await?;
+
         repo.save().await?;
 
         Ok(AddUserPayload::Added(user))
@@ -431,8 +414,15 @@ impl UserMutations {
 
         let deactivate = input.deactivate.unwrap_or(false);
 
+        let was_locked = user.locked_at.is_some();
         let user = repo.user().lock(&state.clock(), user).await?;
 
+        if !was_locked {
+            repo.job()
+                .schedule_job(SendWebhookEventJob::user_locked(&user))
+                .await?;
+        }
+
         if deactivate {
             info!("Scheduling deactivation of user {}", user.id);
             repo.job()
@@ -587,6 +577,12 @@ impl UserMutations {
             });
         }
 
+        if password_manager.is_password_breached(&input.new_password) {
+            return Ok(SetPasswordPayload {
+                status: SetPasswordStatus::BreachedNewPassword,
+            });
+        }
+
         let mut repo = state.repository().await?;
         let Some(user) = repo.user().lookup(user_id).await? else {
             return Ok(SetPasswordPayload {
@@ -650,6 +646,17 @@ impl UserMutations {
             )
             .await?;
 
+        repo.audit_log()
+            .add(
+                &mut state.rng(),
+                &state.clock(),
+                requester
+                    .audit_event(AuditLogEventKind::PasswordChanged)
+                    .for_user(&user)
+                    .with_data(json!({ "method": "change" })),
+            )
+            .await?;
+
         repo.save().await?;
 
         Ok(SetPasswordPayload {
@@ -658,6 +665,7 @@ impl UserMutations {
     }
 
     /// Set the password for yourself, using a recovery ticket sent by e-mail.
+    #[allow(clippy::too_many_lines)]
     async fn set_password_by_recovery(
         &self,
         ctx: &Context<'_>,
@@ -686,6 +694,12 @@ impl UserMutations {
             });
         }
 
+        if password_manager.is_password_breached(&input.new_password) {
+            return Ok(SetPasswordPayload {
+                status: SetPasswordStatus::BreachedNewPassword,
+            });
+        }
+
         let mut repo = state.repository().await?;
 
         let Some(ticket) = repo.user_recovery().find_ticket(&input.ticket).await? else {
@@ -712,20 +726,31 @@ impl UserMutations {
             });
         }
 
-        let user_email = repo
-            .user_email()
-            .lookup(ticket.user_email_id)
-            .await?
-            // Only allow confirmed email addresses
-            .filter(|email| email.confirmed_at.is_some())
-            .context("Unknown email address")?;
-
         let user = repo
             .user()
-            .lookup(user_email.user_id)
+            .lookup(ticket.user_id)
             .await?
             .context("Invalid user")?;
 
+        // Make sure the user still has the email address or the upstream link the
+        // ticket was issued for
+        if let Some(user_email_id) = ticket.user_email_id {
+            repo.user_email()
+                .lookup(user_email_id)
+                .await?
+                // Only allow confirmed email addresses
+                .filter(|email| email.user_id == user.id && email.confirmed_at.is_some())
+                .context("Unknown email address")?;
+        }
+
+        if let Some(upstream_oauth_link_id) = ticket.upstream_oauth_link_id {
+            repo.upstream_oauth_link()
+                .lookup(upstream_oauth_link_id)
+                .await?
+                .filter(|link| link.user_id == Some(user.id))
+                .context("Unknown upstream link")?;
+        }
+
         if !user.is_valid() {
             return Ok(SetPasswordPayload {
                 status: SetPasswordStatus::AccountLocked,
@@ -747,6 +772,19 @@ impl UserMutations {
             )
             .await?;
 
+        repo.audit_log()
+            .add(
+                &mut state.rng(),
+                &clock,
+                NewAuditLogEvent::new(AuditLogEventKind::PasswordChanged)
+                    .for_user(&user)
+                    .with_data(json!({
+                        "method": "recovery",
+                        "recovery_session_id": session.id,
+                    })),
+            )
+            .await?;
+
         // Mark the session as consumed
         repo.user_recovery()
             .consume_ticket(&clock, ticket, session)
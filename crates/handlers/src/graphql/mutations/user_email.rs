@@ -6,11 +6,13 @@
 
 use anyhow::Context as _;
 use async_graphql::{Context, Description, Enum, InputObject, Object, ID};
+use mas_data_model::AuditLogEventKind;
 use mas_storage::{
-    job::{JobRepositoryExt, ProvisionUserJob, VerifyEmailJob},
+    job::{JobRepositoryExt, ProvisionUserJob, SendWebhookEventJob, VerifyEmailJob},
     user::{UserEmailRepository, UserRepository},
     RepositoryAccess,
 };
+use serde_json::json;
 
 use crate::graphql::{
     model::{NodeType, User, UserEmail},
@@ -416,6 +418,22 @@ impl UserEmailMutations {
             let mut policy = state.policy().await?;
             let res = policy.evaluate_email(&input.email).await?;
             if !res.valid() {
+                repo.audit_log()
+                    .add(
+                        &mut state.rng(),
+                        &state.clock(),
+                        requester
+                            .audit_event(AuditLogEventKind::PolicyDenied)
+                            .for_user(&user)
+                            .with_data(json!({
+                                "policy": "email",
+                                "email": input.email,
+                                "violations": res.violations,
+                            })),
+                    )
+                    .await?;
+                repo.save().await?;
+
                 return Ok(AddEmailPayload::Denied {
                     violations: res.violations,
                 });
@@ -435,6 +453,20 @@ impl UserEmailMutations {
                 .add(&mut rng, &clock, &user, input.email)
                 .await?;
 
+            repo.audit_log()
+                .add(
+                    &mut rng,
+                    &clock,
+                    requester
+                        .audit_event(AuditLogEventKind::EmailAdded)
+                        .for_user(&user)
+                        .with_data(json!({
+                            "user_email_id": user_email.id,
+                            "email": user_email.email,
+                        })),
+                )
+                .await?;
+
             (true, user_email)
         };
 
@@ -445,6 +477,10 @@ impl UserEmailMutations {
                     .user_email()
                     .mark_as_verified(&state.clock(), user_email)
                     .await?;
+
+                repo.job()
+                    .schedule_job(SendWebhookEventJob::user_email_verified(&user, &user_email))
+                    .await?;
             } else {
                 // TODO: figure out the locale
                 repo.job()
@@ -567,6 +603,10 @@ impl UserEmailMutations {
             .mark_as_verified(&clock, user_email)
             .await?;
 
+        repo.job()
+            .schedule_job(SendWebhookEventJob::user_email_verified(&user, &user_email))
+            .await?;
+
         repo.job()
             .schedule_job(ProvisionUserJob::new(&user))
             .await?;
@@ -615,6 +655,20 @@ impl UserEmailMutations {
 
         repo.user_email().remove(user_email.clone()).await?;
 
+        repo.audit_log()
+            .add(
+                &mut state.rng(),
+                &state.clock(),
+                requester
+                    .audit_event(AuditLogEventKind::EmailRemoved)
+                    .for_user(&user)
+                    .with_data(json!({
+                        "user_email_id": user_email.id,
+                        "email": user_email.email,
+                    })),
+            )
+            .await?;
+
         // Schedule a job to update the user
         repo.job()
             .schedule_job(ProvisionUserJob::new(&user))
@@ -658,6 +712,20 @@ impl UserEmailMutations {
 
         repo.user_email().set_as_primary(&user_email).await?;
 
+        repo.audit_log()
+            .add(
+                &mut state.rng(),
+                &state.clock(),
+                requester
+                    .audit_event(AuditLogEventKind::PrimaryEmailChanged)
+                    .for_user_id(user_email.user_id)
+                    .with_data(json!({
+                        "user_email_id": user_email.id,
+                        "email": user_email.email,
+                    })),
+            )
+            .await?;
+
         // The user primary email should already be up to date
         let user = repo
             .user()
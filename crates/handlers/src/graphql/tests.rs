@@ -6,7 +6,7 @@
 
 use axum::http::Request;
 use hyper::StatusCode;
-use mas_data_model::{AccessToken, Client, TokenType, User};
+use mas_data_model::{AccessToken, Client, TokenType, UpstreamOAuthLink, User};
 use mas_matrix::{HomeserverConnection, ProvisionRequest};
 use mas_router::SimpleRoute;
 use mas_storage::{
@@ -19,6 +19,7 @@ use oauth2_types::{
     scope::{Scope, ScopeToken, OPENID},
 };
 use sqlx::PgPool;
+use zeroize::Zeroizing;
 
 use crate::{
     test_utils,
@@ -775,3 +776,150 @@ async fn test_add_user(pool: PgPool) {
         })
     );
 }
+
+async fn add_upstream_link(state: &TestState, user: &User, subject: &str) -> UpstreamOAuthLink {
+    let mut repo = state.repository().await.unwrap();
+    let mut rng = state.rng();
+
+    let provider = repo
+        .upstream_oauth_provider()
+        .add(
+            &mut rng,
+            &state.clock,
+            test_utils::test_upstream_oauth_provider_params(),
+        )
+        .await
+        .unwrap();
+    let link = repo
+        .upstream_oauth_link()
+        .add(&mut rng, &state.clock, &provider, subject.to_owned())
+        .await
+        .unwrap();
+    repo.upstream_oauth_link()
+        .associate_to_user(&link, user)
+        .await
+        .unwrap();
+
+    repo.save().await.unwrap();
+
+    link
+}
+
+async fn remove_upstream_link(
+    state: &TestState,
+    access_token: &str,
+    link: &UpstreamOAuthLink,
+) -> serde_json::Value {
+    let request = Request::post("/graphql")
+        .bearer(access_token)
+        .json(serde_json::json!({
+            "query": r"
+                mutation RemoveUpstreamLink($id: ID!) {
+                    removeUpstreamLink(input: {upstreamLinkId: $id}) {
+                        status
+                    }
+                }
+            ",
+            "variables": {
+                "id": format!("upstream_oauth2_link:{}", link.id),
+            },
+        }));
+    let response = state.request(request).await;
+    response.assert_status(StatusCode::OK);
+    let response: GraphQLResponse = response.json();
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+
+    response.data["removeUpstreamLink"]["status"].clone()
+}
+
+async fn link_exists(state: &TestState, link: &UpstreamOAuthLink) -> bool {
+    let mut repo = state.repository().await.unwrap();
+    let link = repo.upstream_oauth_link().lookup(link.id).await.unwrap();
+    repo.cancel().await.unwrap();
+    link.is_some()
+}
+
+/// Test that users can remove their upstream links, as long as they can still
+/// sign in afterwards
+#[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+async fn test_remove_upstream_link(pool: PgPool) {
+    setup();
+    let state = TestState::from_pool(pool).await.unwrap();
+
+    let client = create_test_client(&state).await;
+    let user = create_test_user(&state, "alice").await;
+    let access_token = start_oauth_session(&state, &client, &user, Scope::from_iter([GRAPHQL]))
+        .await
+        .access_token;
+
+    // The only way to sign in can't be removed
+    let first = add_upstream_link(&state, &user, "first").await;
+    assert_eq!(
+        remove_upstream_link(&state, &access_token, &first).await,
+        "LAST_SIGN_IN_METHOD"
+    );
+    assert!(link_exists(&state, &first).await);
+
+    // It can once there is another link
+    let second = add_upstream_link(&state, &user, "second").await;
+    assert_eq!(
+        remove_upstream_link(&state, &access_token, &first).await,
+        "REMOVED"
+    );
+    assert!(!link_exists(&state, &first).await);
+
+    // Or once the user has a password
+    assert_eq!(
+        remove_upstream_link(&state, &access_token, &second).await,
+        "LAST_SIGN_IN_METHOD"
+    );
+    let (version, hashed_password) = state
+        .password_manager
+        .hash(&mut state.rng(), Zeroizing::new(b"password".to_vec()))
+        .await
+        .unwrap();
+    let mut repo = state.repository().await.unwrap();
+    repo.user_password()
+        .add(
+            &mut state.rng(),
+            &state.clock,
+            &user,
+            version,
+            hashed_password,
+            None,
+        )
+        .await
+        .unwrap();
+    repo.save().await.unwrap();
+
+    assert_eq!(
+        remove_upstream_link(&state, &access_token, &second).await,
+        "REMOVED"
+    );
+    assert!(!link_exists(&state, &second).await);
+}
+
+/// Test that users can't remove the upstream links of other users
+#[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+async fn test_remove_upstream_link_of_other_user(pool: PgPool) {
+    setup();
+    let state = TestState::from_pool(pool).await.unwrap();
+
+    let client = create_test_client(&state).await;
+    let alice = create_test_user(&state, "alice").await;
+    let bob = create_test_user(&state, "bob").await;
+    let access_token = start_oauth_session(&state, &client, &alice, Scope::from_iter([GRAPHQL]))
+        .await
+        .access_token;
+
+    // Bob can sign in with another link, so only the ownership check prevents
+    // the removal
+    let link = add_upstream_link(&state, &bob, "bob").await;
+    add_upstream_link(&state, &bob, "bob-other").await;
+
+    assert_eq!(
+        remove_upstream_link(&state, &access_token, &link).await,
+        "NOT_FOUND"
+    );
+    assert!(link_exists(&state, &link).await);
+}
@@ -47,6 +47,7 @@ use tower::util::AndThenLayer;
 use tower_http::cors::{Any, CorsLayer};
 
 mod admin;
+pub mod bulk_users;
 mod compat;
 mod graphql;
 mod health;
@@ -57,10 +58,13 @@ mod views;
 
 mod activity_tracker;
 mod captcha;
+mod login_lockout;
+mod new_sign_in;
 mod preferred_language;
 mod rate_limit;
 #[cfg(test)]
 mod test_utils;
+mod username;
 
 /// Implement `From<E>` for `RouteError`, for "internal server error" kind of
 /// errors.
@@ -88,7 +92,7 @@ pub use self::{
     },
     preferred_language::PreferredLanguage,
     rate_limit::{Limiter, RequesterFingerprint},
-    upstream_oauth2::cache::MetadataCache,
+    upstream_oauth2::{cache::MetadataCache, tokens::UpstreamTokenRefresher},
 };
 
 pub fn healthcheck_router<S>() -> Router<S>
@@ -180,6 +184,7 @@ where
     S: Clone + Send + Sync + 'static,
     Keystore: FromRef<S>,
     UrlBuilder: FromRef<S>,
+    MetadataCache: FromRef<S>,
     BoxRepository: FromRequestParts<S>,
     ActivityTracker: FromRequestParts<S>,
     BoundActivityTracker: FromRequestParts<S>,
@@ -187,6 +192,7 @@ where
     reqwest::Client: FromRef<S>,
     SiteConfig: FromRef<S>,
     BoxHomeserverConnection: FromRef<S>,
+    PgPool: FromRef<S>,
     BoxClock: FromRequestParts<S>,
     BoxRng: FromRequestParts<S>,
     Policy: FromRequestParts<S>,
@@ -221,6 +227,14 @@ where
             mas_router::OAuth2DeviceAuthorizationEndpoint::route(),
             post(self::oauth2::device::authorize::post),
         )
+        .route(
+            mas_router::UpstreamOAuth2TokenExchange::route(),
+            post(self::upstream_oauth2::token_exchange::post),
+        )
+        .route(
+            mas_router::UpstreamOAuth2BackchannelLogout::route(),
+            post(self::upstream_oauth2::backchannel_logout::post),
+        )
         .layer(
             CorsLayer::new()
                 .allow_origin(Any)
@@ -405,6 +419,10 @@ where
             get(self::upstream_oauth2::callback::handler)
                 .post(self::upstream_oauth2::callback::handler),
         )
+        .route(
+            mas_router::UpstreamOAuth2FrontchannelLogout::route(),
+            get(self::upstream_oauth2::frontchannel_logout::get),
+        )
         .route(
             mas_router::UpstreamOAuth2Link::route(),
             get(self::upstream_oauth2::link::get).post(self::upstream_oauth2::link::post),
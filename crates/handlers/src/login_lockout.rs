@@ -0,0 +1,101 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+//! Temporary lockout of accounts after repeated failed login attempts
+//!
+//! Unlike the [`crate::Limiter`], failed attempts are tracked in the
+//! database, so that they are shared between all the instances of the service.
+
+use mas_data_model::{LoginLockoutConfig, User};
+use mas_storage::{
+    job::{JobRepositoryExt, SendLoginLockoutEmailJob},
+    Clock, RepositoryAccess,
+};
+
+/// Check whether the [`User`] is currently locked out
+///
+/// # Errors
+///
+/// Returns an error if the repository fails
+pub(crate) async fn is_locked_out<R: RepositoryAccess>(
+    config: Option<&LoginLockoutConfig>,
+    clock: &impl Clock,
+    repo: &mut R,
+    user: &User,
+) -> Result<bool, R::Error> {
+    if config.is_none() {
+        return Ok(false);
+    }
+
+    let lockout = repo.user_login_lockout().lookup(user).await?;
+    Ok(lockout.is_some_and(|lockout| lockout.is_locked(clock.now())))
+}
+
+/// Record a failed login attempt on the [`User`] account, locking it out and
+/// notifying the user if there were too many of them
+///
+/// The changes are only persisted once the repository is saved.
+///
+/// # Errors
+///
+/// Returns an error if the repository fails
+pub(crate) async fn record_failure<R: RepositoryAccess>(
+    config: Option<&LoginLockoutConfig>,
+    clock: &impl Clock,
+    repo: &mut R,
+    user: &User,
+    language: Option<String>,
+) -> Result<(), R::Error> {
+    let Some(config) = config else {
+        return Ok(());
+    };
+
+    let lockout = repo
+        .user_login_lockout()
+        .record_failure(clock, user, config.window)
+        .await?;
+
+    if lockout.locked_until.is_some() || lockout.failed_attempts < config.max_failed_attempts {
+        return Ok(());
+    }
+
+    let locked_until = clock.now() + config.duration;
+    tracing::warn!(
+        user.id = %user.id,
+        failed_attempts = lockout.failed_attempts,
+        %locked_until,
+        "Too many failed login attempts, locking the account out",
+    );
+
+    repo.user_login_lockout()
+        .lock(lockout, locked_until)
+        .await?;
+
+    let mut job = SendLoginLockoutEmailJob::new(user);
+    if let Some(language) = language {
+        job = job.with_language(language);
+    }
+    repo.job().schedule_job(job).await?;
+
+    Ok(())
+}
+
+/// Forget the failed login attempts on the [`User`] account after a
+/// successful login
+///
+/// # Errors
+///
+/// Returns an error if the repository fails
+pub(crate) async fn clear<R: RepositoryAccess>(
+    config: Option<&LoginLockoutConfig>,
+    repo: &mut R,
+    user: &User,
+) -> Result<(), R::Error> {
+    if config.is_some() {
+        repo.user_login_lockout().clear(user).await?;
+    }
+
+    Ok(())
+}
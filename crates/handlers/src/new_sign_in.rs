@@ -0,0 +1,123 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+//! Notify users by email when someone signs in to their account from a device
+//! or a location which wasn't seen before
+
+use std::net::IpAddr;
+
+use mas_data_model::{BrowserSession, User, UserAgent};
+use mas_storage::{
+    app_session::AppSessionFilter,
+    job::{JobRepositoryExt, NewSignInSession, SendNewSignInEmailJob},
+    user::BrowserSessionFilter,
+    RepositoryAccess,
+};
+
+/// Check whether the [`User`] already has a session with the same user agent
+/// and IP address
+///
+/// This must be called before the new session is created, so that it isn't
+/// taken into account.
+///
+/// # Errors
+///
+/// Returns an error if the repository fails
+pub(crate) async fn is_familiar<R: RepositoryAccess>(
+    repo: &mut R,
+    user: &User,
+    user_agent: Option<&UserAgent>,
+    ip: Option<IpAddr>,
+) -> Result<bool, R::Error> {
+    // If we know nothing about the client, there is nothing to compare with
+    if user_agent.is_none() && ip.is_none() {
+        return Ok(true);
+    }
+
+    let mut browser_session_filter = BrowserSessionFilter::new().for_user(user);
+    let mut app_session_filter = AppSessionFilter::new().for_user(user);
+
+    if let Some(user_agent) = user_agent {
+        browser_session_filter = browser_session_filter.with_user_agent(&user_agent.raw);
+        app_session_filter = app_session_filter.with_user_agent(&user_agent.raw);
+    }
+
+    if let Some(ip) = ip {
+        browser_session_filter = browser_session_filter.with_last_active_ip(ip);
+        app_session_filter = app_session_filter.with_last_active_ip(ip);
+    }
+
+    if repo.browser_session().count(browser_session_filter).await? > 0 {
+        return Ok(true);
+    }
+
+    let count = repo.app_session().count(app_session_filter).await?;
+    Ok(count > 0)
+}
+
+/// Check whether a session started from an existing [`BrowserSession`] comes
+/// from a familiar device or location
+///
+/// The browser session was already checked when it was created, so this only
+/// looks further if it is now used from another IP address.
+///
+/// # Errors
+///
+/// Returns an error if the repository fails
+pub(crate) async fn is_familiar_for_browser_session<R: RepositoryAccess>(
+    repo: &mut R,
+    browser_session: &BrowserSession,
+    ip: Option<IpAddr>,
+) -> Result<bool, R::Error> {
+    if ip.is_none()
+        || browser_session.last_active_ip.is_none()
+        || browser_session.last_active_ip == ip
+    {
+        return Ok(true);
+    }
+
+    is_familiar(
+        repo,
+        &browser_session.user,
+        browser_session.user_agent.as_ref(),
+        ip,
+    )
+    .await
+}
+
+/// Schedule an email telling the [`User`] about a new sign-in to their
+/// account
+///
+/// # Errors
+///
+/// Returns an error if the repository fails
+pub(crate) async fn notify<R: RepositoryAccess>(
+    repo: &mut R,
+    user: &User,
+    session: NewSignInSession,
+    ip: Option<IpAddr>,
+    location: Option<&str>,
+    language: Option<String>,
+) -> Result<(), R::Error> {
+    tracing::info!(
+        user.id = %user.id,
+        ?session,
+        "Sign-in from an unfamiliar device or location, notifying the user",
+    );
+
+    let mut job = SendNewSignInEmailJob::new(user, session);
+    if let Some(ip) = ip {
+        job = job.with_ip(ip);
+    }
+    if let Some(location) = location {
+        job = job.with_location(location.to_owned());
+    }
+    if let Some(language) = language {
+        job = job.with_language(language);
+    }
+    repo.job().schedule_job(job).await?;
+
+    Ok(())
+}
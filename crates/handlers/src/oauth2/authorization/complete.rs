@@ -10,24 +10,28 @@ use axum::{
 };
 use hyper::StatusCode;
 use mas_axum_utils::{cookies::CookieJar, csrf::CsrfExt, sentry::SentryEventID, SessionInfoExt};
-use mas_data_model::{AuthorizationGrant, BrowserSession, Client, Device};
+use mas_data_model::{AuditLogEventKind, AuthorizationGrant, BrowserSession, Client, Device};
 use mas_keystore::Keystore;
 use mas_policy::{EvaluationResult, Policy};
 use mas_router::{PostAuthAction, UrlBuilder};
 use mas_storage::{
+    audit_log::NewAuditLogEvent,
+    job::{JobRepositoryExt, NewSignInSession, SendWebhookEventJob},
     oauth2::{OAuth2AuthorizationGrantRepository, OAuth2ClientRepository, OAuth2SessionRepository},
     user::BrowserSessionRepository,
     BoxClock, BoxRepository, BoxRng, Clock, RepositoryAccess,
 };
 use mas_templates::{PolicyViolationContext, TemplateContext, Templates};
 use oauth2_types::requests::AuthorizationResponse;
+use serde_json::json;
 use thiserror::Error;
 use tracing::warn;
 use ulid::Ulid;
 
 use super::callback::CallbackDestination;
 use crate::{
-    impl_from_error_for_route, oauth2::generate_id_token, BoundActivityTracker, PreferredLanguage,
+    impl_from_error_for_route, new_sign_in, oauth2::generate_id_token,
+    upstream_oauth2::user_groups, BoundActivityTracker, PreferredLanguage,
 };
 
 #[derive(Debug, Error)]
@@ -195,6 +199,7 @@ impl_from_error_for_route!(GrantCompletionError: mas_policy::LoadError);
 impl_from_error_for_route!(GrantCompletionError: mas_policy::EvaluationError);
 impl_from_error_for_route!(GrantCompletionError: super::super::IdTokenSignatureError);
 
+#[allow(clippy::too_many_lines)]
 pub(crate) async fn complete(
     rng: &mut (impl rand::RngCore + rand::CryptoRng + Send),
     clock: &impl Clock,
@@ -225,11 +230,30 @@ pub(crate) async fn complete(
     };
 
     // Run through the policy
+    let groups = user_groups(&mut repo, &browser_session.user).await?;
     let res = policy
-        .evaluate_authorization_grant(&grant, client, &browser_session.user)
+        .evaluate_authorization_grant(&grant, client, &browser_session.user, &groups)
         .await?;
 
     if !res.valid() {
+        repo.audit_log()
+            .add(
+                rng,
+                clock,
+                NewAuditLogEvent::new(AuditLogEventKind::PolicyDenied)
+                    .with_actor(&browser_session.user)
+                    .for_user(&browser_session.user)
+                    .with_ip_address(activity_tracker.ip())
+                    .with_data(json!({
+                        "policy": "authorization_grant",
+                        "client_id": client.id,
+                        "scope": grant.scope.to_string(),
+                        "violations": res.violations,
+                    })),
+            )
+            .await?;
+        repo.save().await?;
+
         return Err(GrantCompletionError::PolicyViolation(grant, res));
     }
 
@@ -250,12 +274,53 @@ pub(crate) async fn complete(
         return Err(GrantCompletionError::RequiresConsent);
     }
 
+    // This has to be checked before the new session is created
+    let ip = activity_tracker.ip();
+    let familiar =
+        new_sign_in::is_familiar_for_browser_session(&mut repo, browser_session, ip).await?;
+
     // All good, let's start the session
     let session = repo
         .oauth2_session()
         .add_from_browser_session(rng, clock, client, browser_session, grant.scope.clone())
         .await?;
 
+    if !familiar {
+        new_sign_in::notify(
+            &mut repo,
+            &browser_session.user,
+            NewSignInSession::OAuth2(session.id),
+            ip,
+            activity_tracker.location(),
+            None,
+        )
+        .await?;
+    }
+
+    repo.audit_log()
+        .add(
+            rng,
+            clock,
+            NewAuditLogEvent::new(AuditLogEventKind::SessionCreated)
+                .with_actor(&browser_session.user)
+                .for_user(&browser_session.user)
+                .with_ip_address(activity_tracker.ip())
+                .with_data(json!({
+                    "session_type": "oauth2",
+                    "session_id": session.id,
+                    "client_id": client.id,
+                    "grant_type": "authorization_code",
+                })),
+        )
+        .await?;
+
+    repo.job()
+        .schedule_job(SendWebhookEventJob::session_started(
+            browser_session.user.id,
+            NewSignInSession::OAuth2(session.id),
+        ))
+        .await?;
+
     let grant = repo
         .oauth2_authorization_grant()
         .fulfill(clock, &session, grant)
@@ -15,18 +15,23 @@ use mas_axum_utils::{
     sentry::SentryEventID,
     SessionInfoExt,
 };
-use mas_data_model::{AuthorizationGrantStage, Device};
+use mas_data_model::{AuditLogEventKind, AuthorizationGrantStage, Device};
 use mas_policy::Policy;
 use mas_router::{PostAuthAction, UrlBuilder};
 use mas_storage::{
+    audit_log::NewAuditLogEvent,
     oauth2::{OAuth2AuthorizationGrantRepository, OAuth2ClientRepository},
     BoxClock, BoxRepository, BoxRng,
 };
 use mas_templates::{ConsentContext, PolicyViolationContext, TemplateContext, Templates};
+use serde_json::json;
 use thiserror::Error;
 use ulid::Ulid;
 
-use crate::{impl_from_error_for_route, BoundActivityTracker, PreferredLanguage};
+use crate::{
+    impl_from_error_for_route, upstream_oauth2::user_groups, BoundActivityTracker,
+    PreferredLanguage,
+};
 
 #[derive(Debug, Error)]
 pub enum RouteError {
@@ -110,8 +115,9 @@ pub(crate) async fn get(
 
         let (csrf_token, cookie_jar) = cookie_jar.csrf_token(&clock, &mut rng);
 
+        let groups = user_groups(&mut repo, &session.user).await?;
         let res = policy
-            .evaluate_authorization_grant(&grant, &client, &session.user)
+            .evaluate_authorization_grant(&grant, &client, &session.user, &groups)
             .await?;
 
         if res.valid() {
@@ -184,11 +190,30 @@ pub(crate) async fn post(
         .await?
         .ok_or(RouteError::NoSuchClient)?;
 
+    let groups = user_groups(&mut repo, &session.user).await?;
     let res = policy
-        .evaluate_authorization_grant(&grant, &client, &session.user)
+        .evaluate_authorization_grant(&grant, &client, &session.user, &groups)
         .await?;
 
     if !res.valid() {
+        repo.audit_log()
+            .add(
+                &mut rng,
+                &clock,
+                NewAuditLogEvent::new(AuditLogEventKind::PolicyDenied)
+                    .with_actor(&session.user)
+                    .for_user(&session.user)
+                    .with_ip_address(activity_tracker.ip())
+                    .with_data(json!({
+                        "policy": "authorization_grant",
+                        "client_id": client.id,
+                        "scope": grant.scope.to_string(),
+                        "violations": res.violations,
+                    })),
+            )
+            .await?;
+        repo.save().await?;
+
         return Err(RouteError::PolicyViolation);
     }
 
@@ -16,15 +16,17 @@ use mas_axum_utils::{
     csrf::{CsrfExt, ProtectedForm},
     FancyError, SessionInfoExt,
 };
+use mas_data_model::AuditLogEventKind;
 use mas_policy::Policy;
 use mas_router::UrlBuilder;
-use mas_storage::{BoxClock, BoxRepository, BoxRng};
+use mas_storage::{audit_log::NewAuditLogEvent, BoxClock, BoxRepository, BoxRng};
 use mas_templates::{DeviceConsentContext, PolicyViolationContext, TemplateContext, Templates};
 use serde::Deserialize;
+use serde_json::json;
 use tracing::warn;
 use ulid::Ulid;
 
-use crate::{BoundActivityTracker, PreferredLanguage};
+use crate::{upstream_oauth2::user_groups, BoundActivityTracker, PreferredLanguage};
 
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "lowercase")]
@@ -82,8 +84,9 @@ pub(crate) async fn get(
         .context("Client not found")?;
 
     // Evaluate the policy
+    let groups = user_groups(&mut repo, &session.user).await?;
     let res = policy
-        .evaluate_device_code_grant(&grant, &client, &session.user)
+        .evaluate_device_code_grant(&grant, &client, &session.user, &groups)
         .await?;
     if !res.valid() {
         warn!(violation = ?res, "Device code grant for client {} denied by policy", client.id);
@@ -157,12 +160,31 @@ pub(crate) async fn post(
         .context("Client not found")?;
 
     // Evaluate the policy
+    let groups = user_groups(&mut repo, &session.user).await?;
     let res = policy
-        .evaluate_device_code_grant(&grant, &client, &session.user)
+        .evaluate_device_code_grant(&grant, &client, &session.user, &groups)
         .await?;
     if !res.valid() {
         warn!(violation = ?res, "Device code grant for client {} denied by policy", client.id);
 
+        repo.audit_log()
+            .add(
+                &mut rng,
+                &clock,
+                NewAuditLogEvent::new(AuditLogEventKind::PolicyDenied)
+                    .with_actor(&session.user)
+                    .for_user(&session.user)
+                    .with_ip_address(activity_tracker.ip())
+                    .with_data(json!({
+                        "policy": "device_code_grant",
+                        "client_id": client.id,
+                        "scope": grant.scope.to_string(),
+                        "violations": res.violations,
+                    })),
+            )
+            .await?;
+        repo.save().await?;
+
         let (csrf_token, cookie_jar) = cookie_jar.csrf_token(&clock, &mut rng);
         let ctx = PolicyViolationContext::for_device_code_grant(grant, client)
             .with_session(session)
@@ -7,10 +7,13 @@
 use axum::{extract::State, response::IntoResponse, Json};
 use hyper::StatusCode;
 use mas_axum_utils::sentry::SentryEventID;
+use mas_data_model::AuditLogEventKind;
 use mas_iana::oauth::OAuthClientAuthenticationMethod;
 use mas_keystore::Encrypter;
 use mas_policy::{Policy, Violation};
-use mas_storage::{oauth2::OAuth2ClientRepository, BoxClock, BoxRepository, BoxRng};
+use mas_storage::{
+    audit_log::NewAuditLogEvent, oauth2::OAuth2ClientRepository, BoxClock, BoxRepository, BoxRng,
+};
 use oauth2_types::{
     errors::{ClientError, ClientErrorCode},
     registration::{
@@ -21,11 +24,12 @@ use oauth2_types::{
 use psl::Psl;
 use rand::distributions::{Alphanumeric, DistString};
 use serde::Serialize;
+use serde_json::json;
 use thiserror::Error;
 use tracing::info;
 use url::Url;
 
-use crate::impl_from_error_for_route;
+use crate::{impl_from_error_for_route, BoundActivityTracker};
 
 #[derive(Debug, Error)]
 pub(crate) enum RouteError {
@@ -195,6 +199,7 @@ pub(crate) async fn post(
     clock: BoxClock,
     mut repo: BoxRepository,
     mut policy: Policy,
+    activity_tracker: BoundActivityTracker,
     State(encrypter): State<Encrypter>,
     body: Result<Json<ClientMetadata>, axum::extract::rejection::JsonRejection>,
 ) -> Result<impl IntoResponse, RouteError> {
@@ -246,6 +251,22 @@ pub(crate) async fn post(
 
     let res = policy.evaluate_client_registration(&metadata).await?;
     if !res.valid() {
+        repo.audit_log()
+            .add(
+                &mut rng,
+                &clock,
+                NewAuditLogEvent::new(AuditLogEventKind::PolicyDenied)
+                    .with_ip_address(activity_tracker.ip())
+                    .with_data(json!({
+                        "policy": "client_registration",
+                        "client_name": metadata.client_name.as_ref().map(Localized::non_localized),
+                        "redirect_uris": metadata.redirect_uris(),
+                        "violations": res.violations,
+                    })),
+            )
+            .await?;
+        repo.save().await?;
+
         return Err(RouteError::PolicyDenied(res.violations));
     }
 
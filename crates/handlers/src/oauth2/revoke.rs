@@ -10,17 +10,19 @@ use mas_axum_utils::{
     client_authorization::{ClientAuthorization, CredentialsVerificationError},
     sentry::SentryEventID,
 };
-use mas_data_model::TokenType;
+use mas_data_model::{AuditLogEventKind, TokenType};
 use mas_iana::oauth::OAuthTokenTypeHint;
 use mas_keystore::Encrypter;
 use mas_storage::{
-    job::{JobRepositoryExt, SyncDevicesJob},
-    BoxClock, BoxRepository, RepositoryAccess,
+    audit_log::NewAuditLogEvent,
+    job::{JobRepositoryExt, NewSignInSession, SendWebhookEventJob, SyncDevicesJob},
+    BoxClock, BoxRepository, BoxRng, RepositoryAccess,
 };
 use oauth2_types::{
     errors::{ClientError, ClientErrorCode},
     requests::RevocationRequest,
 };
+use serde_json::json;
 use thiserror::Error;
 
 use crate::{impl_from_error_for_route, BoundActivityTracker};
@@ -109,6 +111,7 @@ impl From<mas_data_model::TokenFormatError> for RouteError {
     err,
 )]
 pub(crate) async fn post(
+    mut rng: BoxRng,
     clock: BoxClock,
     State(http_client): State<reqwest::Client>,
     mut repo: BoxRepository,
@@ -212,6 +215,28 @@ pub(crate) async fn post(
         repo.job().schedule_job(SyncDevicesJob::new(&user)).await?;
     }
 
+    let mut event = NewAuditLogEvent::new(AuditLogEventKind::SessionFinished)
+        .with_ip_address(activity_tracker.ip())
+        .with_data(json!({
+            "session_type": "oauth2",
+            "session_id": session.id,
+            "client_id": client.id,
+            "reason": "revoked",
+        }));
+    if let Some(user_id) = session.user_id {
+        event = event.for_user_id(user_id);
+    }
+    repo.audit_log().add(&mut rng, &clock, event).await?;
+
+    if let Some(user_id) = session.user_id {
+        repo.job()
+            .schedule_job(SendWebhookEventJob::session_ended(
+                user_id,
+                NewSignInSession::OAuth2(session.id),
+            ))
+            .await?;
+    }
+
     // Now that we checked everything, we can end the session.
     repo.oauth2_session().finish(&clock, session).await?;
 
@@ -14,7 +14,8 @@ use mas_axum_utils::{
     sentry::SentryEventID,
 };
 use mas_data_model::{
-    AuthorizationGrantStage, Client, Device, DeviceCodeGrantState, SiteConfig, TokenType, UserAgent,
+    AuditLogEventKind, AuthorizationGrantStage, Client, Device, DeviceCodeGrantState, SiteConfig,
+    TokenType, UserAgent,
 };
 use mas_keystore::{Encrypter, Keystore};
 use mas_matrix::BoxHomeserverConnection;
@@ -22,6 +23,8 @@ use mas_oidc_client::types::scope::ScopeToken;
 use mas_policy::Policy;
 use mas_router::UrlBuilder;
 use mas_storage::{
+    audit_log::NewAuditLogEvent,
+    job::{JobRepositoryExt, NewSignInSession, SendWebhookEventJob},
     oauth2::{
         OAuth2AccessTokenRepository, OAuth2AuthorizationGrantRepository,
         OAuth2RefreshTokenRepository, OAuth2SessionRepository,
@@ -38,12 +41,13 @@ use oauth2_types::{
     },
     scope,
 };
+use serde_json::json;
 use thiserror::Error;
 use tracing::debug;
 use ulid::Ulid;
 
 use super::{generate_id_token, generate_token_pair};
-use crate::{impl_from_error_for_route, BoundActivityTracker};
+use crate::{impl_from_error_for_route, new_sign_in, BoundActivityTracker};
 
 #[derive(Debug, Error)]
 pub(crate) enum RouteError {
@@ -591,6 +595,23 @@ async fn client_credentials_grant(
         .evaluate_client_credentials_grant(&scope, client)
         .await?;
     if !res.valid() {
+        repo.audit_log()
+            .add(
+                rng,
+                clock,
+                NewAuditLogEvent::new(AuditLogEventKind::PolicyDenied)
+                    .with_ip_address(activity_tracker.ip())
+                    .with_user_agent(user_agent.as_ref())
+                    .with_data(json!({
+                        "policy": "client_credentials_grant",
+                        "client_id": client.id,
+                        "scope": scope.to_string(),
+                        "violations": res.violations,
+                    })),
+            )
+            .await?;
+        repo.save().await?;
+
         return Err(RouteError::DeniedByPolicy(res.violations));
     }
 
@@ -600,6 +621,22 @@ async fn client_credentials_grant(
         .add_from_client_credentials(rng, clock, client, scope)
         .await?;
 
+    repo.audit_log()
+        .add(
+            rng,
+            clock,
+            NewAuditLogEvent::new(AuditLogEventKind::SessionCreated)
+                .with_ip_address(activity_tracker.ip())
+                .with_user_agent(user_agent.as_ref())
+                .with_data(json!({
+                    "session_type": "oauth2",
+                    "session_id": session.id,
+                    "client_id": client.id,
+                    "grant_type": "client_credentials",
+                })),
+        )
+        .await?;
+
     if let Some(user_agent) = user_agent {
         session = repo
             .oauth2_session()
@@ -632,6 +669,7 @@ async fn client_credentials_grant(
     Ok((params, repo))
 }
 
+#[allow(clippy::too_many_lines)]
 async fn device_code_grant(
     rng: &mut BoxRng,
     clock: &impl Clock,
@@ -689,12 +727,55 @@ async fn device_code_grant(
         .await?
         .ok_or(RouteError::NoSuchBrowserSession)?;
 
+    // The device may be different from the browser which approved the grant. This
+    // has to be checked before the new session is created.
+    let ip = activity_tracker.ip();
+    let familiar =
+        new_sign_in::is_familiar(&mut repo, &browser_session.user, user_agent.as_ref(), ip).await?;
+
     // Start the session
     let mut session = repo
         .oauth2_session()
         .add_from_browser_session(rng, clock, client, &browser_session, grant.scope)
         .await?;
 
+    repo.audit_log()
+        .add(
+            rng,
+            clock,
+            NewAuditLogEvent::new(AuditLogEventKind::SessionCreated)
+                .with_actor(&browser_session.user)
+                .for_user(&browser_session.user)
+                .with_ip_address(ip)
+                .with_user_agent(user_agent.as_ref())
+                .with_data(json!({
+                    "session_type": "oauth2",
+                    "session_id": session.id,
+                    "client_id": client.id,
+                    "grant_type": "device_code",
+                })),
+        )
+        .await?;
+
+    repo.job()
+        .schedule_job(SendWebhookEventJob::session_started(
+            browser_session.user.id,
+            NewSignInSession::OAuth2(session.id),
+        ))
+        .await?;
+
+    if !familiar {
+        new_sign_in::notify(
+            &mut repo,
+            &browser_session.user,
+            NewSignInSession::OAuth2(session.id),
+            ip,
+            activity_tracker.location(),
+            None,
+        )
+        .await?;
+    }
+
     // XXX: should we get the user agent from the device code grant instead?
     if let Some(user_agent) = user_agent {
         session = repo
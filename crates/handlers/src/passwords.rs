@@ -8,10 +8,22 @@ use std::{collections::HashMap, sync::Arc};
 
 use anyhow::Context;
 use argon2::{password_hash::SaltString, Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use camino::Utf8Path;
 use futures_util::future::OptionFuture;
+use mas_data_model::{Password, User};
+use mas_http::RequestBuilderExt;
+use mas_matrix::{BoxHomeserverConnection, ProvisionRequest};
+use mas_storage::{
+    job::{JobRepositoryExt, SendWebhookEventJob},
+    user::{UserEmailRepository, UserPasswordRepository, UserRepository},
+    Clock, RepositoryAccess,
+};
 use pbkdf2::Pbkdf2;
 use rand::{CryptoRng, Rng, RngCore, SeedableRng};
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
 use thiserror::Error;
+use url::Url;
 use zeroize::Zeroizing;
 use zxcvbn::zxcvbn;
 
@@ -24,6 +36,8 @@ pub struct PasswordManagerDisabledError;
 #[derive(Clone)]
 pub struct PasswordManager {
     inner: Option<Arc<InnerPasswordManager>>,
+    external: Option<Arc<ExternalPasswordProvider>>,
+    breached_passwords: Option<Arc<BreachedPasswords>>,
 }
 
 struct InnerPasswordManager {
@@ -66,13 +80,41 @@ impl PasswordManager {
                 current_version,
                 other_hashers,
             })),
+            external: None,
+            breached_passwords: None,
         })
     }
 
     /// Creates a new disabled password manager
     #[must_use]
     pub const fn disabled() -> Self {
-        Self { inner: None }
+        Self {
+            inner: None,
+            external: None,
+            breached_passwords: None,
+        }
+    }
+
+    /// Set the external password provider, which is asked to verify
+    /// credentials before the local password database
+    #[must_use]
+    pub fn with_external_provider(mut self, provider: ExternalPasswordProvider) -> Self {
+        self.external = Some(Arc::new(provider));
+        self
+    }
+
+    /// Get the external password provider, if any
+    #[must_use]
+    pub fn external_provider(&self) -> Option<&ExternalPasswordProvider> {
+        self.external.as_deref()
+    }
+
+    /// Set the list of known breached passwords, which new passwords are
+    /// checked against
+    #[must_use]
+    pub fn with_breached_passwords(mut self, breached_passwords: BreachedPasswords) -> Self {
+        self.breached_passwords = Some(Arc::new(breached_passwords));
+        self
     }
 
     /// Checks if the password manager is enabled or not
@@ -90,6 +132,54 @@ impl PasswordManager {
         self.inner.clone().ok_or(PasswordManagerDisabledError)
     }
 
+    /// Get the version of the default hashing scheme
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the password manager is disabled
+    pub fn current_version(&self) -> Result<SchemeVersion, PasswordManagerDisabledError> {
+        Ok(self.get_inner()?.current_version)
+    }
+
+    /// Returns true if hashes from the given scheme version can be verified,
+    /// either because it is the default scheme or one of the older ones.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the password manager is disabled
+    pub fn is_supported_version(
+        &self,
+        version: SchemeVersion,
+    ) -> Result<bool, PasswordManagerDisabledError> {
+        let inner = self.get_inner()?;
+        Ok(inner.current_version == version || inner.other_hashers.contains_key(&version))
+    }
+
+    /// Returns true if the given hash is well-formed for the hashing scheme of
+    /// the given version, without verifying it against any password.
+    ///
+    /// Returns false if the version is not supported.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the password manager is disabled
+    pub fn is_valid_hash(
+        &self,
+        version: SchemeVersion,
+        hashed_password: &str,
+    ) -> Result<bool, PasswordManagerDisabledError> {
+        let inner = self.get_inner()?;
+        let hasher = if inner.current_version == version {
+            &inner.current_hasher
+        } else if let Some(hasher) = inner.other_hashers.get(&version) {
+            hasher
+        } else {
+            return Ok(false);
+        };
+
+        Ok(hasher.is_valid_hash(hashed_password))
+    }
+
     /// Returns true if and only if the given password satisfies the minimum
     /// complexity requirements.
     ///
@@ -102,6 +192,15 @@ impl PasswordManager {
         Ok(u8::from(score.score()) >= inner.minimum_complexity)
     }
 
+    /// Returns true if the given password is in the list of known breached
+    /// passwords. Always returns false if no list was loaded.
+    #[must_use]
+    pub fn is_password_breached(&self, password: &str) -> bool {
+        self.breached_passwords
+            .as_ref()
+            .is_some_and(|list| list.contains(password))
+    }
+
     /// Hash a password with the default hashing scheme.
     /// Returns the version of the hashing scheme used and the hashed password.
     ///
@@ -203,6 +302,378 @@ impl PasswordManager {
     }
 }
 
+/// A list of SHA-1 hashes of known breached passwords, used to reject new
+/// passwords which appear in credential-stuffing lists
+#[derive(Debug, Default)]
+pub struct BreachedPasswords {
+    /// Sorted and deduplicated list of hashes
+    hashes: Vec<[u8; 20]>,
+}
+
+impl BreachedPasswords {
+    /// Parse a list of hashes in the format used by the Have I Been Pwned
+    /// password downloads.
+    ///
+    /// Each line starts with the hex-encoded SHA-1 hash of a password, which
+    /// may be followed by a colon and the number of times it was seen in
+    /// breaches. Empty lines are ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a line doesn't start with a valid SHA-1 hash
+    pub fn parse(input: &str) -> Result<Self, anyhow::Error> {
+        let mut hashes = Vec::new();
+
+        for (index, line) in input.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let hex = line.split_once(':').map_or(line, |(hex, _count)| hex);
+            let hash = decode_sha1(hex)
+                .with_context(|| format!("Invalid SHA-1 hash on line {}", index + 1))?;
+            hashes.push(hash);
+        }
+
+        // The files are usually sorted already, but don't rely on it
+        hashes.sort_unstable();
+        hashes.dedup();
+
+        Ok(Self { hashes })
+    }
+
+    /// Load a list of hashes from a file on disk, see [`Self::parse`] for the
+    /// expected format
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file could not be read or is invalid
+    pub async fn load(path: &Utf8Path) -> Result<Self, anyhow::Error> {
+        let input = tokio::fs::read_to_string(path).await?;
+        tokio::task::spawn_blocking(move || Self::parse(&input)).await?
+    }
+
+    /// Number of hashes in the list
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+    }
+
+    /// Whether the list is empty
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+
+    /// Check if the given password is in the list
+    #[must_use]
+    pub fn contains(&self, password: &str) -> bool {
+        let hash: [u8; 20] = Sha1::digest(password.as_bytes()).into();
+        self.hashes.binary_search(&hash).is_ok()
+    }
+}
+
+fn decode_sha1(hex: &str) -> Option<[u8; 20]> {
+    if hex.len() != 40 || !hex.is_ascii() {
+        return None;
+    }
+
+    let mut hash = [0; 20];
+    for (byte, chunk) in hash.iter_mut().zip(hex.as_bytes().chunks_exact(2)) {
+        let chunk = std::str::from_utf8(chunk).ok()?;
+        *byte = u8::from_str_radix(chunk, 16).ok()?;
+    }
+
+    Some(hash)
+}
+
+/// An external HTTP service which verifies user credentials
+///
+/// The credentials are sent in a POST request as a JSON object with the
+/// `username` and `password` fields, and the service replies with an
+/// [`ExternalPasswordResponse`].
+pub struct ExternalPasswordProvider {
+    http_client: reqwest::Client,
+    url: Url,
+    shared_secret: Option<String>,
+    provision_users: bool,
+    local_fallback: bool,
+}
+
+#[derive(Serialize)]
+struct ExternalPasswordRequest<'a> {
+    username: &'a str,
+    password: &'a str,
+}
+
+/// The reply of an [`ExternalPasswordProvider`]
+#[derive(Debug, Deserialize)]
+pub struct ExternalPasswordResponse {
+    /// Whether the credentials were accepted
+    pub authenticated: bool,
+
+    /// The display name of the user, set when provisioning it
+    #[serde(default)]
+    pub display_name: Option<String>,
+
+    /// The email address of the user, added when provisioning it
+    #[serde(default)]
+    pub email: Option<String>,
+}
+
+impl ExternalPasswordProvider {
+    /// Creates a new [`ExternalPasswordProvider`]
+    #[must_use]
+    pub fn new(http_client: reqwest::Client, url: Url) -> Self {
+        Self {
+            http_client,
+            url,
+            shared_secret: None,
+            provision_users: false,
+            local_fallback: true,
+        }
+    }
+
+    /// Set the shared secret sent as a bearer token to the service
+    #[must_use]
+    pub fn with_shared_secret(mut self, shared_secret: String) -> Self {
+        self.shared_secret = Some(shared_secret);
+        self
+    }
+
+    /// Set whether unknown users accepted by the service should be created
+    #[must_use]
+    pub fn with_provision_users(mut self, provision_users: bool) -> Self {
+        self.provision_users = provision_users;
+        self
+    }
+
+    /// Set whether the local password database should be checked when the
+    /// service did not accept the credentials
+    #[must_use]
+    pub fn with_local_fallback(mut self, local_fallback: bool) -> Self {
+        self.local_fallback = local_fallback;
+        self
+    }
+
+    /// Whether unknown users accepted by the service should be created
+    #[must_use]
+    pub fn provision_users(&self) -> bool {
+        self.provision_users
+    }
+
+    /// Whether the local password database should be checked when the service
+    /// did not accept the credentials
+    #[must_use]
+    pub fn local_fallback(&self) -> bool {
+        self.local_fallback
+    }
+
+    /// Ask the service to verify the given credentials
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request failed, or if the service replied with
+    /// an invalid response
+    #[tracing::instrument(name = "passwords.external.verify", skip_all, fields(url = %self.url))]
+    pub async fn verify(
+        &self,
+        username: &str,
+        password: &[u8],
+    ) -> Result<ExternalPasswordResponse, anyhow::Error> {
+        let password = std::str::from_utf8(password).context("Password is not valid UTF-8")?;
+
+        let mut request = self
+            .http_client
+            .post(self.url.clone())
+            .json(&ExternalPasswordRequest { username, password });
+
+        if let Some(shared_secret) = &self.shared_secret {
+            request = request.bearer_auth(shared_secret);
+        }
+
+        let response = request
+            .send_traced()
+            .await
+            .context("Failed to send request to the external password provider")?
+            .error_for_status()
+            .context("External password provider replied with an error")?
+            .json()
+            .await
+            .context("Invalid response from the external password provider")?;
+
+        Ok(response)
+    }
+}
+
+/// The outcome of checking credentials with the [`ExternalPasswordProvider`]
+pub(crate) enum ExternalVerification {
+    /// The provider accepted the credentials. The user exists locally, and the
+    /// password is its up-to-date local password.
+    Accepted { user: User, user_password: Password },
+
+    /// The provider did not accept the credentials, and the local password
+    /// database should not be checked
+    Rejected,
+
+    /// There is no external provider, or it did not accept the credentials
+    /// and the local password database should be checked. This gives back the
+    /// local user, if any.
+    Skipped(Option<User>),
+}
+
+/// Check the credentials with the external password provider, if any.
+///
+/// If the provider accepts them, this creates and provisions the user if it
+/// doesn't exist yet (if enabled), and makes sure the password is saved in the
+/// local password database, so that the session can be marked as
+/// authenticated by it.
+///
+/// # Errors
+///
+/// Returns an error if the provider could not be reached and the local
+/// password database should not be checked, or if the database or homeserver
+/// operations failed.
+#[allow(clippy::too_many_lines)]
+pub(crate) async fn verify_external(
+    password_manager: &PasswordManager,
+    mut rng: &mut (impl RngCore + CryptoRng + Send),
+    clock: &impl Clock,
+    repo: &mut impl RepositoryAccess,
+    homeserver: &BoxHomeserverConnection,
+    user: Option<User>,
+    username: &str,
+    password: &Zeroizing<Vec<u8>>,
+) -> Result<ExternalVerification, anyhow::Error> {
+    let Some(provider) = password_manager.external_provider() else {
+        return Ok(ExternalVerification::Skipped(user));
+    };
+
+    // Locked or deactivated users can't log in, whatever the provider says
+    if user.as_ref().is_some_and(|user| !user.is_valid()) {
+        return Ok(ExternalVerification::Rejected);
+    }
+
+    let response = match provider.verify(username, password).await {
+        Ok(response) => response,
+        Err(e) if provider.local_fallback() => {
+            tracing::warn!(
+                error = &*e as &dyn std::error::Error,
+                "Failed to verify credentials with the external password provider, falling back to the local password database"
+            );
+            return Ok(ExternalVerification::Skipped(user));
+        }
+        Err(e) => return Err(e),
+    };
+
+    if !response.authenticated {
+        return Ok(if provider.local_fallback() {
+            ExternalVerification::Skipped(user)
+        } else {
+            ExternalVerification::Rejected
+        });
+    }
+
+    let user = if let Some(user) = user {
+        user
+    } else {
+        if !provider.provision_users() {
+            tracing::info!(
+                username,
+                "External password provider accepted an unknown user, but provisioning is disabled"
+            );
+            return Ok(ExternalVerification::Rejected);
+        }
+
+        // Ask the homeserver to make sure the username is valid
+        if !homeserver.is_localpart_available(username).await? {
+            tracing::warn!(
+                username,
+                "External password provider accepted a user which can't be provisioned on the homeserver"
+            );
+            return Ok(ExternalVerification::Rejected);
+        }
+
+        let user = repo
+            .user()
+            .add(&mut rng, clock, username.to_owned())
+            .await?;
+
+        repo.job()
+            .schedule_job(SendWebhookEventJob::user_registered(&user))
+            .await?;
+
+        let mxid = homeserver.mxid(&user.username);
+        let mut request = ProvisionRequest::new(mxid, user.sub.clone());
+
+        if let Some(email) = response.email {
+            // The provider is trusted, so the email is marked as verified
+            let user_email = repo
+                .user_email()
+                .add(&mut rng, clock, &user, email.clone())
+                .await?;
+            let user_email = repo
+                .user_email()
+                .mark_as_verified(clock, user_email)
+                .await?;
+            repo.user_email().set_as_primary(&user_email).await?;
+            repo.job()
+                .schedule_job(SendWebhookEventJob::user_email_verified(&user, &user_email))
+                .await?;
+            request = request.set_emails(vec![email]);
+        }
+
+        if let Some(display_name) = response.display_name {
+            request = request.set_displayname(display_name);
+        }
+
+        // Provision the user right away, as the caller might need to create a device
+        // for it on the homeserver
+        homeserver.provision_user(&request).await?;
+
+        user
+    };
+
+    // Check if the local password is already up-to-date, and save it otherwise
+    let current_password = repo.user_password().active(&user).await?;
+    if let Some(current_password) = &current_password {
+        let verified = password_manager
+            .verify(
+                current_password.version,
+                password.clone(),
+                current_password.hashed_password.clone(),
+            )
+            .await
+            .is_ok();
+
+        if verified && current_password.version == password_manager.current_version()? {
+            return Ok(ExternalVerification::Accepted {
+                user,
+                user_password: current_password.clone(),
+            });
+        }
+    }
+
+    let (version, hashed_password) = password_manager.hash(&mut rng, password.clone()).await?;
+    let user_password = repo
+        .user_password()
+        .add(
+            &mut rng,
+            clock,
+            &user,
+            version,
+            hashed_password,
+            current_password.as_ref(),
+        )
+        .await?;
+
+    Ok(ExternalVerification::Accepted {
+        user,
+        user_password,
+    })
+}
+
 /// A hashing scheme, with an optional pepper
 pub struct Hasher {
     algorithm: Algorithm,
@@ -244,6 +715,10 @@ impl Hasher {
         self.algorithm
             .verify_blocking(hashed_password, password, self.pepper.as_deref())
     }
+
+    fn is_valid_hash(&self, hashed_password: &str) -> bool {
+        self.algorithm.is_valid_hash(hashed_password)
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -254,6 +729,26 @@ enum Algorithm {
 }
 
 impl Algorithm {
+    /// Check that the hash is in the format this algorithm produces: a bcrypt
+    /// hash, or a PHC string with the right algorithm identifier
+    fn is_valid_hash(self, hashed_password: &str) -> bool {
+        match self {
+            Self::Bcrypt { .. } => hashed_password.parse::<bcrypt::HashParts>().is_ok(),
+
+            Self::Argon2id => PasswordHash::new(hashed_password).is_ok_and(|hash| {
+                argon2::Algorithm::try_from(hash.algorithm) == Ok(argon2::Algorithm::Argon2id)
+                    && hash.salt.is_some()
+                    && hash.hash.is_some()
+            }),
+
+            Self::Pbkdf2 => PasswordHash::new(hashed_password).is_ok_and(|hash| {
+                pbkdf2::Algorithm::try_from(hash.algorithm).is_ok()
+                    && hash.salt.is_some()
+                    && hash.hash.is_some()
+            }),
+        }
+    }
+
     fn hash_blocking<R: CryptoRng + RngCore>(
         self,
         mut rng: R,
@@ -451,6 +946,30 @@ mod tests {
         assert!(alg.verify_blocking(&hash, password, Some(pepper)).is_err());
     }
 
+    #[test]
+    fn hash_format() {
+        let mut rng = rand_chacha::ChaChaRng::seed_from_u64(42);
+        let algorithms = [
+            Algorithm::Bcrypt { cost: Some(4) },
+            Algorithm::Argon2id,
+            Algorithm::Pbkdf2,
+        ];
+
+        let hashes = algorithms.map(|alg| alg.hash_blocking(&mut rng, b"hunter2", None).unwrap());
+
+        // Each algorithm only accepts its own hashes
+        for (i, alg) in algorithms.into_iter().enumerate() {
+            for (j, hash) in hashes.iter().enumerate() {
+                assert_eq!(alg.is_valid_hash(hash), i == j, "{alg:?} with {hash}");
+            }
+
+            assert!(!alg.is_valid_hash(""));
+            assert!(!alg.is_valid_hash("hunter2"));
+            assert!(!alg.is_valid_hash("$2b$12$tooshort"));
+            assert!(!alg.is_valid_hash("$argon2id$v=19$m=19456,t=2,p=1"));
+        }
+    }
+
     #[allow(clippy::too_many_lines)]
     #[tokio::test]
     async fn hash_verify_and_upgrade() {
@@ -525,6 +1044,11 @@ mod tests {
         )
         .unwrap();
 
+        // Hashes from the older schemes can still be verified
+        assert!(manager.is_supported_version(1).unwrap());
+        assert!(manager.is_supported_version(2).unwrap());
+        assert!(!manager.is_supported_version(3).unwrap());
+
         // Verifying still works
         manager
             .verify(version, password.clone(), hash.clone())
@@ -620,4 +1144,35 @@ mod tests {
             .await
             .expect_err("Verification should have failed");
     }
+
+    #[test]
+    fn breached_passwords() {
+        // Lowercase hashes, unsorted lines, duplicates and lines without counts
+        // should all be accepted
+        let list = BreachedPasswords::parse(
+            "F3BBBD66A63D4BF1747940578EC3D0103530E21D:17\n\
+             5baa61e4c9b93f3f0682250b6cf8331b7ee68fd8:9545824\n\
+             \n\
+             F3BBBD66A63D4BF1747940578EC3D0103530E21D\n",
+        )
+        .expect("Failed to parse the list");
+
+        assert_eq!(list.len(), 2);
+        assert!(list.contains("password"));
+        assert!(list.contains("hunter2"));
+        assert!(!list.contains("correct horse battery staple"));
+
+        // Invalid hashes are rejected
+        BreachedPasswords::parse("5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD:12\n")
+            .expect_err("Parsing should have failed");
+        BreachedPasswords::parse("not a hash at all, but 40 characters!!!\n")
+            .expect_err("Parsing should have failed");
+
+        // The manager only reports breached passwords if a list is loaded
+        let manager = PasswordManager::new(0, [(1, Hasher::argon2id(None))]).unwrap();
+        assert!(!manager.is_password_breached("password"));
+        let manager = manager.with_breached_passwords(list);
+        assert!(manager.is_password_breached("password"));
+        assert!(!manager.is_password_breached("correct horse battery staple"));
+    }
 }
@@ -27,6 +27,9 @@ pub enum PasswordCheckLimitedError {
 
     #[error("Too many password checks for user {0}")]
     User(Ulid),
+
+    #[error("User {0} is temporarily locked out after too many failed login attempts")]
+    LockedOut(Ulid),
 }
 
 #[derive(Debug, Clone, thiserror::Error)]
@@ -183,6 +186,24 @@ impl Limiter {
         Ok(())
     }
 
+    /// Check if a password check can be performed for a user which doesn't
+    /// exist locally
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the operation is rate limited
+    pub fn check_password_for_unknown_user(
+        &self,
+        key: RequesterFingerprint,
+    ) -> Result<(), PasswordCheckLimitedError> {
+        self.inner
+            .password_check_for_requester
+            .check_key(&key)
+            .map_err(|_| PasswordCheckLimitedError::Requester(key))?;
+
+        Ok(())
+    }
+
     /// Check if an account registration can be performed
     ///
     /// # Errors
@@ -29,16 +29,26 @@ use mas_axum_utils::{
     ErrorWrapper,
 };
 use mas_config::RateLimitingConfig;
-use mas_data_model::SiteConfig;
+use mas_data_model::{
+    SiteConfig, UpstreamOAuthProviderClaimsImports, UpstreamOAuthProviderDiscoveryMode,
+    UpstreamOAuthProviderPkceMode, UpstreamOAuthProviderResponseMode,
+    UpstreamOAuthProviderTokenAuthMethod,
+};
 use mas_i18n::Translator;
 use mas_keystore::{Encrypter, JsonWebKey, JsonWebKeySet, Keystore, PrivateKey};
 use mas_matrix::{BoxHomeserverConnection, HomeserverConnection, MockHomeserverConnection};
 use mas_policy::{InstantiateError, Policy, PolicyFactory};
 use mas_router::{SimpleRoute, UrlBuilder};
-use mas_storage::{clock::MockClock, BoxClock, BoxRepository, BoxRng};
+use mas_storage::{
+    clock::MockClock, upstream_oauth2::UpstreamOAuthProviderParams, BoxClock, BoxRepository, BoxRng,
+};
 use mas_storage_pg::{DatabaseError, PgRepository};
 use mas_templates::{SiteConfigExt, Templates};
-use oauth2_types::{registration::ClientRegistrationResponse, requests::AccessTokenResponse};
+use oauth2_types::{
+    registration::ClientRegistrationResponse,
+    requests::AccessTokenResponse,
+    scope::{Scope, OPENID},
+};
 use rand::SeedableRng;
 use rand_chacha::ChaChaRng;
 use serde::{de::DeserializeOwned, Serialize};
@@ -82,6 +92,7 @@ pub(crate) async fn policy_factory(
         client_registration: "client_registration/violation".to_owned(),
         authorization_grant: "authorization_grant/violation".to_owned(),
         email: "email/violation".to_owned(),
+        upstream_oauth2_mapping: Some("upstream_oauth2_mapping/result".to_owned()),
     };
 
     let policy_factory = PolicyFactory::load(file, data, entrypoints).await?;
@@ -131,12 +142,44 @@ pub fn test_site_config() -> SiteConfig {
         imprint: None,
         password_login_enabled: true,
         password_registration_enabled: true,
+        registration_token_required: false,
         email_change_allowed: true,
         displayname_change_allowed: true,
         password_change_allowed: true,
         account_recovery_allowed: true,
         captcha: None,
         minimum_password_complexity: 1,
+        login_lockout: None,
+    }
+}
+
+/// Parameters for an upstream OAuth 2.0 provider at `https://example.com/`
+///
+/// Tests needing different settings can override them with the struct update
+/// syntax.
+pub fn test_upstream_oauth_provider_params() -> UpstreamOAuthProviderParams {
+    UpstreamOAuthProviderParams {
+        issuer: "https://example.com/".to_owned(),
+        human_name: Some("Example Ltd.".to_owned()),
+        brand_name: None,
+        scope: Scope::from_iter([OPENID]),
+        token_endpoint_auth_method: UpstreamOAuthProviderTokenAuthMethod::None,
+        token_endpoint_signing_alg: None,
+        client_id: "client".to_owned(),
+        encrypted_client_secret: None,
+        claims_imports: UpstreamOAuthProviderClaimsImports::default(),
+        authorization_endpoint_override: None,
+        token_endpoint_override: None,
+        userinfo_endpoint_override: None,
+        fetch_userinfo: false,
+        store_tokens: false,
+        token_exchange_clients: Vec::new(),
+        domains: Vec::new(),
+        jwks_uri_override: None,
+        discovery_mode: UpstreamOAuthProviderDiscoveryMode::Oidc,
+        pkce_mode: UpstreamOAuthProviderPkceMode::Auto,
+        response_mode: UpstreamOAuthProviderResponseMode::Query,
+        additional_authorization_parameters: Vec::new(),
     }
 }
 
@@ -208,6 +251,7 @@ impl TestState {
             rng: Arc::clone(&rng),
             clock: Arc::clone(&clock),
             password_manager: password_manager.clone(),
+            url_builder: url_builder.clone(),
         };
         let state: crate::graphql::BoxState = Box::new(graphql_state);
 
@@ -370,6 +414,7 @@ struct TestGraphQLState {
     clock: Arc<MockClock>,
     rng: Arc<Mutex<ChaChaRng>>,
     password_manager: PasswordManager,
+    url_builder: UrlBuilder,
 }
 
 #[async_trait]
@@ -402,6 +447,10 @@ impl graphql::State for TestGraphQLState {
         &self.site_config
     }
 
+    fn url_builder(&self) -> &UrlBuilder {
+        &self.url_builder
+    }
+
     fn rng(&self) -> BoxRng {
         let mut parent_rng = self.rng.lock().expect("Failed to lock RNG");
         let rng = ChaChaRng::from_rng(&mut *parent_rng).expect("Failed to seed RNG");
@@ -12,7 +12,7 @@ use hyper::StatusCode;
 use mas_axum_utils::{cookies::CookieJar, sentry::SentryEventID};
 use mas_data_model::UpstreamOAuthProvider;
 use mas_oidc_client::requests::authorization_code::AuthorizationRequestData;
-use mas_router::UrlBuilder;
+use mas_router::{UpstreamOAuth2AuthorizeParams, UrlBuilder};
 use mas_storage::{
     upstream_oauth2::{UpstreamOAuthProviderRepository, UpstreamOAuthSessionRepository},
     BoxClock, BoxRepository, BoxRng,
@@ -21,10 +21,7 @@ use thiserror::Error;
 use ulid::Ulid;
 
 use super::{cache::LazyProviderInfos, UpstreamSessionsCookie};
-use crate::{
-    impl_from_error_for_route, upstream_oauth2::cache::MetadataCache,
-    views::shared::OptionalPostAuthAction,
-};
+use crate::{impl_from_error_for_route, upstream_oauth2::cache::MetadataCache};
 
 #[derive(Debug, Error)]
 pub(crate) enum RouteError {
@@ -66,7 +63,7 @@ pub(crate) async fn get(
     State(http_client): State<reqwest::Client>,
     cookie_jar: CookieJar,
     Path(provider_id): Path<Ulid>,
-    Query(query): Query<OptionalPostAuthAction>,
+    Query(query): Query<UpstreamOAuth2AuthorizeParams>,
 ) -> Result<impl IntoResponse, RouteError> {
     let provider = repo
         .upstream_oauth_provider()
@@ -110,6 +107,12 @@ pub(crate) async fn get(
         for (key, value) in &provider.additional_authorization_parameters {
             params.append_pair(key, value);
         }
+
+        // Forward the login hint, so that the user doesn't have to type their
+        // identifier again on the provider
+        if let Some(login_hint) = &query.login_hint {
+            params.append_pair("login_hint", login_hint);
+        }
     }
 
     let session = repo
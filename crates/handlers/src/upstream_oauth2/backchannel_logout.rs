@@ -0,0 +1,509 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+//! Handle OpenID Connect Back-Channel Logout requests sent by upstream
+//! providers, ending the sessions which were authenticated through them
+
+use std::collections::BTreeSet;
+
+use axum::{
+    extract::{Path, State},
+    response::IntoResponse,
+    Form, Json,
+};
+use hyper::{header::CACHE_CONTROL, StatusCode};
+use mas_axum_utils::sentry::SentryEventID;
+use mas_data_model::UpstreamOAuthProvider;
+use mas_iana::jose::JsonWebSignatureAlg;
+use mas_oidc_client::{
+    error::LogoutTokenError,
+    requests::jose::{fetch_jwks, verify_logout_token, JwtVerificationData},
+};
+use mas_storage::{
+    upstream_oauth2::UpstreamOAuthSessionFilter, BoxClock, BoxRepository, BoxRng, Pagination,
+};
+use oauth2_types::errors::{ClientError, ClientErrorCode};
+use serde::Deserialize;
+use thiserror::Error;
+use ulid::Ulid;
+
+use super::{
+    cache::LazyProviderInfos,
+    logout::{finish_sessions_authenticated_by, sync_devices, LogoutChannel, BATCH_SIZE},
+};
+use crate::{impl_from_error_for_route, MetadataCache};
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct BackchannelLogoutRequest {
+    logout_token: String,
+}
+
+#[derive(Debug, Error)]
+pub(crate) enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error("Provider not found")]
+    ProviderNotFound,
+
+    #[error("Invalid logout token")]
+    InvalidLogoutToken(#[source] LogoutTokenError),
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+impl_from_error_for_route!(mas_oidc_client::error::DiscoveryError);
+impl_from_error_for_route!(mas_oidc_client::error::JwksError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let event_id = sentry::capture_error(&self);
+        let response = match self {
+            Self::Internal(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ClientError::from(ClientErrorCode::ServerError)),
+            )
+                .into_response(),
+            e @ Self::ProviderNotFound => (
+                StatusCode::NOT_FOUND,
+                Json(
+                    ClientError::from(ClientErrorCode::InvalidRequest)
+                        .with_description(e.to_string()),
+                ),
+            )
+                .into_response(),
+            Self::InvalidLogoutToken(e) => (
+                StatusCode::BAD_REQUEST,
+                Json(
+                    ClientError::from(ClientErrorCode::InvalidRequest)
+                        .with_description(format!("Invalid logout token: {e}")),
+                ),
+            )
+                .into_response(),
+        };
+
+        (
+            SentryEventID::from(event_id),
+            [(CACHE_CONTROL, "no-store")],
+            response,
+        )
+            .into_response()
+    }
+}
+
+#[tracing::instrument(
+    name = "handlers.upstream_oauth2.backchannel_logout.post",
+    fields(upstream_oauth_provider.id = %provider_id),
+    skip_all,
+    err,
+)]
+pub(crate) async fn post(
+    mut rng: BoxRng,
+    clock: BoxClock,
+    mut repo: BoxRepository,
+    State(metadata_cache): State<MetadataCache>,
+    State(client): State<reqwest::Client>,
+    Path(provider_id): Path<Ulid>,
+    Form(request): Form<BackchannelLogoutRequest>,
+) -> Result<impl IntoResponse, RouteError> {
+    let provider = repo
+        .upstream_oauth_provider()
+        .lookup(provider_id)
+        .await?
+        .filter(UpstreamOAuthProvider::enabled)
+        .ok_or(RouteError::ProviderNotFound)?;
+
+    // The logout token is signed with the same keys as the ID tokens
+    let mut lazy_metadata = LazyProviderInfos::new(&metadata_cache, &provider, &client);
+    let jwks = fetch_jwks(&client, lazy_metadata.jwks_uri().await?).await?;
+
+    let verification_data = JwtVerificationData {
+        issuer: &provider.issuer,
+        jwks: &jwks,
+        // TODO: make that configurable
+        signing_algorithm: &JsonWebSignatureAlg::Rs256,
+        client_id: &provider.client_id,
+    };
+
+    let claims = verify_logout_token(&request.logout_token, verification_data, clock.now())
+        .map_err(RouteError::InvalidLogoutToken)?;
+
+    // Find the upstream sessions matching the subject and/or the session ID
+    let mut filter = UpstreamOAuthSessionFilter::new().for_provider(&provider);
+    if let Some(sub) = &claims.sub {
+        filter = filter.with_sub_claim(sub);
+    }
+    if let Some(sid) = &claims.sid {
+        filter = filter.with_sid_claim(sid);
+    }
+
+    let mut users = BTreeSet::new();
+    let mut pagination = Pagination::first(BATCH_SIZE);
+    loop {
+        let page = repo
+            .upstream_oauth_session()
+            .list(filter, pagination)
+            .await?;
+
+        finish_sessions_authenticated_by(
+            &mut repo,
+            &mut rng,
+            &clock,
+            &provider,
+            LogoutChannel::Backchannel,
+            &page.edges,
+            &mut users,
+        )
+        .await?;
+
+        match page.edges.last() {
+            Some(last) if page.has_next_page => pagination = pagination.after(last.id),
+            _ => break,
+        }
+    }
+
+    sync_devices(&mut repo, users).await?;
+
+    repo.save().await?;
+
+    Ok(([(CACHE_CONTROL, "no-store")], StatusCode::OK))
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::Request;
+    use mas_data_model::{AuditLogEventKind, BrowserSession, UpstreamOAuthProviderDiscoveryMode};
+    use mas_jose::jwt::{JsonWebSignatureHeader, Jwt};
+    use mas_router::Route;
+    use mas_storage::{
+        audit_log::AuditLogFilter, upstream_oauth2::UpstreamOAuthProviderParams, Clock,
+        RepositoryAccess,
+    };
+    use serde_json::json;
+    use sqlx::{types::Json, PgPool};
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+    use crate::test_utils::{
+        setup, test_upstream_oauth_provider_params, RequestBuilderExt, ResponseExt, TestState,
+    };
+
+    const BACKCHANNEL_LOGOUT_EVENT: &str = "http://schemas.openid.net/event/backchannel-logout";
+
+    /// Set up a provider whose keys are served by the mock server
+    async fn provider(state: &TestState, mock_server: &MockServer) -> UpstreamOAuthProvider {
+        Mock::given(method("GET"))
+            .and(path("/jwks"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(state.key_store.public_jwks()))
+            .mount(mock_server)
+            .await;
+
+        let mut repo = state.repository().await.unwrap();
+        let provider = repo
+            .upstream_oauth_provider()
+            .add(
+                &mut state.rng(),
+                &state.clock,
+                UpstreamOAuthProviderParams {
+                    discovery_mode: UpstreamOAuthProviderDiscoveryMode::Disabled,
+                    jwks_uri_override: Some(format!("{}/jwks", mock_server.uri()).parse().unwrap()),
+                    ..test_upstream_oauth_provider_params()
+                },
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        provider
+    }
+
+    /// Start a browser session for the user, authenticated through the provider
+    /// by an ID token with the given session ID
+    async fn login(
+        state: &TestState,
+        provider: &UpstreamOAuthProvider,
+        username: &str,
+        sid: &str,
+    ) -> BrowserSession {
+        let mut rng = state.rng();
+        let mut repo = state.repository().await.unwrap();
+
+        let existing = repo.user().find_by_username(username).await.unwrap();
+        let user = if let Some(user) = existing {
+            user
+        } else {
+            repo.user()
+                .add(&mut rng, &state.clock, username.to_owned())
+                .await
+                .unwrap()
+        };
+
+        let existing = repo
+            .upstream_oauth_link()
+            .find_by_subject(provider, username)
+            .await
+            .unwrap();
+        let link = if let Some(link) = existing {
+            link
+        } else {
+            let link = repo
+                .upstream_oauth_link()
+                .add(&mut rng, &state.clock, provider, username.to_owned())
+                .await
+                .unwrap();
+            repo.upstream_oauth_link()
+                .associate_to_user(&link, &user)
+                .await
+                .unwrap();
+            link
+        };
+
+        let upstream_session = repo
+            .upstream_oauth_session()
+            .add(
+                &mut rng,
+                &state.clock,
+                provider,
+                sid.to_owned(),
+                None,
+                "nonce".to_owned(),
+            )
+            .await
+            .unwrap();
+        let upstream_session = repo
+            .upstream_oauth_session()
+            .complete_with_link(
+                &state.clock,
+                upstream_session,
+                &link,
+                None,
+                Some(json!({ "sub": username, "sid": sid })),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+
+        let browser_session = repo
+            .browser_session()
+            .add(&mut rng, &state.clock, &user, None)
+            .await
+            .unwrap();
+        repo.browser_session()
+            .authenticate_with_upstream(&mut rng, &state.clock, &browser_session, &upstream_session)
+            .await
+            .unwrap();
+
+        repo.save().await.unwrap();
+
+        browser_session
+    }
+
+    fn sign(state: &TestState, claims: serde_json::Value) -> String {
+        let key = state
+            .key_store
+            .signing_key_for_algorithm(&JsonWebSignatureAlg::Rs256)
+            .unwrap();
+        let signer = key
+            .params()
+            .signing_key_for_alg(&JsonWebSignatureAlg::Rs256)
+            .unwrap();
+        let header = JsonWebSignatureHeader::new(JsonWebSignatureAlg::Rs256);
+        Jwt::sign_with_rng(&mut state.rng(), header, claims, &signer)
+            .unwrap()
+            .into_string()
+    }
+
+    fn logout_claims(state: &TestState, provider: &UpstreamOAuthProvider) -> serde_json::Value {
+        json!({
+            "iss": provider.issuer,
+            "aud": provider.client_id,
+            "iat": state.clock.now().timestamp(),
+            "jti": "logout",
+            "events": { BACKCHANNEL_LOGOUT_EVENT: {} },
+        })
+    }
+
+    fn logout_request(provider: &UpstreamOAuthProvider, logout_token: &str) -> Request<String> {
+        Request::post(&*mas_router::UpstreamOAuth2BackchannelLogout::new(provider.id).path())
+            .form(json!({ "logout_token": logout_token }))
+    }
+
+    async fn is_active(state: &TestState, browser_session: &BrowserSession) -> bool {
+        let mut repo = state.repository().await.unwrap();
+        let browser_session = repo
+            .browser_session()
+            .lookup(browser_session.id)
+            .await
+            .unwrap()
+            .unwrap();
+        repo.cancel().await.unwrap();
+        browser_session.active()
+    }
+
+    async fn ended_sessions(pool: &PgPool) -> Vec<serde_json::Value> {
+        let jobs: Vec<Json<serde_json::Value>> = sqlx::query_scalar(
+            "SELECT job FROM apalis.jobs WHERE job_type = 'send-webhook-event' ORDER BY id",
+        )
+        .fetch_all(pool)
+        .await
+        .unwrap();
+
+        jobs.into_iter()
+            .filter(|job| job["kind"] == "session.ended")
+            .map(|job| job["data"]["session"].clone())
+            .collect()
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_logout_with_sid(pool: PgPool) {
+        setup();
+        let mock_server = MockServer::start().await;
+        let state = TestState::from_pool(pool.clone()).await.unwrap();
+        let provider = provider(&state, &mock_server).await;
+        let first = login(&state, &provider, "alice", "first").await;
+        let second = login(&state, &provider, "alice", "second").await;
+
+        let mut claims = logout_claims(&state, &provider);
+        claims["sid"] = json!("first");
+        let response = state
+            .request(logout_request(&provider, &sign(&state, claims)))
+            .await;
+        response.assert_status(StatusCode::OK);
+
+        // Only the session authenticated with that session ID ended
+        assert!(!is_active(&state, &first).await);
+        assert!(is_active(&state, &second).await);
+        assert_eq!(
+            ended_sessions(&pool).await,
+            vec![json!({ "kind": "browser", "id": first.id })]
+        );
+
+        // It is recorded in the audit log
+        let mut repo = state.repository().await.unwrap();
+        let events = repo
+            .audit_log()
+            .list(
+                AuditLogFilter::new()
+                    .for_user(&first.user)
+                    .with_kind(AuditLogEventKind::SessionFinished),
+                Pagination::first(10),
+            )
+            .await
+            .unwrap();
+        repo.cancel().await.unwrap();
+        assert_eq!(events.edges.len(), 1);
+        assert_eq!(events.edges[0].data["session_id"], json!(first.id));
+        assert_eq!(events.edges[0].data["reason"], "upstream_logout");
+        assert_eq!(events.edges[0].data["channel"], "backchannel");
+
+        // The devices of the user get synced with the homeserver
+        let job: Json<serde_json::Value> =
+            sqlx::query_scalar("SELECT job FROM apalis.jobs WHERE job_type = 'sync-devices'")
+                .fetch_one(&pool)
+                .await
+                .expect("Sync devices job to be scheduled");
+        assert_eq!(job["user_id"], json!(first.user.id));
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_logout_with_sub(pool: PgPool) {
+        setup();
+        let mock_server = MockServer::start().await;
+        let state = TestState::from_pool(pool.clone()).await.unwrap();
+        let provider = provider(&state, &mock_server).await;
+        let first = login(&state, &provider, "alice", "first").await;
+        let second = login(&state, &provider, "alice", "second").await;
+        let other = login(&state, &provider, "bob", "other").await;
+
+        let mut claims = logout_claims(&state, &provider);
+        claims["sub"] = json!("alice");
+        let response = state
+            .request(logout_request(&provider, &sign(&state, claims)))
+            .await;
+        response.assert_status(StatusCode::OK);
+
+        // All the sessions of that subject ended
+        assert!(!is_active(&state, &first).await);
+        assert!(!is_active(&state, &second).await);
+        assert!(is_active(&state, &other).await);
+        let mut ended = ended_sessions(&pool).await;
+        let mut expected = vec![
+            json!({ "kind": "browser", "id": first.id }),
+            json!({ "kind": "browser", "id": second.id }),
+        ];
+        ended.sort_by_key(ToString::to_string);
+        expected.sort_by_key(ToString::to_string);
+        assert_eq!(ended, expected);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_invalid_logout_token(pool: PgPool) {
+        setup();
+        let mock_server = MockServer::start().await;
+        let state = TestState::from_pool(pool.clone()).await.unwrap();
+        let provider = provider(&state, &mock_server).await;
+        let browser_session = login(&state, &provider, "alice", "first").await;
+
+        let mut claims = logout_claims(&state, &provider);
+        claims["sid"] = json!("first");
+
+        // A token whose signature doesn't match its payload
+        let valid = sign(&state, claims.clone());
+        let mut other_claims = claims.clone();
+        other_claims["sid"] = json!("second");
+        let other = sign(&state, other_claims);
+        let (header_and_payload, _) = other.rsplit_once('.').unwrap();
+        let (_, signature) = valid.rsplit_once('.').unwrap();
+        let bad_signature = format!("{header_and_payload}.{signature}");
+
+        // A token without the logout event
+        let mut missing_events = claims.clone();
+        missing_events.as_object_mut().unwrap().remove("events");
+
+        // A token with a nonce, which could be an ID token
+        let mut with_nonce = claims.clone();
+        with_nonce["nonce"] = json!("nonce");
+
+        for logout_token in [
+            bad_signature,
+            sign(&state, missing_events),
+            sign(&state, with_nonce),
+        ] {
+            let response = state
+                .request(logout_request(&provider, &logout_token))
+                .await;
+            response.assert_status(StatusCode::BAD_REQUEST);
+            let body: ClientError = response.json();
+            assert_eq!(body.error, ClientErrorCode::InvalidRequest);
+        }
+
+        assert!(is_active(&state, &browser_session).await);
+        assert!(ended_sessions(&pool).await.is_empty());
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_unknown_provider(pool: PgPool) {
+        setup();
+        let mock_server = MockServer::start().await;
+        let state = TestState::from_pool(pool.clone()).await.unwrap();
+        let provider = provider(&state, &mock_server).await;
+        let browser_session = login(&state, &provider, "alice", "first").await;
+
+        let mut claims = logout_claims(&state, &provider);
+        claims["sid"] = json!("first");
+        let logout_token = sign(&state, claims);
+
+        let request =
+            Request::post(&*mas_router::UpstreamOAuth2BackchannelLogout::new(Ulid::nil()).path())
+                .form(json!({ "logout_token": logout_token }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NOT_FOUND);
+
+        assert!(is_active(&state, &browser_session).await);
+    }
+}
@@ -108,6 +108,18 @@ impl<'a> LazyProviderInfos<'a> {
         Ok(self.load().await?.token_endpoint())
     }
 
+    /// Get the userinfo endpoint for the provider.
+    ///
+    /// Uses [`UpstreamOAuthProvider.userinfo_endpoint_override`] if set,
+    /// otherwise uses the one from discovery, which may be missing.
+    pub async fn userinfo_endpoint(&mut self) -> Result<Option<&Url>, DiscoveryError> {
+        if let Some(userinfo_endpoint) = &self.provider.userinfo_endpoint_override {
+            return Ok(Some(userinfo_endpoint));
+        }
+
+        Ok(self.load().await?.userinfo_endpoint.as_ref())
+    }
+
     /// Get the PKCE methods supported by the provider.
     ///
     /// If the mode is set to auto, it will use the ones from discovery,
@@ -391,6 +403,11 @@ mod tests {
             authorization_endpoint_override: None,
             scope: Scope::from_iter([OPENID]),
             token_endpoint_override: None,
+            userinfo_endpoint_override: None,
+            fetch_userinfo: false,
+            store_tokens: false,
+            token_exchange_clients: Vec::new(),
+            domains: Vec::new(),
             client_id: "client_id".to_owned(),
             encrypted_client_secret: None,
             token_endpoint_signing_alg: None,
@@ -428,6 +445,9 @@ mod tests {
                 token_endpoint_override: Some(
                     "https://example.com/token_override".parse().unwrap(),
                 ),
+                userinfo_endpoint_override: Some(
+                    "https://example.com/userinfo_override".parse().unwrap(),
+                ),
                 ..provider.clone()
             };
             let cache = MetadataCache::new();
@@ -448,6 +468,14 @@ mod tests {
                 lazy_metadata.token_endpoint().await.unwrap().as_str(),
                 "https://example.com/token_override"
             );
+            assert_eq!(
+                lazy_metadata
+                    .userinfo_endpoint()
+                    .await
+                    .unwrap()
+                    .map(Url::as_str),
+                Some("https://example.com/userinfo_override")
+            );
             // This shouldn't trigger a new fetch as the endpoint is overriden
             calls += 0;
         }
@@ -473,6 +501,11 @@ mod tests {
                     Url::parse("https://example.com/authorize_override").unwrap(),
                 ),
                 token_endpoint_override: None,
+                userinfo_endpoint_override: None,
+                fetch_userinfo: false,
+                store_tokens: false,
+                token_exchange_clients: Vec::new(),
+                domains: Vec::new(),
                 ..provider.clone()
             };
             let cache = MetadataCache::new();
@@ -27,7 +27,7 @@ use mas_storage::{
     BoxClock, BoxRepository, BoxRng, Clock,
 };
 use mas_templates::{FormPostContext, Templates};
-use oauth2_types::errors::ClientErrorCode;
+use oauth2_types::{errors::ClientErrorCode, scope::OPENID};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use ulid::Ulid;
@@ -36,6 +36,7 @@ use super::{
     cache::LazyProviderInfos,
     client_credentials_for_provider,
     template::{environment, AttributeMappingContext},
+    tokens::save_tokens,
     UpstreamSessionsCookie,
 };
 use crate::{impl_from_error_for_route, upstream_oauth2::cache::MetadataCache, PreferredLanguage};
@@ -90,7 +91,10 @@ pub(crate) enum RouteError {
     #[error("Missing ID token")]
     MissingIDToken,
 
-    #[error("Could not extract subject from ID token")]
+    #[error("The provider has no userinfo endpoint")]
+    MissingUserinfoEndpoint,
+
+    #[error("Could not extract subject from the claims")]
     ExtractSubject(#[source] minijinja::Error),
 
     #[error("Subject is empty")]
@@ -125,8 +129,10 @@ impl_from_error_for_route!(mas_storage::RepositoryError);
 impl_from_error_for_route!(mas_oidc_client::error::DiscoveryError);
 impl_from_error_for_route!(mas_oidc_client::error::JwksError);
 impl_from_error_for_route!(mas_oidc_client::error::TokenAuthorizationCodeError);
+impl_from_error_for_route!(mas_oidc_client::error::UserInfoError);
 impl_from_error_for_route!(super::ProviderCredentialsError);
 impl_from_error_for_route!(super::cookie::UpstreamSessionNotFound);
+impl_from_error_for_route!(super::tokens::TokenVaultError);
 
 impl IntoResponse for RouteError {
     fn into_response(self) -> axum::response::Response {
@@ -251,10 +257,20 @@ pub(crate) async fn handler(
 
     let mut lazy_metadata = LazyProviderInfos::new(&metadata_cache, &provider, &client);
 
+    // The provider only returns an ID token if we asked for the `openid` scope.
+    // Plain OAuth 2.0 providers don't have one, and we fetch the claims from the
+    // userinfo endpoint instead.
+    let expects_id_token = provider.scope.contains(&OPENID);
+
     // Fetch the JWKS
-    let jwks =
-        mas_oidc_client::requests::jose::fetch_jwks(&client, lazy_metadata.jwks_uri().await?)
-            .await?;
+    let jwks = if expects_id_token {
+        Some(
+            mas_oidc_client::requests::jose::fetch_jwks(&client, lazy_metadata.jwks_uri().await?)
+                .await?,
+        )
+    } else {
+        None
+    };
 
     // Figure out the client credentials
     let client_credentials = client_credentials_for_provider(
@@ -274,13 +290,13 @@ pub(crate) async fn handler(
         redirect_uri,
     };
 
-    let id_token_verification_data = JwtVerificationData {
+    let id_token_verification_data = jwks.as_ref().map(|jwks| JwtVerificationData {
         issuer: &provider.issuer,
-        jwks: &jwks,
+        jwks,
         // TODO: make that configurable
         signing_algorithm: &mas_iana::jose::JsonWebSignatureAlg::Rs256,
         client_id: &provider.client_id,
-    };
+    });
 
     let (response, id_token) =
         mas_oidc_client::requests::authorization_code::access_token_with_authorization_code(
@@ -289,15 +305,50 @@ pub(crate) async fn handler(
             lazy_metadata.token_endpoint().await?,
             code,
             validation_data,
-            Some(id_token_verification_data),
+            id_token_verification_data,
             clock.now(),
             &mut rng,
         )
         .await?;
 
-    let (_header, id_token) = id_token.ok_or(RouteError::MissingIDToken)?.into_parts();
+    if id_token.is_none() && !provider.fetch_userinfo {
+        return Err(RouteError::MissingIDToken);
+    }
+
+    let userinfo = if provider.fetch_userinfo {
+        let userinfo_endpoint = lazy_metadata
+            .userinfo_endpoint()
+            .await?
+            .ok_or(RouteError::MissingUserinfoEndpoint)?;
+
+        Some(
+            mas_oidc_client::requests::userinfo::fetch_userinfo(
+                &client,
+                userinfo_endpoint,
+                &response.access_token,
+                None,
+                id_token.as_ref(),
+            )
+            .await?,
+        )
+    } else {
+        None
+    };
 
-    let mut context = AttributeMappingContext::new().with_id_token_claims(id_token);
+    let mut context = AttributeMappingContext::new();
+    let mut id_token_claims = None;
+    if let Some(id_token) = id_token {
+        let (_header, id_token) = id_token.into_parts();
+        // Keep the claims around, so that the session can be found again when the
+        // provider sends a back-channel logout request
+        id_token_claims = Some(serde_json::Value::Object(
+            id_token.clone().into_iter().collect(),
+        ));
+        context = context.with_id_token_claims(id_token);
+    }
+    if let Some(userinfo) = userinfo.clone() {
+        context = context.with_userinfo_claims(userinfo);
+    }
     if let Some(extra_callback_parameters) = extra_callback_parameters.clone() {
         context = context.with_extra_callback_parameters(extra_callback_parameters);
     }
@@ -333,6 +384,11 @@ pub(crate) async fn handler(
             .await?
     };
 
+    // Keep the tokens if the provider is configured to do so
+    if provider.store_tokens {
+        save_tokens(&clock, &mut repo, &encrypter, &link, &response).await?;
+    }
+
     let session = repo
         .upstream_oauth_session()
         .complete_with_link(
@@ -340,7 +396,9 @@ pub(crate) async fn handler(
             session,
             &link,
             response.id_token,
+            id_token_claims,
             extra_callback_parameters,
+            userinfo.map(|claims| serde_json::Value::Object(claims.into_iter().collect())),
         )
         .await?;
 
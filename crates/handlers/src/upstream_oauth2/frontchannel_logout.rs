@@ -0,0 +1,424 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+//! Handle OpenID Connect Front-Channel Logout requests, which upstream
+//! providers send by loading this endpoint in an iframe, ending the sessions
+//! which were authenticated through them
+
+use std::collections::BTreeSet;
+
+use axum::{
+    extract::{Path, Query},
+    response::IntoResponse,
+};
+use hyper::{
+    header::{CACHE_CONTROL, PRAGMA},
+    StatusCode,
+};
+use mas_axum_utils::{cookies::CookieJar, sentry::SentryEventID, SessionInfoExt};
+use mas_data_model::{AuthenticationMethod, UpstreamOAuthProvider};
+use mas_storage::{
+    upstream_oauth2::UpstreamOAuthSessionFilter, BoxClock, BoxRepository, BoxRng, Pagination,
+};
+use serde::Deserialize;
+use thiserror::Error;
+use ulid::Ulid;
+
+use super::logout::{finish_sessions_authenticated_by, sync_devices, LogoutChannel, BATCH_SIZE};
+use crate::impl_from_error_for_route;
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct FrontchannelLogoutParams {
+    iss: Option<String>,
+    sid: Option<String>,
+}
+
+#[derive(Debug, Error)]
+pub(crate) enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error("Provider not found")]
+    ProviderNotFound,
+
+    #[error("The iss and sid parameters must be given together")]
+    MissingParameter,
+
+    #[error("The iss parameter doesn't match the provider")]
+    IssuerMismatch,
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let event_id = sentry::capture_error(&self);
+        let response = match self {
+            Self::Internal(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+            e @ Self::ProviderNotFound => (StatusCode::NOT_FOUND, e.to_string()).into_response(),
+            e @ (Self::MissingParameter | Self::IssuerMismatch) => {
+                (StatusCode::BAD_REQUEST, e.to_string()).into_response()
+            }
+        };
+
+        (
+            SentryEventID::from(event_id),
+            [(CACHE_CONTROL, "no-cache, no-store"), (PRAGMA, "no-cache")],
+            response,
+        )
+            .into_response()
+    }
+}
+
+/// Providers which send the `iss` and `sid` parameters get the sessions
+/// authenticated with that session ID ended, regardless of the cookies sent
+/// along, as browsers increasingly withhold them from third-party iframes.
+/// Otherwise, the session of the browser is ended if it was authenticated
+/// through that provider.
+#[tracing::instrument(
+    name = "handlers.upstream_oauth2.frontchannel_logout.get",
+    fields(upstream_oauth_provider.id = %provider_id),
+    skip_all,
+    err,
+)]
+pub(crate) async fn get(
+    mut rng: BoxRng,
+    clock: BoxClock,
+    mut repo: BoxRepository,
+    cookie_jar: CookieJar,
+    Path(provider_id): Path<Ulid>,
+    Query(params): Query<FrontchannelLogoutParams>,
+) -> Result<impl IntoResponse, RouteError> {
+    let provider = repo
+        .upstream_oauth_provider()
+        .lookup(provider_id)
+        .await?
+        .filter(UpstreamOAuthProvider::enabled)
+        .ok_or(RouteError::ProviderNotFound)?;
+
+    let (session_info, mut cookie_jar) = cookie_jar.session_info();
+    let mut users = BTreeSet::new();
+
+    match (params.iss, params.sid) {
+        (Some(iss), Some(sid)) => {
+            if iss != provider.issuer {
+                return Err(RouteError::IssuerMismatch);
+            }
+
+            let filter = UpstreamOAuthSessionFilter::new()
+                .for_provider(&provider)
+                .with_sid_claim(&sid);
+            let mut pagination = Pagination::first(BATCH_SIZE);
+            loop {
+                let page = repo
+                    .upstream_oauth_session()
+                    .list(filter, pagination)
+                    .await?;
+
+                finish_sessions_authenticated_by(
+                    &mut repo,
+                    &mut rng,
+                    &clock,
+                    &provider,
+                    LogoutChannel::Frontchannel,
+                    &page.edges,
+                    &mut users,
+                )
+                .await?;
+
+                match page.edges.last() {
+                    Some(last) if page.has_next_page => pagination = pagination.after(last.id),
+                    _ => break,
+                }
+            }
+        }
+
+        (None, None) => {
+            let maybe_session = session_info.load_session(&mut repo).await?;
+            let mut upstream_oauth_session = None;
+            if let Some(browser_session) = &maybe_session {
+                let authentication = repo
+                    .browser_session()
+                    .get_last_authentication(browser_session)
+                    .await?;
+
+                if let Some(AuthenticationMethod::UpstreamOAuth2 {
+                    upstream_oauth2_session_id,
+                }) = authentication.map(|a| a.authentication_method)
+                {
+                    upstream_oauth_session = repo
+                        .upstream_oauth_session()
+                        .lookup(upstream_oauth2_session_id)
+                        .await?;
+                }
+            }
+
+            // Only end the session of the browser if it was authenticated through
+            // this provider
+            if let Some(upstream_oauth_session) =
+                upstream_oauth_session.filter(|session| session.provider_id == provider.id)
+            {
+                finish_sessions_authenticated_by(
+                    &mut repo,
+                    &mut rng,
+                    &clock,
+                    &provider,
+                    LogoutChannel::Frontchannel,
+                    &[upstream_oauth_session],
+                    &mut users,
+                )
+                .await?;
+
+                cookie_jar = cookie_jar.update_session_info(&session_info.mark_session_ended());
+            }
+        }
+
+        _ => return Err(RouteError::MissingParameter),
+    }
+
+    sync_devices(&mut repo, users).await?;
+
+    repo.save().await?;
+
+    Ok((
+        [(CACHE_CONTROL, "no-cache, no-store"), (PRAGMA, "no-cache")],
+        cookie_jar,
+        StatusCode::OK,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::Request;
+    use mas_data_model::{AuditLogEventKind, BrowserSession};
+    use mas_router::Route;
+    use mas_storage::{audit_log::AuditLogFilter, RepositoryAccess};
+    use serde_json::json;
+    use sqlx::PgPool;
+
+    use super::*;
+    use crate::test_utils::{
+        setup, test_upstream_oauth_provider_params, CookieHelper, RequestBuilderExt, ResponseExt,
+        TestState,
+    };
+
+    async fn provider(state: &TestState) -> UpstreamOAuthProvider {
+        let mut repo = state.repository().await.unwrap();
+        let provider = repo
+            .upstream_oauth_provider()
+            .add(
+                &mut state.rng(),
+                &state.clock,
+                test_upstream_oauth_provider_params(),
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        provider
+    }
+
+    /// Start a browser session for the user, authenticated through the provider
+    /// by an ID token with the given session ID
+    async fn login(
+        state: &TestState,
+        provider: &UpstreamOAuthProvider,
+        username: &str,
+        sid: &str,
+    ) -> BrowserSession {
+        let mut rng = state.rng();
+        let mut repo = state.repository().await.unwrap();
+
+        let existing = repo.user().find_by_username(username).await.unwrap();
+        let user = if let Some(user) = existing {
+            user
+        } else {
+            repo.user()
+                .add(&mut rng, &state.clock, username.to_owned())
+                .await
+                .unwrap()
+        };
+
+        let existing = repo
+            .upstream_oauth_link()
+            .find_by_subject(provider, username)
+            .await
+            .unwrap();
+        let link = if let Some(link) = existing {
+            link
+        } else {
+            let link = repo
+                .upstream_oauth_link()
+                .add(&mut rng, &state.clock, provider, username.to_owned())
+                .await
+                .unwrap();
+            repo.upstream_oauth_link()
+                .associate_to_user(&link, &user)
+                .await
+                .unwrap();
+            link
+        };
+
+        let upstream_session = repo
+            .upstream_oauth_session()
+            .add(
+                &mut rng,
+                &state.clock,
+                provider,
+                sid.to_owned(),
+                None,
+                "nonce".to_owned(),
+            )
+            .await
+            .unwrap();
+        let upstream_session = repo
+            .upstream_oauth_session()
+            .complete_with_link(
+                &state.clock,
+                upstream_session,
+                &link,
+                None,
+                Some(json!({ "sub": username, "sid": sid })),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+
+        let browser_session = repo
+            .browser_session()
+            .add(&mut rng, &state.clock, &user, None)
+            .await
+            .unwrap();
+        repo.browser_session()
+            .authenticate_with_upstream(&mut rng, &state.clock, &browser_session, &upstream_session)
+            .await
+            .unwrap();
+
+        repo.save().await.unwrap();
+
+        browser_session
+    }
+
+    fn logout_request(provider: &UpstreamOAuthProvider, query: &str) -> Request<String> {
+        let path = mas_router::UpstreamOAuth2FrontchannelLogout::new(provider.id).path();
+        Request::get(format!("{path}?{query}")).empty()
+    }
+
+    async fn is_active(state: &TestState, browser_session: &BrowserSession) -> bool {
+        let mut repo = state.repository().await.unwrap();
+        let browser_session = repo
+            .browser_session()
+            .lookup(browser_session.id)
+            .await
+            .unwrap()
+            .unwrap();
+        repo.cancel().await.unwrap();
+        browser_session.active()
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_logout_with_sid(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool.clone()).await.unwrap();
+        let provider = provider(&state).await;
+        let first = login(&state, &provider, "alice", "first").await;
+        let second = login(&state, &provider, "alice", "second").await;
+
+        // Cookies aren't needed when the session ID is given
+        let query =
+            serde_urlencoded::to_string([("iss", provider.issuer.as_str()), ("sid", "first")])
+                .unwrap();
+        let response = state.request(logout_request(&provider, &query)).await;
+        response.assert_status(StatusCode::OK);
+        response.assert_header_value(CACHE_CONTROL, "no-cache, no-store");
+
+        // Only the session authenticated with that session ID ended
+        assert!(!is_active(&state, &first).await);
+        assert!(is_active(&state, &second).await);
+
+        // It is recorded in the audit log
+        let mut repo = state.repository().await.unwrap();
+        let events = repo
+            .audit_log()
+            .list(
+                AuditLogFilter::new()
+                    .for_user(&first.user)
+                    .with_kind(AuditLogEventKind::SessionFinished),
+                Pagination::first(10),
+            )
+            .await
+            .unwrap();
+        repo.cancel().await.unwrap();
+        assert_eq!(events.edges.len(), 1);
+        assert_eq!(events.edges[0].data["session_id"], json!(first.id));
+        assert_eq!(events.edges[0].data["reason"], "upstream_logout");
+        assert_eq!(events.edges[0].data["channel"], "frontchannel");
+
+        // The devices of the user get synced with the homeserver
+        let job: sqlx::types::Json<serde_json::Value> =
+            sqlx::query_scalar("SELECT job FROM apalis.jobs WHERE job_type = 'sync-devices'")
+                .fetch_one(&pool)
+                .await
+                .expect("Sync devices job to be scheduled");
+        assert_eq!(job["user_id"], json!(first.user.id));
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_logout_with_cookie(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let provider = provider(&state).await;
+        let other_provider = self::provider(&state).await;
+        let browser_session = login(&state, &provider, "alice", "first").await;
+        let cookies = CookieHelper::new();
+        cookies.import(state.cookie_jar().set_session(&browser_session));
+
+        // Logging out from another provider leaves the session alone
+        let request = cookies.with_cookies(logout_request(&other_provider, ""));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        assert!(is_active(&state, &browser_session).await);
+
+        // Without parameters, the session of the browser ends
+        let request = cookies.with_cookies(logout_request(&provider, ""));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        assert!(!is_active(&state, &browser_session).await);
+
+        // Without cookies, there is nothing to do
+        let response = state.request(logout_request(&provider, "")).await;
+        response.assert_status(StatusCode::OK);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_invalid_parameters(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let provider = provider(&state).await;
+        let browser_session = login(&state, &provider, "alice", "first").await;
+
+        for query in [
+            "sid=first".to_owned(),
+            serde_urlencoded::to_string([("iss", provider.issuer.as_str())]).unwrap(),
+            "iss=https%3A%2F%2Fother.example.com%2F&sid=first".to_owned(),
+        ] {
+            let response = state.request(logout_request(&provider, &query)).await;
+            response.assert_status(StatusCode::BAD_REQUEST);
+        }
+
+        assert!(is_active(&state, &browser_session).await);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_unknown_provider(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+
+        let path = mas_router::UpstreamOAuth2FrontchannelLogout::new(Ulid::nil()).path();
+        let response = state.request(Request::get(&*path).empty()).await;
+        response.assert_status(StatusCode::NOT_FOUND);
+    }
+}
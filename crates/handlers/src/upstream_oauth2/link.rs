@@ -4,6 +4,8 @@
 // SPDX-License-Identifier: AGPL-3.0-only
 // Please see LICENSE in the repository root for full details.
 
+use std::collections::HashMap;
+
 use axum::{
     extract::{Path, State},
     response::{Html, IntoResponse, Response},
@@ -17,25 +19,37 @@ use mas_axum_utils::{
     sentry::SentryEventID,
     FancyError, SessionInfoExt,
 };
-use mas_data_model::{User, UserAgent};
+use mas_data_model::{
+    AuditLogEventKind, BrowserSession, UpstreamOAuthAuthorizationSession, UpstreamOAuthLink,
+    UpstreamOAuthProvider, User, UserAgent,
+};
 use mas_jose::jwt::Jwt;
 use mas_matrix::BoxHomeserverConnection;
-use mas_policy::Policy;
-use mas_router::UrlBuilder;
+use mas_policy::{Policy, UpstreamOAuth2Mapping};
+use mas_router::{PostAuthAction, UrlBuilder};
 use mas_storage::{
-    job::{JobRepositoryExt, ProvisionUserJob},
+    audit_log::NewAuditLogEvent,
+    job::{JobRepositoryExt, NewSignInSession, ProvisionUserJob, SendWebhookEventJob},
     upstream_oauth2::{UpstreamOAuthLinkRepository, UpstreamOAuthSessionRepository},
-    user::{BrowserSessionRepository, UserEmailRepository, UserRepository},
-    BoxClock, BoxRepository, BoxRng, RepositoryAccess,
+    user::{
+        BrowserSessionRepository, UserEmailRepository, UserRecoveryRepository,
+        UserRegistrationTokenRepository, UserRepository,
+    },
+    BoxClock, BoxRepository, BoxRng, Clock, RepositoryAccess,
 };
 use mas_templates::{
-    ErrorContext, FieldError, FormError, TemplateContext, Templates, ToFormState,
-    UpstreamExistingLinkContext, UpstreamRegister, UpstreamSuggestLink,
+    EmptyContext, ErrorContext, FieldError, FormError, TemplateContext, Templates, ToFormState,
+    UpstreamDeniedContext, UpstreamExistingLinkContext, UpstreamRegister, UpstreamSuggestLink,
 };
 use minijinja::Environment;
+use rand::{
+    distributions::{Alphanumeric, DistString},
+    RngCore,
+};
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use thiserror::Error;
-use tracing::warn;
+use tracing::{info, warn};
 use ulid::Ulid;
 
 use super::{
@@ -43,12 +57,14 @@ use super::{
     UpstreamSessionsCookie,
 };
 use crate::{
-    impl_from_error_for_route, views::shared::OptionalPostAuthAction, PreferredLanguage, SiteConfig,
+    impl_from_error_for_route, new_sign_in, views::shared::OptionalPostAuthAction,
+    BoundActivityTracker, PreferredLanguage, SiteConfig,
 };
 
 const DEFAULT_LOCALPART_TEMPLATE: &str = "{{ user.preferred_username }}";
 const DEFAULT_DISPLAYNAME_TEMPLATE: &str = "{{ user.name }}";
 const DEFAULT_EMAIL_TEMPLATE: &str = "{{ user.email }}";
+const DEFAULT_GROUPS_TEMPLATE: &str = "{{ user.groups | tojson }}";
 
 #[derive(Debug, Error)]
 pub(crate) enum RouteError {
@@ -163,6 +179,535 @@ fn render_attribute_template(
     }
 }
 
+/// Build the context used to render the attribute templates, from the claims
+/// the upstream provider returned in the authorization session
+///
+/// # Errors
+///
+/// Returns an error if the ID token saved in the session can't be decoded
+fn attribute_mapping_context(
+    upstream_session: &UpstreamOAuthAuthorizationSession,
+) -> Result<minijinja::Value, RouteError> {
+    let id_token = upstream_session.id_token().map(Jwt::try_from).transpose()?;
+
+    let mut context = AttributeMappingContext::new();
+    if let Some(id_token) = id_token {
+        let (_, payload) = id_token.into_parts();
+        context = context.with_id_token_claims(payload);
+    }
+    if let Some(serde_json::Value::Object(userinfo)) = upstream_session.userinfo() {
+        context = context.with_userinfo_claims(userinfo.clone().into_iter().collect());
+    }
+    if let Some(extra_callback_parameters) = upstream_session.extra_callback_parameters() {
+        context = context.with_extra_callback_parameters(extra_callback_parameters.clone());
+    }
+
+    Ok(context.build())
+}
+
+/// Evaluate the claims mapping policy, if one is configured, against the
+/// claims the upstream provider returned in the authorization session
+///
+/// # Errors
+///
+/// Returns an error if the ID token saved in the session can't be decoded, or
+/// if the policy evaluation fails
+async fn evaluate_claims_mapping(
+    policy: &mut Policy,
+    provider: &UpstreamOAuthProvider,
+    upstream_session: &UpstreamOAuthAuthorizationSession,
+) -> Result<Option<UpstreamOAuth2Mapping>, RouteError> {
+    let id_token = upstream_session
+        .id_token()
+        .map(Jwt::<HashMap<String, serde_json::Value>>::try_from)
+        .transpose()?
+        .map(|id_token| {
+            let (_, payload) = id_token.into_parts();
+            payload.into_iter().collect::<serde_json::Map<_, _>>()
+        });
+
+    let userinfo = match upstream_session.userinfo() {
+        Some(serde_json::Value::Object(userinfo)) => Some(userinfo),
+        _ => None,
+    };
+
+    let mapping = policy
+        .evaluate_upstream_oauth2_mapping(provider, id_token.as_ref(), userinfo)
+        .await?;
+
+    Ok(mapping)
+}
+
+/// Use the value the claims mapping policy computed for an attribute if it
+/// set one, and render the attribute template otherwise
+///
+/// # Errors
+///
+/// Returns an error if the attribute is required but the template fails to
+/// render or is empty
+fn map_attribute(
+    mapped: Option<&str>,
+    environment: &Environment,
+    template: &str,
+    context: &minijinja::Value,
+    required: bool,
+) -> Result<Option<String>, RouteError> {
+    match mapped {
+        Some(value) if !value.is_empty() => Ok(Some(value.to_owned())),
+        _ => render_attribute_template(environment, template, context, required),
+    }
+}
+
+/// Render the page shown to a user refused by the claims mapping policy
+fn denied_by_mapping_error(
+    templates: &Templates,
+    locale: &mas_i18n::DataLocale,
+    mapping: &UpstreamOAuth2Mapping,
+) -> Result<Response, RouteError> {
+    let violations = mapping
+        .violations
+        .iter()
+        .map(|violation| violation.msg.clone())
+        .collect();
+    let ctx = UpstreamDeniedContext::new(violations).with_language(locale.clone());
+
+    Ok((
+        StatusCode::FORBIDDEN,
+        Html(templates.render_upstream_oauth2_denied(&ctx)?),
+    )
+        .into_response())
+}
+
+/// Render the groups the upstream provider claims the user is a member of
+///
+/// The template can either render to a JSON list of strings, to a JSON string,
+/// or to a plain string which is taken as a single group.
+fn render_groups(
+    environment: &Environment,
+    template: &str,
+    context: &minijinja::Value,
+) -> Vec<String> {
+    let rendered = match environment.render_str(template, context) {
+        Ok(rendered) => rendered,
+        Err(source) => {
+            warn!(error = &source as &dyn std::error::Error, %template, "Error while rendering groups template");
+            return Vec::new();
+        }
+    };
+
+    let rendered = rendered.trim();
+    if rendered.is_empty() {
+        return Vec::new();
+    }
+
+    match serde_json::from_str(rendered) {
+        Ok(serde_json::Value::Array(values)) => values
+            .into_iter()
+            .filter_map(|value| match value {
+                serde_json::Value::String(group) => Some(group),
+                serde_json::Value::Null => None,
+                other => Some(other.to_string()),
+            })
+            .collect(),
+        Ok(serde_json::Value::String(group)) => vec![group],
+        Ok(serde_json::Value::Null) => Vec::new(),
+        Ok(other) => vec![other.to_string()],
+        Err(_) => vec![rendered.to_owned()],
+    }
+}
+
+/// Import the groups claimed by the upstream provider on the link, and update
+/// the admin rights and the lock of the [`User`] accordingly
+///
+/// Users who aren't a member of any of the required groups get locked. Users
+/// are never unlocked automatically, as they may have been locked for other
+/// reasons.
+///
+/// Returns the updated link and user
+///
+/// # Errors
+///
+/// Returns an error if the repository fails
+async fn sync_groups(
+    clock: &dyn Clock,
+    repo: &mut BoxRepository,
+    provider: &UpstreamOAuthProvider,
+    link: UpstreamOAuthLink,
+    context: &minijinja::Value,
+    user: User,
+) -> Result<(UpstreamOAuthLink, User), RouteError> {
+    let preference = &provider.claims_imports.groups;
+    if !preference.enabled() {
+        // Drop the groups which may have been imported with a previous configuration
+        let link = if link.groups.is_empty() {
+            link
+        } else {
+            repo.upstream_oauth_link()
+                .set_groups(link, Vec::new())
+                .await?
+        };
+        return Ok((link, user));
+    }
+
+    let template = preference
+        .template
+        .as_deref()
+        .unwrap_or(DEFAULT_GROUPS_TEMPLATE);
+    let groups = render_groups(&environment(), template, context);
+
+    let mut user = user;
+    if let Some(can_request_admin) = preference.can_request_admin(&groups) {
+        if user.can_request_admin != can_request_admin {
+            info!(
+                user.id = %user.id,
+                can_request_admin,
+                "Updating the admin rights of the user from the upstream provider groups"
+            );
+            user = repo
+                .user()
+                .set_can_request_admin(user, can_request_admin)
+                .await?;
+        }
+    }
+
+    if !preference.is_allowed(&groups) && user.locked_at.is_none() {
+        info!(
+            user.id = %user.id,
+            upstream_oauth_provider.id = %provider.id,
+            "Locking user who isn't a member of any of the required groups"
+        );
+        user = repo.user().lock(clock, user).await?;
+
+        repo.job()
+            .schedule_job(SendWebhookEventJob::user_locked(&user))
+            .await?;
+    }
+
+    let link = if link.groups == groups {
+        link
+    } else {
+        repo.upstream_oauth_link().set_groups(link, groups).await?
+    };
+
+    Ok((link, user))
+}
+
+/// Render the error shown to a user who got locked because they aren't a
+/// member of any of the groups required by the upstream provider
+fn locked_by_groups_error(
+    templates: &Templates,
+    locale: &mas_i18n::DataLocale,
+) -> Result<Response, RouteError> {
+    // TODO: translate
+    let ctx = ErrorContext::new()
+        .with_code("Account locked")
+        .with_description(
+            "Your account was locked, as the upstream account provider doesn't list you as a \
+            member of any of the groups required to use this service"
+                .to_owned(),
+        )
+        .with_language(locale);
+
+    Ok((StatusCode::FORBIDDEN, Html(templates.render_error(&ctx)?)).into_response())
+}
+
+/// Re-import the claims which should be synced on every login, for a user
+/// logging in through an existing link
+///
+/// The groups are imported again, the display name is pushed to the
+/// homeserver if it differs from the one it knows, and the email address
+/// previously imported through this link is replaced if it changed upstream.
+///
+/// Returns the updated user, which may have been locked if they lost the
+/// groups required by the provider
+///
+/// # Errors
+///
+/// Returns an error if a required attribute can't be rendered, or if the
+/// repository fails
+#[allow(clippy::too_many_arguments)]
+async fn sync_claims_on_login(
+    rng: &mut (dyn RngCore + Send),
+    clock: &dyn Clock,
+    repo: &mut BoxRepository,
+    homeserver: &BoxHomeserverConnection,
+    provider: &UpstreamOAuthProvider,
+    link: UpstreamOAuthLink,
+    upstream_session: &UpstreamOAuthAuthorizationSession,
+    mapping: Option<&UpstreamOAuth2Mapping>,
+    user: User,
+) -> Result<User, RouteError> {
+    let context = attribute_mapping_context(upstream_session)?;
+    let (link, user) = sync_groups(clock, repo, provider, link, &context, user).await?;
+
+    let claims_imports = &provider.claims_imports;
+    if !user.is_valid()
+        || (!claims_imports.displayname.sync_on_login && !claims_imports.email.sync_on_login)
+    {
+        return Ok(user);
+    }
+
+    let env = environment();
+
+    let mut job = ProvisionUserJob::new(&user);
+    let mut needs_provisioning = false;
+
+    if claims_imports.displayname.sync_on_login {
+        let template = claims_imports
+            .displayname
+            .template
+            .as_deref()
+            .unwrap_or(DEFAULT_DISPLAYNAME_TEMPLATE);
+
+        if let Some(display_name) = map_attribute(
+            mapping.and_then(|mapping| mapping.displayname.as_deref()),
+            &env,
+            template,
+            &context,
+            claims_imports.displayname.is_required(),
+        )? {
+            // Avoid provisioning the user again if the display name didn't change
+            let mxid = homeserver.mxid(&user.username);
+            let current = match homeserver.query_user(&mxid).await {
+                Ok(matrix_user) => matrix_user.displayname,
+                Err(err) => {
+                    warn!(
+                        error = &*err as &dyn std::error::Error,
+                        "Failed to query the user on the homeserver, pushing the display name anyway"
+                    );
+                    None
+                }
+            };
+
+            if current.as_deref() != Some(display_name.as_str()) {
+                job = job.set_display_name(display_name);
+                needs_provisioning = true;
+            }
+        }
+    }
+
+    if claims_imports.email.sync_on_login {
+        let template = claims_imports
+            .email
+            .template
+            .as_deref()
+            .unwrap_or(DEFAULT_EMAIL_TEMPLATE);
+
+        if let Some(email) = map_attribute(
+            mapping.and_then(|mapping| mapping.emails.first().map(String::as_str)),
+            &env,
+            template,
+            &context,
+            claims_imports.email.is_required(),
+        )? {
+            // Is the email verified according to the upstream provider?
+            let provider_email_verified = env
+                .render_str("{{ user.email_verified | string }}", &context)
+                .is_ok_and(|v| v == "true");
+            let mark_as_verified = claims_imports
+                .verify_email
+                .should_mark_as_verified(provider_email_verified);
+
+            needs_provisioning |=
+                sync_imported_email(rng, clock, repo, link, &user, email, mark_as_verified).await?;
+        }
+    }
+
+    if needs_provisioning {
+        repo.job().schedule_job(job).await?;
+    }
+
+    Ok(user)
+}
+
+/// Replace the email address imported through the link with the one the
+/// upstream provider currently returns
+///
+/// Returns `true` if the user's email addresses changed
+///
+/// # Errors
+///
+/// Returns an error if the repository fails
+async fn sync_imported_email(
+    rng: &mut (dyn RngCore + Send),
+    clock: &dyn Clock,
+    repo: &mut BoxRepository,
+    link: UpstreamOAuthLink,
+    user: &User,
+    email: String,
+    mark_as_verified: bool,
+) -> Result<bool, RouteError> {
+    let previous = if let Some(id) = link.imported_user_email_id {
+        repo.user_email().lookup(id).await?
+    } else {
+        None
+    };
+
+    let previous = match previous {
+        // The email address didn't change upstream, but it may have been verified
+        // in the meantime
+        Some(previous) if previous.email == email => {
+            if previous.confirmed_at.is_some() || !mark_as_verified {
+                return Ok(false);
+            }
+
+            let previous = repo.user_email().mark_as_verified(clock, previous).await?;
+            repo.job()
+                .schedule_job(SendWebhookEventJob::user_email_verified(user, &previous))
+                .await?;
+            if user.primary_user_email_id.is_none() {
+                repo.user_email().set_as_primary(&previous).await?;
+            }
+            return Ok(true);
+        }
+        previous => previous,
+    };
+
+    // The user may already have this email address
+    let existing = repo.user_email().find(user, &email).await?;
+    let user_email = match existing {
+        Some(user_email) => user_email,
+        None => repo.user_email().add(rng, clock, user, email).await?,
+    };
+
+    let user_email = if user_email.confirmed_at.is_none() && mark_as_verified {
+        let user_email = repo
+            .user_email()
+            .mark_as_verified(clock, user_email)
+            .await?;
+        repo.job()
+            .schedule_job(SendWebhookEventJob::user_email_verified(user, &user_email))
+            .await?;
+        user_email
+    } else {
+        user_email
+    };
+
+    let was_primary = previous
+        .as_ref()
+        .is_some_and(|previous| user.primary_user_email_id == Some(previous.id));
+
+    if user_email.confirmed_at.is_some() && (was_primary || user.primary_user_email_id.is_none()) {
+        repo.user_email().set_as_primary(&user_email).await?;
+    }
+
+    if let Some(previous) = previous {
+        if was_primary && user_email.confirmed_at.is_none() {
+            // The primary email address can't be replaced by one which isn't
+            // verified, so keep it around
+            warn!(
+                user.id = %user.id,
+                user_email.id = %previous.id,
+                "Not removing the previously imported primary email address, as the new one isn't verified"
+            );
+        } else {
+            repo.user_email().remove(previous).await?;
+        }
+    }
+
+    repo.upstream_oauth_link()
+        .set_imported_email(link, Some(&user_email))
+        .await?;
+
+    Ok(true)
+}
+
+/// Record in the audit log that the user logged in through the upstream
+/// provider, and whether a new browser session was created for them
+///
+/// # Errors
+///
+/// Returns an error if the repository fails
+#[allow(clippy::too_many_arguments)]
+async fn record_login(
+    rng: &mut (dyn RngCore + Send),
+    clock: &dyn Clock,
+    repo: &mut BoxRepository,
+    activity_tracker: &BoundActivityTracker,
+    user_agent: Option<&UserAgent>,
+    upstream_session: &UpstreamOAuthAuthorizationSession,
+    session: &BrowserSession,
+    new_session: bool,
+) -> Result<(), RouteError> {
+    let kinds: &[AuditLogEventKind] = if new_session {
+        &[
+            AuditLogEventKind::LoginSucceeded,
+            AuditLogEventKind::SessionCreated,
+        ]
+    } else {
+        &[AuditLogEventKind::LoginSucceeded]
+    };
+
+    for kind in kinds {
+        repo.audit_log()
+            .add(
+                rng,
+                clock,
+                NewAuditLogEvent::new(*kind)
+                    .for_user(&session.user)
+                    .with_ip_address(activity_tracker.ip())
+                    .with_user_agent(user_agent)
+                    .with_data(json!({
+                        "method": "upstream_oauth2",
+                        "upstream_oauth_provider_id": upstream_session.provider_id,
+                        "session_type": "browser",
+                        "session_id": session.id,
+                    })),
+            )
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Start an account recovery for the user who proved they own the linked
+/// upstream account by signing in with the provider, and send them to the page
+/// where they can set a new password
+///
+/// # Errors
+///
+/// Returns an error if the repository fails
+#[allow(clippy::too_many_arguments)]
+async fn start_recovery(
+    rng: &mut BoxRng,
+    clock: &dyn Clock,
+    repo: &mut BoxRepository,
+    url_builder: &UrlBuilder,
+    link: &UpstreamOAuthLink,
+    user: &User,
+    user_agent: UserAgent,
+    activity_tracker: &BoundActivityTracker,
+    locale: &mas_i18n::DataLocale,
+) -> Result<Response, RouteError> {
+    let recovery_session = repo
+        .user_recovery()
+        .add_upstream_session(
+            rng,
+            clock,
+            user_agent,
+            activity_tracker.ip(),
+            locale.to_string(),
+        )
+        .await?;
+
+    let ticket = Alphanumeric.sample_string(rng, 32);
+    let ticket = repo
+        .user_recovery()
+        .add_upstream_ticket(rng, clock, &recovery_session, user, link, ticket)
+        .await?;
+
+    info!(
+        user.id = %user.id,
+        upstream_oauth_link.id = %link.id,
+        user_recovery_session.id = %recovery_session.id,
+        "Starting account recovery through an upstream provider"
+    );
+
+    Ok(url_builder
+        .redirect(&mas_router::AccountRecoveryFinish::new(ticket.ticket))
+        .into_response())
+}
+
 #[derive(Deserialize, Serialize)]
 #[serde(rename_all = "lowercase", tag = "action")]
 pub(crate) enum FormData {
@@ -175,6 +720,8 @@ pub(crate) enum FormData {
         import_display_name: Option<String>,
         #[serde(default)]
         accept_terms: Option<String>,
+        #[serde(default)]
+        registration_token: Option<String>,
     },
     Link,
 }
@@ -194,10 +741,12 @@ pub(crate) async fn get(
     clock: BoxClock,
     mut repo: BoxRepository,
     mut policy: Policy,
+    activity_tracker: BoundActivityTracker,
     PreferredLanguage(locale): PreferredLanguage,
     State(templates): State<Templates>,
     State(url_builder): State<UrlBuilder>,
     State(homeserver): State<BoxHomeserverConnection>,
+    State(site_config): State<SiteConfig>,
     cookie_jar: CookieJar,
     user_agent: Option<TypedHeader<headers::UserAgent>>,
     Path(link_id): Path<Ulid>,
@@ -208,6 +757,9 @@ pub(crate) async fn get(
         .lookup_link(link_id)
         .map_err(|_| RouteError::MissingCookie)?;
 
+    // The user signed in with the provider to recover their account
+    let recovering = matches!(post_auth_action, Some(PostAuthAction::RecoverAccount));
+
     let post_auth_action = OptionalPostAuthAction {
         post_auth_action: post_auth_action.cloned(),
     };
@@ -234,6 +786,27 @@ pub(crate) async fn get(
         return Err(RouteError::SessionConsumed);
     }
 
+    let provider = repo
+        .upstream_oauth_provider()
+        .lookup(link.provider_id)
+        .await?
+        .ok_or(RouteError::ProviderNotFound)?;
+
+    // The claims mapping policy may refuse the upstream account altogether
+    let mapping = evaluate_claims_mapping(&mut policy, &provider, &upstream_session).await?;
+    if let Some(mapping) = mapping.as_ref().filter(|mapping| !mapping.valid()) {
+        return Ok((
+            cookie_jar,
+            denied_by_mapping_error(&templates, &locale, mapping)?,
+        ));
+    }
+
+    if recovering && !site_config.account_recovery_allowed {
+        let context = EmptyContext.with_language(locale);
+        let rendered = templates.render_recovery_disabled(&context)?;
+        return Ok((cookie_jar, Html(rendered).into_response()));
+    }
+
     let (user_session_info, cookie_jar) = cookie_jar.session_info();
     let (csrf_token, mut cookie_jar) = cookie_jar.csrf_token(&clock, &mut rng);
     let maybe_user_session = user_session_info.load_session(&mut repo).await?;
@@ -242,6 +815,25 @@ pub(crate) async fn get(
         (Some(session), Some(user_id)) if session.user.id == user_id => {
             // Session already linked, and link matches the currently logged
             // user. Mark the session as consumed and renew the authentication.
+            let user = sync_claims_on_login(
+                &mut rng,
+                &clock,
+                &mut repo,
+                &homeserver,
+                &provider,
+                link,
+                &upstream_session,
+                mapping.as_ref(),
+                session.user.clone(),
+            )
+            .await?;
+
+            if !user.is_valid() {
+                // The lock has to be saved, even though the login is refused
+                repo.save().await?;
+                return Ok((cookie_jar, locked_by_groups_error(&templates, &locale)?));
+            }
+
             let upstream_session = repo
                 .upstream_oauth_session()
                 .consume(&clock, upstream_session)
@@ -251,6 +843,18 @@ pub(crate) async fn get(
                 .authenticate_with_upstream(&mut rng, &clock, &session, &upstream_session)
                 .await?;
 
+            record_login(
+                &mut rng,
+                &clock,
+                &mut repo,
+                &activity_tracker,
+                user_agent.as_ref(),
+                &upstream_session,
+                &session,
+                false,
+            )
+            .await?;
+
             cookie_jar = cookie_jar.set_session(&session);
 
             repo.save().await?;
@@ -288,6 +892,77 @@ pub(crate) async fn get(
             Html(templates.render_upstream_oauth2_suggest_link(&ctx)?).into_response()
         }
 
+        (None, Some(user_id)) if recovering => {
+            // Session linked, and the user is recovering their account: let them set a
+            // new password instead of logging them in
+            let user = repo
+                .user()
+                .lookup(user_id)
+                .await?
+                .filter(User::is_valid)
+                .ok_or(RouteError::UserNotFound)?;
+
+            // The recovery is subject to the same group requirements as the login
+            let user = sync_claims_on_login(
+                &mut rng,
+                &clock,
+                &mut repo,
+                &homeserver,
+                &provider,
+                link.clone(),
+                &upstream_session,
+                mapping.as_ref(),
+                user,
+            )
+            .await?;
+
+            if !user.is_valid() {
+                // The lock has to be saved, even though the recovery is refused
+                repo.save().await?;
+                return Ok((cookie_jar, locked_by_groups_error(&templates, &locale)?));
+            }
+
+            let response = start_recovery(
+                &mut rng,
+                &clock,
+                &mut repo,
+                &url_builder,
+                &link,
+                &user,
+                user_agent.unwrap_or_else(|| UserAgent::parse(String::new())),
+                &activity_tracker,
+                &locale,
+            )
+            .await?;
+
+            repo.upstream_oauth_session()
+                .consume(&clock, upstream_session)
+                .await?;
+
+            cookie_jar = sessions_cookie
+                .consume_link(link_id)?
+                .save(cookie_jar, &clock);
+
+            repo.save().await?;
+
+            response
+        }
+
+        (None, None) if recovering => {
+            // Session not linked, so it can't be used to recover an account
+            // TODO: translate
+            let ctx = ErrorContext::new()
+                .with_code("Account not found")
+                .with_description(
+                    "This upstream account isn't linked to any account on this service, so it \
+                    can't be used to recover one"
+                        .to_owned(),
+                )
+                .with_language(&locale);
+
+            (StatusCode::NOT_FOUND, Html(templates.render_error(&ctx)?)).into_response()
+        }
+
         (None, Some(user_id)) => {
             // Session linked, but user not logged in: do the login
             let user = repo
@@ -297,10 +972,53 @@ pub(crate) async fn get(
                 .filter(mas_data_model::User::is_valid)
                 .ok_or(RouteError::UserNotFound)?;
 
+            let user = sync_claims_on_login(
+                &mut rng,
+                &clock,
+                &mut repo,
+                &homeserver,
+                &provider,
+                link,
+                &upstream_session,
+                mapping.as_ref(),
+                user,
+            )
+            .await?;
+
+            if !user.is_valid() {
+                // The lock has to be saved, even though the login is refused
+                repo.save().await?;
+                return Ok((cookie_jar, locked_by_groups_error(&templates, &locale)?));
+            }
+
+            // This has to be checked before the new session is created
+            let ip = activity_tracker.ip();
+            let familiar =
+                new_sign_in::is_familiar(&mut repo, &user, user_agent.as_ref(), ip).await?;
+
             let session = repo
                 .browser_session()
-                .add(&mut rng, &clock, &user, user_agent)
+                .add(&mut rng, &clock, &user, user_agent.clone())
+                .await?;
+
+            repo.job()
+                .schedule_job(SendWebhookEventJob::session_started(
+                    user.id,
+                    NewSignInSession::Browser(session.id),
+                ))
+                .await?;
+
+            if !familiar {
+                new_sign_in::notify(
+                    &mut repo,
+                    &user,
+                    NewSignInSession::Browser(session.id),
+                    ip,
+                    activity_tracker.location(),
+                    Some(locale.to_string()),
+                )
                 .await?;
+            }
 
             let upstream_session = repo
                 .upstream_oauth_session()
@@ -311,6 +1029,18 @@ pub(crate) async fn get(
                 .authenticate_with_upstream(&mut rng, &clock, &session, &upstream_session)
                 .await?;
 
+            record_login(
+                &mut rng,
+                &clock,
+                &mut repo,
+                &activity_tracker,
+                user_agent.as_ref(),
+                &upstream_session,
+                &session,
+                true,
+            )
+            .await?;
+
             cookie_jar = sessions_cookie
                 .consume_link(link_id)?
                 .save(cookie_jar, &clock);
@@ -324,27 +1054,11 @@ pub(crate) async fn get(
         (None, None) => {
             // Session not linked and used not logged in: suggest creating an
             // account or logging in an existing user
-            let id_token = upstream_session.id_token().map(Jwt::try_from).transpose()?;
-
-            let provider = repo
-                .upstream_oauth_provider()
-                .lookup(link.provider_id)
-                .await?
-                .ok_or(RouteError::ProviderNotFound)?;
-
             let ctx = UpstreamRegister::default();
 
             let env = environment();
 
-            let mut context = AttributeMappingContext::new();
-            if let Some(id_token) = id_token {
-                let (_, payload) = id_token.into_parts();
-                context = context.with_id_token_claims(payload);
-            }
-            if let Some(extra_callback_parameters) = upstream_session.extra_callback_parameters() {
-                context = context.with_extra_callback_parameters(extra_callback_parameters.clone());
-            }
-            let context = context.build();
+            let context = attribute_mapping_context(&upstream_session)?;
 
             let ctx = if provider.claims_imports.displayname.ignore() {
                 ctx
@@ -356,7 +1070,10 @@ pub(crate) async fn get(
                     .as_deref()
                     .unwrap_or(DEFAULT_DISPLAYNAME_TEMPLATE);
 
-                match render_attribute_template(
+                match map_attribute(
+                    mapping
+                        .as_ref()
+                        .and_then(|mapping| mapping.displayname.as_deref()),
                     &env,
                     template,
                     &context,
@@ -378,7 +1095,10 @@ pub(crate) async fn get(
                     .as_deref()
                     .unwrap_or(DEFAULT_EMAIL_TEMPLATE);
 
-                match render_attribute_template(
+                match map_attribute(
+                    mapping
+                        .as_ref()
+                        .and_then(|mapping| mapping.emails.first().map(String::as_str)),
                     &env,
                     template,
                     &context,
@@ -399,7 +1119,10 @@ pub(crate) async fn get(
                     .as_deref()
                     .unwrap_or(DEFAULT_LOCALPART_TEMPLATE);
 
-                match render_attribute_template(
+                match map_attribute(
+                    mapping
+                        .as_ref()
+                        .and_then(|mapping| mapping.localpart.as_deref()),
                     &env,
                     template,
                     &context,
@@ -437,8 +1160,12 @@ pub(crate) async fn get(
                             ));
                         }
 
+                        let emails = mapping
+                            .as_ref()
+                            .map(|mapping| mapping.emails.as_slice())
+                            .unwrap_or_default();
                         let res = policy
-                            .evaluate_upstream_oauth_register(&localpart, None)
+                            .evaluate_upstream_oauth_register(&localpart, None, emails)
                             .await?;
 
                         if !res.valid() {
@@ -484,6 +1211,7 @@ pub(crate) async fn post(
     mut repo: BoxRepository,
     cookie_jar: CookieJar,
     user_agent: Option<TypedHeader<headers::UserAgent>>,
+    activity_tracker: BoundActivityTracker,
     mut policy: Policy,
     PreferredLanguage(locale): PreferredLanguage,
     State(templates): State<Templates>,
@@ -527,12 +1255,28 @@ pub(crate) async fn post(
         return Err(RouteError::SessionConsumed);
     }
 
+    let provider = repo
+        .upstream_oauth_provider()
+        .lookup(link.provider_id)
+        .await?
+        .ok_or(RouteError::ProviderNotFound)?;
+
+    // The claims mapping policy may refuse the upstream account altogether
+    let mapping = evaluate_claims_mapping(&mut policy, &provider, &upstream_session).await?;
+    if let Some(mapping) = mapping.as_ref().filter(|mapping| !mapping.valid()) {
+        return Ok((
+            cookie_jar,
+            denied_by_mapping_error(&templates, &locale, mapping)?,
+        )
+            .into_response());
+    }
+
     let (csrf_token, cookie_jar) = cookie_jar.csrf_token(&clock, &mut rng);
     let (user_session_info, cookie_jar) = cookie_jar.session_info();
     let maybe_user_session = user_session_info.load_session(&mut repo).await?;
     let form_state = form.to_form_state();
 
-    let session = match (maybe_user_session, link.user_id, form) {
+    let (session, new_session) = match (maybe_user_session, link.user_id, form) {
         (Some(session), None, FormData::Link) => {
             // The user is already logged in, the link is not linked to any user, and the
             // user asked to link their account.
@@ -540,7 +1284,24 @@ pub(crate) async fn post(
                 .associate_to_user(&link, &session.user)
                 .await?;
 
-            session
+            let context = attribute_mapping_context(&upstream_session)?;
+            let (_link, user) = sync_groups(
+                &clock,
+                &mut repo,
+                &provider,
+                link.clone(),
+                &context,
+                session.user.clone(),
+            )
+            .await?;
+
+            if !user.is_valid() {
+                // The lock has to be saved, even though the linking is refused
+                repo.save().await?;
+                return locked_by_groups_error(&templates, &locale);
+            }
+
+            (session, false)
         }
 
         (
@@ -551,6 +1312,7 @@ pub(crate) async fn post(
                 import_email,
                 import_display_name,
                 accept_terms,
+                registration_token,
             },
         ) => {
             // The user got the form to register a new account, and is not logged in.
@@ -563,32 +1325,48 @@ pub(crate) async fn post(
             let import_display_name = import_display_name.is_some();
             let accept_terms = accept_terms.is_some();
 
-            let id_token = upstream_session.id_token().map(Jwt::try_from).transpose()?;
-
-            let provider = repo
-                .upstream_oauth_provider()
-                .lookup(link.provider_id)
-                .await?
-                .ok_or(RouteError::ProviderNotFound)?;
-
-            // Let's try to import the claims from the ID token
+            // Let's try to import the claims from the ID token and the userinfo endpoint
             let env = environment();
 
-            let mut context = AttributeMappingContext::new();
-            if let Some(id_token) = id_token {
-                let (_, payload) = id_token.into_parts();
-                context = context.with_id_token_claims(payload);
-            }
-            if let Some(extra_callback_parameters) = upstream_session.extra_callback_parameters() {
-                context = context.with_extra_callback_parameters(extra_callback_parameters.clone());
-            }
-            let context = context.build();
+            let context = attribute_mapping_context(&upstream_session)?;
 
             // Is the email verified according to the upstream provider?
             let provider_email_verified = env
                 .render_str("{{ user.email_verified | string }}", &context)
                 .map_or(false, |v| v == "true");
 
+            // Users who aren't members of the required groups can't register
+            let groups = if provider.claims_imports.groups.enabled() {
+                let template = provider
+                    .claims_imports
+                    .groups
+                    .template
+                    .as_deref()
+                    .unwrap_or(DEFAULT_GROUPS_TEMPLATE);
+                render_groups(&env, template, &context)
+            } else {
+                Vec::new()
+            };
+
+            if !provider.claims_imports.groups.is_allowed(&groups) {
+                // TODO: translate
+                let ctx = ErrorContext::new()
+                    .with_code("Registration not allowed")
+                    .with_description(
+                        "The upstream account provider doesn't list you as a member of any of \
+                        the groups required to use this service"
+                            .to_owned(),
+                    )
+                    .with_language(&locale);
+
+                return Ok((
+                    StatusCode::FORBIDDEN,
+                    cookie_jar,
+                    Html(templates.render_error(&ctx)?),
+                )
+                    .into_response());
+            }
+
             // Create a template context in case we need to re-render because of an error
             let ctx = UpstreamRegister::default();
 
@@ -604,7 +1382,10 @@ pub(crate) async fn post(
                     .as_deref()
                     .unwrap_or(DEFAULT_DISPLAYNAME_TEMPLATE);
 
-                render_attribute_template(
+                map_attribute(
+                    mapping
+                        .as_ref()
+                        .and_then(|mapping| mapping.displayname.as_deref()),
                     &env,
                     template,
                     &context,
@@ -631,7 +1412,10 @@ pub(crate) async fn post(
                     .as_deref()
                     .unwrap_or(DEFAULT_EMAIL_TEMPLATE);
 
-                render_attribute_template(
+                map_attribute(
+                    mapping
+                        .as_ref()
+                        .and_then(|mapping| mapping.emails.first().map(String::as_str)),
                     &env,
                     template,
                     &context,
@@ -655,7 +1439,10 @@ pub(crate) async fn post(
                     .as_deref()
                     .unwrap_or(DEFAULT_LOCALPART_TEMPLATE);
 
-                render_attribute_template(
+                map_attribute(
+                    mapping
+                        .as_ref()
+                        .and_then(|mapping| mapping.localpart.as_deref()),
                     &env,
                     template,
                     &context,
@@ -740,12 +1527,53 @@ pub(crate) async fn post(
                     .into_response());
             }
 
-            // Policy check
-            let res = policy
-                .evaluate_upstream_oauth_register(&username, email.as_deref())
-                .await?;
-            if !res.valid() {
-                let form_state =
+            // If the site requires a registration token, make sure the user gave a valid
+            // one
+            let registration_token = if site_config.registration_token_required {
+                let registration_token = match registration_token.filter(|t| !t.is_empty()) {
+                    None => Err(FieldError::Required),
+                    Some(token) => {
+                        match repo.user_registration_token().find_by_token(&token).await? {
+                            Some(token) if token.is_valid(clock.now()) => Ok(token),
+                            _ => Err(FieldError::Invalid),
+                        }
+                    }
+                };
+
+                match registration_token {
+                    Ok(registration_token) => Some(registration_token),
+                    Err(error) => {
+                        let form_state = form_state.with_error_on_field(
+                            mas_templates::UpstreamRegisterFormField::RegistrationToken,
+                            error,
+                        );
+
+                        let ctx = ctx
+                            .with_form_state(form_state)
+                            .with_csrf(csrf_token.form_value())
+                            .with_language(locale);
+                        return Ok((
+                            cookie_jar,
+                            Html(templates.render_upstream_oauth2_do_register(&ctx)?),
+                        )
+                            .into_response());
+                    }
+                }
+            } else {
+                None
+            };
+
+            // Policy check, with all the email addresses the claims mapping policy found,
+            // even if they are not imported
+            let emails = mapping
+                .as_ref()
+                .map(|mapping| mapping.emails.as_slice())
+                .unwrap_or_default();
+            let res = policy
+                .evaluate_upstream_oauth_register(&username, email.as_deref(), emails)
+                .await?;
+            if !res.valid() {
+                let form_state =
                     res.violations
                         .into_iter()
                         .fold(form_state, |form_state, violation| {
@@ -774,7 +1602,43 @@ pub(crate) async fn post(
             }
 
             // Now we can create the user
-            let user = repo.user().add(&mut rng, &clock, username).await?;
+            let mut user = repo.user().add(&mut rng, &clock, username).await?;
+
+            if let Some(registration_token) = registration_token {
+                // The token is checked again when using it, as it may have been used up or
+                // revoked in the meantime. In this case, nothing is saved.
+                let used = repo
+                    .user_registration_token()
+                    .use_token(&clock, registration_token, &user)
+                    .await?;
+
+                if used.is_none() {
+                    let form_state = form_state.with_error_on_field(
+                        mas_templates::UpstreamRegisterFormField::RegistrationToken,
+                        FieldError::Invalid,
+                    );
+
+                    let ctx = ctx
+                        .with_form_state(form_state)
+                        .with_csrf(csrf_token.form_value())
+                        .with_language(locale);
+                    return Ok((
+                        cookie_jar,
+                        Html(templates.render_upstream_oauth2_do_register(&ctx)?),
+                    )
+                        .into_response());
+                }
+            }
+
+            if provider.claims_imports.groups.can_request_admin(&groups) == Some(true) {
+                user = repo.user().set_can_request_admin(user, true).await?;
+            }
+
+            if !groups.is_empty() {
+                repo.upstream_oauth_link()
+                    .set_groups(link.clone(), groups)
+                    .await?;
+            }
 
             if let Some(terms_url) = &site_config.tos_uri {
                 repo.user_terms()
@@ -792,12 +1656,23 @@ pub(crate) async fn post(
 
             repo.job().schedule_job(job).await?;
 
+            repo.job()
+                .schedule_job(SendWebhookEventJob::user_registered(&user))
+                .await?;
+
             // If we have an email, add it to the user
             if let Some(email) = email {
                 let user_email = repo
                     .user_email()
                     .add(&mut rng, &clock, &user, email)
                     .await?;
+
+                // Remember which email was imported, so that it can be replaced if it
+                // changes upstream
+                repo.upstream_oauth_link()
+                    .set_imported_email(link.clone(), Some(&user_email))
+                    .await?;
+
                 // Mark the email as verified according to the policy and whether the provider
                 // claims it is, and make it the primary email.
                 if provider
@@ -811,6 +1686,10 @@ pub(crate) async fn post(
                         .await?;
 
                     repo.user_email().set_as_primary(&user_email).await?;
+
+                    repo.job()
+                        .schedule_job(SendWebhookEventJob::user_email_verified(&user, &user_email))
+                        .await?;
                 }
             }
 
@@ -818,14 +1697,24 @@ pub(crate) async fn post(
                 .associate_to_user(&link, &user)
                 .await?;
 
-            repo.browser_session()
-                .add(&mut rng, &clock, &user, user_agent)
-                .await?
+            let session = repo
+                .browser_session()
+                .add(&mut rng, &clock, &user, user_agent.clone())
+                .await?;
+
+            (session, true)
         }
 
         _ => return Err(RouteError::InvalidFormAction),
     };
 
+    repo.job()
+        .schedule_job(SendWebhookEventJob::session_started(
+            session.user.id,
+            NewSignInSession::Browser(session.id),
+        ))
+        .await?;
+
     let upstream_session = repo
         .upstream_oauth_session()
         .consume(&clock, upstream_session)
@@ -835,6 +1724,18 @@ pub(crate) async fn post(
         .authenticate_with_upstream(&mut rng, &clock, &session, &upstream_session)
         .await?;
 
+    record_login(
+        &mut rng,
+        &clock,
+        &mut repo,
+        &activity_tracker,
+        user_agent.as_ref(),
+        &upstream_session,
+        &session,
+        new_session,
+    )
+    .await?;
+
     let cookie_jar = sessions_cookie
         .consume_link(link_id)?
         .save(cookie_jar, &clock);
@@ -847,20 +1748,128 @@ pub(crate) async fn post(
 
 #[cfg(test)]
 mod tests {
-    use hyper::{header::CONTENT_TYPE, Request, StatusCode};
+    use chrono::Duration;
+    use hyper::{
+        header::{CONTENT_TYPE, LOCATION},
+        Request, StatusCode,
+    };
     use mas_data_model::{
-        UpstreamOAuthProviderClaimsImports, UpstreamOAuthProviderImportPreference,
-        UpstreamOAuthProviderTokenAuthMethod,
+        AuditLogEventKind, UpstreamOAuthLink, UpstreamOAuthProvider,
+        UpstreamOAuthProviderClaimsImports, UpstreamOAuthProviderGroupsPreference,
+        UpstreamOAuthProviderImportPreference, UpstreamOAuthProviderTokenAuthMethod,
     };
     use mas_iana::jose::JsonWebSignatureAlg;
     use mas_jose::jwt::{JsonWebSignatureHeader, Jwt};
-    use mas_router::Route;
-    use mas_storage::upstream_oauth2::UpstreamOAuthProviderParams;
+    use mas_matrix::{HomeserverConnection, ProvisionRequest};
+    use mas_router::{PostAuthAction, Route};
+    use mas_storage::{
+        audit_log::AuditLogFilter, upstream_oauth2::UpstreamOAuthProviderParams, Clock, Pagination,
+    };
     use oauth2_types::scope::{Scope, OPENID};
-    use sqlx::PgPool;
+    use rand::distributions::{Alphanumeric, DistString};
+    use sqlx::{types::Json, PgPool};
 
-    use super::UpstreamSessionsCookie;
-    use crate::test_utils::{setup, CookieHelper, RequestBuilderExt, ResponseExt, TestState};
+    use super::{
+        environment, render_groups, AttributeMappingContext, UpstreamSessionsCookie,
+        DEFAULT_GROUPS_TEMPLATE,
+    };
+    use crate::{
+        test_utils::{
+            policy_factory, setup, test_site_config, test_upstream_oauth_provider_params,
+            CookieHelper, RequestBuilderExt, ResponseExt, TestState,
+        },
+        SiteConfig,
+    };
+
+    /// Complete an upstream authorization session for the link, with an ID
+    /// token carrying the given claims, and get the cookies needed to go
+    /// through the link page
+    async fn complete_upstream_session(
+        state: &TestState,
+        provider: &UpstreamOAuthProvider,
+        link: &UpstreamOAuthLink,
+        claims: serde_json::Value,
+        post_auth_action: Option<PostAuthAction>,
+    ) -> CookieHelper {
+        let mut rng = state.rng();
+
+        let key = state
+            .key_store
+            .signing_key_for_algorithm(&JsonWebSignatureAlg::Rs256)
+            .unwrap();
+        let signer = key
+            .params()
+            .signing_key_for_alg(&JsonWebSignatureAlg::Rs256)
+            .unwrap();
+        let header = JsonWebSignatureHeader::new(JsonWebSignatureAlg::Rs256);
+        let id_token = Jwt::sign_with_rng(&mut rng, header, claims, &signer).unwrap();
+        let state_str = Alphanumeric.sample_string(&mut rng, 16);
+
+        let mut repo = state.repository().await.unwrap();
+        let session = repo
+            .upstream_oauth_session()
+            .add(
+                &mut rng,
+                &state.clock,
+                provider,
+                state_str.clone(),
+                None,
+                "nonce".to_owned(),
+            )
+            .await
+            .unwrap();
+        let session = repo
+            .upstream_oauth_session()
+            .complete_with_link(
+                &state.clock,
+                session,
+                link,
+                Some(id_token.into_string()),
+                None,
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let cookies = CookieHelper::new();
+        let upstream_sessions = UpstreamSessionsCookie::default()
+            .add(session.id, provider.id, state_str, post_auth_action)
+            .add_link_to_session(session.id, link.id)
+            .unwrap();
+        cookies.import(upstream_sessions.save(state.cookie_jar(), &state.clock));
+        cookies
+    }
+
+    #[test]
+    fn test_render_groups() {
+        let env = environment();
+        let context = AttributeMappingContext::new()
+            .with_id_token_claims(
+                [
+                    ("groups".to_owned(), serde_json::json!(["admins", "users"])),
+                    ("role".to_owned(), serde_json::json!("staff")),
+                ]
+                .into(),
+            )
+            .build();
+
+        assert_eq!(
+            render_groups(&env, DEFAULT_GROUPS_TEMPLATE, &context),
+            vec!["admins".to_owned(), "users".to_owned()]
+        );
+        assert_eq!(
+            render_groups(&env, "{{ user.role }}", &context),
+            vec!["staff".to_owned()]
+        );
+        assert_eq!(
+            render_groups(&env, "{{ user.role | tojson }}", &context),
+            vec!["staff".to_owned()]
+        );
+        assert!(render_groups(&env, "{{ user.missing }}", &context).is_empty());
+        assert!(render_groups(&env, "{{ user.missing | tojson }}", &context).is_empty());
+    }
 
     #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
     async fn test_register(pool: PgPool) {
@@ -873,10 +1882,12 @@ mod tests {
             localpart: UpstreamOAuthProviderImportPreference {
                 action: mas_data_model::UpstreamOAuthProviderImportAction::Force,
                 template: None,
+                sync_on_login: false,
             },
             email: UpstreamOAuthProviderImportPreference {
                 action: mas_data_model::UpstreamOAuthProviderImportAction::Force,
                 template: None,
+                sync_on_login: false,
             },
             ..UpstreamOAuthProviderClaimsImports::default()
         };
@@ -921,6 +1932,11 @@ mod tests {
                     claims_imports,
                     authorization_endpoint_override: None,
                     token_endpoint_override: None,
+                    userinfo_endpoint_override: None,
+                    fetch_userinfo: false,
+                    store_tokens: false,
+                    token_exchange_clients: Vec::new(),
+                    domains: Vec::new(),
                     jwks_uri_override: None,
                     discovery_mode: mas_data_model::UpstreamOAuthProviderDiscoveryMode::Oidc,
                     pkce_mode: mas_data_model::UpstreamOAuthProviderPkceMode::Auto,
@@ -958,6 +1974,8 @@ mod tests {
                 &link,
                 Some(id_token.into_string()),
                 None,
+                None,
+                None,
             )
             .await
             .unwrap();
@@ -1029,5 +2047,570 @@ mod tests {
 
         assert_eq!(email.email, "john@example.com");
         assert!(email.confirmed_at.is_some());
+
+        // The login and the new session were recorded in the audit log
+        let events = repo
+            .audit_log()
+            .list(AuditLogFilter::new().for_user(&user), Pagination::first(10))
+            .await
+            .unwrap();
+        let mut kinds: Vec<_> = events.edges.iter().map(|event| event.kind).collect();
+        kinds.sort_by_key(ToString::to_string);
+        assert_eq!(
+            kinds,
+            [
+                AuditLogEventKind::LoginSucceeded,
+                AuditLogEventKind::SessionCreated
+            ]
+        );
+        for event in &events.edges {
+            assert_eq!(event.data["method"], "upstream_oauth2");
+            assert_eq!(
+                event.data["upstream_oauth_provider_id"],
+                provider.id.to_string()
+            );
+        }
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_register_registration_token(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool_with_site_config(
+            pool,
+            SiteConfig {
+                registration_token_required: true,
+                ..test_site_config()
+            },
+        )
+        .await
+        .unwrap();
+        let mut rng = state.rng();
+
+        // Provision a provider which forces the username, a link and a few tokens
+        let mut repo = state.repository().await.unwrap();
+        let provider = repo
+            .upstream_oauth_provider()
+            .add(
+                &mut rng,
+                &state.clock,
+                UpstreamOAuthProviderParams {
+                    claims_imports: UpstreamOAuthProviderClaimsImports {
+                        localpart: UpstreamOAuthProviderImportPreference {
+                            action: mas_data_model::UpstreamOAuthProviderImportAction::Force,
+                            template: None,
+                            sync_on_login: false,
+                        },
+                        ..UpstreamOAuthProviderClaimsImports::default()
+                    },
+                    ..test_upstream_oauth_provider_params()
+                },
+            )
+            .await
+            .unwrap();
+
+        let link = repo
+            .upstream_oauth_link()
+            .add(&mut rng, &state.clock, &provider, "subject".to_owned())
+            .await
+            .unwrap();
+
+        let token = repo
+            .user_registration_token()
+            .add(&mut rng, &state.clock, "invite".to_owned(), Some(2), None)
+            .await
+            .unwrap();
+
+        repo.user_registration_token()
+            .add(
+                &mut rng,
+                &state.clock,
+                "expired".to_owned(),
+                None,
+                Some(state.clock.now() - Duration::try_minutes(1).unwrap()),
+            )
+            .await
+            .unwrap();
+
+        repo.save().await.unwrap();
+
+        let cookies = complete_upstream_session(
+            &state,
+            &provider,
+            &link,
+            serde_json::json!({ "preferred_username": "john" }),
+            None,
+        )
+        .await;
+
+        let request = Request::get(&*mas_router::UpstreamOAuth2Link::new(link.id).path()).empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::OK);
+        assert!(response.body().contains("name=\"registration_token\""));
+
+        // Extract the CSRF token from the response body
+        let csrf_token = response
+            .body()
+            .split("name=\"csrf\" value=\"")
+            .nth(1)
+            .unwrap()
+            .split('\"')
+            .next()
+            .unwrap()
+            .to_owned();
+
+        let register = |registration_token: Option<&str>| {
+            let mut form = serde_json::json!({
+                "csrf": csrf_token,
+                "action": "register",
+                "accept_terms": "on",
+            });
+            if let Some(registration_token) = registration_token {
+                form["registration_token"] = registration_token.into();
+            }
+            let request =
+                Request::post(&*mas_router::UpstreamOAuth2Link::new(link.id).path()).form(form);
+            cookies.with_cookies(request)
+        };
+
+        // A missing token is rejected
+        let response = state.request(register(None)).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::OK);
+        assert!(response.body().contains("This field is required"));
+
+        // So are unknown and expired tokens
+        for registration_token in ["unknown", "expired"] {
+            let response = state.request(register(Some(registration_token))).await;
+            cookies.save_cookies(&response);
+            response.assert_status(StatusCode::OK);
+            assert!(response
+                .body()
+                .contains("This registration token is invalid or has expired"));
+        }
+
+        // No user was created by the rejected attempts
+        let mut repo = state.repository().await.unwrap();
+        assert!(repo
+            .user()
+            .find_by_username("john")
+            .await
+            .unwrap()
+            .is_none());
+        repo.save().await.unwrap();
+
+        // The valid token is accepted, and one of its uses is consumed
+        let response = state.request(register(Some("invite"))).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::SEE_OTHER);
+
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .find_by_username("john")
+            .await
+            .unwrap()
+            .expect("user exists");
+        let link = repo
+            .upstream_oauth_link()
+            .lookup(link.id)
+            .await
+            .unwrap()
+            .expect("link exists");
+        assert_eq!(link.user_id, Some(user.id));
+
+        let token = repo
+            .user_registration_token()
+            .lookup(token.id)
+            .await
+            .unwrap()
+            .expect("token exists");
+        assert_eq!(token.times_used, 1);
+        assert_eq!(token.pending_uses, 1);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_recovery(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let mut rng = state.rng();
+        let cookies = CookieHelper::new();
+
+        // Provision a provider, a user and a link between the two
+        let mut repo = state.repository().await.unwrap();
+        let provider = repo
+            .upstream_oauth_provider()
+            .add(
+                &mut rng,
+                &state.clock,
+                test_upstream_oauth_provider_params(),
+            )
+            .await
+            .unwrap();
+
+        let user = repo
+            .user()
+            .add(&mut rng, &state.clock, "john".to_owned())
+            .await
+            .unwrap();
+
+        let link = repo
+            .upstream_oauth_link()
+            .add(&mut rng, &state.clock, &provider, "subject".to_owned())
+            .await
+            .unwrap();
+
+        repo.upstream_oauth_link()
+            .associate_to_user(&link, &user)
+            .await
+            .unwrap();
+
+        let session = repo
+            .upstream_oauth_session()
+            .add(
+                &mut rng,
+                &state.clock,
+                &provider,
+                "state".to_owned(),
+                None,
+                "nonce".to_owned(),
+            )
+            .await
+            .unwrap();
+
+        let session = repo
+            .upstream_oauth_session()
+            .complete_with_link(&state.clock, session, &link, None, None, None, None)
+            .await
+            .unwrap();
+
+        repo.save().await.unwrap();
+
+        let cookie_jar = state.cookie_jar();
+        let upstream_sessions = UpstreamSessionsCookie::default()
+            .add(
+                session.id,
+                provider.id,
+                "state".to_owned(),
+                Some(PostAuthAction::RecoverAccount),
+            )
+            .add_link_to_session(session.id, link.id)
+            .unwrap();
+        let cookie_jar = upstream_sessions.save(cookie_jar, &state.clock);
+        cookies.import(cookie_jar);
+
+        // Going through the link page should not log the user in, but send them
+        // to the page where they can set a new password
+        let request = Request::get(&*mas_router::UpstreamOAuth2Link::new(link.id).path()).empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::SEE_OTHER);
+
+        let location = response.headers().get(LOCATION).unwrap().to_str().unwrap();
+        let (_, ticket) = location
+            .split_once("/account/password/recovery?ticket=")
+            .expect("redirects to the recovery page");
+
+        let mut repo = state.repository().await.unwrap();
+        let ticket = repo
+            .user_recovery()
+            .find_ticket(ticket)
+            .await
+            .unwrap()
+            .expect("ticket exists");
+
+        assert_eq!(ticket.user_id, user.id);
+        assert_eq!(ticket.upstream_oauth_link_id, Some(link.id));
+        assert_eq!(ticket.user_email_id, None);
+
+        let session = repo
+            .upstream_oauth_session()
+            .lookup(session.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(session.is_consumed());
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_recovery_removed_from_group(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let mut rng = state.rng();
+
+        // Provision a provider requiring a group, and a user linked to it
+        let mut repo = state.repository().await.unwrap();
+        let provider = repo
+            .upstream_oauth_provider()
+            .add(
+                &mut rng,
+                &state.clock,
+                UpstreamOAuthProviderParams {
+                    claims_imports: UpstreamOAuthProviderClaimsImports {
+                        groups: UpstreamOAuthProviderGroupsPreference {
+                            required_groups: vec!["staff".to_owned()],
+                            ..UpstreamOAuthProviderGroupsPreference::default()
+                        },
+                        ..UpstreamOAuthProviderClaimsImports::default()
+                    },
+                    ..test_upstream_oauth_provider_params()
+                },
+            )
+            .await
+            .unwrap();
+
+        let user = repo
+            .user()
+            .add(&mut rng, &state.clock, "john".to_owned())
+            .await
+            .unwrap();
+
+        let link = repo
+            .upstream_oauth_link()
+            .add(&mut rng, &state.clock, &provider, "subject".to_owned())
+            .await
+            .unwrap();
+        repo.upstream_oauth_link()
+            .associate_to_user(&link, &user)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        // The upstream provider no longer lists the user in the required group
+        let claims = serde_json::json!({
+            "groups": ["users"],
+        });
+        let cookies = complete_upstream_session(
+            &state,
+            &provider,
+            &link,
+            claims,
+            Some(PostAuthAction::RecoverAccount),
+        )
+        .await;
+
+        let request = Request::get(&*mas_router::UpstreamOAuth2Link::new(link.id).path()).empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::FORBIDDEN);
+
+        // The user got locked, and no recovery ticket was issued
+        let mut repo = state.repository().await.unwrap();
+        let user = repo.user().lookup(user.id).await.unwrap().unwrap();
+        assert!(user.locked_at.is_some());
+
+        let tickets: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM user_recovery_tickets")
+            .fetch_one(&state.pool)
+            .await
+            .unwrap();
+        assert_eq!(tickets, 0);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_sync_claims_on_login(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool.clone()).await.unwrap();
+        let mut rng = state.rng();
+
+        let sync_on_login = UpstreamOAuthProviderImportPreference {
+            action: mas_data_model::UpstreamOAuthProviderImportAction::Force,
+            template: None,
+            sync_on_login: true,
+        };
+
+        // Provision a provider syncing the display name and email on login, and a
+        // user linked to it, with an email previously imported through the link
+        let mut repo = state.repository().await.unwrap();
+        let provider = repo
+            .upstream_oauth_provider()
+            .add(
+                &mut rng,
+                &state.clock,
+                UpstreamOAuthProviderParams {
+                    claims_imports: UpstreamOAuthProviderClaimsImports {
+                        displayname: sync_on_login.clone(),
+                        email: sync_on_login,
+                        ..UpstreamOAuthProviderClaimsImports::default()
+                    },
+                    ..test_upstream_oauth_provider_params()
+                },
+            )
+            .await
+            .unwrap();
+
+        let user = repo
+            .user()
+            .add(&mut rng, &state.clock, "john".to_owned())
+            .await
+            .unwrap();
+
+        let link = repo
+            .upstream_oauth_link()
+            .add(&mut rng, &state.clock, &provider, "subject".to_owned())
+            .await
+            .unwrap();
+        repo.upstream_oauth_link()
+            .associate_to_user(&link, &user)
+            .await
+            .unwrap();
+
+        let old_email = repo
+            .user_email()
+            .add(&mut rng, &state.clock, &user, "john@example.com".to_owned())
+            .await
+            .unwrap();
+        let old_email = repo
+            .user_email()
+            .mark_as_verified(&state.clock, old_email)
+            .await
+            .unwrap();
+        repo.user_email().set_as_primary(&old_email).await.unwrap();
+        let link = repo
+            .upstream_oauth_link()
+            .set_imported_email(link, Some(&old_email))
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let mxid = state.homeserver_connection.mxid(&user.username);
+        state
+            .homeserver_connection
+            .provision_user(
+                &ProvisionRequest::new(mxid.clone(), user.sub.clone())
+                    .set_displayname("Johnny".to_owned()),
+            )
+            .await
+            .unwrap();
+
+        let provision_jobs = || async {
+            let jobs: Vec<Json<serde_json::Value>> =
+                sqlx::query_scalar("SELECT job FROM apalis.jobs WHERE job_type = 'provision-user'")
+                    .fetch_all(&pool)
+                    .await
+                    .unwrap();
+            jobs
+        };
+
+        let claims = serde_json::json!({
+            "name": "John Doe",
+            "email": "john.doe@example.com",
+            "email_verified": true,
+        });
+
+        // Log in with changed claims
+        let cookies =
+            complete_upstream_session(&state, &provider, &link, claims.clone(), None).await;
+        let request = Request::get(&*mas_router::UpstreamOAuth2Link::new(link.id).path()).empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::SEE_OTHER);
+
+        // The new display name is pushed to the homeserver
+        let jobs = provision_jobs().await;
+        assert_eq!(jobs.len(), 1);
+        assert_eq!(jobs[0]["user_id"], serde_json::json!(user.id));
+        assert_eq!(jobs[0]["set_display_name"], "John Doe");
+
+        // The imported email was replaced, and is now the primary one
+        let mut repo = state.repository().await.unwrap();
+        let user = repo.user().lookup(user.id).await.unwrap().unwrap();
+        let emails = repo.user_email().all(&user).await.unwrap();
+        assert_eq!(emails.len(), 1);
+        assert_eq!(emails[0].email, "john.doe@example.com");
+        assert!(emails[0].confirmed_at.is_some());
+        assert_eq!(user.primary_user_email_id, Some(emails[0].id));
+        let link = repo
+            .upstream_oauth_link()
+            .lookup(link.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(link.imported_user_email_id, Some(emails[0].id));
+        repo.cancel().await.unwrap();
+
+        // Once the homeserver has the new display name, logging in again with the
+        // same claims doesn't change anything
+        state
+            .homeserver_connection
+            .set_displayname(&mxid, "John Doe")
+            .await
+            .unwrap();
+
+        let cookies = complete_upstream_session(&state, &provider, &link, claims, None).await;
+        let request = Request::get(&*mas_router::UpstreamOAuth2Link::new(link.id).path()).empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::SEE_OTHER);
+
+        assert_eq!(provision_jobs().await.len(), 1);
+
+        let mut repo = state.repository().await.unwrap();
+        let user_emails = repo.user_email().all(&user).await.unwrap();
+        assert_eq!(user_emails, emails);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_denied_by_claims_mapping(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool.clone()).await.unwrap();
+        state.policy_factory = policy_factory(serde_json::json!({
+            "upstream_oauth2_mapping": {
+                "denied_claims": {
+                    "department": ["contractors"],
+                },
+            },
+        }))
+        .await
+        .unwrap();
+        let mut rng = state.rng();
+
+        let mut repo = state.repository().await.unwrap();
+        let provider = repo
+            .upstream_oauth_provider()
+            .add(
+                &mut rng,
+                &state.clock,
+                test_upstream_oauth_provider_params(),
+            )
+            .await
+            .unwrap();
+        let link = repo
+            .upstream_oauth_link()
+            .add(&mut rng, &state.clock, &provider, "subject".to_owned())
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let claims = serde_json::json!({
+            "preferred_username": "john",
+            "department": "contractors",
+        });
+        let cookies = complete_upstream_session(&state, &provider, &link, claims, None).await;
+
+        // The upstream account is refused with a translated page
+        let request = Request::get(&*mas_router::UpstreamOAuth2Link::new(link.id).path()).empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::FORBIDDEN);
+        assert!(response.body().contains("be used on this service"));
+
+        // The upstream session was not consumed, and no user was registered
+        let consumed: bool = sqlx::query_scalar(
+            "SELECT consumed_at IS NOT NULL FROM upstream_oauth_authorization_sessions",
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert!(!consumed);
+
+        let mut repo = state.repository().await.unwrap();
+        assert!(repo
+            .user()
+            .find_by_username("john")
+            .await
+            .unwrap()
+            .is_none());
     }
 }
@@ -0,0 +1,151 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+//! Shared logic for the upstream OpenID Connect logout endpoints
+
+use std::collections::BTreeSet;
+
+use mas_data_model::{AuditLogEventKind, UpstreamOAuthAuthorizationSession, UpstreamOAuthProvider};
+use mas_storage::{
+    audit_log::NewAuditLogEvent,
+    compat::CompatSessionFilter,
+    job::{JobRepositoryExt, NewSignInSession, SendWebhookEventJob, SyncDevicesJob},
+    oauth2::OAuth2SessionFilter,
+    user::BrowserSessionFilter,
+    BoxRepository, Clock, Pagination, RepositoryAccess, RepositoryError,
+};
+use rand::RngCore;
+use serde_json::json;
+use tracing::info;
+use ulid::Ulid;
+
+/// How many sessions are looked at once
+pub(super) const BATCH_SIZE: usize = 100;
+
+/// Through which channel the upstream provider told us about the logout
+#[derive(Debug, Clone, Copy)]
+pub(super) enum LogoutChannel {
+    Backchannel,
+    Frontchannel,
+}
+
+impl LogoutChannel {
+    const fn as_str(self) -> &'static str {
+        match self {
+            Self::Backchannel => "backchannel",
+            Self::Frontchannel => "frontchannel",
+        }
+    }
+}
+
+/// End the browser sessions authenticated by the given upstream sessions,
+/// along with the OAuth 2.0 and compatibility sessions they started, record it
+/// in the audit log, tell the webhook endpoints about it, and collect the users
+/// they belonged to
+pub(super) async fn finish_sessions_authenticated_by(
+    repo: &mut BoxRepository,
+    rng: &mut (dyn RngCore + Send),
+    clock: &dyn Clock,
+    provider: &UpstreamOAuthProvider,
+    channel: LogoutChannel,
+    upstream_oauth_sessions: &[UpstreamOAuthAuthorizationSession],
+    users: &mut BTreeSet<Ulid>,
+) -> Result<(), RepositoryError> {
+    let filter = BrowserSessionFilter::new()
+        .authenticated_by_upstream_sessions_only(upstream_oauth_sessions)
+        .active_only();
+
+    loop {
+        // Sessions are ended as we go, so we always look at the first page
+        let page = repo
+            .browser_session()
+            .list(filter, Pagination::first(BATCH_SIZE))
+            .await?;
+
+        if page.edges.is_empty() {
+            break;
+        }
+
+        for browser_session in page.edges {
+            let oauth2_sessions = repo
+                .oauth2_session()
+                .finish_bulk(
+                    clock,
+                    OAuth2SessionFilter::new()
+                        .for_browser_session(&browser_session)
+                        .active_only(),
+                )
+                .await?;
+
+            let compat_sessions = repo
+                .compat_session()
+                .finish_bulk(
+                    clock,
+                    CompatSessionFilter::new()
+                        .for_browser_session(&browser_session)
+                        .active_only(),
+                )
+                .await?;
+
+            info!(
+                user.id = %browser_session.user.id,
+                user_session.id = %browser_session.id,
+                channel = channel.as_str(),
+                oauth2_sessions,
+                compat_sessions,
+                "Ending browser session after an upstream logout"
+            );
+
+            repo.audit_log()
+                .add(
+                    rng,
+                    clock,
+                    NewAuditLogEvent::new(AuditLogEventKind::SessionFinished)
+                        .for_user(&browser_session.user)
+                        .with_data(json!({
+                            "session_type": "browser",
+                            "session_id": browser_session.id,
+                            "reason": "upstream_logout",
+                            "channel": channel.as_str(),
+                            "upstream_oauth_provider_id": provider.id,
+                            "oauth2_sessions": oauth2_sessions,
+                            "compat_sessions": compat_sessions,
+                        })),
+                )
+                .await?;
+
+            repo.job()
+                .schedule_job(SendWebhookEventJob::session_ended(
+                    browser_session.user.id,
+                    NewSignInSession::Browser(browser_session.id),
+                ))
+                .await?;
+
+            users.insert(browser_session.user.id);
+            repo.browser_session()
+                .finish(clock, browser_session)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Schedule a job to remove the devices of the ended sessions from the
+/// homeserver, for each of the given users
+pub(super) async fn sync_devices(
+    repo: &mut BoxRepository,
+    users: BTreeSet<Ulid>,
+) -> Result<(), RepositoryError> {
+    for user_id in users {
+        let Some(user) = repo.user().lookup(user_id).await? else {
+            continue;
+        };
+
+        repo.job().schedule_job(SyncDevicesJob::new(&user)).await?;
+    }
+
+    Ok(())
+}
@@ -4,26 +4,61 @@
 // SPDX-License-Identifier: AGPL-3.0-only
 // Please see LICENSE in the repository root for full details.
 
-use std::string::FromUtf8Error;
+use std::{collections::BTreeSet, string::FromUtf8Error};
 
-use mas_data_model::{UpstreamOAuthProvider, UpstreamOAuthProviderTokenAuthMethod};
+use mas_data_model::{UpstreamOAuthProvider, UpstreamOAuthProviderTokenAuthMethod, User};
 use mas_iana::jose::JsonWebSignatureAlg;
 use mas_keystore::{DecryptError, Encrypter, Keystore};
 use mas_oidc_client::types::client_credentials::ClientCredentials;
+use mas_storage::{upstream_oauth2::UpstreamOAuthLinkFilter, Pagination, RepositoryAccess};
 use pkcs8::DecodePrivateKey;
 use serde::Deserialize;
 use thiserror::Error;
 use url::Url;
 
 pub(crate) mod authorize;
+pub(crate) mod backchannel_logout;
 pub(crate) mod cache;
 pub(crate) mod callback;
 mod cookie;
+pub(crate) mod frontchannel_logout;
 pub(crate) mod link;
+mod logout;
 mod template;
+pub(crate) mod token_exchange;
+pub(crate) mod tokens;
 
 use self::cookie::UpstreamSessions as UpstreamSessionsCookie;
 
+/// Collect the groups the upstream providers claimed the [`User`] is a member
+/// of, through all of their links
+///
+/// # Errors
+///
+/// Returns an error if the repository fails
+pub(crate) async fn user_groups<R: RepositoryAccess>(
+    repo: &mut R,
+    user: &User,
+) -> Result<Vec<String>, R::Error> {
+    let filter = UpstreamOAuthLinkFilter::new().for_user(user);
+    let mut groups = BTreeSet::new();
+    let mut pagination = Pagination::first(100);
+    loop {
+        let page = repo.upstream_oauth_link().list(filter, pagination).await?;
+
+        for link in &page.edges {
+            groups.extend(link.groups.iter().cloned());
+        }
+
+        match page.edges.last() {
+            Some(last) if page.has_next_page => pagination = pagination.after(last.id),
+            _ => break,
+        }
+    }
+
+    Ok(groups.into_iter().collect())
+}
+
 #[derive(Debug, Error)]
 #[allow(clippy::enum_variant_names)]
 enum ProviderCredentialsError {
@@ -15,13 +15,15 @@ use minijinja::{
 /// Context passed to the attribute mapping template
 ///
 /// The variables available in the template are:
-/// - `user`: claims for the user, currently from the ID token. Later, we'll
-///   also allow importing from the userinfo endpoint
+/// - `user`: claims for the user, merged from the ID token and the userinfo
+///   endpoint, the latter taking precedence
 /// - `id_token_claims`: claims from the ID token
+/// - `userinfo_claims`: claims from the userinfo endpoint
 /// - `extra_callback_parameters`: extra parameters passed to the callback
 #[derive(Debug, Default)]
 pub(crate) struct AttributeMappingContext {
     id_token_claims: Option<HashMap<String, serde_json::Value>>,
+    userinfo_claims: Option<HashMap<String, serde_json::Value>>,
     extra_callback_parameters: Option<serde_json::Value>,
 }
 
@@ -38,6 +40,14 @@ impl AttributeMappingContext {
         self
     }
 
+    pub fn with_userinfo_claims(
+        mut self,
+        userinfo_claims: HashMap<String, serde_json::Value>,
+    ) -> Self {
+        self.userinfo_claims = Some(userinfo_claims);
+        self
+    }
+
     pub fn with_extra_callback_parameters(
         mut self,
         extra_callback_parameters: serde_json::Value,
@@ -49,12 +59,31 @@ impl AttributeMappingContext {
     pub fn build(self) -> Value {
         Value::from_object(self)
     }
+
+    /// Merge the claims from the ID token and the userinfo endpoint
+    fn user_claims(&self) -> Option<HashMap<&str, &serde_json::Value>> {
+        if self.id_token_claims.is_none() && self.userinfo_claims.is_none() {
+            return None;
+        }
+
+        let claims = self
+            .id_token_claims
+            .iter()
+            .chain(self.userinfo_claims.iter())
+            .flatten()
+            .map(|(key, value)| (key.as_str(), value))
+            .collect();
+
+        Some(claims)
+    }
 }
 
 impl Object for AttributeMappingContext {
     fn get_value(self: &Arc<Self>, name: &Value) -> Option<Value> {
         match name.as_str()? {
-            "user" | "id_token_claims" => self.id_token_claims.as_ref().map(Value::from_serialize),
+            "user" => self.user_claims().as_ref().map(Value::from_serialize),
+            "id_token_claims" => self.id_token_claims.as_ref().map(Value::from_serialize),
+            "userinfo_claims" => self.userinfo_claims.as_ref().map(Value::from_serialize),
             "extra_callback_parameters" => self
                 .extra_callback_parameters
                 .as_ref()
@@ -64,17 +93,18 @@ impl Object for AttributeMappingContext {
     }
 
     fn enumerate(self: &Arc<Self>) -> Enumerator {
-        match (
-            self.id_token_claims.is_some(),
-            self.extra_callback_parameters.is_some(),
-        ) {
-            (true, true) => {
-                Enumerator::Str(&["user", "id_token_claims", "extra_callback_parameters"])
-            }
-            (true, false) => Enumerator::Str(&["user", "id_token_claims"]),
-            (false, true) => Enumerator::Str(&["extra_callback_parameters"]),
-            (false, false) => Enumerator::Str(&["user"]),
+        let mut keys = vec!["user"];
+        if self.id_token_claims.is_some() {
+            keys.push("id_token_claims");
         }
+        if self.userinfo_claims.is_some() {
+            keys.push("userinfo_claims");
+        }
+        if self.extra_callback_parameters.is_some() {
+            keys.push("extra_callback_parameters");
+        }
+
+        Enumerator::Values(keys.into_iter().map(Value::from).collect())
     }
 }
 
@@ -167,7 +197,9 @@ pub fn environment() -> Environment<'static> {
 
 #[cfg(test)]
 mod tests {
-    use super::environment;
+    use std::collections::HashMap;
+
+    use super::{environment, AttributeMappingContext};
 
     #[test]
     fn test_split() {
@@ -210,4 +242,39 @@ mod tests {
             .unwrap();
         assert_eq!(res, "unpadded");
     }
+
+    #[test]
+    fn test_userinfo_claims() {
+        let env = environment();
+        let id_token_claims = HashMap::from([
+            ("sub".to_owned(), serde_json::json!("abc")),
+            ("name".to_owned(), serde_json::json!("Old name")),
+        ]);
+        let userinfo_claims = HashMap::from([
+            ("name".to_owned(), serde_json::json!("New name")),
+            ("login".to_owned(), serde_json::json!("alice")),
+        ]);
+
+        // Userinfo claims take precedence over the ID token ones
+        let context = AttributeMappingContext::new()
+            .with_id_token_claims(id_token_claims)
+            .with_userinfo_claims(userinfo_claims.clone())
+            .build();
+        let res = env
+            .render_str(
+                "{{ user.sub }} {{ user.name }} {{ user.login }} {{ id_token_claims.name }}",
+                context,
+            )
+            .unwrap();
+        assert_eq!(res, "abc New name alice Old name");
+
+        // Plain OAuth 2.0 providers only have userinfo claims
+        let context = AttributeMappingContext::new()
+            .with_userinfo_claims(userinfo_claims)
+            .build();
+        let res = env
+            .render_str("{{ user.login }} {{ userinfo_claims.name }}", context)
+            .unwrap();
+        assert_eq!(res, "alice New name");
+    }
 }
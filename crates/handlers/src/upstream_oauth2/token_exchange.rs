@@ -0,0 +1,717 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+//! Let trusted clients get the access token issued by an upstream provider to
+//! the user, in exchange for an access token issued to that client, in a way
+//! similar to RFC 8693 token exchange
+
+use axum::{extract::State, response::IntoResponse, Json};
+use hyper::StatusCode;
+use mas_axum_utils::{
+    client_authorization::{ClientAuthorization, CredentialsVerificationError},
+    sentry::SentryEventID,
+};
+use mas_data_model::{
+    TokenType, UpstreamOAuthLink, UpstreamOAuthLinkTokens, UpstreamOAuthProvider,
+};
+use mas_iana::oauth::OAuthClientAuthenticationMethod;
+use mas_keystore::{Encrypter, Keystore};
+use mas_storage::{
+    upstream_oauth2::UpstreamOAuthLinkFilter, BoxClock, BoxRepository, BoxRng, Clock, Pagination,
+    RepositoryAccess, RepositoryError,
+};
+use mas_storage_pg::PgRepository;
+use oauth2_types::errors::{ClientError, ClientErrorCode};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use thiserror::Error;
+use ulid::Ulid;
+
+use super::tokens::{decrypt_access_token, TokenVaultError, UpstreamTokenRefresher, REFRESH_LEASE};
+use crate::{impl_from_error_for_route, MetadataCache};
+
+const TOKEN_EXCHANGE_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:token-exchange";
+const ACCESS_TOKEN_TYPE: &str = "urn:ietf:params:oauth:token-type:access_token";
+
+/// How many times the tokens are looked up again while someone else refreshes
+/// them, and how long to wait between each attempt
+const REFRESH_WAIT_ATTEMPTS: usize = 10;
+const REFRESH_WAIT_INTERVAL: std::time::Duration = std::time::Duration::from_millis(300);
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct TokenExchangeRequest {
+    grant_type: String,
+    subject_token: String,
+    subject_token_type: String,
+
+    /// The ID of the upstream provider to get the token from
+    audience: Ulid,
+
+    #[serde(default)]
+    requested_token_type: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct TokenExchangeResponse {
+    access_token: String,
+    issued_token_type: &'static str,
+    token_type: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expires_in: Option<i64>,
+}
+
+#[derive(Debug, Error)]
+pub(crate) enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error("could not find client")]
+    ClientNotFound,
+
+    #[error(transparent)]
+    ClientCredentialsVerification(#[from] CredentialsVerificationError),
+
+    #[error("bad request")]
+    BadRequest,
+
+    #[error("unsupported grant type")]
+    UnsupportedGrantType,
+
+    #[error("unsupported token type")]
+    UnsupportedTokenType,
+
+    #[error("the subject token is not valid")]
+    InvalidSubjectToken,
+
+    #[error("the upstream provider does not exist")]
+    UnknownProvider,
+
+    #[error("client is not allowed to get tokens from this provider")]
+    NotAllowed,
+
+    #[error("no valid token is available for this user and provider")]
+    NoUpstreamToken,
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+impl_from_error_for_route!(TokenVaultError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let event_id = sentry::capture_error(&self);
+        let response = match self {
+            Self::Internal(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ClientError::from(ClientErrorCode::ServerError)),
+            )
+                .into_response(),
+            Self::ClientNotFound => (
+                StatusCode::UNAUTHORIZED,
+                Json(ClientError::from(ClientErrorCode::InvalidClient)),
+            )
+                .into_response(),
+            Self::ClientCredentialsVerification(e) => (
+                StatusCode::UNAUTHORIZED,
+                Json(
+                    ClientError::from(ClientErrorCode::InvalidClient)
+                        .with_description(e.to_string()),
+                ),
+            )
+                .into_response(),
+            e @ Self::BadRequest => (
+                StatusCode::BAD_REQUEST,
+                Json(
+                    ClientError::from(ClientErrorCode::InvalidRequest)
+                        .with_description(e.to_string()),
+                ),
+            )
+                .into_response(),
+            e @ Self::UnsupportedGrantType => (
+                StatusCode::BAD_REQUEST,
+                Json(
+                    ClientError::from(ClientErrorCode::UnsupportedGrantType)
+                        .with_description(e.to_string()),
+                ),
+            )
+                .into_response(),
+            e @ Self::UnsupportedTokenType => (
+                StatusCode::BAD_REQUEST,
+                Json(
+                    ClientError::from(ClientErrorCode::UnsupportedTokenType)
+                        .with_description(e.to_string()),
+                ),
+            )
+                .into_response(),
+            e @ (Self::InvalidSubjectToken | Self::UnknownProvider | Self::NoUpstreamToken) => (
+                StatusCode::BAD_REQUEST,
+                Json(
+                    ClientError::from(ClientErrorCode::InvalidGrant)
+                        .with_description(e.to_string()),
+                ),
+            )
+                .into_response(),
+            e @ Self::NotAllowed => (
+                StatusCode::BAD_REQUEST,
+                Json(
+                    ClientError::from(ClientErrorCode::UnauthorizedClient)
+                        .with_description(e.to_string()),
+                ),
+            )
+                .into_response(),
+        };
+
+        (SentryEventID::from(event_id), response).into_response()
+    }
+}
+
+/// Refresh expired tokens, or wait for someone else to refresh them if they
+/// are already being refreshed
+///
+/// Returns `None` if the tokens were removed, or if they weren't refreshed in
+/// time
+async fn refresh_expired(
+    rng: &mut (dyn RngCore + Send),
+    clock: &dyn Clock,
+    mut repo: BoxRepository,
+    pool: &PgPool,
+    refresher: &UpstreamTokenRefresher,
+    link: &UpstreamOAuthLink,
+    tokens: UpstreamOAuthLinkTokens,
+) -> Result<Option<UpstreamOAuthLinkTokens>, RouteError> {
+    // Lease the tokens, so that the same refresh token isn't used by the
+    // background refresher or another request at the same time
+    let leased = repo
+        .upstream_oauth_link()
+        .lease_tokens(clock, tokens, clock.now() + REFRESH_LEASE)
+        .await?;
+    repo.save().await?;
+
+    if let Some(leased) = leased {
+        return Ok(refresher.refresh_leased(rng, clock, pool, leased).await?);
+    }
+
+    for _ in 0..REFRESH_WAIT_ATTEMPTS {
+        tokio::time::sleep(REFRESH_WAIT_INTERVAL).await;
+
+        let mut repo = PgRepository::from_pool(pool)
+            .await
+            .map_err(RepositoryError::from_error)?
+            .boxed();
+        let tokens = repo.upstream_oauth_link().lookup_tokens(link).await?;
+        repo.cancel().await?;
+
+        match tokens {
+            Some(tokens) if tokens.is_access_token_expired(clock.now()) => {}
+            tokens => return Ok(tokens),
+        }
+    }
+
+    Ok(None)
+}
+
+#[tracing::instrument(
+    name = "handlers.upstream_oauth2.token_exchange.post",
+    fields(client.id = client_authorization.client_id()),
+    skip_all,
+    err,
+)]
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn post(
+    mut rng: BoxRng,
+    clock: BoxClock,
+    mut repo: BoxRepository,
+    State(http_client): State<reqwest::Client>,
+    State(metadata_cache): State<MetadataCache>,
+    State(keystore): State<Keystore>,
+    State(encrypter): State<Encrypter>,
+    State(pool): State<PgPool>,
+    client_authorization: ClientAuthorization<TokenExchangeRequest>,
+) -> Result<impl IntoResponse, RouteError> {
+    let client = client_authorization
+        .credentials
+        .fetch(&mut repo)
+        .await?
+        .ok_or(RouteError::ClientNotFound)?;
+
+    // Only confidential clients can get upstream tokens
+    let method = match &client.token_endpoint_auth_method {
+        None | Some(OAuthClientAuthenticationMethod::None) => {
+            return Err(RouteError::NotAllowed);
+        }
+        Some(c) => c,
+    };
+
+    client_authorization
+        .credentials
+        .verify(&http_client, &encrypter, method, &client)
+        .await?;
+
+    let Some(form) = client_authorization.form else {
+        return Err(RouteError::BadRequest);
+    };
+
+    if form.grant_type != TOKEN_EXCHANGE_GRANT_TYPE {
+        return Err(RouteError::UnsupportedGrantType);
+    }
+
+    if form.subject_token_type != ACCESS_TOKEN_TYPE
+        || form
+            .requested_token_type
+            .as_deref()
+            .is_some_and(|requested| requested != ACCESS_TOKEN_TYPE)
+    {
+        return Err(RouteError::UnsupportedTokenType);
+    }
+
+    let provider = repo
+        .upstream_oauth_provider()
+        .lookup(form.audience)
+        .await?
+        .filter(UpstreamOAuthProvider::enabled)
+        .ok_or(RouteError::UnknownProvider)?;
+
+    if !provider.store_tokens || !provider.token_exchange_clients.contains(&client.id) {
+        return Err(RouteError::NotAllowed);
+    }
+
+    // The subject token must be a valid access token issued to this client, for a
+    // user
+    if TokenType::check(&form.subject_token).ok() != Some(TokenType::AccessToken) {
+        return Err(RouteError::InvalidSubjectToken);
+    }
+
+    let access_token = repo
+        .oauth2_access_token()
+        .find_by_token(&form.subject_token)
+        .await?
+        .filter(|access_token| access_token.is_valid(clock.now()))
+        .ok_or(RouteError::InvalidSubjectToken)?;
+
+    let session = repo
+        .oauth2_session()
+        .lookup(access_token.session_id)
+        .await?
+        .filter(|session| session.is_valid() && session.client_id == client.id)
+        .ok_or(RouteError::InvalidSubjectToken)?;
+
+    let user_id = session.user_id.ok_or(RouteError::InvalidSubjectToken)?;
+    let user = repo
+        .user()
+        .lookup(user_id)
+        .await?
+        .filter(mas_data_model::User::is_valid)
+        .ok_or(RouteError::InvalidSubjectToken)?;
+
+    // Find the link between the user and the provider
+    let filter = UpstreamOAuthLinkFilter::new()
+        .for_user(&user)
+        .for_provider(&provider);
+    let link = repo
+        .upstream_oauth_link()
+        .list(filter, Pagination::first(1))
+        .await?
+        .edges
+        .into_iter()
+        .next()
+        .ok_or(RouteError::NoUpstreamToken)?;
+
+    let mut tokens = repo
+        .upstream_oauth_link()
+        .lookup_tokens(&link)
+        .await?
+        .ok_or(RouteError::NoUpstreamToken)?;
+
+    // The token might have expired if the background refresh didn't happen in time
+    if tokens.is_access_token_expired(clock.now()) {
+        if tokens.encrypted_refresh_token.is_none() {
+            return Err(RouteError::NoUpstreamToken);
+        }
+
+        let refresher =
+            UpstreamTokenRefresher::new(http_client, metadata_cache, keystore, encrypter.clone());
+        tokens = refresh_expired(&mut rng, &clock, repo, &pool, &refresher, &link, tokens)
+            .await?
+            .ok_or(RouteError::NoUpstreamToken)?;
+    } else {
+        repo.save().await?;
+    }
+
+    let expires_in = tokens
+        .access_token_expires_at
+        .map(|expires_at| (expires_at - clock.now()).num_seconds());
+    let access_token = decrypt_access_token(&encrypter, &tokens)?;
+
+    Ok(Json(TokenExchangeResponse {
+        access_token,
+        issued_token_type: ACCESS_TOKEN_TYPE,
+        token_type: "Bearer",
+        expires_in,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use hyper::Request;
+    use mas_data_model::UpstreamOAuthProviderDiscoveryMode;
+    use mas_router::SimpleRoute;
+    use oauth2_types::{
+        registration::ClientRegistrationResponse,
+        scope::{Scope, OPENID},
+    };
+    use serde_json::json;
+    use wiremock::{
+        matchers::{body_string_contains, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+    use crate::{
+        oauth2::generate_token_pair,
+        test_utils::{
+            setup, test_upstream_oauth_provider_params, RequestBuilderExt, ResponseExt, TestState,
+        },
+    };
+
+    /// A client allowed to exchange its access token for the upstream tokens
+    /// kept for its user
+    struct Fixture {
+        client_id: String,
+        client_secret: String,
+        subject_token: String,
+        provider: UpstreamOAuthProvider,
+        link: UpstreamOAuthLink,
+    }
+
+    impl Fixture {
+        fn request(&self) -> hyper::Request<String> {
+            Request::post(mas_router::UpstreamOAuth2TokenExchange::PATH).form(json!({
+                "grant_type": TOKEN_EXCHANGE_GRANT_TYPE,
+                "subject_token": self.subject_token,
+                "subject_token_type": ACCESS_TOKEN_TYPE,
+                "audience": self.provider.id,
+                "client_id": self.client_id,
+                "client_secret": self.client_secret,
+            }))
+        }
+    }
+
+    /// Set up a provider whose token endpoint is on the mock server, and a
+    /// user with tokens from it expiring in a minute
+    #[allow(clippy::too_many_lines)]
+    async fn prepare(
+        state: &TestState,
+        mock_server: &MockServer,
+        refresh_token: Option<&str>,
+    ) -> Fixture {
+        let request = Request::post(mas_router::OAuth2RegistrationEndpoint::PATH).json(json!({
+            "client_uri": "https://client.com/",
+            "grant_types": [],
+            "token_endpoint_auth_method": "client_secret_post",
+        }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::CREATED);
+        let ClientRegistrationResponse {
+            client_id,
+            client_secret,
+            ..
+        } = response.json();
+
+        let mut rng = state.rng();
+        let mut repo = state.repository().await.unwrap();
+
+        let client = repo
+            .oauth2_client()
+            .find_by_client_id(&client_id)
+            .await
+            .unwrap()
+            .unwrap();
+
+        let provider = repo
+            .upstream_oauth_provider()
+            .add(
+                &mut rng,
+                &state.clock,
+                mas_storage::upstream_oauth2::UpstreamOAuthProviderParams {
+                    discovery_mode: UpstreamOAuthProviderDiscoveryMode::Disabled,
+                    token_endpoint_override: Some(
+                        format!("{}/token", mock_server.uri()).parse().unwrap(),
+                    ),
+                    store_tokens: true,
+                    token_exchange_clients: vec![client.id],
+                    ..test_upstream_oauth_provider_params()
+                },
+            )
+            .await
+            .unwrap();
+
+        let user = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        let link = repo
+            .upstream_oauth_link()
+            .add(&mut rng, &state.clock, &provider, "subject".to_owned())
+            .await
+            .unwrap();
+        repo.upstream_oauth_link()
+            .associate_to_user(&link, &user)
+            .await
+            .unwrap();
+
+        let encrypted_access_token = state
+            .encrypter
+            .encrypt_to_string(b"upstream-access")
+            .unwrap();
+        let encrypted_refresh_token =
+            refresh_token.map(|token| state.encrypter.encrypt_to_string(token.as_bytes()).unwrap());
+        repo.upstream_oauth_link()
+            .save_tokens(
+                &state.clock,
+                &link,
+                encrypted_access_token,
+                encrypted_refresh_token,
+                Some(state.clock.now() + Duration::try_minutes(1).unwrap()),
+            )
+            .await
+            .unwrap();
+
+        let browser_session = repo
+            .browser_session()
+            .add(&mut rng, &state.clock, &user, None)
+            .await
+            .unwrap();
+        let session = repo
+            .oauth2_session()
+            .add_from_browser_session(
+                &mut rng,
+                &state.clock,
+                &client,
+                &browser_session,
+                Scope::from_iter([OPENID]),
+            )
+            .await
+            .unwrap();
+        let (access_token, _) = generate_token_pair(
+            &mut rng,
+            &state.clock,
+            &mut repo,
+            &session,
+            Duration::try_hours(1).unwrap(),
+        )
+        .await
+        .unwrap();
+
+        repo.save().await.unwrap();
+
+        Fixture {
+            client_id,
+            client_secret: client_secret.unwrap(),
+            subject_token: access_token.access_token,
+            provider,
+            link,
+        }
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_refresh_success(pool: PgPool) {
+        setup();
+        let mock_server = MockServer::start().await;
+        let state = TestState::from_pool(pool).await.unwrap();
+        let fixture = prepare(&state, &mock_server, Some("upstream-refresh")).await;
+
+        Mock::given(method("POST"))
+            .and(path("/token"))
+            .and(body_string_contains("grant_type=refresh_token"))
+            .and(body_string_contains("refresh_token=upstream-refresh"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "access_token": "new-upstream-access",
+                "refresh_token": "new-upstream-refresh",
+                "token_type": "Bearer",
+                "expires_in": 3600,
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // The stored token is handed out as long as it is valid
+        let response = state.request(fixture.request()).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["access_token"], "upstream-access");
+        assert_eq!(body["expires_in"], 60);
+
+        // Once it expired, it gets refreshed, and the lease on it is released
+        state.clock.advance(Duration::try_minutes(2).unwrap());
+        let response = state.request(fixture.request()).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["access_token"], "new-upstream-access");
+        assert_eq!(body["expires_in"], 3600);
+
+        let mut repo = state.repository().await.unwrap();
+        let tokens = repo
+            .upstream_oauth_link()
+            .lookup_tokens(&fixture.link)
+            .await
+            .unwrap()
+            .unwrap();
+        let refresh_token = state
+            .encrypter
+            .decrypt_string(tokens.encrypted_refresh_token.as_deref().unwrap())
+            .unwrap();
+        assert_eq!(refresh_token, b"new-upstream-refresh");
+        assert!(repo
+            .upstream_oauth_link()
+            .lease_tokens(
+                &state.clock,
+                tokens,
+                state.clock.now() + Duration::try_minutes(2).unwrap(),
+            )
+            .await
+            .unwrap()
+            .is_some());
+        repo.cancel().await.unwrap();
+
+        // The new token is handed out without calling the provider again
+        let response = state.request(fixture.request()).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["access_token"], "new-upstream-access");
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_refresh_failure(pool: PgPool) {
+        setup();
+        let mock_server = MockServer::start().await;
+        let state = TestState::from_pool(pool).await.unwrap();
+        let fixture = prepare(&state, &mock_server, Some("upstream-refresh")).await;
+        state.clock.advance(Duration::try_minutes(2).unwrap());
+
+        // The provider is unavailable the first time
+        Mock::given(method("POST"))
+            .and(path("/token"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Then rejects the refresh token
+        Mock::given(method("POST"))
+            .and(path("/token"))
+            .respond_with(ResponseTemplate::new(400).set_body_json(json!({
+                "error": "invalid_grant",
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let response = state.request(fixture.request()).await;
+        response.assert_status(StatusCode::INTERNAL_SERVER_ERROR);
+
+        // The tokens are kept, to be retried once the lease expired
+        let mut repo = state.repository().await.unwrap();
+        assert!(repo
+            .upstream_oauth_link()
+            .lookup_tokens(&fixture.link)
+            .await
+            .unwrap()
+            .is_some());
+        repo.cancel().await.unwrap();
+
+        state.clock.advance(Duration::try_minutes(3).unwrap());
+        let response = state.request(fixture.request()).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        let body: ClientError = response.json();
+        assert_eq!(body.error, ClientErrorCode::InvalidGrant);
+
+        // The rejected tokens are removed
+        let mut repo = state.repository().await.unwrap();
+        assert!(repo
+            .upstream_oauth_link()
+            .lookup_tokens(&fixture.link)
+            .await
+            .unwrap()
+            .is_none());
+        repo.cancel().await.unwrap();
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_expired_without_refresh_token(pool: PgPool) {
+        setup();
+        let mock_server = MockServer::start().await;
+        let state = TestState::from_pool(pool).await.unwrap();
+        let fixture = prepare(&state, &mock_server, None).await;
+
+        Mock::given(method("POST"))
+            .and(path("/token"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(0)
+            .mount(&mock_server)
+            .await;
+
+        state.clock.advance(Duration::try_minutes(2).unwrap());
+        let response = state.request(fixture.request()).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        let body: ClientError = response.json();
+        assert_eq!(body.error, ClientErrorCode::InvalidGrant);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_refresh_expiring(pool: PgPool) {
+        setup();
+        let mock_server = MockServer::start().await;
+        let state = TestState::from_pool(pool).await.unwrap();
+        let fixture = prepare(&state, &mock_server, Some("upstream-refresh")).await;
+
+        Mock::given(method("POST"))
+            .and(path("/token"))
+            .and(body_string_contains("refresh_token=upstream-refresh"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "access_token": "new-upstream-access",
+                "token_type": "Bearer",
+                "expires_in": 3600,
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // The tokens expire within the refresh margin, so they get refreshed in the
+        // background, keeping the refresh token which wasn't rotated
+        let refresher = UpstreamTokenRefresher::new(
+            state.http_client.clone(),
+            state.metadata_cache.clone(),
+            state.key_store.clone(),
+            state.encrypter.clone(),
+        );
+        refresher
+            .refresh_expiring(&mut state.rng(), &state.clock, &state.pool)
+            .await
+            .unwrap();
+
+        let mut repo = state.repository().await.unwrap();
+        let tokens = repo
+            .upstream_oauth_link()
+            .lookup_tokens(&fixture.link)
+            .await
+            .unwrap()
+            .unwrap();
+        repo.cancel().await.unwrap();
+        assert_eq!(
+            decrypt_access_token(&state.encrypter, &tokens).unwrap(),
+            "new-upstream-access"
+        );
+        assert!(tokens.encrypted_refresh_token.is_some());
+
+        // Nothing is left to refresh
+        refresher
+            .refresh_expiring(&mut state.rng(), &state.clock, &state.pool)
+            .await
+            .unwrap();
+    }
+}
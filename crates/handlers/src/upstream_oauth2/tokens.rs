@@ -0,0 +1,285 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+//! Keep the tokens issued by upstream providers, so that they can be handed to
+//! trusted clients, and refresh them before they expire
+
+use std::string::FromUtf8Error;
+
+use chrono::Duration;
+use mas_data_model::{UpstreamOAuthLink, UpstreamOAuthLinkTokens};
+use mas_keystore::{DecryptError, Encrypter, Keystore};
+use mas_oidc_client::error::{DiscoveryError, TokenRefreshError, TokenRequestError};
+use mas_storage::{BoxRepository, Clock, RepositoryAccess, RepositoryError};
+use oauth2_types::requests::AccessTokenResponse;
+use rand::RngCore;
+use sqlx::PgPool;
+use thiserror::Error;
+
+use super::{cache::LazyProviderInfos, client_credentials_for_provider, ProviderCredentialsError};
+use crate::MetadataCache;
+
+/// How long before they expire the access tokens are refreshed
+const REFRESH_MARGIN: Duration = Duration::minutes(5);
+
+/// For how long the tokens are leased while they are being refreshed
+pub(super) const REFRESH_LEASE: Duration = Duration::minutes(2);
+
+/// How many tokens are leased at once
+const BATCH_SIZE: usize = 50;
+
+#[derive(Debug, Error)]
+pub(super) enum TokenVaultError {
+    #[error("Could not encrypt the token")]
+    Encrypt(#[from] mas_keystore::aead::Error),
+
+    #[error("Could not decrypt the token")]
+    Decrypt(#[from] DecryptError),
+
+    #[error("The decrypted token is invalid")]
+    InvalidToken(#[from] FromUtf8Error),
+
+    #[error("No refresh token was stored")]
+    MissingRefreshToken,
+
+    #[error(transparent)]
+    ProviderCredentials(#[from] ProviderCredentialsError),
+
+    #[error(transparent)]
+    Discovery(#[from] DiscoveryError),
+
+    #[error(transparent)]
+    Refresh(#[from] TokenRefreshError),
+
+    #[error(transparent)]
+    Repository(#[from] RepositoryError),
+}
+
+/// Encrypt and save the tokens from a token response of the provider
+///
+/// # Errors
+///
+/// Returns an error if the tokens can't be encrypted, or if the repository
+/// fails
+pub(super) async fn save_tokens(
+    clock: &dyn Clock,
+    repo: &mut BoxRepository,
+    encrypter: &Encrypter,
+    link: &UpstreamOAuthLink,
+    response: &AccessTokenResponse,
+) -> Result<UpstreamOAuthLinkTokens, TokenVaultError> {
+    let encrypted_access_token = encrypter.encrypt_to_string(response.access_token.as_bytes())?;
+    let encrypted_refresh_token = response
+        .refresh_token
+        .as_deref()
+        .map(|refresh_token| encrypter.encrypt_to_string(refresh_token.as_bytes()))
+        .transpose()?;
+    let access_token_expires_at = response
+        .expires_in
+        .map(|expires_in| clock.now() + expires_in);
+
+    let tokens = repo
+        .upstream_oauth_link()
+        .save_tokens(
+            clock,
+            link,
+            encrypted_access_token,
+            encrypted_refresh_token,
+            access_token_expires_at,
+        )
+        .await?;
+
+    Ok(tokens)
+}
+
+/// Decrypt the access token stored for a link
+///
+/// # Errors
+///
+/// Returns an error if the token can't be decrypted
+pub(super) fn decrypt_access_token(
+    encrypter: &Encrypter,
+    tokens: &UpstreamOAuthLinkTokens,
+) -> Result<String, TokenVaultError> {
+    let access_token = encrypter.decrypt_string(&tokens.encrypted_access_token)?;
+    Ok(String::from_utf8(access_token)?)
+}
+
+/// Refreshes the tokens issued by upstream providers
+#[derive(Clone)]
+pub struct UpstreamTokenRefresher {
+    http_client: reqwest::Client,
+    metadata_cache: MetadataCache,
+    keystore: Keystore,
+    encrypter: Encrypter,
+}
+
+impl UpstreamTokenRefresher {
+    #[must_use]
+    pub fn new(
+        http_client: reqwest::Client,
+        metadata_cache: MetadataCache,
+        keystore: Keystore,
+        encrypter: Encrypter,
+    ) -> Self {
+        Self {
+            http_client,
+            metadata_cache,
+            keystore,
+            encrypter,
+        }
+    }
+
+    /// Refresh tokens which were leased beforehand, without holding a
+    /// transaction open while calling the provider
+    ///
+    /// Returns `None` if the tokens can't be refreshed anymore, in which case
+    /// they are removed. On other errors, the lease is kept until it expires,
+    /// so that the refresh isn't retried right away.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the refresh failed, or if the repository fails
+    pub(super) async fn refresh_leased(
+        &self,
+        mut rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        pool: &PgPool,
+        tokens: UpstreamOAuthLinkTokens,
+    ) -> Result<Option<UpstreamOAuthLinkTokens>, TokenVaultError> {
+        let mut repo = repository(pool).await?;
+
+        let Some(link) = repo.upstream_oauth_link().lookup(tokens.link_id).await? else {
+            // The tokens were removed along with the link
+            repo.cancel().await?;
+            return Ok(None);
+        };
+
+        let provider = repo
+            .upstream_oauth_provider()
+            .lookup(link.provider_id)
+            .await?
+            .filter(|provider| provider.enabled() && provider.store_tokens);
+
+        let Some(provider) = provider else {
+            // The provider doesn't want the tokens to be kept anymore
+            repo.upstream_oauth_link().remove_tokens(tokens).await?;
+            repo.save().await?;
+            return Ok(None);
+        };
+
+        repo.cancel().await?;
+
+        let encrypted_refresh_token = tokens
+            .encrypted_refresh_token
+            .as_deref()
+            .ok_or(TokenVaultError::MissingRefreshToken)?;
+        let refresh_token = self.encrypter.decrypt_string(encrypted_refresh_token)?;
+        let refresh_token = String::from_utf8(refresh_token)?;
+
+        let mut lazy_metadata =
+            LazyProviderInfos::new(&self.metadata_cache, &provider, &self.http_client);
+        let token_endpoint = lazy_metadata.token_endpoint().await?;
+        let client_credentials = client_credentials_for_provider(
+            &provider,
+            token_endpoint,
+            &self.keystore,
+            &self.encrypter,
+        )?;
+
+        let res = mas_oidc_client::requests::refresh_token::refresh_access_token(
+            &self.http_client,
+            client_credentials,
+            token_endpoint,
+            refresh_token,
+            None,
+            None,
+            None,
+            clock.now(),
+            &mut rng,
+        )
+        .await;
+
+        let mut repo = repository(pool).await?;
+
+        let response = match res {
+            Ok((response, _)) => response,
+            Err(TokenRefreshError::Token(TokenRequestError::OAuth2(e)))
+                if e.error_code() == Some("invalid_grant") =>
+            {
+                // The refresh token is not valid anymore, the tokens are useless
+                tracing::info!(
+                    upstream_oauth_link.id = %link.id,
+                    "Upstream provider rejected the refresh token, removing the stored tokens"
+                );
+                repo.upstream_oauth_link().remove_tokens(tokens).await?;
+                repo.save().await?;
+                return Ok(None);
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let tokens = save_tokens(clock, &mut repo, &self.encrypter, &link, &response).await?;
+        repo.save().await?;
+
+        Ok(Some(tokens))
+    }
+
+    /// Refresh all the tokens which are about to expire
+    ///
+    /// Each batch of tokens is leased in its own transaction before calling
+    /// the providers. Tokens which failed to refresh are retried once their
+    /// lease expired, without blocking the others.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the repository fails
+    pub async fn refresh_expiring(
+        &self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        pool: &PgPool,
+    ) -> Result<(), RepositoryError> {
+        let mut after = None;
+        loop {
+            let mut repo = repository(pool).await?;
+            let batch = repo
+                .upstream_oauth_link()
+                .lease_tokens_to_refresh(
+                    clock,
+                    clock.now() + REFRESH_MARGIN,
+                    clock.now() + REFRESH_LEASE,
+                    after,
+                    BATCH_SIZE,
+                )
+                .await?;
+            repo.save().await?;
+
+            let Some(last) = batch.last() else {
+                return Ok(());
+            };
+            after = Some(last.link_id);
+
+            for tokens in batch {
+                let link_id = tokens.link_id;
+                if let Err(e) = self.refresh_leased(rng, clock, pool, tokens).await {
+                    tracing::warn!(
+                        upstream_oauth_link.id = %link_id,
+                        error = &e as &dyn std::error::Error,
+                        "Failed to refresh the upstream tokens"
+                    );
+                }
+            }
+        }
+    }
+}
+
+async fn repository(pool: &PgPool) -> Result<BoxRepository, RepositoryError> {
+    let repo = mas_storage_pg::PgRepository::from_pool(pool)
+        .await
+        .map_err(RepositoryError::from_error)?
+        .boxed();
+    Ok(repo)
+}
@@ -0,0 +1,54 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+//! Validation of the usernames set by administrators, through the admin API,
+//! SCIM, GraphQL and bulk imports
+
+fn valid_username_character(c: char) -> bool {
+    c.is_ascii_lowercase()
+        || c.is_ascii_digit()
+        || c == '='
+        || c == '_'
+        || c == '-'
+        || c == '.'
+        || c == '/'
+        || c == '+'
+}
+
+/// Check that a username is a valid Matrix localpart, which doesn't start
+/// with an underscore
+pub(crate) fn username_valid(username: &str) -> bool {
+    if username.is_empty() || username.len() > 255 {
+        return false;
+    }
+
+    // Should not start with an underscore
+    if username.starts_with('_') {
+        return false;
+    }
+
+    // Should only contain valid characters
+    if !username.chars().all(valid_username_character) {
+        return false;
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::username_valid;
+
+    #[test]
+    fn test_username_valid() {
+        assert!(username_valid("alice"));
+        assert!(username_valid("alice.bob-42=+/_"));
+        assert!(!username_valid(""));
+        assert!(!username_valid("_alice"));
+        assert!(!username_valid("Alice"));
+        assert!(!username_valid("alice bob"));
+        assert!(!username_valid(&"a".repeat(256)));
+    }
+}
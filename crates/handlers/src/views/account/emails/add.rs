@@ -13,16 +13,18 @@ use mas_axum_utils::{
     csrf::{CsrfExt, ProtectedForm},
     FancyError, SessionInfoExt,
 };
-use mas_data_model::SiteConfig;
+use mas_data_model::{AuditLogEventKind, SiteConfig};
 use mas_policy::Policy;
 use mas_router::UrlBuilder;
 use mas_storage::{
+    audit_log::NewAuditLogEvent,
     job::{JobRepositoryExt, VerifyEmailJob},
     user::UserEmailRepository,
     BoxClock, BoxRepository, BoxRng,
 };
 use mas_templates::{EmailAddContext, ErrorContext, TemplateContext, Templates};
 use serde::Deserialize;
+use serde_json::json;
 
 use crate::{views::shared::OptionalPostAuthAction, BoundActivityTracker, PreferredLanguage};
 
@@ -117,6 +119,23 @@ pub(crate) async fn post(
     // Run the email policy
     let res = policy.evaluate_email(&form.email).await?;
     if !res.valid() {
+        repo.audit_log()
+            .add(
+                &mut rng,
+                &clock,
+                NewAuditLogEvent::new(AuditLogEventKind::PolicyDenied)
+                    .with_actor(&session.user)
+                    .for_user(&session.user)
+                    .with_ip_address(activity_tracker.ip())
+                    .with_data(json!({
+                        "policy": "email",
+                        "email": form.email,
+                        "violations": res.violations,
+                    })),
+            )
+            .await?;
+        repo.save().await?;
+
         return Err(FancyError::new(
             ErrorContext::new()
                 .with_description(format!("Email address {:?} denied by policy", form.email))
@@ -129,9 +148,27 @@ pub(crate) async fn post(
     let user_email = if let Some(user_email) = existing_user_email {
         user_email
     } else {
-        repo.user_email()
+        let user_email = repo
+            .user_email()
             .add(&mut rng, &clock, &session.user, form.email)
-            .await?
+            .await?;
+
+        repo.audit_log()
+            .add(
+                &mut rng,
+                &clock,
+                NewAuditLogEvent::new(AuditLogEventKind::EmailAdded)
+                    .with_actor(&session.user)
+                    .for_user(&session.user)
+                    .with_ip_address(activity_tracker.ip())
+                    .with_data(json!({
+                        "user_email_id": user_email.id,
+                        "email": user_email.email,
+                    })),
+            )
+            .await?;
+
+        user_email
     };
 
     // If the email was not confirmed, send a confirmation email & redirect to the
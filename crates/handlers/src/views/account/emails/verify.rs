@@ -16,7 +16,7 @@ use mas_axum_utils::{
 };
 use mas_router::UrlBuilder;
 use mas_storage::{
-    job::{JobRepositoryExt, ProvisionUserJob},
+    job::{JobRepositoryExt, ProvisionUserJob, SendWebhookEventJob},
     user::UserEmailRepository,
     BoxClock, BoxRepository, BoxRng, RepositoryAccess,
 };
@@ -137,10 +137,18 @@ pub(crate) async fn post(
         repo.user_email().set_as_primary(&user_email).await?;
     }
 
-    repo.user_email()
+    let user_email = repo
+        .user_email()
         .mark_as_verified(&clock, user_email)
         .await?;
 
+    repo.job()
+        .schedule_job(SendWebhookEventJob::user_email_verified(
+            &session.user,
+            &user_email,
+        ))
+        .await?;
+
     repo.job()
         .schedule_job(ProvisionUserJob::new(&session.user))
         .await?;
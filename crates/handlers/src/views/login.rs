@@ -4,6 +4,8 @@
 // SPDX-License-Identifier: AGPL-3.0-only
 // Please see LICENSE in the repository root for full details.
 
+use std::net::IpAddr;
+
 use axum::{
     extract::{Form, Query, State},
     response::{Html, IntoResponse, Response},
@@ -15,11 +17,16 @@ use mas_axum_utils::{
     csrf::{CsrfExt, CsrfToken, ProtectedForm},
     FancyError, SessionInfoExt,
 };
-use mas_data_model::{oauth2::LoginHint, BrowserSession, UserAgent};
+use mas_data_model::{
+    oauth2::LoginHint, AuditLogEventKind, BrowserSession, Password, UpstreamOAuthProvider, User,
+    UserAgent,
+};
 use mas_i18n::DataLocale;
 use mas_matrix::BoxHomeserverConnection;
-use mas_router::{UpstreamOAuth2Authorize, UrlBuilder};
+use mas_router::{PostAuthAction, UpstreamOAuth2Authorize, UrlBuilder};
 use mas_storage::{
+    audit_log::NewAuditLogEvent,
+    job::{JobRepositoryExt, NewSignInSession, SendWebhookEventJob},
     upstream_oauth2::UpstreamOAuthProviderRepository,
     user::{BrowserSessionRepository, UserPasswordRepository, UserRepository},
     BoxClock, BoxRepository, BoxRng, Clock, RepositoryAccess,
@@ -30,18 +37,24 @@ use mas_templates::{
 };
 use rand::{CryptoRng, Rng};
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use zeroize::Zeroizing;
 
 use super::shared::OptionalPostAuthAction;
 use crate::{
-    passwords::PasswordManager, BoundActivityTracker, Limiter, PreferredLanguage,
-    RequesterFingerprint, SiteConfig,
+    login_lockout, new_sign_in,
+    passwords::{verify_external, ExternalVerification, PasswordManager},
+    rate_limit::PasswordCheckLimitedError,
+    BoundActivityTracker, Limiter, PreferredLanguage, RequesterFingerprint, SiteConfig,
 };
 
 #[derive(Debug, Deserialize, Serialize)]
 pub(crate) struct LoginForm {
     username: String,
-    password: String,
+
+    /// Absent when submitting the first step of the home-realm discovery
+    #[serde(default)]
+    password: Option<String>,
 }
 
 impl ToFormState for LoginForm {
@@ -92,9 +105,14 @@ pub(crate) async fn get(
         return Ok((cookie_jar, url_builder.redirect(&destination)).into_response());
     };
 
+    let discovery = discovery_enabled(&providers, &query);
+    let providers = listed_providers(providers, discovery);
+
     let content = render(
         locale,
-        LoginContext::default().with_upstream_providers(providers),
+        LoginContext::default()
+            .with_upstream_providers(providers)
+            .with_discovery(discovery),
         query,
         csrf_token,
         &mut repo,
@@ -126,14 +144,58 @@ pub(crate) async fn post(
     Form(form): Form<ProtectedForm<LoginForm>>,
 ) -> Result<Response, FancyError> {
     let user_agent = user_agent.map(|ua| UserAgent::parse(ua.as_str().to_owned()));
+
+    let form = cookie_jar.verify_form(&clock, form)?;
+
+    let (csrf_token, cookie_jar) = cookie_jar.csrf_token(&clock, &mut rng);
+
+    let providers = repo.upstream_oauth_provider().all_enabled().await?;
+    let discovery = discovery_enabled(&providers, &query);
+
+    // The first step of the home-realm discovery only submits the user identifier
+    if discovery && form.password.is_none() {
+        let mut state = form.to_form_state();
+
+        if form.username.is_empty() {
+            state.add_error_on_field(LoginFormField::Username, FieldError::Required);
+        } else if let Some(provider) = discover_provider(&providers, &form.username) {
+            let mut destination =
+                UpstreamOAuth2Authorize::new(provider.id).with_login_hint(form.username);
+
+            if let Some(action) = query.post_auth_action {
+                destination = destination.and_then(action);
+            }
+
+            return Ok((cookie_jar, url_builder.redirect(&destination)).into_response());
+        } else if !site_config.password_login_enabled {
+            state.add_error_on_form(FormError::NoMatchingProvider);
+        }
+
+        // Either show the errors, or continue with the password step
+        let discovery = !state.is_valid();
+        let content = render(
+            locale,
+            LoginContext::default()
+                .with_form_state(state)
+                .with_upstream_providers(listed_providers(providers, discovery))
+                .with_discovery(discovery),
+            query,
+            csrf_token,
+            &mut repo,
+            &templates,
+            homeserver,
+        )
+        .await?;
+
+        return Ok((cookie_jar, Html(content)).into_response());
+    }
+
     if !site_config.password_login_enabled {
         // XXX: is it necessary to have better errors here?
         return Ok(StatusCode::METHOD_NOT_ALLOWED.into_response());
     }
 
-    let form = cookie_jar.verify_form(&clock, form)?;
-
-    let (csrf_token, cookie_jar) = cookie_jar.csrf_token(&clock, &mut rng);
+    let password = form.password.as_deref().unwrap_or_default();
 
     // Validate the form
     let state = {
@@ -143,7 +205,7 @@ pub(crate) async fn post(
             state.add_error_on_field(LoginFormField::Username, FieldError::Required);
         }
 
-        if form.password.is_empty() {
+        if password.is_empty() {
             state.add_error_on_field(LoginFormField::Password, FieldError::Required);
         }
 
@@ -151,12 +213,11 @@ pub(crate) async fn post(
     };
 
     if !state.is_valid() {
-        let providers = repo.upstream_oauth_provider().all_enabled().await?;
         let content = render(
             locale,
             LoginContext::default()
                 .with_form_state(state)
-                .with_upstream_providers(providers),
+                .with_upstream_providers(listed_providers(providers, discovery)),
             query,
             csrf_token,
             &mut repo,
@@ -175,9 +236,14 @@ pub(crate) async fn post(
         &clock,
         limiter,
         requester,
+        &homeserver,
+        &site_config,
+        &locale,
         &form.username,
-        &form.password,
+        password,
         user_agent,
+        activity_tracker.ip(),
+        activity_tracker.location(),
     )
     .await
     {
@@ -193,6 +259,9 @@ pub(crate) async fn post(
             Ok((cookie_jar, reply).into_response())
         }
         Err(e) => {
+            // Failed login attempts are recorded in the database, so we need to save the
+            // transaction when the credentials were wrong
+            let save = matches!(e, FormError::InvalidCredentials);
             let state = state.with_error_on_form(e);
 
             let content = render(
@@ -206,12 +275,17 @@ pub(crate) async fn post(
             )
             .await?;
 
+            if save {
+                repo.save().await?;
+            }
+
             Ok((cookie_jar, Html(content)).into_response())
         }
     }
 }
 
 // TODO: move that logic elsewhere?
+#[allow(clippy::too_many_arguments, clippy::too_many_lines)]
 async fn login(
     password_manager: PasswordManager,
     repo: &mut impl RepositoryAccess,
@@ -219,9 +293,14 @@ async fn login(
     clock: &impl Clock,
     limiter: Limiter,
     requester: RequesterFingerprint,
+    homeserver: &BoxHomeserverConnection,
+    site_config: &SiteConfig,
+    locale: &DataLocale,
     username: &str,
     password: &str,
     user_agent: Option<UserAgent>,
+    ip: Option<IpAddr>,
+    location: Option<&str>,
 ) -> Result<BrowserSession, FormError> {
     // XXX: we're loosing the error context here
     // First, lookup the user
@@ -229,61 +308,141 @@ async fn login(
         .user()
         .find_by_username(username)
         .await
-        .map_err(|_e| FormError::Internal)?
-        .filter(mas_data_model::User::is_valid)
-        .ok_or(FormError::InvalidCredentials)?;
-
-    // Check the rate limit
-    limiter.check_password(requester, &user).map_err(|e| {
+        .map_err(|_e| FormError::Internal)?;
+
+    // Check the rate limit. Unknown users can only log in if they are accepted by
+    // the external password provider.
+    match &user {
+        Some(user) if user.is_valid() => limiter.check_password(requester, user),
+        None if password_manager.external_provider().is_some() => {
+            limiter.check_password_for_unknown_user(requester)
+        }
+        _ => return Err(FormError::InvalidCredentials),
+    }
+    .map_err(|e| {
         tracing::warn!(error = &e as &dyn std::error::Error);
         FormError::RateLimitExceeded
     })?;
 
-    // And its password
-    let user_password = repo
-        .user_password()
-        .active(&user)
+    // Check if the account was locked out after too many failed attempts
+    let lockout_config = site_config.login_lockout.as_ref();
+    if let Some(user) = &user {
+        if login_lockout::is_locked_out(lockout_config, clock, repo, user)
+            .await
+            .map_err(|_e| FormError::Internal)?
+        {
+            let e = PasswordCheckLimitedError::LockedOut(user.id);
+            tracing::warn!(error = &e as &dyn std::error::Error);
+            return Err(FormError::RateLimitExceeded);
+        }
+    }
+
+    // Keep the user around to record failed attempts on it
+    let known_user = user.clone();
+
+    let res = verify_password(
+        &password_manager,
+        repo,
+        &mut rng,
+        clock,
+        homeserver,
+        user,
+        username,
+        password,
+    )
+    .await;
+
+    let (user, user_password) = match res {
+        Ok(verified) => verified,
+        Err(FormError::InvalidCredentials) => {
+            let mut event = NewAuditLogEvent::new(AuditLogEventKind::LoginFailed)
+                .with_ip_address(ip)
+                .with_user_agent(user_agent.as_ref())
+                .with_data(json!({ "method": "password", "username": username }));
+            if let Some(user) = &known_user {
+                event = event.for_user(user);
+            }
+            repo.audit_log()
+                .add(&mut rng, clock, event)
+                .await
+                .map_err(|_e| FormError::Internal)?;
+
+            if let Some(user) = &known_user {
+                login_lockout::record_failure(
+                    lockout_config,
+                    clock,
+                    repo,
+                    user,
+                    Some(locale.to_string()),
+                )
+                .await
+                .map_err(|_e| FormError::Internal)?;
+            }
+
+            return Err(FormError::InvalidCredentials);
+        }
+        Err(e) => return Err(e),
+    };
+
+    login_lockout::clear(lockout_config, repo, &user)
         .await
-        .map_err(|_e| FormError::Internal)?
-        .ok_or(FormError::InvalidCredentials)?;
+        .map_err(|_e| FormError::Internal)?;
 
-    let password = Zeroizing::new(password.as_bytes().to_vec());
+    // This has to be checked before the new session is created
+    let familiar = new_sign_in::is_familiar(repo, &user, user_agent.as_ref(), ip)
+        .await
+        .map_err(|_e| FormError::Internal)?;
 
-    // Verify the password, and upgrade it on-the-fly if needed
-    let new_password_hash = password_manager
-        .verify_and_upgrade(
-            &mut rng,
-            user_password.version,
-            password,
-            user_password.hashed_password.clone(),
-        )
+    // Start a new session
+    let user_session = repo
+        .browser_session()
+        .add(&mut rng, clock, &user, user_agent.clone())
         .await
-        .map_err(|_| FormError::InvalidCredentials)?;
+        .map_err(|_| FormError::Internal)?;
 
-    let user_password = if let Some((version, new_password_hash)) = new_password_hash {
-        // Save the upgraded password
-        repo.user_password()
+    for kind in [
+        AuditLogEventKind::LoginSucceeded,
+        AuditLogEventKind::SessionCreated,
+    ] {
+        repo.audit_log()
             .add(
                 &mut rng,
                 clock,
-                &user,
-                version,
-                new_password_hash,
-                Some(&user_password),
+                NewAuditLogEvent::new(kind)
+                    .for_user(&user)
+                    .with_ip_address(ip)
+                    .with_user_agent(user_agent.as_ref())
+                    .with_data(json!({
+                        "method": "password",
+                        "session_type": "browser",
+                        "session_id": user_session.id,
+                    })),
             )
             .await
-            .map_err(|_| FormError::Internal)?
-    } else {
-        user_password
-    };
+            .map_err(|_| FormError::Internal)?;
+    }
 
-    // Start a new session
-    let user_session = repo
-        .browser_session()
-        .add(&mut rng, clock, &user, user_agent)
+    repo.job()
+        .schedule_job(SendWebhookEventJob::session_started(
+            user.id,
+            NewSignInSession::Browser(user_session.id),
+        ))
         .await
         .map_err(|_| FormError::Internal)?;
 
+    if !familiar {
+        new_sign_in::notify(
+            repo,
+            &user,
+            NewSignInSession::Browser(user_session.id),
+            ip,
+            location,
+            Some(locale.to_string()),
+        )
+        .await
+        .map_err(|_e| FormError::Internal)?;
+    }
+
     // And mark it as authenticated by the password
     repo.browser_session()
         .authenticate_with_password(&mut rng, clock, &user_session, &user_password)
@@ -293,6 +452,134 @@ async fn login(
     Ok(user_session)
 }
 
+/// Verify the credentials, either with the external password provider or
+/// against the local password database
+#[allow(clippy::too_many_arguments)]
+async fn verify_password(
+    password_manager: &PasswordManager,
+    repo: &mut impl RepositoryAccess,
+    mut rng: impl Rng + CryptoRng + Send,
+    clock: &impl Clock,
+    homeserver: &BoxHomeserverConnection,
+    user: Option<User>,
+    username: &str,
+    password: &str,
+) -> Result<(User, Password), FormError> {
+    let password = Zeroizing::new(password.as_bytes().to_vec());
+
+    // Ask the external password provider first, if any
+    let external = verify_external(
+        password_manager,
+        &mut rng,
+        clock,
+        repo,
+        homeserver,
+        user,
+        username,
+        &password,
+    )
+    .await
+    .map_err(|_e| FormError::Internal)?;
+
+    let (user, user_password) = match external {
+        ExternalVerification::Accepted {
+            user,
+            user_password,
+        } => (user, user_password),
+        ExternalVerification::Rejected => return Err(FormError::InvalidCredentials),
+        ExternalVerification::Skipped(user) => {
+            let user = user.ok_or(FormError::InvalidCredentials)?;
+
+            // And its password
+            let user_password = repo
+                .user_password()
+                .active(&user)
+                .await
+                .map_err(|_e| FormError::Internal)?
+                .ok_or(FormError::InvalidCredentials)?;
+
+            // Verify the password, and upgrade it on-the-fly if needed
+            let new_password_hash = password_manager
+                .verify_and_upgrade(
+                    &mut rng,
+                    user_password.version,
+                    password,
+                    user_password.hashed_password.clone(),
+                )
+                .await
+                .map_err(|_| FormError::InvalidCredentials)?;
+
+            let user_password = if let Some((version, new_password_hash)) = new_password_hash {
+                // Save the upgraded password
+                repo.user_password()
+                    .add(
+                        &mut rng,
+                        clock,
+                        &user,
+                        version,
+                        new_password_hash,
+                        Some(&user_password),
+                    )
+                    .await
+                    .map_err(|_| FormError::Internal)?
+            } else {
+                user_password
+            };
+
+            (user, user_password)
+        }
+    };
+
+    Ok((user, user_password))
+}
+
+/// Whether the login page should first ask for the user identifier, to find
+/// out which upstream provider they should be sent to
+fn discovery_enabled(providers: &[UpstreamOAuthProvider], query: &OptionalPostAuthAction) -> bool {
+    // When linking an upstream account, the user has to log in with their password
+    if matches!(
+        query.post_auth_action,
+        Some(PostAuthAction::LinkUpstream { .. })
+    ) {
+        return false;
+    }
+
+    providers
+        .iter()
+        .any(|provider| !provider.domains.is_empty())
+}
+
+/// The providers to list as buttons on the login page. When the home-realm
+/// discovery is enabled, the providers with domains are reached through the
+/// identifier step instead.
+fn listed_providers(
+    providers: Vec<UpstreamOAuthProvider>,
+    discovery: bool,
+) -> Vec<UpstreamOAuthProvider> {
+    if !discovery {
+        return providers;
+    }
+
+    providers
+        .into_iter()
+        .filter(|provider| provider.domains.is_empty())
+        .collect()
+}
+
+/// Find the upstream provider which handles the domain of the given email
+/// address, if any
+fn discover_provider<'a>(
+    providers: &'a [UpstreamOAuthProvider],
+    identifier: &str,
+) -> Option<&'a UpstreamOAuthProvider> {
+    let (_, domain) = identifier.rsplit_once('@')?;
+    let domain = domain.to_lowercase();
+
+    providers
+        .iter()
+        .find(|provider| provider.domains.contains(&domain))
+}
+
 fn handle_login_hint(
     ctx: &mut LoginContext,
     next: &PostAuthContext,
@@ -340,7 +627,7 @@ async fn render(
 #[cfg(test)]
 mod test {
     use hyper::{
-        header::{CONTENT_TYPE, LOCATION},
+        header::{CONTENT_TYPE, LOCATION, USER_AGENT},
         Request, StatusCode,
     };
     use mas_data_model::{
@@ -358,7 +645,8 @@ mod test {
 
     use crate::{
         test_utils::{
-            setup, test_site_config, CookieHelper, RequestBuilderExt, ResponseExt, TestState,
+            setup, test_site_config, test_upstream_oauth_provider_params, CookieHelper,
+            RequestBuilderExt, ResponseExt, TestState,
         },
         SiteConfig,
     };
@@ -408,6 +696,11 @@ mod test {
                     claims_imports: UpstreamOAuthProviderClaimsImports::default(),
                     authorization_endpoint_override: None,
                     token_endpoint_override: None,
+                    userinfo_endpoint_override: None,
+                    fetch_userinfo: false,
+                    store_tokens: false,
+                    token_exchange_clients: Vec::new(),
+                    domains: Vec::new(),
                     jwks_uri_override: None,
                     discovery_mode: mas_data_model::UpstreamOAuthProviderDiscoveryMode::Oidc,
                     pkce_mode: mas_data_model::UpstreamOAuthProviderPkceMode::Auto,
@@ -444,6 +737,11 @@ mod test {
                     claims_imports: UpstreamOAuthProviderClaimsImports::default(),
                     authorization_endpoint_override: None,
                     token_endpoint_override: None,
+                    userinfo_endpoint_override: None,
+                    fetch_userinfo: false,
+                    store_tokens: false,
+                    token_exchange_clients: Vec::new(),
+                    domains: Vec::new(),
                     jwks_uri_override: None,
                     discovery_mode: mas_data_model::UpstreamOAuthProviderDiscoveryMode::Oidc,
                     pkce_mode: mas_data_model::UpstreamOAuthProviderPkceMode::Auto,
@@ -533,6 +831,150 @@ mod test {
         assert!(response.body().contains("john"));
     }
 
+    /// Sign in as `john` from a fresh browser with the given user agent
+    async fn login_with_user_agent(state: &TestState, user_agent: &str) {
+        let cookies = CookieHelper::new();
+
+        let request = Request::get("/login")
+            .header(USER_AGENT, user_agent)
+            .empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::OK);
+        let csrf_token = response
+            .body()
+            .split("name=\"csrf\" value=\"")
+            .nth(1)
+            .unwrap()
+            .split('\"')
+            .next()
+            .unwrap()
+            .to_owned();
+
+        let request =
+            Request::post("/login")
+                .header(USER_AGENT, user_agent)
+                .form(serde_json::json!({
+                    "csrf": csrf_token,
+                    "username": "john",
+                    "password": "hunter2",
+                }));
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::SEE_OTHER);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_password_login_new_device_notification(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool.clone()).await.unwrap();
+        let mut rng = state.rng();
+
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut rng, &state.clock, "john".to_owned())
+            .await
+            .unwrap();
+        let (version, hash) = state
+            .password_manager
+            .hash(&mut rng, Zeroizing::new("hunter2".as_bytes().to_vec()))
+            .await
+            .unwrap();
+        repo.user_password()
+            .add(&mut rng, &state.clock, &user, version, hash, None)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let count_jobs = || async {
+            let jobs: Vec<serde_json::Value> = sqlx::query_scalar(
+                "SELECT job FROM apalis.jobs WHERE job_type = 'send-new-sign-in-email'",
+            )
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+            jobs.len()
+        };
+
+        // The first sign-in from this device sends an email
+        login_with_user_agent(&state, "Mozilla/5.0 (X11; Linux x86_64) Firefox/130.0").await;
+        assert_eq!(count_jobs().await, 1);
+
+        // Signing in again from the same device doesn't
+        login_with_user_agent(&state, "Mozilla/5.0 (X11; Linux x86_64) Firefox/130.0").await;
+        assert_eq!(count_jobs().await, 1);
+
+        // But signing in from another device does
+        login_with_user_agent(&state, "Mozilla/5.0 (Macintosh) Safari/605.1.15").await;
+        assert_eq!(count_jobs().await, 2);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_home_realm_discovery(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let mut rng = state.rng();
+        let cookies = CookieHelper::new();
+
+        // Add a provider which handles the example.com domain
+        let mut repo = state.repository().await.unwrap();
+        let provider = repo
+            .upstream_oauth_provider()
+            .add(
+                &mut rng,
+                &state.clock,
+                UpstreamOAuthProviderParams {
+                    domains: vec!["example.com".to_owned()],
+                    ..test_upstream_oauth_provider_params()
+                },
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        // The login page should only ask for the identifier, and not list the provider
+        let request = Request::get("/login").empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::OK);
+        assert!(!response.body().contains("name=\"password\""));
+        assert!(!response.body().contains(&escape_html("Example Ltd.")));
+        let csrf_token = response
+            .body()
+            .split("name=\"csrf\" value=\"")
+            .nth(1)
+            .unwrap()
+            .split('\"')
+            .next()
+            .unwrap();
+
+        // An address on the provider's domain should redirect to it, with a login hint
+        let request = Request::post("/login").form(serde_json::json!({
+            "csrf": csrf_token,
+            "username": "alice@Example.com",
+        }));
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::SEE_OTHER);
+        let destination = mas_router::UpstreamOAuth2Authorize::new(provider.id)
+            .with_login_hint("alice@Example.com".to_owned());
+        response.assert_header_value(LOCATION, &destination.path_and_query());
+
+        // Any other identifier should continue with the password step
+        let request = Request::post("/login").form(serde_json::json!({
+            "csrf": csrf_token,
+            "username": "john",
+        }));
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        assert!(response.body().contains("name=\"password\""));
+        assert!(response.body().contains("value=\"john\""));
+    }
+
     #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
     async fn test_password_login_rate_limit(pool: PgPool) {
         setup();
@@ -13,13 +13,21 @@ use mas_axum_utils::{
     csrf::{CsrfExt, ProtectedForm},
     FancyError, SessionInfoExt,
 };
+use mas_data_model::AuditLogEventKind;
 use mas_router::{PostAuthAction, UrlBuilder};
-use mas_storage::{user::BrowserSessionRepository, BoxClock, BoxRepository};
+use mas_storage::{
+    audit_log::NewAuditLogEvent,
+    job::{JobRepositoryExt, NewSignInSession, SendWebhookEventJob},
+    user::BrowserSessionRepository,
+    BoxClock, BoxRepository, BoxRng,
+};
+use serde_json::json;
 
 use crate::BoundActivityTracker;
 
 #[tracing::instrument(name = "handlers.views.logout.post", skip_all, err)]
 pub(crate) async fn post(
+    mut rng: BoxRng,
     clock: BoxClock,
     mut repo: BoxRepository,
     cookie_jar: CookieJar,
@@ -38,6 +46,29 @@ pub(crate) async fn post(
             .record_browser_session(&clock, &session)
             .await;
 
+        repo.audit_log()
+            .add(
+                &mut rng,
+                &clock,
+                NewAuditLogEvent::new(AuditLogEventKind::SessionFinished)
+                    .with_actor(&session.user)
+                    .for_user(&session.user)
+                    .with_ip_address(activity_tracker.ip())
+                    .with_data(json!({
+                        "session_type": "browser",
+                        "session_id": session.id,
+                        "reason": "logout",
+                    })),
+            )
+            .await?;
+
+        repo.job()
+            .schedule_job(SendWebhookEventJob::session_ended(
+                session.user.id,
+                NewSignInSession::Browser(session.id),
+            ))
+            .await?;
+
         repo.browser_session().finish(&clock, session).await?;
         cookie_jar = cookie_jar.update_session_info(&session_info.mark_session_ended());
     }
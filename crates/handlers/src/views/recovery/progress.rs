@@ -52,7 +52,13 @@ pub(crate) async fn get(
         return Ok((cookie_jar, url_builder.redirect(&mas_router::Index)).into_response());
     }
 
-    let Some(recovery_session) = repo.user_recovery().lookup_session(id).await? else {
+    let Some(recovery_session) = repo
+        .user_recovery()
+        .lookup_session(id)
+        .await?
+        // Sessions started with an upstream provider don't send any email
+        .filter(|session| session.email.is_some())
+    else {
         // XXX: is that the right thing to do?
         return Ok((
             cookie_jar,
@@ -106,7 +112,13 @@ pub(crate) async fn post(
         return Ok((cookie_jar, url_builder.redirect(&mas_router::Index)).into_response());
     }
 
-    let Some(recovery_session) = repo.user_recovery().lookup_session(id).await? else {
+    let Some(recovery_session) = repo
+        .user_recovery()
+        .lookup_session(id)
+        .await?
+        // Sessions started with an upstream provider don't send any email
+        .filter(|session| session.email.is_some())
+    else {
         // XXX: is that the right thing to do?
         return Ok((
             cookie_jar,
@@ -125,7 +137,8 @@ pub(crate) async fn post(
     let () = cookie_jar.verify_form(&clock, form)?;
 
     // Check the rate limit if we are about to process the form
-    if let Err(e) = limiter.check_account_recovery(requester, &recovery_session.email) {
+    let email = recovery_session.email.as_deref().unwrap_or_default();
+    if let Err(e) = limiter.check_account_recovery(requester, email) {
         tracing::warn!(error = &e as &dyn std::error::Error);
         let context = RecoveryProgressContext::new(recovery_session, true)
             .with_csrf(csrf_token.form_value())
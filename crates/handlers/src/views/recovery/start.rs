@@ -22,7 +22,8 @@ use mas_data_model::{SiteConfig, UserAgent};
 use mas_router::UrlBuilder;
 use mas_storage::{
     job::{JobRepositoryExt, SendAccountRecoveryEmailsJob},
-    BoxClock, BoxRepository, BoxRng,
+    upstream_oauth2::UpstreamOAuthProviderRepository,
+    BoxClock, BoxRepository, BoxRng, RepositoryAccess,
 };
 use mas_templates::{
     EmptyContext, FieldError, FormError, FormState, RecoveryStartContext, RecoveryStartFormField,
@@ -62,7 +63,10 @@ pub(crate) async fn get(
         return Ok((cookie_jar, url_builder.redirect(&mas_router::Index)).into_response());
     }
 
+    let providers = repo.upstream_oauth_provider().all_enabled().await?;
+
     let context = RecoveryStartContext::new()
+        .with_upstream_providers(providers)
         .with_csrf(csrf_token.form_value())
         .with_language(locale);
 
@@ -122,9 +126,11 @@ pub(crate) async fn post(
     }
 
     if !form_state.is_valid() {
+        let providers = repo.upstream_oauth_provider().all_enabled().await?;
         repo.save().await?;
         let context = RecoveryStartContext::new()
             .with_form_state(form_state)
+            .with_upstream_providers(providers)
             .with_csrf(csrf_token.form_value())
             .with_language(locale);
 
@@ -18,14 +18,20 @@ use mas_axum_utils::{
     csrf::{CsrfExt, CsrfToken, ProtectedForm},
     FancyError, SessionInfoExt,
 };
-use mas_data_model::{CaptchaConfig, UserAgent};
+use mas_data_model::{AuditLogEventKind, CaptchaConfig, UserAgent};
 use mas_i18n::DataLocale;
 use mas_matrix::BoxHomeserverConnection;
 use mas_policy::Policy;
 use mas_router::UrlBuilder;
 use mas_storage::{
-    job::{JobRepositoryExt, ProvisionUserJob, VerifyEmailJob},
-    user::{BrowserSessionRepository, UserEmailRepository, UserPasswordRepository, UserRepository},
+    audit_log::NewAuditLogEvent,
+    job::{
+        JobRepositoryExt, NewSignInSession, ProvisionUserJob, SendWebhookEventJob, VerifyEmailJob,
+    },
+    user::{
+        BrowserSessionRepository, UserEmailRepository, UserPasswordRepository,
+        UserRegistrationTokenRepository, UserRepository,
+    },
     BoxClock, BoxRepository, BoxRng, RepositoryAccess,
 };
 use mas_templates::{
@@ -33,6 +39,7 @@ use mas_templates::{
     ToFormState,
 };
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use zeroize::Zeroizing;
 
 use super::shared::OptionalPostAuthAction;
@@ -49,6 +56,8 @@ pub(crate) struct RegisterForm {
     password_confirm: String,
     #[serde(default)]
     accept_terms: String,
+    #[serde(default)]
+    registration_token: String,
 
     #[serde(flatten, skip_serializing)]
     captcha: CaptchaForm,
@@ -146,6 +155,13 @@ pub(crate) async fn post(
         .await
         .is_ok();
 
+    // Policy denials are recorded in the audit log, so we need to save the
+    // transaction even if the form is invalid
+    let mut policy_denied = false;
+
+    // The registration token to use, if the site requires one
+    let mut registration_token = None;
+
     // Validate the form
     let state = {
         let mut state = form.to_form_state();
@@ -200,6 +216,8 @@ pub(crate) async fn post(
                     message: "Password is too weak".to_owned(),
                 },
             );
+        } else if password_manager.is_password_breached(&form.password) {
+            state.add_error_on_field(RegisterFormField::Password, FieldError::PasswordBreached);
         }
 
         // If the site has terms of service, the user must accept them
@@ -207,10 +225,50 @@ pub(crate) async fn post(
             state.add_error_on_field(RegisterFormField::AcceptTerms, FieldError::Required);
         }
 
+        if site_config.registration_token_required {
+            if form.registration_token.is_empty() {
+                state
+                    .add_error_on_field(RegisterFormField::RegistrationToken, FieldError::Required);
+            } else {
+                match repo
+                    .user_registration_token()
+                    .find_by_token(&form.registration_token)
+                    .await?
+                {
+                    Some(token) if token.is_valid(clock.now()) => {
+                        registration_token = Some(token);
+                    }
+                    _ => state.add_error_on_field(
+                        RegisterFormField::RegistrationToken,
+                        FieldError::Invalid,
+                    ),
+                }
+            }
+        }
+
         let res = policy
             .evaluate_register(&form.username, &form.email)
             .await?;
 
+        if !res.valid() {
+            repo.audit_log()
+                .add(
+                    &mut rng,
+                    &clock,
+                    NewAuditLogEvent::new(AuditLogEventKind::PolicyDenied)
+                        .with_ip_address(activity_tracker.ip())
+                        .with_user_agent(user_agent.as_ref())
+                        .with_data(json!({
+                            "policy": "register",
+                            "username": form.username,
+                            "email": form.email,
+                            "violations": res.violations,
+                        })),
+                )
+                .await?;
+            policy_denied = true;
+        }
+
         for violation in res.violations {
             match violation.field.as_deref() {
                 Some("email") => state.add_error_on_field(
@@ -260,11 +318,41 @@ pub(crate) async fn post(
         )
         .await?;
 
+        if policy_denied {
+            repo.save().await?;
+        }
+
         return Ok((cookie_jar, Html(content)).into_response());
     }
 
     let user = repo.user().add(&mut rng, &clock, form.username).await?;
 
+    if let Some(registration_token) = registration_token {
+        // The token is checked again when using it, as it may have been used up
+        // or revoked in the meantime. In this case, nothing is saved.
+        let used = repo
+            .user_registration_token()
+            .use_token(&clock, registration_token, &user)
+            .await?;
+
+        if used.is_none() {
+            let state = state
+                .with_error_on_field(RegisterFormField::RegistrationToken, FieldError::Invalid);
+            let content = render(
+                locale,
+                RegisterContext::default().with_form_state(state),
+                query,
+                csrf_token,
+                &mut repo,
+                &templates,
+                site_config.captcha.clone(),
+            )
+            .await?;
+
+            return Ok((cookie_jar, Html(content)).into_response());
+        }
+    }
+
     if let Some(tos_uri) = &site_config.tos_uri {
         repo.user_terms()
             .accept_terms(&mut rng, &clock, &user, tos_uri.clone())
@@ -287,7 +375,30 @@ pub(crate) async fn post(
 
     let session = repo
         .browser_session()
-        .add(&mut rng, &clock, &user, user_agent)
+        .add(&mut rng, &clock, &user, user_agent.clone())
+        .await?;
+
+    repo.audit_log()
+        .add(
+            &mut rng,
+            &clock,
+            NewAuditLogEvent::new(AuditLogEventKind::SessionCreated)
+                .for_user(&user)
+                .with_ip_address(activity_tracker.ip())
+                .with_user_agent(user_agent.as_ref())
+                .with_data(json!({
+                    "method": "register",
+                    "session_type": "browser",
+                    "session_id": session.id,
+                })),
+        )
+        .await?;
+
+    repo.job()
+        .schedule_job(SendWebhookEventJob::session_started(
+            user.id,
+            NewSignInSession::Browser(session.id),
+        ))
         .await?;
 
     repo.browser_session()
@@ -302,6 +413,10 @@ pub(crate) async fn post(
         .schedule_job(ProvisionUserJob::new(&user))
         .await?;
 
+    repo.job()
+        .schedule_job(SendWebhookEventJob::user_registered(&user))
+        .await?;
+
     repo.save().await?;
 
     activity_tracker
@@ -613,4 +728,123 @@ mod tests {
         response.assert_status(StatusCode::OK);
         assert!(response.body().contains("This username is already taken"));
     }
+
+    /// When a registration token is required, it should only accept valid
+    /// tokens
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_register_registration_token(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool_with_site_config(
+            pool,
+            SiteConfig {
+                registration_token_required: true,
+                ..test_site_config()
+            },
+        )
+        .await
+        .unwrap();
+        let mut rng = state.rng();
+        let cookies = CookieHelper::new();
+
+        // Create a token which can be used once
+        let mut repo = state.repository().await.unwrap();
+        let token = repo
+            .user_registration_token()
+            .add(&mut rng, &state.clock, "invite".to_owned(), Some(1), None)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        // Render the registration page and get the CSRF token
+        let request = Request::get(&*mas_router::Register::default().path_and_query()).empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::OK);
+        assert!(response.body().contains("name=\"registration_token\""));
+        // Extract the CSRF token from the response body
+        let csrf_token = response
+            .body()
+            .split("name=\"csrf\" value=\"")
+            .nth(1)
+            .unwrap()
+            .split('\"')
+            .next()
+            .unwrap()
+            .to_owned();
+
+        let form = |csrf_token: &str, username: &str, registration_token: &str| {
+            serde_json::json!({
+                "csrf": csrf_token,
+                "username": username,
+                "email": format!("{username}@example.com"),
+                "password": "correcthorsebatterystaple",
+                "password_confirm": "correcthorsebatterystaple",
+                "accept_terms": "on",
+                "registration_token": registration_token,
+            })
+        };
+
+        // An unknown token is rejected
+        let request = Request::post(&*mas_router::Register::default().path_and_query()).form(form(
+            &csrf_token,
+            "john",
+            "unknown",
+        ));
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::OK);
+        assert!(response
+            .body()
+            .contains("This registration token is invalid or has expired"));
+
+        // The valid token is accepted
+        let request = Request::post(&*mas_router::Register::default().path_and_query()).form(form(
+            &csrf_token,
+            "john",
+            "invite",
+        ));
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::SEE_OTHER);
+
+        let mut repo = state.repository().await.unwrap();
+        let token = repo
+            .user_registration_token()
+            .lookup(token.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(token.times_used, 1);
+        assert_eq!(token.pending_uses, 1);
+        repo.save().await.unwrap();
+
+        // It can't be used a second time
+        let cookies = CookieHelper::new();
+        let request = Request::get(&*mas_router::Register::default().path_and_query()).empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        let csrf_token = response
+            .body()
+            .split("name=\"csrf\" value=\"")
+            .nth(1)
+            .unwrap()
+            .split('\"')
+            .next()
+            .unwrap()
+            .to_owned();
+        let request = Request::post(&*mas_router::Register::default().path_and_query()).form(form(
+            &csrf_token,
+            "jane",
+            "invite",
+        ));
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        assert!(response
+            .body()
+            .contains("This registration token is invalid or has expired"));
+    }
 }
@@ -77,6 +77,8 @@ impl OptionalPostAuthAction {
 
             PostAuthAction::ChangePassword => PostAuthContextInner::ChangePassword,
 
+            PostAuthAction::RecoverAccount => PostAuthContextInner::RecoverAccount,
+
             PostAuthAction::LinkUpstream { id } => {
                 let link = repo
                     .upstream_oauth_link()
@@ -16,7 +16,10 @@ mod reqwest;
 
 pub use self::{
     ext::{set_propagator, CorsLayerExt},
-    reqwest::{client as reqwest_client, RequestBuilderExt},
+    reqwest::{
+        client as reqwest_client, client_with_certificate as reqwest_client_with_certificate,
+        RequestBuilderExt,
+    },
 };
 
 static METER: LazyLock<opentelemetry::metrics::Meter> = LazyLock::new(|| {
@@ -30,6 +30,7 @@ use opentelemetry_semantic_conventions::{
         NETWORK_TYPE, SERVER_ADDRESS, SERVER_PORT, URL_FULL, URL_SCHEME, USER_AGENT_ORIGINAL,
     },
 };
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
 use tokio::time::Instant;
 use tower::{BoxError, Service as _};
 use tracing::Instrument;
@@ -90,16 +91,44 @@ impl reqwest::dns::Resolve for TracingResolver {
 /// Panics if the client fails to build, which should never happen
 #[must_use]
 pub fn client() -> reqwest::Client {
+    builder(rustls_platform_verifier::tls_config())
+        .build()
+        .expect("failed to create HTTP client")
+}
+
+/// Create a new [`reqwest::Client`] with sane parameters, which authenticates
+/// with the given client certificate for mutual TLS
+///
+/// # Errors
+///
+/// Returns an error if the client certificate or key is invalid
+///
+/// # Panics
+///
+/// Panics if the client fails to build, which should never happen
+pub fn client_with_certificate(
+    certificate_chain: Vec<CertificateDer<'static>>,
+    key: PrivateKeyDer<'static>,
+) -> Result<reqwest::Client, rustls::Error> {
+    let tls_config = rustls::ClientConfig::builder()
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(rustls_platform_verifier::Verifier::new()))
+        .with_client_auth_cert(certificate_chain, key)?;
+
+    Ok(builder(tls_config)
+        .build()
+        .expect("failed to create HTTP client"))
+}
+
+fn builder(tls_config: rustls::ClientConfig) -> reqwest::ClientBuilder {
     // TODO: can/should we limit in-flight requests?
     reqwest::Client::builder()
         .dns_resolver(Arc::new(TracingResolver::new()))
-        .use_preconfigured_tls(rustls_platform_verifier::tls_config())
+        .use_preconfigured_tls(tls_config)
         .user_agent(USER_AGENT)
         .timeout(Duration::from_secs(60))
         .connect_timeout(Duration::from_secs(30))
         .read_timeout(Duration::from_secs(30))
-        .build()
-        .expect("failed to create HTTP client")
 }
 
 async fn send_traced(
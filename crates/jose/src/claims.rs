@@ -525,7 +525,16 @@ mod oidc_core {
     pub const UPDATED_AT: Claim<Timestamp> = Claim::new("updated_at");
 }
 
-pub use self::{oidc_core::*, rfc7519::*};
+/// Claims defined in OIDC.FrontChannel sec. 3 and OIDC.BackChannel sec. 2.4
+/// <https://openid.net/specs/openid-connect-frontchannel-1_0.html#ClaimsContents>
+/// <https://openid.net/specs/openid-connect-backchannel-1_0.html#LogoutToken>
+mod oidc_logout {
+    use super::Claim;
+
+    pub const SID: Claim<String> = Claim::new("sid");
+}
+
+pub use self::{oidc_core::*, oidc_logout::*, rfc7519::*};
 
 #[cfg(test)]
 mod tests {
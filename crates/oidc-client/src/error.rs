@@ -202,6 +202,31 @@ pub enum IdTokenError {
     WrongAuthTime,
 }
 
+/// All possible errors when verifying a logout token.
+#[derive(Debug, Error)]
+pub enum LogoutTokenError {
+    #[error(transparent)]
+    /// An error occurred validating the logout token's signature and basic
+    /// claims.
+    Jwt(#[from] JwtVerificationError),
+
+    #[error(transparent)]
+    /// An error occurred extracting a claim.
+    Claim(#[from] ClaimError),
+
+    /// The `events` claim doesn't contain the back-channel logout event.
+    #[error("missing back-channel logout event")]
+    MissingLogoutEvent,
+
+    /// The logout token has neither a `sub` nor a `sid` claim.
+    #[error("missing subject identifier or session ID")]
+    MissingSubjectOrSessionId,
+
+    /// The logout token has a `nonce` claim, which is forbidden.
+    #[error("logout token must not have a nonce")]
+    UnexpectedNonce,
+}
+
 /// All errors that can occur when adding client credentials to the request.
 #[derive(Debug, Error)]
 pub enum CredentialsError {
@@ -263,6 +288,15 @@ pub struct OAuth2Error {
     inner: reqwest::Error,
 }
 
+impl OAuth2Error {
+    /// The error code returned by the provider, if the response could be
+    /// parsed
+    #[must_use]
+    pub fn error_code(&self) -> Option<&str> {
+        self.error.as_ref().map(|error| error.error.as_str())
+    }
+}
+
 impl std::fmt::Display for OAuth2Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         if let Some(error) = &self.error {
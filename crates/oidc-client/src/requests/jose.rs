@@ -20,7 +20,7 @@ use serde_json::Value;
 use url::Url;
 
 use crate::{
-    error::{IdTokenError, JwksError, JwtVerificationError},
+    error::{IdTokenError, JwksError, JwtVerificationError, LogoutTokenError},
     types::IdToken,
 };
 
@@ -203,3 +203,76 @@ pub fn verify_id_token<'a>(
 
     Ok(id_token)
 }
+
+/// The event that must be present in the `events` claim of a logout token.
+pub const BACKCHANNEL_LOGOUT_EVENT: &str = "http://schemas.openid.net/event/backchannel-logout";
+
+/// The claims of a verified logout token, identifying what should be logged
+/// out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogoutTokenClaims {
+    /// The subject identifier of the user to log out, if any.
+    pub sub: Option<String>,
+
+    /// The ID of the session to log out, if any.
+    pub sid: Option<String>,
+}
+
+/// Decode and verify an OpenID Connect Back-Channel Logout token.
+///
+/// Besides the checks of [`verify_signed_jwt()`], the following checks are
+/// performed:
+///
+/// * The `iat` claim must be present must be in the past.
+///
+/// * The `events` claim must contain the back-channel logout event.
+///
+/// * The `nonce` claim must not be present.
+///
+/// * At least one of the `sub` and `sid` claims must be present.
+///
+/// # Arguments
+///
+/// * `logout_token` - The serialized logout token to decode and verify.
+///
+/// * `verification_data` - The data necessary to verify the logout token.
+///
+/// * `now` - The current time.
+///
+/// # Errors
+///
+/// Returns an error if the data is invalid or verification fails.
+pub fn verify_logout_token(
+    logout_token: &str,
+    verification_data: JwtVerificationData<'_>,
+    now: DateTime<Utc>,
+) -> Result<LogoutTokenClaims, LogoutTokenError> {
+    let logout_token = verify_signed_jwt(logout_token, verification_data)?;
+
+    let mut claims = logout_token.payload().clone();
+
+    // `iat` claim must be present.
+    claims::IAT.extract_required_with_options(&mut claims, TimeOptions::new(now))?;
+
+    // Must be a back-channel logout event.
+    let has_logout_event = claims
+        .get("events")
+        .and_then(Value::as_object)
+        .is_some_and(|events| events.contains_key(BACKCHANNEL_LOGOUT_EVENT));
+    if !has_logout_event {
+        return Err(LogoutTokenError::MissingLogoutEvent);
+    }
+
+    // Must not have a nonce, so that it can't be confused with an ID Token.
+    if claims.contains_key("nonce") {
+        return Err(LogoutTokenError::UnexpectedNonce);
+    }
+
+    let sub = claims::SUB.extract_optional(&mut claims)?;
+    let sid = claims::SID.extract_optional(&mut claims)?;
+    if sub.is_none() && sid.is_none() {
+        return Err(LogoutTokenError::MissingSubjectOrSessionId);
+    }
+
+    Ok(LogoutTokenClaims { sub, sid })
+}
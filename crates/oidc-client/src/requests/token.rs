@@ -7,6 +7,8 @@
 //! Requests for the Token endpoint.
 
 use chrono::{DateTime, Utc};
+use headers::HeaderValue;
+use http::header::ACCEPT;
 use mas_http::RequestBuilderExt;
 use oauth2_types::requests::{AccessTokenRequest, AccessTokenResponse};
 use rand::Rng;
@@ -48,7 +50,11 @@ pub async fn request_access_token(
 ) -> Result<AccessTokenResponse, TokenRequestError> {
     tracing::debug!(?request, "Requesting access token...");
 
-    let token_request = http_client.post(token_endpoint.as_str());
+    // Some plain OAuth 2.0 providers, like GitHub, reply with a form-encoded body
+    // unless asked explicitly for JSON
+    let token_request = http_client
+        .post(token_endpoint.as_str())
+        .header(ACCEPT, HeaderValue::from_static("application/json"));
 
     let token_response = client_credentials
         .authenticated_form(token_request, &request, now, rng)?
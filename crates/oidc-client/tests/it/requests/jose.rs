@@ -16,8 +16,10 @@ use mas_jose::{
     jwt::{JsonWebSignatureHeader, Jwt},
 };
 use mas_oidc_client::{
-    error::{IdTokenError, JwtVerificationError},
-    requests::jose::{verify_id_token, JwtVerificationData},
+    error::{IdTokenError, JwtVerificationError, LogoutTokenError},
+    requests::jose::{
+        verify_id_token, verify_logout_token, JwtVerificationData, BACKCHANNEL_LOGOUT_EVENT,
+    },
     types::IdToken,
 };
 
@@ -240,3 +242,124 @@ async fn fail_verify_id_token_wrong_auth_time() {
 
     assert_matches!(error, IdTokenError::WrongAuthTime);
 }
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum LogoutTokenFlag {
+    MissingEvent,
+    WithNonce,
+    MissingSubjectAndSessionId,
+}
+
+/// Generate a logout token with the given settings.
+fn logout_token(
+    issuer: &str,
+    flag: Option<LogoutTokenFlag>,
+) -> (IdToken<'static>, PublicJsonWebKeySet) {
+    let signing_alg = ID_TOKEN_SIGNING_ALG;
+
+    let keystore = keystore(&signing_alg);
+    let mut claims = HashMap::new();
+
+    claims::ISS.insert(&mut claims, issuer.to_owned()).unwrap();
+    claims::AUD
+        .insert(&mut claims, CLIENT_ID.to_owned())
+        .unwrap();
+    claims::IAT.insert(&mut claims, now()).unwrap();
+
+    if flag != Some(LogoutTokenFlag::MissingSubjectAndSessionId) {
+        claims::SUB
+            .insert(&mut claims, SUBJECT_IDENTIFIER.to_owned())
+            .unwrap();
+        claims::SID
+            .insert(&mut claims, "session-id".to_owned())
+            .unwrap();
+    }
+
+    if flag != Some(LogoutTokenFlag::MissingEvent) {
+        claims.insert(
+            "events".to_owned(),
+            serde_json::json!({ BACKCHANNEL_LOGOUT_EVENT: {} }),
+        );
+    }
+
+    if flag == Some(LogoutTokenFlag::WithNonce) {
+        claims.insert("nonce".to_owned(), serde_json::json!("nonce"));
+    }
+
+    let key = keystore.signing_key_for_algorithm(&signing_alg).unwrap();
+    let signer = key.params().signing_key_for_alg(&signing_alg).unwrap();
+    let header = JsonWebSignatureHeader::new(signing_alg).with_kid(key.kid().unwrap());
+    let logout_token = Jwt::sign(header, claims, &signer).unwrap();
+
+    (logout_token, keystore.public_jwks())
+}
+
+#[tokio::test]
+async fn pass_verify_logout_token() {
+    let issuer = "http://localhost/";
+    let (logout_token, jwks) = logout_token(issuer, None);
+
+    let verification_data = JwtVerificationData {
+        issuer,
+        jwks: &jwks,
+        client_id: &CLIENT_ID.to_owned(),
+        signing_algorithm: &ID_TOKEN_SIGNING_ALG,
+    };
+
+    let claims = verify_logout_token(logout_token.as_str(), verification_data, now()).unwrap();
+
+    assert_eq!(claims.sub.as_deref(), Some(SUBJECT_IDENTIFIER));
+    assert_eq!(claims.sid.as_deref(), Some("session-id"));
+}
+
+#[tokio::test]
+async fn fail_verify_logout_token_missing_event() {
+    let issuer = "http://localhost/";
+    let (logout_token, jwks) = logout_token(issuer, Some(LogoutTokenFlag::MissingEvent));
+
+    let verification_data = JwtVerificationData {
+        issuer,
+        jwks: &jwks,
+        client_id: &CLIENT_ID.to_owned(),
+        signing_algorithm: &ID_TOKEN_SIGNING_ALG,
+    };
+
+    let error = verify_logout_token(logout_token.as_str(), verification_data, now()).unwrap_err();
+
+    assert_matches!(error, LogoutTokenError::MissingLogoutEvent);
+}
+
+#[tokio::test]
+async fn fail_verify_logout_token_with_nonce() {
+    let issuer = "http://localhost/";
+    let (logout_token, jwks) = logout_token(issuer, Some(LogoutTokenFlag::WithNonce));
+
+    let verification_data = JwtVerificationData {
+        issuer,
+        jwks: &jwks,
+        client_id: &CLIENT_ID.to_owned(),
+        signing_algorithm: &ID_TOKEN_SIGNING_ALG,
+    };
+
+    let error = verify_logout_token(logout_token.as_str(), verification_data, now()).unwrap_err();
+
+    assert_matches!(error, LogoutTokenError::UnexpectedNonce);
+}
+
+#[tokio::test]
+async fn fail_verify_logout_token_missing_subject_and_session_id() {
+    let issuer = "http://localhost/";
+    let (logout_token, jwks) =
+        logout_token(issuer, Some(LogoutTokenFlag::MissingSubjectAndSessionId));
+
+    let verification_data = JwtVerificationData {
+        issuer,
+        jwks: &jwks,
+        client_id: &CLIENT_ID.to_owned(),
+        signing_algorithm: &ID_TOKEN_SIGNING_ALG,
+    };
+
+    let error = verify_logout_token(logout_token.as_str(), verification_data, now()).unwrap_err();
+
+    assert_matches!(error, LogoutTokenError::MissingSubjectOrSessionId);
+}
@@ -41,7 +41,7 @@ async fn pass_fetch_userinfo() {
         &userinfo_endpoint,
         ACCESS_TOKEN,
         None,
-        &auth_id_token,
+        Some(&auth_id_token),
     )
     .await
     .unwrap();
@@ -49,6 +49,32 @@ async fn pass_fetch_userinfo() {
     assert_eq!(claims.get("email").unwrap(), "janedoe@example.com");
 }
 
+#[tokio::test]
+async fn pass_fetch_userinfo_without_id_token() {
+    let (http_client, mock_server, issuer) = init_test().await;
+    let userinfo_endpoint = issuer.join("userinfo").unwrap();
+
+    Mock::given(method("GET"))
+        .and(path("/userinfo"))
+        .and(header(
+            "authorization",
+            format!("Bearer {ACCESS_TOKEN}").as_str(),
+        ))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "id": 42,
+            "login": "janedoe",
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let claims = fetch_userinfo(&http_client, &userinfo_endpoint, ACCESS_TOKEN, None, None)
+        .await
+        .unwrap();
+
+    assert_eq!(claims.get("id").unwrap(), 42);
+    assert_eq!(claims.get("login").unwrap(), "janedoe");
+}
+
 #[tokio::test]
 async fn fail_wrong_subject_identifier() {
     let (http_client, mock_server, issuer) = init_test().await;
@@ -73,7 +99,7 @@ async fn fail_wrong_subject_identifier() {
         &userinfo_endpoint,
         ACCESS_TOKEN,
         None,
-        &auth_id_token,
+        Some(&auth_id_token),
     )
     .await
     .unwrap_err();
@@ -8,6 +8,7 @@ use std::path::{Path, PathBuf};
 
 use mas_policy::model::{
     AuthorizationGrantInput, ClientRegistrationInput, EmailInput, PasswordInput, RegisterInput,
+    UpstreamOAuth2MappingInput,
 };
 use schemars::{gen::SchemaSettings, JsonSchema};
 
@@ -46,4 +47,5 @@ fn main() {
     write_schema::<AuthorizationGrantInput>(output_root, "authorization_grant_input.json");
     write_schema::<EmailInput>(output_root, "email_input.json");
     write_schema::<PasswordInput>(output_root, "password_input.json");
+    write_schema::<UpstreamOAuth2MappingInput>(output_root, "upstream_oauth2_mapping_input.json");
 }
@@ -6,7 +6,7 @@
 
 pub mod model;
 
-use mas_data_model::{AuthorizationGrant, Client, DeviceCodeGrant, User};
+use mas_data_model::{AuthorizationGrant, Client, DeviceCodeGrant, UpstreamOAuthProvider, User};
 use oauth2_types::{registration::VerifiedClientMetadata, scope::Scope};
 use opa_wasm::{
     wasmtime::{Config, Engine, Module, OptLevel, Store},
@@ -15,8 +15,11 @@ use opa_wasm::{
 use thiserror::Error;
 use tokio::io::{AsyncRead, AsyncReadExt};
 
-use self::model::{AuthorizationGrantInput, ClientRegistrationInput, EmailInput, RegisterInput};
-pub use self::model::{EvaluationResult, Violation};
+use self::model::{
+    AuthorizationGrantInput, ClientRegistrationInput, EmailInput, RegisterInput,
+    UpstreamOAuth2MappingInput, UpstreamOAuth2MappingResult,
+};
+pub use self::model::{EvaluationResult, UpstreamOAuth2Mapping, Violation};
 use crate::model::GrantType;
 
 #[derive(Debug, Error)]
@@ -56,16 +59,22 @@ pub struct Entrypoints {
     pub client_registration: String,
     pub authorization_grant: String,
     pub email: String,
+
+    /// Optional entrypoint used to map the claims of upstream OAuth 2.0
+    /// providers
+    pub upstream_oauth2_mapping: Option<String>,
 }
 
 impl Entrypoints {
-    fn all(&self) -> [&str; 4] {
+    fn all(&self) -> impl Iterator<Item = &str> {
         [
             self.register.as_str(),
             self.client_registration.as_str(),
             self.authorization_grant.as_str(),
             self.email.as_str(),
         ]
+        .into_iter()
+        .chain(self.upstream_oauth2_mapping.as_deref())
     }
 }
 
@@ -222,8 +231,13 @@ impl Policy {
         &mut self,
         username: &str,
         email: Option<&str>,
+        emails: &[String],
     ) -> Result<EvaluationResult, EvaluationError> {
-        let input = RegisterInput::UpstreamOAuth2 { username, email };
+        let input = RegisterInput::UpstreamOAuth2 {
+            username,
+            email,
+            emails,
+        };
 
         let [res]: [EvaluationResult; 1] = self
             .instance
@@ -233,6 +247,44 @@ impl Policy {
         Ok(res)
     }
 
+    /// Evaluate the upstream OAuth 2.0 claims mapping policy, if one is
+    /// configured
+    ///
+    /// Returns `None` if no entrypoint is configured for it
+    #[tracing::instrument(
+        name = "policy.evaluate.upstream_oauth2_mapping",
+        skip_all,
+        fields(
+            input.provider.id = %provider.id,
+        ),
+        err,
+    )]
+    pub async fn evaluate_upstream_oauth2_mapping(
+        &mut self,
+        provider: &UpstreamOAuthProvider,
+        id_token: Option<&serde_json::Map<String, serde_json::Value>>,
+        userinfo: Option<&serde_json::Map<String, serde_json::Value>>,
+    ) -> Result<Option<UpstreamOAuth2Mapping>, EvaluationError> {
+        let Some(entrypoint) = &self.entrypoints.upstream_oauth2_mapping else {
+            return Ok(None);
+        };
+
+        let provider_id = provider.id.to_string();
+        let input = UpstreamOAuth2MappingInput {
+            provider_id: &provider_id,
+            issuer: &provider.issuer,
+            id_token,
+            userinfo,
+        };
+
+        let [res]: [UpstreamOAuth2MappingResult; 1] = self
+            .instance
+            .evaluate(&mut self.store, entrypoint, &input)
+            .await?;
+
+        Ok(Some(res.result))
+    }
+
     #[tracing::instrument(skip(self))]
     pub async fn evaluate_client_registration(
         &mut self,
@@ -268,12 +320,14 @@ impl Policy {
         authorization_grant: &AuthorizationGrant,
         client: &Client,
         user: &User,
+        groups: &[String],
     ) -> Result<EvaluationResult, EvaluationError> {
         let input = AuthorizationGrantInput {
             user: Some(user),
             client,
             scope: &authorization_grant.scope,
             grant_type: GrantType::AuthorizationCode,
+            groups,
         };
 
         let [res]: [EvaluationResult; 1] = self
@@ -307,6 +361,7 @@ impl Policy {
             client,
             scope,
             grant_type: GrantType::ClientCredentials,
+            groups: &[],
         };
 
         let [res]: [EvaluationResult; 1] = self
@@ -337,12 +392,14 @@ impl Policy {
         device_code_grant: &DeviceCodeGrant,
         client: &Client,
         user: &User,
+        groups: &[String],
     ) -> Result<EvaluationResult, EvaluationError> {
         let input = AuthorizationGrantInput {
             user: Some(user),
             client,
             scope: &device_code_grant.scope,
             grant_type: GrantType::DeviceCode,
+            groups,
         };
 
         let [res]: [EvaluationResult; 1] = self
@@ -383,6 +440,7 @@ mod tests {
             client_registration: "client_registration/violation".to_owned(),
             authorization_grant: "authorization_grant/violation".to_owned(),
             email: "email/violation".to_owned(),
+            upstream_oauth2_mapping: Some("upstream_oauth2_mapping/result".to_owned()),
         };
 
         let factory = PolicyFactory::load(file, data, entrypoints).await.unwrap();
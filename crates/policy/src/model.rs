@@ -14,7 +14,7 @@ use oauth2_types::{registration::VerifiedClientMetadata, scope::Scope};
 use serde::{Deserialize, Serialize};
 
 /// A single violation of a policy.
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[cfg_attr(feature = "jsonschema", derive(schemars::JsonSchema))]
 pub struct Violation {
     pub msg: String,
@@ -66,6 +66,12 @@ pub enum RegisterInput<'a> {
 
         #[serde(skip_serializing_if = "Option::is_none")]
         email: Option<&'a str>,
+
+        /// All the email addresses the claims mapping policy found for the
+        /// user, including the ones which won't be imported
+        #[serde(skip_serializing_if = "<[_]>::is_empty")]
+        #[cfg_attr(feature = "jsonschema", schemars(default))]
+        emails: &'a [String],
     },
 }
 
@@ -112,6 +118,10 @@ pub struct AuthorizationGrantInput<'a> {
     pub scope: &'a Scope,
 
     pub grant_type: GrantType,
+
+    /// The groups the user is a member of, as claimed by the upstream
+    /// providers they logged in with
+    pub groups: &'a [String],
 }
 
 /// Input for the email add policy.
@@ -122,6 +132,83 @@ pub struct EmailInput<'a> {
     pub email: &'a str,
 }
 
+/// Input for the upstream OAuth 2.0 claims mapping policy.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(feature = "jsonschema", derive(schemars::JsonSchema))]
+pub struct UpstreamOAuth2MappingInput<'a> {
+    /// The ID of the upstream provider the user signed in with
+    pub provider_id: &'a str,
+
+    /// The issuer of the upstream provider
+    pub issuer: &'a str,
+
+    /// The claims of the ID token returned by the upstream provider, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(
+        feature = "jsonschema",
+        schemars(with = "Option<std::collections::HashMap<String, serde_json::Value>>")
+    )]
+    pub id_token: Option<&'a serde_json::Map<String, serde_json::Value>>,
+
+    /// The claims returned by the userinfo endpoint of the upstream provider,
+    /// if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(
+        feature = "jsonschema",
+        schemars(with = "Option<std::collections::HashMap<String, serde_json::Value>>")
+    )]
+    pub userinfo: Option<&'a serde_json::Map<String, serde_json::Value>>,
+}
+
+/// The attributes the upstream OAuth 2.0 claims mapping policy computed.
+///
+/// Attributes which are not set fall back to the templates of the provider
+/// configuration.
+#[derive(Deserialize, Debug, Default)]
+pub struct UpstreamOAuth2Mapping {
+    #[serde(default)]
+    pub localpart: Option<String>,
+
+    #[serde(default)]
+    pub displayname: Option<String>,
+
+    #[serde(default)]
+    pub emails: Vec<String>,
+
+    #[serde(default)]
+    pub violations: Vec<Violation>,
+}
+
+impl std::fmt::Display for UpstreamOAuth2Mapping {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut first = true;
+        for violation in &self.violations {
+            if first {
+                first = false;
+            } else {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", violation.msg)?;
+        }
+        Ok(())
+    }
+}
+
+impl UpstreamOAuth2Mapping {
+    /// Returns true if the policy allowed the user to continue.
+    #[must_use]
+    pub fn valid(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+/// The result of the upstream OAuth 2.0 claims mapping policy evaluation.
+#[derive(Deserialize, Debug)]
+pub(crate) struct UpstreamOAuth2MappingResult {
+    pub result: UpstreamOAuth2Mapping,
+}
+
 /// Input for the password set policy.
 #[derive(Serialize, Debug)]
 #[serde(rename_all = "snake_case")]
@@ -23,6 +23,7 @@ pub enum PostAuthAction {
         id: Ulid,
     },
     ChangePassword,
+    RecoverAccount,
     LinkUpstream {
         id: Ulid,
     },
@@ -70,6 +71,7 @@ impl PostAuthAction {
                 url_builder.redirect(&CompatLoginSsoComplete::new(*id, None))
             }
             Self::ChangePassword => url_builder.redirect(&AccountPasswordChange),
+            Self::RecoverAccount => url_builder.redirect(&AccountRecoveryStart),
             Self::LinkUpstream { id } => url_builder.redirect(&UpstreamOAuth2Link::new(*id)),
             Self::ManageAccount { action } => url_builder.redirect(&Account {
                 action: action.clone(),
@@ -509,6 +511,38 @@ impl SimpleRoute for AccountPasswordChange {
     const PATH: &'static str = "/account/password/change";
 }
 
+/// `GET /account/sessions/:id`
+///
+/// Handled by the React frontend; this struct definition is purely for
+/// redirects and links in emails.
+#[derive(Debug, Clone)]
+pub enum AccountSession {
+    /// A browser session
+    Browser(Ulid),
+
+    /// A compatibility layer session
+    Compat(Ulid),
+
+    /// An OAuth 2.0 session
+    OAuth2(Ulid),
+}
+
+impl Route for AccountSession {
+    type Query = ();
+    fn route() -> &'static str {
+        "/account/sessions/:id"
+    }
+
+    fn path(&self) -> std::borrow::Cow<'static, str> {
+        // The frontend expects the GraphQL node ID of the session
+        match self {
+            Self::Browser(id) => format!("/account/sessions/browser_session:{id}").into(),
+            Self::Compat(id) => format!("/account/sessions/compat_session:{id}").into(),
+            Self::OAuth2(id) => format!("/account/sessions/oauth2_session:{id}").into(),
+        }
+    }
+}
+
 /// `GET /authorize/:grant_id`
 #[derive(Debug, Clone)]
 pub struct ContinueAuthorizationGrant(pub Ulid);
@@ -629,10 +663,20 @@ impl Route for CompatLoginSsoComplete {
     }
 }
 
+#[derive(Default, Debug, Serialize, Deserialize, Clone)]
+pub struct UpstreamOAuth2AuthorizeParams {
+    #[serde(flatten)]
+    pub post_auth_action: Option<PostAuthAction>,
+
+    /// A hint about the user's identity to forward to the provider
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub login_hint: Option<String>,
+}
+
 /// `GET /upstream/authorize/:id`
 pub struct UpstreamOAuth2Authorize {
     id: Ulid,
-    post_auth_action: Option<PostAuthAction>,
+    params: UpstreamOAuth2AuthorizeParams,
 }
 
 impl UpstreamOAuth2Authorize {
@@ -640,19 +684,28 @@ impl UpstreamOAuth2Authorize {
     pub const fn new(id: Ulid) -> Self {
         Self {
             id,
-            post_auth_action: None,
+            params: UpstreamOAuth2AuthorizeParams {
+                post_auth_action: None,
+                login_hint: None,
+            },
         }
     }
 
     #[must_use]
     pub fn and_then(mut self, action: PostAuthAction) -> Self {
-        self.post_auth_action = Some(action);
+        self.params.post_auth_action = Some(action);
+        self
+    }
+
+    #[must_use]
+    pub fn with_login_hint(mut self, login_hint: String) -> Self {
+        self.params.login_hint = Some(login_hint);
         self
     }
 }
 
 impl Route for UpstreamOAuth2Authorize {
-    type Query = PostAuthAction;
+    type Query = UpstreamOAuth2AuthorizeParams;
     fn route() -> &'static str {
         "/upstream/authorize/:provider_id"
     }
@@ -662,7 +715,11 @@ impl Route for UpstreamOAuth2Authorize {
     }
 
     fn query(&self) -> Option<&Self::Query> {
-        self.post_auth_action.as_ref()
+        if self.params.post_auth_action.is_none() && self.params.login_hint.is_none() {
+            None
+        } else {
+            Some(&self.params)
+        }
     }
 }
 
@@ -712,6 +769,60 @@ impl Route for UpstreamOAuth2Link {
     }
 }
 
+/// `POST /upstream/backchannel-logout/:id`
+pub struct UpstreamOAuth2BackchannelLogout {
+    id: Ulid,
+}
+
+impl UpstreamOAuth2BackchannelLogout {
+    #[must_use]
+    pub const fn new(id: Ulid) -> Self {
+        Self { id }
+    }
+}
+
+impl Route for UpstreamOAuth2BackchannelLogout {
+    type Query = ();
+    fn route() -> &'static str {
+        "/upstream/backchannel-logout/:provider_id"
+    }
+
+    fn path(&self) -> std::borrow::Cow<'static, str> {
+        format!("/upstream/backchannel-logout/{}", self.id).into()
+    }
+}
+
+/// `GET /upstream/frontchannel-logout/:id`
+pub struct UpstreamOAuth2FrontchannelLogout {
+    id: Ulid,
+}
+
+impl UpstreamOAuth2FrontchannelLogout {
+    #[must_use]
+    pub const fn new(id: Ulid) -> Self {
+        Self { id }
+    }
+}
+
+impl Route for UpstreamOAuth2FrontchannelLogout {
+    type Query = ();
+    fn route() -> &'static str {
+        "/upstream/frontchannel-logout/:provider_id"
+    }
+
+    fn path(&self) -> std::borrow::Cow<'static, str> {
+        format!("/upstream/frontchannel-logout/{}", self.id).into()
+    }
+}
+
+/// `POST /upstream/token`
+#[derive(Default, Debug, Clone)]
+pub struct UpstreamOAuth2TokenExchange;
+
+impl SimpleRoute for UpstreamOAuth2TokenExchange {
+    const PATH: &'static str = "/upstream/token";
+}
+
 /// `GET|POST /link`
 #[derive(Default, Serialize, Deserialize, Debug, Clone)]
 pub struct DeviceCodeLink {
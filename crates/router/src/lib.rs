@@ -30,6 +30,17 @@ mod tests {
             Login::and_continue_grant(Ulid::nil()).path_and_query(),
             Cow::Borrowed("/login?kind=continue_authorization_grant&id=00000000000000000000000000")
         );
+        assert_eq!(
+            UpstreamOAuth2Authorize::new(Ulid::nil()).path_and_query(),
+            Cow::Borrowed("/upstream/authorize/00000000000000000000000000")
+        );
+        assert_eq!(
+            UpstreamOAuth2Authorize::new(Ulid::nil())
+                .and_then(PostAuthAction::continue_grant(Ulid::nil()))
+                .with_login_hint("alice@example.com".to_owned())
+                .path_and_query(),
+            Cow::Borrowed("/upstream/authorize/00000000000000000000000000?kind=continue_authorization_grant&id=00000000000000000000000000&login_hint=alice%40example.com")
+        );
     }
 
     #[test]
@@ -0,0 +1,24 @@
+-- Copyright 2024 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only
+-- Please see LICENSE in the repository root for full details.
+
+-- Tracks failed login attempts on user accounts, so that they can be
+-- temporarily locked out after too many of them. This is shared across all
+-- the instances of the service, unlike the in-memory rate limiters.
+CREATE TABLE "user_login_lockouts" (
+  "user_id" UUID NOT NULL
+    CONSTRAINT "user_login_lockouts_pkey"
+    PRIMARY KEY
+    REFERENCES "users" ("user_id")
+    ON DELETE CASCADE,
+
+  -- How many failed login attempts were made in the current window
+  "failed_attempts" INTEGER NOT NULL,
+
+  -- When the last failed login attempt happened
+  "last_failed_at" TIMESTAMP WITH TIME ZONE NOT NULL,
+
+  -- Until when the account is locked out, if it is
+  "locked_until" TIMESTAMP WITH TIME ZONE
+);
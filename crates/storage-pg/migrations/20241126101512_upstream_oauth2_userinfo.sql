@@ -0,0 +1,15 @@
+-- Copyright 2024 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only
+-- Please see LICENSE in the repository root for full details.
+
+-- Add columns to the upstream_oauth_providers table to fetch claims from the
+-- userinfo endpoint, for providers which don't return an ID token
+ALTER TABLE "upstream_oauth_providers"
+    ADD COLUMN "fetch_userinfo" BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN "userinfo_endpoint_override" TEXT;
+
+-- Add a column to the upstream_oauth_authorization_sessions table to store
+-- the claims fetched from the userinfo endpoint
+ALTER TABLE "upstream_oauth_authorization_sessions"
+    ADD COLUMN "userinfo" JSONB;
@@ -0,0 +1,10 @@
+-- Copyright 2024 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only
+-- Please see LICENSE in the repository root for full details.
+
+-- Keep track of the email address which was imported from the upstream
+-- provider through a link, so that it can be replaced when it changes upstream
+ALTER TABLE "upstream_oauth_links"
+    ADD COLUMN "imported_user_email_id" UUID
+        REFERENCES "user_emails" ("user_email_id") ON DELETE SET NULL;
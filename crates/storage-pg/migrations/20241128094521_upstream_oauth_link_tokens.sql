@@ -0,0 +1,33 @@
+-- Copyright 2024 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only
+-- Please see LICENSE in the repository root for full details.
+
+-- Add columns to the upstream_oauth_providers table to keep the tokens issued
+-- by the provider, and to let some clients get them
+ALTER TABLE "upstream_oauth_providers"
+    ADD COLUMN "store_tokens" BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN "token_exchange_client_ids" UUID[] NOT NULL DEFAULT '{}';
+
+-- The tokens issued by the upstream provider for a link, encrypted
+CREATE TABLE "upstream_oauth_link_tokens" (
+  "upstream_oauth_link_id" UUID NOT NULL
+    CONSTRAINT "upstream_oauth_link_tokens_pkey"
+    PRIMARY KEY
+    REFERENCES "upstream_oauth_links" ("upstream_oauth_link_id")
+    ON DELETE CASCADE,
+
+  "encrypted_access_token" TEXT NOT NULL,
+  "encrypted_refresh_token" TEXT,
+
+  -- When the access token expires, if the provider told us
+  "access_token_expires_at" TIMESTAMP WITH TIME ZONE,
+
+  "created_at" TIMESTAMP WITH TIME ZONE NOT NULL,
+  "updated_at" TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+-- Used to find the tokens which need to be refreshed
+CREATE INDEX "upstream_oauth_link_tokens_access_token_expires_at_idx"
+    ON "upstream_oauth_link_tokens" ("access_token_expires_at")
+    WHERE "encrypted_refresh_token" IS NOT NULL;
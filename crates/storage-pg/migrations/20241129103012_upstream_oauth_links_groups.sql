@@ -0,0 +1,9 @@
+-- Copyright 2024 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only
+-- Please see LICENSE in the repository root for full details.
+
+-- Keep track of the groups the upstream provider claimed the user is a member
+-- of, so that they can be used in policy decisions
+ALTER TABLE "upstream_oauth_links"
+    ADD COLUMN "groups" TEXT[] NOT NULL DEFAULT '{}';
@@ -0,0 +1,9 @@
+-- Copyright 2024 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only
+-- Please see LICENSE in the repository root for full details.
+
+-- The email domains for which users should be sent to this provider when
+-- signing in, for home-realm discovery
+ALTER TABLE "upstream_oauth_providers"
+    ADD COLUMN "domains" TEXT[] NOT NULL DEFAULT '{}';
@@ -0,0 +1,26 @@
+-- Copyright 2024 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only
+-- Please see LICENSE in the repository root for full details.
+
+-- Keep the claims of the ID token on upstream authorization sessions, so that
+-- sessions can be found by their `sub` and `sid` claims when the provider
+-- sends a back-channel logout request
+ALTER TABLE "upstream_oauth_authorization_sessions"
+    ADD COLUMN "id_token_claims" JSONB;
+
+CREATE INDEX "upstream_oauth_authorization_sessions_sub_idx"
+    ON "upstream_oauth_authorization_sessions" (
+        "upstream_oauth_provider_id",
+        (("id_token_claims"->>'sub'))
+    );
+
+CREATE INDEX "upstream_oauth_authorization_sessions_sid_idx"
+    ON "upstream_oauth_authorization_sessions" (
+        "upstream_oauth_provider_id",
+        (("id_token_claims"->>'sid'))
+    );
+
+-- Used to find the browser sessions authenticated by an upstream session
+CREATE INDEX "user_session_authentications_upstream_oauth_session_id_idx"
+    ON "user_session_authentications" ("upstream_oauth_authorization_session_id");
@@ -0,0 +1,29 @@
+-- Copyright 2024 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only
+-- Please see LICENSE in the repository root for full details.
+
+-- Let users recover their account by signing in with a linked upstream
+-- provider. Such recovery sessions aren't started from an email address, and
+-- their tickets are issued for the upstream link instead of an email address.
+ALTER TABLE "user_recovery_sessions"
+  ALTER COLUMN "email" DROP NOT NULL;
+
+ALTER TABLE "user_recovery_tickets"
+  ADD COLUMN "user_id" UUID
+    REFERENCES "users" ("user_id")
+    ON DELETE CASCADE,
+  ADD COLUMN "upstream_oauth_link_id" UUID
+    REFERENCES "upstream_oauth_links" ("upstream_oauth_link_id")
+    ON DELETE CASCADE,
+  ALTER COLUMN "user_email_id" DROP NOT NULL;
+
+UPDATE "user_recovery_tickets"
+  SET "user_id" = "user_emails"."user_id"
+  FROM "user_emails"
+  WHERE "user_emails"."user_email_id" = "user_recovery_tickets"."user_email_id";
+
+ALTER TABLE "user_recovery_tickets"
+  ALTER COLUMN "user_id" SET NOT NULL,
+  ADD CONSTRAINT "user_recovery_tickets_email_or_link_check"
+    CHECK ("user_email_id" IS NOT NULL OR "upstream_oauth_link_id" IS NOT NULL);
@@ -0,0 +1,43 @@
+-- Copyright 2024 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only
+-- Please see LICENSE in the repository root for full details.
+
+-- Append-only log of security-relevant events. Users and sessions are
+-- referenced without foreign keys, so that events outlive them.
+CREATE TABLE "audit_log_events" (
+  "audit_log_event_id" UUID NOT NULL
+    CONSTRAINT "audit_log_events_pkey"
+    PRIMARY KEY,
+
+  "created_at" TIMESTAMP WITH TIME ZONE NOT NULL,
+
+  -- The kind of event, e.g. 'login.failed' or 'admin.action'
+  "kind" TEXT NOT NULL,
+
+  -- The user who did the action, if any
+  "actor_user_id" UUID,
+
+  -- The OAuth 2.0 session which did the action, e.g. an admin API session
+  "actor_oauth2_session_id" UUID,
+
+  -- The user affected by the action, if any
+  "user_id" UUID,
+
+  "ip_address" INET,
+  "user_agent" TEXT,
+
+  -- Additional details, depending on the kind of event
+  "data" JSONB NOT NULL DEFAULT '{}'
+);
+
+-- Used to prune old events
+CREATE INDEX "audit_log_events_created_at_idx"
+  ON "audit_log_events" ("created_at");
+
+-- Used to look up the events affecting or done by a user
+CREATE INDEX "audit_log_events_user_id_idx"
+  ON "audit_log_events" ("user_id");
+
+CREATE INDEX "audit_log_events_actor_user_id_idx"
+  ON "audit_log_events" ("actor_user_id");
@@ -0,0 +1,38 @@
+-- Copyright 2024 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only
+-- Please see LICENSE in the repository root for full details.
+
+-- Log of the deliveries of events to the webhook endpoints. Endpoints are
+-- defined in the configuration, so they are referenced without foreign keys.
+CREATE TABLE "webhook_deliveries" (
+  "webhook_delivery_id" UUID NOT NULL
+    CONSTRAINT "webhook_deliveries_pkey"
+    PRIMARY KEY,
+
+  -- The ID of the endpoint, as configured
+  "endpoint_id" UUID NOT NULL,
+
+  -- The kind of event, e.g. 'user.registered'
+  "event_kind" TEXT NOT NULL,
+
+  -- The details of the event sent to the endpoint
+  "payload" JSONB NOT NULL,
+
+  "created_at" TIMESTAMP WITH TIME ZONE NOT NULL,
+
+  -- How many times the delivery was attempted, and the outcome of the last
+  -- failed attempt
+  "attempts" INTEGER NOT NULL DEFAULT 0,
+  "last_attempted_at" TIMESTAMP WITH TIME ZONE,
+  "last_error" TEXT,
+
+  -- Set once the endpoint acknowledged the event
+  "delivered_at" TIMESTAMP WITH TIME ZONE,
+
+  -- Set once we gave up on delivering the event
+  "failed_at" TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX "webhook_deliveries_endpoint_id_idx"
+  ON "webhook_deliveries" ("endpoint_id");
@@ -0,0 +1,45 @@
+-- Copyright 2024 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only
+-- Please see LICENSE in the repository root for full details.
+
+-- Usage statistics, aggregated per day by a periodic job
+CREATE TABLE "usage_stats" (
+  "usage_stats_id" UUID NOT NULL
+    CONSTRAINT "usage_stats_pkey"
+    PRIMARY KEY,
+
+  -- The day (in UTC) the statistics are about
+  "day" DATE NOT NULL
+    CONSTRAINT "usage_stats_day_unique"
+    UNIQUE,
+
+  -- Number of users with a session active during the day
+  "daily_active_users" BIGINT NOT NULL,
+
+  -- Number of users with a session active during the 30 days up to the end
+  -- of the day
+  "monthly_active_users" BIGINT NOT NULL,
+
+  -- Number of users who registered during the day
+  "registrations" BIGINT NOT NULL,
+
+  -- Number of logins during the day, by method
+  "password_logins" BIGINT NOT NULL,
+  "upstream_oauth2_logins" BIGINT NOT NULL,
+  "compat_logins" BIGINT NOT NULL,
+
+  -- When the statistics were last computed. They are recomputed while the day
+  -- is still in progress
+  "computed_at" TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+-- Those help counting what happened during a day
+CREATE INDEX "users_created_at_idx"
+  ON "users" ("created_at");
+
+CREATE INDEX "user_session_authentications_created_at_idx"
+  ON "user_session_authentications" ("created_at");
+
+CREATE INDEX "compat_sessions_created_at_idx"
+  ON "compat_sessions" ("created_at");
@@ -0,0 +1,41 @@
+-- Copyright 2024 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only
+-- Please see LICENSE in the repository root for full details.
+
+-- Tokens which can be required to register new accounts
+CREATE TABLE "user_registration_tokens" (
+  "user_registration_token_id" UUID NOT NULL
+    CONSTRAINT "user_registration_tokens_pkey"
+    PRIMARY KEY,
+
+  -- The token users have to provide when registering
+  "token" TEXT NOT NULL
+    CONSTRAINT "user_registration_tokens_token_unique"
+    UNIQUE,
+
+  -- How many times the token can be used, NULL for unlimited
+  "usage_limit" INTEGER,
+
+  -- How many times the token was used
+  "times_used" INTEGER NOT NULL DEFAULT 0,
+
+  "created_at" TIMESTAMP WITH TIME ZONE NOT NULL,
+  "last_used_at" TIMESTAMP WITH TIME ZONE,
+  "expires_at" TIMESTAMP WITH TIME ZONE,
+  "revoked_at" TIMESTAMP WITH TIME ZONE
+);
+
+-- The users who registered with each token
+CREATE TABLE "user_registration_token_uses" (
+  "user_registration_token_id" UUID NOT NULL
+    REFERENCES "user_registration_tokens" ("user_registration_token_id"),
+
+  "user_id" UUID NOT NULL
+    REFERENCES "users" ("user_id") ON DELETE CASCADE,
+
+  "created_at" TIMESTAMP WITH TIME ZONE NOT NULL,
+
+  CONSTRAINT "user_registration_token_uses_pkey"
+    PRIMARY KEY ("user_registration_token_id", "user_id")
+);
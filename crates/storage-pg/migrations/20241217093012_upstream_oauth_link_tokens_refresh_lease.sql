@@ -0,0 +1,10 @@
+-- Copyright 2024 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only
+-- Please see LICENSE in the repository root for full details.
+
+-- Tokens are leased while they are being refreshed, so that the upstream
+-- provider isn't called from within a transaction, and that the same refresh
+-- token isn't used twice at the same time
+ALTER TABLE "upstream_oauth_link_tokens"
+    ADD COLUMN "refresh_leased_until" TIMESTAMP WITH TIME ZONE;
@@ -0,0 +1,30 @@
+-- Copyright 2024 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only
+-- Please see LICENSE in the repository root for full details.
+
+-- The days on which each user had an active session. Sessions only keep when
+-- they were last active, so this is what the usage statistics are computed
+-- from, to keep counting users who were active again since.
+CREATE TABLE "user_activity_days" (
+  "day" DATE NOT NULL,
+
+  "user_id" UUID NOT NULL
+    REFERENCES "users" ("user_id") ON DELETE CASCADE,
+
+  CONSTRAINT "user_activity_days_pkey"
+    PRIMARY KEY ("day", "user_id")
+);
+
+-- Start from the last activity recorded on the existing sessions
+INSERT INTO "user_activity_days" ("day", "user_id")
+  SELECT DISTINCT ("last_active_at" AT TIME ZONE 'UTC')::date, "user_id"
+  FROM (
+    SELECT "user_id", "last_active_at" FROM "user_sessions"
+    UNION ALL
+    SELECT "user_id", "last_active_at" FROM "oauth2_sessions"
+    WHERE "user_id" IS NOT NULL
+    UNION ALL
+    SELECT "user_id", "last_active_at" FROM "compat_sessions"
+  ) AS "sessions"
+  WHERE "last_active_at" IS NOT NULL;
@@ -241,6 +241,16 @@ fn split_filter(
         oauth2_filter = oauth2_filter.with_last_active_after(last_active_after);
     }
 
+    if let Some(user_agent) = filter.user_agent() {
+        compat_filter = compat_filter.with_user_agent(user_agent);
+        oauth2_filter = oauth2_filter.with_user_agent(user_agent);
+    }
+
+    if let Some(last_active_ip) = filter.last_active_ip() {
+        compat_filter = compat_filter.with_last_active_ip(last_active_ip);
+        oauth2_filter = oauth2_filter.with_last_active_ip(last_active_ip);
+    }
+
     (compat_filter, oauth2_filter)
 }
 
@@ -0,0 +1,451 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+//! A module containing the PostgreSQL implementation of the audit log
+//! repository
+
+use std::net::IpAddr;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use mas_data_model::{AuditLogEvent, AuditLogEventKind};
+use mas_storage::{
+    audit_log::{AuditLogFilter, AuditLogRepository, NewAuditLogEvent},
+    Clock, Page, Pagination,
+};
+use rand::RngCore;
+use sea_query::{Expr, PostgresQueryBuilder, Query};
+use sea_query_binder::SqlxBinder;
+use sqlx::{types::Json, PgConnection};
+use ulid::Ulid;
+use uuid::Uuid;
+
+use crate::{
+    filter::{Filter, StatementExt},
+    iden::AuditLogEvents,
+    pagination::QueryBuilderExt,
+    tracing::ExecuteExt,
+    DatabaseError, DatabaseInconsistencyError,
+};
+
+/// An implementation of [`AuditLogRepository`] for a PostgreSQL connection
+pub struct PgAuditLogRepository<'c> {
+    conn: &'c mut PgConnection,
+}
+
+impl<'c> PgAuditLogRepository<'c> {
+    /// Create a new [`PgAuditLogRepository`] from an active PostgreSQL
+    /// connection
+    pub fn new(conn: &'c mut PgConnection) -> Self {
+        Self { conn }
+    }
+}
+
+mod priv_ {
+    // The enum_def macro generates a public enum, which we don't want, because it
+    // triggers the missing docs warning
+
+    use std::net::IpAddr;
+
+    use chrono::{DateTime, Utc};
+    use sea_query::enum_def;
+    use sqlx::types::Json;
+    use uuid::Uuid;
+
+    #[derive(sqlx::FromRow)]
+    #[enum_def]
+    pub(super) struct AuditLogEventLookup {
+        pub(super) audit_log_event_id: Uuid,
+        pub(super) created_at: DateTime<Utc>,
+        pub(super) kind: String,
+        pub(super) actor_user_id: Option<Uuid>,
+        pub(super) actor_oauth2_session_id: Option<Uuid>,
+        pub(super) user_id: Option<Uuid>,
+        pub(super) ip_address: Option<IpAddr>,
+        pub(super) user_agent: Option<String>,
+        pub(super) data: Json<serde_json::Value>,
+    }
+}
+
+use priv_::{AuditLogEventLookup, AuditLogEventLookupIden};
+
+impl TryFrom<AuditLogEventLookup> for AuditLogEvent {
+    type Error = DatabaseInconsistencyError;
+
+    fn try_from(value: AuditLogEventLookup) -> Result<Self, Self::Error> {
+        let id = Ulid::from(value.audit_log_event_id);
+        let kind: AuditLogEventKind = value.kind.parse().map_err(|e| {
+            DatabaseInconsistencyError::on("audit_log_events")
+                .column("kind")
+                .row(id)
+                .source(e)
+        })?;
+
+        Ok(AuditLogEvent {
+            id,
+            created_at: value.created_at,
+            kind,
+            actor_user_id: value.actor_user_id.map(Ulid::from),
+            actor_session_id: value.actor_oauth2_session_id.map(Ulid::from),
+            user_id: value.user_id.map(Ulid::from),
+            ip_address: value.ip_address,
+            user_agent: value.user_agent,
+            data: value.data.0,
+        })
+    }
+}
+
+impl Filter for AuditLogFilter<'_> {
+    fn generate_condition(&self, _has_joins: bool) -> impl sea_query::IntoCondition {
+        sea_query::Condition::all()
+            .add_option(self.kind().map(|kind| {
+                Expr::col((AuditLogEvents::Table, AuditLogEvents::Kind)).eq(kind.as_str())
+            }))
+            .add_option(self.user().map(|user| {
+                Expr::col((AuditLogEvents::Table, AuditLogEvents::UserId)).eq(Uuid::from(user.id))
+            }))
+            .add_option(self.actor().map(|actor| {
+                Expr::col((AuditLogEvents::Table, AuditLogEvents::ActorUserId))
+                    .eq(Uuid::from(actor.id))
+            }))
+            .add_option(self.actor_session().map(|session| {
+                Expr::col((AuditLogEvents::Table, AuditLogEvents::ActorOAuth2SessionId))
+                    .eq(Uuid::from(session.id))
+            }))
+            .add_option(self.created_before().map(|created_before| {
+                Expr::col((AuditLogEvents::Table, AuditLogEvents::CreatedAt)).lt(created_before)
+            }))
+            .add_option(self.created_after().map(|created_after| {
+                Expr::col((AuditLogEvents::Table, AuditLogEvents::CreatedAt)).gt(created_after)
+            }))
+    }
+}
+
+#[async_trait]
+impl<'c> AuditLogRepository for PgAuditLogRepository<'c> {
+    type Error = DatabaseError;
+
+    #[tracing::instrument(
+        name = "db.audit_log.lookup",
+        skip_all,
+        fields(
+            db.query.text,
+            audit_log_event.id = %id,
+        ),
+        err,
+    )]
+    async fn lookup(&mut self, id: Ulid) -> Result<Option<AuditLogEvent>, Self::Error> {
+        let res = sqlx::query_as!(
+            AuditLogEventLookup,
+            r#"
+                SELECT audit_log_event_id
+                     , created_at
+                     , kind
+                     , actor_user_id
+                     , actor_oauth2_session_id
+                     , user_id
+                     , ip_address as "ip_address: IpAddr"
+                     , user_agent
+                     , data as "data: Json<serde_json::Value>"
+                FROM audit_log_events
+                WHERE audit_log_event_id = $1
+            "#,
+            Uuid::from(id),
+        )
+        .traced()
+        .fetch_optional(&mut *self.conn)
+        .await?;
+
+        let Some(res) = res else { return Ok(None) };
+
+        Ok(Some(res.try_into()?))
+    }
+
+    #[tracing::instrument(
+        name = "db.audit_log.add",
+        skip_all,
+        fields(
+            db.query.text,
+            audit_log_event.id,
+            audit_log_event.kind = %event.kind(),
+        ),
+        err,
+    )]
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        event: NewAuditLogEvent,
+    ) -> Result<AuditLogEvent, Self::Error> {
+        let created_at = clock.now();
+        let id = Ulid::from_datetime_with_source(created_at.into(), rng);
+        tracing::Span::current().record("audit_log_event.id", tracing::field::display(id));
+
+        sqlx::query!(
+            r#"
+                INSERT INTO audit_log_events
+                    ( audit_log_event_id
+                    , created_at
+                    , kind
+                    , actor_user_id
+                    , actor_oauth2_session_id
+                    , user_id
+                    , ip_address
+                    , user_agent
+                    , data
+                    )
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            "#,
+            Uuid::from(id),
+            created_at,
+            event.kind().as_str(),
+            event.actor_user_id().map(Uuid::from),
+            event.actor_session_id().map(Uuid::from),
+            event.user_id().map(Uuid::from),
+            event.ip_address() as Option<IpAddr>,
+            event.user_agent(),
+            event.data(),
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(AuditLogEvent {
+            id,
+            created_at,
+            kind: event.kind(),
+            actor_user_id: event.actor_user_id(),
+            actor_session_id: event.actor_session_id(),
+            user_id: event.user_id(),
+            ip_address: event.ip_address(),
+            user_agent: event.user_agent().map(ToOwned::to_owned),
+            data: event.data().clone(),
+        })
+    }
+
+    #[tracing::instrument(
+        name = "db.audit_log.list",
+        skip_all,
+        fields(
+            db.query.text,
+        ),
+        err,
+    )]
+    async fn list(
+        &mut self,
+        filter: AuditLogFilter<'_>,
+        pagination: Pagination,
+    ) -> Result<Page<AuditLogEvent>, Self::Error> {
+        let (sql, arguments) = Query::select()
+            .expr_as(
+                Expr::col((AuditLogEvents::Table, AuditLogEvents::AuditLogEventId)),
+                AuditLogEventLookupIden::AuditLogEventId,
+            )
+            .expr_as(
+                Expr::col((AuditLogEvents::Table, AuditLogEvents::CreatedAt)),
+                AuditLogEventLookupIden::CreatedAt,
+            )
+            .expr_as(
+                Expr::col((AuditLogEvents::Table, AuditLogEvents::Kind)),
+                AuditLogEventLookupIden::Kind,
+            )
+            .expr_as(
+                Expr::col((AuditLogEvents::Table, AuditLogEvents::ActorUserId)),
+                AuditLogEventLookupIden::ActorUserId,
+            )
+            .expr_as(
+                Expr::col((AuditLogEvents::Table, AuditLogEvents::ActorOAuth2SessionId)),
+                AuditLogEventLookupIden::ActorOauth2SessionId,
+            )
+            .expr_as(
+                Expr::col((AuditLogEvents::Table, AuditLogEvents::UserId)),
+                AuditLogEventLookupIden::UserId,
+            )
+            .expr_as(
+                Expr::col((AuditLogEvents::Table, AuditLogEvents::IpAddress)),
+                AuditLogEventLookupIden::IpAddress,
+            )
+            .expr_as(
+                Expr::col((AuditLogEvents::Table, AuditLogEvents::UserAgent)),
+                AuditLogEventLookupIden::UserAgent,
+            )
+            .expr_as(
+                Expr::col((AuditLogEvents::Table, AuditLogEvents::Data)),
+                AuditLogEventLookupIden::Data,
+            )
+            .from(AuditLogEvents::Table)
+            .apply_filter(filter)
+            .generate_pagination(
+                (AuditLogEvents::Table, AuditLogEvents::AuditLogEventId),
+                pagination,
+            )
+            .build_sqlx(PostgresQueryBuilder);
+
+        let edges: Vec<AuditLogEventLookup> = sqlx::query_as_with(&sql, arguments)
+            .traced()
+            .fetch_all(&mut *self.conn)
+            .await?;
+
+        let page = pagination.process(edges).try_map(AuditLogEvent::try_from)?;
+
+        Ok(page)
+    }
+
+    #[tracing::instrument(
+        name = "db.audit_log.count",
+        skip_all,
+        fields(
+            db.query.text,
+        ),
+        err,
+    )]
+    async fn count(&mut self, filter: AuditLogFilter<'_>) -> Result<usize, Self::Error> {
+        let (sql, arguments) = Query::select()
+            .expr(Expr::col((AuditLogEvents::Table, AuditLogEvents::AuditLogEventId)).count())
+            .from(AuditLogEvents::Table)
+            .apply_filter(filter)
+            .build_sqlx(PostgresQueryBuilder);
+
+        let count: i64 = sqlx::query_scalar_with(&sql, arguments)
+            .traced()
+            .fetch_one(&mut *self.conn)
+            .await?;
+
+        count
+            .try_into()
+            .map_err(DatabaseError::to_invalid_operation)
+    }
+
+    #[tracing::instrument(
+        name = "db.audit_log.prune",
+        skip_all,
+        fields(
+            db.query.text,
+            before = %before,
+        ),
+        err,
+    )]
+    async fn prune(&mut self, before: DateTime<Utc>) -> Result<usize, Self::Error> {
+        let res = sqlx::query!(
+            r#"
+                DELETE FROM audit_log_events
+                WHERE created_at < $1
+            "#,
+            before,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(res.rows_affected().try_into().unwrap_or(usize::MAX))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use mas_data_model::AuditLogEventKind;
+    use mas_storage::{
+        audit_log::{AuditLogFilter, NewAuditLogEvent},
+        clock::MockClock,
+        Clock, Pagination, RepositoryAccess,
+    };
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+    use sqlx::PgPool;
+
+    use crate::PgRepository;
+
+    #[sqlx::test(migrator = "crate::MIGRATOR")]
+    async fn test_audit_log_repo(pool: PgPool) {
+        let mut rng = ChaChaRng::seed_from_u64(42);
+        let clock = MockClock::default();
+        let mut repo = PgRepository::from_pool(&pool).await.unwrap();
+
+        let alice = repo
+            .user()
+            .add(&mut rng, &clock, "alice".to_owned())
+            .await
+            .unwrap();
+        let bob = repo
+            .user()
+            .add(&mut rng, &clock, "bob".to_owned())
+            .await
+            .unwrap();
+
+        let all = AuditLogFilter::new();
+        let pagination = Pagination::first(10);
+        assert_eq!(repo.audit_log().count(all).await.unwrap(), 0);
+
+        let failed = repo
+            .audit_log()
+            .add(
+                &mut rng,
+                &clock,
+                NewAuditLogEvent::new(AuditLogEventKind::LoginFailed)
+                    .for_user(&alice)
+                    .with_ip_address(Some("1.2.3.4".parse().unwrap()))
+                    .with_data(serde_json::json!({ "method": "password" })),
+            )
+            .await
+            .unwrap();
+        assert_eq!(failed.kind, AuditLogEventKind::LoginFailed);
+        assert_eq!(failed.user_id, Some(alice.id));
+
+        // Lookup the event
+        let event = repo.audit_log().lookup(failed.id).await.unwrap().unwrap();
+        assert_eq!(event, failed);
+
+        clock.advance(Duration::try_minutes(1).unwrap());
+
+        // Bob locks Alice out
+        let locked = repo
+            .audit_log()
+            .add(
+                &mut rng,
+                &clock,
+                NewAuditLogEvent::new(AuditLogEventKind::AdminAction)
+                    .with_actor(&bob)
+                    .for_user(&alice)
+                    .with_data(serde_json::json!({ "operation": "lockUser" })),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(repo.audit_log().count(all).await.unwrap(), 2);
+        assert_eq!(
+            repo.audit_log().count(all.for_user(&alice)).await.unwrap(),
+            2
+        );
+        assert_eq!(repo.audit_log().count(all.for_user(&bob)).await.unwrap(), 0);
+
+        let filter = all.by_actor(&bob);
+        let page = repo.audit_log().list(filter, pagination).await.unwrap();
+        assert!(!page.has_next_page);
+        assert_eq!(page.edges, vec![locked.clone()]);
+
+        let filter = all.with_kind(AuditLogEventKind::LoginFailed);
+        let page = repo.audit_log().list(filter, pagination).await.unwrap();
+        assert_eq!(page.edges, vec![failed.clone()]);
+
+        let filter = all.with_created_after(failed.created_at);
+        let page = repo.audit_log().list(filter, pagination).await.unwrap();
+        assert_eq!(page.edges, vec![locked.clone()]);
+
+        let filter = all.with_created_before(locked.created_at);
+        let page = repo.audit_log().list(filter, pagination).await.unwrap();
+        assert_eq!(page.edges, vec![failed.clone()]);
+
+        // Prune the events older than the lockout
+        let pruned = repo.audit_log().prune(locked.created_at).await.unwrap();
+        assert_eq!(pruned, 1);
+        assert!(repo.audit_log().lookup(failed.id).await.unwrap().is_none());
+        assert_eq!(repo.audit_log().count(all).await.unwrap(), 1);
+
+        // Nothing left to prune
+        let pruned = repo.audit_log().prune(clock.now()).await.unwrap();
+        assert_eq!(pruned, 0);
+    }
+}
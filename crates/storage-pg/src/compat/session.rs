@@ -17,7 +17,7 @@ use mas_storage::{
     Clock, Page, Pagination,
 };
 use rand::RngCore;
-use sea_query::{enum_def, Expr, PostgresQueryBuilder, Query};
+use sea_query::{enum_def, Alias, Expr, Func, PostgresQueryBuilder, Query};
 use sea_query_binder::SqlxBinder;
 use sqlx::PgConnection;
 use ulid::Ulid;
@@ -253,6 +253,25 @@ impl Filter for CompatSessionFilter<'_> {
                 Expr::col((CompatSessions::Table, CompatSessions::LastActiveAt))
                     .lt(last_active_before)
             }))
+            .add_option(self.user_agent().map(|user_agent| {
+                Expr::col((CompatSessions::Table, CompatSessions::UserAgent)).eq(user_agent)
+            }))
+            .add_option(self.last_active_ip().map(|last_active_ip| {
+                // sea-query doesn't know about IP addresses, so we pass it as text
+                Expr::col((CompatSessions::Table, CompatSessions::LastActiveIp))
+                    .eq(Expr::val(last_active_ip.to_string()).cast_as(Alias::new("inet")))
+            }))
+            .add_option(self.created_before().map(|created_before| {
+                Expr::col((CompatSessions::Table, CompatSessions::CreatedAt)).lt(created_before)
+            }))
+            .add_option(self.inactive_since().map(|inactive_since| {
+                // Sessions which were never used are inactive since their creation
+                Expr::expr(Func::coalesce([
+                    Expr::col((CompatSessions::Table, CompatSessions::LastActiveAt)).into(),
+                    Expr::col((CompatSessions::Table, CompatSessions::CreatedAt)).into(),
+                ]))
+                .lt(inactive_since)
+            }))
             .add_option(self.device().map(|device| {
                 Expr::col((CompatSessions::Table, CompatSessions::DeviceId)).eq(device.as_str())
             }))
@@ -593,6 +612,24 @@ impl<'c> CompatSessionRepository for PgCompatSessionRepository<'c> {
 
         DatabaseError::ensure_affected_rows(&res, ids.len().try_into().unwrap_or(u64::MAX))?;
 
+        // Also record the days on which the users were active, for the usage
+        // statistics
+        sqlx::query!(
+            r#"
+                INSERT INTO user_activity_days (day, user_id)
+                SELECT DISTINCT (t.last_active_at AT TIME ZONE 'UTC')::date, compat_sessions.user_id
+                FROM UNNEST($1::uuid[], $2::timestamptz[])
+                    AS t(compat_session_id, last_active_at)
+                INNER JOIN compat_sessions USING (compat_session_id)
+                ON CONFLICT DO NOTHING
+            "#,
+            &ids,
+            &last_activities,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
         Ok(())
     }
 
@@ -18,6 +18,14 @@ pub enum UserSessions {
     LastActiveIp,
 }
 
+#[derive(sea_query::Iden)]
+pub enum UserSessionAuthentications {
+    Table,
+    UserSessionId,
+    #[iden = "upstream_oauth_authorization_session_id"]
+    UpstreamOAuthAuthorizationSessionId,
+}
+
 #[derive(sea_query::Iden)]
 pub enum Users {
     Table,
@@ -39,6 +47,19 @@ pub enum UserEmails {
     ConfirmedAt,
 }
 
+#[derive(sea_query::Iden)]
+pub enum UserRegistrationTokens {
+    Table,
+    UserRegistrationTokenId,
+    Token,
+    UsageLimit,
+    TimesUsed,
+    CreatedAt,
+    LastUsedAt,
+    ExpiresAt,
+    RevokedAt,
+}
+
 #[derive(sea_query::Iden)]
 pub enum CompatSessions {
     Table,
@@ -66,6 +87,34 @@ pub enum CompatSsoLogins {
     ExchangedAt,
 }
 
+#[derive(sea_query::Iden)]
+#[iden = "oauth2_clients"]
+pub enum OAuth2Clients {
+    Table,
+    #[iden = "oauth2_client_id"]
+    OAuth2ClientId,
+    EncryptedClientSecret,
+    ApplicationType,
+    RedirectUris,
+    GrantTypeAuthorizationCode,
+    GrantTypeRefreshToken,
+    GrantTypeClientCredentials,
+    GrantTypeDeviceCode,
+    ClientName,
+    LogoUri,
+    ClientUri,
+    PolicyUri,
+    TosUri,
+    JwksUri,
+    Jwks,
+    IdTokenSignedResponseAlg,
+    UserinfoSignedResponseAlg,
+    TokenEndpointAuthMethod,
+    TokenEndpointAuthSigningAlg,
+    InitiateLoginUri,
+    IsStatic,
+}
+
 #[derive(sea_query::Iden)]
 #[iden = "oauth2_sessions"]
 pub enum OAuth2Sessions {
@@ -108,6 +157,11 @@ pub enum UpstreamOAuthProviders {
     JwksUriOverride,
     TokenEndpointOverride,
     AuthorizationEndpointOverride,
+    UserinfoEndpointOverride,
+    FetchUserinfo,
+    StoreTokens,
+    TokenExchangeClientIds,
+    Domains,
 }
 
 #[derive(sea_query::Iden)]
@@ -120,5 +174,60 @@ pub enum UpstreamOAuthLinks {
     UpstreamOAuthProviderId,
     UserId,
     Subject,
+    ImportedUserEmailId,
+    Groups,
+    CreatedAt,
+}
+
+#[derive(sea_query::Iden)]
+#[iden = "upstream_oauth_authorization_sessions"]
+pub enum UpstreamOAuthAuthorizationSessions {
+    Table,
+    #[iden = "upstream_oauth_authorization_session_id"]
+    UpstreamOAuthAuthorizationSessionId,
+    #[iden = "upstream_oauth_provider_id"]
+    UpstreamOAuthProviderId,
+    #[iden = "upstream_oauth_link_id"]
+    UpstreamOAuthLinkId,
+    State,
+    CodeChallengeVerifier,
+    Nonce,
+    IdToken,
+    IdTokenClaims,
+    ExtraCallbackParameters,
+    Userinfo,
     CreatedAt,
+    CompletedAt,
+    ConsumedAt,
+}
+
+#[derive(sea_query::Iden)]
+pub enum AuditLogEvents {
+    Table,
+    AuditLogEventId,
+    CreatedAt,
+    Kind,
+    ActorUserId,
+    #[iden = "actor_oauth2_session_id"]
+    ActorOAuth2SessionId,
+    UserId,
+    IpAddress,
+    UserAgent,
+    Data,
+}
+
+#[derive(sea_query::Iden)]
+pub enum UsageStats {
+    Table,
+    #[iden = "usage_stats_id"]
+    Id,
+    Day,
+    DailyActiveUsers,
+    MonthlyActiveUsers,
+    Registrations,
+    PasswordLogins,
+    #[iden = "upstream_oauth2_logins"]
+    UpstreamOAuth2Logins,
+    CompatLogins,
+    ComputedAt,
 }
@@ -49,12 +49,13 @@ impl<'c> JobRepository for PgJobRepository<'c> {
 
         let res = sqlx::query!(
             r#"
-                INSERT INTO apalis.jobs (job, id, job_type)
-                VALUES ($1::json, $2::text, $3::text)
+                INSERT INTO apalis.jobs (job, id, job_type, run_at)
+                VALUES ($1::json, $2::text, $3::text, COALESCE($4, now()))
             "#,
             submission.payload(),
             id.to_string(),
             submission.name(),
+            submission.run_at(),
         )
         .traced()
         .execute(&mut *self.conn)
@@ -163,11 +163,14 @@
 use sqlx::migrate::Migrator;
 
 pub mod app_session;
+pub mod audit_log;
 pub mod compat;
 pub mod job;
 pub mod oauth2;
 pub mod upstream_oauth2;
+pub mod usage_stats;
 pub mod user;
+pub mod webhook;
 
 mod errors;
 pub(crate) mod filter;
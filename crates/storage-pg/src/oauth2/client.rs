@@ -14,7 +14,10 @@ use async_trait::async_trait;
 use mas_data_model::{Client, JwksOrJwksUri, User};
 use mas_iana::{jose::JsonWebSignatureAlg, oauth::OAuthClientAuthenticationMethod};
 use mas_jose::jwk::PublicJsonWebKeySet;
-use mas_storage::{oauth2::OAuth2ClientRepository, Clock};
+use mas_storage::{
+    oauth2::{OAuth2ClientFilter, OAuth2ClientRepository},
+    Clock, Page, Pagination,
+};
 use oauth2_types::{
     oidc::ApplicationType,
     requests::GrantType,
@@ -22,13 +25,21 @@ use oauth2_types::{
 };
 use opentelemetry_semantic_conventions::attribute::DB_QUERY_TEXT;
 use rand::RngCore;
+use sea_query::{enum_def, Expr, PostgresQueryBuilder, Query};
+use sea_query_binder::SqlxBinder;
 use sqlx::PgConnection;
 use tracing::{info_span, Instrument};
 use ulid::Ulid;
 use url::Url;
 use uuid::Uuid;
 
-use crate::{tracing::ExecuteExt, DatabaseError, DatabaseInconsistencyError};
+use crate::{
+    filter::{Filter, StatementExt},
+    iden::OAuth2Clients,
+    pagination::QueryBuilderExt,
+    tracing::ExecuteExt,
+    DatabaseError, DatabaseInconsistencyError,
+};
 
 /// An implementation of [`OAuth2ClientRepository`] for a PostgreSQL connection
 pub struct PgOAuth2ClientRepository<'c> {
@@ -44,7 +55,8 @@ impl<'c> PgOAuth2ClientRepository<'c> {
 }
 
 #[allow(clippy::struct_excessive_bools)]
-#[derive(Debug)]
+#[derive(Debug, sqlx::FromRow)]
+#[enum_def]
 struct OAuth2ClientLookup {
     oauth2_client_id: Uuid,
     encrypted_client_secret: Option<String>,
@@ -66,6 +78,7 @@ struct OAuth2ClientLookup {
     token_endpoint_auth_method: Option<String>,
     token_endpoint_auth_signing_alg: Option<String>,
     initiate_login_uri: Option<String>,
+    is_static: bool,
 }
 
 impl TryInto<Client> for OAuth2ClientLookup {
@@ -246,10 +259,19 @@ impl TryInto<Client> for OAuth2ClientLookup {
             token_endpoint_auth_method,
             token_endpoint_auth_signing_alg,
             initiate_login_uri,
+            is_static: self.is_static,
         })
     }
 }
 
+impl Filter for OAuth2ClientFilter<'_> {
+    fn generate_condition(&self, _has_joins: bool) -> impl sea_query::IntoCondition {
+        sea_query::Condition::all().add_option(self.is_static().map(|is_static| {
+            Expr::col((OAuth2Clients::Table, OAuth2Clients::IsStatic)).eq(is_static)
+        }))
+    }
+}
+
 #[async_trait]
 impl<'c> OAuth2ClientRepository for PgOAuth2ClientRepository<'c> {
     type Error = DatabaseError;
@@ -287,6 +309,7 @@ impl<'c> OAuth2ClientRepository for PgOAuth2ClientRepository<'c> {
                      , token_endpoint_auth_method
                      , token_endpoint_auth_signing_alg
                      , initiate_login_uri
+                     , is_static
                 FROM oauth2_clients c
 
                 WHERE oauth2_client_id = $1
@@ -338,6 +361,7 @@ impl<'c> OAuth2ClientRepository for PgOAuth2ClientRepository<'c> {
                      , token_endpoint_auth_method
                      , token_endpoint_auth_signing_alg
                      , initiate_login_uri
+                     , is_static
                 FROM oauth2_clients c
 
                 WHERE oauth2_client_id = ANY($1::uuid[])
@@ -485,6 +509,7 @@ impl<'c> OAuth2ClientRepository for PgOAuth2ClientRepository<'c> {
             token_endpoint_auth_method,
             token_endpoint_auth_signing_alg,
             initiate_login_uri,
+            is_static: false,
         })
     }
 
@@ -589,6 +614,7 @@ impl<'c> OAuth2ClientRepository for PgOAuth2ClientRepository<'c> {
             token_endpoint_auth_method: None,
             token_endpoint_auth_signing_alg: None,
             initiate_login_uri: None,
+            is_static: true,
         })
     }
 
@@ -624,6 +650,7 @@ impl<'c> OAuth2ClientRepository for PgOAuth2ClientRepository<'c> {
                      , token_endpoint_auth_method
                      , token_endpoint_auth_signing_alg
                      , initiate_login_uri
+                     , is_static
                 FROM oauth2_clients c
                 WHERE is_static = TRUE
             "#,
@@ -637,6 +664,262 @@ impl<'c> OAuth2ClientRepository for PgOAuth2ClientRepository<'c> {
             .collect()
     }
 
+    #[tracing::instrument(
+        name = "db.oauth2_client.list",
+        skip_all,
+        fields(
+            db.query.text,
+        ),
+        err,
+    )]
+    async fn list(
+        &mut self,
+        filter: OAuth2ClientFilter<'_>,
+        pagination: Pagination,
+    ) -> Result<Page<Client>, Self::Error> {
+        let (sql, arguments) = Query::select()
+            .expr_as(
+                Expr::col((OAuth2Clients::Table, OAuth2Clients::OAuth2ClientId)),
+                OAuth2ClientLookupIden::Oauth2ClientId,
+            )
+            .expr_as(
+                Expr::col((OAuth2Clients::Table, OAuth2Clients::EncryptedClientSecret)),
+                OAuth2ClientLookupIden::EncryptedClientSecret,
+            )
+            .expr_as(
+                Expr::col((OAuth2Clients::Table, OAuth2Clients::ApplicationType)),
+                OAuth2ClientLookupIden::ApplicationType,
+            )
+            .expr_as(
+                Expr::col((OAuth2Clients::Table, OAuth2Clients::RedirectUris)),
+                OAuth2ClientLookupIden::RedirectUris,
+            )
+            .expr_as(
+                Expr::col((
+                    OAuth2Clients::Table,
+                    OAuth2Clients::GrantTypeAuthorizationCode,
+                )),
+                OAuth2ClientLookupIden::GrantTypeAuthorizationCode,
+            )
+            .expr_as(
+                Expr::col((OAuth2Clients::Table, OAuth2Clients::GrantTypeRefreshToken)),
+                OAuth2ClientLookupIden::GrantTypeRefreshToken,
+            )
+            .expr_as(
+                Expr::col((
+                    OAuth2Clients::Table,
+                    OAuth2Clients::GrantTypeClientCredentials,
+                )),
+                OAuth2ClientLookupIden::GrantTypeClientCredentials,
+            )
+            .expr_as(
+                Expr::col((OAuth2Clients::Table, OAuth2Clients::GrantTypeDeviceCode)),
+                OAuth2ClientLookupIden::GrantTypeDeviceCode,
+            )
+            .expr_as(
+                Expr::col((OAuth2Clients::Table, OAuth2Clients::ClientName)),
+                OAuth2ClientLookupIden::ClientName,
+            )
+            .expr_as(
+                Expr::col((OAuth2Clients::Table, OAuth2Clients::LogoUri)),
+                OAuth2ClientLookupIden::LogoUri,
+            )
+            .expr_as(
+                Expr::col((OAuth2Clients::Table, OAuth2Clients::ClientUri)),
+                OAuth2ClientLookupIden::ClientUri,
+            )
+            .expr_as(
+                Expr::col((OAuth2Clients::Table, OAuth2Clients::PolicyUri)),
+                OAuth2ClientLookupIden::PolicyUri,
+            )
+            .expr_as(
+                Expr::col((OAuth2Clients::Table, OAuth2Clients::TosUri)),
+                OAuth2ClientLookupIden::TosUri,
+            )
+            .expr_as(
+                Expr::col((OAuth2Clients::Table, OAuth2Clients::JwksUri)),
+                OAuth2ClientLookupIden::JwksUri,
+            )
+            .expr_as(
+                Expr::col((OAuth2Clients::Table, OAuth2Clients::Jwks)),
+                OAuth2ClientLookupIden::Jwks,
+            )
+            .expr_as(
+                Expr::col((
+                    OAuth2Clients::Table,
+                    OAuth2Clients::IdTokenSignedResponseAlg,
+                )),
+                OAuth2ClientLookupIden::IdTokenSignedResponseAlg,
+            )
+            .expr_as(
+                Expr::col((
+                    OAuth2Clients::Table,
+                    OAuth2Clients::UserinfoSignedResponseAlg,
+                )),
+                OAuth2ClientLookupIden::UserinfoSignedResponseAlg,
+            )
+            .expr_as(
+                Expr::col((OAuth2Clients::Table, OAuth2Clients::TokenEndpointAuthMethod)),
+                OAuth2ClientLookupIden::TokenEndpointAuthMethod,
+            )
+            .expr_as(
+                Expr::col((
+                    OAuth2Clients::Table,
+                    OAuth2Clients::TokenEndpointAuthSigningAlg,
+                )),
+                OAuth2ClientLookupIden::TokenEndpointAuthSigningAlg,
+            )
+            .expr_as(
+                Expr::col((OAuth2Clients::Table, OAuth2Clients::InitiateLoginUri)),
+                OAuth2ClientLookupIden::InitiateLoginUri,
+            )
+            .expr_as(
+                Expr::col((OAuth2Clients::Table, OAuth2Clients::IsStatic)),
+                OAuth2ClientLookupIden::IsStatic,
+            )
+            .from(OAuth2Clients::Table)
+            .apply_filter(filter)
+            .generate_pagination(
+                (OAuth2Clients::Table, OAuth2Clients::OAuth2ClientId),
+                pagination,
+            )
+            .build_sqlx(PostgresQueryBuilder);
+
+        let edges: Vec<OAuth2ClientLookup> = sqlx::query_as_with(&sql, arguments)
+            .traced()
+            .fetch_all(&mut *self.conn)
+            .await?;
+
+        let page = pagination.process(edges).try_map(TryInto::try_into)?;
+
+        Ok(page)
+    }
+
+    #[tracing::instrument(
+        name = "db.oauth2_client.count",
+        skip_all,
+        fields(
+            db.query.text,
+        ),
+        err,
+    )]
+    async fn count(&mut self, filter: OAuth2ClientFilter<'_>) -> Result<usize, Self::Error> {
+        let (sql, arguments) = Query::select()
+            .expr(Expr::col((OAuth2Clients::Table, OAuth2Clients::OAuth2ClientId)).count())
+            .from(OAuth2Clients::Table)
+            .apply_filter(filter)
+            .build_sqlx(PostgresQueryBuilder);
+
+        let count: i64 = sqlx::query_scalar_with(&sql, arguments)
+            .traced()
+            .fetch_one(&mut *self.conn)
+            .await?;
+
+        count
+            .try_into()
+            .map_err(DatabaseError::to_invalid_operation)
+    }
+
+    #[tracing::instrument(
+        name = "db.oauth2_client.update_metadata",
+        skip_all,
+        fields(
+            db.query.text,
+            %client.id,
+        ),
+        err,
+    )]
+    async fn update_metadata(
+        &mut self,
+        mut client: Client,
+        redirect_uris: Vec<Url>,
+        grant_types: Vec<GrantType>,
+        client_name: Option<String>,
+        logo_uri: Option<Url>,
+        client_uri: Option<Url>,
+        policy_uri: Option<Url>,
+        tos_uri: Option<Url>,
+    ) -> Result<Client, Self::Error> {
+        let redirect_uris_array = redirect_uris.iter().map(Url::to_string).collect::<Vec<_>>();
+
+        let res = sqlx::query!(
+            r#"
+                UPDATE oauth2_clients
+                SET redirect_uris = $2
+                  , grant_type_authorization_code = $3
+                  , grant_type_refresh_token = $4
+                  , grant_type_client_credentials = $5
+                  , grant_type_device_code = $6
+                  , client_name = $7
+                  , logo_uri = $8
+                  , client_uri = $9
+                  , policy_uri = $10
+                  , tos_uri = $11
+                WHERE oauth2_client_id = $1
+            "#,
+            Uuid::from(client.id),
+            &redirect_uris_array,
+            grant_types.contains(&GrantType::AuthorizationCode),
+            grant_types.contains(&GrantType::RefreshToken),
+            grant_types.contains(&GrantType::ClientCredentials),
+            grant_types.contains(&GrantType::DeviceCode),
+            client_name,
+            logo_uri.as_ref().map(Url::as_str),
+            client_uri.as_ref().map(Url::as_str),
+            policy_uri.as_ref().map(Url::as_str),
+            tos_uri.as_ref().map(Url::as_str),
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        client.redirect_uris = redirect_uris;
+        client.grant_types = grant_types;
+        client.client_name = client_name;
+        client.logo_uri = logo_uri;
+        client.client_uri = client_uri;
+        client.policy_uri = policy_uri;
+        client.tos_uri = tos_uri;
+
+        Ok(client)
+    }
+
+    #[tracing::instrument(
+        name = "db.oauth2_client.set_encrypted_client_secret",
+        skip_all,
+        fields(
+            db.query.text,
+            %client.id,
+        ),
+        err,
+    )]
+    async fn set_encrypted_client_secret(
+        &mut self,
+        mut client: Client,
+        encrypted_client_secret: String,
+    ) -> Result<Client, Self::Error> {
+        let res = sqlx::query!(
+            r#"
+                UPDATE oauth2_clients
+                SET encrypted_client_secret = $2
+                WHERE oauth2_client_id = $1
+            "#,
+            Uuid::from(client.id),
+            encrypted_client_secret,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        client.encrypted_client_secret = Some(encrypted_client_secret);
+
+        Ok(client)
+    }
+
     #[tracing::instrument(
         name = "db.oauth2_client.get_consent_for_user",
         skip_all,
@@ -25,9 +25,13 @@ pub use self::{
 mod tests {
     use chrono::Duration;
     use mas_data_model::{AuthorizationCode, UserAgent};
+    use mas_iana::oauth::OAuthClientAuthenticationMethod;
     use mas_storage::{
         clock::MockClock,
-        oauth2::{OAuth2DeviceCodeGrantParams, OAuth2SessionFilter, OAuth2SessionRepository},
+        oauth2::{
+            OAuth2ClientFilter, OAuth2DeviceCodeGrantParams, OAuth2SessionFilter,
+            OAuth2SessionRepository,
+        },
         Clock, Pagination,
     };
     use oauth2_types::{
@@ -384,6 +388,134 @@ mod tests {
 
     /// Test the [`OAuth2SessionRepository::list`] and
     /// [`OAuth2SessionRepository::count`] methods.
+    /// Test the [`OAuth2ClientRepository`] list, count and update methods
+    #[sqlx::test(migrator = "crate::MIGRATOR")]
+    async fn test_list_clients(pool: PgPool) {
+        let mut rng = ChaChaRng::seed_from_u64(42);
+        let clock = MockClock::default();
+        let mut repo = PgRepository::from_pool(&pool).await.unwrap().boxed();
+
+        let all = OAuth2ClientFilter::new();
+        let static_only = all.static_only();
+        let dynamic_only = all.dynamic_only();
+
+        // There are no clients yet
+        assert_eq!(repo.oauth2_client().count(all).await.unwrap(), 0);
+        let page = repo
+            .oauth2_client()
+            .list(all, Pagination::first(10))
+            .await
+            .unwrap();
+        assert!(page.edges.is_empty());
+
+        let dynamic = repo
+            .oauth2_client()
+            .add(
+                &mut rng,
+                &clock,
+                vec!["https://example.com/redirect".parse().unwrap()],
+                None,
+                None,
+                vec![GrantType::AuthorizationCode],
+                Some("Dynamic client".to_owned()),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                Some(OAuthClientAuthenticationMethod::None),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        assert!(!dynamic.is_static);
+
+        let static_client = repo
+            .oauth2_client()
+            .upsert_static(
+                Ulid::from_datetime_with_source(clock.now().into(), &mut rng),
+                OAuthClientAuthenticationMethod::ClientSecretBasic,
+                Some("encrypted".to_owned()),
+                None,
+                None,
+                Vec::new(),
+            )
+            .await
+            .unwrap();
+        assert!(static_client.is_static);
+
+        assert_eq!(repo.oauth2_client().count(all).await.unwrap(), 2);
+        assert_eq!(repo.oauth2_client().count(static_only).await.unwrap(), 1);
+        assert_eq!(repo.oauth2_client().count(dynamic_only).await.unwrap(), 1);
+
+        let page = repo
+            .oauth2_client()
+            .list(all, Pagination::first(10))
+            .await
+            .unwrap();
+        assert_eq!(page.edges.len(), 2);
+        assert!(!page.has_next_page);
+
+        let page = repo
+            .oauth2_client()
+            .list(static_only, Pagination::first(10))
+            .await
+            .unwrap();
+        assert_eq!(page.edges.len(), 1);
+        assert_eq!(page.edges[0].id, static_client.id);
+        assert!(page.edges[0].is_static);
+
+        // Update the dynamic client metadata
+        let dynamic = repo
+            .oauth2_client()
+            .update_metadata(
+                dynamic,
+                vec!["https://example.com/other-redirect".parse().unwrap()],
+                vec![GrantType::AuthorizationCode, GrantType::RefreshToken],
+                Some("Renamed client".to_owned()),
+                Some("https://example.com/logo.png".parse().unwrap()),
+                None,
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+
+        let lookup = repo
+            .oauth2_client()
+            .lookup(dynamic.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(lookup, dynamic);
+        assert_eq!(lookup.client_name.as_deref(), Some("Renamed client"));
+        assert_eq!(
+            lookup.grant_types,
+            vec![GrantType::AuthorizationCode, GrantType::RefreshToken]
+        );
+
+        // Rotate the client secret
+        let dynamic = repo
+            .oauth2_client()
+            .set_encrypted_client_secret(dynamic, "new-secret".to_owned())
+            .await
+            .unwrap();
+        let lookup = repo
+            .oauth2_client()
+            .lookup(dynamic.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            lookup.encrypted_client_secret.as_deref(),
+            Some("new-secret")
+        );
+    }
+
     #[sqlx::test(migrator = "crate::MIGRATOR")]
     async fn test_list_sessions(pool: PgPool) {
         let mut rng = ChaChaRng::seed_from_u64(42);
@@ -697,6 +829,25 @@ mod tests {
         assert_eq!(list.edges[0], session11);
         assert_eq!(repo.oauth2_session().count(filter).await.unwrap(), 1);
 
+        // Exclude the sessions of some clients
+        let excluded = [client1.id];
+        let filter = OAuth2SessionFilter::new().excluding_clients(&excluded);
+        let list = repo
+            .oauth2_session()
+            .list(filter, pagination)
+            .await
+            .unwrap();
+        assert_eq!(list.edges.len(), 2);
+        assert_eq!(list.edges[0], session21);
+        assert_eq!(list.edges[1], session22);
+        assert_eq!(
+            repo.oauth2_session()
+                .count(OAuth2SessionFilter::new().excluding_clients(&[]))
+                .await
+                .unwrap(),
+            4
+        );
+
         // Finish all sessions of a client in batch
         let affected = repo
             .oauth2_session()
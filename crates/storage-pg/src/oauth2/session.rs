@@ -15,7 +15,9 @@ use mas_storage::{
 };
 use oauth2_types::scope::{Scope, ScopeToken};
 use rand::RngCore;
-use sea_query::{enum_def, extension::postgres::PgExpr, Expr, PgFunc, PostgresQueryBuilder, Query};
+use sea_query::{
+    enum_def, extension::postgres::PgExpr, Alias, Expr, Func, PgFunc, PostgresQueryBuilder, Query,
+};
 use sea_query_binder::SqlxBinder;
 use sqlx::PgConnection;
 use ulid::Ulid;
@@ -133,6 +135,33 @@ impl Filter for OAuth2SessionFilter<'_> {
                 Expr::col((OAuth2Sessions::Table, OAuth2Sessions::LastActiveAt))
                     .lt(last_active_before)
             }))
+            .add_option(self.user_agent().map(|user_agent| {
+                Expr::col((OAuth2Sessions::Table, OAuth2Sessions::UserAgent)).eq(user_agent)
+            }))
+            .add_option(self.last_active_ip().map(|last_active_ip| {
+                // sea-query doesn't know about IP addresses, so we pass it as text
+                Expr::col((OAuth2Sessions::Table, OAuth2Sessions::LastActiveIp))
+                    .eq(Expr::val(last_active_ip.to_string()).cast_as(Alias::new("inet")))
+            }))
+            .add_option(self.created_before().map(|created_before| {
+                Expr::col((OAuth2Sessions::Table, OAuth2Sessions::CreatedAt)).lt(created_before)
+            }))
+            .add_option(self.inactive_since().map(|inactive_since| {
+                // Sessions which were never used are inactive since their creation
+                Expr::expr(Func::coalesce([
+                    Expr::col((OAuth2Sessions::Table, OAuth2Sessions::LastActiveAt)).into(),
+                    Expr::col((OAuth2Sessions::Table, OAuth2Sessions::CreatedAt)).into(),
+                ]))
+                .lt(inactive_since)
+            }))
+            .add_option(
+                self.excluded_clients()
+                    .filter(|client_ids| !client_ids.is_empty())
+                    .map(|client_ids| {
+                        Expr::col((OAuth2Sessions::Table, OAuth2Sessions::OAuth2ClientId))
+                            .is_not_in(client_ids.iter().copied().map(Uuid::from))
+                    }),
+            )
     }
 }
 
@@ -447,6 +476,25 @@ impl<'c> OAuth2SessionRepository for PgOAuth2SessionRepository<'c> {
 
         DatabaseError::ensure_affected_rows(&res, ids.len().try_into().unwrap_or(u64::MAX))?;
 
+        // Also record the days on which the users were active, for the usage
+        // statistics
+        sqlx::query!(
+            r#"
+                INSERT INTO user_activity_days (day, user_id)
+                SELECT DISTINCT (t.last_active_at AT TIME ZONE 'UTC')::date, oauth2_sessions.user_id
+                FROM UNNEST($1::uuid[], $2::timestamptz[])
+                    AS t(oauth2_session_id, last_active_at)
+                INNER JOIN oauth2_sessions USING (oauth2_session_id)
+                WHERE oauth2_sessions.user_id IS NOT NULL
+                ON CONFLICT DO NOTHING
+            "#,
+            &ids,
+            &last_activities,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
         Ok(())
     }
 
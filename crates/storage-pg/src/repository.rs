@@ -9,6 +9,7 @@ use std::ops::{Deref, DerefMut};
 use futures_util::{future::BoxFuture, FutureExt, TryFutureExt};
 use mas_storage::{
     app_session::AppSessionRepository,
+    audit_log::AuditLogRepository,
     compat::{
         CompatAccessTokenRepository, CompatRefreshTokenRepository, CompatSessionRepository,
         CompatSsoLoginRepository,
@@ -22,7 +23,9 @@ use mas_storage::{
         UpstreamOAuthLinkRepository, UpstreamOAuthProviderRepository,
         UpstreamOAuthSessionRepository,
     },
+    usage_stats::UsageStatsRepository,
     user::{BrowserSessionRepository, UserEmailRepository, UserPasswordRepository, UserRepository},
+    webhook::WebhookDeliveryRepository,
     BoxRepository, MapErr, Repository, RepositoryAccess, RepositoryError, RepositoryTransaction,
 };
 use sqlx::{PgConnection, PgPool, Postgres, Transaction};
@@ -30,6 +33,7 @@ use tracing::Instrument;
 
 use crate::{
     app_session::PgAppSessionRepository,
+    audit_log::PgAuditLogRepository,
     compat::{
         PgCompatAccessTokenRepository, PgCompatRefreshTokenRepository, PgCompatSessionRepository,
         PgCompatSsoLoginRepository,
@@ -44,10 +48,13 @@ use crate::{
         PgUpstreamOAuthLinkRepository, PgUpstreamOAuthProviderRepository,
         PgUpstreamOAuthSessionRepository,
     },
+    usage_stats::PgUsageStatsRepository,
     user::{
-        PgBrowserSessionRepository, PgUserEmailRepository, PgUserPasswordRepository,
-        PgUserRecoveryRepository, PgUserRepository, PgUserTermsRepository,
+        PgBrowserSessionRepository, PgUserEmailRepository, PgUserLoginLockoutRepository,
+        PgUserPasswordRepository, PgUserRecoveryRepository, PgUserRegistrationTokenRepository,
+        PgUserRepository, PgUserTermsRepository,
     },
+    webhook::PgWebhookDeliveryRepository,
     DatabaseError,
 };
 
@@ -188,6 +195,18 @@ where
         Box::new(PgUserTermsRepository::new(self.conn.as_mut()))
     }
 
+    fn user_login_lockout<'c>(
+        &'c mut self,
+    ) -> Box<dyn mas_storage::user::UserLoginLockoutRepository<Error = Self::Error> + 'c> {
+        Box::new(PgUserLoginLockoutRepository::new(self.conn.as_mut()))
+    }
+
+    fn user_registration_token<'c>(
+        &'c mut self,
+    ) -> Box<dyn mas_storage::user::UserRegistrationTokenRepository<Error = Self::Error> + 'c> {
+        Box::new(PgUserRegistrationTokenRepository::new(self.conn.as_mut()))
+    }
+
     fn browser_session<'c>(
         &'c mut self,
     ) -> Box<dyn BrowserSessionRepository<Error = Self::Error> + 'c> {
@@ -263,4 +282,18 @@ where
     fn job<'c>(&'c mut self) -> Box<dyn JobRepository<Error = Self::Error> + 'c> {
         Box::new(PgJobRepository::new(self.conn.as_mut()))
     }
+
+    fn audit_log<'c>(&'c mut self) -> Box<dyn AuditLogRepository<Error = Self::Error> + 'c> {
+        Box::new(PgAuditLogRepository::new(self.conn.as_mut()))
+    }
+
+    fn webhook_delivery<'c>(
+        &'c mut self,
+    ) -> Box<dyn WebhookDeliveryRepository<Error = Self::Error> + 'c> {
+        Box::new(PgWebhookDeliveryRepository::new(self.conn.as_mut()))
+    }
+
+    fn usage_stats<'c>(&'c mut self) -> Box<dyn UsageStatsRepository<Error = Self::Error> + 'c> {
+        Box::new(PgUsageStatsRepository::new(self.conn.as_mut()))
+    }
 }
@@ -6,15 +6,19 @@
 
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
-use mas_data_model::{UpstreamOAuthLink, UpstreamOAuthProvider, User};
+use mas_data_model::{
+    UpstreamOAuthLink, UpstreamOAuthLinkTokens, UpstreamOAuthProvider, User, UserEmail,
+};
 use mas_storage::{
     upstream_oauth2::{UpstreamOAuthLinkFilter, UpstreamOAuthLinkRepository},
     Clock, Page, Pagination,
 };
+use opentelemetry_semantic_conventions::attribute::DB_QUERY_TEXT;
 use rand::RngCore;
 use sea_query::{enum_def, Expr, PostgresQueryBuilder, Query};
 use sea_query_binder::SqlxBinder;
 use sqlx::PgConnection;
+use tracing::{info_span, Instrument};
 use ulid::Ulid;
 use uuid::Uuid;
 
@@ -47,6 +51,8 @@ struct LinkLookup {
     upstream_oauth_provider_id: Uuid,
     user_id: Option<Uuid>,
     subject: String,
+    imported_user_email_id: Option<Uuid>,
+    groups: Vec<String>,
     created_at: DateTime<Utc>,
 }
 
@@ -57,11 +63,35 @@ impl From<LinkLookup> for UpstreamOAuthLink {
             provider_id: Ulid::from(value.upstream_oauth_provider_id),
             user_id: value.user_id.map(Ulid::from),
             subject: value.subject,
+            imported_user_email_id: value.imported_user_email_id.map(Ulid::from),
+            groups: value.groups,
             created_at: value.created_at,
         }
     }
 }
 
+struct LinkTokensLookup {
+    upstream_oauth_link_id: Uuid,
+    encrypted_access_token: String,
+    encrypted_refresh_token: Option<String>,
+    access_token_expires_at: Option<DateTime<Utc>>,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+impl From<LinkTokensLookup> for UpstreamOAuthLinkTokens {
+    fn from(value: LinkTokensLookup) -> Self {
+        UpstreamOAuthLinkTokens {
+            link_id: Ulid::from(value.upstream_oauth_link_id),
+            encrypted_access_token: value.encrypted_access_token,
+            encrypted_refresh_token: value.encrypted_refresh_token,
+            access_token_expires_at: value.access_token_expires_at,
+            created_at: value.created_at,
+            updated_at: value.updated_at,
+        }
+    }
+}
+
 impl Filter for UpstreamOAuthLinkFilter<'_> {
     fn generate_condition(&self, _has_joins: bool) -> impl sea_query::IntoCondition {
         sea_query::Condition::all()
@@ -124,6 +154,8 @@ impl<'c> UpstreamOAuthLinkRepository for PgUpstreamOAuthLinkRepository<'c> {
                     upstream_oauth_provider_id,
                     user_id,
                     subject,
+                    imported_user_email_id,
+                    groups,
                     created_at
                 FROM upstream_oauth_links
                 WHERE upstream_oauth_link_id = $1
@@ -163,6 +195,8 @@ impl<'c> UpstreamOAuthLinkRepository for PgUpstreamOAuthLinkRepository<'c> {
                     upstream_oauth_provider_id,
                     user_id,
                     subject,
+                    imported_user_email_id,
+                    groups,
                     created_at
                 FROM upstream_oauth_links
                 WHERE upstream_oauth_provider_id = $1
@@ -227,6 +261,8 @@ impl<'c> UpstreamOAuthLinkRepository for PgUpstreamOAuthLinkRepository<'c> {
             provider_id: upstream_oauth_provider.id,
             user_id: None,
             subject,
+            imported_user_email_id: None,
+            groups: Vec::new(),
             created_at,
         })
     }
@@ -264,6 +300,299 @@ impl<'c> UpstreamOAuthLinkRepository for PgUpstreamOAuthLinkRepository<'c> {
         Ok(())
     }
 
+    #[tracing::instrument(
+        name = "db.upstream_oauth_link.set_imported_email",
+        skip_all,
+        fields(
+            db.query.text,
+            %upstream_oauth_link.id,
+            user_email.id = user_email.map(|e| tracing::field::display(e.id)),
+        ),
+        err,
+    )]
+    async fn set_imported_email(
+        &mut self,
+        mut upstream_oauth_link: UpstreamOAuthLink,
+        user_email: Option<&UserEmail>,
+    ) -> Result<UpstreamOAuthLink, Self::Error> {
+        let imported_user_email_id = user_email.map(|user_email| user_email.id);
+        let res = sqlx::query!(
+            r#"
+                UPDATE upstream_oauth_links
+                SET imported_user_email_id = $1
+                WHERE upstream_oauth_link_id = $2
+            "#,
+            imported_user_email_id.map(Uuid::from),
+            Uuid::from(upstream_oauth_link.id),
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        upstream_oauth_link.imported_user_email_id = imported_user_email_id;
+        Ok(upstream_oauth_link)
+    }
+
+    #[tracing::instrument(
+        name = "db.upstream_oauth_link.set_groups",
+        skip_all,
+        fields(
+            db.query.text,
+            %upstream_oauth_link.id,
+        ),
+        err,
+    )]
+    async fn set_groups(
+        &mut self,
+        mut upstream_oauth_link: UpstreamOAuthLink,
+        groups: Vec<String>,
+    ) -> Result<UpstreamOAuthLink, Self::Error> {
+        let res = sqlx::query!(
+            r#"
+                UPDATE upstream_oauth_links
+                SET groups = $1
+                WHERE upstream_oauth_link_id = $2
+            "#,
+            &groups,
+            Uuid::from(upstream_oauth_link.id),
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        upstream_oauth_link.groups = groups;
+        Ok(upstream_oauth_link)
+    }
+
+    #[tracing::instrument(
+        name = "db.upstream_oauth_link.lookup_tokens",
+        skip_all,
+        fields(
+            db.query.text,
+            %upstream_oauth_link.id,
+        ),
+        err,
+    )]
+    async fn lookup_tokens(
+        &mut self,
+        upstream_oauth_link: &UpstreamOAuthLink,
+    ) -> Result<Option<UpstreamOAuthLinkTokens>, Self::Error> {
+        let res = sqlx::query_as!(
+            LinkTokensLookup,
+            r#"
+                SELECT
+                    upstream_oauth_link_id,
+                    encrypted_access_token,
+                    encrypted_refresh_token,
+                    access_token_expires_at,
+                    created_at,
+                    updated_at
+                FROM upstream_oauth_link_tokens
+                WHERE upstream_oauth_link_id = $1
+            "#,
+            Uuid::from(upstream_oauth_link.id),
+        )
+        .traced()
+        .fetch_optional(&mut *self.conn)
+        .await?
+        .map(Into::into);
+
+        Ok(res)
+    }
+
+    #[tracing::instrument(
+        name = "db.upstream_oauth_link.save_tokens",
+        skip_all,
+        fields(
+            db.query.text,
+            %upstream_oauth_link.id,
+        ),
+        err,
+    )]
+    async fn save_tokens(
+        &mut self,
+        clock: &dyn Clock,
+        upstream_oauth_link: &UpstreamOAuthLink,
+        encrypted_access_token: String,
+        encrypted_refresh_token: Option<String>,
+        access_token_expires_at: Option<DateTime<Utc>>,
+    ) -> Result<UpstreamOAuthLinkTokens, Self::Error> {
+        let now = clock.now();
+        let res = sqlx::query_as!(
+            LinkTokensLookup,
+            r#"
+                INSERT INTO upstream_oauth_link_tokens (
+                    upstream_oauth_link_id,
+                    encrypted_access_token,
+                    encrypted_refresh_token,
+                    access_token_expires_at,
+                    created_at,
+                    updated_at
+                ) VALUES ($1, $2, $3, $4, $5, $5)
+                ON CONFLICT (upstream_oauth_link_id)
+                    DO UPDATE
+                    SET
+                        encrypted_access_token = EXCLUDED.encrypted_access_token,
+                        encrypted_refresh_token = COALESCE(
+                            EXCLUDED.encrypted_refresh_token,
+                            upstream_oauth_link_tokens.encrypted_refresh_token
+                        ),
+                        access_token_expires_at = EXCLUDED.access_token_expires_at,
+                        updated_at = EXCLUDED.updated_at,
+                        refresh_leased_until = NULL
+                RETURNING
+                    upstream_oauth_link_id,
+                    encrypted_access_token,
+                    encrypted_refresh_token,
+                    access_token_expires_at,
+                    created_at,
+                    updated_at
+            "#,
+            Uuid::from(upstream_oauth_link.id),
+            encrypted_access_token,
+            encrypted_refresh_token,
+            access_token_expires_at,
+            now,
+        )
+        .traced()
+        .fetch_one(&mut *self.conn)
+        .await?;
+
+        Ok(res.into())
+    }
+
+    #[tracing::instrument(
+        name = "db.upstream_oauth_link.remove_tokens",
+        skip_all,
+        fields(
+            db.query.text,
+            upstream_oauth_link.id = %tokens.link_id,
+        ),
+        err,
+    )]
+    async fn remove_tokens(&mut self, tokens: UpstreamOAuthLinkTokens) -> Result<(), Self::Error> {
+        let res = sqlx::query!(
+            r#"
+                DELETE FROM upstream_oauth_link_tokens
+                WHERE upstream_oauth_link_id = $1
+            "#,
+            Uuid::from(tokens.link_id),
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(
+        name = "db.upstream_oauth_link.lease_tokens",
+        skip_all,
+        fields(
+            db.query.text,
+            upstream_oauth_link.id = %tokens.link_id,
+        ),
+        err,
+    )]
+    async fn lease_tokens(
+        &mut self,
+        clock: &dyn Clock,
+        tokens: UpstreamOAuthLinkTokens,
+        leased_until: DateTime<Utc>,
+    ) -> Result<Option<UpstreamOAuthLinkTokens>, Self::Error> {
+        let res = sqlx::query_as!(
+            LinkTokensLookup,
+            r#"
+                UPDATE upstream_oauth_link_tokens
+                SET refresh_leased_until = $2
+                WHERE upstream_oauth_link_id = $1
+                  AND (refresh_leased_until IS NULL OR refresh_leased_until <= $3)
+                RETURNING
+                    upstream_oauth_link_id,
+                    encrypted_access_token,
+                    encrypted_refresh_token,
+                    access_token_expires_at,
+                    created_at,
+                    updated_at
+            "#,
+            Uuid::from(tokens.link_id),
+            leased_until,
+            clock.now(),
+        )
+        .traced()
+        .fetch_optional(&mut *self.conn)
+        .await?
+        .map(Into::into);
+
+        Ok(res)
+    }
+
+    #[tracing::instrument(
+        name = "db.upstream_oauth_link.lease_tokens_to_refresh",
+        skip_all,
+        fields(
+            db.query.text,
+        ),
+        err,
+    )]
+    async fn lease_tokens_to_refresh(
+        &mut self,
+        clock: &dyn Clock,
+        expires_before: DateTime<Utc>,
+        leased_until: DateTime<Utc>,
+        after: Option<Ulid>,
+        limit: usize,
+    ) -> Result<Vec<UpstreamOAuthLinkTokens>, Self::Error> {
+        let limit: i64 = limit
+            .try_into()
+            .map_err(DatabaseError::to_invalid_operation)?;
+
+        let res = sqlx::query_as!(
+            LinkTokensLookup,
+            r#"
+                UPDATE upstream_oauth_link_tokens
+                SET refresh_leased_until = $2
+                WHERE upstream_oauth_link_id IN (
+                    SELECT upstream_oauth_link_id
+                    FROM upstream_oauth_link_tokens
+                    WHERE encrypted_refresh_token IS NOT NULL
+                      AND access_token_expires_at < $1
+                      AND (refresh_leased_until IS NULL OR refresh_leased_until <= $3)
+                      AND ($4::uuid IS NULL OR upstream_oauth_link_id > $4)
+                    ORDER BY upstream_oauth_link_id
+                    LIMIT $5
+                    FOR UPDATE SKIP LOCKED
+                )
+                RETURNING
+                    upstream_oauth_link_id,
+                    encrypted_access_token,
+                    encrypted_refresh_token,
+                    access_token_expires_at,
+                    created_at,
+                    updated_at
+            "#,
+            expires_before,
+            leased_until,
+            clock.now(),
+            after.map(Uuid::from),
+            limit,
+        )
+        .traced()
+        .fetch_all(&mut *self.conn)
+        .await?;
+
+        let mut tokens: Vec<UpstreamOAuthLinkTokens> = res.into_iter().map(Into::into).collect();
+        // UPDATE ... RETURNING doesn't keep the order of the subquery
+        tokens.sort_by_key(|tokens| tokens.link_id);
+        Ok(tokens)
+    }
+
     #[tracing::instrument(
         name = "db.upstream_oauth_link.list",
         skip_all,
@@ -300,6 +629,17 @@ impl<'c> UpstreamOAuthLinkRepository for PgUpstreamOAuthLinkRepository<'c> {
                 Expr::col((UpstreamOAuthLinks::Table, UpstreamOAuthLinks::Subject)),
                 LinkLookupIden::Subject,
             )
+            .expr_as(
+                Expr::col((
+                    UpstreamOAuthLinks::Table,
+                    UpstreamOAuthLinks::ImportedUserEmailId,
+                )),
+                LinkLookupIden::ImportedUserEmailId,
+            )
+            .expr_as(
+                Expr::col((UpstreamOAuthLinks::Table, UpstreamOAuthLinks::Groups)),
+                LinkLookupIden::Groups,
+            )
             .expr_as(
                 Expr::col((UpstreamOAuthLinks::Table, UpstreamOAuthLinks::CreatedAt)),
                 LinkLookupIden::CreatedAt,
@@ -355,4 +695,50 @@ impl<'c> UpstreamOAuthLinkRepository for PgUpstreamOAuthLinkRepository<'c> {
             .try_into()
             .map_err(DatabaseError::to_invalid_operation)
     }
+
+    #[tracing::instrument(
+        name = "db.upstream_oauth_link.remove",
+        skip_all,
+        fields(
+            db.query.text,
+            %upstream_oauth_link.id,
+            %upstream_oauth_link.provider_id,
+        ),
+        err,
+    )]
+    async fn remove(&mut self, upstream_oauth_link: UpstreamOAuthLink) -> Result<(), Self::Error> {
+        // Authentications which used those sessions are kept, but lose their reference
+        // to the session
+        let span = info_span!(
+            "db.upstream_oauth_link.remove.sessions",
+            { DB_QUERY_TEXT } = tracing::field::Empty
+        );
+        sqlx::query!(
+            r#"
+                DELETE FROM upstream_oauth_authorization_sessions
+                WHERE upstream_oauth_link_id = $1
+            "#,
+            Uuid::from(upstream_oauth_link.id),
+        )
+        .record(&span)
+        .execute(&mut *self.conn)
+        .instrument(span)
+        .await?;
+
+        // The tokens are deleted in cascade
+        let res = sqlx::query!(
+            r#"
+                DELETE FROM upstream_oauth_links
+                WHERE upstream_oauth_link_id = $1
+            "#,
+            Uuid::from(upstream_oauth_link.id),
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        Ok(())
+    }
 }
@@ -27,10 +27,10 @@ mod tests {
         upstream_oauth2::{
             UpstreamOAuthLinkFilter, UpstreamOAuthLinkRepository, UpstreamOAuthProviderFilter,
             UpstreamOAuthProviderParams, UpstreamOAuthProviderRepository,
-            UpstreamOAuthSessionRepository,
+            UpstreamOAuthSessionFilter, UpstreamOAuthSessionRepository,
         },
-        user::UserRepository,
-        Pagination, RepositoryAccess,
+        user::{BrowserSessionFilter, BrowserSessionRepository, UserRepository},
+        Clock, Pagination, RepositoryAccess,
     };
     use oauth2_types::scope::{Scope, OPENID};
     use rand::SeedableRng;
@@ -65,6 +65,11 @@ mod tests {
                     encrypted_client_secret: None,
                     claims_imports: UpstreamOAuthProviderClaimsImports::default(),
                     token_endpoint_override: None,
+                    userinfo_endpoint_override: None,
+                    fetch_userinfo: false,
+                    store_tokens: false,
+                    token_exchange_clients: Vec::new(),
+                    domains: Vec::new(),
                     authorization_endpoint_override: None,
                     jwks_uri_override: None,
                     discovery_mode: mas_data_model::UpstreamOAuthProviderDiscoveryMode::Oidc,
@@ -145,7 +150,15 @@ mod tests {
 
         let session = repo
             .upstream_oauth_session()
-            .complete_with_link(&clock, session, &link, None, None)
+            .complete_with_link(
+                &clock,
+                session,
+                &link,
+                None,
+                Some(serde_json::json!({ "sub": "a-subject", "sid": "a-session" })),
+                None,
+                None,
+            )
             .await
             .unwrap();
         // Reload the session
@@ -202,6 +215,235 @@ mod tests {
 
         assert_eq!(repo.upstream_oauth_link().count(filter).await.unwrap(), 1);
 
+        // Find the session by the claims of its ID token
+        let filter = UpstreamOAuthSessionFilter::new()
+            .for_provider(&provider)
+            .with_sid_claim("a-session");
+        let sessions = repo
+            .upstream_oauth_session()
+            .list(filter, Pagination::first(10))
+            .await
+            .unwrap();
+        assert_eq!(sessions.edges.len(), 1);
+        assert_eq!(sessions.edges[0].id, session.id);
+
+        let filter = UpstreamOAuthSessionFilter::new()
+            .for_provider(&provider)
+            .with_sub_claim("another-subject");
+        let sessions_for_other_subject = repo
+            .upstream_oauth_session()
+            .list(filter, Pagination::first(10))
+            .await
+            .unwrap();
+        assert!(sessions_for_other_subject.edges.is_empty());
+
+        // Find the browser sessions authenticated by this upstream session
+        let browser_session = repo
+            .browser_session()
+            .add(&mut rng, &clock, &user, None)
+            .await
+            .unwrap();
+        let filter =
+            BrowserSessionFilter::new().authenticated_by_upstream_sessions_only(&sessions.edges);
+        assert_eq!(repo.browser_session().count(filter).await.unwrap(), 0);
+
+        repo.browser_session()
+            .authenticate_with_upstream(&mut rng, &clock, &browser_session, &session)
+            .await
+            .unwrap();
+        assert_eq!(repo.browser_session().count(filter).await.unwrap(), 1);
+
+        // Remember an email address imported through the link
+        assert_eq!(link.imported_user_email_id, None);
+        let user_email = repo
+            .user_email()
+            .add(&mut rng, &clock, &user, "john@example.com".to_owned())
+            .await
+            .unwrap();
+        let link = repo
+            .upstream_oauth_link()
+            .set_imported_email(link, Some(&user_email))
+            .await
+            .unwrap();
+        assert_eq!(link.imported_user_email_id, Some(user_email.id));
+
+        let link = repo
+            .upstream_oauth_link()
+            .lookup(link.id)
+            .await
+            .unwrap()
+            .expect("link to be found in the database");
+        assert_eq!(link.imported_user_email_id, Some(user_email.id));
+
+        // Removing the email address clears it on the link
+        repo.user_email().remove(user_email).await.unwrap();
+        let link = repo
+            .upstream_oauth_link()
+            .lookup(link.id)
+            .await
+            .unwrap()
+            .expect("link to be found in the database");
+        assert_eq!(link.imported_user_email_id, None);
+
+        // Set the groups claimed by the provider
+        assert!(link.groups.is_empty());
+        let link = repo
+            .upstream_oauth_link()
+            .set_groups(link, vec!["admins".to_owned(), "users".to_owned()])
+            .await
+            .unwrap();
+        let link = repo
+            .upstream_oauth_link()
+            .lookup(link.id)
+            .await
+            .unwrap()
+            .expect("link to be found in the database");
+        assert_eq!(link.groups, vec!["admins".to_owned(), "users".to_owned()]);
+
+        // Store tokens for the link
+        assert!(repo
+            .upstream_oauth_link()
+            .lookup_tokens(&link)
+            .await
+            .unwrap()
+            .is_none());
+        let tokens = repo
+            .upstream_oauth_link()
+            .save_tokens(
+                &clock,
+                &link,
+                "access".to_owned(),
+                Some("refresh".to_owned()),
+                Some(clock.now() + Duration::minutes(10)),
+            )
+            .await
+            .unwrap();
+        assert_eq!(tokens.link_id, link.id);
+        assert!(!tokens.is_access_token_expired(clock.now()));
+
+        // Nothing needs to be refreshed yet
+        let leased_until = clock.now() + Duration::minutes(2);
+        let to_refresh = repo
+            .upstream_oauth_link()
+            .lease_tokens_to_refresh(
+                &clock,
+                clock.now() + Duration::minutes(5),
+                leased_until,
+                None,
+                10,
+            )
+            .await
+            .unwrap();
+        assert!(to_refresh.is_empty());
+
+        // Until the access token is about to expire
+        let to_refresh = repo
+            .upstream_oauth_link()
+            .lease_tokens_to_refresh(
+                &clock,
+                clock.now() + Duration::minutes(15),
+                leased_until,
+                None,
+                10,
+            )
+            .await
+            .unwrap();
+        assert_eq!(to_refresh, vec![tokens.clone()]);
+
+        // Leased tokens can't be leased again until the lease expires
+        let to_refresh = repo
+            .upstream_oauth_link()
+            .lease_tokens_to_refresh(
+                &clock,
+                clock.now() + Duration::minutes(15),
+                leased_until,
+                None,
+                10,
+            )
+            .await
+            .unwrap();
+        assert!(to_refresh.is_empty());
+        assert!(repo
+            .upstream_oauth_link()
+            .lease_tokens(&clock, tokens.clone(), leased_until)
+            .await
+            .unwrap()
+            .is_none());
+
+        clock.advance(Duration::minutes(3));
+        assert!(repo
+            .upstream_oauth_link()
+            .lease_tokens(&clock, tokens, clock.now() + Duration::minutes(2))
+            .await
+            .unwrap()
+            .is_some());
+
+        // Saving a new access token without a refresh token keeps the old one
+        let tokens = repo
+            .upstream_oauth_link()
+            .save_tokens(&clock, &link, "new access".to_owned(), None, None)
+            .await
+            .unwrap();
+        assert_eq!(tokens.encrypted_access_token, "new access");
+        assert_eq!(tokens.encrypted_refresh_token.as_deref(), Some("refresh"));
+        assert_eq!(tokens.access_token_expires_at, None);
+
+        // Saving new tokens releases the lease
+        assert!(repo
+            .upstream_oauth_link()
+            .lease_tokens(&clock, tokens, clock.now() + Duration::minutes(2))
+            .await
+            .unwrap()
+            .is_some());
+
+        let tokens = repo
+            .upstream_oauth_link()
+            .lookup_tokens(&link)
+            .await
+            .unwrap()
+            .expect("tokens to be found in the database");
+        assert_eq!(tokens.encrypted_access_token, "new access");
+
+        repo.upstream_oauth_link()
+            .remove_tokens(tokens)
+            .await
+            .unwrap();
+        assert!(repo
+            .upstream_oauth_link()
+            .lookup_tokens(&link)
+            .await
+            .unwrap()
+            .is_none());
+
+        // Remove the link, along with the sessions which resolved to it and its tokens
+        repo.upstream_oauth_link()
+            .save_tokens(&clock, &link, "access".to_owned(), None, None)
+            .await
+            .unwrap();
+        repo.upstream_oauth_link()
+            .remove(link.clone())
+            .await
+            .unwrap();
+        assert!(repo
+            .upstream_oauth_link()
+            .lookup(link.id)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(repo
+            .upstream_oauth_session()
+            .lookup(session.id)
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(
+            repo.upstream_oauth_link()
+                .count(UpstreamOAuthLinkFilter::new().for_user(&user))
+                .await
+                .unwrap(),
+            0
+        );
+
         // There should be exactly one enabled provider
         assert_eq!(
             repo.upstream_oauth_provider()
@@ -307,6 +549,11 @@ mod tests {
                         encrypted_client_secret: None,
                         claims_imports: UpstreamOAuthProviderClaimsImports::default(),
                         token_endpoint_override: None,
+                        userinfo_endpoint_override: None,
+                        fetch_userinfo: false,
+                        store_tokens: false,
+                        token_exchange_clients: Vec::new(),
+                        domains: Vec::new(),
                         authorization_endpoint_override: None,
                         jwks_uri_override: None,
                         discovery_mode: mas_data_model::UpstreamOAuthProviderDiscoveryMode::Oidc,
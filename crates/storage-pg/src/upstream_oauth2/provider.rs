@@ -62,6 +62,11 @@ struct ProviderLookup {
     jwks_uri_override: Option<String>,
     authorization_endpoint_override: Option<String>,
     token_endpoint_override: Option<String>,
+    userinfo_endpoint_override: Option<String>,
+    fetch_userinfo: bool,
+    store_tokens: bool,
+    token_exchange_client_ids: Vec<Uuid>,
+    domains: Vec<String>,
     discovery_mode: String,
     pkce_mode: String,
     response_mode: String,
@@ -70,6 +75,7 @@ struct ProviderLookup {
 
 impl TryFrom<ProviderLookup> for UpstreamOAuthProvider {
     type Error = DatabaseInconsistencyError;
+    #[allow(clippy::too_many_lines)]
     fn try_from(value: ProviderLookup) -> Result<Self, Self::Error> {
         let id = value.upstream_oauth_provider_id.into();
         let scope = value.scope.parse().map_err(|e| {
@@ -117,6 +123,17 @@ impl TryFrom<ProviderLookup> for UpstreamOAuthProvider {
                     .source(e)
             })?;
 
+        let userinfo_endpoint_override = value
+            .userinfo_endpoint_override
+            .map(|x| x.parse())
+            .transpose()
+            .map_err(|e| {
+                DatabaseInconsistencyError::on("upstream_oauth_providers")
+                    .column("userinfo_endpoint_override")
+                    .row(id)
+                    .source(e)
+            })?;
+
         let jwks_uri_override = value
             .jwks_uri_override
             .map(|x| x.parse())
@@ -169,6 +186,15 @@ impl TryFrom<ProviderLookup> for UpstreamOAuthProvider {
             claims_imports: value.claims_imports.0,
             authorization_endpoint_override,
             token_endpoint_override,
+            userinfo_endpoint_override,
+            fetch_userinfo: value.fetch_userinfo,
+            store_tokens: value.store_tokens,
+            token_exchange_clients: value
+                .token_exchange_client_ids
+                .into_iter()
+                .map(Ulid::from)
+                .collect(),
+            domains: value.domains,
             jwks_uri_override,
             discovery_mode,
             pkce_mode,
@@ -224,6 +250,11 @@ impl<'c> UpstreamOAuthProviderRepository for PgUpstreamOAuthProviderRepository<'
                     jwks_uri_override,
                     authorization_endpoint_override,
                     token_endpoint_override,
+                    userinfo_endpoint_override,
+                    fetch_userinfo,
+                    store_tokens,
+                    token_exchange_client_ids,
+                    domains,
                     discovery_mode,
                     pkce_mode,
                     response_mode,
@@ -266,6 +297,13 @@ impl<'c> UpstreamOAuthProviderRepository for PgUpstreamOAuthProviderRepository<'
         let id = Ulid::from_datetime_with_source(created_at.into(), rng);
         tracing::Span::current().record("upstream_oauth_provider.id", tracing::field::display(id));
 
+        let token_exchange_client_ids: Vec<Uuid> = params
+            .token_exchange_clients
+            .iter()
+            .copied()
+            .map(Uuid::from)
+            .collect();
+
         sqlx::query!(
             r#"
             INSERT INTO upstream_oauth_providers (
@@ -281,13 +319,18 @@ impl<'c> UpstreamOAuthProviderRepository for PgUpstreamOAuthProviderRepository<'
                 claims_imports,
                 authorization_endpoint_override,
                 token_endpoint_override,
+                userinfo_endpoint_override,
+                fetch_userinfo,
+                store_tokens,
+                token_exchange_client_ids,
+                domains,
                 jwks_uri_override,
                 discovery_mode,
                 pkce_mode,
                 response_mode,
                 created_at
-            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9,
-                      $10, $11, $12, $13, $14, $15, $16, $17)
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10,
+                      $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21, $22)
         "#,
             Uuid::from(id),
             &params.issuer,
@@ -310,6 +353,14 @@ impl<'c> UpstreamOAuthProviderRepository for PgUpstreamOAuthProviderRepository<'
                 .token_endpoint_override
                 .as_ref()
                 .map(ToString::to_string),
+            params
+                .userinfo_endpoint_override
+                .as_ref()
+                .map(ToString::to_string),
+            params.fetch_userinfo,
+            params.store_tokens,
+            &token_exchange_client_ids,
+            &params.domains,
             params.jwks_uri_override.as_ref().map(ToString::to_string),
             params.discovery_mode.as_str(),
             params.pkce_mode.as_str(),
@@ -335,6 +386,11 @@ impl<'c> UpstreamOAuthProviderRepository for PgUpstreamOAuthProviderRepository<'
             claims_imports: params.claims_imports,
             authorization_endpoint_override: params.authorization_endpoint_override,
             token_endpoint_override: params.token_endpoint_override,
+            userinfo_endpoint_override: params.userinfo_endpoint_override,
+            fetch_userinfo: params.fetch_userinfo,
+            store_tokens: params.store_tokens,
+            token_exchange_clients: params.token_exchange_clients,
+            domains: params.domains,
             jwks_uri_override: params.jwks_uri_override,
             discovery_mode: params.discovery_mode,
             pkce_mode: params.pkce_mode,
@@ -428,6 +484,13 @@ impl<'c> UpstreamOAuthProviderRepository for PgUpstreamOAuthProviderRepository<'
     ) -> Result<UpstreamOAuthProvider, Self::Error> {
         let created_at = clock.now();
 
+        let token_exchange_client_ids: Vec<Uuid> = params
+            .token_exchange_clients
+            .iter()
+            .copied()
+            .map(Uuid::from)
+            .collect();
+
         let created_at = sqlx::query_scalar!(
             r#"
                 INSERT INTO upstream_oauth_providers (
@@ -443,14 +506,19 @@ impl<'c> UpstreamOAuthProviderRepository for PgUpstreamOAuthProviderRepository<'
                     claims_imports,
                     authorization_endpoint_override,
                     token_endpoint_override,
+                    userinfo_endpoint_override,
+                    fetch_userinfo,
+                    store_tokens,
+                    token_exchange_client_ids,
+                    domains,
                     jwks_uri_override,
                     discovery_mode,
                     pkce_mode,
                     response_mode,
                     additional_parameters,
                     created_at
-                ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9,
-                          $10, $11, $12, $13, $14, $15, $16, $17, $18)
+                ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11,
+                          $12, $13, $14, $15, $16, $17, $18, $19, $20, $21, $22, $23)
                 ON CONFLICT (upstream_oauth_provider_id)
                     DO UPDATE
                     SET
@@ -466,6 +534,11 @@ impl<'c> UpstreamOAuthProviderRepository for PgUpstreamOAuthProviderRepository<'
                         claims_imports = EXCLUDED.claims_imports,
                         authorization_endpoint_override = EXCLUDED.authorization_endpoint_override,
                         token_endpoint_override = EXCLUDED.token_endpoint_override,
+                        userinfo_endpoint_override = EXCLUDED.userinfo_endpoint_override,
+                        fetch_userinfo = EXCLUDED.fetch_userinfo,
+                        store_tokens = EXCLUDED.store_tokens,
+                        token_exchange_client_ids = EXCLUDED.token_exchange_client_ids,
+                        domains = EXCLUDED.domains,
                         jwks_uri_override = EXCLUDED.jwks_uri_override,
                         discovery_mode = EXCLUDED.discovery_mode,
                         pkce_mode = EXCLUDED.pkce_mode,
@@ -494,6 +567,14 @@ impl<'c> UpstreamOAuthProviderRepository for PgUpstreamOAuthProviderRepository<'
                 .token_endpoint_override
                 .as_ref()
                 .map(ToString::to_string),
+            params
+                .userinfo_endpoint_override
+                .as_ref()
+                .map(ToString::to_string),
+            params.fetch_userinfo,
+            params.store_tokens,
+            &token_exchange_client_ids,
+            &params.domains,
             params.jwks_uri_override.as_ref().map(ToString::to_string),
             params.discovery_mode.as_str(),
             params.pkce_mode.as_str(),
@@ -520,6 +601,11 @@ impl<'c> UpstreamOAuthProviderRepository for PgUpstreamOAuthProviderRepository<'
             claims_imports: params.claims_imports,
             authorization_endpoint_override: params.authorization_endpoint_override,
             token_endpoint_override: params.token_endpoint_override,
+            userinfo_endpoint_override: params.userinfo_endpoint_override,
+            fetch_userinfo: params.fetch_userinfo,
+            store_tokens: params.store_tokens,
+            token_exchange_clients: params.token_exchange_clients,
+            domains: params.domains,
             jwks_uri_override: params.jwks_uri_override,
             discovery_mode: params.discovery_mode,
             pkce_mode: params.pkce_mode,
@@ -672,6 +758,41 @@ impl<'c> UpstreamOAuthProviderRepository for PgUpstreamOAuthProviderRepository<'
                 )),
                 ProviderLookupIden::TokenEndpointOverride,
             )
+            .expr_as(
+                Expr::col((
+                    UpstreamOAuthProviders::Table,
+                    UpstreamOAuthProviders::UserinfoEndpointOverride,
+                )),
+                ProviderLookupIden::UserinfoEndpointOverride,
+            )
+            .expr_as(
+                Expr::col((
+                    UpstreamOAuthProviders::Table,
+                    UpstreamOAuthProviders::FetchUserinfo,
+                )),
+                ProviderLookupIden::FetchUserinfo,
+            )
+            .expr_as(
+                Expr::col((
+                    UpstreamOAuthProviders::Table,
+                    UpstreamOAuthProviders::StoreTokens,
+                )),
+                ProviderLookupIden::StoreTokens,
+            )
+            .expr_as(
+                Expr::col((
+                    UpstreamOAuthProviders::Table,
+                    UpstreamOAuthProviders::TokenExchangeClientIds,
+                )),
+                ProviderLookupIden::TokenExchangeClientIds,
+            )
+            .expr_as(
+                Expr::col((
+                    UpstreamOAuthProviders::Table,
+                    UpstreamOAuthProviders::Domains,
+                )),
+                ProviderLookupIden::Domains,
+            )
             .expr_as(
                 Expr::col((
                     UpstreamOAuthProviders::Table,
@@ -792,6 +913,11 @@ impl<'c> UpstreamOAuthProviderRepository for PgUpstreamOAuthProviderRepository<'
                     jwks_uri_override,
                     authorization_endpoint_override,
                     token_endpoint_override,
+                    userinfo_endpoint_override,
+                    fetch_userinfo,
+                    store_tokens,
+                    token_exchange_client_ids,
+                    domains,
                     discovery_mode,
                     pkce_mode,
                     response_mode,
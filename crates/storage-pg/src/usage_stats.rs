@@ -0,0 +1,625 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+//! A module containing the PostgreSQL implementation of the usage statistics
+//! repository
+
+use async_trait::async_trait;
+use chrono::{Duration, NaiveDate};
+use mas_data_model::UsageStats;
+use mas_storage::{
+    usage_stats::{UsageStatsFilter, UsageStatsRepository},
+    Clock, Page, Pagination,
+};
+use rand::RngCore;
+use sea_query::{Expr, PostgresQueryBuilder, Query};
+use sea_query_binder::SqlxBinder;
+use sqlx::PgConnection;
+use ulid::Ulid;
+use uuid::Uuid;
+
+use crate::{
+    filter::{Filter, StatementExt},
+    iden,
+    pagination::QueryBuilderExt,
+    tracing::ExecuteExt,
+    DatabaseError, DatabaseInconsistencyError,
+};
+
+/// How many days are considered when counting the monthly active users
+const MONTHLY_ACTIVE_DAYS: i64 = 30;
+
+/// An implementation of [`UsageStatsRepository`] for a PostgreSQL connection
+pub struct PgUsageStatsRepository<'c> {
+    conn: &'c mut PgConnection,
+}
+
+impl<'c> PgUsageStatsRepository<'c> {
+    /// Create a new [`PgUsageStatsRepository`] from an active PostgreSQL
+    /// connection
+    pub fn new(conn: &'c mut PgConnection) -> Self {
+        Self { conn }
+    }
+}
+
+mod priv_ {
+    // The enum_def macro generates a public enum, which we don't want, because it
+    // triggers the missing docs warning
+
+    use chrono::{DateTime, NaiveDate, Utc};
+    use sea_query::enum_def;
+    use uuid::Uuid;
+
+    #[derive(sqlx::FromRow)]
+    #[enum_def]
+    pub(super) struct UsageStatsLookup {
+        pub(super) usage_stats_id: Uuid,
+        pub(super) day: NaiveDate,
+        pub(super) daily_active_users: i64,
+        pub(super) monthly_active_users: i64,
+        pub(super) registrations: i64,
+        pub(super) password_logins: i64,
+        pub(super) upstream_oauth2_logins: i64,
+        pub(super) compat_logins: i64,
+        pub(super) computed_at: DateTime<Utc>,
+    }
+}
+
+use priv_::{UsageStatsLookup, UsageStatsLookupIden};
+
+impl TryFrom<UsageStatsLookup> for UsageStats {
+    type Error = DatabaseInconsistencyError;
+
+    fn try_from(value: UsageStatsLookup) -> Result<Self, Self::Error> {
+        let id = Ulid::from(value.usage_stats_id);
+        let count = |column: &'static str, value: i64| {
+            u64::try_from(value).map_err(|e| {
+                DatabaseInconsistencyError::on("usage_stats")
+                    .column(column)
+                    .row(id)
+                    .source(e)
+            })
+        };
+
+        Ok(UsageStats {
+            id,
+            day: value.day,
+            daily_active_users: count("daily_active_users", value.daily_active_users)?,
+            monthly_active_users: count("monthly_active_users", value.monthly_active_users)?,
+            registrations: count("registrations", value.registrations)?,
+            password_logins: count("password_logins", value.password_logins)?,
+            upstream_oauth2_logins: count("upstream_oauth2_logins", value.upstream_oauth2_logins)?,
+            compat_logins: count("compat_logins", value.compat_logins)?,
+            computed_at: value.computed_at,
+        })
+    }
+}
+
+impl Filter for UsageStatsFilter {
+    fn generate_condition(&self, _has_joins: bool) -> impl sea_query::IntoCondition {
+        sea_query::Condition::all()
+            .add_option(self.since().map(|since| {
+                Expr::col((iden::UsageStats::Table, iden::UsageStats::Day)).gte(since)
+            }))
+            .add_option(self.until().map(|until| {
+                Expr::col((iden::UsageStats::Table, iden::UsageStats::Day)).lte(until)
+            }))
+    }
+}
+
+#[async_trait]
+impl<'c> UsageStatsRepository for PgUsageStatsRepository<'c> {
+    type Error = DatabaseError;
+
+    #[tracing::instrument(
+        name = "db.usage_stats.lookup",
+        skip_all,
+        fields(
+            db.query.text,
+            usage_stats.id = %id,
+        ),
+        err,
+    )]
+    async fn lookup(&mut self, id: Ulid) -> Result<Option<UsageStats>, Self::Error> {
+        let res = sqlx::query_as!(
+            UsageStatsLookup,
+            r#"
+                SELECT usage_stats_id
+                     , day
+                     , daily_active_users
+                     , monthly_active_users
+                     , registrations
+                     , password_logins
+                     , upstream_oauth2_logins
+                     , compat_logins
+                     , computed_at
+                FROM usage_stats
+                WHERE usage_stats_id = $1
+            "#,
+            Uuid::from(id),
+        )
+        .traced()
+        .fetch_optional(&mut *self.conn)
+        .await?;
+
+        let Some(res) = res else { return Ok(None) };
+
+        Ok(Some(res.try_into()?))
+    }
+
+    #[tracing::instrument(
+        name = "db.usage_stats.find_by_day",
+        skip_all,
+        fields(
+            db.query.text,
+            usage_stats.day = %day,
+        ),
+        err,
+    )]
+    async fn find_by_day(&mut self, day: NaiveDate) -> Result<Option<UsageStats>, Self::Error> {
+        let res = sqlx::query_as!(
+            UsageStatsLookup,
+            r#"
+                SELECT usage_stats_id
+                     , day
+                     , daily_active_users
+                     , monthly_active_users
+                     , registrations
+                     , password_logins
+                     , upstream_oauth2_logins
+                     , compat_logins
+                     , computed_at
+                FROM usage_stats
+                WHERE day = $1
+            "#,
+            day,
+        )
+        .traced()
+        .fetch_optional(&mut *self.conn)
+        .await?;
+
+        let Some(res) = res else { return Ok(None) };
+
+        Ok(Some(res.try_into()?))
+    }
+
+    #[tracing::instrument(
+        name = "db.usage_stats.record",
+        skip_all,
+        fields(
+            db.query.text,
+            usage_stats.id,
+            usage_stats.day = %day,
+        ),
+        err,
+    )]
+    async fn record(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        day: NaiveDate,
+    ) -> Result<UsageStats, Self::Error> {
+        let computed_at = clock.now();
+        let start = day.and_time(chrono::NaiveTime::MIN).and_utc();
+        let end = start + Duration::try_days(1).unwrap();
+        let month_start = day - Duration::try_days(MONTHLY_ACTIVE_DAYS).unwrap();
+
+        // The ID is derived from the start of the day, so that the statistics
+        // are ordered by day. It is only used if the statistics of this day
+        // weren't recorded yet.
+        let id = Ulid::from_datetime_with_source(start.into(), rng);
+
+        // The active users are counted from the days on which they were active,
+        // as sessions only keep their last activity. Those only increase, so the
+        // statistics of a day can be recomputed after it ended without losing
+        // the users who were active again since.
+        // Compatibility sessions created through the SSO login flow are linked
+        // to a browser session, so they are counted as password or upstream
+        // logins instead.
+        let res = sqlx::query_as!(
+            UsageStatsLookup,
+            r#"
+                INSERT INTO usage_stats
+                    ( usage_stats_id
+                    , day
+                    , daily_active_users
+                    , monthly_active_users
+                    , registrations
+                    , password_logins
+                    , upstream_oauth2_logins
+                    , compat_logins
+                    , computed_at
+                    )
+                SELECT
+                    $1,
+                    $2,
+                    (SELECT COUNT(*) FROM user_activity_days
+                     WHERE day = $2),
+                    (SELECT COUNT(DISTINCT user_id) FROM user_activity_days
+                     WHERE day > $4 AND day <= $2),
+                    (SELECT COUNT(*) FROM users
+                     WHERE created_at >= $3 AND created_at < $5),
+                    (SELECT COUNT(*) FROM user_session_authentications
+                     WHERE user_password_id IS NOT NULL
+                       AND created_at >= $3 AND created_at < $5),
+                    (SELECT COUNT(*) FROM user_session_authentications
+                     WHERE upstream_oauth_authorization_session_id IS NOT NULL
+                       AND created_at >= $3 AND created_at < $5),
+                    (SELECT COUNT(*) FROM compat_sessions
+                     WHERE user_session_id IS NULL
+                       AND created_at >= $3 AND created_at < $5),
+                    $6
+                ON CONFLICT (day) DO UPDATE
+                SET daily_active_users = EXCLUDED.daily_active_users
+                  , monthly_active_users = EXCLUDED.monthly_active_users
+                  , registrations = EXCLUDED.registrations
+                  , password_logins = EXCLUDED.password_logins
+                  , upstream_oauth2_logins = EXCLUDED.upstream_oauth2_logins
+                  , compat_logins = EXCLUDED.compat_logins
+                  , computed_at = EXCLUDED.computed_at
+                RETURNING usage_stats_id
+                        , day
+                        , daily_active_users
+                        , monthly_active_users
+                        , registrations
+                        , password_logins
+                        , upstream_oauth2_logins
+                        , compat_logins
+                        , computed_at
+            "#,
+            Uuid::from(id),
+            day,
+            start,
+            month_start,
+            end,
+            computed_at,
+        )
+        .traced()
+        .fetch_one(&mut *self.conn)
+        .await?;
+
+        let stats = UsageStats::try_from(res)?;
+        tracing::Span::current().record("usage_stats.id", tracing::field::display(stats.id));
+
+        Ok(stats)
+    }
+
+    #[tracing::instrument(
+        name = "db.usage_stats.list",
+        skip_all,
+        fields(
+            db.query.text,
+        ),
+        err,
+    )]
+    async fn list(
+        &mut self,
+        filter: UsageStatsFilter,
+        pagination: Pagination,
+    ) -> Result<Page<UsageStats>, Self::Error> {
+        let (sql, arguments) = Query::select()
+            .expr_as(
+                Expr::col((iden::UsageStats::Table, iden::UsageStats::Id)),
+                UsageStatsLookupIden::UsageStatsId,
+            )
+            .expr_as(
+                Expr::col((iden::UsageStats::Table, iden::UsageStats::Day)),
+                UsageStatsLookupIden::Day,
+            )
+            .expr_as(
+                Expr::col((iden::UsageStats::Table, iden::UsageStats::DailyActiveUsers)),
+                UsageStatsLookupIden::DailyActiveUsers,
+            )
+            .expr_as(
+                Expr::col((
+                    iden::UsageStats::Table,
+                    iden::UsageStats::MonthlyActiveUsers,
+                )),
+                UsageStatsLookupIden::MonthlyActiveUsers,
+            )
+            .expr_as(
+                Expr::col((iden::UsageStats::Table, iden::UsageStats::Registrations)),
+                UsageStatsLookupIden::Registrations,
+            )
+            .expr_as(
+                Expr::col((iden::UsageStats::Table, iden::UsageStats::PasswordLogins)),
+                UsageStatsLookupIden::PasswordLogins,
+            )
+            .expr_as(
+                Expr::col((
+                    iden::UsageStats::Table,
+                    iden::UsageStats::UpstreamOAuth2Logins,
+                )),
+                UsageStatsLookupIden::UpstreamOauth2Logins,
+            )
+            .expr_as(
+                Expr::col((iden::UsageStats::Table, iden::UsageStats::CompatLogins)),
+                UsageStatsLookupIden::CompatLogins,
+            )
+            .expr_as(
+                Expr::col((iden::UsageStats::Table, iden::UsageStats::ComputedAt)),
+                UsageStatsLookupIden::ComputedAt,
+            )
+            .from(iden::UsageStats::Table)
+            .apply_filter(filter)
+            .generate_pagination((iden::UsageStats::Table, iden::UsageStats::Id), pagination)
+            .build_sqlx(PostgresQueryBuilder);
+
+        let edges: Vec<UsageStatsLookup> = sqlx::query_as_with(&sql, arguments)
+            .traced()
+            .fetch_all(&mut *self.conn)
+            .await?;
+
+        let page = pagination.process(edges).try_map(UsageStats::try_from)?;
+
+        Ok(page)
+    }
+
+    #[tracing::instrument(
+        name = "db.usage_stats.count",
+        skip_all,
+        fields(
+            db.query.text,
+        ),
+        err,
+    )]
+    async fn count(&mut self, filter: UsageStatsFilter) -> Result<usize, Self::Error> {
+        let (sql, arguments) = Query::select()
+            .expr(Expr::col((iden::UsageStats::Table, iden::UsageStats::Id)).count())
+            .from(iden::UsageStats::Table)
+            .apply_filter(filter)
+            .build_sqlx(PostgresQueryBuilder);
+
+        let count: i64 = sqlx::query_scalar_with(&sql, arguments)
+            .traced()
+            .fetch_one(&mut *self.conn)
+            .await?;
+
+        count
+            .try_into()
+            .map_err(DatabaseError::to_invalid_operation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, NaiveDate};
+    use mas_storage::{
+        clock::MockClock,
+        compat::CompatSessionRepository,
+        usage_stats::UsageStatsFilter,
+        user::{BrowserSessionRepository, UserPasswordRepository},
+        Clock, Pagination, RepositoryAccess,
+    };
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+    use sqlx::PgPool;
+
+    use crate::PgRepository;
+
+    #[sqlx::test(migrator = "crate::MIGRATOR")]
+    async fn test_usage_stats_repo(pool: PgPool) {
+        let mut rng = ChaChaRng::seed_from_u64(42);
+        let clock = MockClock::default();
+        let mut repo = PgRepository::from_pool(&pool).await.unwrap();
+        let today = clock.now().date_naive();
+        let yesterday = today.pred_opt().unwrap();
+
+        // Nothing happened yet
+        let stats = repo
+            .usage_stats()
+            .record(&mut rng, &clock, today)
+            .await
+            .unwrap();
+        assert_eq!(stats.day, today);
+        assert_eq!(stats.daily_active_users, 0);
+        assert_eq!(stats.monthly_active_users, 0);
+        assert_eq!(stats.registrations, 0);
+        assert!(!stats.is_final());
+
+        // Alice registers and logs in with a password
+        let alice = repo
+            .user()
+            .add(&mut rng, &clock, "alice".to_owned())
+            .await
+            .unwrap();
+        let password = repo
+            .user_password()
+            .add(&mut rng, &clock, &alice, 1, "hash".to_owned(), None)
+            .await
+            .unwrap();
+        let browser_session = repo
+            .browser_session()
+            .add(&mut rng, &clock, &alice, None)
+            .await
+            .unwrap();
+        repo.browser_session()
+            .authenticate_with_password(&mut rng, &clock, &browser_session, &password)
+            .await
+            .unwrap();
+        repo.browser_session()
+            .record_batch_activity(vec![(browser_session.id, clock.now(), None)])
+            .await
+            .unwrap();
+
+        // Bob logs in through the compatibility layer
+        let bob = repo
+            .user()
+            .add(&mut rng, &clock, "bob".to_owned())
+            .await
+            .unwrap();
+        let device = mas_data_model::Device::generate(&mut rng);
+        let compat_session = repo
+            .compat_session()
+            .add(&mut rng, &clock, &bob, device, None, false)
+            .await
+            .unwrap();
+        repo.compat_session()
+            .record_batch_activity(vec![(compat_session.id, clock.now(), None)])
+            .await
+            .unwrap();
+
+        // Recomputing the same day updates the existing statistics
+        let updated = repo
+            .usage_stats()
+            .record(&mut rng, &clock, today)
+            .await
+            .unwrap();
+        assert_eq!(updated.id, stats.id);
+        assert_eq!(updated.daily_active_users, 2);
+        assert_eq!(updated.monthly_active_users, 2);
+        assert_eq!(updated.registrations, 2);
+        assert_eq!(updated.password_logins, 1);
+        assert_eq!(updated.upstream_oauth2_logins, 0);
+        assert_eq!(updated.compat_logins, 1);
+
+        // Nothing happened yesterday
+        let previous = repo
+            .usage_stats()
+            .record(&mut rng, &clock, yesterday)
+            .await
+            .unwrap();
+        assert_eq!(previous.daily_active_users, 0);
+        assert_eq!(previous.registrations, 0);
+        assert!(previous.is_final());
+
+        // The next day, Alice is still active but Bob isn't
+        clock.advance(Duration::try_days(1).unwrap());
+        repo.browser_session()
+            .record_batch_activity(vec![(browser_session.id, clock.now(), None)])
+            .await
+            .unwrap();
+        let tomorrow = clock.now().date_naive();
+        let next = repo
+            .usage_stats()
+            .record(&mut rng, &clock, tomorrow)
+            .await
+            .unwrap();
+        assert_eq!(next.daily_active_users, 1);
+        assert_eq!(next.monthly_active_users, 2);
+        assert_eq!(next.registrations, 0);
+
+        // Alice's activity moved to the next day, but the count of the previous
+        // day doesn't decrease when it is finalized
+        let finalized = repo
+            .usage_stats()
+            .record(&mut rng, &clock, today)
+            .await
+            .unwrap();
+        assert_eq!(finalized.daily_active_users, 2);
+        assert!(finalized.is_final());
+
+        // Lookup the statistics
+        let found = repo.usage_stats().lookup(next.id).await.unwrap().unwrap();
+        assert_eq!(found, next);
+        let found = repo
+            .usage_stats()
+            .find_by_day(today)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(found, finalized);
+        assert!(repo
+            .usage_stats()
+            .find_by_day(NaiveDate::from_ymd_opt(2000, 1, 1).unwrap())
+            .await
+            .unwrap()
+            .is_none());
+
+        // List and count the statistics
+        let all = UsageStatsFilter::new();
+        assert_eq!(repo.usage_stats().count(all).await.unwrap(), 3);
+        assert_eq!(
+            repo.usage_stats()
+                .count(all.with_since(today))
+                .await
+                .unwrap(),
+            2
+        );
+        assert_eq!(
+            repo.usage_stats()
+                .count(all.with_until(today))
+                .await
+                .unwrap(),
+            2
+        );
+
+        let page = repo
+            .usage_stats()
+            .list(
+                all.with_since(today).with_until(today),
+                Pagination::first(10),
+            )
+            .await
+            .unwrap();
+        assert!(!page.has_next_page);
+        assert_eq!(page.edges, vec![finalized]);
+    }
+
+    #[sqlx::test(migrator = "crate::MIGRATOR")]
+    async fn test_usage_stats_active_on_both_days(pool: PgPool) {
+        let mut rng = ChaChaRng::seed_from_u64(42);
+        let clock = MockClock::default();
+        let mut repo = PgRepository::from_pool(&pool).await.unwrap();
+        let today = clock.now().date_naive();
+
+        // Alice and Bob are active today, but the statistics are not computed
+        // before the day ends
+        let mut sessions = Vec::new();
+        for username in ["alice", "bob"] {
+            let user = repo
+                .user()
+                .add(&mut rng, &clock, username.to_owned())
+                .await
+                .unwrap();
+            let session = repo
+                .browser_session()
+                .add(&mut rng, &clock, &user, None)
+                .await
+                .unwrap();
+            repo.browser_session()
+                .record_batch_activity(vec![(session.id, clock.now(), None)])
+                .await
+                .unwrap();
+            sessions.push(session);
+        }
+
+        // The next day, Alice is active again before the statistics of the
+        // previous day are finalized
+        clock.advance(Duration::try_days(1).unwrap());
+        repo.browser_session()
+            .record_batch_activity(vec![(sessions[0].id, clock.now(), None)])
+            .await
+            .unwrap();
+
+        // She is still counted in the previous day
+        let finalized = repo
+            .usage_stats()
+            .record(&mut rng, &clock, today)
+            .await
+            .unwrap();
+        assert!(finalized.is_final());
+        assert_eq!(finalized.daily_active_users, 2);
+        assert_eq!(finalized.monthly_active_users, 2);
+
+        let current = repo
+            .usage_stats()
+            .record(&mut rng, &clock, clock.now().date_naive())
+            .await
+            .unwrap();
+        assert_eq!(current.daily_active_users, 1);
+        assert_eq!(current.monthly_active_users, 2);
+
+        // 30 days after the first day, only Alice is still in the monthly window
+        clock.advance(Duration::try_days(29).unwrap());
+        let later = repo
+            .usage_stats()
+            .record(&mut rng, &clock, clock.now().date_naive())
+            .await
+            .unwrap();
+        assert_eq!(later.daily_active_users, 0);
+        assert_eq!(later.monthly_active_users, 1);
+    }
+}
@@ -0,0 +1,204 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use mas_data_model::{User, UserLoginLockout};
+use mas_storage::{user::UserLoginLockoutRepository, Clock};
+use sqlx::PgConnection;
+use ulid::Ulid;
+use uuid::Uuid;
+
+use crate::{tracing::ExecuteExt, DatabaseError, DatabaseInconsistencyError};
+
+/// An implementation of [`UserLoginLockoutRepository`] for a PostgreSQL
+/// connection
+pub struct PgUserLoginLockoutRepository<'c> {
+    conn: &'c mut PgConnection,
+}
+
+impl<'c> PgUserLoginLockoutRepository<'c> {
+    /// Create a new [`PgUserLoginLockoutRepository`] from an active PostgreSQL
+    /// connection
+    pub fn new(conn: &'c mut PgConnection) -> Self {
+        Self { conn }
+    }
+}
+
+struct UserLoginLockoutLookup {
+    user_id: Uuid,
+    failed_attempts: i32,
+    last_failed_at: DateTime<Utc>,
+    locked_until: Option<DateTime<Utc>>,
+}
+
+impl TryFrom<UserLoginLockoutLookup> for UserLoginLockout {
+    type Error = DatabaseInconsistencyError;
+
+    fn try_from(value: UserLoginLockoutLookup) -> Result<Self, Self::Error> {
+        let user_id = Ulid::from(value.user_id);
+        let failed_attempts = value.failed_attempts.try_into().map_err(|e| {
+            DatabaseInconsistencyError::on("user_login_lockouts")
+                .column("failed_attempts")
+                .row(user_id)
+                .source(e)
+        })?;
+
+        Ok(UserLoginLockout {
+            user_id,
+            failed_attempts,
+            last_failed_at: value.last_failed_at,
+            locked_until: value.locked_until,
+        })
+    }
+}
+
+#[async_trait]
+impl<'c> UserLoginLockoutRepository for PgUserLoginLockoutRepository<'c> {
+    type Error = DatabaseError;
+
+    #[tracing::instrument(
+        name = "db.user_login_lockout.lookup",
+        skip_all,
+        fields(
+            db.query.text,
+            %user.id,
+        ),
+        err,
+    )]
+    async fn lookup(&mut self, user: &User) -> Result<Option<UserLoginLockout>, Self::Error> {
+        let res = sqlx::query_as!(
+            UserLoginLockoutLookup,
+            r#"
+                SELECT user_id
+                     , failed_attempts
+                     , last_failed_at
+                     , locked_until
+                FROM user_login_lockouts
+                WHERE user_id = $1
+            "#,
+            Uuid::from(user.id),
+        )
+        .traced()
+        .fetch_optional(&mut *self.conn)
+        .await?;
+
+        let Some(res) = res else { return Ok(None) };
+
+        Ok(Some(res.try_into()?))
+    }
+
+    #[tracing::instrument(
+        name = "db.user_login_lockout.record_failure",
+        skip_all,
+        fields(
+            db.query.text,
+            %user.id,
+        ),
+        err,
+    )]
+    async fn record_failure(
+        &mut self,
+        clock: &dyn Clock,
+        user: &User,
+        window: Duration,
+    ) -> Result<UserLoginLockout, Self::Error> {
+        let now = clock.now();
+        let window_start = now - window;
+
+        // Start counting again if the previous attempts are too old, or if the
+        // previous lockout expired
+        let res = sqlx::query_as!(
+            UserLoginLockoutLookup,
+            r#"
+                INSERT INTO user_login_lockouts
+                    (user_id, failed_attempts, last_failed_at)
+                VALUES ($1, 1, $2)
+                ON CONFLICT (user_id) DO UPDATE
+                SET failed_attempts = CASE
+                        WHEN user_login_lockouts.last_failed_at < $3
+                          OR user_login_lockouts.locked_until <= $2
+                        THEN 1
+                        ELSE user_login_lockouts.failed_attempts + 1
+                    END
+                  , locked_until = CASE
+                        WHEN user_login_lockouts.locked_until <= $2 THEN NULL
+                        ELSE user_login_lockouts.locked_until
+                    END
+                  , last_failed_at = $2
+                RETURNING user_id
+                        , failed_attempts
+                        , last_failed_at
+                        , locked_until
+            "#,
+            Uuid::from(user.id),
+            now,
+            window_start,
+        )
+        .traced()
+        .fetch_one(&mut *self.conn)
+        .await?;
+
+        Ok(res.try_into()?)
+    }
+
+    #[tracing::instrument(
+        name = "db.user_login_lockout.lock",
+        skip_all,
+        fields(
+            db.query.text,
+            user.id = %lockout.user_id,
+            %locked_until,
+        ),
+        err,
+    )]
+    async fn lock(
+        &mut self,
+        mut lockout: UserLoginLockout,
+        locked_until: DateTime<Utc>,
+    ) -> Result<UserLoginLockout, Self::Error> {
+        let res = sqlx::query!(
+            r#"
+                UPDATE user_login_lockouts
+                SET locked_until = $2
+                WHERE user_id = $1
+            "#,
+            Uuid::from(lockout.user_id),
+            locked_until,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        lockout.locked_until = Some(locked_until);
+        Ok(lockout)
+    }
+
+    #[tracing::instrument(
+        name = "db.user_login_lockout.clear",
+        skip_all,
+        fields(
+            db.query.text,
+            %user.id,
+        ),
+        err,
+    )]
+    async fn clear(&mut self, user: &User) -> Result<bool, Self::Error> {
+        let res = sqlx::query!(
+            r#"
+                DELETE FROM user_login_lockouts
+                WHERE user_id = $1
+            "#,
+            Uuid::from(user.id),
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(res.rows_affected() > 0)
+    }
+}
@@ -29,8 +29,10 @@ use crate::{
 };
 
 mod email;
+mod lockout;
 mod password;
 mod recovery;
+mod registration_token;
 mod session;
 mod terms;
 
@@ -38,8 +40,9 @@ mod terms;
 mod tests;
 
 pub use self::{
-    email::PgUserEmailRepository, password::PgUserPasswordRepository,
-    recovery::PgUserRecoveryRepository, session::PgBrowserSessionRepository,
+    email::PgUserEmailRepository, lockout::PgUserLoginLockoutRepository,
+    password::PgUserPasswordRepository, recovery::PgUserRecoveryRepository,
+    registration_token::PgUserRegistrationTokenRepository, session::PgBrowserSessionRepository,
     terms::PgUserTermsRepository,
 };
 
@@ -8,7 +8,9 @@ use std::net::IpAddr;
 
 use async_trait::async_trait;
 use chrono::{DateTime, Duration, Utc};
-use mas_data_model::{UserAgent, UserEmail, UserRecoverySession, UserRecoveryTicket};
+use mas_data_model::{
+    UpstreamOAuthLink, User, UserAgent, UserEmail, UserRecoverySession, UserRecoveryTicket,
+};
 use mas_storage::{user::UserRecoveryRepository, Clock};
 use rand::RngCore;
 use sqlx::PgConnection;
@@ -32,7 +34,7 @@ impl<'c> PgUserRecoveryRepository<'c> {
 
 struct UserRecoverySessionRow {
     user_recovery_session_id: Uuid,
-    email: String,
+    email: Option<String>,
     user_agent: String,
     ip_address: Option<IpAddr>,
     locale: String,
@@ -57,7 +59,9 @@ impl From<UserRecoverySessionRow> for UserRecoverySession {
 struct UserRecoveryTicketRow {
     user_recovery_ticket_id: Uuid,
     user_recovery_session_id: Uuid,
-    user_email_id: Uuid,
+    user_id: Uuid,
+    user_email_id: Option<Uuid>,
+    upstream_oauth_link_id: Option<Uuid>,
     ticket: String,
     created_at: DateTime<Utc>,
     expires_at: DateTime<Utc>,
@@ -68,7 +72,9 @@ impl From<UserRecoveryTicketRow> for UserRecoveryTicket {
         Self {
             id: row.user_recovery_ticket_id.into(),
             user_recovery_session_id: row.user_recovery_session_id.into(),
-            user_email_id: row.user_email_id.into(),
+            user_id: row.user_id.into(),
+            user_email_id: row.user_email_id.map(Ulid::from),
+            upstream_oauth_link_id: row.upstream_oauth_link_id.map(Ulid::from),
             ticket: row.ticket,
             created_at: row.created_at,
             expires_at: row.expires_at,
@@ -168,7 +174,62 @@ impl<'c> UserRecoveryRepository for PgUserRecoveryRepository<'c> {
 
         let user_recovery_session = UserRecoverySession {
             id,
-            email,
+            email: Some(email),
+            user_agent,
+            ip_address,
+            locale,
+            created_at,
+            consumed_at: None,
+        };
+
+        Ok(user_recovery_session)
+    }
+
+    #[tracing::instrument(
+        name = "db.user_recovery.add_upstream_session",
+        skip_all,
+        fields(
+            db.query.text,
+            user_recovery_session.id,
+            user_recovery_session.user_agent = &*user_agent,
+            user_recovery_session.ip_address = ip_address.map(|ip| ip.to_string()),
+        )
+    )]
+    async fn add_upstream_session(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user_agent: UserAgent,
+        ip_address: Option<IpAddr>,
+        locale: String,
+    ) -> Result<UserRecoverySession, Self::Error> {
+        let created_at = clock.now();
+        let id = Ulid::from_datetime_with_source(created_at.into(), rng);
+        tracing::Span::current().record("user_recovery_session.id", tracing::field::display(id));
+        sqlx::query!(
+            r#"
+                INSERT INTO user_recovery_sessions (
+                      user_recovery_session_id
+                    , user_agent
+                    , ip_address
+                    , locale
+                    , created_at
+                )
+                VALUES ($1, $2, $3, $4, $5)
+            "#,
+            Uuid::from(id),
+            &*user_agent,
+            ip_address as Option<IpAddr>,
+            &locale,
+            created_at,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        let user_recovery_session = UserRecoverySession {
+            id,
+            email: None,
             user_agent,
             ip_address,
             locale,
@@ -198,7 +259,9 @@ impl<'c> UserRecoveryRepository for PgUserRecoveryRepository<'c> {
                 SELECT
                       user_recovery_ticket_id
                     , user_recovery_session_id
+                    , user_id
                     , user_email_id
+                    , upstream_oauth_link_id
                     , ticket
                     , created_at
                     , expires_at
@@ -249,15 +312,17 @@ impl<'c> UserRecoveryRepository for PgUserRecoveryRepository<'c> {
                 INSERT INTO user_recovery_tickets (
                       user_recovery_ticket_id
                     , user_recovery_session_id
+                    , user_id
                     , user_email_id
                     , ticket
                     , created_at
                     , expires_at
                 )
-                VALUES ($1, $2, $3, $4, $5, $6)
+                VALUES ($1, $2, $3, $4, $5, $6, $7)
             "#,
             Uuid::from(id),
             Uuid::from(user_recovery_session.id),
+            Uuid::from(user_email.user_id),
             Uuid::from(user_email.id),
             &ticket,
             created_at,
@@ -270,7 +335,76 @@ impl<'c> UserRecoveryRepository for PgUserRecoveryRepository<'c> {
         let ticket = UserRecoveryTicket {
             id,
             user_recovery_session_id: user_recovery_session.id,
-            user_email_id: user_email.id,
+            user_id: user_email.user_id,
+            user_email_id: Some(user_email.id),
+            upstream_oauth_link_id: None,
+            ticket,
+            created_at,
+            expires_at,
+        };
+
+        Ok(ticket)
+    }
+
+    #[tracing::instrument(
+        name = "db.user_recovery.add_upstream_ticket",
+        skip_all,
+        fields(
+            db.query.text,
+            user_recovery_ticket.id,
+            user_recovery_ticket.id = ticket,
+            %user_recovery_session.id,
+            %user.id,
+            %upstream_oauth_link.id,
+        )
+    )]
+    async fn add_upstream_ticket(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user_recovery_session: &UserRecoverySession,
+        user: &User,
+        upstream_oauth_link: &UpstreamOAuthLink,
+        ticket: String,
+    ) -> Result<UserRecoveryTicket, Self::Error> {
+        let created_at = clock.now();
+        let id = Ulid::from_datetime_with_source(created_at.into(), rng);
+        tracing::Span::current().record("user_recovery_ticket.id", tracing::field::display(id));
+
+        // TODO: move that to a parameter
+        let expires_at = created_at + Duration::minutes(10);
+
+        sqlx::query!(
+            r#"
+                INSERT INTO user_recovery_tickets (
+                      user_recovery_ticket_id
+                    , user_recovery_session_id
+                    , user_id
+                    , upstream_oauth_link_id
+                    , ticket
+                    , created_at
+                    , expires_at
+                )
+                VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+            Uuid::from(id),
+            Uuid::from(user_recovery_session.id),
+            Uuid::from(user.id),
+            Uuid::from(upstream_oauth_link.id),
+            &ticket,
+            created_at,
+            expires_at,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        let ticket = UserRecoveryTicket {
+            id,
+            user_recovery_session_id: user_recovery_session.id,
+            user_id: user.id,
+            user_email_id: None,
+            upstream_oauth_link_id: Some(upstream_oauth_link.id),
             ticket,
             created_at,
             expires_at,
@@ -285,9 +419,8 @@ impl<'c> UserRecoveryRepository for PgUserRecoveryRepository<'c> {
         fields(
             db.query.text,
             %user_recovery_ticket.id,
-            user_email.id = %user_recovery_ticket.user_email_id,
+            user.id = %user_recovery_ticket.user_id,
             %user_recovery_session.id,
-            %user_recovery_session.email,
         ),
         err,
     )]
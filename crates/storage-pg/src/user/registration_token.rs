@@ -0,0 +1,541 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use mas_data_model::{User, UserRegistrationToken};
+use mas_storage::{
+    user::{UserRegistrationTokenFilter, UserRegistrationTokenRepository},
+    Clock, Page, Pagination,
+};
+use rand::RngCore;
+use sea_query::{Expr, PostgresQueryBuilder, Query};
+use sea_query_binder::SqlxBinder;
+use sqlx::PgConnection;
+use ulid::Ulid;
+use uuid::Uuid;
+
+use crate::{
+    filter::{Filter, StatementExt},
+    iden::UserRegistrationTokens,
+    pagination::QueryBuilderExt,
+    tracing::ExecuteExt,
+    DatabaseError, DatabaseInconsistencyError,
+};
+
+/// Counts the users who registered with a token but haven't verified an email
+/// address yet. This is a correlated subquery on the `user_registration_tokens`
+/// table, and must be kept in sync with the queries in this module.
+const PENDING_USES: &str = r"
+    (SELECT COUNT(*) FROM user_registration_token_uses
+     WHERE user_registration_token_uses.user_registration_token_id
+           = user_registration_tokens.user_registration_token_id
+       AND NOT EXISTS (
+           SELECT 1 FROM user_emails
+           WHERE user_emails.user_id = user_registration_token_uses.user_id
+             AND user_emails.confirmed_at IS NOT NULL
+       ))
+";
+
+/// An implementation of [`UserRegistrationTokenRepository`] for a PostgreSQL
+/// connection
+pub struct PgUserRegistrationTokenRepository<'c> {
+    conn: &'c mut PgConnection,
+}
+
+impl<'c> PgUserRegistrationTokenRepository<'c> {
+    /// Create a new [`PgUserRegistrationTokenRepository`] from an active
+    /// PostgreSQL connection
+    pub fn new(conn: &'c mut PgConnection) -> Self {
+        Self { conn }
+    }
+}
+
+mod priv_ {
+    // The enum_def macro generates a public enum, which we don't want, because it
+    // triggers the missing docs warning
+
+    use chrono::{DateTime, Utc};
+    use sea_query::enum_def;
+    use uuid::Uuid;
+
+    #[derive(sqlx::FromRow)]
+    #[enum_def]
+    pub(super) struct UserRegistrationTokenLookup {
+        pub(super) user_registration_token_id: Uuid,
+        pub(super) token: String,
+        pub(super) usage_limit: Option<i32>,
+        pub(super) times_used: i32,
+        pub(super) pending_uses: i64,
+        pub(super) created_at: DateTime<Utc>,
+        pub(super) last_used_at: Option<DateTime<Utc>>,
+        pub(super) expires_at: Option<DateTime<Utc>>,
+        pub(super) revoked_at: Option<DateTime<Utc>>,
+    }
+}
+
+use priv_::{UserRegistrationTokenLookup, UserRegistrationTokenLookupIden};
+
+impl TryFrom<UserRegistrationTokenLookup> for UserRegistrationToken {
+    type Error = DatabaseInconsistencyError;
+
+    fn try_from(value: UserRegistrationTokenLookup) -> Result<Self, Self::Error> {
+        let id = Ulid::from(value.user_registration_token_id);
+        let inconsistency = |column: &'static str| {
+            move |e| {
+                DatabaseInconsistencyError::on("user_registration_tokens")
+                    .column(column)
+                    .row(id)
+                    .source(e)
+            }
+        };
+
+        let usage_limit = value
+            .usage_limit
+            .map(u32::try_from)
+            .transpose()
+            .map_err(inconsistency("usage_limit"))?;
+        let times_used = u32::try_from(value.times_used).map_err(inconsistency("times_used"))?;
+        let pending_uses =
+            u32::try_from(value.pending_uses).map_err(inconsistency("pending_uses"))?;
+
+        Ok(UserRegistrationToken {
+            id,
+            token: value.token,
+            usage_limit,
+            times_used,
+            pending_uses,
+            created_at: value.created_at,
+            last_used_at: value.last_used_at,
+            expires_at: value.expires_at,
+            revoked_at: value.revoked_at,
+        })
+    }
+}
+
+impl Filter for UserRegistrationTokenFilter {
+    fn generate_condition(&self, _has_joins: bool) -> impl sea_query::IntoCondition {
+        let revoked_at = Expr::col((
+            UserRegistrationTokens::Table,
+            UserRegistrationTokens::RevokedAt,
+        ));
+        let expires_at = || {
+            Expr::col((
+                UserRegistrationTokens::Table,
+                UserRegistrationTokens::ExpiresAt,
+            ))
+        };
+        let usage_limit = || {
+            Expr::col((
+                UserRegistrationTokens::Table,
+                UserRegistrationTokens::UsageLimit,
+            ))
+        };
+        let times_used = Expr::col((
+            UserRegistrationTokens::Table,
+            UserRegistrationTokens::TimesUsed,
+        ));
+
+        sea_query::Condition::all()
+            .add_option(self.revoked().map(|revoked| {
+                if revoked {
+                    revoked_at.is_not_null()
+                } else {
+                    revoked_at.is_null()
+                }
+            }))
+            .add_option(self.expired().map(|expired| {
+                if expired {
+                    sea_query::Condition::all().add(expires_at().lte(self.now()))
+                } else {
+                    sea_query::Condition::any()
+                        .add(expires_at().is_null())
+                        .add(expires_at().gt(self.now()))
+                }
+            }))
+            .add_option(self.exhausted().map(|exhausted| {
+                if exhausted {
+                    sea_query::Condition::all().add(times_used.gte(usage_limit()))
+                } else {
+                    sea_query::Condition::any()
+                        .add(usage_limit().is_null())
+                        .add(times_used.lt(usage_limit()))
+                }
+            }))
+    }
+}
+
+#[async_trait]
+impl<'c> UserRegistrationTokenRepository for PgUserRegistrationTokenRepository<'c> {
+    type Error = DatabaseError;
+
+    #[tracing::instrument(
+        name = "db.user_registration_token.lookup",
+        skip_all,
+        fields(
+            db.query.text,
+            user_registration_token.id = %id,
+        ),
+        err,
+    )]
+    async fn lookup(&mut self, id: Ulid) -> Result<Option<UserRegistrationToken>, Self::Error> {
+        let res = sqlx::query_as!(
+            UserRegistrationTokenLookup,
+            r#"
+                SELECT user_registration_token_id
+                     , token
+                     , usage_limit
+                     , times_used
+                     , (SELECT COUNT(*) FROM user_registration_token_uses
+                        WHERE user_registration_token_uses.user_registration_token_id
+                              = user_registration_tokens.user_registration_token_id
+                          AND NOT EXISTS (
+                              SELECT 1 FROM user_emails
+                              WHERE user_emails.user_id = user_registration_token_uses.user_id
+                                AND user_emails.confirmed_at IS NOT NULL
+                          )) AS "pending_uses!"
+                     , created_at
+                     , last_used_at
+                     , expires_at
+                     , revoked_at
+                FROM user_registration_tokens
+                WHERE user_registration_token_id = $1
+            "#,
+            Uuid::from(id),
+        )
+        .traced()
+        .fetch_optional(&mut *self.conn)
+        .await?;
+
+        let Some(res) = res else { return Ok(None) };
+
+        Ok(Some(res.try_into()?))
+    }
+
+    #[tracing::instrument(
+        name = "db.user_registration_token.find_by_token",
+        skip_all,
+        fields(
+            db.query.text,
+        ),
+        err,
+    )]
+    async fn find_by_token(
+        &mut self,
+        token: &str,
+    ) -> Result<Option<UserRegistrationToken>, Self::Error> {
+        let res = sqlx::query_as!(
+            UserRegistrationTokenLookup,
+            r#"
+                SELECT user_registration_token_id
+                     , token
+                     , usage_limit
+                     , times_used
+                     , (SELECT COUNT(*) FROM user_registration_token_uses
+                        WHERE user_registration_token_uses.user_registration_token_id
+                              = user_registration_tokens.user_registration_token_id
+                          AND NOT EXISTS (
+                              SELECT 1 FROM user_emails
+                              WHERE user_emails.user_id = user_registration_token_uses.user_id
+                                AND user_emails.confirmed_at IS NOT NULL
+                          )) AS "pending_uses!"
+                     , created_at
+                     , last_used_at
+                     , expires_at
+                     , revoked_at
+                FROM user_registration_tokens
+                WHERE token = $1
+            "#,
+            token,
+        )
+        .traced()
+        .fetch_optional(&mut *self.conn)
+        .await?;
+
+        let Some(res) = res else { return Ok(None) };
+
+        Ok(Some(res.try_into()?))
+    }
+
+    #[tracing::instrument(
+        name = "db.user_registration_token.add",
+        skip_all,
+        fields(
+            db.query.text,
+            user_registration_token.id,
+        ),
+        err,
+    )]
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        token: String,
+        usage_limit: Option<u32>,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<UserRegistrationToken, Self::Error> {
+        let created_at = clock.now();
+        let id = Ulid::from_datetime_with_source(created_at.into(), rng);
+        tracing::Span::current().record("user_registration_token.id", tracing::field::display(id));
+
+        let usage_limit_i32 = usage_limit
+            .map(i32::try_from)
+            .transpose()
+            .map_err(DatabaseError::to_invalid_operation)?;
+
+        sqlx::query!(
+            r#"
+                INSERT INTO user_registration_tokens
+                    ( user_registration_token_id
+                    , token
+                    , usage_limit
+                    , created_at
+                    , expires_at
+                    )
+                VALUES ($1, $2, $3, $4, $5)
+            "#,
+            Uuid::from(id),
+            &token,
+            usage_limit_i32,
+            created_at,
+            expires_at,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(UserRegistrationToken {
+            id,
+            token,
+            usage_limit,
+            times_used: 0,
+            pending_uses: 0,
+            created_at,
+            last_used_at: None,
+            expires_at,
+            revoked_at: None,
+        })
+    }
+
+    #[tracing::instrument(
+        name = "db.user_registration_token.use_token",
+        skip_all,
+        fields(
+            db.query.text,
+            user_registration_token.id = %token.id,
+            %user.id,
+        ),
+        err,
+    )]
+    async fn use_token(
+        &mut self,
+        clock: &dyn Clock,
+        token: UserRegistrationToken,
+        user: &User,
+    ) -> Result<Option<UserRegistrationToken>, Self::Error> {
+        let now = clock.now();
+
+        // The token is checked again here, so that concurrent registrations
+        // can't use it more times than allowed
+        let res = sqlx::query!(
+            r#"
+                UPDATE user_registration_tokens
+                SET times_used = times_used + 1
+                  , last_used_at = $2
+                WHERE user_registration_token_id = $1
+                  AND revoked_at IS NULL
+                  AND (expires_at IS NULL OR expires_at > $2)
+                  AND (usage_limit IS NULL OR times_used < usage_limit)
+            "#,
+            Uuid::from(token.id),
+            now,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        if res.rows_affected() == 0 {
+            return Ok(None);
+        }
+
+        sqlx::query!(
+            r#"
+                INSERT INTO user_registration_token_uses
+                    ( user_registration_token_id
+                    , user_id
+                    , created_at
+                    )
+                VALUES ($1, $2, $3)
+            "#,
+            Uuid::from(token.id),
+            Uuid::from(user.id),
+            now,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        self.lookup(token.id).await
+    }
+
+    #[tracing::instrument(
+        name = "db.user_registration_token.revoke",
+        skip_all,
+        fields(
+            db.query.text,
+            user_registration_token.id = %token.id,
+        ),
+        err,
+    )]
+    async fn revoke(
+        &mut self,
+        clock: &dyn Clock,
+        mut token: UserRegistrationToken,
+    ) -> Result<UserRegistrationToken, Self::Error> {
+        let revoked_at = clock.now();
+        let res = sqlx::query!(
+            r#"
+                UPDATE user_registration_tokens
+                SET revoked_at = $2
+                WHERE user_registration_token_id = $1
+            "#,
+            Uuid::from(token.id),
+            revoked_at,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        token.revoked_at = Some(revoked_at);
+        Ok(token)
+    }
+
+    #[tracing::instrument(
+        name = "db.user_registration_token.list",
+        skip_all,
+        fields(
+            db.query.text,
+        ),
+        err,
+    )]
+    async fn list(
+        &mut self,
+        filter: UserRegistrationTokenFilter,
+        pagination: Pagination,
+    ) -> Result<Page<UserRegistrationToken>, Self::Error> {
+        let (sql, arguments) = Query::select()
+            .expr_as(
+                Expr::col((
+                    UserRegistrationTokens::Table,
+                    UserRegistrationTokens::UserRegistrationTokenId,
+                )),
+                UserRegistrationTokenLookupIden::UserRegistrationTokenId,
+            )
+            .expr_as(
+                Expr::col((UserRegistrationTokens::Table, UserRegistrationTokens::Token)),
+                UserRegistrationTokenLookupIden::Token,
+            )
+            .expr_as(
+                Expr::col((
+                    UserRegistrationTokens::Table,
+                    UserRegistrationTokens::UsageLimit,
+                )),
+                UserRegistrationTokenLookupIden::UsageLimit,
+            )
+            .expr_as(
+                Expr::col((
+                    UserRegistrationTokens::Table,
+                    UserRegistrationTokens::TimesUsed,
+                )),
+                UserRegistrationTokenLookupIden::TimesUsed,
+            )
+            .expr_as(
+                Expr::cust(PENDING_USES),
+                UserRegistrationTokenLookupIden::PendingUses,
+            )
+            .expr_as(
+                Expr::col((
+                    UserRegistrationTokens::Table,
+                    UserRegistrationTokens::CreatedAt,
+                )),
+                UserRegistrationTokenLookupIden::CreatedAt,
+            )
+            .expr_as(
+                Expr::col((
+                    UserRegistrationTokens::Table,
+                    UserRegistrationTokens::LastUsedAt,
+                )),
+                UserRegistrationTokenLookupIden::LastUsedAt,
+            )
+            .expr_as(
+                Expr::col((
+                    UserRegistrationTokens::Table,
+                    UserRegistrationTokens::ExpiresAt,
+                )),
+                UserRegistrationTokenLookupIden::ExpiresAt,
+            )
+            .expr_as(
+                Expr::col((
+                    UserRegistrationTokens::Table,
+                    UserRegistrationTokens::RevokedAt,
+                )),
+                UserRegistrationTokenLookupIden::RevokedAt,
+            )
+            .from(UserRegistrationTokens::Table)
+            .apply_filter(filter)
+            .generate_pagination(
+                (
+                    UserRegistrationTokens::Table,
+                    UserRegistrationTokens::UserRegistrationTokenId,
+                ),
+                pagination,
+            )
+            .build_sqlx(PostgresQueryBuilder);
+
+        let edges: Vec<UserRegistrationTokenLookup> = sqlx::query_as_with(&sql, arguments)
+            .traced()
+            .fetch_all(&mut *self.conn)
+            .await?;
+
+        let page = pagination
+            .process(edges)
+            .try_map(UserRegistrationToken::try_from)?;
+
+        Ok(page)
+    }
+
+    #[tracing::instrument(
+        name = "db.user_registration_token.count",
+        skip_all,
+        fields(
+            db.query.text,
+        ),
+        err,
+    )]
+    async fn count(&mut self, filter: UserRegistrationTokenFilter) -> Result<usize, Self::Error> {
+        let (sql, arguments) = Query::select()
+            .expr(
+                Expr::col((
+                    UserRegistrationTokens::Table,
+                    UserRegistrationTokens::UserRegistrationTokenId,
+                ))
+                .count(),
+            )
+            .from(UserRegistrationTokens::Table)
+            .apply_filter(filter)
+            .build_sqlx(PostgresQueryBuilder);
+
+        let count: i64 = sqlx::query_scalar_with(&sql, arguments)
+            .traced()
+            .fetch_one(&mut *self.conn)
+            .await?;
+
+        count
+            .try_into()
+            .map_err(DatabaseError::to_invalid_operation)
+    }
+}
@@ -17,7 +17,7 @@ use mas_storage::{
     Clock, Page, Pagination,
 };
 use rand::RngCore;
-use sea_query::{Expr, PostgresQueryBuilder};
+use sea_query::{Alias, Expr, Func, PostgresQueryBuilder, Query};
 use sea_query_binder::SqlxBinder;
 use sqlx::PgConnection;
 use ulid::Ulid;
@@ -25,7 +25,7 @@ use uuid::Uuid;
 
 use crate::{
     filter::StatementExt,
-    iden::{UserSessions, Users},
+    iden::{UserSessionAuthentications, UserSessions, Users},
     pagination::QueryBuilderExt,
     tracing::ExecuteExt,
     DatabaseError, DatabaseInconsistencyError,
@@ -145,6 +145,50 @@ impl crate::filter::Filter for BrowserSessionFilter<'_> {
             .add_option(self.last_active_before().map(|last_active_before| {
                 Expr::col((UserSessions::Table, UserSessions::LastActiveAt)).lt(last_active_before)
             }))
+            .add_option(self.user_agent().map(|user_agent| {
+                Expr::col((UserSessions::Table, UserSessions::UserAgent)).eq(user_agent)
+            }))
+            .add_option(self.last_active_ip().map(|last_active_ip| {
+                // sea-query doesn't know about IP addresses, so we pass it as text
+                Expr::col((UserSessions::Table, UserSessions::LastActiveIp))
+                    .eq(Expr::val(last_active_ip.to_string()).cast_as(Alias::new("inet")))
+            }))
+            .add_option(self.created_before().map(|created_before| {
+                Expr::col((UserSessions::Table, UserSessions::CreatedAt)).lt(created_before)
+            }))
+            .add_option(self.inactive_since().map(|inactive_since| {
+                // Sessions which were never used are inactive since their creation
+                Expr::expr(Func::coalesce([
+                    Expr::col((UserSessions::Table, UserSessions::LastActiveAt)).into(),
+                    Expr::col((UserSessions::Table, UserSessions::CreatedAt)).into(),
+                ]))
+                .lt(inactive_since)
+            }))
+            .add_option(
+                self.authenticated_by_upstream_sessions()
+                    .map(|upstream_oauth_sessions| {
+                        Expr::col((UserSessions::Table, UserSessions::UserSessionId)).in_subquery(
+                        Query::select()
+                            .expr(Expr::col((
+                                UserSessionAuthentications::Table,
+                                UserSessionAuthentications::UserSessionId,
+                            )))
+                            .from(UserSessionAuthentications::Table)
+                            .and_where(
+                                Expr::col((
+                                    UserSessionAuthentications::Table,
+                                    UserSessionAuthentications::UpstreamOAuthAuthorizationSessionId,
+                                ))
+                                .is_in(
+                                    upstream_oauth_sessions
+                                        .iter()
+                                        .map(|session| Uuid::from(session.id)),
+                                ),
+                            )
+                            .take(),
+                    )
+                    }),
+            )
     }
 }
 
@@ -598,6 +642,24 @@ impl<'c> BrowserSessionRepository for PgBrowserSessionRepository<'c> {
 
         DatabaseError::ensure_affected_rows(&res, ids.len().try_into().unwrap_or(u64::MAX))?;
 
+        // Also record the days on which the users were active, for the usage
+        // statistics
+        sqlx::query!(
+            r#"
+                INSERT INTO user_activity_days (day, user_id)
+                SELECT DISTINCT (t.last_active_at AT TIME ZONE 'UTC')::date, user_sessions.user_id
+                FROM UNNEST($1::uuid[], $2::timestamptz[])
+                    AS t(user_session_id, last_active_at)
+                INNER JOIN user_sessions USING (user_session_id)
+                ON CONFLICT DO NOTHING
+            "#,
+            &ids,
+            &last_activities,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
         Ok(())
     }
 }
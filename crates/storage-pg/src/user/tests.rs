@@ -4,14 +4,18 @@
 // SPDX-License-Identifier: AGPL-3.0-only
 // Please see LICENSE in the repository root for full details.
 
+use std::net::IpAddr;
+
 use chrono::Duration;
+use mas_data_model::UserAgent;
 use mas_storage::{
     clock::MockClock,
     user::{
         BrowserSessionFilter, BrowserSessionRepository, UserEmailFilter, UserEmailRepository,
-        UserFilter, UserPasswordRepository, UserRepository,
+        UserFilter, UserLoginLockoutRepository, UserPasswordRepository,
+        UserRegistrationTokenFilter, UserRegistrationTokenRepository, UserRepository,
     },
-    Pagination, RepositoryAccess,
+    Clock, Pagination, RepositoryAccess,
 };
 use rand::SeedableRng;
 use rand_chacha::ChaChaRng;
@@ -659,6 +663,76 @@ async fn test_user_session(pool: PgPool) {
     assert_eq!(repo.browser_session().count(all_bob).await.unwrap(), 5);
     assert_eq!(repo.browser_session().count(active_bob).await.unwrap(), 0);
     assert_eq!(repo.browser_session().count(finished).await.unwrap(), 11);
+
+    // Filter sessions by user agent and last active IP
+    let user_agent = UserAgent::parse(
+        "Mozilla/5.0 (X11; Linux x86_64; rv:131.0) Gecko/20100101 Firefox/131.0".to_owned(),
+    );
+    let session = repo
+        .browser_session()
+        .add(&mut rng, &clock, &alice, Some(user_agent.clone()))
+        .await
+        .unwrap();
+    let ip = IpAddr::from([192_u8, 0, 2, 1]);
+    repo.browser_session()
+        .record_batch_activity(vec![(session.id, clock.now(), Some(ip))])
+        .await
+        .unwrap();
+
+    let with_user_agent = all_alice.with_user_agent(&user_agent.raw);
+    let with_ip = all_alice.with_last_active_ip(ip);
+    let with_other_ip = all_alice.with_last_active_ip(IpAddr::from([192_u8, 0, 2, 2]));
+    assert_eq!(
+        repo.browser_session().count(with_user_agent).await.unwrap(),
+        1
+    );
+    assert_eq!(repo.browser_session().count(with_ip).await.unwrap(), 1);
+    assert_eq!(
+        repo.browser_session().count(with_other_ip).await.unwrap(),
+        0
+    );
+    assert_eq!(
+        repo.browser_session()
+            .count(all_bob.with_user_agent(&user_agent.raw))
+            .await
+            .unwrap(),
+        0
+    );
+
+    // Filter sessions by creation time and inactivity
+    clock.advance(Duration::minutes(1));
+    let fresh = repo
+        .browser_session()
+        .add(&mut rng, &clock, &alice, None)
+        .await
+        .unwrap();
+    let created_before = active_alice.with_created_before(clock.now());
+    let inactive_since = active_alice.with_inactive_since(clock.now());
+    assert_eq!(repo.browser_session().count(active_alice).await.unwrap(), 2);
+    assert_eq!(
+        repo.browser_session().count(created_before).await.unwrap(),
+        1
+    );
+    assert_eq!(
+        repo.browser_session().count(inactive_since).await.unwrap(),
+        1
+    );
+
+    // Sessions which were never used count as inactive since their creation
+    clock.advance(Duration::minutes(1));
+    repo.browser_session()
+        .record_batch_activity(vec![(session.id, clock.now(), None)])
+        .await
+        .unwrap();
+    clock.advance(Duration::minutes(1));
+    let inactive_since = active_alice.with_inactive_since(clock.now() - Duration::seconds(90));
+    let list = repo
+        .browser_session()
+        .list(inactive_since, Pagination::first(10))
+        .await
+        .unwrap();
+    assert_eq!(list.edges.len(), 1);
+    assert_eq!(list.edges[0].id, fresh.id);
 }
 
 #[sqlx::test(migrator = "crate::MIGRATOR")]
@@ -715,3 +789,241 @@ async fn test_user_terms(pool: PgPool) {
         .unwrap();
     assert_eq!(res, 2);
 }
+
+#[sqlx::test(migrator = "crate::MIGRATOR")]
+async fn test_user_login_lockout(pool: PgPool) {
+    let mut repo = PgRepository::from_pool(&pool).await.unwrap();
+    let mut rng = ChaChaRng::seed_from_u64(42);
+    let clock = MockClock::default();
+    let window = Duration::minutes(15);
+
+    let user = repo
+        .user()
+        .add(&mut rng, &clock, "john".to_owned())
+        .await
+        .unwrap();
+
+    // There are no failed attempts yet
+    assert!(repo
+        .user_login_lockout()
+        .lookup(&user)
+        .await
+        .unwrap()
+        .is_none());
+
+    // Record a few failed attempts
+    for expected in 1..=3 {
+        let lockout = repo
+            .user_login_lockout()
+            .record_failure(&clock, &user, window)
+            .await
+            .unwrap();
+        assert_eq!(lockout.failed_attempts, expected);
+        assert_eq!(lockout.last_failed_at, clock.now());
+        assert!(!lockout.is_locked(clock.now()));
+        clock.advance(Duration::minutes(1));
+    }
+
+    // Lock the account
+    let lockout = repo
+        .user_login_lockout()
+        .lookup(&user)
+        .await
+        .unwrap()
+        .unwrap();
+    let locked_until = clock.now() + Duration::minutes(10);
+    let lockout = repo
+        .user_login_lockout()
+        .lock(lockout, locked_until)
+        .await
+        .unwrap();
+    assert!(lockout.is_locked(clock.now()));
+
+    let lockout = repo
+        .user_login_lockout()
+        .lookup(&user)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(lockout.locked_until, Some(locked_until));
+    assert!(lockout.is_locked(clock.now()));
+
+    // Once the lockout expired, failed attempts are counted from scratch
+    clock.advance(Duration::minutes(11));
+    assert!(!lockout.is_locked(clock.now()));
+    let lockout = repo
+        .user_login_lockout()
+        .record_failure(&clock, &user, window)
+        .await
+        .unwrap();
+    assert_eq!(lockout.failed_attempts, 1);
+    assert_eq!(lockout.locked_until, None);
+
+    // Same if the last failed attempt is out of the window
+    clock.advance(Duration::minutes(16));
+    let lockout = repo
+        .user_login_lockout()
+        .record_failure(&clock, &user, window)
+        .await
+        .unwrap();
+    assert_eq!(lockout.failed_attempts, 1);
+
+    // Clearing removes the failed attempts
+    assert!(repo.user_login_lockout().clear(&user).await.unwrap());
+    assert!(repo
+        .user_login_lockout()
+        .lookup(&user)
+        .await
+        .unwrap()
+        .is_none());
+    assert!(!repo.user_login_lockout().clear(&user).await.unwrap());
+}
+
+#[sqlx::test(migrator = "crate::MIGRATOR")]
+async fn test_user_registration_token(pool: PgPool) {
+    let mut repo = PgRepository::from_pool(&pool).await.unwrap().boxed();
+    let mut rng = ChaChaRng::seed_from_u64(42);
+    let clock = MockClock::default();
+
+    let all = UserRegistrationTokenFilter::new(clock.now());
+    let revoked = all.with_revoked(true);
+    let exhausted = all.with_exhausted(true);
+    let usable = all
+        .with_revoked(false)
+        .with_expired(false)
+        .with_exhausted(false);
+
+    assert!(repo
+        .user_registration_token()
+        .find_by_token("invite")
+        .await
+        .unwrap()
+        .is_none());
+    assert_eq!(repo.user_registration_token().count(all).await.unwrap(), 0);
+
+    // Create a token which can be used once
+    let token = repo
+        .user_registration_token()
+        .add(&mut rng, &clock, "invite".to_owned(), Some(1), None)
+        .await
+        .unwrap();
+    assert!(token.is_valid(clock.now()));
+
+    let token_lookup = repo
+        .user_registration_token()
+        .find_by_token("invite")
+        .await
+        .unwrap()
+        .expect("token not found");
+    assert_eq!(token_lookup, token);
+
+    // And one which expires in an hour
+    let expiring = repo
+        .user_registration_token()
+        .add(
+            &mut rng,
+            &clock,
+            "expiring".to_owned(),
+            None,
+            Some(clock.now() + Duration::try_hours(1).unwrap()),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(repo.user_registration_token().count(all).await.unwrap(), 2);
+    assert_eq!(
+        repo.user_registration_token().count(usable).await.unwrap(),
+        2
+    );
+
+    // Register a user with the first token
+    let alice = repo
+        .user()
+        .add(&mut rng, &clock, "alice".to_owned())
+        .await
+        .unwrap();
+    let token = repo
+        .user_registration_token()
+        .use_token(&clock, token, &alice)
+        .await
+        .unwrap()
+        .expect("token should be usable");
+    assert_eq!(token.times_used, 1);
+    assert_eq!(token.pending_uses, 1);
+    assert_eq!(token.last_used_at, Some(clock.now()));
+    assert!(token.is_exhausted());
+
+    // Verifying an email address clears the pending use
+    let email = repo
+        .user_email()
+        .add(&mut rng, &clock, &alice, "alice@example.com".to_owned())
+        .await
+        .unwrap();
+    repo.user_email()
+        .mark_as_verified(&clock, email)
+        .await
+        .unwrap();
+    let token = repo
+        .user_registration_token()
+        .lookup(token.id)
+        .await
+        .unwrap()
+        .expect("token not found");
+    assert_eq!(token.pending_uses, 0);
+
+    // The token can't be used anymore
+    let bob = repo
+        .user()
+        .add(&mut rng, &clock, "bob".to_owned())
+        .await
+        .unwrap();
+    assert!(repo
+        .user_registration_token()
+        .use_token(&clock, token.clone(), &bob)
+        .await
+        .unwrap()
+        .is_none());
+    assert_eq!(
+        repo.user_registration_token()
+            .count(exhausted)
+            .await
+            .unwrap(),
+        1
+    );
+
+    // Once expired, the second token can't be used either
+    clock.advance(Duration::try_hours(2).unwrap());
+    let expired = UserRegistrationTokenFilter::new(clock.now()).with_expired(true);
+    assert_eq!(
+        repo.user_registration_token().count(expired).await.unwrap(),
+        1
+    );
+    assert!(!expiring.is_valid(clock.now()));
+    assert!(repo
+        .user_registration_token()
+        .use_token(&clock, expiring.clone(), &bob)
+        .await
+        .unwrap()
+        .is_none());
+
+    // Revoke the first token
+    let token = repo
+        .user_registration_token()
+        .revoke(&clock, token)
+        .await
+        .unwrap();
+    assert!(token.revoked_at.is_some());
+    assert_eq!(
+        repo.user_registration_token().count(revoked).await.unwrap(),
+        1
+    );
+
+    let page = repo
+        .user_registration_token()
+        .list(all, Pagination::first(10))
+        .await
+        .unwrap();
+    assert_eq!(page.edges.len(), 2);
+    assert_eq!(page.edges[0].id, token.id);
+    assert_eq!(page.edges[1].id, expiring.id);
+}
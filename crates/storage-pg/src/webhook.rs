@@ -0,0 +1,409 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+//! A module containing the PostgreSQL implementation of the webhook delivery
+//! repository
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use mas_data_model::{WebhookDelivery, WebhookEndpoint, WebhookEventKind};
+use mas_storage::{webhook::WebhookDeliveryRepository, Clock};
+use rand::RngCore;
+use sqlx::{types::Json, PgConnection};
+use ulid::Ulid;
+use uuid::Uuid;
+
+use crate::{tracing::ExecuteExt, DatabaseError, DatabaseInconsistencyError};
+
+/// An implementation of [`WebhookDeliveryRepository`] for a PostgreSQL
+/// connection
+pub struct PgWebhookDeliveryRepository<'c> {
+    conn: &'c mut PgConnection,
+}
+
+impl<'c> PgWebhookDeliveryRepository<'c> {
+    /// Create a new [`PgWebhookDeliveryRepository`] from an active PostgreSQL
+    /// connection
+    pub fn new(conn: &'c mut PgConnection) -> Self {
+        Self { conn }
+    }
+}
+
+struct WebhookDeliveryLookup {
+    webhook_delivery_id: Uuid,
+    endpoint_id: Uuid,
+    event_kind: String,
+    payload: Json<serde_json::Value>,
+    created_at: DateTime<Utc>,
+    attempts: i32,
+    last_attempted_at: Option<DateTime<Utc>>,
+    last_error: Option<String>,
+    delivered_at: Option<DateTime<Utc>>,
+    failed_at: Option<DateTime<Utc>>,
+}
+
+impl TryFrom<WebhookDeliveryLookup> for WebhookDelivery {
+    type Error = DatabaseInconsistencyError;
+
+    fn try_from(value: WebhookDeliveryLookup) -> Result<Self, Self::Error> {
+        let id = Ulid::from(value.webhook_delivery_id);
+        let kind: WebhookEventKind = value.event_kind.parse().map_err(|e| {
+            DatabaseInconsistencyError::on("webhook_deliveries")
+                .column("event_kind")
+                .row(id)
+                .source(e)
+        })?;
+
+        let attempts = value.attempts.try_into().map_err(|e| {
+            DatabaseInconsistencyError::on("webhook_deliveries")
+                .column("attempts")
+                .row(id)
+                .source(e)
+        })?;
+
+        Ok(WebhookDelivery {
+            id,
+            endpoint_id: Ulid::from(value.endpoint_id),
+            kind,
+            payload: value.payload.0,
+            created_at: value.created_at,
+            attempts,
+            last_attempted_at: value.last_attempted_at,
+            last_error: value.last_error,
+            delivered_at: value.delivered_at,
+            failed_at: value.failed_at,
+        })
+    }
+}
+
+#[async_trait]
+impl<'c> WebhookDeliveryRepository for PgWebhookDeliveryRepository<'c> {
+    type Error = DatabaseError;
+
+    #[tracing::instrument(
+        name = "db.webhook_delivery.lookup",
+        skip_all,
+        fields(
+            db.query.text,
+            webhook_delivery.id = %id,
+        ),
+        err,
+    )]
+    async fn lookup(&mut self, id: Ulid) -> Result<Option<WebhookDelivery>, Self::Error> {
+        let res = sqlx::query_as!(
+            WebhookDeliveryLookup,
+            r#"
+                SELECT webhook_delivery_id
+                     , endpoint_id
+                     , event_kind
+                     , payload as "payload: Json<serde_json::Value>"
+                     , created_at
+                     , attempts
+                     , last_attempted_at
+                     , last_error
+                     , delivered_at
+                     , failed_at
+                FROM webhook_deliveries
+                WHERE webhook_delivery_id = $1
+            "#,
+            Uuid::from(id),
+        )
+        .traced()
+        .fetch_optional(&mut *self.conn)
+        .await?;
+
+        let Some(res) = res else { return Ok(None) };
+
+        Ok(Some(res.try_into()?))
+    }
+
+    #[tracing::instrument(
+        name = "db.webhook_delivery.add",
+        skip_all,
+        fields(
+            db.query.text,
+            webhook_delivery.id,
+            webhook_delivery.kind = %kind,
+            webhook_endpoint.id = %endpoint.id,
+        ),
+        err,
+    )]
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        endpoint: &WebhookEndpoint,
+        kind: WebhookEventKind,
+        payload: serde_json::Value,
+    ) -> Result<WebhookDelivery, Self::Error> {
+        let created_at = clock.now();
+        let id = Ulid::from_datetime_with_source(created_at.into(), rng);
+        tracing::Span::current().record("webhook_delivery.id", tracing::field::display(id));
+
+        sqlx::query!(
+            r#"
+                INSERT INTO webhook_deliveries
+                    ( webhook_delivery_id
+                    , endpoint_id
+                    , event_kind
+                    , payload
+                    , created_at
+                    )
+                VALUES ($1, $2, $3, $4, $5)
+            "#,
+            Uuid::from(id),
+            Uuid::from(endpoint.id),
+            kind.as_str(),
+            payload,
+            created_at,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(WebhookDelivery {
+            id,
+            endpoint_id: endpoint.id,
+            kind,
+            payload,
+            created_at,
+            attempts: 0,
+            last_attempted_at: None,
+            last_error: None,
+            delivered_at: None,
+            failed_at: None,
+        })
+    }
+
+    #[tracing::instrument(
+        name = "db.webhook_delivery.record_failed_attempt",
+        skip_all,
+        fields(
+            db.query.text,
+            %webhook_delivery.id,
+        ),
+        err,
+    )]
+    async fn record_failed_attempt(
+        &mut self,
+        clock: &dyn Clock,
+        mut webhook_delivery: WebhookDelivery,
+        error: String,
+    ) -> Result<WebhookDelivery, Self::Error> {
+        let last_attempted_at = clock.now();
+
+        let res = sqlx::query!(
+            r#"
+                UPDATE webhook_deliveries
+                SET attempts = attempts + 1
+                  , last_attempted_at = $2
+                  , last_error = $3
+                WHERE webhook_delivery_id = $1
+            "#,
+            Uuid::from(webhook_delivery.id),
+            last_attempted_at,
+            &error,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        webhook_delivery.attempts += 1;
+        webhook_delivery.last_attempted_at = Some(last_attempted_at);
+        webhook_delivery.last_error = Some(error);
+
+        Ok(webhook_delivery)
+    }
+
+    #[tracing::instrument(
+        name = "db.webhook_delivery.mark_as_delivered",
+        skip_all,
+        fields(
+            db.query.text,
+            %webhook_delivery.id,
+        ),
+        err,
+    )]
+    async fn mark_as_delivered(
+        &mut self,
+        clock: &dyn Clock,
+        mut webhook_delivery: WebhookDelivery,
+    ) -> Result<WebhookDelivery, Self::Error> {
+        let delivered_at = clock.now();
+
+        let res = sqlx::query!(
+            r#"
+                UPDATE webhook_deliveries
+                SET attempts = attempts + 1
+                  , last_attempted_at = $2
+                  , delivered_at = $2
+                WHERE webhook_delivery_id = $1
+            "#,
+            Uuid::from(webhook_delivery.id),
+            delivered_at,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        webhook_delivery.attempts += 1;
+        webhook_delivery.last_attempted_at = Some(delivered_at);
+        webhook_delivery.delivered_at = Some(delivered_at);
+
+        Ok(webhook_delivery)
+    }
+
+    #[tracing::instrument(
+        name = "db.webhook_delivery.mark_as_failed",
+        skip_all,
+        fields(
+            db.query.text,
+            %webhook_delivery.id,
+        ),
+        err,
+    )]
+    async fn mark_as_failed(
+        &mut self,
+        clock: &dyn Clock,
+        mut webhook_delivery: WebhookDelivery,
+    ) -> Result<WebhookDelivery, Self::Error> {
+        let failed_at = clock.now();
+
+        let res = sqlx::query!(
+            r#"
+                UPDATE webhook_deliveries
+                SET failed_at = $2
+                WHERE webhook_delivery_id = $1
+            "#,
+            Uuid::from(webhook_delivery.id),
+            failed_at,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        webhook_delivery.failed_at = Some(failed_at);
+
+        Ok(webhook_delivery)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use mas_data_model::{WebhookEndpoint, WebhookEventKind, WebhookSigning};
+    use mas_storage::{clock::MockClock, Clock, RepositoryAccess};
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+    use sqlx::PgPool;
+    use ulid::Ulid;
+
+    use crate::PgRepository;
+
+    #[sqlx::test(migrator = "crate::MIGRATOR")]
+    async fn test_webhook_delivery_repo(pool: PgPool) {
+        let mut rng = ChaChaRng::seed_from_u64(42);
+        let clock = MockClock::default();
+        let mut repo = PgRepository::from_pool(&pool).await.unwrap();
+
+        let endpoint = WebhookEndpoint {
+            id: Ulid::from_datetime_with_source(clock.now().into(), &mut rng),
+            url: "https://crm.example.com/hooks/mas".parse().unwrap(),
+            events: BTreeSet::new(),
+            signing: WebhookSigning::Hmac {
+                secret: "hunter2".to_owned(),
+            },
+        };
+
+        let payload = serde_json::json!({ "username": "alice" });
+        let delivery = repo
+            .webhook_delivery()
+            .add(
+                &mut rng,
+                &clock,
+                &endpoint,
+                WebhookEventKind::UserRegistered,
+                payload.clone(),
+            )
+            .await
+            .unwrap();
+        assert!(delivery.is_pending());
+        assert_eq!(delivery.endpoint_id, endpoint.id);
+        assert_eq!(delivery.attempts, 0);
+
+        // Lookup the delivery
+        let lookup = repo
+            .webhook_delivery()
+            .lookup(delivery.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(lookup, delivery);
+
+        // Lookup an unknown delivery
+        assert!(repo
+            .webhook_delivery()
+            .lookup(Ulid::nil())
+            .await
+            .unwrap()
+            .is_none());
+
+        // Record a failed attempt
+        let delivery = repo
+            .webhook_delivery()
+            .record_failed_attempt(&clock, delivery, "HTTP 503".to_owned())
+            .await
+            .unwrap();
+        assert!(delivery.is_pending());
+        assert_eq!(delivery.attempts, 1);
+        assert_eq!(delivery.last_error.as_deref(), Some("HTTP 503"));
+
+        // Then a successful one
+        let delivery = repo
+            .webhook_delivery()
+            .mark_as_delivered(&clock, delivery)
+            .await
+            .unwrap();
+        assert!(!delivery.is_pending());
+        assert_eq!(delivery.attempts, 2);
+        assert_eq!(delivery.delivered_at, Some(clock.now()));
+
+        let lookup = repo
+            .webhook_delivery()
+            .lookup(delivery.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(lookup, delivery);
+
+        // Give up on another delivery
+        let delivery = repo
+            .webhook_delivery()
+            .add(
+                &mut rng,
+                &clock,
+                &endpoint,
+                WebhookEventKind::UserDeactivated,
+                payload,
+            )
+            .await
+            .unwrap();
+        let delivery = repo
+            .webhook_delivery()
+            .mark_as_failed(&clock, delivery)
+            .await
+            .unwrap();
+        assert!(!delivery.is_pending());
+        assert_eq!(delivery.failed_at, Some(clock.now()));
+        assert!(delivery.delivered_at.is_none());
+    }
+}
@@ -6,6 +6,8 @@
 
 //! Repositories to interact with all kinds of sessions
 
+use std::net::IpAddr;
+
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use mas_data_model::{BrowserSession, CompatSession, Device, Session, User};
@@ -54,6 +56,8 @@ pub struct AppSessionFilter<'a> {
     device_id: Option<&'a Device>,
     last_active_before: Option<DateTime<Utc>>,
     last_active_after: Option<DateTime<Utc>>,
+    user_agent: Option<&'a str>,
+    last_active_ip: Option<IpAddr>,
 }
 
 impl<'a> AppSessionFilter<'a> {
@@ -132,6 +136,36 @@ impl<'a> AppSessionFilter<'a> {
         self.last_active_after
     }
 
+    /// Only return sessions with the given raw user agent
+    #[must_use]
+    pub fn with_user_agent(mut self, user_agent: &'a str) -> Self {
+        self.user_agent = Some(user_agent);
+        self
+    }
+
+    /// Get the user agent filter
+    ///
+    /// Returns [`None`] if no user agent filter was set
+    #[must_use]
+    pub fn user_agent(&self) -> Option<&'a str> {
+        self.user_agent
+    }
+
+    /// Only return sessions which were last active from the given IP address
+    #[must_use]
+    pub fn with_last_active_ip(mut self, last_active_ip: IpAddr) -> Self {
+        self.last_active_ip = Some(last_active_ip);
+        self
+    }
+
+    /// Get the last active IP filter
+    ///
+    /// Returns [`None`] if no IP filter was set
+    #[must_use]
+    pub fn last_active_ip(&self) -> Option<IpAddr> {
+        self.last_active_ip
+    }
+
     /// Only return active compatibility sessions
     #[must_use]
     pub fn active_only(mut self) -> Self {
@@ -0,0 +1,354 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+//! Repository to record and query the security audit log
+
+use std::net::IpAddr;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use mas_data_model::{AuditLogEvent, AuditLogEventKind, Session, User, UserAgent};
+use rand_core::RngCore;
+use ulid::Ulid;
+
+use crate::{repository_impl, Clock, Page, Pagination};
+
+/// An event to record in the audit log
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewAuditLogEvent {
+    kind: AuditLogEventKind,
+    actor_user_id: Option<Ulid>,
+    actor_session_id: Option<Ulid>,
+    user_id: Option<Ulid>,
+    ip_address: Option<IpAddr>,
+    user_agent: Option<String>,
+    data: serde_json::Value,
+}
+
+impl NewAuditLogEvent {
+    /// Create a new event of the given kind, with no actor, subject or
+    /// details
+    #[must_use]
+    pub fn new(kind: AuditLogEventKind) -> Self {
+        Self {
+            kind,
+            actor_user_id: None,
+            actor_session_id: None,
+            user_id: None,
+            ip_address: None,
+            user_agent: None,
+            data: serde_json::Value::Object(serde_json::Map::new()),
+        }
+    }
+
+    /// Set the user who did the action
+    #[must_use]
+    pub fn with_actor(mut self, user: &User) -> Self {
+        self.actor_user_id = Some(user.id);
+        self
+    }
+
+    /// Set the OAuth 2.0 session which did the action. This also sets the
+    /// actor user if the session belongs to one.
+    #[must_use]
+    pub fn with_actor_session(mut self, session: &Session) -> Self {
+        self.actor_session_id = Some(session.id);
+        if session.user_id.is_some() {
+            self.actor_user_id = session.user_id;
+        }
+        self
+    }
+
+    /// Set the user affected by the action
+    #[must_use]
+    pub fn for_user(mut self, user: &User) -> Self {
+        self.user_id = Some(user.id);
+        self
+    }
+
+    /// Set the ID of the user affected by the action
+    #[must_use]
+    pub fn for_user_id(mut self, user_id: Ulid) -> Self {
+        self.user_id = Some(user_id);
+        self
+    }
+
+    /// Set the IP address from which the request was made
+    #[must_use]
+    pub fn with_ip_address(mut self, ip_address: Option<IpAddr>) -> Self {
+        self.ip_address = ip_address;
+        self
+    }
+
+    /// Set the user agent which made the request
+    #[must_use]
+    pub fn with_user_agent(mut self, user_agent: Option<&UserAgent>) -> Self {
+        self.user_agent = user_agent.map(|ua| ua.raw.clone());
+        self
+    }
+
+    /// Set additional details about the event
+    #[must_use]
+    pub fn with_data(mut self, data: serde_json::Value) -> Self {
+        self.data = data;
+        self
+    }
+
+    /// The kind of event
+    #[must_use]
+    pub fn kind(&self) -> AuditLogEventKind {
+        self.kind
+    }
+
+    /// The ID of the user who did the action
+    #[must_use]
+    pub fn actor_user_id(&self) -> Option<Ulid> {
+        self.actor_user_id
+    }
+
+    /// The ID of the OAuth 2.0 session which did the action
+    #[must_use]
+    pub fn actor_session_id(&self) -> Option<Ulid> {
+        self.actor_session_id
+    }
+
+    /// The ID of the user affected by the action
+    #[must_use]
+    pub fn user_id(&self) -> Option<Ulid> {
+        self.user_id
+    }
+
+    /// The IP address from which the request was made
+    #[must_use]
+    pub fn ip_address(&self) -> Option<IpAddr> {
+        self.ip_address
+    }
+
+    /// The user agent which made the request
+    #[must_use]
+    pub fn user_agent(&self) -> Option<&str> {
+        self.user_agent.as_deref()
+    }
+
+    /// Additional details about the event
+    #[must_use]
+    pub fn data(&self) -> &serde_json::Value {
+        &self.data
+    }
+}
+
+/// Filter parameters for listing audit log events
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct AuditLogFilter<'a> {
+    kind: Option<AuditLogEventKind>,
+    user: Option<&'a User>,
+    actor: Option<&'a User>,
+    actor_session: Option<&'a Session>,
+    created_before: Option<DateTime<Utc>>,
+    created_after: Option<DateTime<Utc>>,
+}
+
+impl<'a> AuditLogFilter<'a> {
+    /// Create a new [`AuditLogFilter`] with default values
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Filter for events of the given kind
+    #[must_use]
+    pub fn with_kind(mut self, kind: AuditLogEventKind) -> Self {
+        self.kind = Some(kind);
+        self
+    }
+
+    /// Get the kind filter
+    ///
+    /// Returns [`None`] if no kind filter was set
+    #[must_use]
+    pub fn kind(&self) -> Option<AuditLogEventKind> {
+        self.kind
+    }
+
+    /// Filter for events affecting the given user
+    #[must_use]
+    pub fn for_user(mut self, user: &'a User) -> Self {
+        self.user = Some(user);
+        self
+    }
+
+    /// Get the user filter
+    ///
+    /// Returns [`None`] if no user filter was set
+    #[must_use]
+    pub fn user(&self) -> Option<&'a User> {
+        self.user
+    }
+
+    /// Filter for events done by the given user
+    #[must_use]
+    pub fn by_actor(mut self, actor: &'a User) -> Self {
+        self.actor = Some(actor);
+        self
+    }
+
+    /// Get the actor filter
+    ///
+    /// Returns [`None`] if no actor filter was set
+    #[must_use]
+    pub fn actor(&self) -> Option<&'a User> {
+        self.actor
+    }
+
+    /// Filter for events done through the given OAuth 2.0 session
+    #[must_use]
+    pub fn by_actor_session(mut self, session: &'a Session) -> Self {
+        self.actor_session = Some(session);
+        self
+    }
+
+    /// Get the actor session filter
+    ///
+    /// Returns [`None`] if no actor session filter was set
+    #[must_use]
+    pub fn actor_session(&self) -> Option<&'a Session> {
+        self.actor_session
+    }
+
+    /// Only return events recorded before the given time
+    #[must_use]
+    pub fn with_created_before(mut self, created_before: DateTime<Utc>) -> Self {
+        self.created_before = Some(created_before);
+        self
+    }
+
+    /// Get the created before filter
+    ///
+    /// Returns [`None`] if no created before filter was set
+    #[must_use]
+    pub fn created_before(&self) -> Option<DateTime<Utc>> {
+        self.created_before
+    }
+
+    /// Only return events recorded after the given time
+    #[must_use]
+    pub fn with_created_after(mut self, created_after: DateTime<Utc>) -> Self {
+        self.created_after = Some(created_after);
+        self
+    }
+
+    /// Get the created after filter
+    ///
+    /// Returns [`None`] if no created after filter was set
+    #[must_use]
+    pub fn created_after(&self) -> Option<DateTime<Utc>> {
+        self.created_after
+    }
+}
+
+/// An [`AuditLogRepository`] helps recording and querying security-relevant
+/// events in the audit log
+///
+/// The audit log is append-only: events can't be modified, and are only
+/// removed once they are older than the retention period.
+#[async_trait]
+pub trait AuditLogRepository: Send + Sync {
+    /// The error type returned by the repository
+    type Error;
+
+    /// Lookup an event by its ID
+    ///
+    /// Returns `None` if no event was found
+    ///
+    /// # Parameters
+    ///
+    /// * `id`: The ID of the event to lookup
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn lookup(&mut self, id: Ulid) -> Result<Option<AuditLogEvent>, Self::Error>;
+
+    /// Record an event in the audit log
+    ///
+    /// Returns the recorded event
+    ///
+    /// # Parameters
+    ///
+    /// * `rng`: The random number generator to use
+    /// * `clock`: The clock used to generate timestamps
+    /// * `event`: The event to record
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        event: NewAuditLogEvent,
+    ) -> Result<AuditLogEvent, Self::Error>;
+
+    /// List events matching the given filter, ordered by ID
+    ///
+    /// # Parameters
+    ///
+    /// * `filter`: The filter parameters
+    /// * `pagination`: The pagination parameters
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn list(
+        &mut self,
+        filter: AuditLogFilter<'_>,
+        pagination: Pagination,
+    ) -> Result<Page<AuditLogEvent>, Self::Error>;
+
+    /// Count the events matching the given filter
+    ///
+    /// # Parameters
+    ///
+    /// * `filter`: The filter parameters
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn count(&mut self, filter: AuditLogFilter<'_>) -> Result<usize, Self::Error>;
+
+    /// Remove the events recorded before the given time
+    ///
+    /// Returns the number of events removed
+    ///
+    /// # Parameters
+    ///
+    /// * `before`: Events recorded before this time are removed
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn prune(&mut self, before: DateTime<Utc>) -> Result<usize, Self::Error>;
+}
+
+repository_impl!(AuditLogRepository:
+    async fn lookup(&mut self, id: Ulid) -> Result<Option<AuditLogEvent>, Self::Error>;
+
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        event: NewAuditLogEvent,
+    ) -> Result<AuditLogEvent, Self::Error>;
+
+    async fn list(
+        &mut self,
+        filter: AuditLogFilter<'_>,
+        pagination: Pagination,
+    ) -> Result<Page<AuditLogEvent>, Self::Error>;
+
+    async fn count(&mut self, filter: AuditLogFilter<'_>) -> Result<usize, Self::Error>;
+
+    async fn prune(&mut self, before: DateTime<Utc>) -> Result<usize, Self::Error>;
+);
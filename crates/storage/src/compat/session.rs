@@ -64,6 +64,10 @@ pub struct CompatSessionFilter<'a> {
     device: Option<&'a Device>,
     last_active_before: Option<DateTime<Utc>>,
     last_active_after: Option<DateTime<Utc>>,
+    user_agent: Option<&'a str>,
+    last_active_ip: Option<IpAddr>,
+    created_before: Option<DateTime<Utc>>,
+    inactive_since: Option<DateTime<Utc>>,
 }
 
 impl<'a> CompatSessionFilter<'a> {
@@ -142,6 +146,69 @@ impl<'a> CompatSessionFilter<'a> {
         self.last_active_after
     }
 
+    /// Only return sessions with the given raw user agent
+    #[must_use]
+    pub fn with_user_agent(mut self, user_agent: &'a str) -> Self {
+        self.user_agent = Some(user_agent);
+        self
+    }
+
+    /// Get the user agent filter
+    ///
+    /// Returns [`None`] if no user agent filter was set
+    #[must_use]
+    pub fn user_agent(&self) -> Option<&'a str> {
+        self.user_agent
+    }
+
+    /// Only return sessions which were last active from the given IP address
+    #[must_use]
+    pub fn with_last_active_ip(mut self, last_active_ip: IpAddr) -> Self {
+        self.last_active_ip = Some(last_active_ip);
+        self
+    }
+
+    /// Get the last active IP filter
+    ///
+    /// Returns [`None`] if no IP filter was set
+    #[must_use]
+    pub fn last_active_ip(&self) -> Option<IpAddr> {
+        self.last_active_ip
+    }
+
+    /// Only return sessions created before the given time
+    #[must_use]
+    pub fn with_created_before(mut self, created_before: DateTime<Utc>) -> Self {
+        self.created_before = Some(created_before);
+        self
+    }
+
+    /// Get the created before filter
+    ///
+    /// Returns [`None`] if no created before filter was set
+    #[must_use]
+    pub fn created_before(&self) -> Option<DateTime<Utc>> {
+        self.created_before
+    }
+
+    /// Only return sessions which were not active since the given time
+    ///
+    /// Sessions which were never active are considered inactive since they
+    /// were created.
+    #[must_use]
+    pub fn with_inactive_since(mut self, inactive_since: DateTime<Utc>) -> Self {
+        self.inactive_since = Some(inactive_since);
+        self
+    }
+
+    /// Get the inactive since filter
+    ///
+    /// Returns [`None`] if no inactive since filter was set
+    #[must_use]
+    pub fn inactive_since(&self) -> Option<DateTime<Utc>> {
+        self.inactive_since
+    }
+
     /// Only return active compatibility sessions
     #[must_use]
     pub fn active_only(mut self) -> Self {
@@ -10,6 +10,7 @@ use std::{num::ParseIntError, ops::Deref};
 
 pub use apalis_core::job::{Job, JobId};
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use opentelemetry::trace::{SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -21,6 +22,7 @@ use crate::repository_impl;
 pub struct JobSubmission {
     name: &'static str,
     payload: Value,
+    run_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -114,6 +116,7 @@ impl JobSubmission {
         Self {
             name: J::NAME,
             payload,
+            run_at: None,
         }
     }
 
@@ -133,6 +136,13 @@ impl JobSubmission {
         })
     }
 
+    /// Only run the job after the given time.
+    #[must_use]
+    pub fn with_run_at(mut self, run_at: DateTime<Utc>) -> Self {
+        self.run_at = Some(run_at);
+        self
+    }
+
     /// The name of the job.
     #[must_use]
     pub fn name(&self) -> &'static str {
@@ -144,6 +154,13 @@ impl JobSubmission {
     pub fn payload(&self) -> &Value {
         &self.payload
     }
+
+    /// The time after which the job should run, if it should not run right
+    /// away.
+    #[must_use]
+    pub fn run_at(&self) -> Option<DateTime<Utc>> {
+        self.run_at
+    }
 }
 
 /// A [`JobRepository`] is used to schedule jobs to be executed by a worker.
@@ -190,6 +207,22 @@ pub trait JobRepositoryExt {
         &mut self,
         job: J,
     ) -> Result<JobId, Self::Error>;
+
+    /// Schedule a job to be executed after the given time.
+    ///
+    /// # Parameters
+    ///
+    /// * `job` - The job to schedule.
+    /// * `run_at` - The time after which the job should run.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn schedule_job_at<J: Job + Serialize + Send>(
+        &mut self,
+        job: J,
+        run_at: DateTime<Utc>,
+    ) -> Result<JobId, Self::Error>;
 }
 
 #[async_trait]
@@ -218,12 +251,40 @@ where
         self.schedule_submission(JobSubmission::new_with_span_context(job, span_context))
             .await
     }
+
+    #[tracing::instrument(
+        name = "db.job.schedule_job_at",
+        skip_all,
+        fields(
+            job.name = J::NAME,
+            job.run_at = %run_at,
+        ),
+    )]
+    async fn schedule_job_at<J: Job + Serialize + Send>(
+        &mut self,
+        job: J,
+        run_at: DateTime<Utc>,
+    ) -> Result<JobId, Self::Error> {
+        let span = tracing::Span::current();
+        let ctx = span.context();
+        let span = ctx.span();
+        let span_context = span.span_context();
+
+        self.schedule_submission(
+            JobSubmission::new_with_span_context(job, span_context).with_run_at(run_at),
+        )
+        .await
+    }
 }
 
 mod jobs {
+    use std::net::IpAddr;
+
     // XXX: Move this somewhere else?
     use apalis_core::job::Job;
-    use mas_data_model::{Device, User, UserEmail, UserRecoverySession};
+    use mas_data_model::{
+        Device, User, UserEmail, UserRecoverySession, WebhookDelivery, WebhookEventKind,
+    };
     use serde::{Deserialize, Serialize};
     use ulid::Ulid;
 
@@ -506,9 +567,296 @@ mod jobs {
     impl Job for SendAccountRecoveryEmailsJob {
         const NAME: &'static str = "send-account-recovery-email";
     }
+
+    /// Tell a user that their account was locked out after too many failed
+    /// login attempts
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct SendLoginLockoutEmailJob {
+        user_id: Ulid,
+        language: Option<String>,
+    }
+
+    impl SendLoginLockoutEmailJob {
+        /// Create a new job to send the login lockout email
+        ///
+        /// # Parameters
+        ///
+        /// * `user` - The user whose account was locked out
+        #[must_use]
+        pub fn new(user: &User) -> Self {
+            Self {
+                user_id: user.id,
+                language: None,
+            }
+        }
+
+        /// Set the language to use for the email.
+        #[must_use]
+        pub fn with_language(mut self, language: String) -> Self {
+            self.language = Some(language);
+            self
+        }
+
+        /// The language to use for the email.
+        #[must_use]
+        pub fn language(&self) -> Option<&str> {
+            self.language.as_deref()
+        }
+
+        /// The ID of the user whose account was locked out
+        #[must_use]
+        pub fn user_id(&self) -> Ulid {
+            self.user_id
+        }
+    }
+
+    impl Job for SendLoginLockoutEmailJob {
+        const NAME: &'static str = "send-login-lockout-email";
+    }
+
+    /// The kind of session a [`SendNewSignInEmailJob`] or a
+    /// [`SendWebhookEventJob`] is about
+    #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+    #[serde(tag = "kind", content = "id", rename_all = "snake_case")]
+    pub enum NewSignInSession {
+        /// A browser session
+        Browser(Ulid),
+
+        /// A compatibility layer session
+        Compat(Ulid),
+
+        /// An OAuth 2.0 session
+        #[serde(rename = "oauth2")]
+        OAuth2(Ulid),
+    }
+
+    /// Tell a user that someone signed in to their account from an unfamiliar
+    /// device or location
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct SendNewSignInEmailJob {
+        user_id: Ulid,
+        session: NewSignInSession,
+        ip: Option<IpAddr>,
+        location: Option<String>,
+        language: Option<String>,
+    }
+
+    impl SendNewSignInEmailJob {
+        /// Create a new job to send the new sign-in email
+        ///
+        /// # Parameters
+        ///
+        /// * `user` - The user who signed in
+        /// * `session` - The session which was created
+        #[must_use]
+        pub fn new(user: &User, session: NewSignInSession) -> Self {
+            Self {
+                user_id: user.id,
+                session,
+                ip: None,
+                location: None,
+                language: None,
+            }
+        }
+
+        /// Set the IP address from which the user signed in
+        #[must_use]
+        pub fn with_ip(mut self, ip: IpAddr) -> Self {
+            self.ip = Some(ip);
+            self
+        }
+
+        /// Set the approximate location from which the user signed in
+        #[must_use]
+        pub fn with_location(mut self, location: String) -> Self {
+            self.location = Some(location);
+            self
+        }
+
+        /// Set the language to use for the email.
+        #[must_use]
+        pub fn with_language(mut self, language: String) -> Self {
+            self.language = Some(language);
+            self
+        }
+
+        /// The language to use for the email.
+        #[must_use]
+        pub fn language(&self) -> Option<&str> {
+            self.language.as_deref()
+        }
+
+        /// The ID of the user who signed in
+        #[must_use]
+        pub fn user_id(&self) -> Ulid {
+            self.user_id
+        }
+
+        /// The session which was created
+        #[must_use]
+        pub fn session(&self) -> NewSignInSession {
+            self.session
+        }
+
+        /// The IP address from which the user signed in
+        #[must_use]
+        pub fn ip(&self) -> Option<IpAddr> {
+            self.ip
+        }
+
+        /// The approximate location from which the user signed in
+        #[must_use]
+        pub fn location(&self) -> Option<&str> {
+            self.location.as_deref()
+        }
+    }
+
+    impl Job for SendNewSignInEmailJob {
+        const NAME: &'static str = "send-new-sign-in-email";
+    }
+
+    /// Send an event to the webhook endpoints which are interested in it
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct SendWebhookEventJob {
+        kind: WebhookEventKind,
+        data: serde_json::Value,
+    }
+
+    impl SendWebhookEventJob {
+        /// Create a new job to send an arbitrary event
+        ///
+        /// # Parameters
+        ///
+        /// * `kind` - The kind of event
+        /// * `data` - The details of the event
+        #[must_use]
+        pub fn new(kind: WebhookEventKind, data: serde_json::Value) -> Self {
+            Self { kind, data }
+        }
+
+        fn for_user(kind: WebhookEventKind, user: &User) -> Self {
+            Self::new(
+                kind,
+                serde_json::json!({
+                    "user_id": user.id,
+                    "username": user.username,
+                }),
+            )
+        }
+
+        fn for_session(kind: WebhookEventKind, user_id: Ulid, session: NewSignInSession) -> Self {
+            Self::new(
+                kind,
+                serde_json::json!({
+                    "user_id": user_id,
+                    "session": session,
+                }),
+            )
+        }
+
+        /// Create a new job to tell that a user registered
+        #[must_use]
+        pub fn user_registered(user: &User) -> Self {
+            Self::for_user(WebhookEventKind::UserRegistered, user)
+        }
+
+        /// Create a new job to tell that a user verified an email address
+        #[must_use]
+        pub fn user_email_verified(user: &User, user_email: &UserEmail) -> Self {
+            Self::new(
+                WebhookEventKind::UserEmailVerified,
+                serde_json::json!({
+                    "user_id": user.id,
+                    "username": user.username,
+                    "email": user_email.email,
+                }),
+            )
+        }
+
+        /// Create a new job to tell that a user was locked
+        #[must_use]
+        pub fn user_locked(user: &User) -> Self {
+            Self::for_user(WebhookEventKind::UserLocked, user)
+        }
+
+        /// Create a new job to tell that a user was deactivated
+        #[must_use]
+        pub fn user_deactivated(user: &User) -> Self {
+            Self::for_user(WebhookEventKind::UserDeactivated, user)
+        }
+
+        /// Create a new job to tell that a user started a session
+        ///
+        /// # Parameters
+        ///
+        /// * `user_id` - The ID of the user who owns the session
+        /// * `session` - The session which started
+        #[must_use]
+        pub fn session_started(user_id: Ulid, session: NewSignInSession) -> Self {
+            Self::for_session(WebhookEventKind::SessionStarted, user_id, session)
+        }
+
+        /// Create a new job to tell that a session of a user ended
+        ///
+        /// # Parameters
+        ///
+        /// * `user_id` - The ID of the user who owned the session
+        /// * `session` - The session which ended
+        #[must_use]
+        pub fn session_ended(user_id: Ulid, session: NewSignInSession) -> Self {
+            Self::for_session(WebhookEventKind::SessionEnded, user_id, session)
+        }
+
+        /// The kind of event
+        #[must_use]
+        pub fn kind(&self) -> WebhookEventKind {
+            self.kind
+        }
+
+        /// The details of the event
+        #[must_use]
+        pub fn data(&self) -> &serde_json::Value {
+            &self.data
+        }
+    }
+
+    impl Job for SendWebhookEventJob {
+        const NAME: &'static str = "send-webhook-event";
+    }
+
+    /// Attempt to deliver an event to a webhook endpoint
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct DeliverWebhookJob {
+        delivery_id: Ulid,
+    }
+
+    impl DeliverWebhookJob {
+        /// Create a new job to attempt a delivery
+        ///
+        /// # Parameters
+        ///
+        /// * `delivery` - The delivery to attempt
+        #[must_use]
+        pub fn new(delivery: &WebhookDelivery) -> Self {
+            Self {
+                delivery_id: delivery.id,
+            }
+        }
+
+        /// The ID of the delivery to attempt
+        #[must_use]
+        pub fn delivery_id(&self) -> Ulid {
+            self.delivery_id
+        }
+    }
+
+    impl Job for DeliverWebhookJob {
+        const NAME: &'static str = "deliver-webhook";
+    }
 }
 
 pub use self::jobs::{
-    DeactivateUserJob, DeleteDeviceJob, ProvisionDeviceJob, ProvisionUserJob, ReactivateUserJob,
-    SendAccountRecoveryEmailsJob, SyncDevicesJob, VerifyEmailJob,
+    DeactivateUserJob, DeleteDeviceJob, DeliverWebhookJob, NewSignInSession, ProvisionDeviceJob,
+    ProvisionUserJob, ReactivateUserJob, SendAccountRecoveryEmailsJob, SendLoginLockoutEmailJob,
+    SendNewSignInEmailJob, SendWebhookEventJob, SyncDevicesJob, VerifyEmailJob,
 };
@@ -117,11 +117,14 @@ pub(crate) mod repository;
 mod utils;
 
 pub mod app_session;
+pub mod audit_log;
 pub mod compat;
 pub mod job;
 pub mod oauth2;
 pub mod upstream_oauth2;
+pub mod usage_stats;
 pub mod user;
+pub mod webhook;
 
 pub use self::{
     clock::{Clock, SystemClock},
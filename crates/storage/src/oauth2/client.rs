@@ -4,7 +4,10 @@
 // SPDX-License-Identifier: AGPL-3.0-only
 // Please see LICENSE in the repository root for full details.
 
-use std::collections::{BTreeMap, BTreeSet};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    marker::PhantomData,
+};
 
 use async_trait::async_trait;
 use mas_data_model::{Client, User};
@@ -15,7 +18,49 @@ use rand_core::RngCore;
 use ulid::Ulid;
 use url::Url;
 
-use crate::{repository_impl, Clock};
+use crate::{pagination::Page, repository_impl, Clock, Pagination};
+
+/// Filter parameters for listing OAuth 2.0 clients
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct OAuth2ClientFilter<'a> {
+    /// Filter by whether the client is defined in the configuration
+    ///
+    /// If `None`, all clients are returned
+    is_static: Option<bool>,
+
+    _lifetime: PhantomData<&'a ()>,
+}
+
+impl<'a> OAuth2ClientFilter<'a> {
+    /// Create a new [`OAuth2ClientFilter`] with default values
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return only clients defined in the configuration
+    #[must_use]
+    pub const fn static_only(mut self) -> Self {
+        self.is_static = Some(true);
+        self
+    }
+
+    /// Return only clients which were registered dynamically or through the
+    /// admin API
+    #[must_use]
+    pub const fn dynamic_only(mut self) -> Self {
+        self.is_static = Some(false);
+        self
+    }
+
+    /// Get the static filter
+    ///
+    /// Returns `None` if the filter is not set
+    #[must_use]
+    pub const fn is_static(&self) -> Option<bool> {
+        self.is_static
+    }
+}
 
 /// An [`OAuth2ClientRepository`] helps interacting with [`Client`] saved in the
 /// storage backend
@@ -152,6 +197,82 @@ pub trait OAuth2ClientRepository: Send + Sync {
     /// Returns [`Self::Error`] if the underlying repository fails
     async fn all_static(&mut self) -> Result<Vec<Client>, Self::Error>;
 
+    /// List [`Client`]s with the given filter and pagination
+    ///
+    /// # Parameters
+    ///
+    /// * `filter`: The filter to apply
+    /// * `pagination`: The pagination parameters
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn list(
+        &mut self,
+        filter: OAuth2ClientFilter<'_>,
+        pagination: Pagination,
+    ) -> Result<Page<Client>, Self::Error>;
+
+    /// Count the [`Client`]s with the given filter
+    ///
+    /// # Parameters
+    ///
+    /// * `filter`: The filter to apply
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn count(&mut self, filter: OAuth2ClientFilter<'_>) -> Result<usize, Self::Error>;
+
+    /// Update the metadata of a [`Client`]
+    ///
+    /// Returns the updated client
+    ///
+    /// # Parameters
+    ///
+    /// * `client`: The client to update
+    /// * `redirect_uris`: The list of redirect URIs used by this client
+    /// * `grant_types`: The list of grant types this client can use
+    /// * `client_name`: The human-readable name of this client, if given
+    /// * `logo_uri`: The URI of the logo of this client, if given
+    /// * `client_uri`: The URI of a website of this client, if given
+    /// * `policy_uri`: The URI of the privacy policy of this client, if given
+    /// * `tos_uri`: The URI of the terms of service of this client, if given
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    #[allow(clippy::too_many_arguments)]
+    async fn update_metadata(
+        &mut self,
+        client: Client,
+        redirect_uris: Vec<Url>,
+        grant_types: Vec<GrantType>,
+        client_name: Option<String>,
+        logo_uri: Option<Url>,
+        client_uri: Option<Url>,
+        policy_uri: Option<Url>,
+        tos_uri: Option<Url>,
+    ) -> Result<Client, Self::Error>;
+
+    /// Replace the encrypted secret of a [`Client`]
+    ///
+    /// Returns the updated client
+    ///
+    /// # Parameters
+    ///
+    /// * `client`: The client to update
+    /// * `encrypted_client_secret`: The new encrypted client secret
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn set_encrypted_client_secret(
+        &mut self,
+        client: Client,
+        encrypted_client_secret: String,
+    ) -> Result<Client, Self::Error>;
+
     /// Get the list of scopes that the user has given consent for the given
     /// client
     ///
@@ -260,6 +381,32 @@ repository_impl!(OAuth2ClientRepository:
 
     async fn all_static(&mut self) -> Result<Vec<Client>, Self::Error>;
 
+    async fn list(
+        &mut self,
+        filter: OAuth2ClientFilter<'_>,
+        pagination: Pagination,
+    ) -> Result<Page<Client>, Self::Error>;
+
+    async fn count(&mut self, filter: OAuth2ClientFilter<'_>) -> Result<usize, Self::Error>;
+
+    async fn update_metadata(
+        &mut self,
+        client: Client,
+        redirect_uris: Vec<Url>,
+        grant_types: Vec<GrantType>,
+        client_name: Option<String>,
+        logo_uri: Option<Url>,
+        client_uri: Option<Url>,
+        policy_uri: Option<Url>,
+        tos_uri: Option<Url>,
+    ) -> Result<Client, Self::Error>;
+
+    async fn set_encrypted_client_secret(
+        &mut self,
+        client: Client,
+        encrypted_client_secret: String,
+    ) -> Result<Client, Self::Error>;
+
     async fn delete(&mut self, client: Client) -> Result<(), Self::Error>;
 
     async fn delete_by_id(&mut self, id: Ulid) -> Result<(), Self::Error>;
@@ -9,6 +9,7 @@ use thiserror::Error;
 
 use crate::{
     app_session::AppSessionRepository,
+    audit_log::AuditLogRepository,
     compat::{
         CompatAccessTokenRepository, CompatRefreshTokenRepository, CompatSessionRepository,
         CompatSsoLoginRepository,
@@ -22,10 +23,13 @@ use crate::{
         UpstreamOAuthLinkRepository, UpstreamOAuthProviderRepository,
         UpstreamOAuthSessionRepository,
     },
+    usage_stats::UsageStatsRepository,
     user::{
-        BrowserSessionRepository, UserEmailRepository, UserPasswordRepository,
-        UserRecoveryRepository, UserRepository, UserTermsRepository,
+        BrowserSessionRepository, UserEmailRepository, UserLoginLockoutRepository,
+        UserPasswordRepository, UserRecoveryRepository, UserRegistrationTokenRepository,
+        UserRepository, UserTermsRepository,
     },
+    webhook::WebhookDeliveryRepository,
 };
 
 /// A [`Repository`] helps interacting with the underlying storage backend.
@@ -132,6 +136,16 @@ pub trait RepositoryAccess: Send {
     /// Get an [`UserTermsRepository`]
     fn user_terms<'c>(&'c mut self) -> Box<dyn UserTermsRepository<Error = Self::Error> + 'c>;
 
+    /// Get an [`UserLoginLockoutRepository`]
+    fn user_login_lockout<'c>(
+        &'c mut self,
+    ) -> Box<dyn UserLoginLockoutRepository<Error = Self::Error> + 'c>;
+
+    /// Get an [`UserRegistrationTokenRepository`]
+    fn user_registration_token<'c>(
+        &'c mut self,
+    ) -> Box<dyn UserRegistrationTokenRepository<Error = Self::Error> + 'c>;
+
     /// Get a [`BrowserSessionRepository`]
     fn browser_session<'c>(
         &'c mut self,
@@ -191,6 +205,17 @@ pub trait RepositoryAccess: Send {
 
     /// Get a [`JobRepository`]
     fn job<'c>(&'c mut self) -> Box<dyn JobRepository<Error = Self::Error> + 'c>;
+
+    /// Get an [`AuditLogRepository`]
+    fn audit_log<'c>(&'c mut self) -> Box<dyn AuditLogRepository<Error = Self::Error> + 'c>;
+
+    /// Get a [`WebhookDeliveryRepository`]
+    fn webhook_delivery<'c>(
+        &'c mut self,
+    ) -> Box<dyn WebhookDeliveryRepository<Error = Self::Error> + 'c>;
+
+    /// Get an [`UsageStatsRepository`]
+    fn usage_stats<'c>(&'c mut self) -> Box<dyn UsageStatsRepository<Error = Self::Error> + 'c>;
 }
 
 /// Implementations of the [`RepositoryAccess`], [`RepositoryTransaction`] and
@@ -201,6 +226,7 @@ mod impls {
     use super::RepositoryAccess;
     use crate::{
         app_session::AppSessionRepository,
+        audit_log::AuditLogRepository,
         compat::{
             CompatAccessTokenRepository, CompatRefreshTokenRepository, CompatSessionRepository,
             CompatSsoLoginRepository,
@@ -215,10 +241,13 @@ mod impls {
             UpstreamOAuthLinkRepository, UpstreamOAuthProviderRepository,
             UpstreamOAuthSessionRepository,
         },
+        usage_stats::UsageStatsRepository,
         user::{
-            BrowserSessionRepository, UserEmailRepository, UserPasswordRepository, UserRepository,
+            BrowserSessionRepository, UserEmailRepository, UserLoginLockoutRepository,
+            UserPasswordRepository, UserRegistrationTokenRepository, UserRepository,
             UserTermsRepository,
         },
+        webhook::WebhookDeliveryRepository,
         MapErr, Repository, RepositoryTransaction,
     };
 
@@ -312,6 +341,24 @@ mod impls {
             Box::new(MapErr::new(self.inner.user_terms(), &mut self.mapper))
         }
 
+        fn user_login_lockout<'c>(
+            &'c mut self,
+        ) -> Box<dyn UserLoginLockoutRepository<Error = Self::Error> + 'c> {
+            Box::new(MapErr::new(
+                self.inner.user_login_lockout(),
+                &mut self.mapper,
+            ))
+        }
+
+        fn user_registration_token<'c>(
+            &'c mut self,
+        ) -> Box<dyn UserRegistrationTokenRepository<Error = Self::Error> + 'c> {
+            Box::new(MapErr::new(
+                self.inner.user_registration_token(),
+                &mut self.mapper,
+            ))
+        }
+
         fn browser_session<'c>(
             &'c mut self,
         ) -> Box<dyn BrowserSessionRepository<Error = Self::Error> + 'c> {
@@ -405,6 +452,22 @@ mod impls {
         fn job<'c>(&'c mut self) -> Box<dyn JobRepository<Error = Self::Error> + 'c> {
             Box::new(MapErr::new(self.inner.job(), &mut self.mapper))
         }
+
+        fn audit_log<'c>(&'c mut self) -> Box<dyn AuditLogRepository<Error = Self::Error> + 'c> {
+            Box::new(MapErr::new(self.inner.audit_log(), &mut self.mapper))
+        }
+
+        fn webhook_delivery<'c>(
+            &'c mut self,
+        ) -> Box<dyn WebhookDeliveryRepository<Error = Self::Error> + 'c> {
+            Box::new(MapErr::new(self.inner.webhook_delivery(), &mut self.mapper))
+        }
+
+        fn usage_stats<'c>(
+            &'c mut self,
+        ) -> Box<dyn UsageStatsRepository<Error = Self::Error> + 'c> {
+            Box::new(MapErr::new(self.inner.usage_stats(), &mut self.mapper))
+        }
     }
 
     impl<R: RepositoryAccess + ?Sized> RepositoryAccess for Box<R> {
@@ -452,6 +515,18 @@ mod impls {
             (**self).user_terms()
         }
 
+        fn user_login_lockout<'c>(
+            &'c mut self,
+        ) -> Box<dyn UserLoginLockoutRepository<Error = Self::Error> + 'c> {
+            (**self).user_login_lockout()
+        }
+
+        fn user_registration_token<'c>(
+            &'c mut self,
+        ) -> Box<dyn UserRegistrationTokenRepository<Error = Self::Error> + 'c> {
+            (**self).user_registration_token()
+        }
+
         fn browser_session<'c>(
             &'c mut self,
         ) -> Box<dyn BrowserSessionRepository<Error = Self::Error> + 'c> {
@@ -527,5 +602,21 @@ mod impls {
         fn job<'c>(&'c mut self) -> Box<dyn JobRepository<Error = Self::Error> + 'c> {
             (**self).job()
         }
+
+        fn audit_log<'c>(&'c mut self) -> Box<dyn AuditLogRepository<Error = Self::Error> + 'c> {
+            (**self).audit_log()
+        }
+
+        fn webhook_delivery<'c>(
+            &'c mut self,
+        ) -> Box<dyn WebhookDeliveryRepository<Error = Self::Error> + 'c> {
+            (**self).webhook_delivery()
+        }
+
+        fn usage_stats<'c>(
+            &'c mut self,
+        ) -> Box<dyn UsageStatsRepository<Error = Self::Error> + 'c> {
+            (**self).usage_stats()
+        }
     }
 }
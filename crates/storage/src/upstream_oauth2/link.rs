@@ -5,7 +5,10 @@
 // Please see LICENSE in the repository root for full details.
 
 use async_trait::async_trait;
-use mas_data_model::{UpstreamOAuthLink, UpstreamOAuthProvider, User};
+use chrono::{DateTime, Utc};
+use mas_data_model::{
+    UpstreamOAuthLink, UpstreamOAuthLinkTokens, UpstreamOAuthProvider, User, UserEmail,
+};
 use rand_core::RngCore;
 use ulid::Ulid;
 
@@ -158,6 +161,148 @@ pub trait UpstreamOAuthLinkRepository: Send + Sync {
         user: &User,
     ) -> Result<(), Self::Error>;
 
+    /// Set the email address which was imported from the upstream provider
+    /// through this link
+    ///
+    /// Returns the updated upstream OAuth link
+    ///
+    /// # Parameters
+    ///
+    /// * `upstream_oauth_link`: The upstream OAuth link to update
+    /// * `user_email`: The imported email address, or `None` to clear it
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn set_imported_email(
+        &mut self,
+        upstream_oauth_link: UpstreamOAuthLink,
+        user_email: Option<&UserEmail>,
+    ) -> Result<UpstreamOAuthLink, Self::Error>;
+
+    /// Set the groups the upstream provider claimed the user is a member of
+    ///
+    /// Returns the updated upstream OAuth link
+    ///
+    /// # Parameters
+    ///
+    /// * `upstream_oauth_link`: The upstream OAuth link to update
+    /// * `groups`: The groups claimed by the upstream provider
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn set_groups(
+        &mut self,
+        upstream_oauth_link: UpstreamOAuthLink,
+        groups: Vec<String>,
+    ) -> Result<UpstreamOAuthLink, Self::Error>;
+
+    /// Lookup the tokens issued by the upstream provider for a link
+    ///
+    /// Returns `None` if no tokens were stored for this link
+    ///
+    /// # Parameters
+    ///
+    /// * `upstream_oauth_link`: The upstream OAuth link
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn lookup_tokens(
+        &mut self,
+        upstream_oauth_link: &UpstreamOAuthLink,
+    ) -> Result<Option<UpstreamOAuthLinkTokens>, Self::Error>;
+
+    /// Save the tokens issued by the upstream provider for a link, replacing
+    /// the ones previously stored, and release the refresh lease on them
+    ///
+    /// The previous refresh token is kept if no new one is given, as
+    /// providers don't always rotate them.
+    ///
+    /// Returns the saved tokens
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock used to generate timestamps
+    /// * `upstream_oauth_link`: The upstream OAuth link
+    /// * `encrypted_access_token`: The encrypted access token
+    /// * `encrypted_refresh_token`: The encrypted refresh token, if any
+    /// * `access_token_expires_at`: When the access token expires, if known
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn save_tokens(
+        &mut self,
+        clock: &dyn Clock,
+        upstream_oauth_link: &UpstreamOAuthLink,
+        encrypted_access_token: String,
+        encrypted_refresh_token: Option<String>,
+        access_token_expires_at: Option<DateTime<Utc>>,
+    ) -> Result<UpstreamOAuthLinkTokens, Self::Error>;
+
+    /// Remove the tokens stored for a link
+    ///
+    /// # Parameters
+    ///
+    /// * `tokens`: The tokens to remove
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn remove_tokens(&mut self, tokens: UpstreamOAuthLinkTokens) -> Result<(), Self::Error>;
+
+    /// Lease the tokens stored for a link, so that they can be refreshed
+    /// outside of a transaction without anyone else refreshing them at the
+    /// same time
+    ///
+    /// The lease is released when new tokens are saved, or when it expires.
+    ///
+    /// Returns the leased tokens, or `None` if someone else holds a lease on
+    /// them, or if they were removed
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock used to check whether other leases expired
+    /// * `tokens`: The tokens to lease
+    /// * `leased_until`: When the lease expires
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn lease_tokens(
+        &mut self,
+        clock: &dyn Clock,
+        tokens: UpstreamOAuthLinkTokens,
+        leased_until: DateTime<Utc>,
+    ) -> Result<Option<UpstreamOAuthLinkTokens>, Self::Error>;
+
+    /// Lease a batch of tokens which have a refresh token and an access token
+    /// expiring before the given time, ordered by link ID
+    ///
+    /// Tokens currently leased by someone else are skipped.
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock used to check whether other leases expired
+    /// * `expires_before`: The time before which the access token expires
+    /// * `leased_until`: When the leases expire
+    /// * `after`: Only lease the tokens of links with an ID after this one
+    /// * `limit`: The maximum number of tokens to lease
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn lease_tokens_to_refresh(
+        &mut self,
+        clock: &dyn Clock,
+        expires_before: DateTime<Utc>,
+        leased_until: DateTime<Utc>,
+        after: Option<Ulid>,
+        limit: usize,
+    ) -> Result<Vec<UpstreamOAuthLinkTokens>, Self::Error>;
+
     /// List [`UpstreamOAuthLink`] with the given filter and pagination
     ///
     /// # Parameters
@@ -184,6 +329,20 @@ pub trait UpstreamOAuthLinkRepository: Send + Sync {
     ///
     /// Returns [`Self::Error`] if the underlying repository fails
     async fn count(&mut self, filter: UpstreamOAuthLinkFilter<'_>) -> Result<usize, Self::Error>;
+
+    /// Delete an [`UpstreamOAuthLink`]
+    ///
+    /// This also deletes the authorization sessions which resolved to this
+    /// link, and the tokens stored for it.
+    ///
+    /// # Parameters
+    ///
+    /// * `upstream_oauth_link`: The [`UpstreamOAuthLink`] to delete
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn remove(&mut self, upstream_oauth_link: UpstreamOAuthLink) -> Result<(), Self::Error>;
 }
 
 repository_impl!(UpstreamOAuthLinkRepository:
@@ -209,6 +368,50 @@ repository_impl!(UpstreamOAuthLinkRepository:
         user: &User,
     ) -> Result<(), Self::Error>;
 
+    async fn set_imported_email(
+        &mut self,
+        upstream_oauth_link: UpstreamOAuthLink,
+        user_email: Option<&UserEmail>,
+    ) -> Result<UpstreamOAuthLink, Self::Error>;
+
+    async fn set_groups(
+        &mut self,
+        upstream_oauth_link: UpstreamOAuthLink,
+        groups: Vec<String>,
+    ) -> Result<UpstreamOAuthLink, Self::Error>;
+
+    async fn lookup_tokens(
+        &mut self,
+        upstream_oauth_link: &UpstreamOAuthLink,
+    ) -> Result<Option<UpstreamOAuthLinkTokens>, Self::Error>;
+
+    async fn save_tokens(
+        &mut self,
+        clock: &dyn Clock,
+        upstream_oauth_link: &UpstreamOAuthLink,
+        encrypted_access_token: String,
+        encrypted_refresh_token: Option<String>,
+        access_token_expires_at: Option<DateTime<Utc>>,
+    ) -> Result<UpstreamOAuthLinkTokens, Self::Error>;
+
+    async fn remove_tokens(&mut self, tokens: UpstreamOAuthLinkTokens) -> Result<(), Self::Error>;
+
+    async fn lease_tokens(
+        &mut self,
+        clock: &dyn Clock,
+        tokens: UpstreamOAuthLinkTokens,
+        leased_until: DateTime<Utc>,
+    ) -> Result<Option<UpstreamOAuthLinkTokens>, Self::Error>;
+
+    async fn lease_tokens_to_refresh(
+        &mut self,
+        clock: &dyn Clock,
+        expires_before: DateTime<Utc>,
+        leased_until: DateTime<Utc>,
+        after: Option<Ulid>,
+        limit: usize,
+    ) -> Result<Vec<UpstreamOAuthLinkTokens>, Self::Error>;
+
     async fn list(
         &mut self,
         filter: UpstreamOAuthLinkFilter<'_>,
@@ -216,4 +419,6 @@ repository_impl!(UpstreamOAuthLinkRepository:
     ) -> Result<Page<UpstreamOAuthLink>, Self::Error>;
 
     async fn count(&mut self, filter: UpstreamOAuthLinkFilter<'_>) -> Result<usize, Self::Error>;
+
+    async fn remove(&mut self, upstream_oauth_link: UpstreamOAuthLink) -> Result<(), Self::Error>;
 );
@@ -59,6 +59,22 @@ pub struct UpstreamOAuthProviderParams {
     /// discovered
     pub token_endpoint_override: Option<Url>,
 
+    /// The URL to use as the userinfo endpoint. If `None`, the URL will be
+    /// discovered
+    pub userinfo_endpoint_override: Option<Url>,
+
+    /// Whether to fetch the user claims from the userinfo endpoint
+    pub fetch_userinfo: bool,
+
+    /// Whether to keep the tokens issued by the provider
+    pub store_tokens: bool,
+
+    /// The IDs of the clients allowed to get the tokens issued by the provider
+    pub token_exchange_clients: Vec<Ulid>,
+
+    /// The email domains for which users should be sent to this provider
+    pub domains: Vec<String>,
+
     /// The URL to use when fetching JWKS. If `None`, the URL will be discovered
     pub jwks_uri_override: Option<Url>,
 
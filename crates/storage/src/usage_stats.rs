@@ -0,0 +1,170 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+//! Repository to compute and query the usage statistics
+
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use mas_data_model::UsageStats;
+use rand_core::RngCore;
+use ulid::Ulid;
+
+use crate::{repository_impl, Clock, Page, Pagination};
+
+/// Filter parameters for listing usage statistics
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct UsageStatsFilter {
+    since: Option<NaiveDate>,
+    until: Option<NaiveDate>,
+}
+
+impl UsageStatsFilter {
+    /// Create a new [`UsageStatsFilter`] with default values
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only return the statistics of this day and the following ones
+    #[must_use]
+    pub fn with_since(mut self, since: NaiveDate) -> Self {
+        self.since = Some(since);
+        self
+    }
+
+    /// Get the since filter
+    ///
+    /// Returns [`None`] if no since filter was set
+    #[must_use]
+    pub fn since(&self) -> Option<NaiveDate> {
+        self.since
+    }
+
+    /// Only return the statistics of this day and the previous ones
+    #[must_use]
+    pub fn with_until(mut self, until: NaiveDate) -> Self {
+        self.until = Some(until);
+        self
+    }
+
+    /// Get the until filter
+    ///
+    /// Returns [`None`] if no until filter was set
+    #[must_use]
+    pub fn until(&self) -> Option<NaiveDate> {
+        self.until
+    }
+}
+
+/// A [`UsageStatsRepository`] helps computing and querying the daily usage
+/// statistics
+///
+/// Activity is only tracked as the last time each session was active, so the
+/// number of active users of a day can't be computed after the fact. The
+/// statistics of the current day are meant to be recomputed regularly, and
+/// finalized shortly after the day ends.
+#[async_trait]
+pub trait UsageStatsRepository: Send + Sync {
+    /// The error type returned by the repository
+    type Error;
+
+    /// Lookup the statistics by their ID
+    ///
+    /// Returns `None` if no statistics were found
+    ///
+    /// # Parameters
+    ///
+    /// * `id`: The ID of the statistics to lookup
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn lookup(&mut self, id: Ulid) -> Result<Option<UsageStats>, Self::Error>;
+
+    /// Find the statistics of a day
+    ///
+    /// Returns `None` if the statistics of this day were never computed
+    ///
+    /// # Parameters
+    ///
+    /// * `day`: The day to lookup
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn find_by_day(&mut self, day: NaiveDate) -> Result<Option<UsageStats>, Self::Error>;
+
+    /// Compute the statistics of a day, and record them
+    ///
+    /// If the statistics of this day were already recorded, they are updated.
+    /// Because later activity hides earlier one, the number of active users
+    /// never decreases when the statistics are recomputed.
+    ///
+    /// Returns the recorded statistics
+    ///
+    /// # Parameters
+    ///
+    /// * `rng`: The random number generator to use
+    /// * `clock`: The clock used to generate timestamps
+    /// * `day`: The day to compute the statistics of
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn record(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        day: NaiveDate,
+    ) -> Result<UsageStats, Self::Error>;
+
+    /// List the statistics matching the given filter, ordered by ID
+    ///
+    /// # Parameters
+    ///
+    /// * `filter`: The filter parameters
+    /// * `pagination`: The pagination parameters
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn list(
+        &mut self,
+        filter: UsageStatsFilter,
+        pagination: Pagination,
+    ) -> Result<Page<UsageStats>, Self::Error>;
+
+    /// Count the statistics matching the given filter
+    ///
+    /// # Parameters
+    ///
+    /// * `filter`: The filter parameters
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn count(&mut self, filter: UsageStatsFilter) -> Result<usize, Self::Error>;
+}
+
+repository_impl!(UsageStatsRepository:
+    async fn lookup(&mut self, id: Ulid) -> Result<Option<UsageStats>, Self::Error>;
+
+    async fn find_by_day(&mut self, day: NaiveDate) -> Result<Option<UsageStats>, Self::Error>;
+
+    async fn record(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        day: NaiveDate,
+    ) -> Result<UsageStats, Self::Error>;
+
+    async fn list(
+        &mut self,
+        filter: UsageStatsFilter,
+        pagination: Pagination,
+    ) -> Result<Page<UsageStats>, Self::Error>;
+
+    async fn count(&mut self, filter: UsageStatsFilter) -> Result<usize, Self::Error>;
+);
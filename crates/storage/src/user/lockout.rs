@@ -0,0 +1,101 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use mas_data_model::{User, UserLoginLockout};
+
+use crate::{repository_impl, Clock};
+
+/// A [`UserLoginLockoutRepository`] helps keeping track of failed login
+/// attempts on a [`User`] account, and locking it out after too many of them
+#[async_trait]
+pub trait UserLoginLockoutRepository: Send + Sync {
+    /// The error type returned by the repository
+    type Error;
+
+    /// Lookup the failed login attempts of a [`User`]
+    ///
+    /// Returns `None` if there were no failed attempts since the last
+    /// successful login
+    ///
+    /// # Parameters
+    ///
+    /// * `user`: The [`User`] to lookup
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn lookup(&mut self, user: &User) -> Result<Option<UserLoginLockout>, Self::Error>;
+
+    /// Record a failed login attempt on a [`User`] account
+    ///
+    /// The count of failed attempts is reset if the last one is older than
+    /// `window`, or if a previous lockout has expired.
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock used to generate timestamps
+    /// * `user`: The [`User`] which failed to log in
+    /// * `window`: How long failed attempts are remembered
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn record_failure(
+        &mut self,
+        clock: &dyn Clock,
+        user: &User,
+        window: Duration,
+    ) -> Result<UserLoginLockout, Self::Error>;
+
+    /// Lock a [`User`] account out until the given time
+    ///
+    /// # Parameters
+    ///
+    /// * `lockout`: The [`UserLoginLockout`] to update
+    /// * `locked_until`: Until when the account is locked out
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn lock(
+        &mut self,
+        lockout: UserLoginLockout,
+        locked_until: DateTime<Utc>,
+    ) -> Result<UserLoginLockout, Self::Error>;
+
+    /// Forget the failed login attempts of a [`User`], lifting any lockout
+    ///
+    /// Returns `true` if there was anything to clear
+    ///
+    /// # Parameters
+    ///
+    /// * `user`: The [`User`] to clear the failed attempts of
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn clear(&mut self, user: &User) -> Result<bool, Self::Error>;
+}
+
+repository_impl!(UserLoginLockoutRepository:
+    async fn lookup(&mut self, user: &User) -> Result<Option<UserLoginLockout>, Self::Error>;
+
+    async fn record_failure(
+        &mut self,
+        clock: &dyn Clock,
+        user: &User,
+        window: Duration,
+    ) -> Result<UserLoginLockout, Self::Error>;
+
+    async fn lock(
+        &mut self,
+        lockout: UserLoginLockout,
+        locked_until: DateTime<Utc>,
+    ) -> Result<UserLoginLockout, Self::Error>;
+
+    async fn clear(&mut self, user: &User) -> Result<bool, Self::Error>;
+);
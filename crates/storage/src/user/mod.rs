@@ -14,15 +14,19 @@ use ulid::Ulid;
 use crate::{repository_impl, Clock, Page, Pagination};
 
 mod email;
+mod lockout;
 mod password;
 mod recovery;
+mod registration_token;
 mod session;
 mod terms;
 
 pub use self::{
     email::{UserEmailFilter, UserEmailRepository},
+    lockout::UserLoginLockoutRepository,
     password::UserPasswordRepository,
     recovery::UserRecoveryRepository,
+    registration_token::{UserRegistrationTokenFilter, UserRegistrationTokenRepository},
     session::{BrowserSessionFilter, BrowserSessionRepository},
     terms::UserTermsRepository,
 };
@@ -7,7 +7,9 @@
 use std::net::IpAddr;
 
 use async_trait::async_trait;
-use mas_data_model::{UserAgent, UserEmail, UserRecoverySession, UserRecoveryTicket};
+use mas_data_model::{
+    UpstreamOAuthLink, User, UserAgent, UserEmail, UserRecoverySession, UserRecoveryTicket,
+};
 use rand_core::RngCore;
 use ulid::Ulid;
 
@@ -64,6 +66,33 @@ pub trait UserRecoveryRepository: Send + Sync {
         locale: String,
     ) -> Result<UserRecoverySession, Self::Error>;
 
+    /// Create a new [`UserRecoverySession`] for a user who signed in with a
+    /// linked upstream provider
+    ///
+    /// Returns the newly created [`UserRecoverySession`]
+    ///
+    /// # Parameters
+    ///
+    /// * `rng`: The random number generator to use
+    /// * `clock`: The clock to use
+    /// * `user_agent`: The user agent of the browser which initiated the
+    ///   session
+    /// * `ip_address`: The IP address of the browser which initiated the
+    ///   session, if known
+    /// * `locale`: The locale of the browser which initiated the session
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn add_upstream_session(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user_agent: UserAgent,
+        ip_address: Option<IpAddr>,
+        locale: String,
+    ) -> Result<UserRecoverySession, Self::Error>;
+
     /// Find a [`UserRecoveryTicket`] by its ticket
     ///
     /// Returns `None` if no [`UserRecoveryTicket`] was found
@@ -103,6 +132,32 @@ pub trait UserRecoveryRepository: Send + Sync {
         ticket: String,
     ) -> Result<UserRecoveryTicket, Self::Error>;
 
+    /// Add a [`UserRecoveryTicket`] to the given [`UserRecoverySession`] for
+    /// the given [`UpstreamOAuthLink`]
+    ///
+    /// # Parameters
+    ///
+    /// * `rng`: The random number generator to use
+    /// * `clock`: The clock to use
+    /// * `session`: The [`UserRecoverySession`] to add the ticket to
+    /// * `user`: The [`User`] the upstream link belongs to
+    /// * `upstream_oauth_link`: The [`UpstreamOAuthLink`] the user signed in
+    ///   with
+    /// * `ticket`: The ticket to add
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn add_upstream_ticket(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user_recovery_session: &UserRecoverySession,
+        user: &User,
+        upstream_oauth_link: &UpstreamOAuthLink,
+        ticket: String,
+    ) -> Result<UserRecoveryTicket, Self::Error>;
+
     /// Consume a [`UserRecoveryTicket`] and mark the session as used
     ///
     /// # Parameters
@@ -136,6 +191,15 @@ repository_impl!(UserRecoveryRepository:
         locale: String,
     ) -> Result<UserRecoverySession, Self::Error>;
 
+    async fn add_upstream_session(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user_agent: UserAgent,
+        ip_address: Option<IpAddr>,
+        locale: String,
+    ) -> Result<UserRecoverySession, Self::Error>;
+
     async fn find_ticket(
         &mut self,
         ticket: &str,
@@ -150,6 +214,16 @@ repository_impl!(UserRecoveryRepository:
         ticket: String,
     ) -> Result<UserRecoveryTicket, Self::Error>;
 
+    async fn add_upstream_ticket(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user_recovery_session: &UserRecoverySession,
+        user: &User,
+        upstream_oauth_link: &UpstreamOAuthLink,
+        ticket: String,
+    ) -> Result<UserRecoveryTicket, Self::Error>;
+
     async fn consume_ticket(
         &mut self,
         clock: &dyn Clock,
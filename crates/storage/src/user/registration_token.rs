@@ -0,0 +1,254 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use mas_data_model::{User, UserRegistrationToken};
+use rand_core::RngCore;
+use ulid::Ulid;
+
+use crate::{repository_impl, Clock, Page, Pagination};
+
+/// Filter parameters for listing user registration tokens
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UserRegistrationTokenFilter {
+    now: DateTime<Utc>,
+    revoked: Option<bool>,
+    expired: Option<bool>,
+    exhausted: Option<bool>,
+}
+
+impl UserRegistrationTokenFilter {
+    /// Create a new [`UserRegistrationTokenFilter`], which checks whether
+    /// tokens are expired at the given time
+    #[must_use]
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self {
+            now,
+            revoked: None,
+            expired: None,
+            exhausted: None,
+        }
+    }
+
+    /// The time at which tokens are checked for expiration
+    #[must_use]
+    pub fn now(&self) -> DateTime<Utc> {
+        self.now
+    }
+
+    /// Filter for tokens which were revoked, or not
+    #[must_use]
+    pub fn with_revoked(mut self, revoked: bool) -> Self {
+        self.revoked = Some(revoked);
+        self
+    }
+
+    /// Get the revoked filter
+    ///
+    /// Returns [`None`] if no revoked filter was set
+    #[must_use]
+    pub fn revoked(&self) -> Option<bool> {
+        self.revoked
+    }
+
+    /// Filter for tokens which have expired, or not
+    #[must_use]
+    pub fn with_expired(mut self, expired: bool) -> Self {
+        self.expired = Some(expired);
+        self
+    }
+
+    /// Get the expired filter
+    ///
+    /// Returns [`None`] if no expired filter was set
+    #[must_use]
+    pub fn expired(&self) -> Option<bool> {
+        self.expired
+    }
+
+    /// Filter for tokens which were used as many times as allowed, or not
+    #[must_use]
+    pub fn with_exhausted(mut self, exhausted: bool) -> Self {
+        self.exhausted = Some(exhausted);
+        self
+    }
+
+    /// Get the exhausted filter
+    ///
+    /// Returns [`None`] if no exhausted filter was set
+    #[must_use]
+    pub fn exhausted(&self) -> Option<bool> {
+        self.exhausted
+    }
+}
+
+/// A [`UserRegistrationTokenRepository`] helps interacting with the tokens
+/// which can be required to register new accounts
+#[async_trait]
+pub trait UserRegistrationTokenRepository: Send + Sync {
+    /// The error type returned by the repository
+    type Error;
+
+    /// Lookup a registration token by its ID
+    ///
+    /// Returns `None` if no token was found
+    ///
+    /// # Parameters
+    ///
+    /// * `id`: The ID of the token to lookup
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn lookup(&mut self, id: Ulid) -> Result<Option<UserRegistrationToken>, Self::Error>;
+
+    /// Find a registration token by its value
+    ///
+    /// Returns `None` if no token was found
+    ///
+    /// # Parameters
+    ///
+    /// * `token`: The token to find
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn find_by_token(
+        &mut self,
+        token: &str,
+    ) -> Result<Option<UserRegistrationToken>, Self::Error>;
+
+    /// Create a new registration token
+    ///
+    /// Returns the newly created token
+    ///
+    /// # Parameters
+    ///
+    /// * `rng`: The random number generator to use
+    /// * `clock`: The clock used to generate timestamps
+    /// * `token`: The token users have to provide when registering
+    /// * `usage_limit`: How many times the token can be used, `None` for
+    ///   unlimited
+    /// * `expires_at`: When the token expires, `None` if it never does
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        token: String,
+        usage_limit: Option<u32>,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<UserRegistrationToken, Self::Error>;
+
+    /// Record that a user registered with a token
+    ///
+    /// Returns the updated token, or `None` if the token can't be used
+    /// anymore, because it was revoked, expired, or used as many times as
+    /// allowed in the meantime.
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock used to generate timestamps
+    /// * `token`: The token which was used
+    /// * `user`: The user who registered with the token
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn use_token(
+        &mut self,
+        clock: &dyn Clock,
+        token: UserRegistrationToken,
+        user: &User,
+    ) -> Result<Option<UserRegistrationToken>, Self::Error>;
+
+    /// Revoke a registration token, so that it can't be used anymore
+    ///
+    /// Returns the revoked token
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock used to generate timestamps
+    /// * `token`: The token to revoke
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn revoke(
+        &mut self,
+        clock: &dyn Clock,
+        token: UserRegistrationToken,
+    ) -> Result<UserRegistrationToken, Self::Error>;
+
+    /// List registration tokens matching the given filter, ordered by ID
+    ///
+    /// # Parameters
+    ///
+    /// * `filter`: The filter parameters
+    /// * `pagination`: The pagination parameters
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn list(
+        &mut self,
+        filter: UserRegistrationTokenFilter,
+        pagination: Pagination,
+    ) -> Result<Page<UserRegistrationToken>, Self::Error>;
+
+    /// Count the registration tokens matching the given filter
+    ///
+    /// # Parameters
+    ///
+    /// * `filter`: The filter parameters
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn count(&mut self, filter: UserRegistrationTokenFilter) -> Result<usize, Self::Error>;
+}
+
+repository_impl!(UserRegistrationTokenRepository:
+    async fn lookup(&mut self, id: Ulid) -> Result<Option<UserRegistrationToken>, Self::Error>;
+
+    async fn find_by_token(
+        &mut self,
+        token: &str,
+    ) -> Result<Option<UserRegistrationToken>, Self::Error>;
+
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        token: String,
+        usage_limit: Option<u32>,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<UserRegistrationToken, Self::Error>;
+
+    async fn use_token(
+        &mut self,
+        clock: &dyn Clock,
+        token: UserRegistrationToken,
+        user: &User,
+    ) -> Result<Option<UserRegistrationToken>, Self::Error>;
+
+    async fn revoke(
+        &mut self,
+        clock: &dyn Clock,
+        token: UserRegistrationToken,
+    ) -> Result<UserRegistrationToken, Self::Error>;
+
+    async fn list(
+        &mut self,
+        filter: UserRegistrationTokenFilter,
+        pagination: Pagination,
+    ) -> Result<Page<UserRegistrationToken>, Self::Error>;
+
+    async fn count(&mut self, filter: UserRegistrationTokenFilter) -> Result<usize, Self::Error>;
+);
@@ -39,6 +39,11 @@ pub struct BrowserSessionFilter<'a> {
     state: Option<BrowserSessionState>,
     last_active_before: Option<DateTime<Utc>>,
     last_active_after: Option<DateTime<Utc>>,
+    user_agent: Option<&'a str>,
+    last_active_ip: Option<IpAddr>,
+    created_before: Option<DateTime<Utc>>,
+    inactive_since: Option<DateTime<Utc>>,
+    authenticated_by_upstream_sessions: Option<&'a [UpstreamOAuthAuthorizationSession]>,
 }
 
 impl<'a> BrowserSessionFilter<'a> {
@@ -91,6 +96,69 @@ impl<'a> BrowserSessionFilter<'a> {
         self.last_active_after
     }
 
+    /// Only return sessions with the given raw user agent
+    #[must_use]
+    pub fn with_user_agent(mut self, user_agent: &'a str) -> Self {
+        self.user_agent = Some(user_agent);
+        self
+    }
+
+    /// Get the user agent filter
+    ///
+    /// Returns [`None`] if no user agent filter was set
+    #[must_use]
+    pub fn user_agent(&self) -> Option<&'a str> {
+        self.user_agent
+    }
+
+    /// Only return sessions which were last active from the given IP address
+    #[must_use]
+    pub fn with_last_active_ip(mut self, last_active_ip: IpAddr) -> Self {
+        self.last_active_ip = Some(last_active_ip);
+        self
+    }
+
+    /// Get the last active IP filter
+    ///
+    /// Returns [`None`] if no IP filter was set
+    #[must_use]
+    pub fn last_active_ip(&self) -> Option<IpAddr> {
+        self.last_active_ip
+    }
+
+    /// Only return sessions created before the given time
+    #[must_use]
+    pub fn with_created_before(mut self, created_before: DateTime<Utc>) -> Self {
+        self.created_before = Some(created_before);
+        self
+    }
+
+    /// Get the created before filter
+    ///
+    /// Returns [`None`] if no created before filter was set
+    #[must_use]
+    pub fn created_before(&self) -> Option<DateTime<Utc>> {
+        self.created_before
+    }
+
+    /// Only return sessions which were not active since the given time
+    ///
+    /// Sessions which were never active are considered inactive since they
+    /// were created.
+    #[must_use]
+    pub fn with_inactive_since(mut self, inactive_since: DateTime<Utc>) -> Self {
+        self.inactive_since = Some(inactive_since);
+        self
+    }
+
+    /// Get the inactive since filter
+    ///
+    /// Returns [`None`] if no inactive since filter was set
+    #[must_use]
+    pub fn inactive_since(&self) -> Option<DateTime<Utc>> {
+        self.inactive_since
+    }
+
     /// Only return active browser sessions
     #[must_use]
     pub fn active_only(mut self) -> Self {
@@ -110,6 +178,27 @@ impl<'a> BrowserSessionFilter<'a> {
     pub fn state(&self) -> Option<BrowserSessionState> {
         self.state
     }
+
+    /// Only return browser sessions which were authenticated by one of the
+    /// given upstream OAuth authorization sessions
+    #[must_use]
+    pub fn authenticated_by_upstream_sessions_only(
+        mut self,
+        upstream_oauth_sessions: &'a [UpstreamOAuthAuthorizationSession],
+    ) -> Self {
+        self.authenticated_by_upstream_sessions = Some(upstream_oauth_sessions);
+        self
+    }
+
+    /// Get the upstream OAuth authorization sessions filter
+    ///
+    /// Returns [`None`] if no filter was set
+    #[must_use]
+    pub fn authenticated_by_upstream_sessions(
+        &self,
+    ) -> Option<&'a [UpstreamOAuthAuthorizationSession]> {
+        self.authenticated_by_upstream_sessions
+    }
 }
 
 /// A [`BrowserSessionRepository`] helps interacting with [`BrowserSession`]
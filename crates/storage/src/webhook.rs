@@ -0,0 +1,146 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+//! Repository to keep track of the deliveries of webhook events
+
+use async_trait::async_trait;
+use mas_data_model::{WebhookDelivery, WebhookEndpoint, WebhookEventKind};
+use rand_core::RngCore;
+use ulid::Ulid;
+
+use crate::{repository_impl, Clock};
+
+/// A [`WebhookDeliveryRepository`] helps interacting with the log of
+/// deliveries of events to webhook endpoints
+#[async_trait]
+pub trait WebhookDeliveryRepository: Send + Sync {
+    /// The error type returned by the repository
+    type Error;
+
+    /// Lookup a delivery by its ID
+    ///
+    /// Returns `None` if no delivery was found
+    ///
+    /// # Parameters
+    ///
+    /// * `id`: The ID of the delivery to lookup
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn lookup(&mut self, id: Ulid) -> Result<Option<WebhookDelivery>, Self::Error>;
+
+    /// Record a new pending delivery of an event to an endpoint
+    ///
+    /// Returns the newly created delivery
+    ///
+    /// # Parameters
+    ///
+    /// * `rng`: The random number generator to use
+    /// * `clock`: The clock used to generate timestamps
+    /// * `endpoint`: The endpoint to deliver the event to
+    /// * `kind`: The kind of event
+    /// * `payload`: The event to send
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        endpoint: &WebhookEndpoint,
+        kind: WebhookEventKind,
+        payload: serde_json::Value,
+    ) -> Result<WebhookDelivery, Self::Error>;
+
+    /// Record a failed attempt to deliver the event
+    ///
+    /// Returns the updated delivery
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock used to generate timestamps
+    /// * `delivery`: The delivery which was attempted
+    /// * `error`: What went wrong
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn record_failed_attempt(
+        &mut self,
+        clock: &dyn Clock,
+        delivery: WebhookDelivery,
+        error: String,
+    ) -> Result<WebhookDelivery, Self::Error>;
+
+    /// Mark the delivery as successful, after an attempt succeeded
+    ///
+    /// Returns the updated delivery
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock used to generate timestamps
+    /// * `delivery`: The delivery to mark as successful
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn mark_as_delivered(
+        &mut self,
+        clock: &dyn Clock,
+        delivery: WebhookDelivery,
+    ) -> Result<WebhookDelivery, Self::Error>;
+
+    /// Give up on the delivery, after too many failed attempts
+    ///
+    /// Returns the updated delivery
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock used to generate timestamps
+    /// * `delivery`: The delivery to give up on
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn mark_as_failed(
+        &mut self,
+        clock: &dyn Clock,
+        delivery: WebhookDelivery,
+    ) -> Result<WebhookDelivery, Self::Error>;
+}
+
+repository_impl!(WebhookDeliveryRepository:
+    async fn lookup(&mut self, id: Ulid) -> Result<Option<WebhookDelivery>, Self::Error>;
+
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        endpoint: &WebhookEndpoint,
+        kind: WebhookEventKind,
+        payload: serde_json::Value,
+    ) -> Result<WebhookDelivery, Self::Error>;
+
+    async fn record_failed_attempt(
+        &mut self,
+        clock: &dyn Clock,
+        delivery: WebhookDelivery,
+        error: String,
+    ) -> Result<WebhookDelivery, Self::Error>;
+
+    async fn mark_as_delivered(
+        &mut self,
+        clock: &dyn Clock,
+        delivery: WebhookDelivery,
+    ) -> Result<WebhookDelivery, Self::Error>;
+
+    async fn mark_as_failed(
+        &mut self,
+        clock: &dyn Clock,
+        delivery: WebhookDelivery,
+    ) -> Result<WebhookDelivery, Self::Error>;
+);
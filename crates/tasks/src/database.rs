@@ -18,7 +18,7 @@ use apalis_core::{
 };
 use apalis_cron::CronStream;
 use chrono::{DateTime, Utc};
-use mas_storage::{oauth2::OAuth2AccessTokenRepository, RepositoryAccess};
+use mas_storage::{oauth2::OAuth2AccessTokenRepository, Clock, RepositoryAccess};
 use tracing::{debug, info};
 
 use crate::{
@@ -65,6 +65,50 @@ pub async fn cleanup_expired_tokens(
     Ok(())
 }
 
+#[derive(Default, Clone)]
+pub struct PruneAuditLogJob {
+    scheduled: DateTime<Utc>,
+}
+
+impl From<DateTime<Utc>> for PruneAuditLogJob {
+    fn from(scheduled: DateTime<Utc>) -> Self {
+        Self { scheduled }
+    }
+}
+
+impl Job for PruneAuditLogJob {
+    const NAME: &'static str = "prune-audit-log";
+}
+
+impl TracedJob for PruneAuditLogJob {}
+
+pub async fn prune_audit_log(
+    job: PruneAuditLogJob,
+    ctx: JobContext,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
+    debug!("prune audit log job scheduled at {}", job.scheduled);
+
+    let state = ctx.state();
+    let Some(retention) = state.audit_log_retention() else {
+        // Events are kept forever
+        return Ok(());
+    };
+
+    let clock = state.clock();
+    let mut repo = state.repository().await?;
+
+    let count = repo.audit_log().prune(clock.now() - retention).await?;
+    repo.save().await?;
+
+    if count == 0 {
+        debug!("no audit log event to prune");
+    } else {
+        info!(count, "pruned old audit log events");
+    }
+
+    Ok(())
+}
+
 pub(crate) fn register(
     suffix: &str,
     monitor: Monitor<TokioExecutor>,
@@ -78,6 +122,16 @@ pub(crate) fn register(
         .layer(metrics_layer())
         .layer(trace_layer())
         .build_fn(cleanup_expired_tokens);
+    let monitor = monitor.register(worker);
+
+    let schedule = apalis_cron::Schedule::from_str("0 0 * * * *").unwrap();
+    let worker_name = format!("{job}-{suffix}", job = PruneAuditLogJob::NAME);
+    let worker = WorkerBuilder::new(worker_name)
+        .stream(CronStream::new(schedule).timer(TokioTimer).to_stream())
+        .layer(state.inject())
+        .layer(metrics_layer())
+        .layer(trace_layer())
+        .build_fn(prune_audit_log);
 
     monitor.register(worker)
 }
@@ -7,10 +7,20 @@
 use anyhow::Context;
 use apalis_core::{context::JobContext, executor::TokioExecutor, monitor::Monitor};
 use chrono::Duration;
+use mas_data_model::{User, UserEmail};
 use mas_email::{Address, Mailbox};
 use mas_i18n::locale;
-use mas_storage::job::{JobWithSpanContext, VerifyEmailJob};
-use mas_templates::{EmailVerificationContext, TemplateContext};
+use mas_router::AccountSession;
+use mas_storage::{
+    job::{
+        JobWithSpanContext, NewSignInSession, SendLoginLockoutEmailJob, SendNewSignInEmailJob,
+        VerifyEmailJob,
+    },
+    BoxRepository,
+};
+use mas_templates::{
+    EmailLoginLockoutContext, EmailNewSignInContext, EmailVerificationContext, TemplateContext,
+};
 use rand::{distributions::Uniform, Rng};
 use tracing::info;
 
@@ -88,6 +98,179 @@ async fn verify_email(
     Ok(())
 }
 
+#[tracing::instrument(
+    name = "job.send_login_lockout_email",
+    fields(user.id = %job.user_id()),
+    skip_all,
+    err(Debug),
+)]
+async fn send_login_lockout_email(
+    job: JobWithSpanContext<SendLoginLockoutEmailJob>,
+    ctx: JobContext,
+) -> Result<(), anyhow::Error> {
+    let state = ctx.state();
+    let mut repo = state.repository().await?;
+    let mailer = state.mailer();
+
+    let language = job
+        .language()
+        .and_then(|l| l.parse().ok())
+        .unwrap_or(locale!("en").into());
+
+    let user = repo
+        .user()
+        .lookup(job.user_id())
+        .await?
+        .context("User not found")?;
+
+    let Some(lockout) = repo.user_login_lockout().lookup(&user).await? else {
+        info!("Login lockout was cleared, not sending email");
+        return Ok(());
+    };
+
+    let Some(user_email) = verified_primary_email(&mut repo, &user).await? else {
+        return Ok(());
+    };
+
+    let address: Address = user_email.email.parse()?;
+    let mailbox = Mailbox::new(Some(user.username.clone()), address);
+
+    let context = EmailLoginLockoutContext::new(user, lockout).with_language(language);
+
+    mailer.send_login_lockout_email(mailbox, &context).await?;
+
+    info!(
+        email.id = %user_email.id,
+        "Login lockout email sent"
+    );
+
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "job.send_new_sign_in_email",
+    fields(user.id = %job.user_id()),
+    skip_all,
+    err(Debug),
+)]
+async fn send_new_sign_in_email(
+    job: JobWithSpanContext<SendNewSignInEmailJob>,
+    ctx: JobContext,
+) -> Result<(), anyhow::Error> {
+    let state = ctx.state();
+    let mut repo = state.repository().await?;
+    let mailer = state.mailer();
+    let url_builder = state.url_builder();
+
+    let language = job
+        .language()
+        .and_then(|l| l.parse().ok())
+        .unwrap_or(locale!("en").into());
+
+    let user = repo
+        .user()
+        .lookup(job.user_id())
+        .await?
+        .context("User not found")?;
+
+    // Lookup the session, to get its user agent and when it was created
+    let (finished, created_at, user_agent, route) = match job.session() {
+        NewSignInSession::Browser(id) => {
+            let session = repo
+                .browser_session()
+                .lookup(id)
+                .await?
+                .context("Browser session not found")?;
+            (
+                session.finished_at.is_some(),
+                session.created_at,
+                session.user_agent,
+                AccountSession::Browser(id),
+            )
+        }
+        NewSignInSession::Compat(id) => {
+            let session = repo
+                .compat_session()
+                .lookup(id)
+                .await?
+                .context("Compat session not found")?;
+            (
+                session.is_finished(),
+                session.created_at,
+                session.user_agent,
+                AccountSession::Compat(id),
+            )
+        }
+        NewSignInSession::OAuth2(id) => {
+            let session = repo
+                .oauth2_session()
+                .lookup(id)
+                .await?
+                .context("OAuth 2.0 session not found")?;
+            (
+                session.is_finished(),
+                session.created_at,
+                session.user_agent,
+                AccountSession::OAuth2(id),
+            )
+        }
+    };
+
+    if finished {
+        info!("Session already ended, not sending email");
+        return Ok(());
+    }
+
+    let Some(user_email) = verified_primary_email(&mut repo, &user).await? else {
+        return Ok(());
+    };
+
+    let address: Address = user_email.email.parse()?;
+    let mailbox = Mailbox::new(Some(user.username.clone()), address);
+
+    let session_link = url_builder.absolute_url_for(&route);
+    let context = EmailNewSignInContext::new(user, created_at, session_link)
+        .with_user_agent(user_agent)
+        .with_ip_address(job.ip())
+        .with_location(job.location().map(ToOwned::to_owned))
+        .with_language(language);
+
+    mailer.send_new_sign_in_email(mailbox, &context).await?;
+
+    info!(
+        email.id = %user_email.id,
+        "New sign-in email sent"
+    );
+
+    Ok(())
+}
+
+/// Lookup the primary email address of the user, if it was verified.
+///
+/// Security notices are only sent to that address.
+async fn verified_primary_email(
+    repo: &mut BoxRepository,
+    user: &User,
+) -> Result<Option<UserEmail>, anyhow::Error> {
+    let Some(primary_user_email_id) = user.primary_user_email_id else {
+        info!("User has no primary email address, not sending email");
+        return Ok(None);
+    };
+
+    let user_email = repo
+        .user_email()
+        .lookup(primary_user_email_id)
+        .await?
+        .context("User email not found")?;
+
+    if user_email.confirmed_at.is_none() {
+        info!("Primary email address is not verified, not sending email");
+        return Ok(None);
+    }
+
+    Ok(Some(user_email))
+}
+
 pub(crate) fn register(
     suffix: &str,
     monitor: Monitor<TokioExecutor>,
@@ -97,5 +280,11 @@ pub(crate) fn register(
     let verify_email_worker =
         crate::build!(VerifyEmailJob => verify_email, suffix, state, storage_factory);
 
-    monitor.register(verify_email_worker)
+    let send_login_lockout_email_worker = crate::build!(SendLoginLockoutEmailJob => send_login_lockout_email, suffix, state, storage_factory);
+    let send_new_sign_in_email_worker = crate::build!(SendNewSignInEmailJob => send_new_sign_in_email, suffix, state, storage_factory);
+
+    monitor
+        .register(verify_email_worker)
+        .register(send_login_lockout_email_worker)
+        .register(send_new_sign_in_email_worker)
 }
@@ -7,7 +7,10 @@
 use std::sync::Arc;
 
 use apalis_core::{executor::TokioExecutor, layers::extensions::Extension, monitor::Monitor};
+use mas_data_model::{SessionExpirationConfig, WebhookEndpoint};
 use mas_email::Mailer;
+use mas_handlers::UpstreamTokenRefresher;
+use mas_keystore::Keystore;
 use mas_matrix::HomeserverConnection;
 use mas_router::UrlBuilder;
 use mas_storage::{BoxClock, BoxRepository, SystemClock};
@@ -22,9 +25,13 @@ mod database;
 mod email;
 mod matrix;
 mod recovery;
+mod sessions;
 mod storage;
+mod upstream_oauth2;
+mod usage_stats;
 mod user;
 mod utils;
+mod webhooks;
 
 #[derive(Clone)]
 struct State {
@@ -33,15 +40,28 @@ struct State {
     clock: SystemClock,
     homeserver: Arc<dyn HomeserverConnection<Error = anyhow::Error>>,
     url_builder: UrlBuilder,
+    session_expiration: Arc<SessionExpirationConfig>,
+    audit_log_retention: Option<chrono::Duration>,
+    http_client: reqwest::Client,
+    key_store: Keystore,
+    webhook_endpoints: Arc<Vec<WebhookEndpoint>>,
+    upstream_token_refresher: UpstreamTokenRefresher,
 }
 
 impl State {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         pool: Pool<Postgres>,
         clock: SystemClock,
         mailer: Mailer,
         homeserver: impl HomeserverConnection<Error = anyhow::Error> + 'static,
         url_builder: UrlBuilder,
+        session_expiration: SessionExpirationConfig,
+        audit_log_retention: Option<chrono::Duration>,
+        http_client: reqwest::Client,
+        key_store: Keystore,
+        webhook_endpoints: Vec<WebhookEndpoint>,
+        upstream_token_refresher: UpstreamTokenRefresher,
     ) -> Self {
         Self {
             pool,
@@ -49,6 +69,12 @@ impl State {
             clock,
             homeserver: Arc::new(homeserver),
             url_builder,
+            session_expiration: Arc::new(session_expiration),
+            audit_log_retention,
+            http_client,
+            key_store,
+            webhook_endpoints: Arc::new(webhook_endpoints),
+            upstream_token_refresher,
         }
     }
 
@@ -87,6 +113,30 @@ impl State {
     pub fn url_builder(&self) -> &UrlBuilder {
         &self.url_builder
     }
+
+    pub fn session_expiration(&self) -> &SessionExpirationConfig {
+        &self.session_expiration
+    }
+
+    pub fn audit_log_retention(&self) -> Option<chrono::Duration> {
+        self.audit_log_retention
+    }
+
+    pub fn http_client(&self) -> &reqwest::Client {
+        &self.http_client
+    }
+
+    pub fn key_store(&self) -> &Keystore {
+        &self.key_store
+    }
+
+    pub fn webhook_endpoints(&self) -> &[WebhookEndpoint] {
+        &self.webhook_endpoints
+    }
+
+    pub fn upstream_token_refresher(&self) -> &UpstreamTokenRefresher {
+        &self.upstream_token_refresher
+    }
 }
 
 trait JobContextExt {
@@ -132,12 +182,19 @@ pub(crate) use build;
 /// # Errors
 ///
 /// This function can fail if the database connection fails.
+#[allow(clippy::too_many_arguments)]
 pub async fn init(
     name: &str,
     pool: &Pool<Postgres>,
     mailer: &Mailer,
     homeserver: impl HomeserverConnection<Error = anyhow::Error> + 'static,
     url_builder: UrlBuilder,
+    session_expiration: SessionExpirationConfig,
+    audit_log_retention: Option<chrono::Duration>,
+    http_client: reqwest::Client,
+    key_store: Keystore,
+    webhook_endpoints: Vec<WebhookEndpoint>,
+    upstream_token_refresher: UpstreamTokenRefresher,
 ) -> Result<Monitor<TokioExecutor>, sqlx::Error> {
     let state = State::new(
         pool.clone(),
@@ -145,14 +202,24 @@ pub async fn init(
         mailer.clone(),
         homeserver,
         url_builder,
+        session_expiration,
+        audit_log_retention,
+        http_client,
+        key_store,
+        webhook_endpoints,
+        upstream_token_refresher,
     );
     let factory = PostgresStorageFactory::new(pool.clone());
     let monitor = Monitor::new().executor(TokioExecutor::new());
     let monitor = self::database::register(name, monitor, &state);
+    let monitor = self::sessions::register(name, monitor, &state);
+    let monitor = self::usage_stats::register(name, monitor, &state);
+    let monitor = self::upstream_oauth2::register(name, monitor, &state);
     let monitor = self::email::register(name, monitor, &state, &factory);
     let monitor = self::matrix::register(name, monitor, &state, &factory);
     let monitor = self::user::register(name, monitor, &state, &factory);
     let monitor = self::recovery::register(name, monitor, &state, &factory);
+    let monitor = self::webhooks::register(name, monitor, &state, &factory);
     // TODO: we might want to grab the join handle here
     factory.listen().await?;
     debug!(?monitor, "workers registered");
@@ -46,7 +46,12 @@ async fn send_account_recovery_email_job(
         .await?
         .context("User recovery session not found")?;
 
-    tracing::Span::current().record("user_recovery_session.email", &session.email);
+    let Some(email) = session.email.as_deref() else {
+        info!("Recovery session wasn't started from an email address, not sending email");
+        return Ok(());
+    };
+
+    tracing::Span::current().record("user_recovery_session.email", email);
 
     if session.consumed_at.is_some() {
         info!("Recovery session already consumed, not sending email");
@@ -64,9 +69,7 @@ async fn send_account_recovery_email_job(
         let page = repo
             .user_email()
             .list(
-                UserEmailFilter::new()
-                    .for_email(&session.email)
-                    .verified_only(),
+                UserEmailFilter::new().for_email(email).verified_only(),
                 cursor,
             )
             .await?;
@@ -0,0 +1,533 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+//! Automatic expiration of sessions which lived for too long, or which were
+//! unused for too long
+
+use std::{collections::BTreeSet, str::FromStr};
+
+use apalis_core::{
+    builder::{WorkerBuilder, WorkerFactoryFn},
+    context::JobContext,
+    executor::TokioExecutor,
+    job::Job,
+    monitor::Monitor,
+    utils::timer::TokioTimer,
+};
+use apalis_cron::CronStream;
+use chrono::{DateTime, Utc};
+use mas_data_model::{AuditLogEventKind, SessionExpiration, SessionExpirationConfig};
+use mas_storage::{
+    audit_log::NewAuditLogEvent,
+    compat::CompatSessionFilter,
+    job::{JobRepositoryExt, NewSignInSession, SendWebhookEventJob, SyncDevicesJob},
+    oauth2::OAuth2SessionFilter,
+    user::BrowserSessionFilter,
+    BoxRepository, Clock, Pagination, RepositoryAccess,
+};
+use rand::RngCore;
+use serde_json::json;
+use tracing::{debug, info, warn};
+use ulid::Ulid;
+
+use crate::{
+    utils::{metrics_layer, trace_layer, TracedJob},
+    JobContextExt, State,
+};
+
+/// How many sessions are ended at once
+const BATCH_SIZE: usize = 100;
+
+#[derive(Default, Clone)]
+pub struct ExpireSessionsJob {
+    scheduled: DateTime<Utc>,
+}
+
+impl From<DateTime<Utc>> for ExpireSessionsJob {
+    fn from(scheduled: DateTime<Utc>) -> Self {
+        Self { scheduled }
+    }
+}
+
+impl Job for ExpireSessionsJob {
+    const NAME: &'static str = "expire-sessions";
+}
+
+impl TracedJob for ExpireSessionsJob {}
+
+/// A limit after which a session is ended
+#[derive(Debug, Clone, Copy)]
+enum Threshold {
+    /// Sessions created before this time are ended
+    CreatedBefore(DateTime<Utc>),
+
+    /// Sessions unused since this time are ended
+    InactiveSince(DateTime<Utc>),
+}
+
+/// Compute the thresholds after which sessions are ended, given the limits and
+/// the current time
+///
+/// Each threshold is applied on its own: a session is ended as soon as it
+/// reaches any of them.
+fn thresholds(expiration: SessionExpiration, now: DateTime<Utc>) -> Vec<Threshold> {
+    let mut thresholds = Vec::new();
+    if let Some(max_lifetime) = expiration.max_lifetime {
+        thresholds.push(Threshold::CreatedBefore(now - max_lifetime));
+    }
+    if let Some(idle_timeout) = expiration.idle_timeout {
+        thresholds.push(Threshold::InactiveSince(now - idle_timeout));
+    }
+    thresholds
+}
+
+/// End the browser sessions matching the filter, one batch at a time
+///
+/// Each ended session is recorded in the audit log with the given reason.
+pub(crate) async fn finish_browser_sessions(
+    repo: &mut BoxRepository,
+    rng: &mut (dyn RngCore + Send),
+    clock: &dyn Clock,
+    filter: BrowserSessionFilter<'_>,
+    reason: &str,
+) -> Result<usize, anyhow::Error> {
+    let mut count = 0;
+    loop {
+        // Sessions are ended as we go, so we always look at the first page
+        let page = repo
+            .browser_session()
+            .list(filter, Pagination::first(BATCH_SIZE))
+            .await?;
+
+        if page.edges.is_empty() {
+            break;
+        }
+
+        for session in page.edges {
+            let session = repo.browser_session().finish(clock, session).await?;
+            repo.audit_log()
+                .add(
+                    rng,
+                    clock,
+                    NewAuditLogEvent::new(AuditLogEventKind::SessionFinished)
+                        .for_user(&session.user)
+                        .with_data(json!({
+                            "session_type": "browser",
+                            "session_id": session.id,
+                            "reason": reason,
+                        })),
+                )
+                .await?;
+            repo.job()
+                .schedule_job(SendWebhookEventJob::session_ended(
+                    session.user.id,
+                    NewSignInSession::Browser(session.id),
+                ))
+                .await?;
+            count += 1;
+        }
+    }
+
+    Ok(count)
+}
+
+/// End the compatibility sessions matching the filter, one batch at a time,
+/// and collect the users they belonged to
+///
+/// Each ended session is recorded in the audit log with the given reason.
+pub(crate) async fn finish_compat_sessions(
+    repo: &mut BoxRepository,
+    rng: &mut (dyn RngCore + Send),
+    clock: &dyn Clock,
+    filter: CompatSessionFilter<'_>,
+    reason: &str,
+    users: &mut BTreeSet<Ulid>,
+) -> Result<usize, anyhow::Error> {
+    let mut count = 0;
+    loop {
+        // Sessions are ended as we go, so we always look at the first page
+        let page = repo
+            .compat_session()
+            .list(filter, Pagination::first(BATCH_SIZE))
+            .await?;
+
+        if page.edges.is_empty() {
+            break;
+        }
+
+        for (session, _) in page.edges {
+            users.insert(session.user_id);
+            let session = repo.compat_session().finish(clock, session).await?;
+            repo.audit_log()
+                .add(
+                    rng,
+                    clock,
+                    NewAuditLogEvent::new(AuditLogEventKind::SessionFinished)
+                        .for_user_id(session.user_id)
+                        .with_data(json!({
+                            "session_type": "compat",
+                            "session_id": session.id,
+                            "reason": reason,
+                        })),
+                )
+                .await?;
+            repo.job()
+                .schedule_job(SendWebhookEventJob::session_ended(
+                    session.user_id,
+                    NewSignInSession::Compat(session.id),
+                ))
+                .await?;
+            count += 1;
+        }
+    }
+
+    Ok(count)
+}
+
+/// End the OAuth 2.0 sessions matching the filter, one batch at a time, and
+/// collect the users they belonged to
+///
+/// Each ended session is recorded in the audit log with the given reason.
+pub(crate) async fn finish_oauth2_sessions(
+    repo: &mut BoxRepository,
+    rng: &mut (dyn RngCore + Send),
+    clock: &dyn Clock,
+    filter: OAuth2SessionFilter<'_>,
+    reason: &str,
+    users: &mut BTreeSet<Ulid>,
+) -> Result<usize, anyhow::Error> {
+    let mut count = 0;
+    loop {
+        // Sessions are ended as we go, so we always look at the first page
+        let page = repo
+            .oauth2_session()
+            .list(filter, Pagination::first(BATCH_SIZE))
+            .await?;
+
+        if page.edges.is_empty() {
+            break;
+        }
+
+        for session in page.edges {
+            users.extend(session.user_id);
+            let session = repo.oauth2_session().finish(clock, session).await?;
+
+            let mut event =
+                NewAuditLogEvent::new(AuditLogEventKind::SessionFinished).with_data(json!({
+                    "session_type": "oauth2",
+                    "session_id": session.id,
+                    "client_id": session.client_id,
+                    "reason": reason,
+                }));
+            if let Some(user_id) = session.user_id {
+                event = event.for_user_id(user_id);
+            }
+            repo.audit_log().add(rng, clock, event).await?;
+
+            // Sessions of clients acting on their own behalf don't concern any user
+            if let Some(user_id) = session.user_id {
+                repo.job()
+                    .schedule_job(SendWebhookEventJob::session_ended(
+                        user_id,
+                        NewSignInSession::OAuth2(session.id),
+                    ))
+                    .await?;
+            }
+            count += 1;
+        }
+    }
+
+    Ok(count)
+}
+
+/// End all the sessions which reached one of the configured limits
+///
+/// Returns the IDs of the users whose compatibility or OAuth 2.0 sessions were
+/// ended, and whose devices need to be synced with the homeserver
+async fn end_expired_sessions(
+    repo: &mut BoxRepository,
+    rng: &mut (dyn RngCore + Send),
+    clock: &dyn Clock,
+    config: &SessionExpirationConfig,
+) -> Result<BTreeSet<Ulid>, anyhow::Error> {
+    let now = clock.now();
+    let mut users = BTreeSet::new();
+
+    for threshold in thresholds(config.browser, now) {
+        let filter = BrowserSessionFilter::new().active_only();
+        let filter = match threshold {
+            Threshold::CreatedBefore(created_before) => filter.with_created_before(created_before),
+            Threshold::InactiveSince(inactive_since) => filter.with_inactive_since(inactive_since),
+        };
+
+        let count = finish_browser_sessions(repo, rng, clock, filter, "expired").await?;
+        if count > 0 {
+            info!(count, ?threshold, "ended expired browser sessions");
+        }
+    }
+
+    for threshold in thresholds(config.compat, now) {
+        let filter = CompatSessionFilter::new().active_only();
+        let filter = match threshold {
+            Threshold::CreatedBefore(created_before) => filter.with_created_before(created_before),
+            Threshold::InactiveSince(inactive_since) => filter.with_inactive_since(inactive_since),
+        };
+
+        let count = finish_compat_sessions(repo, rng, clock, filter, "expired", &mut users).await?;
+        if count > 0 {
+            info!(count, ?threshold, "ended expired compatibility sessions");
+        }
+    }
+
+    // Clients with their own limits are left out of the defaults
+    let overridden: Vec<Ulid> = config.oauth2_clients.keys().copied().collect();
+    let mut oauth2_limits = vec![(None, config.oauth2)];
+    for (client_id, expiration) in &config.oauth2_clients {
+        let Some(client) = repo.oauth2_client().lookup(*client_id).await? else {
+            warn!(client.id = %client_id, "Client with session limits not found");
+            continue;
+        };
+        oauth2_limits.push((Some(client), *expiration));
+    }
+
+    for (client, expiration) in oauth2_limits {
+        for threshold in thresholds(expiration, now) {
+            let filter = OAuth2SessionFilter::new().active_only();
+            let filter = match &client {
+                Some(client) => filter.for_client(client),
+                None => filter.excluding_clients(&overridden),
+            };
+            let filter = match threshold {
+                Threshold::CreatedBefore(created_before) => {
+                    filter.with_created_before(created_before)
+                }
+                Threshold::InactiveSince(inactive_since) => {
+                    filter.with_inactive_since(inactive_since)
+                }
+            };
+
+            let count =
+                finish_oauth2_sessions(repo, rng, clock, filter, "expired", &mut users).await?;
+            if count > 0 {
+                info!(
+                    count,
+                    ?threshold,
+                    client.id = client.as_ref().map(|c| tracing::field::display(c.id)),
+                    "ended expired OAuth 2.0 sessions"
+                );
+            }
+        }
+    }
+
+    Ok(users)
+}
+
+#[tracing::instrument(name = "job.expire_sessions", skip_all, err(Debug))]
+pub async fn expire_sessions(job: ExpireSessionsJob, ctx: JobContext) -> Result<(), anyhow::Error> {
+    debug!("expire sessions job scheduled at {}", job.scheduled);
+
+    let state = ctx.state();
+    let clock = state.clock();
+    let mut rng = state.rng();
+    let mut repo = state.repository().await?;
+
+    let users =
+        end_expired_sessions(&mut repo, &mut rng, &clock, state.session_expiration()).await?;
+
+    // Remove the devices of the ended sessions from the homeserver
+    for user_id in users {
+        let Some(user) = repo.user().lookup(user_id).await? else {
+            continue;
+        };
+        repo.job().schedule_job(SyncDevicesJob::new(&user)).await?;
+    }
+
+    repo.save().await?;
+
+    Ok(())
+}
+
+pub(crate) fn register(
+    suffix: &str,
+    monitor: Monitor<TokioExecutor>,
+    state: &State,
+) -> Monitor<TokioExecutor> {
+    // Expired sessions are looked for every hour
+    let schedule = apalis_cron::Schedule::from_str("0 0 * * * *").unwrap();
+    let worker_name = format!("{job}-{suffix}", job = ExpireSessionsJob::NAME);
+    let worker = WorkerBuilder::new(worker_name)
+        .stream(CronStream::new(schedule).timer(TokioTimer).to_stream())
+        .layer(state.inject())
+        .layer(metrics_layer())
+        .layer(trace_layer())
+        .build_fn(expire_sessions);
+
+    monitor.register(worker)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use mas_data_model::Device;
+    use mas_storage::{
+        audit_log::AuditLogFilter, clock::MockClock, compat::CompatSessionRepository,
+    };
+    use mas_storage_pg::PgRepository;
+    use rand::SeedableRng;
+    use sqlx::PgPool;
+
+    use super::*;
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_end_expired_sessions(pool: PgPool) {
+        let mut rng = rand_chacha::ChaChaRng::seed_from_u64(42);
+        let clock = MockClock::default();
+        let mut repo = PgRepository::from_pool(&pool).await.unwrap().boxed();
+
+        let expiration = SessionExpiration {
+            max_lifetime: Some(Duration::try_days(7).unwrap()),
+            idle_timeout: Some(Duration::try_days(1).unwrap()),
+        };
+        let config = SessionExpirationConfig {
+            browser: expiration,
+            compat: expiration,
+            ..SessionExpirationConfig::default()
+        };
+
+        let user = repo
+            .user()
+            .add(&mut rng, &clock, "alice".to_owned())
+            .await
+            .unwrap();
+
+        // Those sessions will be too old, even though they are still in use
+        let old_browser_session = repo
+            .browser_session()
+            .add(&mut rng, &clock, &user, None)
+            .await
+            .unwrap();
+        let device = Device::generate(&mut rng);
+        let old_compat_session = repo
+            .compat_session()
+            .add(&mut rng, &clock, &user, device, None, false)
+            .await
+            .unwrap();
+
+        clock.advance(Duration::try_days(5).unwrap());
+
+        // Those sessions are young, but will not be used
+        let idle_browser_session = repo
+            .browser_session()
+            .add(&mut rng, &clock, &user, None)
+            .await
+            .unwrap();
+        let device = Device::generate(&mut rng);
+        let idle_compat_session = repo
+            .compat_session()
+            .add(&mut rng, &clock, &user, device, None, false)
+            .await
+            .unwrap();
+
+        clock.advance(Duration::try_days(2).unwrap() + Duration::try_hours(1).unwrap());
+
+        // Those sessions are young and were just created
+        let fresh_browser_session = repo
+            .browser_session()
+            .add(&mut rng, &clock, &user, None)
+            .await
+            .unwrap();
+        let device = Device::generate(&mut rng);
+        let fresh_compat_session = repo
+            .compat_session()
+            .add(&mut rng, &clock, &user, device, None, false)
+            .await
+            .unwrap();
+
+        repo.browser_session()
+            .record_batch_activity(vec![(old_browser_session.id, clock.now(), None)])
+            .await
+            .unwrap();
+        repo.compat_session()
+            .record_batch_activity(vec![(old_compat_session.id, clock.now(), None)])
+            .await
+            .unwrap();
+
+        let users = end_expired_sessions(&mut repo, &mut rng, &clock, &config)
+            .await
+            .unwrap();
+
+        // Only the user of the compatibility sessions needs its devices synced
+        assert_eq!(users, BTreeSet::from([user.id]));
+
+        for (id, finished) in [
+            (old_browser_session.id, true),
+            (idle_browser_session.id, true),
+            (fresh_browser_session.id, false),
+        ] {
+            let session = repo.browser_session().lookup(id).await.unwrap().unwrap();
+            assert_eq!(session.finished_at.is_some(), finished);
+        }
+
+        for (id, finished) in [
+            (old_compat_session.id, true),
+            (idle_compat_session.id, true),
+            (fresh_compat_session.id, false),
+        ] {
+            let session = repo.compat_session().lookup(id).await.unwrap().unwrap();
+            assert_eq!(session.is_finished(), finished);
+        }
+
+        repo.save().await.unwrap();
+
+        // A session.ended event is sent for each of the ended sessions
+        let jobs: Vec<sqlx::types::Json<serde_json::Value>> =
+            sqlx::query_scalar("SELECT job FROM apalis.jobs WHERE job_type = 'send-webhook-event'")
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        let mut ended: Vec<serde_json::Value> = jobs
+            .iter()
+            .inspect(|job| assert_eq!(job["kind"], "session.ended"))
+            .map(|job| job["data"]["session"].clone())
+            .collect();
+        ended.sort_by_key(|session| session["id"].to_string());
+
+        let mut expected = vec![
+            serde_json::json!({"kind": "browser", "id": old_browser_session.id}),
+            serde_json::json!({"kind": "browser", "id": idle_browser_session.id}),
+            serde_json::json!({"kind": "compat", "id": old_compat_session.id}),
+            serde_json::json!({"kind": "compat", "id": idle_compat_session.id}),
+        ];
+        expected.sort_by_key(|session| session["id"].to_string());
+
+        assert_eq!(ended, expected);
+
+        // Each of them is also recorded in the audit log
+        let mut repo = PgRepository::from_pool(&pool).await.unwrap().boxed();
+        let events = repo
+            .audit_log()
+            .list(
+                AuditLogFilter::new()
+                    .for_user(&user)
+                    .with_kind(AuditLogEventKind::SessionFinished),
+                Pagination::first(10),
+            )
+            .await
+            .unwrap();
+        let mut audited: Vec<serde_json::Value> = events
+            .edges
+            .iter()
+            .inspect(|event| assert_eq!(event.data["reason"], "expired"))
+            .map(|event| {
+                serde_json::json!({
+                    "kind": event.data["session_type"],
+                    "id": event.data["session_id"],
+                })
+            })
+            .collect();
+        audited.sort_by_key(|session| session["id"].to_string());
+        assert_eq!(audited, expected);
+    }
+}
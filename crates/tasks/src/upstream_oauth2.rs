@@ -0,0 +1,78 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+//! Periodic refresh of the tokens kept from the upstream providers
+
+use std::str::FromStr;
+
+use apalis_core::{
+    builder::{WorkerBuilder, WorkerFactoryFn},
+    context::JobContext,
+    executor::TokioExecutor,
+    job::Job,
+    monitor::Monitor,
+    utils::timer::TokioTimer,
+};
+use apalis_cron::CronStream;
+use chrono::{DateTime, Utc};
+use tracing::debug;
+
+use crate::{
+    utils::{metrics_layer, trace_layer, TracedJob},
+    JobContextExt, State,
+};
+
+#[derive(Default, Clone)]
+pub struct RefreshUpstreamTokensJob {
+    scheduled: DateTime<Utc>,
+}
+
+impl From<DateTime<Utc>> for RefreshUpstreamTokensJob {
+    fn from(scheduled: DateTime<Utc>) -> Self {
+        Self { scheduled }
+    }
+}
+
+impl Job for RefreshUpstreamTokensJob {
+    const NAME: &'static str = "refresh-upstream-tokens";
+}
+
+impl TracedJob for RefreshUpstreamTokensJob {}
+
+pub async fn refresh_upstream_tokens(
+    job: RefreshUpstreamTokensJob,
+    ctx: JobContext,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
+    debug!("refresh upstream tokens job scheduled at {}", job.scheduled);
+
+    let state = ctx.state();
+    let clock = state.clock();
+    let mut rng = state.rng();
+
+    state
+        .upstream_token_refresher()
+        .refresh_expiring(&mut rng, &clock, state.pool())
+        .await?;
+
+    Ok(())
+}
+
+pub(crate) fn register(
+    suffix: &str,
+    monitor: Monitor<TokioExecutor>,
+    state: &State,
+) -> Monitor<TokioExecutor> {
+    // The tokens about to expire are looked for every minute
+    let schedule = apalis_cron::Schedule::from_str("0 * * * * *").unwrap();
+    let worker_name = format!("{job}-{suffix}", job = RefreshUpstreamTokensJob::NAME);
+    let worker = WorkerBuilder::new(worker_name)
+        .stream(CronStream::new(schedule).timer(TokioTimer).to_stream())
+        .layer(state.inject())
+        .layer(metrics_layer())
+        .layer(trace_layer())
+        .build_fn(refresh_upstream_tokens);
+
+    monitor.register(worker)
+}
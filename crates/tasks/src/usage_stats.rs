@@ -0,0 +1,153 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+//! Periodic computation of the usage statistics
+
+use std::{str::FromStr, sync::LazyLock};
+
+use apalis_core::{
+    builder::{WorkerBuilder, WorkerFactoryFn},
+    context::JobContext,
+    executor::TokioExecutor,
+    job::Job,
+    monitor::Monitor,
+    utils::timer::TokioTimer,
+};
+use apalis_cron::CronStream;
+use chrono::{DateTime, Utc};
+use mas_data_model::UsageStats;
+use mas_storage::{Clock, RepositoryAccess};
+use opentelemetry::{metrics::Gauge, Key, KeyValue};
+use tracing::{debug, info};
+
+use crate::{
+    utils::{metrics_layer, trace_layer, TracedJob},
+    JobContextExt, State,
+};
+
+const METHOD: Key = Key::from_static_str("method");
+
+static DAILY_ACTIVE_USERS: LazyLock<Gauge<u64>> = LazyLock::new(|| {
+    mas_tower::meter()
+        .u64_gauge("mas.usage.daily_active_users")
+        .with_description("The number of users with a session active today")
+        .with_unit("{user}")
+        .init()
+});
+
+static MONTHLY_ACTIVE_USERS: LazyLock<Gauge<u64>> = LazyLock::new(|| {
+    mas_tower::meter()
+        .u64_gauge("mas.usage.monthly_active_users")
+        .with_description("The number of users with a session active in the last 30 days")
+        .with_unit("{user}")
+        .init()
+});
+
+static REGISTRATIONS: LazyLock<Gauge<u64>> = LazyLock::new(|| {
+    mas_tower::meter()
+        .u64_gauge("mas.usage.registrations")
+        .with_description("The number of users who registered today")
+        .with_unit("{user}")
+        .init()
+});
+
+static LOGINS: LazyLock<Gauge<u64>> = LazyLock::new(|| {
+    mas_tower::meter()
+        .u64_gauge("mas.usage.logins")
+        .with_description("The number of logins today, by method")
+        .with_unit("{login}")
+        .init()
+});
+
+fn record_metrics(stats: &UsageStats) {
+    DAILY_ACTIVE_USERS.record(stats.daily_active_users, &[]);
+    MONTHLY_ACTIVE_USERS.record(stats.monthly_active_users, &[]);
+    REGISTRATIONS.record(stats.registrations, &[]);
+    LOGINS.record(stats.password_logins, &[KeyValue::new(METHOD, "password")]);
+    LOGINS.record(
+        stats.upstream_oauth2_logins,
+        &[KeyValue::new(METHOD, "upstream_oauth2")],
+    );
+    LOGINS.record(stats.compat_logins, &[KeyValue::new(METHOD, "compat")]);
+}
+
+#[derive(Default, Clone)]
+pub struct ComputeUsageStatsJob {
+    scheduled: DateTime<Utc>,
+}
+
+impl From<DateTime<Utc>> for ComputeUsageStatsJob {
+    fn from(scheduled: DateTime<Utc>) -> Self {
+        Self { scheduled }
+    }
+}
+
+impl Job for ComputeUsageStatsJob {
+    const NAME: &'static str = "compute-usage-stats";
+}
+
+impl TracedJob for ComputeUsageStatsJob {}
+
+pub async fn compute_usage_stats(
+    job: ComputeUsageStatsJob,
+    ctx: JobContext,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
+    debug!("compute usage stats job scheduled at {}", job.scheduled);
+
+    let state = ctx.state();
+    let clock = state.clock();
+    let mut rng = state.rng();
+    let mut repo = state.repository().await?;
+
+    let today = clock.now().date_naive();
+
+    // Finalize the statistics of the previous day, unless they were already
+    // computed after it ended
+    if let Some(yesterday) = today.pred_opt() {
+        let previous = repo.usage_stats().find_by_day(yesterday).await?;
+        if !previous.is_some_and(|previous| previous.is_final()) {
+            let finalized = repo
+                .usage_stats()
+                .record(&mut rng, &clock, yesterday)
+                .await?;
+            info!(
+                day = %finalized.day,
+                daily_active_users = finalized.daily_active_users,
+                monthly_active_users = finalized.monthly_active_users,
+                "finalized usage stats",
+            );
+        }
+    }
+
+    let current = repo.usage_stats().record(&mut rng, &clock, today).await?;
+    repo.save().await?;
+
+    debug!(
+        daily_active_users = current.daily_active_users,
+        monthly_active_users = current.monthly_active_users,
+        "computed usage stats",
+    );
+    record_metrics(&current);
+
+    Ok(())
+}
+
+pub(crate) fn register(
+    suffix: &str,
+    monitor: Monitor<TokioExecutor>,
+    state: &State,
+) -> Monitor<TokioExecutor> {
+    // The statistics of the current day are refreshed every hour
+    let schedule = apalis_cron::Schedule::from_str("0 0 * * * *").unwrap();
+    let worker_name = format!("{job}-{suffix}", job = ComputeUsageStatsJob::NAME);
+    let worker = WorkerBuilder::new(worker_name)
+        .stream(CronStream::new(schedule).timer(TokioTimer).to_stream())
+        .layer(state.inject())
+        .layer(metrics_layer())
+        .layer(trace_layer())
+        .build_fn(compute_usage_stats);
+
+    monitor.register(worker)
+}
@@ -4,18 +4,27 @@
 // SPDX-License-Identifier: AGPL-3.0-only
 // Please see LICENSE in the repository root for full details.
 
+use std::collections::BTreeSet;
+
 use anyhow::Context;
 use apalis_core::{context::JobContext, executor::TokioExecutor, monitor::Monitor};
 use mas_storage::{
     compat::CompatSessionFilter,
-    job::{DeactivateUserJob, JobWithSpanContext, ReactivateUserJob},
+    job::{
+        DeactivateUserJob, JobRepositoryExt as _, JobWithSpanContext, ReactivateUserJob,
+        SendWebhookEventJob,
+    },
     oauth2::OAuth2SessionFilter,
     user::{BrowserSessionFilter, UserRepository},
     RepositoryAccess,
 };
 use tracing::info;
 
-use crate::{storage::PostgresStorageFactory, JobContextExt, State};
+use crate::{
+    sessions::{finish_browser_sessions, finish_compat_sessions, finish_oauth2_sessions},
+    storage::PostgresStorageFactory,
+    JobContextExt, State,
+};
 
 /// Job to deactivate a user, both locally and on the Matrix homeserver.
 #[tracing::instrument(
@@ -30,6 +39,7 @@ async fn deactivate_user(
 ) -> Result<(), anyhow::Error> {
     let state = ctx.state();
     let clock = state.clock();
+    let mut rng = state.rng();
     let matrix = state.matrix_connection();
     let mut repo = state.repository().await?;
 
@@ -46,34 +56,45 @@ async fn deactivate_user(
         .await
         .context("Failed to lock user")?;
 
-    // Kill all sessions for the user
-    let n = repo
-        .browser_session()
-        .finish_bulk(
-            &clock,
-            BrowserSessionFilter::new().for_user(&user).active_only(),
-        )
-        .await?;
+    // Kill all sessions for the user. The devices don't need to be synced with
+    // the homeserver, as the user is deleted there right after.
+    let mut users = BTreeSet::new();
+    let n = finish_browser_sessions(
+        &mut repo,
+        &mut rng,
+        &clock,
+        BrowserSessionFilter::new().for_user(&user).active_only(),
+        "deactivated",
+    )
+    .await?;
     info!(affected = n, "Killed all browser sessions for user");
 
-    let n = repo
-        .oauth2_session()
-        .finish_bulk(
-            &clock,
-            OAuth2SessionFilter::new().for_user(&user).active_only(),
-        )
-        .await?;
+    let n = finish_oauth2_sessions(
+        &mut repo,
+        &mut rng,
+        &clock,
+        OAuth2SessionFilter::new().for_user(&user).active_only(),
+        "deactivated",
+        &mut users,
+    )
+    .await?;
     info!(affected = n, "Killed all OAuth 2.0 sessions for user");
 
-    let n = repo
-        .compat_session()
-        .finish_bulk(
-            &clock,
-            CompatSessionFilter::new().for_user(&user).active_only(),
-        )
-        .await?;
+    let n = finish_compat_sessions(
+        &mut repo,
+        &mut rng,
+        &clock,
+        CompatSessionFilter::new().for_user(&user).active_only(),
+        "deactivated",
+        &mut users,
+    )
+    .await?;
     info!(affected = n, "Killed all compatibility sessions for user");
 
+    repo.job()
+        .schedule_job(SendWebhookEventJob::user_deactivated(&user))
+        .await?;
+
     // Before calling back to the homeserver, commit the changes to the database, as
     // we want the user to be locked out as soon as possible
     repo.save().await?;
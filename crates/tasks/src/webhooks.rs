@@ -0,0 +1,314 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+use std::fmt::Write as _;
+
+use anyhow::Context;
+use apalis_core::{context::JobContext, executor::TokioExecutor, monitor::Monitor};
+use chrono::Duration;
+use hmac::{Hmac, Mac};
+use mas_data_model::{WebhookEndpoint, WebhookSigning};
+use mas_http::RequestBuilderExt;
+use mas_jose::{
+    constraints::Constrainable,
+    jwt::{JsonWebSignatureHeader, Jwt},
+};
+use mas_storage::{
+    job::{DeliverWebhookJob, JobRepositoryExt as _, JobWithSpanContext, SendWebhookEventJob},
+    Clock, RepositoryAccess,
+};
+use rand::{CryptoRng, RngCore};
+use reqwest::header::CONTENT_TYPE;
+use sha2::Sha256;
+use tracing::{debug, info, warn};
+
+use crate::{storage::PostgresStorageFactory, JobContextExt, State};
+
+/// How many times we try to deliver an event before giving up
+const MAX_ATTEMPTS: u32 = 10;
+
+/// How long we wait for the endpoint to respond
+const REQUEST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// How long to wait before the next attempt, after the given number of
+/// failed attempts: 30 seconds, doubling each time, up to 6 hours.
+fn backoff(attempts: u32) -> Duration {
+    let seconds = 30_i64 << attempts.saturating_sub(1).min(10);
+    Duration::seconds(seconds).min(Duration::hours(6))
+}
+
+/// Compute the hex-encoded HMAC-SHA256 of the request, which covers both the
+/// timestamp and the body, so that a captured request can't be replayed
+/// later with another timestamp
+fn sign(secret: &[u8], timestamp: i64, body: &[u8]) -> Result<String, hmac::digest::InvalidLength> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret)?;
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    let signature =
+        mac.finalize()
+            .into_bytes()
+            .iter()
+            .fold(String::with_capacity(64), |mut acc, byte| {
+                let _ = write!(acc, "{byte:02x}");
+                acc
+            });
+
+    Ok(signature)
+}
+
+#[tracing::instrument(
+    name = "job.send_webhook_event",
+    fields(webhook_event.kind = %job.kind()),
+    skip_all,
+    err(Debug),
+)]
+async fn send_webhook_event(
+    job: JobWithSpanContext<SendWebhookEventJob>,
+    ctx: JobContext,
+) -> Result<(), anyhow::Error> {
+    let state = ctx.state();
+    let clock = state.clock();
+    let mut rng = state.rng();
+
+    let endpoints: Vec<&WebhookEndpoint> = state
+        .webhook_endpoints()
+        .iter()
+        .filter(|endpoint| endpoint.wants(job.kind()))
+        .collect();
+
+    if endpoints.is_empty() {
+        debug!("No webhook endpoint is interested in this event");
+        return Ok(());
+    }
+
+    let mut repo = state.repository().await?;
+
+    for endpoint in endpoints {
+        let delivery = repo
+            .webhook_delivery()
+            .add(&mut rng, &clock, endpoint, job.kind(), job.data().clone())
+            .await?;
+
+        repo.job()
+            .schedule_job(DeliverWebhookJob::new(&delivery))
+            .await?;
+
+        info!(
+            webhook_endpoint.id = %endpoint.id,
+            webhook_delivery.id = %delivery.id,
+            "Scheduled webhook delivery"
+        );
+    }
+
+    repo.save().await?;
+
+    Ok(())
+}
+
+/// Send the event to the endpoint, signed as configured
+async fn send(
+    state: &State,
+    rng: &mut (impl RngCore + CryptoRng + Send),
+    clock: &dyn Clock,
+    endpoint: &WebhookEndpoint,
+    event: serde_json::Value,
+) -> Result<(), anyhow::Error> {
+    let request = state
+        .http_client()
+        .post(endpoint.url.clone())
+        .timeout(REQUEST_TIMEOUT);
+
+    let request = match &endpoint.signing {
+        WebhookSigning::Hmac { secret } => {
+            let body = serde_json::to_vec(&event)?;
+            let timestamp = clock.now().timestamp();
+            let signature = sign(secret.as_bytes(), timestamp, &body)?;
+
+            request
+                .header(CONTENT_TYPE, "application/json")
+                .header("X-MAS-Timestamp", timestamp.to_string())
+                .header("X-MAS-Signature", format!("sha256={signature}"))
+                .body(body)
+        }
+
+        WebhookSigning::Jws { algorithm } => {
+            let key = state
+                .key_store()
+                .signing_key_for_algorithm(algorithm)
+                .with_context(|| format!("No key in the keystore can sign with {algorithm}"))?;
+            let signer = key.params().signing_key_for_alg(algorithm)?;
+            let header = JsonWebSignatureHeader::new(algorithm.clone())
+                .with_kid(key.kid().context("The signing key has no key ID")?);
+            let jwt = Jwt::sign_with_rng(rng, header, event, &signer)?;
+
+            request
+                .header(CONTENT_TYPE, "application/jwt")
+                .body(jwt.into_string())
+        }
+    };
+
+    request.send_traced().await?.error_for_status()?;
+
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "job.deliver_webhook",
+    fields(webhook_delivery.id = %job.delivery_id()),
+    skip_all,
+    err(Debug),
+)]
+async fn deliver_webhook(
+    job: JobWithSpanContext<DeliverWebhookJob>,
+    ctx: JobContext,
+) -> Result<(), anyhow::Error> {
+    let state = ctx.state();
+    let clock = state.clock();
+    let mut rng = state.rng();
+
+    // Load the delivery in a short transaction, so that no database connection
+    // is held while waiting for the endpoint to respond
+    let mut repo = state.repository().await?;
+    let delivery = repo
+        .webhook_delivery()
+        .lookup(job.delivery_id())
+        .await?
+        .context("Webhook delivery not found")?;
+
+    if !delivery.is_pending() {
+        info!("Webhook delivery already completed, skipping");
+        return Ok(());
+    }
+
+    let Some(endpoint) = state
+        .webhook_endpoints()
+        .iter()
+        .find(|endpoint| endpoint.id == delivery.endpoint_id)
+    else {
+        warn!(
+            webhook_endpoint.id = %delivery.endpoint_id,
+            "Webhook endpoint is not configured anymore, giving up"
+        );
+        repo.webhook_delivery()
+            .mark_as_failed(&clock, delivery)
+            .await?;
+        repo.save().await?;
+        return Ok(());
+    };
+
+    repo.save().await?;
+
+    let event = serde_json::json!({
+        "id": delivery.id,
+        "type": delivery.kind,
+        "created_at": delivery.created_at,
+        "data": delivery.payload,
+    });
+
+    let result = send(&state, &mut rng, &clock, endpoint, event).await;
+
+    // Record the result in a new transaction
+    let mut repo = state.repository().await?;
+    match result {
+        Ok(()) => {
+            repo.webhook_delivery()
+                .mark_as_delivered(&clock, delivery)
+                .await?;
+
+            info!("Webhook delivered");
+        }
+
+        Err(e) => {
+            let delivery = repo
+                .webhook_delivery()
+                .record_failed_attempt(&clock, delivery, format!("{e:#}"))
+                .await?;
+
+            if delivery.attempts >= MAX_ATTEMPTS {
+                warn!(
+                    error = &*e as &dyn std::error::Error,
+                    attempts = delivery.attempts,
+                    "Failed to deliver webhook, giving up"
+                );
+
+                repo.webhook_delivery()
+                    .mark_as_failed(&clock, delivery)
+                    .await?;
+            } else {
+                let run_at = clock.now() + backoff(delivery.attempts);
+                warn!(
+                    error = &*e as &dyn std::error::Error,
+                    attempts = delivery.attempts,
+                    %run_at,
+                    "Failed to deliver webhook, will retry"
+                );
+
+                repo.job()
+                    .schedule_job_at(DeliverWebhookJob::new(&delivery), run_at)
+                    .await?;
+            }
+        }
+    }
+
+    repo.save().await?;
+
+    Ok(())
+}
+
+pub(crate) fn register(
+    suffix: &str,
+    monitor: Monitor<TokioExecutor>,
+    state: &State,
+    storage_factory: &PostgresStorageFactory,
+) -> Monitor<TokioExecutor> {
+    let send_webhook_event_worker =
+        crate::build!(SendWebhookEventJob => send_webhook_event, suffix, state, storage_factory);
+    let deliver_webhook_worker =
+        crate::build!(DeliverWebhookJob => deliver_webhook, suffix, state, storage_factory);
+
+    monitor
+        .register(send_webhook_event_worker)
+        .register(deliver_webhook_worker)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::{backoff, sign};
+
+    #[test]
+    fn test_backoff() {
+        assert_eq!(backoff(0), Duration::seconds(30));
+        assert_eq!(backoff(1), Duration::seconds(30));
+        assert_eq!(backoff(2), Duration::seconds(60));
+        assert_eq!(backoff(3), Duration::minutes(2));
+        assert_eq!(backoff(5), Duration::minutes(8));
+        assert_eq!(backoff(10), Duration::seconds(15360));
+
+        // The delay is capped at 6 hours
+        assert_eq!(backoff(11), Duration::hours(6));
+        assert_eq!(backoff(12), Duration::hours(6));
+        assert_eq!(backoff(u32::MAX), Duration::hours(6));
+    }
+
+    #[test]
+    fn test_sign() {
+        // Computed with:
+        // printf '1700000000.{"type":"user.registered"}' \
+        //     | openssl dgst -sha256 -hmac hunter2
+        assert_eq!(
+            sign(b"hunter2", 1_700_000_000, br#"{"type":"user.registered"}"#).unwrap(),
+            "a437efa8039f4f299546910e325d4d7f6cbd58c0654202360f727f3302e14888",
+        );
+
+        // The timestamp is covered by the signature
+        assert_ne!(
+            sign(b"hunter2", 1_700_000_001, br#"{"type":"user.registered"}"#).unwrap(),
+            sign(b"hunter2", 1_700_000_000, br#"{"type":"user.registered"}"#).unwrap(),
+        );
+    }
+}
@@ -21,7 +21,7 @@ use http::{Method, Uri, Version};
 use mas_data_model::{
     AuthorizationGrant, BrowserSession, Client, CompatSsoLogin, CompatSsoLoginState,
     DeviceCodeGrant, UpstreamOAuthLink, UpstreamOAuthProvider, User, UserAgent, UserEmail,
-    UserEmailVerification, UserRecoverySession,
+    UserEmailVerification, UserLoginLockout, UserRecoverySession,
 };
 use mas_i18n::DataLocale;
 use mas_router::{Account, GraphQL, PostAuthAction, UrlBuilder};
@@ -37,7 +37,7 @@ use url::Url;
 pub use self::{
     branding::SiteBranding, captcha::WithCaptcha, ext::SiteConfigExt, features::SiteFeatures,
 };
-use crate::{FieldError, FormField, FormState};
+use crate::{FieldError, FormError, FormField, FormState};
 
 /// Helper trait to construct context wrappers
 pub trait TemplateContext: Serialize {
@@ -404,6 +404,9 @@ pub enum PostAuthContextInner {
     /// Change the account password
     ChangePassword,
 
+    /// Recover the account by signing in with a linked upstream provider
+    RecoverAccount,
+
     /// Link an upstream account
     LinkUpstream {
         /// The upstream provider
@@ -434,6 +437,7 @@ pub struct LoginContext {
     form: FormState<LoginFormField>,
     next: Option<PostAuthContext>,
     providers: Vec<UpstreamOAuthProvider>,
+    discovery: bool,
 }
 
 impl TemplateContext for LoginContext {
@@ -447,11 +451,13 @@ impl TemplateContext for LoginContext {
                 form: FormState::default(),
                 next: None,
                 providers: Vec::new(),
+                discovery: false,
             },
             LoginContext {
                 form: FormState::default(),
                 next: None,
                 providers: Vec::new(),
+                discovery: true,
             },
             LoginContext {
                 form: FormState::default()
@@ -464,12 +470,20 @@ impl TemplateContext for LoginContext {
                     ),
                 next: None,
                 providers: Vec::new(),
+                discovery: false,
             },
             LoginContext {
                 form: FormState::default()
                     .with_error_on_field(LoginFormField::Username, FieldError::Exists),
                 next: None,
                 providers: Vec::new(),
+                discovery: false,
+            },
+            LoginContext {
+                form: FormState::default().with_error_on_form(FormError::NoMatchingProvider),
+                next: None,
+                providers: Vec::new(),
+                discovery: true,
             },
         ]
     }
@@ -493,6 +507,13 @@ impl LoginContext {
         Self { providers, ..self }
     }
 
+    /// Ask for the user identifier first, to find out which upstream provider
+    /// they should be sent to
+    #[must_use]
+    pub fn with_discovery(self, discovery: bool) -> Self {
+        Self { discovery, ..self }
+    }
+
     /// Add a post authentication action to the context
     #[must_use]
     pub fn with_post_action(self, context: PostAuthContext) -> Self {
@@ -521,12 +542,15 @@ pub enum RegisterFormField {
 
     /// The terms of service agreement field
     AcceptTerms,
+
+    /// The registration token field
+    RegistrationToken,
 }
 
 impl FormField for RegisterFormField {
     fn keep(&self) -> bool {
         match self {
-            Self::Username | Self::Email | Self::AcceptTerms => true,
+            Self::Username | Self::Email | Self::AcceptTerms | Self::RegistrationToken => true,
             Self::Password | Self::PasswordConfirm => false,
         }
     }
@@ -817,7 +841,7 @@ impl TemplateContext for EmailRecoveryContext {
         User::samples(now, rng).into_iter().map(|user| {
             let session = UserRecoverySession {
                 id: Ulid::from_datetime_with_source(now.into(), rng),
-                email: "hello@example.com".to_owned(),
+                email: Some("hello@example.com".to_owned()),
                 user_agent: UserAgent::parse("Mozilla/5.0 (Macintosh; Intel Mac OS X 10_8_4) AppleWebKit/536.30.1 (KHTML, like Gecko) Version/6.0.5 Safari/536.30.1".to_owned()),
                 ip_address: Some(IpAddr::from([192_u8, 0, 2, 1])),
                 locale: "en".to_owned(),
@@ -832,6 +856,128 @@ impl TemplateContext for EmailRecoveryContext {
     }
 }
 
+/// Context used by the `emails/login_lockout.{txt,html,subject}` templates
+#[derive(Serialize)]
+pub struct EmailLoginLockoutContext {
+    user: User,
+    lockout: UserLoginLockout,
+}
+
+impl EmailLoginLockoutContext {
+    /// Constructs a context for the login lockout email
+    #[must_use]
+    pub fn new(user: User, lockout: UserLoginLockout) -> Self {
+        Self { user, lockout }
+    }
+
+    /// Returns the user whose account was locked out
+    #[must_use]
+    pub fn user(&self) -> &User {
+        &self.user
+    }
+
+    /// Returns the lockout the email is about
+    #[must_use]
+    pub fn lockout(&self) -> &UserLoginLockout {
+        &self.lockout
+    }
+}
+
+impl TemplateContext for EmailLoginLockoutContext {
+    fn sample(now: chrono::DateTime<Utc>, rng: &mut impl Rng) -> Vec<Self>
+    where
+        Self: Sized,
+    {
+        User::samples(now, rng)
+            .into_iter()
+            .map(|user| {
+                let lockout = UserLoginLockout {
+                    user_id: user.id,
+                    failed_attempts: 10,
+                    last_failed_at: now,
+                    locked_until: Some(now + Duration::minutes(15)),
+                };
+
+                Self::new(user, lockout)
+            })
+            .collect()
+    }
+}
+
+/// Context used by the `emails/new_sign_in.{txt,html,subject}` templates
+#[derive(Serialize)]
+pub struct EmailNewSignInContext {
+    user: User,
+    user_agent: Option<UserAgent>,
+    ip_address: Option<IpAddr>,
+    location: Option<String>,
+    signed_in_at: DateTime<Utc>,
+    session_link: Url,
+}
+
+impl EmailNewSignInContext {
+    /// Constructs a context for the new sign-in email
+    #[must_use]
+    pub fn new(user: User, signed_in_at: DateTime<Utc>, session_link: Url) -> Self {
+        Self {
+            user,
+            user_agent: None,
+            ip_address: None,
+            location: None,
+            signed_in_at,
+            session_link,
+        }
+    }
+
+    /// Set the user agent of the new session
+    #[must_use]
+    pub fn with_user_agent(self, user_agent: Option<UserAgent>) -> Self {
+        Self { user_agent, ..self }
+    }
+
+    /// Set the IP address from which the user signed in
+    #[must_use]
+    pub fn with_ip_address(self, ip_address: Option<IpAddr>) -> Self {
+        Self { ip_address, ..self }
+    }
+
+    /// Set the approximate location from which the user signed in
+    #[must_use]
+    pub fn with_location(self, location: Option<String>) -> Self {
+        Self { location, ..self }
+    }
+
+    /// Returns the user who signed in
+    #[must_use]
+    pub fn user(&self) -> &User {
+        &self.user
+    }
+}
+
+impl TemplateContext for EmailNewSignInContext {
+    fn sample(now: chrono::DateTime<Utc>, rng: &mut impl Rng) -> Vec<Self>
+    where
+        Self: Sized,
+    {
+        User::samples(now, rng)
+            .into_iter()
+            .map(|user| {
+                let session_id = Ulid::from_datetime_with_source(now.into(), rng);
+                let link = format!(
+                    "https://example.com/account/sessions/browser_session:{session_id}"
+                )
+                .parse()
+                .unwrap();
+
+                Self::new(user, now, link)
+                    .with_user_agent(Some(UserAgent::parse("Mozilla/5.0 (Macintosh; Intel Mac OS X 10_8_4) AppleWebKit/536.30.1 (KHTML, like Gecko) Version/6.0.5 Safari/536.30.1".to_owned())))
+                    .with_ip_address(Some(IpAddr::from([192_u8, 0, 2, 1])))
+                    .with_location(Some("Paris, France".to_owned()))
+            })
+            .collect()
+    }
+}
+
 /// Context used by the `emails/verification.{txt,html,subject}` templates
 #[derive(Serialize)]
 pub struct EmailVerificationContext {
@@ -1014,6 +1160,7 @@ impl FormField for RecoveryStartFormField {
 #[derive(Serialize, Default)]
 pub struct RecoveryStartContext {
     form: FormState<RecoveryStartFormField>,
+    providers: Vec<UpstreamOAuthProvider>,
 }
 
 impl RecoveryStartContext {
@@ -1026,7 +1173,14 @@ impl RecoveryStartContext {
     /// Set the form state
     #[must_use]
     pub fn with_form_state(self, form: FormState<RecoveryStartFormField>) -> Self {
-        Self { form }
+        Self { form, ..self }
+    }
+
+    /// Set the upstream OAuth 2.0 providers users can recover their account
+    /// with
+    #[must_use]
+    pub fn with_upstream_providers(self, providers: Vec<UpstreamOAuthProvider>) -> Self {
+        Self { providers, ..self }
     }
 }
 
@@ -1075,7 +1229,7 @@ impl TemplateContext for RecoveryProgressContext {
     {
         let session = UserRecoverySession {
             id: Ulid::from_datetime_with_source(now.into(), rng),
-            email: "name@mail.com".to_owned(),
+            email: Some("name@mail.com".to_owned()),
             user_agent: UserAgent::parse("Mozilla/5.0".to_owned()),
             ip_address: None,
             locale: "en".to_owned(),
@@ -1117,7 +1271,7 @@ impl TemplateContext for RecoveryExpiredContext {
     {
         let session = UserRecoverySession {
             id: Ulid::from_datetime_with_source(now.into(), rng),
-            email: "name@mail.com".to_owned(),
+            email: Some("name@mail.com".to_owned()),
             user_agent: UserAgent::parse("Mozilla/5.0".to_owned()),
             ip_address: None,
             locale: "en".to_owned(),
@@ -1226,6 +1380,31 @@ impl TemplateContext for UpstreamExistingLinkContext {
     }
 }
 
+/// Context used by the `pages/upstream_oauth2/denied.html` template
+#[derive(Serialize)]
+pub struct UpstreamDeniedContext {
+    violations: Vec<String>,
+}
+
+impl UpstreamDeniedContext {
+    /// Constructs a new context with the messages of the policy violations
+    #[must_use]
+    pub fn new(violations: Vec<String>) -> Self {
+        Self { violations }
+    }
+}
+
+impl TemplateContext for UpstreamDeniedContext {
+    fn sample(_now: chrono::DateTime<Utc>, _rng: &mut impl Rng) -> Vec<Self>
+    where
+        Self: Sized,
+    {
+        vec![Self::new(vec![
+            "the department of the upstream account is not allowed".to_owned(),
+        ])]
+    }
+}
+
 /// Context used by the `pages/upstream_oauth2/suggest_link.html`
 /// templates
 #[derive(Serialize)]
@@ -1265,12 +1444,15 @@ pub enum UpstreamRegisterFormField {
 
     /// Accept the terms of service
     AcceptTerms,
+
+    /// The registration token field
+    RegistrationToken,
 }
 
 impl FormField for UpstreamRegisterFormField {
     fn keep(&self) -> bool {
         match self {
-            Self::Username | Self::AcceptTerms => true,
+            Self::Username | Self::AcceptTerms | Self::RegistrationToken => true,
         }
     }
 }
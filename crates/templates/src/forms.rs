@@ -34,6 +34,9 @@ pub enum FieldError {
     /// That value already exists
     Exists,
 
+    /// The password was found in a list of known breached passwords
+    PasswordBreached,
+
     /// Denied by the policy
     Policy {
         /// Message for this policy violation
@@ -65,6 +68,9 @@ pub enum FormError {
 
     /// Failed to validate CAPTCHA
     Captcha,
+
+    /// No upstream provider matches the given identifier
+    NoMatchingProvider,
 }
 
 #[derive(Debug, Default, Serialize)]
@@ -35,13 +35,14 @@ mod macros;
 pub use self::{
     context::{
         ApiDocContext, AppContext, CompatSsoContext, ConsentContext, DeviceConsentContext,
-        DeviceLinkContext, DeviceLinkFormField, EmailAddContext, EmailRecoveryContext,
-        EmailVerificationContext, EmailVerificationPageContext, EmptyContext, ErrorContext,
-        FormPostContext, IndexContext, LoginContext, LoginFormField, NotFoundContext,
-        PolicyViolationContext, PostAuthContext, PostAuthContextInner, ReauthContext,
-        ReauthFormField, RecoveryExpiredContext, RecoveryFinishContext, RecoveryFinishFormField,
-        RecoveryProgressContext, RecoveryStartContext, RecoveryStartFormField, RegisterContext,
-        RegisterFormField, SiteBranding, SiteConfigExt, SiteFeatures, TemplateContext,
+        DeviceLinkContext, DeviceLinkFormField, EmailAddContext, EmailLoginLockoutContext,
+        EmailNewSignInContext, EmailRecoveryContext, EmailVerificationContext,
+        EmailVerificationPageContext, EmptyContext, ErrorContext, FormPostContext, IndexContext,
+        LoginContext, LoginFormField, NotFoundContext, PolicyViolationContext, PostAuthContext,
+        PostAuthContextInner, ReauthContext, ReauthFormField, RecoveryExpiredContext,
+        RecoveryFinishContext, RecoveryFinishFormField, RecoveryProgressContext,
+        RecoveryStartContext, RecoveryStartFormField, RegisterContext, RegisterFormField,
+        SiteBranding, SiteConfigExt, SiteFeatures, TemplateContext, UpstreamDeniedContext,
         UpstreamExistingLinkContext, UpstreamRegister, UpstreamRegisterFormField,
         UpstreamSuggestLink, WithCaptcha, WithCsrf, WithLanguage, WithOptionalSession, WithSession,
     },
@@ -382,6 +383,24 @@ register_templates! {
     /// Render the email recovery subject
     pub fn render_email_recovery_subject(WithLanguage<EmailRecoveryContext>) { "emails/recovery.subject" }
 
+    /// Render the login lockout email (plain text variant)
+    pub fn render_email_login_lockout_txt(WithLanguage<EmailLoginLockoutContext>) { "emails/login_lockout.txt" }
+
+    /// Render the login lockout email (HTML text variant)
+    pub fn render_email_login_lockout_html(WithLanguage<EmailLoginLockoutContext>) { "emails/login_lockout.html" }
+
+    /// Render the login lockout email subject
+    pub fn render_email_login_lockout_subject(WithLanguage<EmailLoginLockoutContext>) { "emails/login_lockout.subject" }
+
+    /// Render the new sign-in email (plain text variant)
+    pub fn render_email_new_sign_in_txt(WithLanguage<EmailNewSignInContext>) { "emails/new_sign_in.txt" }
+
+    /// Render the new sign-in email (HTML text variant)
+    pub fn render_email_new_sign_in_html(WithLanguage<EmailNewSignInContext>) { "emails/new_sign_in.html" }
+
+    /// Render the new sign-in email subject
+    pub fn render_email_new_sign_in_subject(WithLanguage<EmailNewSignInContext>) { "emails/new_sign_in.subject" }
+
     /// Render the email verification email (plain text variant)
     pub fn render_email_verification_txt(WithLanguage<EmailVerificationContext>) { "emails/verification.txt" }
 
@@ -400,6 +419,9 @@ register_templates! {
     /// Render the upstream register screen
     pub fn render_upstream_oauth2_do_register(WithLanguage<WithCsrf<UpstreamRegister>>) { "pages/upstream_oauth2/do_register.html" }
 
+    /// Render the page shown when the claims mapping policy refused an upstream account
+    pub fn render_upstream_oauth2_denied(WithLanguage<UpstreamDeniedContext>) { "pages/upstream_oauth2/denied.html" }
+
     /// Render the device code link page
     pub fn render_device_link(WithLanguage<DeviceLinkContext>) { "pages/device_link.html" }
 
@@ -440,12 +462,19 @@ impl Templates {
         check::render_reauth(self, now, rng)?;
         check::render_form_post::<EmptyContext>(self, now, rng)?;
         check::render_error(self, now, rng)?;
+        check::render_email_login_lockout_txt(self, now, rng)?;
+        check::render_email_login_lockout_html(self, now, rng)?;
+        check::render_email_login_lockout_subject(self, now, rng)?;
+        check::render_email_new_sign_in_txt(self, now, rng)?;
+        check::render_email_new_sign_in_html(self, now, rng)?;
+        check::render_email_new_sign_in_subject(self, now, rng)?;
         check::render_email_verification_txt(self, now, rng)?;
         check::render_email_verification_html(self, now, rng)?;
         check::render_email_verification_subject(self, now, rng)?;
         check::render_upstream_oauth2_link_mismatch(self, now, rng)?;
         check::render_upstream_oauth2_suggest_link(self, now, rng)?;
         check::render_upstream_oauth2_do_register(self, now, rng)?;
+        check::render_upstream_oauth2_denied(self, now, rng)?;
         Ok(())
     }
 }
@@ -468,6 +497,7 @@ mod tests {
             password_login: true,
             password_registration: true,
             account_recovery: true,
+            registration_token: true,
         };
         let vite_manifest_path =
             Utf8Path::new(env!("CARGO_MANIFEST_DIR")).join("../../frontend/dist/manifest.json");
@@ -13,7 +13,11 @@ mod utils;
 
 pub use self::{metrics::*, trace_context::*, tracing::*, utils::*};
 
-fn meter() -> opentelemetry::metrics::Meter {
+/// Get the [`Meter`] used to record the metrics of the service
+///
+/// [`Meter`]: opentelemetry::metrics::Meter
+#[must_use]
+pub fn meter() -> opentelemetry::metrics::Meter {
     opentelemetry::global::meter_with_version(
         env!("CARGO_PKG_NAME"),
         Some(env!("CARGO_PKG_VERSION")),
@@ -0,0 +1,174 @@
+// Copyright 2024 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+import {
+  useMutation,
+  useQueryClient,
+  useSuspenseQuery,
+} from "@tanstack/react-query";
+import { Alert, Button, Heading, Text } from "@vector-im/compound-web";
+import { useTranslation } from "react-i18next";
+import { graphql } from "../../gql";
+import { graphqlRequest } from "../../graphql";
+import { Close, Dialog, Title } from "../Dialog";
+
+// This component lists the upstream providers the user can sign in with, with
+// controls to link their account to a new provider, or to unlink an existing one.
+
+const QUERY = graphql(/* GraphQL */ `
+  query UpstreamLinkList {
+    viewer {
+      __typename
+      ... on User {
+        id
+        upstreamOauth2Links(first: 100) {
+          edges {
+            node {
+              id
+              provider {
+                id
+              }
+            }
+          }
+        }
+      }
+    }
+
+    upstreamOauth2Providers(first: 100) {
+      edges {
+        node {
+          id
+          humanName
+          linkUrl
+        }
+      }
+    }
+  }
+`);
+
+const REMOVE_UPSTREAM_LINK_MUTATION = graphql(/* GraphQL */ `
+  mutation RemoveUpstreamLink($id: ID!) {
+    removeUpstreamLink(input: { upstreamLinkId: $id }) {
+      status
+    }
+  }
+`);
+
+const UnlinkButtonWithConfirmation: React.FC<{
+  name: string;
+  disabled?: boolean;
+  onClick: () => void;
+}> = ({ name, disabled, onClick }) => {
+  const { t } = useTranslation();
+
+  // NOOP function, otherwise we dont render a cancel button
+  const onDeny = (): void => {};
+
+  return (
+    <Dialog
+      trigger={
+        <Button kind="secondary" size="sm" destructive disabled={disabled}>
+          {t("frontend.upstream_link_list.unlink_button")}
+        </Button>
+      }
+    >
+      <Title>
+        {t("frontend.upstream_link_list.unlink_confirmation_modal.body", {
+          name,
+        })}
+      </Title>
+      <div className="flex flex-col gap-4">
+        <Close asChild>
+          <Button kind="primary" destructive onClick={onClick}>
+            {t("frontend.upstream_link_list.unlink_confirmation_modal.action")}
+          </Button>
+        </Close>
+        <Close asChild>
+          <Button kind="tertiary" onClick={onDeny}>
+            {t("action.cancel")}
+          </Button>
+        </Close>
+      </div>
+    </Dialog>
+  );
+};
+
+const UpstreamLinkList: React.FC = () => {
+  const { t } = useTranslation();
+  const queryClient = useQueryClient();
+  const {
+    data: { viewer, upstreamOauth2Providers },
+  } = useSuspenseQuery({
+    queryKey: ["upstreamLinks"],
+    queryFn: ({ signal }) => graphqlRequest({ query: QUERY, signal }),
+  });
+
+  const removeLink = useMutation({
+    mutationFn: (id: string) =>
+      graphqlRequest({
+        query: REMOVE_UPSTREAM_LINK_MUTATION,
+        variables: { id },
+      }),
+    onSuccess: (data) => {
+      if (data.removeUpstreamLink.status === "REMOVED") {
+        queryClient.invalidateQueries({ queryKey: ["upstreamLinks"] });
+      }
+    },
+  });
+
+  const providers = upstreamOauth2Providers.edges.map((edge) => edge.node);
+  if (viewer.__typename !== "User" || providers.length === 0) return null;
+
+  const links = viewer.upstreamOauth2Links.edges.map((edge) => edge.node);
+  const isLastSignInMethod =
+    removeLink.data?.removeUpstreamLink.status === "LAST_SIGN_IN_METHOD";
+
+  return (
+    <div className="flex flex-col gap-4">
+      <Heading size="sm" weight="semibold">
+        {t("frontend.upstream_link_list.heading")}
+      </Heading>
+
+      {isLastSignInMethod && (
+        <Alert
+          type="critical"
+          title={t("frontend.upstream_link_list.last_sign_in_method_alert")}
+        />
+      )}
+
+      {providers.map((provider) => {
+        const name =
+          provider.humanName ??
+          t("frontend.upstream_link_list.unknown_provider");
+        const link = links.find((l) => l.provider.id === provider.id);
+
+        return (
+          <div
+            key={provider.id}
+            className="flex items-center justify-between gap-2"
+          >
+            <Text size="md" weight="semibold">
+              {name}
+            </Text>
+
+            {link ? (
+              <UnlinkButtonWithConfirmation
+                name={name}
+                disabled={removeLink.isPending}
+                onClick={() => removeLink.mutate(link.id)}
+              />
+            ) : (
+              <Button as="a" kind="secondary" size="sm" href={provider.linkUrl}>
+                {t("frontend.upstream_link_list.link_button")}
+              </Button>
+            )}
+          </div>
+        );
+      })}
+    </div>
+  );
+};
+
+export default UpstreamLinkList;
@@ -38,6 +38,8 @@ const documents = {
     "\n  fragment UserGreeting_siteConfig on SiteConfig {\n    displayNameChangeAllowed\n  }\n": types.UserGreeting_SiteConfigFragmentDoc,
     "\n  mutation SetDisplayName($userId: ID!, $displayName: String) {\n    setDisplayName(input: { userId: $userId, displayName: $displayName }) {\n      status\n    }\n  }\n": types.SetDisplayNameDocument,
     "\n  mutation AddEmail($userId: ID!, $email: String!) {\n    addEmail(input: { userId: $userId, email: $email }) {\n      status\n      violations\n      email {\n        id\n        ...UserEmail_email\n      }\n    }\n  }\n": types.AddEmailDocument,
+    "\n  query UpstreamLinkList {\n    viewer {\n      __typename\n      ... on User {\n        id\n        upstreamOauth2Links(first: 100) {\n          edges {\n            node {\n              id\n              provider {\n                id\n              }\n            }\n          }\n        }\n      }\n    }\n\n    upstreamOauth2Providers(first: 100) {\n      edges {\n        node {\n          id\n          humanName\n          linkUrl\n        }\n      }\n    }\n  }\n": types.UpstreamLinkListDocument,
+    "\n  mutation RemoveUpstreamLink($id: ID!) {\n    removeUpstreamLink(input: { upstreamLinkId: $id }) {\n      status\n    }\n  }\n": types.RemoveUpstreamLinkDocument,
     "\n  query UserEmailList(\n    $userId: ID!\n    $first: Int\n    $after: String\n    $last: Int\n    $before: String\n  ) {\n    user(id: $userId) {\n      id\n\n      emails(first: $first, after: $after, last: $last, before: $before) {\n        edges {\n          cursor\n          node {\n            id\n            ...UserEmail_email\n          }\n        }\n        totalCount\n        pageInfo {\n          hasNextPage\n          hasPreviousPage\n          startCursor\n          endCursor\n        }\n      }\n    }\n  }\n": types.UserEmailListDocument,
     "\n  fragment UserEmailList_user on User {\n    id\n    primaryEmail {\n      id\n    }\n  }\n": types.UserEmailList_UserFragmentDoc,
     "\n  fragment UserEmailList_siteConfig on SiteConfig {\n    ...UserEmail_siteConfig\n  }\n": types.UserEmailList_SiteConfigFragmentDoc,
@@ -154,6 +156,14 @@ export function graphql(source: "\n  mutation SetDisplayName($userId: ID!, $disp
  * The graphql function is used to parse GraphQL queries into a document that can be used by GraphQL clients.
  */
 export function graphql(source: "\n  mutation AddEmail($userId: ID!, $email: String!) {\n    addEmail(input: { userId: $userId, email: $email }) {\n      status\n      violations\n      email {\n        id\n        ...UserEmail_email\n      }\n    }\n  }\n"): typeof import('./graphql').AddEmailDocument;
+/**
+ * The graphql function is used to parse GraphQL queries into a document that can be used by GraphQL clients.
+ */
+export function graphql(source: "\n  query UpstreamLinkList {\n    viewer {\n      __typename\n      ... on User {\n        id\n        upstreamOauth2Links(first: 100) {\n          edges {\n            node {\n              id\n              provider {\n                id\n              }\n            }\n          }\n        }\n      }\n    }\n\n    upstreamOauth2Providers(first: 100) {\n      edges {\n        node {\n          id\n          humanName\n          linkUrl\n        }\n      }\n    }\n  }\n"): typeof import('./graphql').UpstreamLinkListDocument;
+/**
+ * The graphql function is used to parse GraphQL queries into a document that can be used by GraphQL clients.
+ */
+export function graphql(source: "\n  mutation RemoveUpstreamLink($id: ID!) {\n    removeUpstreamLink(input: { upstreamLinkId: $id }) {\n      status\n    }\n  }\n"): typeof import('./graphql').RemoveUpstreamLinkDocument;
 /**
  * The graphql function is used to parse GraphQL queries into a document that can be used by GraphQL clients.
  */
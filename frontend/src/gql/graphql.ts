@@ -493,6 +493,11 @@ export type Mutation = {
   lockUser: LockUserPayload;
   /** Remove an email address */
   removeEmail: RemoveEmailPayload;
+  /**
+   * Remove a link between a user and an upstream OAuth 2.0 provider, as long
+   * as the user can still sign in afterwards
+   */
+  removeUpstreamLink: RemoveUpstreamLinkPayload;
   /** Send a verification code for an email address */
   sendVerificationEmail: SendVerificationEmailPayload;
   /**
@@ -576,6 +581,12 @@ export type MutationRemoveEmailArgs = {
 };
 
 
+/** The mutations root of the GraphQL interface. */
+export type MutationRemoveUpstreamLinkArgs = {
+  input: RemoveUpstreamLinkInput;
+};
+
+
 /** The mutations root of the GraphQL interface. */
 export type MutationSendVerificationEmailArgs = {
   input: SendVerificationEmailInput;
@@ -887,6 +898,32 @@ export type RemoveEmailStatus =
   /** The email address was removed */
   | 'REMOVED';
 
+/** The input of the `removeUpstreamLink` mutation. */
+export type RemoveUpstreamLinkInput = {
+  /** The ID of the upstream link to remove. */
+  upstreamLinkId: Scalars['ID']['input'];
+};
+
+export type RemoveUpstreamLinkPayload = {
+  __typename?: 'RemoveUpstreamLinkPayload';
+  /** Status of the operation */
+  status: RemoveUpstreamLinkStatus;
+  /** The upstream link that was removed. */
+  upstreamLink?: Maybe<UpstreamOAuth2Link>;
+};
+
+/** The status of the `removeUpstreamLink` mutation. */
+export type RemoveUpstreamLinkStatus =
+  /**
+   * The upstream link is the last way the user has to sign in, so it can't
+   * be removed.
+   */
+  | 'LAST_SIGN_IN_METHOD'
+  /** The upstream link was not found. */
+  | 'NOT_FOUND'
+  /** The upstream link was removed. */
+  | 'REMOVED';
+
 /** The input for the `sendVerificationEmail` mutation */
 export type SendVerificationEmailInput = {
   /** The ID of the email address to verify */
@@ -1002,6 +1039,8 @@ export type SetPasswordStatus =
   | 'ACCOUNT_LOCKED'
   /** The password was updated. */
   | 'ALLOWED'
+  /** The new password was found in a list of known breached passwords. */
+  | 'BREACHED_NEW_PASSWORD'
   /** The specified recovery ticket has expired. */
   | 'EXPIRED_RECOVERY_TICKET'
   /**
@@ -1149,14 +1188,27 @@ export type UpstreamOAuth2LinkEdge = {
 
 export type UpstreamOAuth2Provider = CreationEvent & Node & {
   __typename?: 'UpstreamOAuth2Provider';
+  /**
+   * A brand identifier for this provider, used to customise the UI, e.g.
+   * `apple`, `google`, `github`, etc.
+   */
+  brandName?: Maybe<Scalars['String']['output']>;
   /** Client ID used for this provider. */
   clientId: Scalars['String']['output'];
   /** When the object was created. */
   createdAt: Scalars['DateTime']['output'];
+  /** A human-readable name for this provider. */
+  humanName?: Maybe<Scalars['String']['output']>;
   /** ID of the object. */
   id: Scalars['ID']['output'];
   /** OpenID Connect issuer URL. */
   issuer: Scalars['String']['output'];
+  /**
+   * URL to start linking the current user to this provider.
+   *
+   * The user is sent back to the account management page once done.
+   */
+  linkUrl: Scalars['Url']['output'];
 };
 
 export type UpstreamOAuth2ProviderConnection = {
@@ -1512,6 +1564,18 @@ export type AddEmailMutation = { __typename?: 'Mutation', addEmail: { __typename
       & { ' $fragmentRefs'?: { 'UserEmail_EmailFragment': UserEmail_EmailFragment } }
     ) | null } };
 
+export type UpstreamLinkListQueryVariables = Exact<{ [key: string]: never; }>;
+
+
+export type UpstreamLinkListQuery = { __typename?: 'Query', viewer: { __typename: 'Anonymous' } | { __typename: 'User', id: string, upstreamOauth2Links: { __typename?: 'UpstreamOAuth2LinkConnection', edges: Array<{ __typename?: 'UpstreamOAuth2LinkEdge', node: { __typename?: 'UpstreamOAuth2Link', id: string, provider: { __typename?: 'UpstreamOAuth2Provider', id: string } } }> } }, upstreamOauth2Providers: { __typename?: 'UpstreamOAuth2ProviderConnection', edges: Array<{ __typename?: 'UpstreamOAuth2ProviderEdge', node: { __typename?: 'UpstreamOAuth2Provider', id: string, humanName?: string | null, linkUrl: string } }> } };
+
+export type RemoveUpstreamLinkMutationVariables = Exact<{
+  id: Scalars['ID']['input'];
+}>;
+
+
+export type RemoveUpstreamLinkMutation = { __typename?: 'Mutation', removeUpstreamLink: { __typename?: 'RemoveUpstreamLinkPayload', status: RemoveUpstreamLinkStatus } };
+
 export type UserEmailListQueryVariables = Exact<{
   userId: Scalars['ID']['input'];
   first?: InputMaybe<Scalars['Int']['input']>;
@@ -2046,6 +2110,42 @@ export const AddEmailDocument = new TypedDocumentString(`
   email
   confirmedAt
 }`) as unknown as TypedDocumentString<AddEmailMutation, AddEmailMutationVariables>;
+export const UpstreamLinkListDocument = new TypedDocumentString(`
+    query UpstreamLinkList {
+  viewer {
+    __typename
+    ... on User {
+      id
+      upstreamOauth2Links(first: 100) {
+        edges {
+          node {
+            id
+            provider {
+              id
+            }
+          }
+        }
+      }
+    }
+  }
+  upstreamOauth2Providers(first: 100) {
+    edges {
+      node {
+        id
+        humanName
+        linkUrl
+      }
+    }
+  }
+}
+    `) as unknown as TypedDocumentString<UpstreamLinkListQuery, UpstreamLinkListQueryVariables>;
+export const RemoveUpstreamLinkDocument = new TypedDocumentString(`
+    mutation RemoveUpstreamLink($id: ID!) {
+  removeUpstreamLink(input: {upstreamLinkId: $id}) {
+    status
+  }
+}
+    `) as unknown as TypedDocumentString<RemoveUpstreamLinkMutation, RemoveUpstreamLinkMutationVariables>;
 export const UserEmailListDocument = new TypedDocumentString(`
     query UserEmailList($userId: ID!, $first: Int, $after: String, $last: Int, $before: String) {
   user(id: $userId) {
@@ -2668,6 +2768,49 @@ export const mockAddEmailMutation = (resolver: GraphQLResponseResolver<AddEmailM
     options
   )
 
+/**
+ * @param resolver A function that accepts [resolver arguments](https://mswjs.io/docs/api/graphql#resolver-argument) and must always return the instruction on what to do with the intercepted request. ([see more](https://mswjs.io/docs/concepts/response-resolver#resolver-instructions))
+ * @param options Options object to customize the behavior of the mock. ([see more](https://mswjs.io/docs/api/graphql#handler-options))
+ * @see https://mswjs.io/docs/basics/response-resolver
+ * @example
+ * mockUpstreamLinkListQuery(
+ *   ({ query, variables }) => {
+ *     return HttpResponse.json({
+ *       data: { viewer, upstreamOauth2Providers }
+ *     })
+ *   },
+ *   requestOptions
+ * )
+ */
+export const mockUpstreamLinkListQuery = (resolver: GraphQLResponseResolver<UpstreamLinkListQuery, UpstreamLinkListQueryVariables>, options?: RequestHandlerOptions) =>
+  graphql.query<UpstreamLinkListQuery, UpstreamLinkListQueryVariables>(
+    'UpstreamLinkList',
+    resolver,
+    options
+  )
+
+/**
+ * @param resolver A function that accepts [resolver arguments](https://mswjs.io/docs/api/graphql#resolver-argument) and must always return the instruction on what to do with the intercepted request. ([see more](https://mswjs.io/docs/concepts/response-resolver#resolver-instructions))
+ * @param options Options object to customize the behavior of the mock. ([see more](https://mswjs.io/docs/api/graphql#handler-options))
+ * @see https://mswjs.io/docs/basics/response-resolver
+ * @example
+ * mockRemoveUpstreamLinkMutation(
+ *   ({ query, variables }) => {
+ *     const { id } = variables;
+ *     return HttpResponse.json({
+ *       data: { removeUpstreamLink }
+ *     })
+ *   },
+ *   requestOptions
+ * )
+ */
+export const mockRemoveUpstreamLinkMutation = (resolver: GraphQLResponseResolver<RemoveUpstreamLinkMutation, RemoveUpstreamLinkMutationVariables>, options?: RequestHandlerOptions) =>
+  graphql.mutation<RemoveUpstreamLinkMutation, RemoveUpstreamLinkMutationVariables>(
+    'RemoveUpstreamLink',
+    resolver,
+    options
+  )
+
 /**
  * @param resolver A function that accepts [resolver arguments](https://mswjs.io/docs/api/graphql#resolver-argument) and must always return the instruction on what to do with the intercepted request. ([see more](https://mswjs.io/docs/concepts/response-resolver#resolver-instructions))
  * @param options Options object to customize the behavior of the mock. ([see more](https://mswjs.io/docs/api/graphql#handler-options))
@@ -33,6 +33,10 @@ export function translateSetPasswordError(
       return t(
         "frontend.password_change.failure.description.password_changes_disabled",
       );
+    case "BREACHED_NEW_PASSWORD":
+      return t(
+        "frontend.password_change.failure.description.breached_new_password",
+      );
     case "ACCOUNT_LOCKED":
       return t("frontend.password_change.failure.description.account_locked");
     case "EXPIRED_RECOVERY_TICKET":
@@ -21,6 +21,7 @@ import * as Collapsible from "../components/Collapsible";
 import LoadingSpinner from "../components/LoadingSpinner";
 import UserEmail from "../components/UserEmail";
 import AddEmailForm from "../components/UserProfile/AddEmailForm";
+import UpstreamLinkList from "../components/UserProfile/UpstreamLinkList";
 import UserEmailList from "../components/UserProfile/UserEmailList";
 
 import { query } from "./_account.index";
@@ -77,6 +78,10 @@ function Index(): React.ReactElement {
           </>
         )}
 
+        <Suspense fallback={<LoadingSpinner mini className="self-center" />}>
+          <UpstreamLinkList />
+        </Suspense>
+
         <Separator />
 
         <Collapsible.Root>
@@ -59,6 +59,39 @@ allowed_scope("urn:mas:admin") {
 	input.client.id == client
 }
 
+# Fine-grained scopes giving access to parts of the admin API
+admin_scopes := {
+	"urn:mas:admin:read",
+	"urn:mas:admin:users:read",
+	"urn:mas:admin:users:write",
+	"urn:mas:admin:users:lock",
+	"urn:mas:admin:sessions",
+	"urn:mas:admin:clients",
+}
+
+# Users and clients which can get full admin access can also get the
+# fine-grained scopes
+allowed_scope(scope) {
+	admin_scopes[scope]
+	interactive_grant_type(input.grant_type)
+	can_request_admin(input.user)
+}
+
+allowed_scope(scope) {
+	admin_scopes[scope]
+	input.grant_type == "client_credentials"
+	some client in data.admin_clients
+	input.client.id == client
+}
+
+# Clients can be given a subset of the fine-grained scopes
+allowed_scope(scope) {
+	admin_scopes[scope]
+	input.grant_type == "client_credentials"
+	some allowed in data.admin_client_scopes[input.client.id]
+	scope == allowed
+}
+
 allowed_scope(scope) {
 	# Grant access to the C-S API only if there is a user
 	interactive_grant_type(input.grant_type)
@@ -4,6 +4,8 @@ user := {"username": "john"}
 
 client := {"client_id": "client"}
 
+admin_client := {"id": "client"}
+
 test_standard_scopes {
 	allow with input.user as user
 		with input.client as client
@@ -155,3 +157,42 @@ test_mas_scopes {
 		with input.grant_type as "authorization_code"
 		with input.scope as "urn:mas:admin"
 }
+
+test_mas_admin_scopes {
+	allow with input.user as user
+		with input.client as client
+		with data.admin_users as ["john"]
+		with input.grant_type as "authorization_code"
+		with input.scope as "urn:mas:admin:users:read"
+
+	not allow with input.user as user
+		with input.client as client
+		with data.admin_users as []
+		with input.grant_type as "authorization_code"
+		with input.scope as "urn:mas:admin:users:read"
+
+	allow with input.client as admin_client
+		with data.admin_clients as ["client"]
+		with input.grant_type as "client_credentials"
+		with input.scope as "urn:mas:admin:sessions"
+
+	allow with input.client as admin_client
+		with data.admin_client_scopes as {"client": ["urn:mas:admin:users:read"]}
+		with input.grant_type as "client_credentials"
+		with input.scope as "urn:mas:admin:users:read"
+
+	not allow with input.client as admin_client
+		with data.admin_client_scopes as {"client": ["urn:mas:admin:users:read"]}
+		with input.grant_type as "client_credentials"
+		with input.scope as "urn:mas:admin:users:lock"
+
+	not allow with input.client as admin_client
+		with data.admin_client_scopes as {"client": ["urn:mas:admin:users:read"]}
+		with input.grant_type as "client_credentials"
+		with input.scope as "urn:mas:admin"
+
+	not allow with input.client as admin_client
+		with data.admin_client_scopes as {"client": ["urn:mas:admin:unknown"]}
+		with input.grant_type as "client_credentials"
+		with input.scope as "urn:mas:admin:unknown"
+}
@@ -49,3 +49,10 @@ violation[object.union({"field": "email"}, v)] {
 	# Get the violation object from the email policy
 	some v in email_policy.violation
 }
+
+# Check the other email addresses the upstream OAuth 2.0 claims mapping policy
+# found for the user with the email policy as well
+violation[object.union({"field": "email"}, v)] {
+	some address in input.emails
+	some v in email_policy.violation with input.email as address
+}
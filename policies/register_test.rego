@@ -39,6 +39,14 @@ test_no_email {
 	allow with input as {"username": "hello", "registration_method": "upstream-oauth2"}
 }
 
+test_upstream_emails {
+	allow with input as {"username": "hello", "registration_method": "upstream-oauth2", "emails": ["hello@element.io", "hello@staging.element.io"]}
+		with data.allowed_domains as ["*.element.io"]
+
+	not allow with input as {"username": "hello", "registration_method": "upstream-oauth2", "emails": ["hello@element.io", "hello@example.com"]}
+		with data.allowed_domains as ["*.element.io"]
+}
+
 test_short_username {
 	not allow with input as {"username": "a", "registration_method": "upstream-oauth2"}
 }
@@ -0,0 +1,44 @@
+# METADATA
+# schemas:
+#   - input: schema["upstream_oauth2_mapping_input"]
+package upstream_oauth2_mapping
+
+import future.keywords.in
+
+# This policy maps the claims returned by upstream providers to the attributes
+# of the user. Attributes left to null fall back to the templates set in the
+# provider configuration. It is meant to be replaced by custom rules, e.g.:
+#
+#   localpart := lower(input.id_token.preferred_username) {
+#   	input.provider_id == "01H8PKNWKKRPCBW4YGH1RWV279"
+#   }
+
+default localpart := null
+
+default displayname := null
+
+default emails := []
+
+result := {
+	"localpart": localpart,
+	"displayname": displayname,
+	"emails": emails,
+	"violations": violation,
+}
+
+default allow := false
+
+allow {
+	count(violation) == 0
+}
+
+# The claims of the ID token take precedence over the userinfo ones
+claims := object.union(object.get(input, "userinfo", {}), object.get(input, "id_token", {}))
+
+# Deny users with a claim set to one of the values listed in the
+# data.upstream_oauth2_mapping.denied_claims object,
+# e.g. {"department": ["contractors"]}
+violation[{"msg": sprintf("the %s of the upstream account is not allowed", [claim])}] {
+	some claim, values in data.upstream_oauth2_mapping.denied_claims
+	claims[claim] in values
+}
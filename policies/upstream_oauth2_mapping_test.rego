@@ -0,0 +1,33 @@
+package upstream_oauth2_mapping
+
+mock_input := {
+	"provider_id": "01H8PKNWKKRPCBW4YGH1RWV279",
+	"issuer": "https://example.com/",
+	"id_token": {"sub": "123", "department": "engineering"},
+	"userinfo": {"sub": "123", "department": "contractors"},
+}
+
+test_allow_by_default {
+	allow with input as mock_input
+}
+
+test_passthrough_by_default {
+	result.localpart == null with input as mock_input
+	result.displayname == null with input as mock_input
+	count(result.emails) == 0 with input as mock_input
+}
+
+test_denied_claim {
+	not allow with input as mock_input
+		with data.upstream_oauth2_mapping.denied_claims as {"department": ["engineering"]}
+}
+
+test_id_token_takes_precedence {
+	allow with input as mock_input
+		with data.upstream_oauth2_mapping.denied_claims as {"department": ["contractors"]}
+}
+
+test_userinfo_claims {
+	not allow with input as {"provider_id": "01H8PKNWKKRPCBW4YGH1RWV279", "issuer": "https://example.com/", "userinfo": {"department": "contractors"}}
+		with data.upstream_oauth2_mapping.denied_claims as {"department": ["contractors"]}
+}